@@ -56,7 +56,7 @@ pub async fn run(cli_options: cli::CliOptionsNodeInfo) {
         NoiseKey::new(&rand::random())
     };
 
-    let mut handshake = handshake::Handshake::new(true);
+    let mut handshake = handshake::Handshake::new(true, rand::random());
     let (remote_peer_id, connection_prototype, mut tcp_socket) = loop {
         match (handshake, tcp_socket) {
             (
@@ -110,9 +110,11 @@ pub async fn run(cli_options: cli::CliOptionsNodeInfo) {
         ping_interval: Duration::from_secs(60),
         ping_protocol: "/ipfs/ping/1.0.0".into(),
         ping_timeout: Duration::from_secs(10),
+        inbound_negotiation_timeout: Duration::from_secs(10),
         randomness_seed: rand::random(),
         request_protocols: vec![established::ConfigRequestResponse {
             name: "/ipfs/id/1.0.0".into(),
+            fallback_names: Vec::new(),
             inbound_config: established::ConfigRequestResponseIn::Empty,
             max_response_size: 4096,
             inbound_allowed: false,
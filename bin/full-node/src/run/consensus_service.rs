@@ -175,6 +175,12 @@ impl ConsensusService {
                     1024
                 },
                 max_disjoint_headers: 1024,
+                max_fork_depth: {
+                    // Re-orgs on live chains are at most a few blocks deep. Forks that diverge
+                    // from the best chain further than this are almost certainly announced by
+                    // misbehaving peers.
+                    Some(256)
+                },
                 max_requests_per_block: NonZeroU32::new(3).unwrap(),
                 download_ahead_blocks: {
                     // Assuming a verification speed of 1k blocks/sec and a 99th download time
@@ -184,6 +190,8 @@ impl ConsensusService {
                     // the chain and the machine of the user.
                     NonZeroU32::new(2000).unwrap()
                 },
+                min_peers_for_ready: 1,
+                randomness_seed: rand::random(),
                 full: Some(all::ConfigFull {
                     finalized_runtime: {
                         // Builds the runtime of the finalized block.
@@ -600,6 +608,7 @@ impl SyncBackground {
                     parent_runtime,
                     block_body_capacity: 0, // TODO: could be set to the size of the tx pool
                     top_trie_root_calculation_cache: None, // TODO: pretty important for performances
+                    cache_storage_reads: true,
                 })
             };
 
@@ -1041,6 +1050,7 @@ impl SyncBackground {
                             all::JustificationVerifyOutcome::NewFinalized {
                                 finalized_blocks,
                                 updates_best_block,
+                                ..
                             },
                         ) => {
                             span.record("outcome", &"success");
@@ -1131,8 +1141,22 @@ impl SyncBackground {
                             ..
                         } => {
                             span.record("outcome", &"failure");
-                            span.record("error", &tracing::field::display(error));
+                            span.record("error", &tracing::field::display(&error));
                             self.sync = sync_out;
+
+                            // The sources that have announced a block on a fork that is too deep
+                            // are misbehaving.
+                            if let all::HeaderVerifyError::ForkTooDeep { .. } = error {
+                                let peers = self
+                                    .sync
+                                    .knows_non_finalized_block(height_to_verify, &hash_to_verify)
+                                    .filter_map(|source_id| self.sync[source_id].clone())
+                                    .collect::<Vec<_>>();
+                                for peer_id in peers {
+                                    self.network_service.penalize(&peer_id).await;
+                                }
+                            }
+
                             continue;
                         }
                     }
@@ -164,6 +164,7 @@ impl NetworkService {
                 best_hash: chain.best_block.1,
                 best_number: chain.best_block.0,
                 genesis_hash: chain.genesis_block_hash,
+                accept_legacy_protocol_names: true,
                 role: protocol::Role::Full,
                 grandpa_protocol_config: if chain.has_grandpa_protocol {
                     // TODO: dummy values
@@ -195,8 +196,17 @@ impl NetworkService {
                 peers_capacity: 100,       // TODO: ?
                 noise_key: config.noise_key,
                 handshake_timeout: Duration::from_secs(8),
+                inbound_negotiation_timeout: Duration::from_secs(10),
                 max_addresses_per_peer: NonZeroUsize::new(5).unwrap(),
                 pending_api_events_buffer_size: NonZeroUsize::new(64).unwrap(),
+                block_announces_dedup_capacity: 256,
+                block_announces_dedup_window: Duration::from_secs(12),
+                blocked_ip_ranges: Vec::new(),
+                max_inbound_connections: 100,
+                max_outbound_connections: 50,
+                reserved_peers: Vec::new(),
+                reserved_slots: 0,
+                idle_connection_timeout: None,
                 randomness_seed: rand::random(),
             }),
             jaeger_service: config.jaeger_service,
@@ -245,6 +255,7 @@ impl NetworkService {
                                 chain_index,
                                 peer_id,
                                 announce,
+                                ..
                             } => {
                                 let decoded = announce.decode();
 
@@ -531,10 +542,17 @@ impl NetworkService {
 
                         tracing::debug!(%multiaddr, "incoming-connection");
 
-                        let connection_id = inner
+                        let connection_id = match inner
                             .network
                             .add_incoming_connection(Instant::now(), multiaddr.clone())
-                            .await;
+                            .await
+                        {
+                            Ok(id) => id,
+                            Err(error) => {
+                                tracing::debug!(%multiaddr, %error, "incoming-connection-refused");
+                                continue;
+                            }
+                        };
 
                         // Ignore errors, as it is possible for the destination task to have been
                         // aborted already.
@@ -705,6 +723,12 @@ impl NetworkService {
             .await
     }
 
+    /// Penalizes the given peer, for example because it has sent invalid data. See
+    /// [`service::ChainNetwork::penalize`].
+    pub async fn penalize(&self, target: &PeerId) {
+        self.inner.network.penalize(Instant::now(), target).await
+    }
+
     /// Sends a blocks request to the given peer.
     // TODO: more docs
     // TODO: proper error type
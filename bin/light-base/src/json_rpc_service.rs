@@ -98,6 +98,9 @@ pub fn service(config: Config) -> (Sender, ServicePrototype) {
             max_clients: 1,
             max_requests_per_client: config.max_pending_requests,
             max_subscriptions_per_client: config.max_subscriptions,
+            // Expensive requests are processed at least once every 8 requests even if cheap
+            // requests are continuously queued.
+            max_consecutive_cheap_requests: NonZeroU32::new(8).unwrap(),
         });
 
     let client_id = requests_subscriptions.add_client_mut().unwrap(); // Adding a client can fail only if the limit is reached.
@@ -155,13 +158,16 @@ impl Sender {
     pub fn queue_rpc_request(&mut self, json_rpc_request: String) -> Result<(), HandleRpcError> {
         // If the request isn't even a valid JSON-RPC request, we can't even send back a response.
         // We have no choice but to immediately refuse the request.
-        if let Err(error) = json_rpc::parse::parse_call(&json_rpc_request) {
-            log::warn!(
-                target: &self.log_target,
-                "Refused malformed JSON-RPC request: {}", error
-            );
-            return Err(HandleRpcError::MalformedJsonRpc(error));
-        }
+        let cost = match json_rpc::parse::parse_call(&json_rpc_request) {
+            Ok(call) => methods::method_cost(call.method),
+            Err(error) => {
+                log::warn!(
+                    target: &self.log_target,
+                    "Refused malformed JSON-RPC request: {}", error
+                );
+                return Err(HandleRpcError::MalformedJsonRpc(error));
+            }
+        };
 
         // Logging the request before it is queued.
         if log::log_enabled!(log::Level::Debug) {
@@ -175,10 +181,11 @@ impl Sender {
             );
         }
 
-        match self
-            .requests_subscriptions
-            .try_queue_client_request(&self.client_id, json_rpc_request)
-        {
+        match self.requests_subscriptions.try_queue_client_request(
+            &self.client_id,
+            json_rpc_request,
+            cost,
+        ) {
             Ok(()) => Ok(()),
             Err(err) => {
                 log::warn!(
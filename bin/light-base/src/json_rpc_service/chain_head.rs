@@ -185,6 +185,7 @@ impl<TPlat: Platform> Background<TPlat> {
                             parameter: iter::once(&call_parameters.0),
                             top_trie_root_calculation_cache: None,
                             offchain_storage_changes: Default::default(),
+                            storage_read_cache: None,
                             storage_top_trie_changes: Default::default(),
                         }) {
                             Err((error, prototype)) => {
@@ -1434,7 +1435,7 @@ fn convert_runtime_spec(
                     impl_version: runtime.impl_version,
                     spec_version: runtime.spec_version,
                     authoring_version: runtime.authoring_version,
-                    transaction_version: runtime.transaction_version,
+                    transaction_version: Some(runtime.transaction_version),
                     apis: runtime
                         .apis
                         .map(|api| (methods::HexString(api.name_hash.to_vec()), api.version))
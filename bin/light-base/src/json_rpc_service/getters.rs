@@ -123,7 +123,7 @@ impl<TPlat: Platform> Background<TPlat> {
                 state_machine_request_id,
                 methods::Response::rpc_methods(methods::RpcMethods {
                     version: 1,
-                    methods: methods::MethodCall::method_names()
+                    methods: methods::MethodCall::method_names_and_aliases()
                         .map(|n| n.into())
                         .collect(),
                 })
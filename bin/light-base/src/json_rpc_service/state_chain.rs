@@ -1072,7 +1072,7 @@ impl<TPlat: Platform> Background<TPlat> {
                     authoring_version: u64::from(runtime_spec.authoring_version),
                     spec_version: u64::from(runtime_spec.spec_version),
                     impl_version: u64::from(runtime_spec.impl_version),
-                    transaction_version: runtime_spec.transaction_version.map(u64::from),
+                    transaction_version: Some(u64::from(runtime_spec.transaction_version)),
                     state_version: runtime_spec.state_version.map(u64::from),
                     apis: runtime_spec
                         .apis
@@ -1251,9 +1251,9 @@ impl<TPlat: Platform> Background<TPlat> {
                                 authoring_version: u64::from(runtime_spec.authoring_version),
                                 spec_version: u64::from(runtime_spec.spec_version),
                                 impl_version: u64::from(runtime_spec.impl_version),
-                                transaction_version: runtime_spec
-                                    .transaction_version
-                                    .map(u64::from),
+                                transaction_version: Some(u64::from(
+                                    runtime_spec.transaction_version,
+                                )),
                                 state_version: runtime_spec.state_version.map(u64::from),
                                 apis: runtime_spec
                                     .apis
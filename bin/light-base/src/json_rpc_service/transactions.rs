@@ -196,13 +196,32 @@ impl<TPlat: Platform> Background<TPlat> {
                 let mut included_block = None;
                 let mut num_broadcasted_peers = 0;
 
-                // TODO: doesn't reported `validated` events
-
                 loop {
                     match transaction_updates.next().await {
                         Some(update) => {
+                            // `Dropped` is always the last status of a transaction.
+                            let is_final = matches!(
+                                update,
+                                transactions_service::TransactionStatus::Dropped(_)
+                            );
+
                             let update = match (update, is_legacy) {
-                                (transactions_service::TransactionStatus::Broadcast(peers), false) => {
+                                (transactions_service::TransactionStatus::Validated, true) => {
+                                    methods::ServerToClient::author_extrinsicUpdate {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionStatus::Ready,
+                                    }
+                                    .to_json_call_object_parameters(None)
+                                }
+                                (transactions_service::TransactionStatus::Validated, false) => {
+                                    methods::ServerToClient::transaction_unstable_watchEvent {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionWatchEvent::Validated {},
+                                    }
+                                    .to_json_call_object_parameters(None)
+                                }
+
+                                (transactions_service::TransactionStatus::Broadcast(peers), true) => {
                                     methods::ServerToClient::author_extrinsicUpdate {
                                         subscription: (&subscription_id).into(),
                                         result: methods::TransactionStatus::Broadcast(
@@ -211,7 +230,7 @@ impl<TPlat: Platform> Background<TPlat> {
                                     }
                                     .to_json_call_object_parameters(None)
                                 }
-                                (transactions_service::TransactionStatus::Broadcast(peers), true) => {
+                                (transactions_service::TransactionStatus::Broadcast(peers), false) => {
                                     num_broadcasted_peers += peers.len();
                                     methods::ServerToClient::transaction_unstable_watchEvent {
                                         subscription: (&subscription_id).into(),
@@ -283,9 +302,6 @@ impl<TPlat: Platform> Background<TPlat> {
                                 | (transactions_service::TransactionStatus::Dropped(
                                     transactions_service::DropReason::MaxPendingTransactionsReached,
                                 ), true)
-                                | (transactions_service::TransactionStatus::Dropped(
-                                    transactions_service::DropReason::Invalid(_),
-                                ), true)
                                 | (transactions_service::TransactionStatus::Dropped(
                                     transactions_service::DropReason::ValidateError(_),
                                 ), true) => {
@@ -295,6 +311,15 @@ impl<TPlat: Platform> Background<TPlat> {
                                     }
                                     .to_json_call_object_parameters(None)
                                 },
+                                (transactions_service::TransactionStatus::Dropped(
+                                    transactions_service::DropReason::Invalid(_),
+                                ), true) => {
+                                    methods::ServerToClient::author_extrinsicUpdate {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionStatus::Invalid,
+                                    }
+                                    .to_json_call_object_parameters(None)
+                                },
                                 (transactions_service::TransactionStatus::Dropped(
                                     transactions_service::DropReason::GapInChain,
                                 ), false) => {
@@ -374,6 +399,10 @@ impl<TPlat: Platform> Background<TPlat> {
                                 .requests_subscriptions
                                 .try_push_notification(&state_machine_subscription, update)
                                 .await;
+
+                            if is_final {
+                                break;
+                            }
                         }
                         None => {
                             // Channel from the transactions service has been closed.
@@ -385,6 +414,26 @@ impl<TPlat: Platform> Background<TPlat> {
                         }
                     }
                 }
+
+                // No notification will be sent anymore. Clean up the subscription so that it
+                // doesn't count towards the limit of subscriptions. Unsubscribing afterwards is
+                // harmless.
+                let ty = if is_legacy {
+                    SubscriptionTy::TransactionLegacy
+                } else {
+                    SubscriptionTy::Transaction
+                };
+                let removed = me
+                    .subscriptions
+                    .lock()
+                    .await
+                    .misc
+                    .remove(&(subscription_id, ty));
+                if removed.is_some() {
+                    me.requests_subscriptions
+                        .stop_subscription(&state_machine_subscription)
+                        .await;
+                }
             }
         };
 
@@ -156,6 +156,7 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                 best_hash: chain.best_block.1,
                 best_number: chain.best_block.0,
                 genesis_hash: chain.genesis_block_hash,
+                accept_legacy_protocol_names: true,
                 role: protocol::Role::Light,
                 allow_inbound_block_requests: false,
             });
@@ -174,7 +175,16 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                 max_addresses_per_peer: NonZeroUsize::new(5).unwrap(),
                 noise_key: config.noise_key,
                 handshake_timeout: Duration::from_secs(8),
+                inbound_negotiation_timeout: Duration::from_secs(10),
                 pending_api_events_buffer_size: NonZeroUsize::new(32).unwrap(),
+                block_announces_dedup_capacity: 256,
+                block_announces_dedup_window: Duration::from_secs(12),
+                blocked_ip_ranges: Vec::new(),
+                max_inbound_connections: 0,
+                max_outbound_connections: 32,
+                reserved_peers: Vec::new(),
+                reserved_slots: 0,
+                idle_connection_timeout: None,
                 randomness_seed: rand::random(),
             }),
             important_nodes: Mutex::new(HashSet::with_capacity_and_hasher(16, Default::default())),
@@ -221,6 +231,7 @@ impl<TPlat: Platform> NetworkService<TPlat> {
                                     chain_index,
                                     peer_id,
                                     announce,
+                                    ..
                                 } => {
                                     log::debug!(
                                         target: "network",
@@ -773,6 +784,13 @@ impl<TPlat: Platform> NetworkService<TPlat> {
             .await
     }
 
+    /// Penalizes the given peer, for example because it has sent invalid data. See
+    /// [`service::ChainNetwork::penalize`].
+    pub async fn penalize(&self, target: &PeerId) {
+        log::debug!(target: "network", "Connection({}) <= Penalize", target);
+        self.inner.network.penalize(TPlat::now(), target).await
+    }
+
     pub async fn set_local_grandpa_state(
         &self,
         chain_index: usize,
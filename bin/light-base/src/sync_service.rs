@@ -35,7 +35,7 @@ use futures::{
 };
 use smoldot::{
     chain,
-    executor::host,
+    executor::{host, read_only_runtime_host},
     libp2p::PeerId,
     network::{protocol, service},
     trie::{self, prefix_proof, proof_verify},
@@ -407,20 +407,14 @@ impl<TPlat: Platform> SyncService<TPlat> {
                 .await
                 .map_err(StorageQueryErrorDetail::Network)
                 .and_then(|outcome| {
-                    let mut result = Vec::with_capacity(requested_keys.clone().count());
-                    for key in requested_keys.clone() {
-                        result.push(
-                            proof_verify::verify_proof(proof_verify::VerifyProofConfig {
-                                proof: outcome.iter().map(|nv| &nv[..]),
-                                requested_key: key.as_ref(),
-                                trie_root_hash: &storage_trie_root,
-                            })
-                            .map_err(StorageQueryErrorDetail::ProofVerification)?
-                            .map(|v| v.to_owned()),
-                        );
-                    }
-                    debug_assert_eq!(result.len(), result.capacity());
-                    Ok(result)
+                    let keys = requested_keys.clone().collect::<Vec<_>>();
+                    let keys = keys.iter().map(|k| k.as_ref()).collect::<Vec<_>>();
+                    read_only_runtime_host::ProofStorage::new(
+                        storage_trie_root,
+                        outcome.iter().map(|nv| &nv[..]),
+                    )
+                    .get_many(&keys)
+                    .map_err(StorageQueryErrorDetail::ProofVerification)
                 });
 
             match result {
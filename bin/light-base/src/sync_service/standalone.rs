@@ -53,6 +53,12 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
                 1024
             },
             max_disjoint_headers: 1024,
+            max_fork_depth: {
+                // Re-orgs on live chains are at most a few blocks deep. Forks that diverge from
+                // the best chain further than this are almost certainly announced by misbehaving
+                // peers.
+                Some(256)
+            },
             max_requests_per_block: NonZeroU32::new(3).unwrap(),
             download_ahead_blocks: {
                 // Verifying a block mostly consists in:
@@ -70,6 +76,8 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
                 // is 5k.
                 NonZeroU32::new(5000).unwrap()
             },
+            min_peers_for_ready: 1,
+            randomness_seed: rand::random(),
             full: None,
         }),
         best_block_updated: false,
@@ -557,6 +565,7 @@ impl<TPlat: Platform> Task<TPlat> {
                 all::ProcessOne::VerifyHeader(verify) => {
                     // Header to verify.
                     let verified_hash = verify.hash();
+                    let verified_height = verify.height();
                     match verify.perform(TPlat::now_from_unix_epoch(), ()) {
                         all::HeaderVerifyOutcome::Success {
                             sync, is_new_best, ..
@@ -608,6 +617,19 @@ impl<TPlat: Platform> Task<TPlat> {
                                 error
                             );
 
+                            // The sources that have announced a block on a fork that is too deep
+                            // are misbehaving.
+                            if let all::HeaderVerifyError::ForkTooDeep { .. } = error {
+                                let peers = self
+                                    .sync
+                                    .knows_non_finalized_block(verified_height, &verified_hash)
+                                    .map(|source_id| self.sync[source_id].0.clone())
+                                    .collect::<Vec<_>>();
+                                for peer_id in peers {
+                                    self.network_service.penalize(&peer_id).await;
+                                }
+                            }
+
                             continue;
                         }
                     }
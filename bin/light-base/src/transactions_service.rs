@@ -206,12 +206,14 @@ impl<TPlat: Platform> TransactionsService<TPlat> {
 /// Update on the state of a transaction in the service.
 ///
 /// > **Note**: Because this code isn't an *actual* transactions pool that leverages the runtime,
-/// >           some variants (e.g. `Invalid`) are missing compared to the ones that can be found
+/// >           some variants (e.g. `Future`) are missing compared to the ones that can be found
 /// >           in Substrate, as they can't possibly be generated by this implementation.
-/// >           Additionally, an equivalent to the `Ready` state in Substrate is missing as it
-/// >           is the default state.
 #[derive(Debug, Clone)]
 pub enum TransactionStatus {
+    /// Transaction has been successfully validated against the best block, and isn't included
+    /// in the best chain. Equivalent to the `Ready` state in Substrate.
+    Validated,
+
     /// Transaction has been broadcasted to the given peers.
     Broadcast(Vec<PeerId>),
 
@@ -380,6 +382,9 @@ async fn background_task<TPlat: Platform>(
                 continue 'channels_rebuild;
             }
 
+            // Report the status changes of the watched transactions detected by the pool.
+            worker.process_pool_status_updates();
+
             // Start the validation process of transactions that need to be validated.
             while worker.validations_in_progress.len() < max_concurrent_validations {
                 // Find a transaction that needs to be validated.
@@ -851,6 +856,7 @@ async fn background_task<TPlat: Platform>(
                                     .unwrap();
                                 if let Some(updates_report) = updates_report {
                                     existing_tx.add_status_update(updates_report);
+                                    worker.pending_transactions.watch_transaction(existing_tx_id);
                                 }
                                 continue;
                             }
@@ -865,7 +871,8 @@ async fn background_task<TPlat: Platform>(
                             }
 
                             // Success path. Inserting in pool.
-                            worker
+                            let is_watched = updates_report.is_some();
+                            let tx_id = worker
                                 .pending_transactions
                                 .add_unvalidated(transaction_bytes, PendingTransaction {
                                     when_reannounce: TPlat::now(),
@@ -879,6 +886,9 @@ async fn background_task<TPlat: Platform>(
                                     latest_status: None,
                                     validation_in_progress: None,
                                 });
+                            if is_watched {
+                                worker.pending_transactions.watch_transaction(tx_id);
+                            }
                         }
                     }
                 }
@@ -991,6 +1001,33 @@ impl<TPlat: Platform> Worker<TPlat> {
             });
         }
     }
+
+    /// Pulls the status updates generated by [`Worker::pending_transactions`] for the
+    /// transactions that have a status channel, and reports them.
+    ///
+    /// Called at each iteration of the main loop, before transactions are removed from the
+    /// pool, as the identifiers of removed transactions can be re-used.
+    fn process_pool_status_updates(&mut self) {
+        while let Some((tx_id, status)) = self.pending_transactions.next_status_update() {
+            match status {
+                // The pool reports a transaction as ready after its first successful validation,
+                // and after the block it was included in has been retracted.
+                light_pool::TransactionStatus::Ready => {
+                    self.pending_transactions
+                        .transaction_user_data_mut(tx_id)
+                        .unwrap()
+                        .update_status(TransactionStatus::Validated);
+                }
+                // Inclusions, finalizations, and removals are reported by the code that processes
+                // them, as they carry information that the pool doesn't have, such as the reason
+                // why a transaction is dropped.
+                light_pool::TransactionStatus::InBlock { .. }
+                | light_pool::TransactionStatus::Finalized { .. }
+                | light_pool::TransactionStatus::Invalid(_)
+                | light_pool::TransactionStatus::Dropped => {}
+            }
+        }
+    }
 }
 
 struct Block {
@@ -97,7 +97,7 @@ pub fn next_slot_claim<'a>(
         let slot_start_from_unix_epoch =
             Duration::from_millis(slot_number.checked_mul(config.slot_duration.get()).unwrap());
         let slot_end_from_unix_epoch =
-            slot_start_from_unix_epoch + Duration::from_secs(config.slot_duration.get());
+            slot_start_from_unix_epoch + Duration::from_millis(config.slot_duration.get());
         debug_assert!(slot_end_from_unix_epoch > config.now_from_unix_epoch);
 
         Some(SlotClaim {
@@ -128,3 +128,54 @@ pub struct SlotClaim {
     /// Index within [`Config::local_authorities`] of the authority that can produce the block.
     pub local_authorities_index: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::header;
+    use core::{num::NonZeroU64, time::Duration};
+
+    #[test]
+    fn slot_claim_follows_time() {
+        let authorities = [
+            header::AuraAuthority {
+                public_key: [1; 32],
+            },
+            header::AuraAuthority {
+                public_key: [2; 32],
+            },
+        ];
+
+        let claim_at = |now_from_unix_epoch: Duration| {
+            super::next_slot_claim(super::Config {
+                now_from_unix_epoch,
+                slot_duration: NonZeroU64::new(6000).unwrap(),
+                current_authorities: header::AuraAuthoritiesIter::from_slice(&authorities),
+                local_authorities: [[2; 32]].iter(),
+            })
+            .unwrap()
+        };
+
+        // The clock is advanced manually, making the outcome independent of the actual time.
+        let mut now = Duration::from_millis(60_000);
+
+        // Slot 10 belongs to the first authority, so the claim is for the next slot.
+        let claim = claim_at(now);
+        assert_eq!(claim.slot_number, 11);
+        assert_eq!(
+            claim.slot_start_from_unix_epoch,
+            Duration::from_millis(66_000)
+        );
+        assert_eq!(
+            claim.slot_end_from_unix_epoch,
+            Duration::from_millis(72_000)
+        );
+        assert_eq!(claim.local_authorities_index, 0);
+
+        // The claim doesn't change until its end is reached.
+        now += Duration::from_millis(11_999);
+        assert_eq!(claim_at(now), claim);
+
+        now += Duration::from_millis(1);
+        assert_eq!(claim_at(now).slot_number, 13);
+    }
+}
@@ -219,6 +219,7 @@ impl AuthoringStart {
             parent_runtime: config.parent_runtime,
             top_trie_root_calculation_cache: config.top_trie_root_calculation_cache,
             block_body_capacity: config.block_body_capacity,
+            cache_storage_reads: config.cache_storage_reads,
             consensus_digest_log_item: match self.consensus {
                 WaitSlotConsensus::Aura(slot) => {
                     runtime::ConfigPreRuntime::Aura(header::AuraPreDigest {
@@ -231,6 +232,11 @@ impl AuthoringStart {
         let inherent_data = inherents::InherentData {
             timestamp: u64::try_from(config.now_from_unix_epoch.as_millis())
                 .unwrap_or(u64::max_value()),
+            consensus: match self.consensus {
+                WaitSlotConsensus::Aura(slot) => Some(inherents::InherentDataConsensus::Aura {
+                    slot_number: slot.slot_number,
+                }),
+            },
         };
 
         (Shared {
@@ -268,6 +274,9 @@ pub struct AuthoringStartConfig<'a> {
     /// Capacity to reserve for the number of extrinsics. Should be higher than the approximate
     /// number of extrinsics that are going to be applied.
     pub block_body_capacity: usize,
+
+    /// See [`runtime::Config::cache_storage_reads`].
+    pub cache_storage_reads: bool,
 }
 
 /// More transactions can be added.
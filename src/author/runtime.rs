@@ -86,6 +86,11 @@ pub struct Config<'a> {
     /// Capacity to reserve for the number of extrinsics. Should be higher than the approximate
     /// number of extrinsics that are going to be applied.
     pub block_body_capacity: usize,
+
+    /// If `true`, the storage values loaded through [`BlockBuild::StorageGet`] are kept in memory
+    /// for the entire duration of the block building, and a storage value that is read multiple
+    /// times, including by different runtime calls, is only requested once.
+    pub cache_storage_reads: bool,
 }
 
 /// Extra configuration depending on the consensus algorithm.
@@ -185,6 +190,11 @@ pub fn build_block(config: Config) -> BlockBuild {
         top_trie_root_calculation_cache: config.top_trie_root_calculation_cache,
         storage_top_trie_changes: Default::default(),
         offchain_storage_changes: Default::default(),
+        storage_read_cache: if config.cache_storage_reads {
+            Some(runtime_host::StorageReadCache::new())
+        } else {
+            None
+        },
     });
 
     let vm = match init_result {
@@ -288,6 +298,7 @@ impl BlockBuild {
                         storage_top_trie_changes: success.storage_top_trie_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
                         top_trie_root_calculation_cache: success.top_trie_root_calculation_cache,
+                        storage_read_cache: success.storage_read_cache,
                     });
                 }
 
@@ -322,6 +333,7 @@ impl BlockBuild {
                         ),
                         storage_top_trie_changes: success.storage_top_trie_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
+                        storage_read_cache: success.storage_read_cache,
                     });
 
                     inner = Inner::Runtime(match init_result {
@@ -339,6 +351,7 @@ impl BlockBuild {
                         storage_top_trie_changes: success.storage_top_trie_changes,
                         offchain_storage_changes: success.offchain_storage_changes,
                         top_trie_root_calculation_cache: success.top_trie_root_calculation_cache,
+                        storage_read_cache: success.storage_read_cache,
                     });
                 }
 
@@ -410,6 +423,7 @@ impl BlockBuild {
                             offchain_storage_changes: success.offchain_storage_changes,
                             top_trie_root_calculation_cache: success
                                 .top_trie_root_calculation_cache,
+                            storage_read_cache: success.storage_read_cache,
                         },
                     };
                 }
@@ -470,6 +484,7 @@ pub struct InherentExtrinsics {
     storage_top_trie_changes: storage_diff::StorageDiff,
     offchain_storage_changes: storage_diff::StorageDiff,
     top_trie_root_calculation_cache: calculate_root::CalculationCache,
+    storage_read_cache: Option<runtime_host::StorageReadCache>,
 }
 
 impl InherentExtrinsics {
@@ -514,6 +529,7 @@ impl InherentExtrinsics {
             top_trie_root_calculation_cache: Some(self.top_trie_root_calculation_cache),
             storage_top_trie_changes: self.storage_top_trie_changes,
             offchain_storage_changes: self.offchain_storage_changes,
+            storage_read_cache: self.storage_read_cache,
         });
 
         let vm = match init_result {
@@ -533,6 +549,7 @@ pub struct ApplyExtrinsic {
     storage_top_trie_changes: storage_diff::StorageDiff,
     offchain_storage_changes: storage_diff::StorageDiff,
     top_trie_root_calculation_cache: calculate_root::CalculationCache,
+    storage_read_cache: Option<runtime_host::StorageReadCache>,
 }
 
 impl ApplyExtrinsic {
@@ -547,6 +564,7 @@ impl ApplyExtrinsic {
             top_trie_root_calculation_cache: Some(self.top_trie_root_calculation_cache),
             storage_top_trie_changes: self.storage_top_trie_changes,
             offchain_storage_changes: self.offchain_storage_changes,
+            storage_read_cache: self.storage_read_cache,
         });
 
         self.shared.stage = Stage::ApplyExtrinsic(extrinsic);
@@ -570,6 +588,7 @@ impl ApplyExtrinsic {
             top_trie_root_calculation_cache: Some(self.top_trie_root_calculation_cache),
             storage_top_trie_changes: self.storage_top_trie_changes,
             offchain_storage_changes: self.offchain_storage_changes,
+            storage_read_cache: self.storage_read_cache,
         });
 
         let vm = match init_result {
@@ -654,7 +673,7 @@ impl NextKey {
 //       In other words, this function might succeed or fail depending on the Substrate chain.
 fn parse_inherent_extrinsics_output(output: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
     nom::combinator::all_consuming(nom::combinator::flat_map(
-        crate::util::nom_scale_compact_usize,
+        crate::util::nom_scale_collection_len(1),
         |num_elems| {
             nom::multi::many_m_n(
                 num_elems,
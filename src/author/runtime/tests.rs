@@ -20,8 +20,9 @@
 use crate::verify::inherents;
 use core::iter;
 
-#[test]
-fn block_building_works() {
+/// Builds a block containing only the inherents on top of the genesis block of the example
+/// chain. Returns the list of keys requested through [`super::BlockBuild::StorageGet`].
+fn build_block_on_genesis(cache_storage_reads: bool) -> Vec<Vec<u8>> {
     let chain_specs = crate::chain_spec::ChainSpec::from_json_bytes(
         &include_bytes!("example-chain-specs.json")[..],
     )
@@ -54,24 +55,36 @@ fn block_building_works() {
             slot_number: 1234u64,
         }),
         top_trie_root_calculation_cache: None,
+        cache_storage_reads,
     });
 
+    let mut requested_keys = Vec::new();
+
     loop {
         match builder {
             super::BlockBuild::Finished(Ok(success)) => {
                 let decoded = crate::header::decode(&success.scale_encoded_header).unwrap();
                 assert_eq!(decoded.number, 1);
                 assert_eq!(*decoded.parent_hash, parent_hash);
-                break;
+                assert!(!success.body.is_empty());
+                assert!(crate::header::verify_extrinsics_root(
+                    decoded,
+                    &success.body
+                ));
+                break requested_keys;
             }
             super::BlockBuild::Finished(Err(err)) => panic!("{}", err),
             super::BlockBuild::ApplyExtrinsic(ext) => builder = ext.finish(),
             super::BlockBuild::ApplyExtrinsicResult { .. } => unreachable!(),
             super::BlockBuild::InherentExtrinsics(ext) => {
-                builder = ext.inject_inherents(inherents::InherentData { timestamp: 1234 });
+                builder = ext.inject_inherents(inherents::InherentData {
+                    timestamp: 1234,
+                    consensus: None,
+                });
             }
             super::BlockBuild::StorageGet(get) => {
                 let key = get.key_as_vec();
+                requested_keys.push(key.clone());
                 let value = genesis_storage
                     .iter()
                     .find(|(k, _)| *k == key)
@@ -90,3 +103,26 @@ fn block_building_works() {
         }
     }
 }
+
+#[test]
+fn block_building_works() {
+    build_block_on_genesis(false);
+}
+
+#[test]
+fn storage_reads_cached() {
+    // Without cache, the various runtime calls read some keys multiple times.
+    let requested_keys = build_block_on_genesis(false);
+    let unique_keys = requested_keys
+        .iter()
+        .collect::<hashbrown::HashSet<_, fnv::FnvBuildHasher>>();
+    assert!(unique_keys.len() < requested_keys.len());
+
+    // With a cache, each key is requested only once.
+    let requested_keys_cached = build_block_on_genesis(true);
+    let unique_keys_cached = requested_keys_cached
+        .iter()
+        .collect::<hashbrown::HashSet<_, fnv::FnvBuildHasher>>();
+    assert_eq!(unique_keys_cached.len(), requested_keys_cached.len());
+    assert_eq!(unique_keys_cached, unique_keys);
+}
@@ -69,9 +69,11 @@ use crate::{
 use alloc::{boxed::Box, format, sync::Arc, vec::Vec};
 use core::{cmp, fmt, mem, num::NonZeroU64, time::Duration};
 use hashbrown::HashMap;
+use rand::SeedableRng as _;
 
 mod best_block;
 mod finality;
+mod tests;
 mod verify;
 
 pub use self::finality::*;
@@ -85,6 +87,10 @@ pub struct Config {
 
     /// Pre-allocated size of the chain, in number of non-finalized blocks.
     pub blocks_capacity: usize,
+
+    /// Seed used by the randomness necessary to verify the signatures of justifications. See
+    /// [`crate::finality::signatures::verify_ed25519`].
+    pub randomness_seed: [u8; 32],
 }
 
 /// Holds state about the current state of the chain for the purpose of verifying headers.
@@ -153,6 +159,7 @@ impl<T> NonFinalizedTree<T> {
                     Default::default(),
                 ),
                 current_best: None,
+                randomness: rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed),
             })),
         }
     }
@@ -294,6 +301,32 @@ impl<T> NonFinalizedTree<T> {
         }
     }
 
+    /// Returns the hash and number of the best block.
+    ///
+    /// Equivalent to [`NonFinalizedTree::best_block_hash`] and the number found in
+    /// [`NonFinalizedTree::best_block_header`], but doesn't decode or hash anything.
+    pub fn best_block(&self) -> ([u8; 32], u64) {
+        let inner = self.inner.as_ref().unwrap();
+        if let Some(index) = inner.current_best {
+            let block = inner.blocks.get(index).unwrap();
+            (block.hash, block.header.number)
+        } else {
+            (
+                inner.finalized_block_hash,
+                inner.finalized_block_header.number,
+            )
+        }
+    }
+
+    /// Returns the hash and number of the latest finalized block.
+    pub fn finalized_block(&self) -> ([u8; 32], u64) {
+        let inner = self.inner.as_ref().unwrap();
+        (
+            inner.finalized_block_hash,
+            inner.finalized_block_header.number,
+        )
+    }
+
     /// Returns consensus information about the current best block of the chain.
     pub fn best_block_consensus(&self) -> chain_information::ChainInformationConsensusRef {
         let inner = self.inner.as_ref().unwrap();
@@ -376,6 +409,116 @@ impl<T> NonFinalizedTree<T> {
             node_index,
         })
     }
+
+    /// Returns the path going from the block whose hash is `from` to the block whose hash is
+    /// `to`.
+    ///
+    /// Both blocks must be either non-finalized blocks of the tree, or the latest finalized
+    /// block.
+    ///
+    /// The path consists of the blocks from `from` to the common ancestor of the two blocks,
+    /// followed with the blocks from that common ancestor to `to`. See [`AncestryPath`].
+    pub fn ancestry_path(
+        &self,
+        from: &[u8; 32],
+        to: &[u8; 32],
+    ) -> Result<AncestryPath, AncestryPathError> {
+        let inner = self.inner.as_ref().unwrap();
+
+        // `None` designates the finalized block.
+        let find = |hash: &[u8; 32]| -> Option<Option<fork_tree::NodeIndex>> {
+            if *hash == inner.finalized_block_hash {
+                Some(None)
+            } else {
+                inner.blocks_by_hash.get(hash).map(|idx| Some(*idx))
+            }
+        };
+        let from_index = find(from).ok_or(AncestryPathError::UnknownFrom)?;
+        let to_index = find(to).ok_or(AncestryPathError::UnknownTo)?;
+
+        let header_of =
+            |index: fork_tree::NodeIndex| (&inner.blocks.get(index).unwrap().header).into();
+
+        Ok(match (from_index, to_index) {
+            (None, None) => AncestryPath {
+                retracted: Vec::new(),
+                common_ancestor: inner.finalized_block_hash,
+                enacted: Vec::new(),
+            },
+            (Some(from_index), None) => AncestryPath {
+                retracted: inner
+                    .blocks
+                    .node_to_root_path(from_index)
+                    .map(header_of)
+                    .collect(),
+                common_ancestor: inner.finalized_block_hash,
+                enacted: Vec::new(),
+            },
+            (None, Some(to_index)) => AncestryPath {
+                retracted: Vec::new(),
+                common_ancestor: inner.finalized_block_hash,
+                enacted: inner
+                    .blocks
+                    .root_to_node_path(to_index)
+                    .map(header_of)
+                    .collect(),
+            },
+            (Some(from_index), Some(to_index)) => {
+                let common_ancestor = match inner.blocks.common_ancestor(from_index, to_index) {
+                    Some(idx) => inner.blocks.get(idx).unwrap().hash,
+                    None => inner.finalized_block_hash,
+                };
+                let (ascend, descend) = inner.blocks.ascend_and_descend(from_index, to_index);
+                AncestryPath {
+                    retracted: ascend.map(header_of).collect(),
+                    common_ancestor,
+                    enacted: descend.map(header_of).collect(),
+                }
+            }
+        })
+    }
+
+    /// Returns the number and hash of the common ancestor of the blocks whose hashes are `from`
+    /// and `to`.
+    ///
+    /// Both blocks must be either non-finalized blocks of the tree, or the latest finalized
+    /// block. Contrary to [`NonFinalizedTree::ancestry_path`], this function doesn't allocate.
+    pub fn common_ancestor(
+        &self,
+        from: &[u8; 32],
+        to: &[u8; 32],
+    ) -> Result<(u64, [u8; 32]), AncestryPathError> {
+        let inner = self.inner.as_ref().unwrap();
+
+        // `None` designates the finalized block.
+        let find = |hash: &[u8; 32]| -> Option<Option<fork_tree::NodeIndex>> {
+            if *hash == inner.finalized_block_hash {
+                Some(None)
+            } else {
+                inner.blocks_by_hash.get(hash).map(|idx| Some(*idx))
+            }
+        };
+        let from_index = find(from).ok_or(AncestryPathError::UnknownFrom)?;
+        let to_index = find(to).ok_or(AncestryPathError::UnknownTo)?;
+
+        let common_ancestor = match (from_index, to_index) {
+            (Some(from_index), Some(to_index)) => {
+                inner.blocks.common_ancestor(from_index, to_index)
+            }
+            _ => None,
+        };
+
+        Ok(match common_ancestor {
+            Some(index) => {
+                let block = inner.blocks.get(index).unwrap();
+                (block.header.number, block.hash)
+            }
+            None => (
+                inner.finalized_block_header.number,
+                inner.finalized_block_hash,
+            ),
+        })
+    }
 }
 
 impl<T> fmt::Debug for NonFinalizedTree<T>
@@ -431,6 +574,8 @@ struct NonFinalizedTreeInner<T> {
     /// Index within [`NonFinalizedTreeInner::blocks`] of the current best block. `None` if and
     /// only if the fork tree is empty.
     current_best: Option<fork_tree::NodeIndex>,
+    /// Source of the randomness necessary to verify the signatures of justifications.
+    randomness: rand_chacha::ChaCha20Rng,
 }
 
 /// State of the consensus of the finalized block.
@@ -505,6 +650,73 @@ enum BlockConsensus {
     },
 }
 
+/// Path between two blocks. See [`NonFinalizedTree::ancestry_path`].
+#[derive(Debug, Clone)]
+pub struct AncestryPath<'a> {
+    /// Headers of the blocks from the source block (included) to the common ancestor (excluded).
+    /// Each block is the parent of the previous one.
+    ///
+    /// Empty if the source block is an ancestor of, or equal to, the target block.
+    pub retracted: Vec<header::HeaderRef<'a>>,
+
+    /// Hash of the common ancestor of the two blocks. Can be equal to one of the two blocks, or
+    /// to the latest finalized block.
+    pub common_ancestor: [u8; 32],
+
+    /// Headers of the blocks from the common ancestor (excluded) to the target block (included).
+    /// Each block is a child of the previous one.
+    ///
+    /// Empty if the target block is an ancestor of, or equal to, the source block.
+    pub enacted: Vec<header::HeaderRef<'a>>,
+}
+
+/// Change of the best block towards a block that doesn't descend from the previous best block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reorg {
+    /// Hashes of the blocks that are no longer part of the best chain, from the previous best
+    /// block (included) to the common ancestor (excluded). Each block is the parent of the
+    /// previous one. Never empty.
+    pub retracted: Vec<[u8; 32]>,
+
+    /// Hash of the common ancestor of the previous and the new best blocks. Can be a block that
+    /// has been finalized in the meanwhile.
+    pub common_ancestor: [u8; 32],
+
+    /// Hashes of the blocks that are now part of the best chain, from the common ancestor
+    /// (excluded) to the new best block (included). Each block is a child of the previous one.
+    /// Never empty.
+    pub enacted: Vec<[u8; 32]>,
+}
+
+impl<'a> AncestryPath<'a> {
+    /// Turns the path from the previous best block to the new best block into a [`Reorg`].
+    ///
+    /// Returns `None` if the source block is an ancestor of, or equal to, the target block, in
+    /// which case changing the best block isn't a re-org.
+    pub fn into_reorg(self) -> Option<Reorg> {
+        if self.retracted.is_empty() {
+            return None;
+        }
+
+        Some(Reorg {
+            retracted: self.retracted.iter().map(|h| h.hash()).collect(),
+            common_ancestor: self.common_ancestor,
+            enacted: self.enacted.iter().map(|h| h.hash()).collect(),
+        })
+    }
+}
+
+/// Error potentially returned by [`NonFinalizedTree::ancestry_path`].
+#[derive(Debug, derive_more::Display)]
+pub enum AncestryPathError {
+    /// Source block is neither in the tree nor the latest finalized block.
+    #[display(fmt = "Source block is unknown")]
+    UnknownFrom,
+    /// Target block is neither in the tree nor the latest finalized block.
+    #[display(fmt = "Target block is unknown")]
+    UnknownTo,
+}
+
 /// Access to a block's information and hierarchy.
 pub struct BlockAccess<'a, T> {
     tree: &'a mut NonFinalizedTreeInner<T>,
@@ -21,6 +21,7 @@ use super::*;
 use crate::finality::{grandpa, justification};
 
 use core::{cmp::Ordering, iter};
+use rand::Rng as _;
 
 impl<T> NonFinalizedTree<T> {
     /// Returns a list of blocks (by their height and hash) that need to be finalized before any
@@ -86,6 +87,106 @@ impl<T> NonFinalizedTree<T> {
         }
     }
 
+    /// Returns the non-finalized block with the highest number that can be voted for by
+    /// GrandPa, or the latest finalized block if there is none.
+    ///
+    /// A block that triggers a change in the list of GrandPa authorities must be finalized
+    /// before any of its descendants can be finalized (see
+    /// [`NonFinalizedTree::finality_checkpoints`]). Consequently, if a change is triggered at
+    /// block N on a given chain, the blocks of that chain whose number is strictly superior to N
+    /// aren't candidates, and N is the highest candidate of that chain.
+    ///
+    /// In case of equality, blocks that are part of the chain of the current best block are
+    /// preferred.
+    ///
+    /// If the chain doesn't use GrandPa, this returns the non-finalized block with the highest
+    /// number.
+    pub fn grandpa_best_candidate(&self) -> (u64, &[u8; 32]) {
+        let inner = self.inner.as_ref().unwrap();
+
+        // Scheduled change that is already finalized.
+        let finalized_scheduled = match &inner.finality {
+            Finality::Outsourced => None,
+            Finality::Grandpa {
+                finalized_scheduled_change,
+                ..
+            } => finalized_scheduled_change.as_ref().map(|(n, _)| *n),
+        };
+        let is_grandpa = matches!(inner.finality, Finality::Grandpa { .. });
+
+        // Blocks that are part of the chain of the current best block.
+        let best_chain = match inner.current_best {
+            Some(b) => inner
+                .blocks
+                .node_to_root_path(b)
+                .collect::<alloc::collections::BTreeSet<_>>(),
+            None => Default::default(),
+        };
+
+        // Height of the earliest scheduled change that isn't finalized yet, for each block
+        // visited so far. Because parents are visited before their children, the value of a
+        // block is derived from the one of its parent.
+        let mut earliest_triggers =
+            alloc::collections::BTreeMap::<fork_tree::NodeIndex, Option<u64>>::new();
+
+        let mut best: Option<(fork_tree::NodeIndex, u64, bool)> = None;
+
+        for (node_index, block) in inner.blocks.iter_ancestry_order() {
+            if is_grandpa {
+                let mut earliest_trigger = match inner.blocks.parent(node_index) {
+                    Some(parent) => *earliest_triggers.get(&parent).unwrap(),
+                    None => finalized_scheduled,
+                };
+
+                for grandpa_digest_item in block.header.digest.logs().filter_map(|d| match d {
+                    header::DigestItemRef::GrandpaConsensus(gp) => Some(gp),
+                    _ => None,
+                }) {
+                    if let header::GrandpaConsensusLogRef::ScheduledChange(change) =
+                        grandpa_digest_item
+                    {
+                        let trigger_block_height =
+                            block.header.number.saturating_add(u64::from(change.delay));
+                        earliest_trigger = Some(match earliest_trigger {
+                            Some(t) => cmp::min(t, trigger_block_height),
+                            None => trigger_block_height,
+                        });
+                    }
+                }
+
+                earliest_triggers.insert(node_index, earliest_trigger);
+
+                if matches!(earliest_trigger, Some(t) if block.header.number > t) {
+                    continue;
+                }
+            }
+
+            let in_best_chain = best_chain.contains(&node_index);
+
+            let is_better = match best {
+                None => true,
+                Some((_, best_number, best_in_best_chain)) => {
+                    block.header.number > best_number
+                        || (block.header.number == best_number
+                            && in_best_chain
+                            && !best_in_best_chain)
+                }
+            };
+
+            if is_better {
+                best = Some((node_index, block.header.number, in_best_chain));
+            }
+        }
+
+        match best {
+            Some((node_index, number, _)) => (number, &inner.blocks.get(node_index).unwrap().hash),
+            None => (
+                inner.finalized_block_header.number,
+                &inner.finalized_block_hash,
+            ),
+        }
+    }
+
     /// Verifies the given justification.
     ///
     /// The verification is performed in the context of the chain. In particular, the
@@ -312,6 +413,8 @@ impl<T> NonFinalizedTreeInner<T> {
                 let decoded = justification::decode::decode_grandpa(scale_encoded_justification)
                     .map_err(JustificationVerifyError::InvalidJustification)?;
 
+                let randomness_seed = self.randomness.sample(rand::distributions::Standard);
+
                 // Delegate the first step to the other function.
                 let (block_index, authorities_set_id, authorities_list) = self
                     .verify_grandpa_finality(decoded.target_hash, u64::from(decoded.target_number))
@@ -321,6 +424,7 @@ impl<T> NonFinalizedTreeInner<T> {
                     justification: decoded,
                     authorities_set_id,
                     authorities_list,
+                    randomness_seed,
                 })
                 .map_err(JustificationVerifyError::VerificationFailed)?;
 
@@ -474,6 +578,7 @@ impl<T> NonFinalizedTreeInner<T> {
         // is picked, contrary to the definition of "best block". But again, considering that this
         // situation is so uncommon, it doesn't really matter.
         debug_assert!(self.current_best.is_some()); // Can only be `None` if the tree is empty.
+        let mut reorg = None;
         let updates_best_block = if block_index_to_finalize == self.current_best.unwrap()
             || !self
                 .blocks
@@ -504,6 +609,24 @@ impl<T> NonFinalizedTreeInner<T> {
             }
 
             debug_assert_ne!(self.current_best, new_best_block);
+
+            // If no block descends from the finalized block, then the new best block is the
+            // finalized block itself.
+            let old_best = self.current_best.unwrap();
+            let new_best = new_best_block.unwrap_or(block_index_to_finalize);
+            if !self.blocks.is_ancestor(old_best, new_best) {
+                let common_ancestor = match self.blocks.common_ancestor(old_best, new_best) {
+                    Some(idx) => self.blocks.get(idx).unwrap().hash,
+                    None => self.finalized_block_hash,
+                };
+                let (ascend, descend) = self.blocks.ascend_and_descend(old_best, new_best);
+                reorg = Some(Reorg {
+                    retracted: ascend.map(|i| self.blocks.get(i).unwrap().hash).collect(),
+                    common_ancestor,
+                    enacted: descend.map(|i| self.blocks.get(i).unwrap().hash).collect(),
+                });
+            }
+
             self.current_best = new_best_block;
             true
         } else {
@@ -517,6 +640,7 @@ impl<T> NonFinalizedTreeInner<T> {
             &mut self.finalized_consensus,
             &new_finalized_block.consensus,
         ) {
+            (FinalizedConsensus::AllAuthorized, BlockConsensus::AllAuthorized) => {}
             (
                 FinalizedConsensus::Aura {
                     authorities_list, ..
@@ -558,7 +682,76 @@ impl<T> NonFinalizedTreeInner<T> {
             iter: self.blocks.prune_ancestors(block_index_to_finalize),
             blocks_by_hash: &mut self.blocks_by_hash,
             updates_best_block,
+            reorg,
+        }
+    }
+
+    /// Checks whether `header`, whose parent is either the block of index `parent_tree_index`
+    /// or the finalized block if `None`, schedules a change in the list of GrandPa authorities
+    /// while another scheduled change hasn't been enacted yet.
+    ///
+    /// Returns the height of the block where the pending change is triggered if that is the
+    /// case, or `None` if there isn't any conflict.
+    ///
+    /// Only one scheduled change can be pending at any given time. A scheduled change is pending
+    /// up until and including the block where it is triggered. Forced changes, however, are
+    /// allowed to override a scheduled change and are never considered as conflicting.
+    pub(super) fn grandpa_conflicting_scheduled_change(
+        &self,
+        parent_tree_index: Option<fork_tree::NodeIndex>,
+        header: &header::HeaderRef,
+    ) -> Option<u64> {
+        let finalized_scheduled_change = match &self.finality {
+            Finality::Outsourced => return None,
+            Finality::Grandpa {
+                finalized_scheduled_change,
+                ..
+            } => finalized_scheduled_change,
+        };
+
+        // If a block contains both a scheduled and a forced change, the forced change takes
+        // precedence.
+        let (mut schedules_change, mut forces_change) = (false, false);
+        for grandpa_digest_item in header.digest.logs().filter_map(|d| match d {
+            header::DigestItemRef::GrandpaConsensus(gp) => Some(gp),
+            _ => None,
+        }) {
+            match grandpa_digest_item {
+                header::GrandpaConsensusLogRef::ScheduledChange(_) => schedules_change = true,
+                header::GrandpaConsensusLogRef::ForcedChange { .. } => forces_change = true,
+                _ => {}
+            }
+        }
+        if !schedules_change || forces_change {
+            return None;
+        }
+
+        // Scheduled change that is already finalized.
+        if let Some((trigger_block_height, _)) = finalized_scheduled_change {
+            if *trigger_block_height >= header.number {
+                return Some(*trigger_block_height);
+            }
+        }
+
+        let parent_tree_index = parent_tree_index?;
+        for ancestor in self.blocks.root_to_node_path(parent_tree_index) {
+            let ancestor = &self.blocks.get(ancestor).unwrap().header;
+            for grandpa_digest_item in ancestor.digest.logs().filter_map(|d| match d {
+                header::DigestItemRef::GrandpaConsensus(gp) => Some(gp),
+                _ => None,
+            }) {
+                if let header::GrandpaConsensusLogRef::ScheduledChange(change) = grandpa_digest_item
+                {
+                    let trigger_block_height =
+                        ancestor.number.saturating_add(u64::from(change.delay));
+                    if trigger_block_height >= header.number {
+                        return Some(trigger_block_height);
+                    }
+                }
+            }
         }
+
+        None
     }
 }
 
@@ -685,6 +878,7 @@ pub struct SetFinalizedBlockIter<'a, T> {
     iter: fork_tree::PruneAncestorsIter<'a, Block<T>>,
     blocks_by_hash: &'a mut HashMap<[u8; 32], fork_tree::NodeIndex, fnv::FnvBuildHasher>,
     updates_best_block: bool,
+    reorg: Option<Reorg>,
 }
 
 impl<'a, T> SetFinalizedBlockIter<'a, T> {
@@ -692,6 +886,15 @@ impl<'a, T> SetFinalizedBlockIter<'a, T> {
     pub fn updates_best_block(&self) -> bool {
         self.updates_best_block
     }
+
+    /// Returns the re-org caused by the finalization, if any.
+    ///
+    /// A re-org happens if the previous best block doesn't descend from the newly-finalized
+    /// block. In that situation, the retracted blocks are pruned from the tree, and the
+    /// [`Reorg::common_ancestor`] is either the newly-finalized block or one of its ancestors.
+    pub fn reorg(&self) -> Option<&Reorg> {
+        self.reorg.as_ref()
+    }
 }
 
 impl<'a, T> Iterator for SetFinalizedBlockIter<'a, T> {
@@ -0,0 +1,461 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use crate::{chain::chain_information, header};
+use core::time::Duration;
+
+/// Builds a [`super::NonFinalizedTree`] whose finalized block is an empty genesis block, and
+/// returns it alongside with the header of this genesis block.
+fn new_tree(
+    consensus: chain_information::ChainInformationConsensus,
+    finality: chain_information::ChainInformationFinality,
+) -> (super::NonFinalizedTree<()>, header::Header) {
+    let genesis = header::Header {
+        parent_hash: [0; 32],
+        number: 0,
+        state_root: [0; 32],
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::empty().into(),
+    };
+
+    let tree = super::NonFinalizedTree::new(super::Config {
+        chain_information: chain_information::ValidChainInformation::try_from(
+            chain_information::ChainInformation {
+                finalized_block_header: genesis.clone(),
+                consensus,
+                finality,
+            },
+        )
+        .unwrap(),
+        blocks_capacity: 16,
+        randomness_seed: [0; 32],
+    });
+
+    (tree, genesis)
+}
+
+/// Builds a header whose parent is `parent`. `salt` is used in order to build different
+/// children of the same parent.
+fn child_header(parent: &header::Header, salt: u8) -> header::Header {
+    header::Header {
+        parent_hash: parent.hash(),
+        number: parent.number + 1,
+        state_root: [salt; 32],
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::empty().into(),
+    }
+}
+
+fn insert(tree: &mut super::NonFinalizedTree<()>, header: &header::Header) {
+    match tree
+        .verify_header(header.scale_encoding_vec(), Duration::new(0, 0))
+        .unwrap()
+    {
+        super::HeaderVerifySuccess::Insert { insert, .. } => insert.insert(()),
+        super::HeaderVerifySuccess::Duplicate => panic!(),
+    }
+}
+
+#[test]
+fn ancestry_path() {
+    let (mut tree, genesis) = new_tree(
+        chain_information::ChainInformationConsensus::AllAuthorized,
+        chain_information::ChainInformationFinality::Outsourced,
+    );
+
+    // Builds the following tree:
+    //
+    // genesis -> a1 -> a2 -> a3
+    //              \-> b2
+    // genesis -> c1
+    let a1 = child_header(&genesis, 1);
+    let a2 = child_header(&a1, 1);
+    let a3 = child_header(&a2, 1);
+    let b2 = child_header(&a1, 2);
+    let c1 = child_header(&genesis, 3);
+    for header in [&a1, &a2, &a3, &b2, &c1] {
+        insert(&mut tree, header);
+    }
+
+    let hashes =
+        |headers: &[header::HeaderRef]| headers.iter().map(|h| h.hash()).collect::<Vec<_>>();
+
+    // Diverging forks with a non-finalized common ancestor.
+    let path = tree.ancestry_path(&a3.hash(), &b2.hash()).unwrap();
+    assert_eq!(hashes(&path.retracted), vec![a3.hash(), a2.hash()]);
+    assert_eq!(path.common_ancestor, a1.hash());
+    assert_eq!(hashes(&path.enacted), vec![b2.hash()]);
+
+    // Diverging forks whose common ancestor is the finalized block.
+    let path = tree.ancestry_path(&c1.hash(), &a2.hash()).unwrap();
+    assert_eq!(hashes(&path.retracted), vec![c1.hash()]);
+    assert_eq!(path.common_ancestor, genesis.hash());
+    assert_eq!(hashes(&path.enacted), vec![a1.hash(), a2.hash()]);
+
+    // Ancestor to descendant.
+    let path = tree.ancestry_path(&genesis.hash(), &a3.hash()).unwrap();
+    assert!(path.retracted.is_empty());
+    assert_eq!(path.common_ancestor, genesis.hash());
+    assert_eq!(hashes(&path.enacted), vec![a1.hash(), a2.hash(), a3.hash()]);
+
+    // Descendant to ancestor.
+    let path = tree.ancestry_path(&a3.hash(), &a1.hash()).unwrap();
+    assert_eq!(hashes(&path.retracted), vec![a3.hash(), a2.hash()]);
+    assert_eq!(path.common_ancestor, a1.hash());
+    assert!(path.enacted.is_empty());
+
+    // The common ancestor can also be obtained on its own.
+    assert_eq!(
+        tree.common_ancestor(&a3.hash(), &b2.hash()).unwrap(),
+        (1, a1.hash())
+    );
+    assert_eq!(
+        tree.common_ancestor(&c1.hash(), &a2.hash()).unwrap(),
+        (0, genesis.hash())
+    );
+    assert_eq!(
+        tree.common_ancestor(&genesis.hash(), &a3.hash()).unwrap(),
+        (0, genesis.hash())
+    );
+    assert_eq!(
+        tree.common_ancestor(&a3.hash(), &a1.hash()).unwrap(),
+        (1, a1.hash())
+    );
+
+    // Unknown blocks.
+    assert!(matches!(
+        tree.common_ancestor(&[0xff; 32], &a1.hash()),
+        Err(super::AncestryPathError::UnknownFrom)
+    ));
+    assert!(matches!(
+        tree.ancestry_path(&[0xff; 32], &a1.hash()),
+        Err(super::AncestryPathError::UnknownFrom)
+    ));
+    assert!(matches!(
+        tree.ancestry_path(&a1.hash(), &[0xff; 32]),
+        Err(super::AncestryPathError::UnknownTo)
+    ));
+}
+
+#[test]
+fn grandpa_best_candidate() {
+    let authority = header::GrandpaAuthority {
+        public_key: [1; 32],
+        weight: core::num::NonZeroU64::new(1).unwrap(),
+    };
+
+    let (mut tree, genesis) = new_tree(
+        chain_information::ChainInformationConsensus::AllAuthorized,
+        chain_information::ChainInformationFinality::Grandpa {
+            after_finalized_block_authorities_set_id: 0,
+            finalized_triggered_authorities: vec![authority],
+            finalized_scheduled_change: None,
+        },
+    );
+
+    assert_eq!(tree.grandpa_best_candidate(), (0, &genesis.hash()));
+
+    // `a1` schedules a change that is triggered at block #3. The blocks of the `a` chain after
+    // `a3` can't be finalized before `a3` is.
+    let a1 = {
+        let digest_items = [header::DigestItem::GrandpaConsensus(
+            header::GrandpaConsensusLog::ScheduledChange(header::GrandpaScheduledChange {
+                next_authorities: vec![authority],
+                delay: 2,
+            }),
+        )];
+        let mut a1 = child_header(&genesis, 1);
+        a1.digest = header::DigestRef::from_slice(&digest_items).unwrap().into();
+        a1
+    };
+    let a2 = child_header(&a1, 1);
+    let a3 = child_header(&a2, 1);
+    let a4 = child_header(&a3, 1);
+    let a5 = child_header(&a4, 1);
+    for header in [&a1, &a2, &a3, &a4, &a5] {
+        insert(&mut tree, header);
+    }
+    assert_eq!(tree.grandpa_best_candidate(), (3, &a3.hash()));
+
+    // A fork without any change becomes the candidate once it is longer than the `a` chain
+    // allows.
+    let b1 = child_header(&genesis, 2);
+    let b2 = child_header(&b1, 2);
+    let b3 = child_header(&b2, 2);
+    for header in [&b1, &b2, &b3] {
+        insert(&mut tree, header);
+    }
+    // `a3` and `b3` have the same number, but `a3` is an ancestor of the current best block.
+    assert_eq!(tree.best_block_hash(), a5.hash());
+    assert_eq!(tree.grandpa_best_candidate(), (3, &a3.hash()));
+
+    let b4 = child_header(&b3, 2);
+    insert(&mut tree, &b4);
+    assert_eq!(tree.grandpa_best_candidate(), (4, &b4.hash()));
+}
+
+#[test]
+fn conflicting_grandpa_scheduled_changes() {
+    let authority = header::GrandpaAuthority {
+        public_key: [1; 32],
+        weight: core::num::NonZeroU64::new(1).unwrap(),
+    };
+
+    let (mut tree, genesis) = new_tree(
+        chain_information::ChainInformationConsensus::AllAuthorized,
+        chain_information::ChainInformationFinality::Grandpa {
+            after_finalized_block_authorities_set_id: 0,
+            finalized_triggered_authorities: vec![authority],
+            finalized_scheduled_change: None,
+        },
+    );
+
+    let scheduled_change = header::DigestItem::GrandpaConsensus(
+        header::GrandpaConsensusLog::ScheduledChange(header::GrandpaScheduledChange {
+            next_authorities: vec![authority],
+            delay: 2,
+        }),
+    );
+    let forced_change =
+        header::DigestItem::GrandpaConsensus(header::GrandpaConsensusLog::ForcedChange {
+            reset_block_height: 0,
+            change: header::GrandpaScheduledChange {
+                next_authorities: vec![authority],
+                delay: 0,
+            },
+        });
+    let with_digest = |mut header: header::Header, items: &[header::DigestItem]| {
+        header.digest = header::DigestRef::from_slice(items).unwrap().into();
+        header
+    };
+
+    // `a1` schedules a change that is triggered at block #3.
+    let a1 = with_digest(child_header(&genesis, 1), &[scheduled_change.clone()]);
+    insert(&mut tree, &a1);
+
+    // Scheduling another change before the one of `a1` is enacted is forbidden.
+    let a2 = with_digest(child_header(&a1, 1), &[scheduled_change.clone()]);
+    assert!(matches!(
+        tree.verify_header(a2.scale_encoding_vec(), Duration::new(0, 0)),
+        Err(super::HeaderVerifyError::ConflictingScheduledChange {
+            pending_trigger_block_height: 3
+        })
+    ));
+
+    // Forced changes, however, override the pending scheduled change.
+    let b2 = with_digest(
+        child_header(&a1, 2),
+        &[scheduled_change.clone(), forced_change],
+    );
+    insert(&mut tree, &b2);
+
+    // The change is still pending at the block where it is triggered.
+    let a2 = child_header(&a1, 1);
+    insert(&mut tree, &a2);
+    let a3 = with_digest(child_header(&a2, 1), &[scheduled_change.clone()]);
+    assert!(matches!(
+        tree.verify_header(a3.scale_encoding_vec(), Duration::new(0, 0)),
+        Err(super::HeaderVerifyError::ConflictingScheduledChange {
+            pending_trigger_block_height: 3
+        })
+    ));
+
+    // Once enacted, a new change can be scheduled.
+    let a3 = child_header(&a2, 1);
+    insert(&mut tree, &a3);
+    let a4 = with_digest(child_header(&a3, 1), &[scheduled_change]);
+    insert(&mut tree, &a4);
+}
+
+#[test]
+fn aura_authorities_change() {
+    let keypairs = [1, 2].map(|n| {
+        schnorrkel::MiniSecretKey::from_bytes(&[n; 32])
+            .unwrap()
+            .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519)
+    });
+    let authority = |n: usize| header::AuraAuthority {
+        public_key: keypairs[n].public.to_bytes(),
+    };
+
+    // Builds a child of `parent` at the given slot, signed by `keypairs[signer]`.
+    let aura_child = |parent: &header::Header,
+                      slot_number: u64,
+                      authorities_change: Option<header::AuraAuthority>,
+                      signer: usize| {
+        let mut digest_items = vec![header::DigestItem::AuraPreDigest(header::AuraPreDigest {
+            slot_number,
+        })];
+        if let Some(new_authority) = authorities_change {
+            digest_items.push(header::DigestItem::AuraConsensus(
+                header::AuraConsensusLog::AuthoritiesChange(vec![new_authority]),
+            ));
+        }
+
+        let mut header = child_header(parent, 0);
+        header.digest = header::DigestRef::from_slice(&digest_items).unwrap().into();
+        let signature = keypairs[signer].sign_simple(b"substrate", &header.hash());
+        header.digest.push_aura_seal(signature.to_bytes()).unwrap();
+        header
+    };
+
+    let (mut tree, genesis) = new_tree(
+        chain_information::ChainInformationConsensus::Aura {
+            finalized_authorities_list: vec![authority(0)],
+            slot_duration: core::num::NonZeroU64::new(6000).unwrap(),
+        },
+        chain_information::ChainInformationFinality::Outsourced,
+    );
+
+    // `a1` is produced by the initial authority and hands over to the second one.
+    let a1 = aura_child(&genesis, 1, Some(authority(1)), 0);
+    assert_eq!(
+        a1.digest
+            .aura_authorities_change()
+            .unwrap()
+            .map(header::AuraAuthority::from)
+            .collect::<Vec<_>>(),
+        vec![authority(1)]
+    );
+    insert(&mut tree, &a1);
+
+    // Children of `a1` must be produced by the new authority.
+    let a2 = aura_child(&a1, 2, None, 0);
+    assert!(a2.digest.aura_authorities_change().is_none());
+    assert!(matches!(
+        tree.verify_header(a2.scale_encoding_vec(), Duration::new(0, 0)),
+        Err(super::HeaderVerifyError::VerificationFailed(
+            crate::verify::header_only::Error::AuraVerification(
+                crate::verify::aura::VerifyError::BadSignature
+            )
+        ))
+    ));
+    let a2 = aura_child(&a1, 2, None, 1);
+    insert(&mut tree, &a2);
+
+    // Once finalized, the new list of authorities is reported in the chain information.
+    tree.set_finalized_block(&a2.hash()).unwrap().for_each(drop);
+    match tree.as_chain_information().as_ref().consensus {
+        chain_information::ChainInformationConsensusRef::Aura {
+            finalized_authorities_list,
+            ..
+        } => assert_eq!(
+            finalized_authorities_list
+                .map(header::AuraAuthority::from)
+                .collect::<Vec<_>>(),
+            vec![authority(1)]
+        ),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn reorg() {
+    let (mut tree, genesis) = new_tree(
+        chain_information::ChainInformationConsensus::AllAuthorized,
+        chain_information::ChainInformationFinality::Outsourced,
+    );
+
+    // Builds the following tree:
+    //
+    // genesis -> a1 -> a2 -> a3 -> a4
+    //              \-> b2 -> b3
+    let a1 = child_header(&genesis, 1);
+    let a2 = child_header(&a1, 1);
+    let a3 = child_header(&a2, 1);
+    let a4 = child_header(&a3, 1);
+    let b2 = child_header(&a1, 2);
+    let b3 = child_header(&b2, 2);
+    for header in [&a1, &a2, &a3, &a4, &b2, &b3] {
+        insert(&mut tree, header);
+    }
+    assert_eq!(tree.best_block_hash(), a4.hash());
+
+    // Switching between two forks is a re-org, but going to a descendant isn't.
+    assert_eq!(
+        tree.ancestry_path(&a3.hash(), &b3.hash())
+            .unwrap()
+            .into_reorg(),
+        Some(super::Reorg {
+            retracted: vec![a3.hash(), a2.hash()],
+            common_ancestor: a1.hash(),
+            enacted: vec![b2.hash(), b3.hash()],
+        })
+    );
+    assert_eq!(
+        tree.ancestry_path(&a1.hash(), &a4.hash())
+            .unwrap()
+            .into_reorg(),
+        None
+    );
+
+    // Finalizing `b2` while `a4` is the best block forces a re-org towards `b3`.
+    let iter = tree.set_finalized_block(&b2.hash()).unwrap();
+    assert!(iter.updates_best_block());
+    assert_eq!(
+        iter.reorg(),
+        Some(&super::Reorg {
+            retracted: vec![a4.hash(), a3.hash(), a2.hash()],
+            common_ancestor: a1.hash(),
+            enacted: vec![b2.hash(), b3.hash()],
+        })
+    );
+    drop(iter);
+    assert_eq!(tree.best_block_hash(), b3.hash());
+    assert_eq!(tree.finalized_block_hash(), b2.hash());
+
+    // Finalizing an ancestor of the best block doesn't re-org.
+    let iter = tree.set_finalized_block(&b3.hash()).unwrap();
+    assert!(iter.reorg().is_none());
+}
+
+#[test]
+fn best_and_finalized_block() {
+    let (mut tree, genesis) = new_tree(
+        chain_information::ChainInformationConsensus::AllAuthorized,
+        chain_information::ChainInformationFinality::Outsourced,
+    );
+
+    // Before any block is imported, both are the block of the chain information.
+    assert_eq!(tree.best_block(), (genesis.hash(), 0));
+    assert_eq!(tree.finalized_block(), (genesis.hash(), 0));
+
+    let a1 = child_header(&genesis, 1);
+    let a2 = child_header(&a1, 1);
+    insert(&mut tree, &a1);
+    assert_eq!(tree.best_block(), (a1.hash(), 1));
+    insert(&mut tree, &a2);
+    assert_eq!(tree.best_block(), (a2.hash(), 2));
+    assert_eq!(tree.finalized_block(), (genesis.hash(), 0));
+
+    // A shorter fork doesn't change the best block.
+    let b1 = child_header(&genesis, 2);
+    insert(&mut tree, &b1);
+    assert_eq!(tree.best_block(), (a2.hash(), 2));
+
+    drop(tree.set_finalized_block(&a1.hash()).unwrap());
+    assert_eq!(tree.best_block(), (a2.hash(), 2));
+    assert_eq!(tree.finalized_block(), (a1.hash(), 1));
+
+    drop(tree.set_finalized_block(&a2.hash()).unwrap());
+    assert_eq!(tree.best_block(), (a2.hash(), 2));
+    assert_eq!(tree.finalized_block(), (a2.hash(), 2));
+    assert_eq!(tree.best_block_hash(), a2.hash());
+    assert_eq!(tree.finalized_block_hash(), a2.hash());
+}
@@ -173,6 +173,25 @@ impl<T> NonFinalizedTreeInner<T> {
             }
         };
 
+        // Only one GrandPa scheduled change can be pending at any given time.
+        if let Some(pending_trigger_block_height) =
+            self.grandpa_conflicting_scheduled_change(parent_tree_index, &decoded_header)
+        {
+            return if full {
+                VerifyOut::Body(BodyVerifyStep1::ConflictingScheduledChange {
+                    chain: NonFinalizedTree { inner: Some(self) },
+                    pending_trigger_block_height,
+                })
+            } else {
+                VerifyOut::HeaderErr(
+                    self,
+                    HeaderVerifyError::ConflictingScheduledChange {
+                        pending_trigger_block_height,
+                    },
+                )
+            };
+        }
+
         // Some consensus-specific information must be fetched from the tree of ancestry. The
         // information is found either in the parent block, or in the finalized block.
         let consensus = if let Some(parent_tree_index) = parent_tree_index {
@@ -267,6 +286,7 @@ impl<T> NonFinalizedTreeInner<T> {
                 },
                 block_header: (&context.header).into(), // TODO: inefficiency ; in case of header only verify we do an extra allocation to build the context above
                 parent_block_header: parent_block_header.into(),
+                skip_seal_verification: false,
             })
             .map_err(HeaderVerifyError::VerificationFailed);
 
@@ -355,14 +375,19 @@ impl<T> VerifyContext<T> {
                 FinalizedConsensus::Aura { .. },
                 _,
             ) => {
-                if authorities_change {
-                    todo!() // TODO: fetch from header
-                            /*BlockConsensus::Aura {
-                                authorities_list:
-                            }*/
-                } else {
-                    BlockConsensus::Aura {
-                        authorities_list: parent_authorities.clone(),
+                // The new list of authorities, if any, applies to the children of this block.
+                match self.header.digest.aura_authorities_change() {
+                    Some(new_list) => {
+                        debug_assert!(authorities_change);
+                        BlockConsensus::Aura {
+                            authorities_list: Arc::new(new_list.map(Into::into).collect()),
+                        }
+                    }
+                    None => {
+                        debug_assert!(!authorities_change);
+                        BlockConsensus::Aura {
+                            authorities_list: parent_authorities.clone(),
+                        }
                     }
                 }
             }
@@ -484,6 +509,23 @@ impl<T> VerifyContext<T> {
 
     fn with_body_verify(mut self, inner: verify::header_body::Verify) -> BodyVerifyStep2<T> {
         match inner {
+            verify::header_body::Verify::Finished(Ok(success))
+                if !success.inherents_warnings.is_empty() =>
+            {
+                // Blocks that aren't valid yet are refused, even if the runtime considers that
+                // they might become valid later.
+                BodyVerifyStep2::Error {
+                    chain: NonFinalizedTree {
+                        inner: Some(self.chain),
+                    },
+                    error: BodyVerifyError::Consensus(
+                        verify::header_body::Error::CheckInherentsError {
+                            errors: success.inherents_warnings,
+                        },
+                    ),
+                    parent_runtime: success.parent_runtime,
+                }
+            }
             verify::header_body::Verify::Finished(Ok(success)) => {
                 // TODO: lots of code in common with header verification
 
@@ -561,6 +603,14 @@ pub enum BodyVerifyStep1<T> {
         parent_hash: [u8; 32],
     },
 
+    /// The block schedules a change in the list of GrandPa authorities while another scheduled
+    /// change is still pending.
+    ConflictingScheduledChange {
+        chain: NonFinalizedTree<T>,
+        /// Height of the block where the pending change is triggered.
+        pending_trigger_block_height: u64,
+    },
+
     /// Verification is pending. In order to continue, a [`host::HostVmPrototype`] of the
     /// runtime of the parent block must be provided.
     ParentRuntimeRequired(BodyVerifyRuntimeRequired<T>),
@@ -707,6 +757,10 @@ impl<T> BodyVerifyRuntimeRequired<T> {
             parent_block_header: parent_block_header.into(),
             block_body,
             top_trie_root_calculation_cache,
+            record_accessed_keys: false,
+            check_inherents: true,
+            cache_storage_reads: true,
+            skip_seal_verification: false,
         });
 
         self.context.with_body_verify(process)
@@ -1092,6 +1146,17 @@ pub enum HeaderVerifyError {
         /// Hash of the parent block in question.
         parent_hash: [u8; 32],
     },
+    /// The block schedules a change in the list of GrandPa authorities while another scheduled
+    /// change is still pending.
+    #[display(
+        fmt = "Block schedules a GrandPa authorities change while the one triggered at \
+               block #{} is still pending.",
+        pending_trigger_block_height
+    )]
+    ConflictingScheduledChange {
+        /// Height of the block where the pending change is triggered.
+        pending_trigger_block_height: u64,
+    },
     /// The block verification has failed. The block is invalid and should be thrown away.
     VerificationFailed(verify::header_only::Error),
 }
@@ -38,7 +38,10 @@
 //! They also do not contain the past history of the chain. It is, however, similarly possible to
 //! for instance download the history from other nodes.
 
-use crate::header;
+use crate::{
+    executor::{self, host, vm},
+    header,
+};
 
 use alloc::vec::Vec;
 use core::num::NonZeroU64;
@@ -258,6 +261,16 @@ impl BabeEpochInformation {
     pub fn validate(&self) -> Result<(), BabeValidityError> {
         BabeEpochInformationRef::from(self).validate()
     }
+
+    /// See [`BabeEpochInformationRef::next_epoch_start_slot`].
+    pub fn next_epoch_start_slot(&self, slots_per_epoch: NonZeroU64) -> Option<u64> {
+        BabeEpochInformationRef::from(self).next_epoch_start_slot(slots_per_epoch)
+    }
+
+    /// See [`BabeEpochInformationRef::slot_to_epoch_index`].
+    pub fn slot_to_epoch_index(&self, slot: u64, slots_per_epoch: NonZeroU64) -> Option<u64> {
+        BabeEpochInformationRef::from(self).slot_to_epoch_index(slot, slots_per_epoch)
+    }
 }
 
 impl<'a> From<BabeEpochInformationRef<'a>> for BabeEpochInformation {
@@ -516,6 +529,40 @@ impl<'a> BabeEpochInformationRef<'a> {
 
         Ok(())
     }
+
+    /// Returns the slot at which the epoch that follows this one starts.
+    ///
+    /// `slots_per_epoch` must be the value found in
+    /// [`ChainInformationConsensus::Babe::slots_per_epoch`].
+    ///
+    /// Returns `None` if [`BabeEpochInformationRef::start_slot_number`] is `None`, which is the
+    /// case for epoch number 0 as long as the slot of block #1 isn't known, or in case of
+    /// overflow.
+    pub fn next_epoch_start_slot(&self, slots_per_epoch: NonZeroU64) -> Option<u64> {
+        self.start_slot_number?.checked_add(slots_per_epoch.get())
+    }
+
+    /// Returns the index of the epoch the given slot belongs to, assuming that this epoch and
+    /// all the ones that follow last `slots_per_epoch` slots.
+    ///
+    /// `slots_per_epoch` must be the value found in
+    /// [`ChainInformationConsensus::Babe::slots_per_epoch`].
+    ///
+    /// Epoch number 0 starts at the slot of block #1 rather than at a multiple of
+    /// `slots_per_epoch`. The calculation is thus relative to the start of this epoch and never
+    /// to slot 0.
+    ///
+    /// Returns `None` if [`BabeEpochInformationRef::start_slot_number`] is `None`, or if `slot`
+    /// is inferior to the start of this epoch.
+    ///
+    /// > **Note**: If no block is produced during an entire epoch, Substrate skips this epoch
+    /// >           altogether. The returned value is only exact as long as no epoch between
+    /// >           this one and the one of `slot` gets skipped.
+    pub fn slot_to_epoch_index(&self, slot: u64, slots_per_epoch: NonZeroU64) -> Option<u64> {
+        let slots_since_start = slot.checked_sub(self.start_slot_number?)?;
+        self.epoch_index
+            .checked_add(slots_since_start / slots_per_epoch.get())
+    }
 }
 
 impl<'a> From<&'a BabeEpochInformation> for BabeEpochInformationRef<'a> {
@@ -599,3 +646,155 @@ pub enum BabeValidityError {
     /// denominator.
     InvalidConstant,
 }
+
+/// Error while running a runtime call in order to extract information from the runtime.
+///
+/// Shared by [`aura_config`], [`babe_genesis_config`] and [`grandpa_genesis_config`]. Errors
+/// related to decoding the output of the runtime call are specific to each module.
+#[derive(Debug, derive_more::Display)]
+pub enum RuntimeCallError {
+    /// Runtime couldn't be found in the storage.
+    RuntimeNotFound,
+    /// Failed to decode heap pages from the storage.
+    HeapPagesDecode(executor::InvalidHeapPagesError),
+    /// Error when initializing the virtual machine.
+    VmInitialization(host::NewErr),
+    /// Error when starting the virtual machine.
+    #[display(fmt = "{}", _0)]
+    VmStart(host::StartErr, host::HostVmPrototype),
+    /// Crash while running the virtual machine.
+    Trapped,
+    /// Virtual machine tried to call a host function that isn't valid in this context.
+    HostFunctionNotAllowed,
+}
+
+impl RuntimeCallError {
+    /// Returns `true` if this error is about an invalid function.
+    pub fn is_function_not_found(&self) -> bool {
+        matches!(
+            self,
+            RuntimeCallError::VmStart(
+                host::StartErr::VirtualMachine(
+                    vm::StartErr::FunctionNotFound | vm::StartErr::NotAFunction
+                ),
+                _
+            )
+        )
+    }
+}
+
+impl From<executor::CallRuntimeError> for RuntimeCallError {
+    fn from(err: executor::CallRuntimeError) -> Self {
+        match err {
+            executor::CallRuntimeError::Start(err, proto) => RuntimeCallError::VmStart(err, proto),
+            executor::CallRuntimeError::Run(..) => RuntimeCallError::Trapped,
+            executor::CallRuntimeError::ForbiddenHostFunction(_) => {
+                RuntimeCallError::HostFunctionNotAllowed
+            }
+        }
+    }
+}
+
+/// Builds the virtual machine prototype of the runtime found in a storage.
+///
+/// Must be passed a closure that returns the storage value corresponding to the given key in
+/// the storage.
+fn runtime_from_storage(
+    mut storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
+) -> Result<host::HostVmPrototype, RuntimeCallError> {
+    let wasm_code = storage_access(b":code").ok_or(RuntimeCallError::RuntimeNotFound)?;
+    let heap_pages =
+        executor::storage_heap_pages_to_value(storage_access(b":heappages").as_deref())
+            .map_err(RuntimeCallError::HeapPagesDecode)?;
+    host::HostVmPrototype::new(host::Config {
+        module: &wasm_code,
+        heap_pages,
+        exec_hint: vm::ExecHint::Oneshot,
+        allow_unresolved_imports: false,
+    })
+    .map_err(RuntimeCallError::VmInitialization)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BabeEpochInformation;
+    use crate::header;
+    use core::num::NonZeroU64;
+
+    fn epoch(epoch_index: u64, start_slot_number: Option<u64>) -> BabeEpochInformation {
+        BabeEpochInformation {
+            epoch_index,
+            start_slot_number,
+            authorities: Vec::new(),
+            randomness: [0; 32],
+            c: (1, 4),
+            allowed_slots: header::BabeAllowedSlots::PrimarySlots,
+        }
+    }
+
+    #[test]
+    fn genesis_epoch_boundary() {
+        let slots_per_epoch = NonZeroU64::new(10).unwrap();
+
+        // The start of epoch 0 isn't known before block #1.
+        let genesis_epoch = epoch(0, None);
+        assert_eq!(genesis_epoch.next_epoch_start_slot(slots_per_epoch), None);
+        assert_eq!(
+            genesis_epoch.slot_to_epoch_index(1005, slots_per_epoch),
+            None
+        );
+
+        // Block #1 is at slot 1003, which isn't a multiple of the epoch duration.
+        let genesis_epoch = epoch(0, Some(1003));
+        assert_eq!(
+            genesis_epoch.next_epoch_start_slot(slots_per_epoch),
+            Some(1013)
+        );
+        assert_eq!(
+            genesis_epoch.slot_to_epoch_index(1002, slots_per_epoch),
+            None
+        );
+        assert_eq!(
+            genesis_epoch.slot_to_epoch_index(1003, slots_per_epoch),
+            Some(0)
+        );
+        assert_eq!(
+            genesis_epoch.slot_to_epoch_index(1012, slots_per_epoch),
+            Some(0)
+        );
+        assert_eq!(
+            genesis_epoch.slot_to_epoch_index(1013, slots_per_epoch),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn later_slots() {
+        let slots_per_epoch = NonZeroU64::new(2400).unwrap();
+        let current = epoch(57, Some(1_000_000));
+
+        assert_eq!(
+            current.next_epoch_start_slot(slots_per_epoch),
+            Some(1_002_400)
+        );
+        assert_eq!(
+            current.slot_to_epoch_index(1_002_399, slots_per_epoch),
+            Some(57)
+        );
+        assert_eq!(
+            current.slot_to_epoch_index(1_002_400, slots_per_epoch),
+            Some(58)
+        );
+        assert_eq!(
+            current.slot_to_epoch_index(1_000_000 + 2400 * 100 + 17, slots_per_epoch),
+            Some(157)
+        );
+        assert_eq!(current.slot_to_epoch_index(999_999, slots_per_epoch), None);
+
+        // Overflows are reported as `None`.
+        assert_eq!(
+            epoch(0, Some(u64::MAX)).next_epoch_start_slot(slots_per_epoch),
+            None
+        );
+    }
+}
@@ -19,18 +19,19 @@
 //!
 //! It can be used on any block.
 
+use super::RuntimeCallError;
 use crate::{
-    executor::{self, host, vm},
-    header,
+    executor::{self, host},
+    header, util,
 };
 
 use alloc::vec::Vec;
-use core::num::NonZeroU64;
+use core::{iter, num::NonZeroU64};
 
 /// Aura configuration of a chain, as extracted from a block.
 ///
 /// The way a chain configures Aura is stored in its runtime.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AuraConfiguration {
     /// List of authorities that can validate block #1.
     pub authorities_list: Vec<header::AuraAuthority>,
@@ -47,17 +48,8 @@ impl AuraConfiguration {
     pub fn from_storage(
         mut storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
     ) -> Result<Self, FromStorageError> {
-        let wasm_code = storage_access(b":code").ok_or(FromStorageError::RuntimeNotFound)?;
-        let heap_pages =
-            executor::storage_heap_pages_to_value(storage_access(b":heappages").as_deref())
-                .map_err(FromStorageError::HeapPagesDecode)?;
-        let vm = host::HostVmPrototype::new(host::Config {
-            module: &wasm_code,
-            heap_pages,
-            exec_hint: vm::ExecHint::Oneshot,
-            allow_unresolved_imports: false,
-        })
-        .map_err(FromStorageError::VmInitialization)?;
+        let vm = super::runtime_from_storage(&mut storage_access)
+            .map_err(FromStorageError::RuntimeCall)?;
         let (cfg, _) = Self::from_virtual_machine_prototype(vm, storage_access)
             .map_err(FromStorageError::VmError)?;
         Ok(cfg)
@@ -73,69 +65,22 @@ impl AuraConfiguration {
         vm: host::HostVmPrototype,
         mut storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
     ) -> Result<(Self, host::HostVmPrototype), FromVmPrototypeError> {
-        let mut vm: host::HostVm = vm
-            .run_no_param("AuraApi_slot_duration")
-            .map_err(|(err, proto)| FromVmPrototypeError::VmStart(err, proto))?
-            .into();
-
-        let (slot_duration, vm_prototype) = loop {
-            match vm {
-                host::HostVm::ReadyToRun(r) => vm = r.run(),
-                host::HostVm::Finished(finished) => {
-                    let slot_duration = NonZeroU64::new(u64::from_le_bytes(
-                        <[u8; 8]>::try_from(finished.value().as_ref())
-                            .map_err(|_| FromVmPrototypeError::BadSlotDuration)?,
-                    ))
-                    .ok_or(FromVmPrototypeError::BadSlotDuration)?;
-                    break (slot_duration, finished.into_prototype());
-                }
-                host::HostVm::Error { .. } => return Err(FromVmPrototypeError::Trapped),
-
-                host::HostVm::ExternalStorageGet(req) => {
-                    let value = storage_access(req.key().as_ref());
-                    vm = req.resume_full_value(value.as_ref().map(|v| &v[..]));
-                }
-
-                host::HostVm::GetMaxLogLevel(resume) => {
-                    vm = resume.resume(0); // Off
-                }
-                host::HostVm::LogEmit(req) => vm = req.resume(),
-
-                _ => return Err(FromVmPrototypeError::HostFunctionNotAllowed),
-            }
-        };
+        let (slot_duration, vm_prototype) =
+            executor::call_runtime(vm, "AuraApi_slot_duration", &[], &mut storage_access)
+                .map_err(|err| FromVmPrototypeError::RuntimeCall(err.into()))?;
+        let slot_duration = NonZeroU64::new(u64::from_le_bytes(
+            <[u8; 8]>::try_from(&slot_duration[..])
+                .map_err(|_| FromVmPrototypeError::BadSlotDuration)?,
+        ))
+        .ok_or(FromVmPrototypeError::BadSlotDuration)?;
 
-        let mut vm: host::HostVm = vm_prototype
-            .run_no_param("AuraApi_authorities")
-            .map_err(|(err, proto)| FromVmPrototypeError::VmStart(err, proto))?
-            .into();
-
-        let (authorities_list, vm_prototype) = loop {
-            match vm {
-                host::HostVm::ReadyToRun(r) => vm = r.run(),
-                host::HostVm::Finished(finished) => {
-                    let authorities_list =
-                        header::AuraAuthoritiesIter::decode(finished.value().as_ref())
-                            .map_err(|_| FromVmPrototypeError::AuthoritiesListDecodeError)?
-                            .map(header::AuraAuthority::from)
-                            .collect::<Vec<_>>();
-                    break (authorities_list, finished.into_prototype());
-                }
-                host::HostVm::Error { .. } => return Err(FromVmPrototypeError::Trapped),
-
-                host::HostVm::ExternalStorageGet(req) => {
-                    let value = storage_access(req.key().as_ref());
-                    vm = req.resume_full_value(value.as_ref().map(|v| &v[..]));
-                }
-
-                host::HostVm::GetMaxLogLevel(resume) => {
-                    vm = resume.resume(0); // Off
-                }
-                host::HostVm::LogEmit(req) => vm = req.resume(),
-
-                _ => return Err(FromVmPrototypeError::HostFunctionNotAllowed),
-            }
-        };
+        let (authorities_list, vm_prototype) =
+            executor::call_runtime(vm_prototype, "AuraApi_authorities", &[], storage_access)
+                .map_err(|err| FromVmPrototypeError::RuntimeCall(err.into()))?;
+        let authorities_list = header::AuraAuthoritiesIter::decode(&authorities_list)
+            .map_err(|_| FromVmPrototypeError::AuthoritiesListDecodeError)?
+            .map(header::AuraAuthority::from)
+            .collect::<Vec<_>>();
 
         let outcome = AuraConfiguration {
             authorities_list,
@@ -144,41 +89,143 @@ impl AuraConfiguration {
 
         Ok((outcome, vm_prototype))
     }
+
+    /// Returns the number of the slot that the given UNIX timestamp, in milliseconds, belongs to.
+    ///
+    /// > **Note**: Aura slots are counted from the UNIX epoch rather than from the genesis block.
+    /// >           The timestamp of the genesis block is therefore irrelevant.
+    pub fn current_slot(&self, now_ms: u64) -> u64 {
+        now_ms / self.slot_duration.get()
+    }
+
+    /// Returns the number of milliseconds elapsed since the start of the slot that the given UNIX
+    /// timestamp, in milliseconds, belongs to.
+    ///
+    /// The returned value is always strictly inferior to [`AuraConfiguration::slot_duration`].
+    pub fn offset_in_current_slot(&self, now_ms: u64) -> u64 {
+        now_ms % self.slot_duration.get()
+    }
+
+    /// Returns the number of milliseconds between the given UNIX timestamp, in milliseconds, and
+    /// the start of the next slot.
+    ///
+    /// The returned value is always non-zero and inferior or equal to
+    /// [`AuraConfiguration::slot_duration`].
+    pub fn time_until_next_slot(&self, now_ms: u64) -> u64 {
+        self.slot_duration.get() - self.offset_in_current_slot(now_ms)
+    }
+
+    /// Returns the UNIX timestamp, in milliseconds, at which the given slot starts.
+    ///
+    /// Saturates at `u64::MAX` if the slot starts after that.
+    pub fn slot_start(&self, slot: u64) -> u64 {
+        slot.saturating_mul(self.slot_duration.get())
+    }
+
+    /// Returns an iterator to list of buffers which, when concatenated, produces the encoding of
+    /// that object.
+    ///
+    /// The encoding consists in a version byte (currently always `0`), followed with the
+    /// SCALE-encoded list of authorities and the little endian slot duration. It can be decoded
+    /// back with [`AuraConfiguration::decode`].
+    pub fn scale_encoding(
+        &'_ self,
+    ) -> impl Iterator<Item = impl AsRef<[u8]> + Clone + '_> + Clone + '_ {
+        let header = iter::once(either::Left([ENCODING_VERSION]))
+            .chain(iter::once(either::Right(util::encode_scale_compact_usize(
+                self.authorities_list.len(),
+            ))))
+            .map(either::Left);
+
+        let authorities = self
+            .authorities_list
+            .iter()
+            .map(|authority| either::Right(either::Left(authority.public_key)));
+
+        header
+            .chain(authorities)
+            .chain(iter::once(either::Right(either::Right(
+                self.slot_duration.get().to_le_bytes(),
+            ))))
+    }
+
+    /// Decodes a configuration previously encoded with [`AuraConfiguration::scale_encoding`].
+    pub fn decode(encoded: &[u8]) -> Result<Self, DecodeError> {
+        let (version, encoded) = encoded.split_first().ok_or(DecodeError::TooShort)?;
+        if *version != ENCODING_VERSION {
+            return Err(DecodeError::UnsupportedVersion(*version));
+        }
+
+        if encoded.len() < 8 {
+            return Err(DecodeError::TooShort);
+        }
+        let (authorities_list, slot_duration) = encoded.split_at(encoded.len() - 8);
+
+        let authorities_list = header::AuraAuthoritiesIter::decode(authorities_list)
+            .map_err(|_| DecodeError::BadAuthoritiesList)?
+            .map(header::AuraAuthority::from)
+            .collect::<Vec<_>>();
+
+        let slot_duration = NonZeroU64::new(u64::from_le_bytes(
+            <[u8; 8]>::try_from(slot_duration).unwrap(),
+        ))
+        .ok_or(DecodeError::ZeroSlotDuration)?;
+
+        Ok(AuraConfiguration {
+            authorities_list,
+            slot_duration,
+        })
+    }
+}
+
+/// Version byte found at the start of the output of [`AuraConfiguration::scale_encoding`].
+const ENCODING_VERSION: u8 = 0;
+
+/// Error when decoding an encoded Aura configuration.
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeError {
+    /// Encoded data is too short.
+    TooShort,
+    /// Encoded data uses an unknown version of the format.
+    #[display(fmt = "Unsupported encoding version: {}", _0)]
+    UnsupportedVersion(u8),
+    /// Failed to decode the list of authorities.
+    BadAuthoritiesList,
+    /// Slot duration is equal to zero.
+    ZeroSlotDuration,
 }
 
 /// Error when retrieving the Aura configuration.
 #[derive(Debug, derive_more::Display)]
 pub enum FromStorageError {
-    /// Runtime couldn't be found in the storage.
-    RuntimeNotFound,
-    /// Failed to decode heap pages from the storage.
-    HeapPagesDecode(executor::InvalidHeapPagesError),
-    /// Error when initializing the virtual machine.
-    VmInitialization(host::NewErr),
+    /// Error while preparing the runtime.
+    #[display(fmt = "{}", _0)]
+    RuntimeCall(RuntimeCallError),
     /// Error while executing the runtime.
     VmError(FromVmPrototypeError),
 }
 
 impl FromStorageError {
-    /// Returns `true` if this error is about an invalid function.
-    pub fn is_function_not_found(&self) -> bool {
+    /// Returns the error that happened during the runtime call, if any.
+    pub fn runtime_call_error(&self) -> Option<&RuntimeCallError> {
         match self {
-            FromStorageError::VmError(err) => err.is_function_not_found(),
-            _ => false,
+            FromStorageError::RuntimeCall(err) => Some(err),
+            FromStorageError::VmError(err) => err.runtime_call_error(),
         }
     }
+
+    /// Returns `true` if this error is about an invalid function.
+    pub fn is_function_not_found(&self) -> bool {
+        matches!(self.runtime_call_error(), Some(err) if err.is_function_not_found())
+    }
 }
 
 /// Error when retrieving the Aura configuration.
 #[derive(Debug, derive_more::Display)]
 pub enum FromVmPrototypeError {
-    /// Error when starting the virtual machine.
+    /// Error while running the runtime.
     #[display(fmt = "{}", _0)]
-    VmStart(host::StartErr, host::HostVmPrototype),
-    /// Crash while running the virtual machine.
-    Trapped,
-    /// Virtual machine tried to call a host function that isn't valid in this context.
-    HostFunctionNotAllowed,
+    RuntimeCall(RuntimeCallError),
     /// Error while decoding the output of the virtual machine for `AuraApi_slot_duration`.
     BadSlotDuration,
     /// Failed to decode the list of authorities returned by `AuraApi_authorities`.
@@ -186,16 +233,159 @@ pub enum FromVmPrototypeError {
 }
 
 impl FromVmPrototypeError {
+    /// Returns the error that happened during the runtime call, if any.
+    pub fn runtime_call_error(&self) -> Option<&RuntimeCallError> {
+        match self {
+            FromVmPrototypeError::RuntimeCall(err) => Some(err),
+            FromVmPrototypeError::BadSlotDuration
+            | FromVmPrototypeError::AuthoritiesListDecodeError => None,
+        }
+    }
+
     /// Returns `true` if this error is about an invalid function.
     pub fn is_function_not_found(&self) -> bool {
-        matches!(
-            self,
-            FromVmPrototypeError::VmStart(
-                host::StartErr::VirtualMachine(
-                    vm::StartErr::FunctionNotFound | vm::StartErr::NotAFunction
-                ),
-                _
-            )
-        )
+        matches!(self.runtime_call_error(), Some(err) if err.is_function_not_found())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::RuntimeCallError;
+    use crate::header;
+    use core::num::NonZeroU64;
+
+    #[test]
+    fn encode_decode() {
+        let encode = |config: &super::AuraConfiguration| {
+            config.scale_encoding().fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            })
+        };
+
+        let config = super::AuraConfiguration {
+            authorities_list: (0..1000u32)
+                .map(|n| {
+                    let mut public_key = [0; 32];
+                    public_key[..4].copy_from_slice(&n.to_le_bytes());
+                    header::AuraAuthority { public_key }
+                })
+                .collect(),
+            slot_duration: NonZeroU64::new(6000).unwrap(),
+        };
+        let encoded = encode(&config);
+        assert_eq!(encoded[0], 0);
+        assert_eq!(super::AuraConfiguration::decode(&encoded).unwrap(), config);
+
+        for slot_duration in [
+            NonZeroU64::new(1).unwrap(),
+            NonZeroU64::new(u64::MAX).unwrap(),
+        ] {
+            let config = super::AuraConfiguration {
+                authorities_list: Vec::new(),
+                slot_duration,
+            };
+            let encoded = encode(&config);
+            assert_eq!(super::AuraConfiguration::decode(&encoded).unwrap(), config);
+        }
+
+        assert!(matches!(
+            super::AuraConfiguration::decode(&[1, 0, 1, 0, 0, 0, 0, 0, 0, 0]),
+            Err(super::DecodeError::UnsupportedVersion(1))
+        ));
+        assert!(matches!(
+            super::AuraConfiguration::decode(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(super::DecodeError::ZeroSlotDuration)
+        ));
+        assert!(matches!(
+            super::AuraConfiguration::decode(&encoded[..encoded.len() - 1]),
+            Err(super::DecodeError::BadAuthoritiesList)
+        ));
+        assert!(matches!(
+            super::AuraConfiguration::decode(&[0, 0]),
+            Err(super::DecodeError::TooShort)
+        ));
+    }
+
+    #[test]
+    fn slots() {
+        let config = super::AuraConfiguration {
+            authorities_list: Vec::new(),
+            slot_duration: NonZeroU64::new(6000).unwrap(),
+        };
+
+        assert_eq!(config.current_slot(0), 0);
+        assert_eq!(config.offset_in_current_slot(0), 0);
+        assert_eq!(config.time_until_next_slot(0), 6000);
+
+        // Right before, at, and right after a slot boundary.
+        assert_eq!(config.current_slot(11_999), 1);
+        assert_eq!(config.offset_in_current_slot(11_999), 5999);
+        assert_eq!(config.time_until_next_slot(11_999), 1);
+        assert_eq!(config.current_slot(12_000), 2);
+        assert_eq!(config.offset_in_current_slot(12_000), 0);
+        assert_eq!(config.time_until_next_slot(12_000), 6000);
+        assert_eq!(config.current_slot(12_001), 2);
+        assert_eq!(config.time_until_next_slot(12_001), 5999);
+
+        assert_eq!(config.slot_start(2), 12_000);
+        assert_eq!(
+            config.slot_start(config.current_slot(12_001)) + config.offset_in_current_slot(12_001),
+            12_001
+        );
+    }
+
+    #[test]
+    fn slots_near_overflow() {
+        let config = super::AuraConfiguration {
+            authorities_list: Vec::new(),
+            slot_duration: NonZeroU64::new(6000).unwrap(),
+        };
+
+        let last_slot = u64::MAX / 6000;
+        assert_eq!(config.current_slot(u64::MAX), last_slot);
+        assert_eq!(config.offset_in_current_slot(u64::MAX), u64::MAX % 6000);
+        assert_eq!(
+            config.time_until_next_slot(u64::MAX),
+            6000 - u64::MAX % 6000
+        );
+        assert_eq!(config.slot_start(last_slot), u64::MAX - u64::MAX % 6000);
+        assert_eq!(config.slot_start(last_slot + 1), u64::MAX);
+        assert_eq!(config.slot_start(u64::MAX), u64::MAX);
+
+        let config = super::AuraConfiguration {
+            authorities_list: Vec::new(),
+            slot_duration: NonZeroU64::new(u64::MAX).unwrap(),
+        };
+        assert_eq!(config.current_slot(u64::MAX - 1), 0);
+        assert_eq!(config.time_until_next_slot(u64::MAX - 1), 1);
+        assert_eq!(config.current_slot(u64::MAX), 1);
+        assert_eq!(config.time_until_next_slot(u64::MAX), u64::MAX);
+        assert_eq!(config.slot_start(1), u64::MAX);
+        assert_eq!(config.slot_start(2), u64::MAX);
+    }
+
+    #[test]
+    fn function_not_found() {
+        // The Polkadot runtime uses Babe and doesn't provide the Aura runtime API.
+        let code = &include_bytes!("../../executor/vm/test-polkadot-runtime-v9160.wasm")[..];
+
+        let err = super::AuraConfiguration::from_storage(|key| match key {
+            b":code" => Some(code.to_vec()),
+            _ => None,
+        })
+        .unwrap_err();
+        assert!(err.is_function_not_found());
+        assert!(matches!(
+            err.runtime_call_error(),
+            Some(RuntimeCallError::VmStart(..))
+        ));
+
+        let err = super::AuraConfiguration::from_storage(|_| None).unwrap_err();
+        assert!(!err.is_function_not_found());
+        assert!(matches!(
+            err.runtime_call_error(),
+            Some(RuntimeCallError::RuntimeNotFound)
+        ));
     }
 }
@@ -206,7 +206,7 @@ fn decode_babe_info(
             nom::number::complete::le_u64,
             nom::number::complete::le_u64,
             nom::number::complete::le_u64,
-            nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+            nom::combinator::flat_map(crate::util::nom_scale_collection_len(40), |num_elems| {
                 nom::multi::many_m_n(
                     num_elems,
                     num_elems,
@@ -228,7 +228,7 @@ fn decode_babe_info(
             nom::number::complete::le_u64,
             nom::number::complete::le_u64,
             |b| {
-                header::BabeAllowedSlots::from_slice(b)
+                header::BabeAllowedSlots::from_slice_or_default(b)
                     .map(|v| (&[][..], v))
                     .map_err(|_| {
                         nom::Err::Error(nom::error::make_error(b, nom::error::ErrorKind::Verify))
@@ -291,6 +291,17 @@ mod tests {
             0, 0, 0, 0, 2,
         ];
 
-        super::decode_babe_info(&sample_data, true).unwrap();
+        let info = super::decode_babe_info(&sample_data, true).unwrap();
+        assert_eq!(
+            info.allowed_slots,
+            crate::header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots
+        );
+
+        // Older runtimes don't include the allowed slots.
+        let info = super::decode_babe_info(&sample_data[..sample_data.len() - 1], true).unwrap();
+        assert_eq!(
+            info.allowed_slots,
+            crate::header::BabeAllowedSlots::PrimarySlots
+        );
     }
 }
@@ -15,6 +15,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use super::RuntimeCallError;
 use crate::{
     executor::{self, host, vm},
     header,
@@ -41,18 +42,8 @@ impl BabeGenesisConfiguration {
     pub fn from_genesis_storage(
         mut genesis_storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
     ) -> Result<Self, FromGenesisStorageError> {
-        let wasm_code =
-            genesis_storage_access(b":code").ok_or(FromGenesisStorageError::RuntimeNotFound)?;
-        let heap_pages =
-            executor::storage_heap_pages_to_value(genesis_storage_access(b":heappages").as_deref())
-                .map_err(FromGenesisStorageError::HeapPagesDecode)?;
-        let vm = host::HostVmPrototype::new(host::Config {
-            module: &wasm_code,
-            heap_pages,
-            exec_hint: vm::ExecHint::Oneshot,
-            allow_unresolved_imports: false,
-        })
-        .map_err(FromGenesisStorageError::VmInitialization)?;
+        let vm = super::runtime_from_storage(&mut genesis_storage_access)
+            .map_err(FromGenesisStorageError::RuntimeCall)?;
         let (cfg, _) = Self::from_virtual_machine_prototype(vm, genesis_storage_access)
             .map_err(FromGenesisStorageError::VmError)?;
         Ok(cfg)
@@ -63,106 +54,175 @@ impl BabeGenesisConfiguration {
     /// Must be passed a closure that returns the storage value corresponding to the given key in
     /// the genesis block storage.
     ///
+    /// The `BabeApi_configuration_v2` runtime function is called if the runtime provides it.
+    /// Otherwise, this function falls back to `BabeApi_configuration`.
+    ///
     /// Returns back the same virtual machine prototype as was passed as parameter.
     pub fn from_virtual_machine_prototype(
         vm: host::HostVmPrototype,
         mut genesis_storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
     ) -> Result<(Self, host::HostVmPrototype), FromVmPrototypeError> {
-        let mut vm: host::HostVm = vm
-            .run_no_param("BabeApi_configuration")
-            .map_err(|(err, proto)| FromVmPrototypeError::VmStart(err, proto))?
-            .into();
-
-        loop {
-            match vm {
-                host::HostVm::ReadyToRun(r) => vm = r.run(),
-                host::HostVm::Finished(finished) => {
-                    let output = finished.value();
-                    let cfg =
-                        nom::combinator::all_consuming(decode_genesis_config)(output.as_ref())
-                            .map(|(_, parse_result)| parse_result)
-                            .map_err(|_| FromVmPrototypeError::OutputDecode)?;
-                    drop(output);
-                    break Ok((cfg, finished.into_prototype()));
-                }
-                host::HostVm::Error { .. } => break Err(FromVmPrototypeError::Trapped),
-
-                host::HostVm::ExternalStorageGet(req) => {
-                    let value = genesis_storage_access(req.key().as_ref());
-                    vm = req.resume_full_value(value.as_ref().map(|v| &v[..]));
-                }
-
-                host::HostVm::GetMaxLogLevel(resume) => {
-                    vm = resume.resume(0); // Off
-                }
-                host::HostVm::LogEmit(req) => vm = req.resume(),
-
-                _ => break Err(FromVmPrototypeError::HostFunctionNotAllowed),
+        let (output, vm_prototype, is_v2) = match executor::call_runtime(
+            vm,
+            "BabeApi_configuration_v2",
+            &[],
+            &mut genesis_storage_access,
+        ) {
+            Ok((output, vm_prototype)) => (output, vm_prototype, true),
+            Err(executor::CallRuntimeError::Start(
+                host::StartErr::VirtualMachine(
+                    vm::StartErr::FunctionNotFound | vm::StartErr::NotAFunction,
+                ),
+                vm_prototype,
+            )) => {
+                let (output, vm_prototype) = executor::call_runtime(
+                    vm_prototype,
+                    "BabeApi_configuration",
+                    &[],
+                    genesis_storage_access,
+                )
+                .map_err(|err| FromVmPrototypeError::RuntimeCall(err.into()))?;
+                (output, vm_prototype, false)
             }
+            Err(err) => return Err(FromVmPrototypeError::RuntimeCall(err.into())),
+        };
+
+        let cfg = if is_v2 {
+            Self::decode_v2(&output)
+        } else {
+            Self::decode_v1(&output)
         }
+        .ok_or(FromVmPrototypeError::OutputDecode)?;
+        Ok((cfg, vm_prototype))
+    }
+
+    /// Decodes the output of the `BabeApi_configuration` runtime function.
+    ///
+    /// Returns `None` if the output is invalid.
+    pub fn decode_v1(output: &[u8]) -> Option<Self> {
+        nom::combinator::all_consuming(decode_genesis_config_v1)(output)
+            .map(|(_, parse_result)| parse_result)
+            .ok()
+    }
+
+    /// Decodes the output of the `BabeApi_configuration_v2` runtime function.
+    ///
+    /// Returns `None` if the output is invalid.
+    pub fn decode_v2(output: &[u8]) -> Option<Self> {
+        nom::combinator::all_consuming(decode_genesis_config_v2)(output)
+            .map(|(_, parse_result)| parse_result)
+            .ok()
     }
 }
 
 /// Error when retrieving the BABE configuration.
 #[derive(Debug, derive_more::Display)]
 pub enum FromGenesisStorageError {
-    /// Runtime couldn't be found in the genesis storage.
-    RuntimeNotFound,
-    /// Failed to decode heap pages from the genesis storage.
-    HeapPagesDecode(executor::InvalidHeapPagesError),
-    /// Error when initializing the virtual machine.
-    VmInitialization(host::NewErr),
+    /// Error while preparing the runtime.
+    #[display(fmt = "{}", _0)]
+    RuntimeCall(RuntimeCallError),
     /// Error while executing the runtime.
     VmError(FromVmPrototypeError),
 }
 
 impl FromGenesisStorageError {
-    /// Returns `true` if this error is about an invalid function.
-    pub fn is_function_not_found(&self) -> bool {
+    /// Returns the error that happened during the runtime call, if any.
+    pub fn runtime_call_error(&self) -> Option<&RuntimeCallError> {
         match self {
-            FromGenesisStorageError::VmError(err) => err.is_function_not_found(),
-            _ => false,
+            FromGenesisStorageError::RuntimeCall(err) => Some(err),
+            FromGenesisStorageError::VmError(err) => err.runtime_call_error(),
         }
     }
+
+    /// Returns `true` if this error is about an invalid function.
+    pub fn is_function_not_found(&self) -> bool {
+        matches!(self.runtime_call_error(), Some(err) if err.is_function_not_found())
+    }
 }
 
 /// Error when retrieving the BABE configuration.
 #[derive(Debug, derive_more::Display)]
 pub enum FromVmPrototypeError {
-    /// Error when starting the virtual machine.
+    /// Error while running the runtime.
     #[display(fmt = "{}", _0)]
-    VmStart(host::StartErr, host::HostVmPrototype),
-    /// Crash while running the virtual machine.
-    Trapped,
-    /// Virtual machine tried to call a host function that isn't valid in this context.
-    HostFunctionNotAllowed,
+    RuntimeCall(RuntimeCallError),
     /// Error while decoding the output of the virtual machine.
     OutputDecode,
 }
 
 impl FromVmPrototypeError {
+    /// Returns the error that happened during the runtime call, if any.
+    pub fn runtime_call_error(&self) -> Option<&RuntimeCallError> {
+        match self {
+            FromVmPrototypeError::RuntimeCall(err) => Some(err),
+            FromVmPrototypeError::OutputDecode => None,
+        }
+    }
+
     /// Returns `true` if this error is about an invalid function.
     pub fn is_function_not_found(&self) -> bool {
-        matches!(
-            self,
-            FromVmPrototypeError::VmStart(
-                host::StartErr::VirtualMachine(
-                    vm::StartErr::FunctionNotFound | vm::StartErr::NotAFunction
-                ),
-                _
-            )
-        )
+        matches!(self.runtime_call_error(), Some(err) if err.is_function_not_found())
     }
 }
 
-fn decode_genesis_config(bytes: &[u8]) -> nom::IResult<&[u8], BabeGenesisConfiguration> {
+fn decode_genesis_config_v1(bytes: &[u8]) -> nom::IResult<&[u8], BabeGenesisConfiguration> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            decode_genesis_config_common,
+            // The `secondary_slots` boolean is encoded as `0` or `1`, which coincide with the
+            // first two variants of `allowed_slots`. Some runtimes that have switched to
+            // `allowed_slots` without providing `BabeApi_configuration_v2` exist, which is why
+            // the third variant is also accepted here.
+            // Older runtimes don't provide this field at all, in which case only primary slots
+            // are allowed.
+            nom::combinator::map(nom::combinator::opt(allowed_slots), |allowed_slots| {
+                allowed_slots.unwrap_or(header::BabeAllowedSlots::PrimarySlots)
+            }),
+        )),
+        |(common, allowed_slots)| common.into_config(allowed_slots),
+    )(bytes)
+}
+
+fn decode_genesis_config_v2(bytes: &[u8]) -> nom::IResult<&[u8], BabeGenesisConfiguration> {
+    nom::combinator::map(
+        nom::sequence::tuple((decode_genesis_config_common, allowed_slots)),
+        |(common, allowed_slots)| common.into_config(allowed_slots),
+    )(bytes)
+}
+
+/// Fields shared between the outputs of `BabeApi_configuration` and
+/// `BabeApi_configuration_v2`.
+struct GenesisConfigCommon {
+    slots_per_epoch: NonZeroU64,
+    c: (u64, u64),
+    authorities: Vec<header::BabeAuthority>,
+    randomness: [u8; 32],
+}
+
+impl GenesisConfigCommon {
+    fn into_config(self, allowed_slots: header::BabeAllowedSlots) -> BabeGenesisConfiguration {
+        BabeGenesisConfiguration {
+            slots_per_epoch: self.slots_per_epoch,
+            epoch0_configuration: header::BabeNextConfig {
+                c: self.c,
+                allowed_slots,
+            },
+            epoch0_information: header::BabeNextEpoch {
+                randomness: self.randomness,
+                authorities: self.authorities,
+            },
+        }
+    }
+}
+
+fn decode_genesis_config_common(bytes: &[u8]) -> nom::IResult<&[u8], GenesisConfigCommon> {
     nom::combinator::map(
         nom::sequence::tuple((
             nom::number::complete::le_u64,
             nom::combinator::map_opt(nom::number::complete::le_u64, NonZeroU64::new),
             nom::number::complete::le_u64,
             nom::number::complete::le_u64,
-            nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+            nom::combinator::flat_map(crate::util::nom_scale_collection_len(40), |num_elems| {
                 nom::multi::many_m_n(
                     num_elems,
                     num_elems,
@@ -181,31 +241,121 @@ fn decode_genesis_config(bytes: &[u8]) -> nom::IResult<&[u8], BabeGenesisConfigu
             nom::combinator::map(nom::bytes::complete::take(32u32), |b| {
                 <[u8; 32]>::try_from(b).unwrap()
             }),
-            nom::branch::alt((
-                nom::combinator::map(nom::bytes::complete::tag(&[0]), |_| {
-                    header::BabeAllowedSlots::PrimarySlots
-                }),
-                nom::combinator::map(nom::bytes::complete::tag(&[1]), |_| {
-                    header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots
-                }),
-                nom::combinator::map(nom::bytes::complete::tag(&[2]), |_| {
-                    header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots
-                }),
-            )),
         )),
-        |(_slot_duration, slots_per_epoch, c0, c1, authorities, randomness, allowed_slots)| {
+        |(_slot_duration, slots_per_epoch, c0, c1, authorities, randomness)| {
             // Note that the slot duration is unused as it is not modifiable anyway.
-            BabeGenesisConfiguration {
+            GenesisConfigCommon {
                 slots_per_epoch,
-                epoch0_configuration: header::BabeNextConfig {
-                    c: (c0, c1),
-                    allowed_slots,
-                },
-                epoch0_information: header::BabeNextEpoch {
-                    randomness,
-                    authorities,
-                },
+                c: (c0, c1),
+                authorities,
+                randomness,
             }
         },
     )(bytes)
 }
+
+fn allowed_slots(bytes: &[u8]) -> nom::IResult<&[u8], header::BabeAllowedSlots> {
+    nom::branch::alt((
+        nom::combinator::map(nom::bytes::complete::tag(&[0]), |_| {
+            header::BabeAllowedSlots::PrimarySlots
+        }),
+        nom::combinator::map(nom::bytes::complete::tag(&[1]), |_| {
+            header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots
+        }),
+        nom::combinator::map(nom::bytes::complete::tag(&[2]), |_| {
+            header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots
+        }),
+    ))(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::header;
+
+    /// Builds the fields shared by both versions of the configuration, with a slot duration of
+    /// 6000, 2400 slots per epoch, `c` equal to `(1, 4)`, and one authority.
+    fn common_fields() -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&6000u64.to_le_bytes());
+        out.extend_from_slice(&2400u64.to_le_bytes());
+        out.extend_from_slice(&1u64.to_le_bytes());
+        out.extend_from_slice(&4u64.to_le_bytes());
+        out.push(4);
+        out.extend_from_slice(&[0xaa; 32]);
+        out.extend_from_slice(&1u64.to_le_bytes());
+        out.extend_from_slice(&[0xbb; 32]);
+        out
+    }
+
+    fn check_common_fields(config: &super::BabeGenesisConfiguration) {
+        assert_eq!(config.slots_per_epoch.get(), 2400);
+        assert_eq!(config.epoch0_configuration.c, (1, 4));
+        assert_eq!(config.epoch0_information.randomness, [0xbb; 32]);
+        assert_eq!(config.epoch0_information.authorities.len(), 1);
+        assert_eq!(
+            config.epoch0_information.authorities[0].public_key,
+            [0xaa; 32]
+        );
+        assert_eq!(config.epoch0_information.authorities[0].weight, 1);
+    }
+
+    #[test]
+    fn decode_v1() {
+        for (secondary_slots, expected) in [
+            (0, header::BabeAllowedSlots::PrimarySlots),
+            (1, header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots),
+        ] {
+            let mut output = common_fields();
+            output.push(secondary_slots);
+            let config = super::BabeGenesisConfiguration::decode_v1(&output).unwrap();
+            check_common_fields(&config);
+            assert_eq!(config.epoch0_configuration.allowed_slots, expected);
+        }
+
+        // Very old runtimes don't provide `secondary_slots` at all.
+        let config = super::BabeGenesisConfiguration::decode_v1(&common_fields()).unwrap();
+        check_common_fields(&config);
+        assert_eq!(
+            config.epoch0_configuration.allowed_slots,
+            header::BabeAllowedSlots::PrimarySlots
+        );
+
+        let mut output = common_fields();
+        output.push(3);
+        assert!(super::BabeGenesisConfiguration::decode_v1(&output).is_none());
+    }
+
+    #[test]
+    fn decode_v2() {
+        for (allowed_slots, expected) in [
+            (0, header::BabeAllowedSlots::PrimarySlots),
+            (1, header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots),
+            (2, header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots),
+        ] {
+            let mut output = common_fields();
+            output.push(allowed_slots);
+            let config = super::BabeGenesisConfiguration::decode_v2(&output).unwrap();
+            check_common_fields(&config);
+            assert_eq!(config.epoch0_configuration.allowed_slots, expected);
+        }
+
+        // Contrary to the first version, `allowed_slots` is mandatory.
+        assert!(super::BabeGenesisConfiguration::decode_v2(&common_fields()).is_none());
+
+        let mut output = common_fields();
+        output.push(3);
+        assert!(super::BabeGenesisConfiguration::decode_v2(&output).is_none());
+    }
+
+    #[test]
+    fn v1_fallback() {
+        // The Polkadot runtime only provides `BabeApi_configuration`.
+        let code = &include_bytes!("../../executor/vm/test-polkadot-runtime-v9160.wasm")[..];
+        let config = super::BabeGenesisConfiguration::from_genesis_storage(|key| match key {
+            b":code" => Some(code.to_vec()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(config.slots_per_epoch.get(), 2400);
+    }
+}
@@ -15,12 +15,13 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use super::RuntimeCallError;
 use crate::{
-    executor::{self, host, vm},
+    executor::{self, host},
     header,
 };
 
-use alloc::{borrow::ToOwned as _, vec::Vec};
+use alloc::vec::Vec;
 use core::num::NonZeroU64;
 
 /// Grandpa configuration of a chain, as extracted from the genesis block.
@@ -58,21 +59,10 @@ impl GrandpaGenesisConfiguration {
             list.remove(0);
             list
         } else {
-            let wasm_code =
-                genesis_storage_access(b":code").ok_or(FromGenesisStorageError::RuntimeNotFound)?;
-            let heap_pages = executor::storage_heap_pages_to_value(
-                genesis_storage_access(b":heappages").as_deref(),
-            )
-            .map_err(FromGenesisStorageError::HeapPagesDecode)?;
-            let vm = host::HostVmPrototype::new(host::Config {
-                module: &wasm_code,
-                heap_pages,
-                exec_hint: vm::ExecHint::Oneshot,
-                allow_unresolved_imports: false,
-            })
-            .map_err(FromGenesisStorageError::VmInitialization)?;
+            let vm = super::runtime_from_storage(&mut genesis_storage_access)
+                .map_err(FromGenesisStorageError::RuntimeCall)?;
             Self::from_virtual_machine_prototype(vm, genesis_storage_access)
-                .map_err(FromGenesisStorageError::VmError)?
+                .map_err(FromGenesisStorageError::RuntimeCall)?
         };
 
         decode_config(&encoded_list).map_err(|()| FromGenesisStorageError::OutputDecode)
@@ -80,95 +70,41 @@ impl GrandpaGenesisConfiguration {
 
     fn from_virtual_machine_prototype(
         vm: host::HostVmPrototype,
-        mut genesis_storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
-    ) -> Result<Vec<u8>, FromVmPrototypeError> {
-        // TODO: DRY with the babe config; put a helper in the executor module
-        let mut vm: host::HostVm = vm
-            .run_no_param("GrandpaApi_grandpa_authorities")
-            .map_err(|(err, proto)| FromVmPrototypeError::VmStart(err, proto))?
-            .into();
-
-        Ok(loop {
-            match vm {
-                host::HostVm::ReadyToRun(r) => vm = r.run(),
-                host::HostVm::Finished(data) => {
-                    break data.value().as_ref().to_owned();
-                }
-                host::HostVm::Error { .. } => return Err(FromVmPrototypeError::Trapped),
-
-                host::HostVm::ExternalStorageGet(rq) => {
-                    let value = genesis_storage_access(rq.key().as_ref());
-                    vm = rq.resume_full_value(value.as_ref().map(|v| &v[..]));
-                }
-
-                host::HostVm::GetMaxLogLevel(resume) => {
-                    vm = resume.resume(0); // Off
-                }
-                host::HostVm::LogEmit(rq) => vm = rq.resume(),
-
-                _ => return Err(FromVmPrototypeError::HostFunctionNotAllowed),
-            }
-        })
+        genesis_storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
+    ) -> Result<Vec<u8>, RuntimeCallError> {
+        let (output, _) = executor::call_runtime(
+            vm,
+            "GrandpaApi_grandpa_authorities",
+            &[],
+            genesis_storage_access,
+        )
+        .map_err(RuntimeCallError::from)?;
+        Ok(output)
     }
 }
 
 /// Error when retrieving the Grandpa configuration.
 #[derive(Debug, derive_more::Display)]
 pub enum FromGenesisStorageError {
-    /// Runtime couldn't be found in the genesis storage.
-    RuntimeNotFound,
-    /// Failed to decode heap pages from the genesis storage.
-    HeapPagesDecode(executor::InvalidHeapPagesError),
     /// Version number of the encoded authorities list isn't recognized.
     UnknownEncodingVersionNumber,
     /// Error while decoding the SCALE-encoded list.
     OutputDecode,
-    /// Error when initializing the virtual machine.
-    VmInitialization(host::NewErr),
-    /// Error while executing the runtime.
-    VmError(FromVmPrototypeError),
-}
-
-impl FromGenesisStorageError {
-    /// Returns `true` if this error is about an invalid function.
-    pub fn is_function_not_found(&self) -> bool {
-        match self {
-            FromGenesisStorageError::VmError(err) => err.is_function_not_found(),
-            _ => false,
-        }
-    }
-}
-
-/// Error when retrieving the Grandpa configuration.
-#[derive(Debug, derive_more::Display)]
-pub enum FromVmPrototypeError {
-    /// Error when initializing the virtual machine.
+    /// Error while running the runtime.
     #[display(fmt = "{}", _0)]
-    VmStart(host::StartErr, host::HostVmPrototype),
-    /// Crash while running the virtual machine.
-    Trapped,
-    /// Virtual machine tried to call a host function that isn't valid in this context.
-    HostFunctionNotAllowed,
+    RuntimeCall(RuntimeCallError),
 }
 
-impl FromVmPrototypeError {
+impl FromGenesisStorageError {
     /// Returns `true` if this error is about an invalid function.
     pub fn is_function_not_found(&self) -> bool {
-        matches!(
-            self,
-            FromVmPrototypeError::VmStart(
-                host::StartErr::VirtualMachine(
-                    vm::StartErr::FunctionNotFound | vm::StartErr::NotAFunction
-                ),
-                _
-            )
-        )
+        matches!(self, FromGenesisStorageError::RuntimeCall(err) if err.is_function_not_found())
     }
 }
 
 fn decode_config(scale_encoded: &[u8]) -> Result<GrandpaGenesisConfiguration, ()> {
     let result: nom::IResult<_, _> = nom::combinator::all_consuming(nom::combinator::flat_map(
-        crate::util::nom_scale_compact_usize,
+        crate::util::nom_scale_collection_len(40),
         |num_elems| {
             nom::multi::fold_many_m_n(
                 num_elems,
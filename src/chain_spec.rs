@@ -34,9 +34,12 @@
 //! - Multiple other miscellaneous information.
 //!
 
-use crate::chain::chain_information::{
-    aura_config, babe_genesis_config, grandpa_genesis_config, BabeEpochInformation,
-    ChainInformation, ChainInformationConsensus, ChainInformationFinality,
+use crate::{
+    chain::chain_information::{
+        aura_config, babe_genesis_config, grandpa_genesis_config, BabeEpochInformation,
+        ChainInformation, ChainInformationConsensus, ChainInformationFinality,
+    },
+    libp2p::{multiaddr, peer_id},
 };
 
 use alloc::{borrow::ToOwned as _, string::String, vec::Vec};
@@ -196,6 +199,48 @@ impl ChainSpec {
         &self.client_spec.boot_nodes
     }
 
+    /// Parses and validates the list of bootnode addresses in the chain specs.
+    ///
+    /// Each address must be a valid multiaddress whose last component is `/p2p` followed with
+    /// the identity of the node, and must use a transport found in [`BootNodeTransport`].
+    /// Addresses that don't satisfy these conditions are returned in
+    /// [`BootNodes::rejected`] rather than silently ignored, so that the API user can, for
+    /// example, print a warning.
+    ///
+    /// Identical bootnodes are only reported once.
+    pub fn boot_nodes_parsed(&self) -> BootNodes {
+        let mut valid = Vec::<BootNode>::with_capacity(self.client_spec.boot_nodes.len());
+        let mut rejected = Vec::new();
+
+        for address in &self.client_spec.boot_nodes {
+            match parse_boot_node(address) {
+                Ok(boot_node) => {
+                    // The list of bootnodes is typically very small, and a linear search is
+                    // thus fine.
+                    if !valid
+                        .iter()
+                        .any(|n| n.peer_id == boot_node.peer_id && n.address == boot_node.address)
+                    {
+                        valid.push(boot_node);
+                    }
+                }
+                Err(error) => {
+                    if !rejected
+                        .iter()
+                        .any(|r: &RejectedBootNode| r.address == *address)
+                    {
+                        rejected.push(RejectedBootNode {
+                            address: address.clone(),
+                            error,
+                        });
+                    }
+                }
+            }
+        }
+
+        BootNodes { valid, rejected }
+    }
+
     /// Returns the list of libp2p multiaddresses of the default telemetry servers of the chain.
     // TODO: more strongly typed?
     pub fn telemetry_endpoints(&'_ self) -> impl Iterator<Item = impl AsRef<str> + '_> + '_ {
@@ -326,6 +371,25 @@ fn convert_epoch(epoch: &light_sync_state::BabeEpoch) -> BabeEpochInformation {
 }
 
 impl LightSyncState {
+    /// Builds the light sync state corresponding to the given [`ChainInformation`].
+    ///
+    /// Use [`LightSyncState::to_json`] in order to obtain the value to put in the
+    /// `lightSyncState` field of a chain specification.
+    ///
+    /// The light sync state format only supports chains using Babe and GrandPa. An error is
+    /// returned for other consensus or finality engines.
+    pub fn from_chain_information(info: &ChainInformation) -> Result<Self, ToLightSyncStateError> {
+        Ok(LightSyncState {
+            inner: light_sync_state::DecodedLightSyncState::from_chain_information(info)?,
+        })
+    }
+
+    /// Returns the JSON representation of this light sync state, in the format of the
+    /// `lightSyncState` field of chain specifications.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.inner.encode()).unwrap()
+    }
+
     pub fn as_chain_information(&self) -> ChainInformation {
         // Create a sorted list of all regular epochs that haven't been pruned from the sync state.
         let mut epochs: Vec<_> = self
@@ -377,6 +441,131 @@ impl LightSyncState {
     }
 }
 
+/// See [`ChainSpec::boot_nodes_parsed`].
+#[derive(Debug, Clone)]
+pub struct BootNodes {
+    /// List of bootnodes whose address is valid, without duplicates and in the order in which
+    /// they appear in the chain specs.
+    pub valid: Vec<BootNode>,
+    /// List of entries that have been rejected, in the order in which they appear in the chain
+    /// specs.
+    pub rejected: Vec<RejectedBootNode>,
+}
+
+/// Bootnode found in the chain specs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootNode {
+    /// Identity of the bootnode.
+    pub peer_id: peer_id::PeerId,
+    /// Address of the bootnode, without the trailing `/p2p` component.
+    pub address: multiaddr::Multiaddr,
+    /// Transport to use in order to reach the bootnode.
+    pub transport: BootNodeTransport,
+}
+
+/// Transport to use in order to reach a bootnode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BootNodeTransport {
+    /// Plain TCP, such as `/ip4/1.2.3.4/tcp/30333` or `/dns/example.com/tcp/30333`.
+    Tcp,
+    /// WebSocket over TCP, such as `/dns/example.com/tcp/30333/ws`.
+    WebSocket,
+    /// WebSocket over TLS over TCP, such as `/dns/example.com/tcp/443/tls/ws` or
+    /// `/dns/example.com/tcp/443/wss`.
+    WebSocketSecure,
+    /// WebRTC, such as `/ip4/1.2.3.4/udp/30333/webrtc/certhash/<hash>`.
+    WebRtc,
+}
+
+/// Bootnode address found in the chain specs that has been rejected.
+#[derive(Debug, Clone)]
+pub struct RejectedBootNode {
+    /// Address as found in the chain specs.
+    pub address: String,
+    /// Reason why the address has been rejected.
+    pub error: BootNodeError,
+}
+
+/// Reason why a bootnode address has been rejected.
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum BootNodeError {
+    /// Failed to parse the multiaddress.
+    #[display(fmt = "Invalid multiaddress: {}", _0)]
+    InvalidMultiaddr(multiaddr::ParseError),
+    /// The multiaddress doesn't end with `/p2p/<peer id>`.
+    #[display(fmt = "Missing /p2p component")]
+    MissingPeerId,
+    /// The multihash following `/p2p` isn't a valid peer id.
+    #[display(fmt = "Invalid peer id")]
+    InvalidPeerId,
+    /// The multiaddress doesn't correspond to any supported transport.
+    #[display(fmt = "Unsupported transport")]
+    UnsupportedTransport,
+}
+
+fn parse_boot_node(address: &str) -> Result<BootNode, BootNodeError> {
+    let mut address = address
+        .parse::<multiaddr::Multiaddr>()
+        .map_err(BootNodeError::InvalidMultiaddr)?;
+
+    let peer_id = match address.iter().last() {
+        Some(multiaddr::ProtocolRef::P2p(peer_id)) => peer_id::PeerId::from_bytes(peer_id.to_vec())
+            .map_err(|_| BootNodeError::InvalidPeerId)?,
+        _ => return Err(BootNodeError::MissingPeerId),
+    };
+    address.pop();
+
+    let transport = {
+        let mut iter = address.iter();
+        let proto1 = iter.next();
+        let proto2 = iter.next();
+        let proto3 = iter.next();
+        let proto4 = iter.next();
+        let proto5 = iter.next();
+        if iter.next().is_some() {
+            return Err(BootNodeError::UnsupportedTransport);
+        }
+
+        match (proto1, proto2, proto3, proto4, proto5) {
+            (
+                Some(
+                    multiaddr::ProtocolRef::Ip4(_)
+                    | multiaddr::ProtocolRef::Ip6(_)
+                    | multiaddr::ProtocolRef::Dns(_)
+                    | multiaddr::ProtocolRef::Dns4(_)
+                    | multiaddr::ProtocolRef::Dns6(_),
+                ),
+                Some(multiaddr::ProtocolRef::Tcp(_)),
+                rest1,
+                rest2,
+                None,
+            ) => match (rest1, rest2) {
+                (None, None) => BootNodeTransport::Tcp,
+                (Some(multiaddr::ProtocolRef::Ws), None) => BootNodeTransport::WebSocket,
+                (Some(multiaddr::ProtocolRef::Wss), None)
+                | (Some(multiaddr::ProtocolRef::Tls), Some(multiaddr::ProtocolRef::Ws)) => {
+                    BootNodeTransport::WebSocketSecure
+                }
+                _ => return Err(BootNodeError::UnsupportedTransport),
+            },
+            (
+                Some(multiaddr::ProtocolRef::Ip4(_) | multiaddr::ProtocolRef::Ip6(_)),
+                Some(multiaddr::ProtocolRef::Udp(_)),
+                Some(multiaddr::ProtocolRef::WebRtc),
+                Some(multiaddr::ProtocolRef::Certhash(_)),
+                None,
+            ) => BootNodeTransport::WebRtc,
+            _ => return Err(BootNodeError::UnsupportedTransport),
+        }
+    };
+
+    Ok(BootNode {
+        peer_id,
+        address,
+        transport,
+    })
+}
+
 /// Error that can happen when parsing a chain spec JSON.
 #[derive(Debug, derive_more::Display)]
 pub struct ParseError(ParseErrorInner);
@@ -387,6 +576,21 @@ enum ParseErrorInner {
     Other,
 }
 
+/// Error potentially returned by [`LightSyncState::from_chain_information`].
+#[derive(Debug, derive_more::Display)]
+pub enum ToLightSyncStateError {
+    /// Chain doesn't use the Babe consensus engine.
+    NotBabe,
+    /// Chain doesn't use the GrandPa finality engine.
+    NotGrandpa,
+    /// Finalized block is the genesis block, which doesn't belong to any Babe epoch.
+    GenesisBlock,
+    /// Finalized block number doesn't fit in 32 bits.
+    BlockNumberOverflow,
+    /// Exporting a GrandPa scheduled change isn't supported.
+    GrandpaScheduledChange,
+}
+
 /// Error when building the chain information from the genesis storage.
 #[derive(Debug, derive_more::Display)]
 pub enum FromGenesisStorageError {
@@ -404,7 +608,7 @@ pub enum FromGenesisStorageError {
 
 #[cfg(test)]
 mod tests {
-    use super::ChainSpec;
+    use super::{BootNodeError, BootNodeTransport, ChainSpec};
 
     #[test]
     fn can_decode_polkadot_genesis() {
@@ -416,4 +620,190 @@ mod tests {
         assert_eq!(specs.client_spec.code_substitutes.get(&1), None);
         assert!(specs.client_spec.code_substitutes.get(&5203203).is_some());
     }
+
+    #[test]
+    fn boot_nodes_parsed() {
+        let mut spec: serde_json::Value =
+            serde_json::from_slice(include_bytes!("chain_spec/example.json")).unwrap();
+        spec.as_object_mut().unwrap().insert(
+            "bootNodes".into(),
+            serde_json::json!([
+                "/dns4/p2p.cc1-0.polkadot.network/tcp/30100/p2p/12D3KooWEdsXX9657ppNqqrRuaCHFvuNemasgU5msLDwSJ6WqsKc",
+                "/ip4/1.2.3.4/tcp/30333/ws/p2p/12D3KooWAtx477KzC8LwqLjWWUG6WF4Gqp2eNXmeqAG98ehAMWYH",
+                "/dns/example.com/tcp/443/tls/ws/p2p/12D3KooWAGCCPZbr9UWGXPtBosTZo91Hb5M3hU8v6xbKgnC5LVao",
+                // Duplicate of the first entry.
+                "/dns4/p2p.cc1-0.polkadot.network/tcp/30100/p2p/12D3KooWEdsXX9657ppNqqrRuaCHFvuNemasgU5msLDwSJ6WqsKc",
+                // Bad transport.
+                "/ip4/1.2.3.4/udp/30333/p2p/12D3KooWJ4eyPowiVcPU46pXuE2cDsiAmuBKXnFcFPapm4xKFdMJ",
+                // Missing `/p2p`.
+                "/dns4/cc1-0.parity.tech/tcp/30333",
+                // Not a multiaddress.
+                "cc1-1.parity.tech:30333",
+            ]),
+        );
+        let spec = ChainSpec::from_json_bytes(serde_json::to_vec(&spec).unwrap()).unwrap();
+
+        let boot_nodes = spec.boot_nodes_parsed();
+
+        assert_eq!(boot_nodes.valid.len(), 3);
+        assert_eq!(
+            boot_nodes.valid[0].peer_id.to_string(),
+            "12D3KooWEdsXX9657ppNqqrRuaCHFvuNemasgU5msLDwSJ6WqsKc"
+        );
+        assert_eq!(
+            boot_nodes.valid[0].address.to_string(),
+            "/dns4/p2p.cc1-0.polkadot.network/tcp/30100"
+        );
+        assert_eq!(boot_nodes.valid[0].transport, BootNodeTransport::Tcp);
+        assert_eq!(boot_nodes.valid[1].transport, BootNodeTransport::WebSocket);
+        assert_eq!(
+            boot_nodes.valid[2].transport,
+            BootNodeTransport::WebSocketSecure
+        );
+
+        assert_eq!(boot_nodes.rejected.len(), 3);
+        assert!(matches!(
+            boot_nodes.rejected[0].error,
+            BootNodeError::UnsupportedTransport
+        ));
+        assert_eq!(
+            boot_nodes.rejected[1].address,
+            "/dns4/cc1-0.parity.tech/tcp/30333"
+        );
+        assert!(matches!(
+            boot_nodes.rejected[1].error,
+            BootNodeError::MissingPeerId
+        ));
+        assert!(matches!(
+            boot_nodes.rejected[2].error,
+            BootNodeError::InvalidMultiaddr(_)
+        ));
+    }
+
+    #[test]
+    fn light_sync_state_round_trip() {
+        use crate::{
+            chain::chain_information::{
+                BabeEpochInformation, ChainInformation, ChainInformationConsensus,
+                ChainInformationFinality,
+            },
+            header,
+        };
+        use core::num::NonZeroU64;
+
+        let epoch = |epoch_index: u64, start_slot_number: u64| BabeEpochInformation {
+            epoch_index,
+            start_slot_number: Some(start_slot_number),
+            authorities: vec![header::BabeAuthority {
+                public_key: [epoch_index as u8; 32],
+                weight: 1,
+            }],
+            randomness: [0xaa; 32],
+            c: (1, 4),
+            allowed_slots: header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots,
+        };
+
+        let info = ChainInformation {
+            finalized_block_header: header::decode(include_bytes!(
+                "header/tests-header-polkadot-512271"
+            ))
+            .unwrap()
+            .into(),
+            consensus: ChainInformationConsensus::Babe {
+                slots_per_epoch: NonZeroU64::new(2400).unwrap(),
+                finalized_block_epoch_information: Some(epoch(213, 267_683_953)),
+                finalized_next_epoch_transition: epoch(214, 267_686_353),
+            },
+            finality: ChainInformationFinality::Grandpa {
+                after_finalized_block_authorities_set_id: 12,
+                finalized_triggered_authorities: vec![header::GrandpaAuthority {
+                    public_key: [0x11; 32],
+                    weight: NonZeroU64::new(1).unwrap(),
+                }],
+                finalized_scheduled_change: None,
+            },
+        };
+
+        let exported = super::LightSyncState::from_chain_information(&info).unwrap();
+
+        // Insert the exported light sync state into a chain spec, then load it back.
+        let mut spec: serde_json::Value =
+            serde_json::from_slice(include_bytes!("chain_spec/example.json")).unwrap();
+        spec.as_object_mut().unwrap().insert(
+            "lightSyncState".into(),
+            serde_json::from_str(&exported.to_json()).unwrap(),
+        );
+        let spec = ChainSpec::from_json_bytes(serde_json::to_vec(&spec).unwrap()).unwrap();
+        let imported = spec.light_sync_state().unwrap().as_chain_information();
+
+        assert_eq!(
+            imported.finalized_block_header.hash(),
+            info.finalized_block_header.hash()
+        );
+
+        match (&imported.consensus, &info.consensus) {
+            (
+                ChainInformationConsensus::Babe {
+                    slots_per_epoch: imported_slots,
+                    finalized_block_epoch_information: Some(imported_current),
+                    finalized_next_epoch_transition: imported_next,
+                },
+                ChainInformationConsensus::Babe {
+                    slots_per_epoch,
+                    finalized_block_epoch_information: Some(current),
+                    finalized_next_epoch_transition: next,
+                },
+            ) => {
+                assert_eq!(imported_slots, slots_per_epoch);
+                for (imported, expected) in [(imported_current, current), (imported_next, next)] {
+                    assert_eq!(imported.epoch_index, expected.epoch_index);
+                    assert_eq!(imported.start_slot_number, expected.start_slot_number);
+                    assert_eq!(imported.authorities, expected.authorities);
+                    assert_eq!(imported.randomness, expected.randomness);
+                    assert_eq!(imported.c, expected.c);
+                    assert_eq!(imported.allowed_slots, expected.allowed_slots);
+                }
+            }
+            _ => panic!(),
+        }
+
+        match imported.finality {
+            ChainInformationFinality::Grandpa {
+                after_finalized_block_authorities_set_id,
+                finalized_triggered_authorities,
+                ..
+            } => {
+                assert_eq!(after_finalized_block_authorities_set_id, 12);
+                assert_eq!(finalized_triggered_authorities.len(), 1);
+                assert_eq!(finalized_triggered_authorities[0].public_key, [0x11; 32]);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn light_sync_state_aura_unsupported() {
+        use crate::chain::chain_information::{
+            ChainInformation, ChainInformationConsensus, ChainInformationFinality,
+        };
+        use core::num::NonZeroU64;
+
+        let info = ChainInformation {
+            finalized_block_header: crate::header::decode(include_bytes!(
+                "header/tests-header-polkadot-512271"
+            ))
+            .unwrap()
+            .into(),
+            consensus: ChainInformationConsensus::Aura {
+                finalized_authorities_list: Vec::new(),
+                slot_duration: NonZeroU64::new(6000).unwrap(),
+            },
+            finality: ChainInformationFinality::Outsourced,
+        };
+
+        assert!(matches!(
+            super::LightSyncState::from_chain_information(&info),
+            Err(super::ToLightSyncStateError::NotBabe)
+        ));
+    }
 }
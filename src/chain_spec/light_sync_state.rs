@@ -15,10 +15,15 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use super::{ParseError, ParseErrorInner};
-use crate::header::BabeNextConfig;
+use super::{ParseError, ParseErrorInner, ToLightSyncStateError};
+use crate::{
+    chain::chain_information::{
+        BabeEpochInformation, ChainInformation, ChainInformationConsensus, ChainInformationFinality,
+    },
+    header::BabeNextConfig,
+};
 
-use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use alloc::{collections::BTreeMap, format, string::String, vec, vec::Vec};
 use parity_scale_codec::{Decode, DecodeAll as _, Encode};
 use serde::{Deserialize, Serialize};
 
@@ -38,6 +43,7 @@ impl LightSyncState {
         let babe_epoch_changes_slice = &self.babe_epoch_changes.0[..];
 
         let decoded = DecodedLightSyncState {
+            babe_finalized_block_weight: self.babe_finalized_block_weight,
             finalized_block_header: crate::header::decode(&self.finalized_block_header.0[..])
                 .map_err(|_| ParseError(ParseErrorInner::Other))?
                 .into(),
@@ -54,10 +60,153 @@ impl LightSyncState {
 #[derive(Debug)]
 pub(super) struct DecodedLightSyncState {
     pub(super) babe_epoch_changes: EpochChanges,
+    pub(super) babe_finalized_block_weight: u32,
     pub(super) finalized_block_header: crate::header::Header,
     pub(super) grandpa_authority_set: AuthoritySet,
 }
 
+impl DecodedLightSyncState {
+    pub(super) fn from_chain_information(
+        info: &ChainInformation,
+    ) -> Result<Self, ToLightSyncStateError> {
+        let finalized_block_hash = info.finalized_block_header.hash();
+        let finalized_block_number = u32::try_from(info.finalized_block_header.number)
+            .map_err(|_| ToLightSyncStateError::BlockNumberOverflow)?;
+
+        let (slots_per_epoch, current_epoch, next_epoch) = match &info.consensus {
+            ChainInformationConsensus::Babe {
+                slots_per_epoch,
+                finalized_block_epoch_information: Some(current_epoch),
+                finalized_next_epoch_transition,
+            } => (
+                slots_per_epoch.get(),
+                current_epoch,
+                finalized_next_epoch_transition,
+            ),
+            ChainInformationConsensus::Babe {
+                finalized_block_epoch_information: None,
+                ..
+            } => return Err(ToLightSyncStateError::GenesisBlock),
+            ChainInformationConsensus::Aura { .. } | ChainInformationConsensus::AllAuthorized => {
+                return Err(ToLightSyncStateError::NotBabe)
+            }
+        };
+
+        let (set_id, current_authorities) = match &info.finality {
+            ChainInformationFinality::Grandpa {
+                finalized_scheduled_change: Some(_),
+                ..
+            } => return Err(ToLightSyncStateError::GrandpaScheduledChange),
+            ChainInformationFinality::Grandpa {
+                after_finalized_block_authorities_set_id,
+                finalized_triggered_authorities,
+                finalized_scheduled_change: None,
+            } => (
+                *after_finalized_block_authorities_set_id,
+                finalized_triggered_authorities,
+            ),
+            ChainInformationFinality::Outsourced => return Err(ToLightSyncStateError::NotGrandpa),
+        };
+
+        // The light sync state indexes epochs by the block that has announced them. This
+        // information isn't available in a `ChainInformation`. Instead, the current epoch is
+        // indexed by the parent of the finalized block and the next epoch by the finalized
+        // block. This is enough for the two epochs to be found again when decoding.
+        let convert_epoch = |epoch: &BabeEpochInformation| {
+            Ok(BabeEpoch {
+                epoch_index: epoch.epoch_index,
+                slot_number: epoch
+                    .start_slot_number
+                    .ok_or(ToLightSyncStateError::GenesisBlock)?,
+                duration: slots_per_epoch,
+                authorities: epoch
+                    .authorities
+                    .iter()
+                    .map(|authority| BabeAuthority {
+                        public_key: authority.public_key,
+                        weight: authority.weight,
+                    })
+                    .collect(),
+                randomness: epoch.randomness,
+                config: BabeNextConfig {
+                    c: epoch.c,
+                    allowed_slots: epoch.allowed_slots,
+                },
+            })
+        };
+        let current_epoch = convert_epoch(current_epoch)?;
+        let next_epoch = convert_epoch(next_epoch)?;
+
+        let current_epoch_key = (
+            info.finalized_block_header.parent_hash,
+            finalized_block_number.saturating_sub(1),
+        );
+        let next_epoch_key = (finalized_block_hash, finalized_block_number);
+
+        let epoch_header = |epoch: &BabeEpoch| EpochHeader {
+            start_slot: epoch.slot_number,
+            end_slot: epoch.slot_number.saturating_add(epoch.duration),
+        };
+
+        let babe_epoch_changes = EpochChanges {
+            inner: ForkTree {
+                roots: vec![ForkTreeNode {
+                    hash: current_epoch_key.0,
+                    number: current_epoch_key.1,
+                    data: PersistedEpochHeader::Regular(epoch_header(&current_epoch)),
+                    children: vec![ForkTreeNode {
+                        hash: next_epoch_key.0,
+                        number: next_epoch_key.1,
+                        data: PersistedEpochHeader::Regular(epoch_header(&next_epoch)),
+                        children: Vec::new(),
+                    }],
+                }],
+                best_finalized_number: Some(finalized_block_number),
+            },
+            epochs: [
+                (current_epoch_key, PersistedEpoch::Regular(current_epoch)),
+                (next_epoch_key, PersistedEpoch::Regular(next_epoch)),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        let grandpa_authority_set = AuthoritySet {
+            current_authorities: current_authorities
+                .iter()
+                .map(|authority| GrandpaAuthority {
+                    public_key: authority.public_key,
+                    weight: authority.weight.get(),
+                })
+                .collect(),
+            set_id,
+            pending_standard_changes: ForkTree {
+                roots: Vec::new(),
+                best_finalized_number: Some(finalized_block_number),
+            },
+            pending_forced_changes: Vec::new(),
+            authority_set_changes: Vec::new(),
+        };
+
+        Ok(DecodedLightSyncState {
+            babe_epoch_changes,
+            // This value isn't known. It isn't used by smoldot either.
+            babe_finalized_block_weight: 0,
+            finalized_block_header: info.finalized_block_header.clone(),
+            grandpa_authority_set,
+        })
+    }
+
+    pub(super) fn encode(&self) -> LightSyncState {
+        LightSyncState {
+            babe_epoch_changes: HexString(self.babe_epoch_changes.encode()),
+            babe_finalized_block_weight: self.babe_finalized_block_weight,
+            finalized_block_header: HexString(self.finalized_block_header.scale_encoding_vec()),
+            grandpa_authority_set: HexString(self.grandpa_authority_set.encode()),
+        }
+    }
+}
+
 #[derive(Debug, Decode, Encode)]
 pub(super) struct EpochChanges {
     inner: ForkTree<PersistedEpochHeader>,
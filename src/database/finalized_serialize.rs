@@ -29,7 +29,7 @@
 //! This feature is expected to be used for example by light clients in order to easily (but
 //! inefficiently) store the state of the finalized chain somewhere and later reload it.
 
-use crate::chain::chain_information;
+use crate::{chain::chain_information, trie};
 
 use alloc::{string::String, vec::Vec};
 use core::iter;
@@ -87,6 +87,64 @@ pub fn decode_chain(
     Ok((chain_info, storage))
 }
 
+/// Same as [`decode_chain`], but additionally verifies that the finalized block storage, if any,
+/// matches the state root found in the header of the finalized block.
+///
+/// `state_version` is the version of the trie encoding of the finalized block, as found in the
+/// runtime version of the chain. `None` should be interpreted the same way as `Some(0)`.
+///
+/// No verification is performed if the encoded data doesn't contain the finalized block storage.
+///
+/// Calculating the state root using a trie encoding other than the version `0` isn't supported
+/// at the moment. If the encoded data contains the finalized block storage and `state_version`
+/// is different from `0`, [`VerifyStorageError::UnsupportedStateVersion`] is returned. Use
+/// [`decode_chain`] in order to load the data without verifying it.
+pub fn decode_chain_verify_storage(
+    encoded: &str,
+    state_version: Option<u8>,
+) -> Result<
+    (
+        chain_information::ValidChainInformation,
+        Option<HashMap<Vec<u8>, Vec<u8>, fnv::FnvBuildHasher>>,
+    ),
+    VerifyStorageError,
+> {
+    let (chain_info, storage) = decode_chain(encoded).map_err(VerifyStorageError::Corrupted)?;
+
+    if let Some(storage) = &storage {
+        // TODO: https://github.com/paritytech/smoldot/issues/1967
+        match state_version {
+            None | Some(0) => {}
+            Some(version) => return Err(VerifyStorageError::UnsupportedStateVersion(version)),
+        }
+
+        let mut trie = trie::Trie::new();
+        for (key, value) in storage {
+            trie.insert(key, value.clone());
+        }
+
+        if trie.root_merkle_value(None) != *chain_info.as_ref().finalized_block_header.state_root {
+            return Err(VerifyStorageError::Corrupted(CorruptedError(
+                CorruptedErrorInner::StorageRootMismatch,
+            )));
+        }
+    }
+
+    Ok((chain_info, storage))
+}
+
+/// Error potentially returned by [`decode_chain_verify_storage`].
+#[derive(Debug, derive_more::Display)]
+pub enum VerifyStorageError {
+    /// Data stored in the local storage is corrupted.
+    #[display(fmt = "{}", _0)]
+    Corrupted(CorruptedError),
+    /// The finalized block storage can't be verified because the trie encoding of the chain
+    /// isn't supported.
+    #[display(fmt = "Unsupported state version: {}", _0)]
+    UnsupportedStateVersion(u8),
+}
+
 /// Opaque error indicating a corruption in the data stored in the local storage.
 #[derive(Debug, derive_more::Display)]
 #[display(fmt = "{}", _0)]
@@ -99,4 +157,60 @@ enum CorruptedErrorInner {
     #[display(fmt = "{}", _0)]
     Deserialize(defs::DeserializeError),
     InvalidChain(chain_information::ValidityError),
+    /// Finalized block storage doesn't match the state root of the finalized block.
+    StorageRootMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chain::chain_information;
+    use crate::chain_spec::{ChainSpec, GenesisStorage};
+
+    #[test]
+    fn corrupted_storage_detected() {
+        let spec =
+            ChainSpec::from_json_bytes(&include_bytes!("../chain_spec/example.json")[..]).unwrap();
+        let chain_info = chain_information::ValidChainInformation::try_from(
+            spec.as_chain_information().unwrap(),
+        )
+        .unwrap();
+        let storage = match spec.genesis_storage() {
+            GenesisStorage::Items(items) => items,
+            GenesisStorage::TrieRootHash(_) => unreachable!(),
+        };
+
+        let valid = super::encode_chain_storage(&chain_info, Some(storage.iter()));
+        assert!(super::decode_chain_verify_storage(&valid, None).is_ok());
+        assert!(super::decode_chain_verify_storage(&valid, Some(0)).is_ok());
+
+        // Modify the value of the first storage item.
+        let corrupted = super::encode_chain_storage(
+            &chain_info,
+            Some(storage.iter().enumerate().map(|(n, (key, value))| {
+                let mut value = value.to_vec();
+                if n == 0 {
+                    value.push(0);
+                }
+                (key, value)
+            })),
+        );
+        assert!(super::decode_chain(&corrupted).is_ok());
+        assert!(super::decode_chain_verify_storage(&corrupted, None).is_err());
+
+        // Snapshots without the storage can't be verified.
+        let pruned = super::encode_chain(&chain_info);
+        assert!(super::decode_chain_verify_storage(&pruned, None).is_ok());
+        assert!(super::decode_chain_verify_storage(&pruned, Some(1)).is_ok());
+
+        // Verifying the storage of a chain using the version 1 of the trie encoding isn't
+        // supported.
+        assert!(matches!(
+            super::decode_chain_verify_storage(&valid, Some(1)),
+            Err(super::VerifyStorageError::UnsupportedStateVersion(1))
+        ));
+        assert!(matches!(
+            super::decode_chain_verify_storage(&corrupted, Some(1)),
+            Err(super::VerifyStorageError::UnsupportedStateVersion(1))
+        ));
+    }
 }
@@ -1252,7 +1252,7 @@ fn decode_babe_epoch_information(
         nom::sequence::tuple((
             nom::number::complete::le_u64,
             util::nom_option_decode(nom::number::complete::le_u64),
-            nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+            nom::combinator::flat_map(crate::util::nom_scale_collection_len(40), |num_elems| {
                 nom::multi::many_m_n(
                     num_elems,
                     num_elems,
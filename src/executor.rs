@@ -27,17 +27,37 @@
 //!
 //! This module contains everything necessary to execute runtime code. The highest-level
 //! sub-module is [`runtime_host`].
+//!
+//! # Storage access
+//!
+//! None of the code in this module accesses the storage by itself, and no trait abstracting
+//! over a storage backend is provided. Instead, [`runtime_host::RuntimeHostVm`] (and similarly
+//! [`read_only_runtime_host::RuntimeHostVm`]) is a state machine whose variants, such as
+//! [`runtime_host::RuntimeHostVm::StorageGet`], [`runtime_host::RuntimeHostVm::NextKey`] or
+//! [`runtime_host::RuntimeHostVm::PrefixKeys`], indicate which storage access is required in
+//! order to continue. Higher-level helpers that only need to read a few storage values, for
+//! example in [`crate::chain::chain_information`], simply accept a closure.
+//!
+//! Concerns such as caching storage values or recording the storage accesses in order to build
+//! a proof are therefore implemented by the API user, in the code that answers these
+//! requests, and apply to all the runtime calls at once.
 
-use alloc::vec::Vec;
+use crate::trie::proof_verify;
+
+use alloc::{borrow::Cow, vec::Vec};
 use core::{fmt, ops, str};
 
 mod allocator; // TODO: make public after refactoring
 pub mod host;
+pub mod metadata;
 pub mod read_only_runtime_host;
 pub mod runtime_host;
 pub mod storage_diff;
 pub mod vm;
 
+#[cfg(test)]
+pub(crate) mod test_module;
+
 /// Default number of heap pages if the storage doesn't specify otherwise.
 ///
 /// # Context
@@ -74,6 +94,99 @@ pub enum InvalidHeapPagesError {
     TooLarge,
 }
 
+/// Fetches the `:code` storage value by calling `storage_get` and returns its Blake2-256 hash.
+///
+/// The storage value is hashed as-is, without being decompressed and without instantiating any
+/// virtual machine. This is the same hash as the one used by [`host::ModulesCache`], and can be
+/// used to identify a runtime in a cache of runtimes.
+pub fn runtime_code_hash<T: AsRef<[u8]>>(
+    storage_get: impl FnOnce(&[u8]) -> Option<T>,
+) -> Result<[u8; 32], MissingCodeError> {
+    let code = storage_get(b":code").ok_or(MissingCodeError)?;
+    Ok(
+        <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], code.as_ref()).as_bytes())
+            .unwrap(),
+    )
+}
+
+/// Error potentially returned by [`runtime_code_hash`].
+#[derive(Debug, derive_more::Display, Clone)]
+#[display(fmt = "No `:code` in the storage")]
+pub struct MissingCodeError;
+
+/// Configuration for [`runtime_from_proof`].
+pub struct RuntimeFromProofConfig<'a, I> {
+    /// Merkle value of the root node of the storage trie of the block whose runtime must be
+    /// built.
+    pub trie_root_hash: &'a [u8; 32],
+
+    /// Unordered list of node values of the storage trie. Must contain the entries necessary to
+    /// prove the storage values of `:code` and `:heappages`.
+    pub proof: I,
+
+    /// Hint about how the runtime is going to be executed.
+    pub exec_hint: vm::ExecHint,
+
+    /// See [`host::Config::allow_unresolved_imports`].
+    pub allow_unresolved_imports: bool,
+}
+
+/// Builds a [`host::HostVmPrototype`] from the `:code` and `:heappages` storage values proven
+/// by the given storage proof, without having access to the rest of the storage.
+///
+/// Similar to [`host::HostVmPrototype::new`], `:code` is transparently decompressed if
+/// necessary.
+pub fn runtime_from_proof<'a>(
+    config: RuntimeFromProofConfig<impl Iterator<Item = &'a [u8]> + Clone>,
+) -> Result<host::HostVmPrototype, RuntimeFromProofError> {
+    let code = proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+        requested_key: b":code",
+        trie_root_hash: config.trie_root_hash,
+        proof: config.proof.clone(),
+    })
+    .map_err(RuntimeFromProofError::CodeProof)?
+    .ok_or(RuntimeFromProofError::MissingCode)?;
+
+    let heap_pages = proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+        requested_key: b":heappages",
+        trie_root_hash: config.trie_root_hash,
+        proof: config.proof,
+    })
+    .map_err(RuntimeFromProofError::HeapPagesProof)?;
+    let heap_pages =
+        storage_heap_pages_to_value(heap_pages).map_err(RuntimeFromProofError::InvalidHeapPages)?;
+
+    host::HostVmPrototype::new(host::Config {
+        module: code,
+        heap_pages,
+        exec_hint: config.exec_hint,
+        allow_unresolved_imports: config.allow_unresolved_imports,
+    })
+    .map_err(RuntimeFromProofError::NewRuntime)
+}
+
+/// Error potentially returned by [`runtime_from_proof`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum RuntimeFromProofError {
+    /// Failed to verify the proof of the `:code` storage value. This is the case if the proof
+    /// doesn't contain the entries necessary to find this value.
+    #[display(fmt = "Failed to verify the proof of `:code`: {}", _0)]
+    CodeProof(proof_verify::Error),
+    /// Failed to verify the proof of the `:heappages` storage value. This is the case if the
+    /// proof doesn't contain the entries necessary to find this value.
+    #[display(fmt = "Failed to verify the proof of `:heappages`: {}", _0)]
+    HeapPagesProof(proof_verify::Error),
+    /// The proof indicates that the storage doesn't contain any `:code`.
+    #[display(fmt = "No `:code` in the storage")]
+    MissingCode,
+    /// The `:heappages` storage value is invalid.
+    #[display(fmt = "Invalid `:heappages`: {}", _0)]
+    InvalidHeapPages(InvalidHeapPagesError),
+    /// Failed to build the runtime from `:code`.
+    #[display(fmt = "{}", _0)]
+    NewRuntime(host::NewErr),
+}
+
 /// Runs the `Core_version` function using the given virtual machine prototype, and returns
 /// the output.
 ///
@@ -142,6 +255,101 @@ pub enum CoreVersionError {
     ForbiddenHostFunction,
 }
 
+/// Runs the given runtime function using the given virtual machine prototype, and returns the
+/// output of the call together with the prototype.
+///
+/// `parameters` are concatenated in memory and passed as the input of the function.
+/// Storage values are loaded by calling `storage_access`, which must return the storage value of
+/// the given key, if any. Logs emitted by the runtime are ignored.
+///
+/// Any host function other than reading from the storage and emitting logs is forbidden. This
+/// function is therefore only suitable for runtime calls that don't modify the storage.
+pub fn call_runtime(
+    vm_proto: host::HostVmPrototype,
+    function_to_call: &str,
+    parameters: &[&[u8]],
+    mut storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
+) -> Result<(Vec<u8>, host::HostVmPrototype), CallRuntimeError> {
+    call_runtime_borrowed(vm_proto, function_to_call, parameters, |key| {
+        storage_access(key).map(Cow::Owned)
+    })
+}
+
+/// Same as [`call_runtime`], except that `storage_access` can return a storage value that is
+/// borrowed from the storage backend instead of allocating a new buffer for each read.
+///
+/// Storage values are directly copied from the buffer returned by `storage_access` to the memory
+/// of the virtual machine.
+pub fn call_runtime_borrowed<'a>(
+    vm_proto: host::HostVmPrototype,
+    function_to_call: &str,
+    parameters: &[&[u8]],
+    mut storage_access: impl FnMut(&[u8]) -> Option<Cow<'a, [u8]>>,
+) -> Result<(Vec<u8>, host::HostVmPrototype), CallRuntimeError> {
+    let mut vm: host::HostVm = vm_proto
+        .run_vectored(function_to_call, parameters.iter())
+        .map_err(|(err, proto)| CallRuntimeError::Start(err, proto))?
+        .into();
+
+    loop {
+        match vm {
+            host::HostVm::ReadyToRun(r) => vm = r.run(),
+            host::HostVm::Finished(finished) => {
+                let output = finished.value().as_ref().to_vec();
+                return Ok((output, finished.into_prototype()));
+            }
+            host::HostVm::Error { prototype, error } => {
+                return Err(CallRuntimeError::Run(error, prototype))
+            }
+
+            host::HostVm::ExternalStorageGet(req) => {
+                let value = storage_access(req.key().as_ref());
+                vm = req.resume_full_value(value.as_ref().map(|v| &v[..]));
+            }
+
+            // Emitted log lines are ignored.
+            host::HostVm::GetMaxLogLevel(resume) => {
+                vm = resume.resume(0); // Off
+            }
+            host::HostVm::LogEmit(log) => vm = log.resume(),
+
+            other => {
+                return Err(CallRuntimeError::ForbiddenHostFunction(
+                    other.into_prototype(),
+                ))
+            }
+        }
+    }
+}
+
+/// Error while executing [`call_runtime`].
+///
+/// Each variant contains the virtual machine prototype that was passed to [`call_runtime`], so
+/// that it can be used again.
+#[derive(Debug, derive_more::Display)]
+pub enum CallRuntimeError {
+    /// Error while starting the execution of the function.
+    #[display(fmt = "Error while starting the execution of the function: {}", _0)]
+    Start(host::StartErr, host::HostVmPrototype),
+    /// Error during the execution of the function.
+    #[display(fmt = "Error during the execution of the function: {}", _0)]
+    Run(host::Error, host::HostVmPrototype),
+    /// The function used a host function that is forbidden in this context.
+    #[display(fmt = "The function used a host function that is forbidden in this context")]
+    ForbiddenHostFunction(host::HostVmPrototype),
+}
+
+impl CallRuntimeError {
+    /// Returns the virtual machine prototype that was passed to [`call_runtime`].
+    pub fn into_prototype(self) -> host::HostVmPrototype {
+        match self {
+            CallRuntimeError::Start(_, proto)
+            | CallRuntimeError::Run(_, proto)
+            | CallRuntimeError::ForbiddenHostFunction(proto) => proto,
+        }
+    }
+}
+
 /// Buffer storing the SCALE-encoded core version.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CoreVersion(Vec<u8>);
@@ -158,6 +366,56 @@ impl AsRef<[u8]> for CoreVersion {
     }
 }
 
+/// Determines whether a runtime upgrade has happened between two blocks, given the hashes of
+/// the `:code` storage values of these two blocks.
+///
+/// If the two hashes are equal, `Ok(None)` is immediately returned and `core_version_fetcher`
+/// isn't called. Otherwise, `core_version_fetcher` is called once for each hash, and is
+/// expected to return the runtime version of the `:code` with that hash, typically by calling
+/// [`core_version`]. Errors returned by `core_version_fetcher` are propagated.
+///
+/// > **Note**: A different `:code` doesn't necessarily mean a different version. If the
+/// >           versions are equal, a [`RuntimeUpgrade`] is still returned, as the code itself
+/// >           has changed.
+pub fn detect_runtime_upgrade<E>(
+    old_code_hash: &[u8; 32],
+    new_code_hash: &[u8; 32],
+    mut core_version_fetcher: impl FnMut(&[u8; 32]) -> Result<CoreVersion, E>,
+) -> Result<Option<RuntimeUpgrade>, E> {
+    if old_code_hash == new_code_hash {
+        return Ok(None);
+    }
+
+    let old_version = core_version_fetcher(old_code_hash)?;
+    let new_version = core_version_fetcher(new_code_hash)?;
+    Ok(Some(RuntimeUpgrade {
+        old_version,
+        new_version,
+    }))
+}
+
+/// Runtime upgrade detected by [`detect_runtime_upgrade`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeUpgrade {
+    /// Runtime version before the upgrade.
+    pub old_version: CoreVersion,
+    /// Runtime version after the upgrade.
+    pub new_version: CoreVersion,
+}
+
+impl RuntimeUpgrade {
+    /// Returns `true` if the `spec_version` of the runtime has changed. A change of
+    /// `spec_version` indicates that the logic of the runtime has changed.
+    pub fn spec_version_changed(&self) -> bool {
+        self.old_version.decode().spec_version != self.new_version.decode().spec_version
+    }
+
+    /// Returns `true` if the `impl_version` of the runtime has changed.
+    pub fn impl_version_changed(&self) -> bool {
+        self.old_version.decode().impl_version != self.new_version.decode().impl_version
+    }
+}
+
 /// Runtime specification, once decoded.
 // TODO: explain these fields
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -181,8 +439,9 @@ pub struct CoreVersionRef<'a> {
     /// Whenever this version number changes, all transactions encoding generated earlier are
     /// invalidated and should be regenerated.
     ///
-    /// Older versions of Substrate didn't provide this field. `None` if the field is missing.
-    pub transaction_version: Option<u32>,
+    /// Older versions of Substrate didn't provide this field, in which case it will contain
+    /// `0`.
+    pub transaction_version: u32,
 
     /// Version number of the state trie encoding version.
     ///
@@ -311,8 +570,8 @@ fn decode(scale_encoded: &[u8]) -> Result<CoreVersionRef, ()> {
                 |inner| CoreVersionApisRefIter { inner },
             ),
             nom::branch::alt((
-                nom::combinator::map(nom::number::complete::le_u32, Some),
-                nom::combinator::map(nom::combinator::eof, |_| None),
+                nom::number::complete::le_u32,
+                nom::combinator::map(nom::combinator::eof, |_| 0),
             )),
             nom::branch::alt((
                 nom::combinator::map(nom::number::complete::u8, Some),
@@ -361,3 +620,189 @@ fn core_version_api<'a, E: nom::error::ParseError<&'a [u8]>>(
         },
     )(bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    fn encode_version(spec_version: u32, impl_version: u32) -> super::CoreVersion {
+        let mut out = Vec::new();
+        out.extend_from_slice(&[4, b'a', 4, b'b']); // `spec_name` and `impl_name`
+        out.extend_from_slice(&1u32.to_le_bytes()); // `authoring_version`
+        out.extend_from_slice(&spec_version.to_le_bytes());
+        out.extend_from_slice(&impl_version.to_le_bytes());
+        out.push(0); // No API
+        assert!(super::decode(&out).is_ok());
+        super::CoreVersion(out)
+    }
+
+    #[test]
+    fn call_runtime_works() {
+        let vm_proto = super::host::HostVmPrototype::new(super::host::Config {
+            module: &include_bytes!("./executor/vm/test-polkadot-runtime-v9160.wasm")[..],
+            heap_pages: super::DEFAULT_HEAP_PAGES,
+            exec_hint: super::vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+
+        let (output, vm_proto) =
+            super::call_runtime(vm_proto, "Core_version", &[], |_| panic!()).unwrap();
+        let version = super::decode(&output).unwrap();
+        assert_eq!(version.spec_name, "polkadot");
+        assert_eq!(version.spec_version, 9160);
+
+        let mut storage_reads = Vec::new();
+        let (output, vm_proto) =
+            super::call_runtime(vm_proto, "GrandpaApi_grandpa_authorities", &[], |key| {
+                storage_reads.push(key.to_vec());
+                None
+            })
+            .unwrap();
+        assert_eq!(storage_reads, vec![b":grandpa_authorities".to_vec()]);
+        assert_eq!(output, vec![0]);
+
+        let err = super::call_runtime(vm_proto, "doesnt_exist", &[], |_| panic!()).unwrap_err();
+        assert!(matches!(err, super::CallRuntimeError::Start(..)));
+
+        // The prototype can be used again after an error.
+        let (output, _) =
+            super::call_runtime(err.into_prototype(), "Core_version", &[], |_| panic!()).unwrap();
+        assert_eq!(super::decode(&output).unwrap().spec_version, 9160);
+    }
+
+    #[test]
+    fn runtime_code_hash() {
+        let code = &include_bytes!("./executor/host/zstd/polkadot-runtime-v9160.wasm.zstd")[..];
+
+        // The code is hashed without being decompressed.
+        let hash = super::runtime_code_hash(|key| {
+            assert_eq!(key, b":code");
+            Some(code)
+        })
+        .unwrap();
+        assert_eq!(
+            hash[..],
+            hex::decode("ae866018e8e57e82b9cb5eb1746d01c646b27e15cc448008a4848aa5b627de84")
+                .unwrap()[..]
+        );
+
+        assert!(matches!(
+            super::runtime_code_hash(|_| None::<Vec<u8>>),
+            Err(super::MissingCodeError)
+        ));
+    }
+
+    #[test]
+    fn runtime_from_proof() {
+        let code = &include_bytes!("./executor/host/zstd/polkadot-runtime-v9160.wasm.zstd")[..];
+        let storage = [
+            (b":code".to_vec(), code.to_vec()),
+            (b":heappages".to_vec(), 1024u64.to_le_bytes().to_vec()),
+            (b":other".to_vec(), vec![0xff; 64]),
+        ];
+        let trie_root_hash = crate::trie::trie_root(&storage);
+
+        let generate = |keys: &[&[u8]]| {
+            let proof = crate::trie::proof_generate::generate_read_proof(
+                crate::trie::proof_generate::Config {
+                    keys: keys.iter(),
+                    storage_entries: storage.iter().map(|(k, v)| (&k[..], &v[..])),
+                    trie_root_hash: &trie_root_hash,
+                },
+            )
+            .unwrap();
+            crate::trie::decode_proof(&proof)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        // `:code` is compressed in the storage.
+        let proof = generate(&[b":code", b":heappages"]);
+        let vm_proto = super::runtime_from_proof(super::RuntimeFromProofConfig {
+            trie_root_hash: &trie_root_hash,
+            proof: proof.iter().map(|p| &p[..]),
+            exec_hint: super::vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+        assert_eq!(vm_proto.heap_pages(), super::vm::HeapPages::new(1024));
+        let (output, _) = super::call_runtime(vm_proto, "Core_version", &[], |_| panic!()).unwrap();
+        assert_eq!(super::decode(&output).unwrap().spec_version, 9160);
+
+        // The proof must cover `:code`.
+        let proof = generate(&[b":other"]);
+        assert!(matches!(
+            super::runtime_from_proof(super::RuntimeFromProofConfig {
+                trie_root_hash: &trie_root_hash,
+                proof: proof.iter().map(|p| &p[..]),
+                exec_hint: super::vm::ExecHint::Oneshot,
+                allow_unresolved_imports: false,
+            }),
+            Err(super::RuntimeFromProofError::CodeProof(_))
+        ));
+    }
+
+    #[test]
+    fn decode_modern_version() {
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(&[8 << 2]);
+        encoded.extend_from_slice(b"polkadot");
+        encoded.extend_from_slice(&[15 << 2]);
+        encoded.extend_from_slice(b"parity-polkadot");
+        encoded.extend_from_slice(&0u32.to_le_bytes()); // `authoring_version`
+        encoded.extend_from_slice(&9160u32.to_le_bytes()); // `spec_version`
+        encoded.extend_from_slice(&2u32.to_le_bytes()); // `impl_version`
+        encoded.push(1 << 2); // One API
+        encoded.extend_from_slice(&super::hash_api_name("Core"));
+        encoded.extend_from_slice(&4u32.to_le_bytes());
+        encoded.extend_from_slice(&11u32.to_le_bytes()); // `transaction_version`
+        encoded.push(1); // `state_version`
+
+        let version = super::decode(&encoded).unwrap();
+        assert_eq!(version.spec_name, "polkadot");
+        assert_eq!(version.impl_name, "parity-polkadot");
+        assert_eq!(version.authoring_version, 0);
+        assert_eq!(version.spec_version, 9160);
+        assert_eq!(version.impl_version, 2);
+        assert!(version.apis.contains("Core", 4..=4));
+        assert_eq!(version.transaction_version, 11);
+        assert_eq!(version.state_version, Some(1));
+
+        // Old encodings don't contain the transaction version.
+        let version = encode_version(1, 1);
+        assert_eq!(version.decode().transaction_version, 0);
+        assert_eq!(version.decode().state_version, None);
+    }
+
+    #[test]
+    fn detect_runtime_upgrade_same_code() {
+        let outcome =
+            super::detect_runtime_upgrade(&[1; 32], &[1; 32], |_| -> Result<_, ()> { panic!() });
+        assert_eq!(outcome, Ok(None));
+    }
+
+    #[test]
+    fn detect_runtime_upgrade_different_code() {
+        let outcome = super::detect_runtime_upgrade(&[1; 32], &[2; 32], |hash| -> Result<_, ()> {
+            match hash {
+                [1, ..] => Ok(encode_version(10, 1)),
+                [2, ..] => Ok(encode_version(11, 1)),
+                _ => unreachable!(),
+            }
+        })
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(outcome.old_version.decode().spec_version, 10);
+        assert_eq!(outcome.new_version.decode().spec_version, 11);
+        assert!(outcome.spec_version_changed());
+        assert!(!outcome.impl_version_changed());
+    }
+
+    #[test]
+    fn detect_runtime_upgrade_error() {
+        let outcome = super::detect_runtime_upgrade(&[1; 32], &[2; 32], |_| Err(()));
+        assert_eq!(outcome, Err(()));
+    }
+}
@@ -127,6 +127,25 @@
 //! The Wasm execution is fully deterministic, and the outcome of the execution only depends on
 //! the inputs. There is, for example, no implicit injection of randomness or of the current time.
 //!
+//! ## Pausing the execution
+//!
+//! [`HostVm`] and all the types of its variants own their data and don't borrow anything. They
+//! implement `Send` and `'static` and can be freely moved around, for example stored in a data
+//! structure or held across an `.await` point, while the value requested by the runtime is being
+//! loaded. The execution is only resumed when a `resume` method is called.
+//!
+//! The methods that expose information about the request, such as
+//! [`ExternalStorageGet::key`], borrow the state. If the request must be answered
+//! asynchronously, copy this information before awaiting, then resume the execution afterwards:
+//!
+//! ```ignore
+//! HostVm::ExternalStorageGet(req) => {
+//!     let key = req.key().as_ref().to_vec();
+//!     let value = fetch_storage_value(&key).await;
+//!     vm = req.resume_full_value(value.as_deref());
+//! }
+//! ```
+//!
 //! ## Example
 //!
 //! ```
@@ -252,11 +271,42 @@ pub struct HostVmPrototype {
 impl HostVmPrototype {
     /// Creates a new [`HostVmPrototype`]. Parses and potentially JITs the module.
     pub fn new(config: Config<impl AsRef<[u8]>>) -> Result<Self, NewErr> {
+        let module = Self::compile(&config)?;
+        Self::from_module(module, config.heap_pages, config.allow_unresolved_imports)
+    }
+
+    /// Creates a new [`HostVmPrototype`], similar to [`HostVmPrototype::new`].
+    ///
+    /// If a module with the same bytes and [`Config::exec_hint`] has previously been compiled
+    /// through the same [`ModulesCache`], the compiled module is reused rather than parsed and
+    /// compiled again. Otherwise, the newly-compiled module is inserted in the cache.
+    pub fn new_with_cache(
+        config: Config<impl AsRef<[u8]>>,
+        cache: &mut ModulesCache,
+    ) -> Result<Self, NewErr> {
+        let code_hash = <[u8; 32]>::try_from(
+            blake2_rfc::blake2b::blake2b(32, &[], config.module.as_ref()).as_bytes(),
+        )
+        .unwrap();
+
+        let module = match cache.get(&code_hash, config.exec_hint) {
+            Some(module) => module,
+            None => {
+                let module = Self::compile(&config)?;
+                cache.insert(code_hash, config.exec_hint, module.clone());
+                module
+            }
+        };
+
+        Self::from_module(module, config.heap_pages, config.allow_unresolved_imports)
+    }
+
+    /// Decompresses the module if necessary and compiles it.
+    fn compile(config: &Config<impl AsRef<[u8]>>) -> Result<vm::Module, NewErr> {
         // TODO: configurable maximum allowed size? a uniform value is important for consensus
         let module = zstd::zstd_decode_if_necessary(config.module.as_ref(), 50 * 1024 * 1024)
             .map_err(NewErr::BadFormat)?;
-        let module = vm::Module::new(module, config.exec_hint).map_err(vm::NewErr::ModuleError)?;
-        Self::from_module(module, config.heap_pages, config.allow_unresolved_imports)
+        Ok(vm::Module::new(module, config.exec_hint).map_err(vm::NewErr::ModuleError)?)
     }
 
     fn from_module(
@@ -406,6 +456,82 @@ impl HostVmPrototype {
     }
 }
 
+/// Cache of compiled Wasm modules, indexed by the hash of their code.
+///
+/// See [`HostVmPrototype::new_with_cache`].
+///
+/// The cache contains at most a fixed number of modules. When full, the module that has been
+/// used the least recently is removed.
+pub struct ModulesCache {
+    /// List of cached modules, with the hash of their code, ordered from the least recently used
+    /// to the most recently used.
+    entries: Vec<([u8; 32], vm::ExecHint, vm::Module)>,
+
+    /// Maximum number of entries in [`ModulesCache::entries`].
+    capacity: usize,
+}
+
+impl ModulesCache {
+    /// Creates a new empty cache that holds at most `capacity` modules.
+    ///
+    /// A `capacity` of 0 disables caching.
+    pub fn new(capacity: usize) -> Self {
+        ModulesCache {
+            entries: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Returns the number of modules in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes all the modules from the cache.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns the module with the given code hash and execution hint, and marks it as the most
+    /// recently used.
+    fn get(&mut self, code_hash: &[u8; 32], exec_hint: vm::ExecHint) -> Option<vm::Module> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(h, e, _)| h == code_hash && *e == exec_hint)?;
+        let entry = self.entries.remove(index);
+        let module = entry.2.clone();
+        self.entries.push(entry);
+        Some(module)
+    }
+
+    /// Inserts a module in the cache, removing the least recently used one if necessary.
+    fn insert(&mut self, code_hash: [u8; 32], exec_hint: vm::ExecHint, module: vm::Module) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity {
+            self.entries.remove(0);
+        }
+
+        self.entries.push((code_hash, exec_hint, module));
+    }
+}
+
+impl fmt::Debug for ModulesCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.entries.iter().map(|(h, e, _)| (h, e)))
+            .finish()
+    }
+}
+
 impl Clone for HostVmPrototype {
     fn clone(&self) -> Self {
         // The `from_module` function returns an error if the format of the module is invalid.
@@ -1345,6 +1471,15 @@ impl ReadyToRun {
                 self.inner
                     .alloc_write_and_return_pointer(host_fn.name(), iter::once(&out))
             }
+            HostFunction::ext_hashing_keccak_512_version_1 => {
+                let mut keccak = tiny_keccak::Keccak::v512();
+                keccak.update(expect_pointer_size!(0).as_ref());
+                let mut out = [0u8; 64];
+                keccak.finalize(&mut out);
+
+                self.inner
+                    .alloc_write_and_return_pointer(host_fn.name(), iter::once(&out))
+            }
             HostFunction::ext_hashing_sha2_256_version_1 => {
                 let mut hasher = sha2::Sha256::new();
                 hasher.update(expect_pointer_size!(0));
@@ -1493,7 +1628,7 @@ impl ReadyToRun {
                     let input = expect_pointer_size!(0);
                     let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
                         nom::combinator::all_consuming(nom::combinator::flat_map(
-                            crate::util::nom_scale_compact_usize,
+                            crate::util::nom_scale_collection_len(2),
                             |num_elems| {
                                 nom::multi::many_m_n(
                                     num_elems,
@@ -1546,7 +1681,7 @@ impl ReadyToRun {
                     let input = expect_pointer_size!(0);
                     let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
                         nom::combinator::all_consuming(nom::combinator::flat_map(
-                            crate::util::nom_scale_compact_usize,
+                            crate::util::nom_scale_collection_len(1),
                             |num_elems| {
                                 nom::multi::many_m_n(
                                     num_elems,
@@ -2077,6 +2212,11 @@ impl ExternalStorageClearPrefix {
     ///
     /// Must be passed how many keys have been cleared, and whether some keys remaing to be
     /// cleared.
+    ///
+    /// For version 2 of the function, this information is returned to the runtime in the form of
+    /// a SCALE-encoded `KillStorageResult`. Once the limit has been reached, the runtime can call
+    /// the function again with the same prefix in order to continue the removal, as the keys
+    /// that have already been removed are no longer part of the storage.
     pub fn resume(self, num_cleared: u32, some_keys_remain: bool) -> HostVm {
         if self.is_v2 {
             self.inner.alloc_write_and_return_pointer_size(
@@ -2821,6 +2961,7 @@ externalities! {
     ext_crypto_start_batch_verify_version_1,
     ext_crypto_finish_batch_verify_version_1,
     ext_hashing_keccak_256_version_1,
+    ext_hashing_keccak_512_version_1,
     ext_hashing_sha2_256_version_1,
     ext_hashing_blake2_128_version_1,
     ext_hashing_blake2_256_version_1,
@@ -2931,7 +3072,8 @@ impl HostFunction {
             HostFunction::ext_crypto_start_batch_verify_version_1 => 0,
             HostFunction::ext_crypto_finish_batch_verify_version_1 => 0,
             HostFunction::ext_hashing_keccak_256_version_1 => 1,
-            HostFunction::ext_hashing_sha2_256_version_1 => todo!(),
+            HostFunction::ext_hashing_keccak_512_version_1 => 1,
+            HostFunction::ext_hashing_sha2_256_version_1 => 1,
             HostFunction::ext_hashing_blake2_128_version_1 => 1,
             HostFunction::ext_hashing_blake2_256_version_1 => 1,
             HostFunction::ext_hashing_twox_64_version_1 => 1,
@@ -3078,10 +3220,201 @@ impl<'a> allocator::Memory for MemAccess<'a> {
 #[cfg(test)]
 mod tests {
     use super::HostVm;
+    use crate::executor::test_module::{self, Instr, ModuleBuilder, ValType};
 
     #[test]
     fn is_send() {
         fn req<T: Send>() {}
         req::<HostVm>();
     }
+
+    #[test]
+    fn storage_get_resumed_later() {
+        let prototype = super::HostVmPrototype::new(super::Config {
+            module: &include_bytes!("./vm/test-polkadot-runtime-v9160.wasm")[..],
+            heap_pages: crate::executor::DEFAULT_HEAP_PAGES,
+            exec_hint: crate::executor::vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+
+        let mut vm: HostVm = prototype
+            .run("AccountNonceApi_account_nonce", &[0; 32])
+            .unwrap()
+            .into();
+        let mut num_storage_gets = 0;
+
+        loop {
+            match vm {
+                HostVm::ReadyToRun(r) => vm = r.run(),
+                HostVm::Finished(finished) => {
+                    // Account nonce of a non-existing account, as a SCALE-encoded `u32`.
+                    assert_eq!(finished.value().as_ref(), &[0, 0, 0, 0]);
+                    break;
+                }
+                HostVm::ExternalStorageGet(req) => {
+                    // The request is moved to a different task and held across an `.await`
+                    // before being answered.
+                    num_storage_gets += 1;
+                    vm = async_std::task::block_on(async_std::task::spawn(async move {
+                        let key = req.key().as_ref().to_vec();
+                        async_std::task::yield_now().await;
+                        assert_eq!(req.key().as_ref(), &key[..]);
+                        req.resume(None)
+                    }));
+                }
+                HostVm::GetMaxLogLevel(req) => vm = req.resume(0),
+                HostVm::LogEmit(req) => vm = req.resume(),
+                _ => unreachable!(),
+            }
+        }
+
+        assert!(num_storage_gets >= 1);
+    }
+
+    /// Builds a minimal Wasm module that exports a `test` function that passes its input to the
+    /// given hashing host function and returns its output, which must be `out_len` bytes.
+    fn hash_module(host_fn: &str, out_len: u32) -> Vec<u8> {
+        let mut module = ModuleBuilder::new();
+        let hash = module.import_function(host_fn, &[ValType::I64], &[ValType::I32]);
+        module.export_function(
+            "test",
+            test_module::input_pointer_size().into_iter().chain([
+                Instr::Call(hash),
+                Instr::I64ExtendI32U,
+                Instr::I64Const(test_module::pointer_size(0, out_len)),
+                Instr::I64Or,
+            ]),
+        );
+        module.build()
+    }
+
+    #[test]
+    fn hashing_host_functions() {
+        let input =
+            b"The quick brown fox jumps over the lazy dog, and the lazy dog jumps over nothing.";
+
+        // Expected values have been calculated independently of the implementations used here.
+        let vectors = [
+            (
+                "ext_hashing_keccak_256_version_1",
+                "29bb7bf4a8cecc9bdadd83970c67b009275f8c010be82c3307a30e7c7787cd2c",
+            ),
+            (
+                "ext_hashing_keccak_512_version_1",
+                "d779916ee9413ffcd464c41d70ea5f7252c75fd1717869effc30e0197e2f5712a5d0670e8e23809cc6c6e4915c0262e748bf6bf6d3d6d4ef1f2fd9b5bd46c4c1",
+            ),
+            (
+                "ext_hashing_sha2_256_version_1",
+                "96637cbee1b026e800f6568200789a494277f69b1b364ee3f7beee372b8d2266",
+            ),
+            (
+                "ext_hashing_blake2_128_version_1",
+                "4fc03b1f4f8d2341758c73560eb44afc",
+            ),
+            (
+                "ext_hashing_blake2_256_version_1",
+                "e49d4ec25c6b31ab3cccbc16036263d621ff2efea13bcd4e6c4ccaeb039573f7",
+            ),
+            ("ext_hashing_twox_64_version_1", "c9c1dca3e9da6395"),
+            (
+                "ext_hashing_twox_128_version_1",
+                "c9c1dca3e9da6395ae00263b4dbda9c7",
+            ),
+            (
+                "ext_hashing_twox_256_version_1",
+                "c9c1dca3e9da6395ae00263b4dbda9c76a327629d6a241c016e9e03b7ea9c8a5",
+            ),
+        ];
+
+        for (host_fn, expected) in vectors {
+            let expected = hex::decode(expected).unwrap();
+            let module = hash_module(host_fn, u32::try_from(expected.len()).unwrap());
+
+            let prototype = super::HostVmPrototype::new(super::Config {
+                module: &module,
+                heap_pages: crate::executor::DEFAULT_HEAP_PAGES,
+                exec_hint: crate::executor::vm::ExecHint::Oneshot,
+                allow_unresolved_imports: false,
+            })
+            .unwrap();
+
+            let mut vm: HostVm = prototype.run("test", &input[..]).unwrap().into();
+            loop {
+                match vm {
+                    HostVm::ReadyToRun(r) => vm = r.run(),
+                    HostVm::Finished(finished) => {
+                        assert_eq!(finished.value().as_ref(), &expected[..], "{}", host_fn);
+                        break;
+                    }
+                    _ => panic!("{}", host_fn),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn modules_cache_reuses_compiled_module() {
+        let module = &include_bytes!("./vm/test-polkadot-runtime-v9160.wasm")[..];
+        let config = |module| super::Config {
+            module,
+            heap_pages: crate::executor::DEFAULT_HEAP_PAGES,
+            exec_hint: crate::executor::vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        };
+
+        // Every module compiled through the cache is inserted in it. Since the cache has room
+        // for two modules, compiling a module again rather than reusing the cached one would
+        // increase its length.
+        let mut cache = super::ModulesCache::new(2);
+        super::HostVmPrototype::new_with_cache(config(module), &mut cache).unwrap();
+        assert_eq!(cache.len(), 1);
+
+        // A module that fails to compile must not be inserted in the cache.
+        assert!(
+            super::HostVmPrototype::new_with_cache(config(&[0, 1, 2][..]), &mut cache).is_err()
+        );
+        assert_eq!(cache.len(), 1);
+
+        // Compiling the same code again hits the cache. The cache entry is shared even though a
+        // different number of heap pages is requested.
+        let proto = super::HostVmPrototype::new_with_cache(
+            super::Config {
+                heap_pages: crate::executor::vm::HeapPages::new(1024),
+                ..config(module)
+            },
+            &mut cache,
+        )
+        .unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            proto.heap_pages(),
+            crate::executor::vm::HeapPages::new(1024)
+        );
+
+        // Modules are cached separately depending on the execution hint.
+        let untrusted = |module| super::Config {
+            exec_hint: crate::executor::vm::ExecHint::Untrusted,
+            ..config(module)
+        };
+        super::HostVmPrototype::new_with_cache(untrusted(module), &mut cache).unwrap();
+        assert_eq!(cache.len(), 2);
+        super::HostVmPrototype::new_with_cache(untrusted(module), &mut cache).unwrap();
+        super::HostVmPrototype::new_with_cache(config(module), &mut cache).unwrap();
+        assert_eq!(cache.len(), 2);
+
+        // The cache never contains more than its capacity.
+        super::HostVmPrototype::new_with_cache(
+            super::Config {
+                exec_hint: crate::executor::vm::ExecHint::CompileAheadOfTime,
+                ..config(module)
+            },
+            &mut cache,
+        )
+        .unwrap();
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
 }
@@ -0,0 +1,224 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Retrieving a specific version of the metadata of a runtime.
+//!
+//! The metadata of a runtime describes, amongst other things, the list of its storage items and
+//! of the transactions it accepts. Its format has evolved over time and is versioned.
+//!
+//! Recent runtimes provide the `Metadata_metadata_versions` function, which returns the list of
+//! metadata versions that they support, and the `Metadata_metadata_at_version` function, which
+//! returns the metadata in a specific version. They can be called with [`metadata_versions`]
+//! and [`metadata_at_version`].
+//!
+//! Tooling that wants to use V15 metadata should request it with [`metadata_at_version`], and
+//! fall back to V14 if [`MetadataAtVersion::VersionUnavailable`] is returned. Older runtimes
+//! don't provide these two functions at all, in which case [`Error::Call`] is returned and the
+//! metadata can only be obtained through the `Metadata_metadata` function.
+
+use super::{call_runtime, host, CallRuntimeError};
+
+use alloc::vec::Vec;
+
+/// Calls the `Metadata_metadata_versions` function of the runtime, and returns the list of
+/// metadata versions that the runtime supports.
+///
+/// Must be passed a closure that returns the storage value corresponding to the given key in
+/// the block storage.
+///
+/// Returns back the same virtual machine prototype as was passed as parameter.
+pub fn metadata_versions(
+    vm: host::HostVmPrototype,
+    storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
+) -> Result<(Vec<u32>, host::HostVmPrototype), Error> {
+    let (output, vm) =
+        call_runtime(vm, "Metadata_metadata_versions", &[], storage_access).map_err(Error::Call)?;
+
+    let versions = nom::combinator::all_consuming(nom::combinator::flat_map(
+        crate::util::nom_scale_collection_len(4),
+        |num_elems| nom::multi::many_m_n(num_elems, num_elems, nom::number::complete::le_u32),
+    ))(&output[..])
+    .map(|(_, versions)| versions)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| ());
+
+    match versions {
+        Ok(versions) => Ok((versions, vm)),
+        Err(()) => Err(Error::Decode(vm)),
+    }
+}
+
+/// Calls the `Metadata_metadata_at_version` function of the runtime, and returns the metadata
+/// in the requested version.
+///
+/// Must be passed a closure that returns the storage value corresponding to the given key in
+/// the block storage.
+///
+/// Returns back the same virtual machine prototype as was passed as parameter.
+pub fn metadata_at_version(
+    vm: host::HostVmPrototype,
+    version: u32,
+    storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
+) -> Result<(MetadataAtVersion, host::HostVmPrototype), Error> {
+    let (output, vm) = call_runtime(
+        vm,
+        "Metadata_metadata_at_version",
+        &[&version.to_le_bytes()],
+        storage_access,
+    )
+    .map_err(Error::Call)?;
+
+    // The output is a SCALE-encoded `Option<Vec<u8>>`.
+    let metadata = nom::combinator::all_consuming(nom::branch::alt((
+        nom::combinator::map(nom::bytes::complete::tag(&[0]), |_| None),
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[1]),
+                crate::util::nom_bytes_decode,
+            ),
+            Some,
+        ),
+    )))(&output[..])
+    .map(|(_, metadata)| metadata)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| ());
+
+    match metadata {
+        Ok(Some(metadata)) => Ok((MetadataAtVersion::Available(metadata.to_vec()), vm)),
+        Ok(None) => Ok((MetadataAtVersion::VersionUnavailable, vm)),
+        Err(()) => Err(Error::Decode(vm)),
+    }
+}
+
+/// Successful outcome of [`metadata_at_version`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataAtVersion {
+    /// The runtime supports the requested version. Contains the metadata, without any length
+    /// prefix, similar to the output of [`crate::remove_metadata_length_prefix`].
+    Available(Vec<u8>),
+    /// The runtime doesn't support the requested version.
+    VersionUnavailable,
+}
+
+/// Error potentially returned by [`metadata_versions`] or [`metadata_at_version`].
+///
+/// Each variant contains the virtual machine prototype that was passed as parameter, so that it
+/// can be used again, for example to call `Metadata_metadata`.
+#[derive(Debug, derive_more::Display)]
+pub enum Error {
+    /// Error while calling the runtime function. This is the case if the runtime doesn't provide
+    /// the function.
+    #[display(fmt = "{}", _0)]
+    Call(CallRuntimeError),
+    /// Failed to decode the output of the runtime function.
+    #[display(fmt = "Failed to decode the output of the runtime function")]
+    Decode(host::HostVmPrototype),
+}
+
+impl Error {
+    /// Returns the virtual machine prototype that was passed as parameter.
+    pub fn into_prototype(self) -> host::HostVmPrototype {
+        match self {
+            Error::Call(err) => err.into_prototype(),
+            Error::Decode(proto) => proto,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        host,
+        test_module::{self, Instr, ModuleBuilder},
+        vm,
+    };
+    use alloc::vec::Vec;
+
+    /// Minimal Wasm module exporting the `Metadata_metadata_versions` and
+    /// `Metadata_metadata_at_version` functions.
+    ///
+    /// The runtime supports the metadata versions 14 and 15, whose metadata are respectively
+    /// `v14` and `v15`. The outputs of the functions are stored in a data segment: the list of
+    /// versions at offset 0, `Some(v14)` at offset 16, `Some(v15)` at offset 32, and `None` at
+    /// offset 48.
+    fn metadata_module() -> Vec<u8> {
+        let mut module = ModuleBuilder::new();
+        module.export_function(
+            "Metadata_metadata_versions",
+            [Instr::I64Const(test_module::pointer_size(0, 9))],
+        );
+        // Select the output depending on the version found in the input.
+        module.export_function(
+            "Metadata_metadata_at_version",
+            [
+                Instr::I64Const(test_module::pointer_size(16, 5)),
+                Instr::I64Const(test_module::pointer_size(32, 5)),
+                Instr::I64Const(test_module::pointer_size(48, 1)),
+                Instr::LocalGet(0),
+                Instr::I32Load { offset: 0 },
+                Instr::I32Const(15),
+                Instr::I32Eq,
+                Instr::Select,
+                Instr::LocalGet(0),
+                Instr::I32Load { offset: 0 },
+                Instr::I32Const(14),
+                Instr::I32Eq,
+                Instr::Select,
+            ],
+        );
+        let mut data = [0; 49];
+        data[0..9].copy_from_slice(&[0x08, 14, 0, 0, 0, 15, 0, 0, 0]);
+        data[16..21].copy_from_slice(&[0x01, 0x0c, b'v', b'1', b'4']);
+        data[32..37].copy_from_slice(&[0x01, 0x0c, b'v', b'1', b'5']);
+        data[48] = 0x00;
+        module.data(0, &data);
+        module.build()
+    }
+
+    #[test]
+    fn multiple_versions() {
+        let vm = host::HostVmPrototype::new(host::Config {
+            module: metadata_module(),
+            heap_pages: vm::HeapPages::new(16),
+            exec_hint: vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+
+        let (versions, vm) = super::metadata_versions(vm, |_| None).unwrap();
+        assert_eq!(versions, [14, 15]);
+
+        let (metadata, vm) = super::metadata_at_version(vm, 15, |_| None).unwrap();
+        assert_eq!(
+            metadata,
+            super::MetadataAtVersion::Available(b"v15".to_vec())
+        );
+
+        let (metadata, vm) = super::metadata_at_version(vm, 14, |_| None).unwrap();
+        assert_eq!(
+            metadata,
+            super::MetadataAtVersion::Available(b"v14".to_vec())
+        );
+
+        let (metadata, vm) = super::metadata_at_version(vm, 16, |_| None).unwrap();
+        assert_eq!(metadata, super::MetadataAtVersion::VersionUnavailable);
+
+        // The prototype can be used again to call other functions.
+        assert!(matches!(
+            super::super::call_runtime(vm, "Metadata_metadata", &[], |_| None),
+            Err(super::super::CallRuntimeError::Start(_, _))
+        ));
+    }
+}
@@ -19,7 +19,10 @@
 
 // TODO: more docs
 
-use crate::executor::{self, host, vm};
+use crate::{
+    executor::{self, host, vm},
+    trie::proof_verify,
+};
 
 use alloc::{
     string::{String, ToString as _},
@@ -54,6 +57,193 @@ pub fn run(
     .run())
 }
 
+/// Configuration for [`run_with_proof`].
+pub struct ProofConfig<'a, TParams, TProof> {
+    /// Virtual machine to be run.
+    pub virtual_machine: host::HostVmPrototype,
+
+    /// Name of the function to be called.
+    pub function_to_call: &'a str,
+
+    /// Parameter of the call, as an iterator of bytes. The concatenation of bytes forms the
+    /// actual input.
+    pub parameter: TParams,
+
+    /// Merkle value of the root node of the storage trie that the runtime call accesses.
+    pub storage_trie_root: &'a [u8; 32],
+
+    /// List of node values of the storage trie. No specific order is required. See
+    /// [`proof_verify::VerifyProofConfig::proof`].
+    pub proof: TProof,
+}
+
+/// Runs the WebAssembly virtual machine to completion, using the given storage proof as the
+/// only source of storage values.
+///
+/// The proof is verified as the runtime accesses the storage. Reading a key that is proven to
+/// not have any storage value returns `None` to the runtime, like a regular storage would. If
+/// the runtime accesses a key that the proof doesn't cover, the execution stops with
+/// [`ProofErrorDetail::KeyNotInProof`].
+pub fn run_with_proof<'b>(
+    config: ProofConfig<
+        '_,
+        impl Iterator<Item = impl AsRef<[u8]>> + Clone,
+        impl Iterator<Item = &'b [u8]> + Clone,
+    >,
+) -> Result<Success, ProofError> {
+    let mut vm = run(Config {
+        virtual_machine: config.virtual_machine,
+        function_to_call: config.function_to_call,
+        parameter: config.parameter,
+    })
+    .map_err(|(error, prototype)| ProofError {
+        detail: ProofErrorDetail::Start(error),
+        prototype,
+    })?;
+
+    let storage = ProofStorage::new(config.storage_trie_root, config.proof);
+
+    loop {
+        match vm {
+            RuntimeHostVm::Finished(Ok(success)) => return Ok(success),
+            RuntimeHostVm::Finished(Err(error)) => {
+                return Err(ProofError {
+                    detail: ProofErrorDetail::Execution(error.detail),
+                    prototype: error.prototype,
+                })
+            }
+            RuntimeHostVm::StorageGet(req) => {
+                let key = req.key_as_vec();
+                match storage.get(&key) {
+                    Ok(value) => vm = req.inject_value(value.map(iter::once)),
+                    Err(error) => {
+                        return Err(ProofError {
+                            detail: ProofErrorDetail::from_proof_error(key, error),
+                            prototype: RuntimeHostVm::StorageGet(req).into_prototype(),
+                        })
+                    }
+                }
+            }
+            RuntimeHostVm::NextKey(req) => {
+                let key = req.key().as_ref().to_vec();
+                match storage.next_key(&key) {
+                    Ok(next_key) => vm = req.inject_key(next_key),
+                    Err(error) => {
+                        return Err(ProofError {
+                            detail: ProofErrorDetail::from_proof_error(key, error),
+                            prototype: RuntimeHostVm::NextKey(req).into_prototype(),
+                        })
+                    }
+                }
+            }
+            RuntimeHostVm::StorageRoot(req) => vm = req.resume(config.storage_trie_root),
+        }
+    }
+}
+
+/// Storage of a block backed by a storage proof. This is the storage used by
+/// [`run_with_proof`].
+///
+/// The proof is verified whenever a value is read.
+#[derive(Debug, Clone)]
+pub struct ProofStorage<'a, TProof> {
+    /// See [`ProofConfig::storage_trie_root`].
+    storage_trie_root: &'a [u8; 32],
+    /// See [`ProofConfig::proof`].
+    proof: TProof,
+}
+
+impl<'a, 'b, TProof> ProofStorage<'a, TProof>
+where
+    TProof: Iterator<Item = &'b [u8]> + Clone,
+{
+    /// Builds a new [`ProofStorage`] from the storage trie root and the list of node values of
+    /// the proof.
+    pub fn new(storage_trie_root: &'a [u8; 32], proof: TProof) -> Self {
+        ProofStorage {
+            storage_trie_root,
+            proof,
+        }
+    }
+
+    /// Returns the storage value associated to the given key, or `None` if the proof proves that
+    /// the key doesn't have any storage value.
+    ///
+    /// Returns an error if the proof doesn't cover the key or is invalid.
+    pub fn get(&self, key: &[u8]) -> Result<Option<&'b [u8]>, proof_verify::Error> {
+        proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+            requested_key: key,
+            trie_root_hash: self.storage_trie_root,
+            proof: self.proof.clone(),
+        })
+    }
+
+    /// Returns the storage values associated to the given keys, in the same order as the keys.
+    ///
+    /// Each element of the returned `Vec` is the same as what [`ProofStorage::get`] would
+    /// return for the corresponding key. Returns an error if the proof doesn't cover at least
+    /// one of the keys or is invalid.
+    pub fn get_many(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, proof_verify::Error> {
+        keys.iter()
+            .map(|key| Ok(self.get(key)?.map(|value| value.to_vec())))
+            .collect()
+    }
+
+    /// Returns the smallest key, strictly superior to `key_before`, that has a storage value, or
+    /// `None` if the proof proves that there isn't any.
+    ///
+    /// Returns an error if the proof doesn't cover the keys in question or is invalid.
+    pub fn next_key(&self, key_before: &[u8]) -> Result<Option<Vec<u8>>, proof_verify::Error> {
+        proof_verify::next_key(proof_verify::NextKeyConfig {
+            key_before,
+            trie_root_hash: self.storage_trie_root,
+            proof: self.proof.clone(),
+        })
+    }
+}
+
+/// Error that can happen during [`run_with_proof`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "{}", detail)]
+pub struct ProofError {
+    /// Exact error that happened.
+    pub detail: ProofErrorDetail,
+    /// Prototype of the virtual machine that was passed through
+    /// [`ProofConfig::virtual_machine`].
+    pub prototype: host::HostVmPrototype,
+}
+
+/// See [`ProofError::detail`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum ProofErrorDetail {
+    /// Error while starting the virtual machine.
+    #[display(fmt = "Error while starting the virtual machine: {}", _0)]
+    Start(host::StartErr),
+    /// Error during the execution.
+    #[display(fmt = "{}", _0)]
+    Execution(ErrorDetail),
+    /// The runtime has accessed a key that isn't covered by the proof.
+    #[display(fmt = "Key not covered by the proof: {}", "hex::encode(key)")]
+    KeyNotInProof {
+        /// Key that the runtime has accessed.
+        key: Vec<u8>,
+    },
+    /// The proof is invalid.
+    #[display(fmt = "Invalid proof: {}", _0)]
+    InvalidProof(proof_verify::Error),
+}
+
+impl ProofErrorDetail {
+    fn from_proof_error(key: Vec<u8>, error: proof_verify::Error) -> Self {
+        match error {
+            proof_verify::Error::MissingProofEntry { .. } => {
+                ProofErrorDetail::KeyNotInProof { key }
+            }
+            error => ProofErrorDetail::InvalidProof(error),
+        }
+    }
+}
+
 /// Execution is successful.
 #[derive(Debug)]
 pub struct Success {
@@ -364,3 +554,119 @@ impl Inner {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::{
+        host,
+        test_module::{self, Instr, ModuleBuilder, ValType},
+        vm,
+    };
+
+    /// Builds a minimal runtime whose `get` function performs a call to
+    /// `ext_storage_get_version_1` with its input as the key, and returns the output of this host
+    /// function.
+    fn storage_get_module() -> Vec<u8> {
+        let mut module = ModuleBuilder::new();
+        let get = module.import_function(
+            "ext_storage_get_version_1",
+            &[ValType::I64],
+            &[ValType::I64],
+        );
+        module.export_function(
+            "get",
+            test_module::input_pointer_size()
+                .into_iter()
+                .chain([Instr::Call(get)]),
+        );
+        module.build()
+    }
+
+    #[test]
+    fn run_with_proof() {
+        // Trie containing the key `[0x12, 0x34]` with value `[0xaa, 0xbb]`, whose root node
+        // has a second child at nibble `2` that isn't part of the proof.
+        let root = {
+            let mut n = vec![0x80, 0x06, 0x00];
+            n.extend_from_slice(&[6 << 2, 0x43, 0x02, 0x34, 2 << 2, 0xaa, 0xbb]);
+            n.push(32 << 2);
+            n.extend_from_slice(&[0xff; 32]);
+            n
+        };
+        let trie_root =
+            <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], &root).as_bytes()).unwrap();
+
+        let virtual_machine = host::HostVmPrototype::new(host::Config {
+            module: storage_get_module(),
+            heap_pages: vm::HeapPages::new(256),
+            exec_hint: vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+
+        let run = |key: &[u8]| {
+            super::run_with_proof(super::ProofConfig {
+                virtual_machine: virtual_machine.clone(),
+                function_to_call: "get",
+                parameter: core::iter::once(key),
+                storage_trie_root: &trie_root,
+                proof: core::iter::once(&root[..]),
+            })
+            .map(|success| success.virtual_machine.value().as_ref().to_vec())
+            .map_err(|err| err.detail)
+        };
+
+        assert_eq!(run(&[0x12, 0x34]).unwrap(), vec![1, 2 << 2, 0xaa, 0xbb]);
+
+        // Proofs of absence.
+        assert_eq!(run(&[0x12, 0x35]).unwrap(), vec![0]);
+        assert_eq!(run(&[0x13]).unwrap(), vec![0]);
+        assert_eq!(run(&[0x30]).unwrap(), vec![0]);
+
+        assert!(matches!(
+            run(&[0x25]),
+            Err(super::ProofErrorDetail::KeyNotInProof { key }) if key == [0x25]
+        ));
+    }
+
+    #[test]
+    fn proof_storage_get_many() {
+        let storage = (0..16u8)
+            .map(|n| (vec![n * 3, n], vec![n; 40]))
+            .collect::<Vec<_>>();
+        let trie_root = crate::trie::trie_root(&storage);
+
+        let requested_keys: [&[u8]; 5] = [
+            &storage[9].0,
+            &[0x01],
+            &storage[2].0,
+            &storage[9].0,
+            &[0x1e, 0x0b],
+        ];
+        let proof =
+            crate::trie::proof_generate::generate_read_proof(crate::trie::proof_generate::Config {
+                keys: requested_keys.iter(),
+                storage_entries: storage.iter().map(|(k, v)| (&k[..], &v[..])),
+                trie_root_hash: &trie_root,
+            })
+            .unwrap();
+        let proof = crate::trie::decode_proof(&proof).unwrap();
+
+        let proof_storage = super::ProofStorage::new(&trie_root, proof.iter().copied());
+        assert_eq!(
+            proof_storage.get_many(&requested_keys).unwrap(),
+            vec![
+                Some(storage[9].1.clone()),
+                None,
+                Some(storage[2].1.clone()),
+                Some(storage[9].1.clone()),
+                None,
+            ]
+        );
+
+        // Keys that the proof doesn't cover.
+        assert!(proof_storage
+            .get_many(&[&storage[2].0, &storage[5].0])
+            .is_err());
+    }
+}
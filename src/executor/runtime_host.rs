@@ -45,12 +45,15 @@ use crate::{
 
 use alloc::{
     borrow::ToOwned as _,
+    collections::BTreeMap,
     string::{String, ToString as _},
     vec::Vec,
 };
-use core::{fmt, iter};
+use core::{fmt, iter, ops};
 use hashbrown::{hash_map::Entry, HashMap, HashSet};
 
+pub mod trace;
+
 /// Configuration for [`run`].
 pub struct Config<'a, TParams> {
     /// Virtual machine to be run.
@@ -74,6 +77,11 @@ pub struct Config<'a, TParams> {
     /// Initial state of [`Success::offchain_storage_changes`]. The changes made during this
     /// execution will be pushed over the value in this field.
     pub offchain_storage_changes: storage_diff::StorageDiff,
+
+    /// Optional cache of the values of the storage. If `None`, values read from the storage
+    /// aren't cached. Must only contain values coming from the same storage as the one the
+    /// [`StorageGet`]s are answered from.
+    pub storage_read_cache: Option<StorageReadCache>,
 }
 
 /// Start running the WebAssembly virtual machine.
@@ -92,11 +100,79 @@ pub fn run(
             config.top_trie_root_calculation_cache.unwrap_or_default(),
         ),
         root_calculation: None,
+        latest_storage_root: None,
+        runtime_environment_updated: false,
+        runtime_environment_updated_before_transaction: false,
+        storage_read_cache: config.storage_read_cache,
         logs: String::new(),
     }
     .run())
 }
 
+/// Cache of the values read from the storage, meant to be shared between the multiple calls
+/// that are performed on top of the same storage, such as all the calls of the execution of a
+/// block.
+///
+/// Values injected through [`StorageGet::inject_value`] or [`StorageGet::inject_value_slice`]
+/// are stored in the cache, and reading them again no longer generates a [`StorageGet`].
+///
+/// Modifying a storage value, or clearing a prefix, removes the concerned keys from the cache.
+/// The new values are found in [`Success::storage_top_trie_changes`] instead. This guarantees
+/// that the cache remains valid if these changes are later discarded, for example by passing the
+/// same cache to a call that uses a different [`Config::storage_top_trie_changes`].
+#[derive(Debug, Default, Clone)]
+pub struct StorageReadCache {
+    /// Values of the storage, indexed by key. Contains `None` if the storage doesn't contain
+    /// any value for this key.
+    values: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl StorageReadCache {
+    /// Builds a new empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of storage values in the cache.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the cache doesn't contain any storage value.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the cached value of the given key, or `None` if the key isn't in the cache.
+    fn get(&self, key: &[u8]) -> Option<Option<&[u8]>> {
+        self.values.get(key).map(|v| v.as_deref())
+    }
+
+    /// Stores a value read from the storage in the cache.
+    fn insert(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+        self.values.insert(key, value);
+    }
+
+    /// Removes the given key from the cache, if it is present.
+    fn invalidate(&mut self, key: &[u8]) {
+        self.values.remove(key);
+    }
+
+    /// Removes all the keys starting with the given prefix from the cache.
+    fn invalidate_prefix(&mut self, prefix: &[u8]) {
+        let to_remove = self
+            .values
+            .range::<[u8], _>((ops::Bound::Included(prefix), ops::Bound::Unbounded))
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in to_remove {
+            self.values.remove(&key);
+        }
+    }
+}
+
 /// Execution is successful.
 #[derive(Debug)]
 pub struct Success {
@@ -111,6 +187,26 @@ pub struct Success {
     pub top_trie_root_calculation_cache: calculate_root::CalculationCache,
     /// Concatenation of all the log messages printed by the runtime.
     pub logs: String,
+    /// Root of the storage trie most recently calculated on behalf of the runtime, if the
+    /// storage hasn't been modified since then.
+    ///
+    /// Contains `None` if the runtime has never requested the root of the storage trie, or if
+    /// it has modified the storage after its latest request.
+    pub storage_root: Option<[u8; 32]>,
+    /// `true` if the runtime has modified the `:code` or `:heappages` keys during the call, for
+    /// example because of a runtime upgrade.
+    ///
+    /// If `true`, the virtual machine passed through [`Config::virtual_machine`] no longer
+    /// corresponds to the storage found in [`Success::storage_top_trie_changes`]. A new virtual
+    /// machine must be built from the values of `:code` and `:heappages` in
+    /// [`Success::storage_top_trie_changes`] before performing further calls on top of these
+    /// changes. If these keys have been modified multiple times, only their final values matter.
+    ///
+    /// Modifications reverted by rolling back a storage transaction are ignored.
+    pub runtime_environment_updated: bool,
+    /// Cache passed through [`Config::storage_read_cache`], updated with the values read during
+    /// this call.
+    pub storage_read_cache: Option<StorageReadCache>,
 }
 
 /// Function execution has succeeded. Contains the return value of the call.
@@ -243,10 +339,20 @@ impl StorageGet {
 
         match self.inner.vm {
             host::HostVm::ExternalStorageGet(req) => {
+                if let Some(storage_read_cache) = self.inner.storage_read_cache.as_mut() {
+                    storage_read_cache.insert(req.key().as_ref().to_vec(), value.clone());
+                }
+
                 // TODO: should actually report the offset and max_size in the API
                 self.inner.vm = req.resume_full_value(value.as_ref().map(|v| &v[..]));
             }
             host::HostVm::ExternalStorageAppend(req) => {
+                self.inner.runtime_environment_updated |=
+                    is_runtime_environment_key(req.key().as_ref());
+                if let Some(storage_read_cache) = self.inner.storage_read_cache.as_mut() {
+                    storage_read_cache.invalidate(req.key().as_ref());
+                }
+
                 // TODO: could be less overhead?
                 let mut value = value.unwrap_or_default();
                 append_to_storage_value(&mut value, req.value().as_ref());
@@ -259,6 +365,10 @@ impl StorageGet {
                 if let calculate_root::RootMerkleValueCalculation::StorageValue(value_request) =
                     self.inner.root_calculation.take().unwrap()
                 {
+                    if let Some(storage_read_cache) = self.inner.storage_read_cache.as_mut() {
+                        storage_read_cache.insert(value_request.key().collect(), value.clone());
+                    }
+
                     self.inner.root_calculation = Some(value_request.inject(value));
                 } else {
                     // We only create a `StorageGet` if the state is `StorageValue`.
@@ -272,6 +382,31 @@ impl StorageGet {
 
         self.inner.run()
     }
+
+    /// Injects the corresponding storage value.
+    ///
+    /// Equivalent to [`StorageGet::inject_value`], except that the value is passed as a single
+    /// buffer. When the runtime is reading a storage value, this buffer is directly copied to the
+    /// memory of the virtual machine. This makes it possible for storage backends that can lend
+    /// their values, such as memory-mapped databases, to avoid any intermediary allocation.
+    pub fn inject_value_slice(mut self, value: Option<&[u8]>) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::ExternalStorageGet(req) => {
+                if let Some(storage_read_cache) = self.inner.storage_read_cache.as_mut() {
+                    storage_read_cache
+                        .insert(req.key().as_ref().to_vec(), value.map(|v| v.to_vec()));
+                }
+
+                // TODO: should actually report the offset and max_size in the API
+                self.inner.vm = req.resume_full_value(value);
+                self.inner.run()
+            }
+            vm => {
+                self.inner.vm = vm;
+                self.inject_value(value.map(iter::once))
+            }
+        }
+    }
 }
 
 /// Fetching the list of keys with a given prefix is required in order to continue.
@@ -308,6 +443,10 @@ impl PrefixKeys {
                 let mut keys_removed_so_far = 0u32;
 
                 let prefix = req.prefix().as_ref().to_owned();
+                if let Some(storage_read_cache) = self.inner.storage_read_cache.as_mut() {
+                    storage_read_cache.invalidate_prefix(&prefix);
+                }
+
                 let mut after_overlay = self
                     .inner
                     .top_trie_changes
@@ -340,6 +479,8 @@ impl PrefixKeys {
                 drop(after_overlay);
 
                 for key in keys_to_remove {
+                    self.inner.runtime_environment_updated |= is_runtime_environment_key(&key);
+                    self.inner.latest_storage_root = None;
                     self.inner
                         .top_trie_root_calculation_cache
                         .as_mut()
@@ -493,6 +634,19 @@ struct Inner {
     /// Trie root calculation in progress.
     root_calculation: Option<calculate_root::RootMerkleValueCalculation>,
 
+    /// See [`Success::storage_root`]. Reset to `None` whenever the storage is modified.
+    latest_storage_root: Option<[u8; 32]>,
+
+    /// See [`Success::runtime_environment_updated`].
+    runtime_environment_updated: bool,
+
+    /// Value of [`Inner::runtime_environment_updated`] when the current storage transaction has
+    /// started. Restored if the transaction is rolled back.
+    runtime_environment_updated_before_transaction: bool,
+
+    /// See [`Config::storage_read_cache`].
+    storage_read_cache: Option<StorageReadCache>,
+
     /// Concatenation of all the log messages generated by the runtime.
     logs: String,
 }
@@ -523,6 +677,9 @@ impl Inner {
                             .top_trie_root_calculation_cache
                             .unwrap(),
                         logs: self.logs,
+                        storage_root: self.latest_storage_root,
+                        runtime_environment_updated: self.runtime_environment_updated,
+                        storage_read_cache: self.storage_read_cache,
                     }));
                 }
 
@@ -530,6 +687,12 @@ impl Inner {
                     let search = self.top_trie_changes.diff_get(req.key().as_ref());
                     if let Some(overlay) = search {
                         self.vm = req.resume_full_value(overlay);
+                    } else if let Some(cached) = self
+                        .storage_read_cache
+                        .as_ref()
+                        .and_then(|cache| cache.get(req.key().as_ref()))
+                    {
+                        self.vm = req.resume_full_value(cached);
                     } else {
                         self.vm = req.into();
                         return RuntimeHostVm::StorageGet(StorageGet { inner: self });
@@ -537,6 +700,12 @@ impl Inner {
                 }
 
                 host::HostVm::ExternalStorageSet(req) => {
+                    self.runtime_environment_updated |=
+                        is_runtime_environment_key(req.key().as_ref());
+                    self.latest_storage_root = None;
+                    if let Some(storage_read_cache) = self.storage_read_cache.as_mut() {
+                        storage_read_cache.invalidate(req.key().as_ref());
+                    }
                     self.top_trie_root_calculation_cache
                         .as_mut()
                         .unwrap()
@@ -563,15 +732,28 @@ impl Inner {
                 }
 
                 host::HostVm::ExternalStorageAppend(req) => {
+                    self.runtime_environment_updated |=
+                        is_runtime_environment_key(req.key().as_ref());
+                    self.latest_storage_root = None;
                     self.top_trie_root_calculation_cache
                         .as_mut()
                         .unwrap()
                         .storage_value_update(req.key().as_ref(), true);
 
-                    let current_value = self.top_trie_changes.diff_get(req.key().as_ref());
+                    let current_value =
+                        self.top_trie_changes
+                            .diff_get(req.key().as_ref())
+                            .or_else(|| {
+                                self.storage_read_cache
+                                    .as_ref()
+                                    .and_then(|cache| cache.get(req.key().as_ref()))
+                            });
                     if let Some(current_value) = current_value {
                         let mut current_value = current_value.unwrap_or_default().to_vec();
                         append_to_storage_value(&mut current_value, req.value().as_ref());
+                        if let Some(storage_read_cache) = self.storage_read_cache.as_mut() {
+                            storage_read_cache.invalidate(req.key().as_ref());
+                        }
                         let previous_value = self
                             .top_trie_changes
                             .diff_insert(req.key().as_ref().to_vec(), current_value);
@@ -606,6 +788,7 @@ impl Inner {
                     match self.root_calculation.take().unwrap() {
                         calculate_root::RootMerkleValueCalculation::Finished { hash, cache } => {
                             self.top_trie_root_calculation_cache = Some(cache);
+                            self.latest_storage_root = Some(hash);
                             self.vm = req.resume(&hash);
                         }
                         calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
@@ -617,11 +800,15 @@ impl Inner {
                         calculate_root::RootMerkleValueCalculation::StorageValue(value_request) => {
                             self.vm = req.into();
                             // TODO: allocating a Vec, meh
-                            if let Some(overlay) = self
-                                .top_trie_changes
-                                .diff_get(&value_request.key().collect::<Vec<_>>())
-                            {
+                            let key = value_request.key().collect::<Vec<_>>();
+                            if let Some(overlay) = self.top_trie_changes.diff_get(&key) {
                                 self.root_calculation = Some(value_request.inject(overlay));
+                            } else if let Some(cached) = self
+                                .storage_read_cache
+                                .as_ref()
+                                .and_then(|cache| cache.get(&key))
+                            {
+                                self.root_calculation = Some(value_request.inject(cached));
                             } else {
                                 self.root_calculation =
                                     Some(calculate_root::RootMerkleValueCalculation::StorageValue(
@@ -687,6 +874,8 @@ impl Inner {
 
                 host::HostVm::StartStorageTransaction(tx) => {
                     self.top_trie_transaction_revert = Some(Default::default());
+                    self.runtime_environment_updated_before_transaction =
+                        self.runtime_environment_updated;
                     self.vm = tx.resume();
                 }
 
@@ -696,6 +885,9 @@ impl Inner {
                     debug_assert!(self.top_trie_transaction_revert.is_some());
 
                     if rollback {
+                        self.latest_storage_root = None;
+                        self.runtime_environment_updated =
+                            self.runtime_environment_updated_before_transaction;
                         for (key, value) in self.top_trie_transaction_revert.take().unwrap() {
                             if let Some(value) = value {
                                 if let Some(value) = value {
@@ -744,6 +936,12 @@ impl Inner {
     }
 }
 
+/// Returns `true` if modifying the storage value of the given key modifies the runtime. See
+/// [`Success::runtime_environment_updated`].
+fn is_runtime_environment_key(key: &[u8]) -> bool {
+    key == b":code" || key == b":heappages"
+}
+
 /// Performs the action described by [`host::HostVm::ExternalStorageAppend`] on an
 /// encoded storage value.
 fn append_to_storage_value(value: &mut Vec<u8>, to_add: &[u8]) {
@@ -787,3 +985,497 @@ fn append_to_storage_value(value: &mut Vec<u8>, to_add: &[u8]) {
     value[..new_len_encoded_size].copy_from_slice(new_len_encoded.as_ref());
     value.extend_from_slice(to_add);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{host, storage_diff, vm};
+    use crate::executor::test_module::{self, Instr, ModuleBuilder, ValType};
+    use alloc::collections::BTreeMap;
+
+    /// Minimal Wasm module exporting a function named `clear_prefix`.
+    ///
+    /// The input of `clear_prefix` is the prefix to clear, followed with the SCALE-encoded
+    /// `Option<u32>` limit of keys to remove (always 5 bytes). This input is passed as is to
+    /// `ext_storage_clear_prefix_version_2`, and the output of the host function is returned.
+    fn clear_prefix_module() -> Vec<u8> {
+        let mut module = ModuleBuilder::new();
+        let clear_prefix = module.import_function(
+            "ext_storage_clear_prefix_version_2",
+            &[ValType::I64, ValType::I64],
+            &[ValType::I64],
+        );
+        module.export_function(
+            "clear_prefix",
+            [
+                // Pointer-size of the prefix.
+                Instr::LocalGet(0),
+                Instr::I64ExtendI32U,
+                Instr::LocalGet(1),
+                Instr::I32Const(5),
+                Instr::I32Sub,
+                Instr::I64ExtendI32U,
+                Instr::I64Const(32),
+                Instr::I64Shl,
+                Instr::I64Or,
+                // Pointer-size of the limit.
+                Instr::LocalGet(0),
+                Instr::LocalGet(1),
+                Instr::I32Add,
+                Instr::I32Const(5),
+                Instr::I32Sub,
+                Instr::I64ExtendI32U,
+                Instr::I64Const(test_module::pointer_size(0, 5)),
+                Instr::I64Or,
+                Instr::Call(clear_prefix),
+            ],
+        );
+        module.build()
+    }
+
+    #[test]
+    fn clear_prefix_with_limit_resumes() {
+        let storage = [
+            (b"ab1".to_vec(), b"1".to_vec()),
+            (b"ab2".to_vec(), b"2".to_vec()),
+            (b"ab3".to_vec(), b"3".to_vec()),
+            (b"ab4".to_vec(), b"4".to_vec()),
+            (b"ab5".to_vec(), b"5".to_vec()),
+            (b"b".to_vec(), b"6".to_vec()),
+        ]
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+
+        let mut virtual_machine = host::HostVmPrototype::new(host::Config {
+            module: clear_prefix_module(),
+            heap_pages: vm::HeapPages::new(16),
+            exec_hint: vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+        let mut storage_top_trie_changes = storage_diff::StorageDiff::empty();
+
+        // Removes at most 2 keys per call. Each call is supposed to continue where the previous
+        // one has stopped.
+        let mut outcomes = Vec::new();
+        for _ in 0..3 {
+            let mut execution = super::run(super::Config {
+                virtual_machine,
+                function_to_call: "clear_prefix",
+                parameter: [&b"ab"[..], &[1, 2, 0, 0, 0][..]].into_iter(),
+                top_trie_root_calculation_cache: None,
+                storage_top_trie_changes,
+                offchain_storage_changes: storage_diff::StorageDiff::empty(),
+                storage_read_cache: None,
+            })
+            .unwrap();
+
+            let success = loop {
+                match execution {
+                    super::RuntimeHostVm::Finished(Ok(success)) => break success,
+                    super::RuntimeHostVm::Finished(Err(err)) => panic!("{}", err),
+                    super::RuntimeHostVm::PrefixKeys(req) => {
+                        let prefix = req.prefix().as_ref().to_vec();
+                        let keys = storage
+                            .keys()
+                            .filter(|k| k.starts_with(&prefix))
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        execution = req.inject_keys_ordered(keys.into_iter());
+                    }
+                    super::RuntimeHostVm::StorageGet(_) | super::RuntimeHostVm::NextKey(_) => {
+                        panic!()
+                    }
+                }
+            };
+
+            outcomes.push(success.virtual_machine.value().as_ref().to_vec());
+            storage_top_trie_changes = success.storage_top_trie_changes;
+            virtual_machine = success.virtual_machine.into_prototype();
+        }
+
+        // `KillStorageResult::SomeRemaining(2)` twice, then `KillStorageResult::AllRemoved(1)`.
+        assert_eq!(
+            outcomes,
+            vec![
+                vec![1, 2, 0, 0, 0],
+                vec![1, 2, 0, 0, 0],
+                vec![0, 1, 0, 0, 0]
+            ]
+        );
+        for key in storage.keys() {
+            let expected = if key.starts_with(b"ab") {
+                Some(None)
+            } else {
+                None
+            };
+            assert_eq!(storage_top_trie_changes.diff_get(key), expected);
+        }
+    }
+
+    #[test]
+    fn clear_prefix_invalidates_read_cache() {
+        let mut storage_read_cache = super::StorageReadCache::new();
+        for key in [&b"a"[..], b"ab1", b"ab2", b"abc", b"b"] {
+            storage_read_cache.insert(key.to_vec(), Some(b"value".to_vec()));
+        }
+
+        let virtual_machine = host::HostVmPrototype::new(host::Config {
+            module: clear_prefix_module(),
+            heap_pages: vm::HeapPages::new(16),
+            exec_hint: vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+
+        // Only one key is removed, but all the cached values of the prefix are discarded.
+        let mut execution = super::run(super::Config {
+            virtual_machine,
+            function_to_call: "clear_prefix",
+            parameter: [&b"ab"[..], &[1, 1, 0, 0, 0][..]].into_iter(),
+            top_trie_root_calculation_cache: None,
+            storage_top_trie_changes: storage_diff::StorageDiff::empty(),
+            offchain_storage_changes: storage_diff::StorageDiff::empty(),
+            storage_read_cache: Some(storage_read_cache),
+        })
+        .unwrap();
+
+        let success = loop {
+            match execution {
+                super::RuntimeHostVm::Finished(Ok(success)) => break success,
+                super::RuntimeHostVm::Finished(Err(err)) => panic!("{}", err),
+                super::RuntimeHostVm::PrefixKeys(req) => {
+                    execution = req.inject_keys_ordered([&b"ab1"[..], b"ab2"].into_iter());
+                }
+                super::RuntimeHostVm::StorageGet(_) | super::RuntimeHostVm::NextKey(_) => {
+                    panic!()
+                }
+            }
+        };
+
+        let storage_read_cache = success.storage_read_cache.unwrap();
+        assert_eq!(storage_read_cache.len(), 2);
+        assert_eq!(storage_read_cache.get(b"a"), Some(Some(&b"value"[..])));
+        assert_eq!(storage_read_cache.get(b"b"), Some(Some(&b"value"[..])));
+        assert_eq!(storage_read_cache.get(b"ab2"), None);
+    }
+
+    /// Minimal Wasm module exporting two functions named `root` and
+    /// `root_then_set`.
+    ///
+    /// `root` calls `ext_storage_root_version_1` and returns its output. `root_then_set` calls
+    /// `ext_storage_root_version_1`, then stores its input at the key equal to its input, and
+    /// returns an empty output.
+    fn storage_root_module() -> Vec<u8> {
+        let mut module = ModuleBuilder::new();
+        let root = module.import_function("ext_storage_root_version_1", &[], &[ValType::I64]);
+        let set = module.import_function(
+            "ext_storage_set_version_1",
+            &[ValType::I64, ValType::I64],
+            &[],
+        );
+        module.export_function("root", [Instr::Call(root)]);
+        module.export_function(
+            "root_then_set",
+            [Instr::Call(root), Instr::Drop]
+                .into_iter()
+                .chain(test_module::input_pointer_size())
+                .chain(test_module::input_pointer_size())
+                .chain([Instr::Call(set), Instr::I64Const(0)]),
+        );
+        module.build()
+    }
+
+    #[test]
+    fn storage_root_reported() {
+        let mut virtual_machine = host::HostVmPrototype::new(host::Config {
+            module: storage_root_module(),
+            heap_pages: vm::HeapPages::new(16),
+            exec_hint: vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+
+        let mut outcomes = Vec::new();
+        for function_to_call in ["root", "root_then_set"] {
+            let mut execution = super::run(super::Config {
+                virtual_machine,
+                function_to_call,
+                parameter: core::iter::once(&b"foo"[..]),
+                top_trie_root_calculation_cache: None,
+                storage_top_trie_changes: storage_diff::StorageDiff::empty(),
+                offchain_storage_changes: storage_diff::StorageDiff::empty(),
+                storage_read_cache: None,
+            })
+            .unwrap();
+
+            // The storage is empty.
+            let success = loop {
+                match execution {
+                    super::RuntimeHostVm::Finished(Ok(success)) => break success,
+                    super::RuntimeHostVm::Finished(Err(err)) => panic!("{}", err),
+                    super::RuntimeHostVm::PrefixKeys(req) => {
+                        execution = req.inject_keys_ordered(core::iter::empty::<Vec<u8>>());
+                    }
+                    super::RuntimeHostVm::StorageGet(req) => {
+                        execution = req.inject_value(None::<core::iter::Empty<Vec<u8>>>);
+                    }
+                    super::RuntimeHostVm::NextKey(_) => panic!(),
+                }
+            };
+
+            outcomes.push((
+                success.virtual_machine.value().as_ref().to_vec(),
+                success.storage_root,
+            ));
+            virtual_machine = success.virtual_machine.into_prototype();
+        }
+
+        let empty_trie = crate::trie::empty_trie_merkle_value();
+        assert_eq!(outcomes[0], (empty_trie.to_vec(), Some(empty_trie)));
+        // The storage has been modified after the root has been calculated.
+        assert_eq!(outcomes[1], (Vec::new(), None));
+    }
+
+    /// Minimal Wasm module exporting a function named `runtime_version`.
+    ///
+    /// The input of `runtime_version` is passed as is to `ext_misc_runtime_version_version_1`,
+    /// and the output of the host function is returned.
+    fn runtime_version_module() -> Vec<u8> {
+        let mut module = ModuleBuilder::new();
+        let runtime_version = module.import_function(
+            "ext_misc_runtime_version_version_1",
+            &[ValType::I64],
+            &[ValType::I64],
+        );
+        module.export_function(
+            "runtime_version",
+            test_module::input_pointer_size()
+                .into_iter()
+                .chain([Instr::Call(runtime_version)]),
+        );
+        module.build()
+    }
+
+    #[test]
+    fn runtime_version_of_new_code() {
+        // Mimics what `set_code` does: the runtime asks for the version of the new code.
+        let run_with_code = |code: &[u8]| -> Vec<u8> {
+            let virtual_machine = host::HostVmPrototype::new(host::Config {
+                module: runtime_version_module(),
+                heap_pages: vm::HeapPages::new(256),
+                exec_hint: vm::ExecHint::Oneshot,
+                allow_unresolved_imports: false,
+            })
+            .unwrap();
+
+            let execution = super::run(super::Config {
+                virtual_machine,
+                function_to_call: "runtime_version",
+                parameter: core::iter::once(code),
+                top_trie_root_calculation_cache: None,
+                storage_top_trie_changes: storage_diff::StorageDiff::empty(),
+                offchain_storage_changes: storage_diff::StorageDiff::empty(),
+                storage_read_cache: None,
+            })
+            .unwrap();
+
+            match execution {
+                super::RuntimeHostVm::Finished(Ok(success)) => {
+                    success.virtual_machine.value().as_ref().to_vec()
+                }
+                super::RuntimeHostVm::Finished(Err(err)) => panic!("{}", err),
+                _ => panic!(),
+            }
+        };
+
+        // Invalid code leads to `None`.
+        assert_eq!(run_with_code(b"not a runtime"), vec![0]);
+
+        let output = run_with_code(&include_bytes!("./vm/test-polkadot-runtime-v9160.wasm")[..]);
+        assert_eq!(output[0], 1);
+        let (version, rest) = crate::util::scale::decode_length_prefixed(&output[1..]).unwrap();
+        assert!(rest.is_empty());
+        let version = super::super::decode(version).unwrap();
+        assert_eq!(version.spec_name, "polkadot");
+        assert_eq!(version.spec_version, 9160);
+    }
+
+    /// Minimal Wasm module exporting a function named `set`.
+    ///
+    /// `set` calls `ext_storage_set_version_1`, using the first 5 bytes of its input as the key
+    /// and the rest of its input as the value, and returns an empty output.
+    fn storage_set_module() -> Vec<u8> {
+        let mut module = ModuleBuilder::new();
+        let set = module.import_function(
+            "ext_storage_set_version_1",
+            &[ValType::I64, ValType::I64],
+            &[],
+        );
+        module.export_function(
+            "set",
+            [
+                // Key.
+                Instr::LocalGet(0),
+                Instr::I64ExtendI32U,
+                Instr::I64Const(test_module::pointer_size(0, 5)),
+                Instr::I64Or,
+                // Value.
+                Instr::LocalGet(0),
+                Instr::I32Const(5),
+                Instr::I32Add,
+                Instr::I64ExtendI32U,
+                Instr::LocalGet(1),
+                Instr::I32Const(5),
+                Instr::I32Sub,
+                Instr::I64ExtendI32U,
+                Instr::I64Const(32),
+                Instr::I64Shl,
+                Instr::I64Or,
+                Instr::Call(set),
+                Instr::I64Const(0),
+            ],
+        );
+        module.build()
+    }
+
+    #[test]
+    fn runtime_environment_updated() {
+        let mut virtual_machine = host::HostVmPrototype::new(host::Config {
+            module: storage_set_module(),
+            heap_pages: vm::HeapPages::new(16),
+            exec_hint: vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+        let mut storage_top_trie_changes = storage_diff::StorageDiff::empty();
+
+        // Mimics a block that performs a `set_code` twice, with another storage modification in
+        // between.
+        let mut outcomes = Vec::new();
+        for parameter in [&b":codenew1"[..], b":foo_bar", b":codenew2"] {
+            let execution = super::run(super::Config {
+                virtual_machine,
+                function_to_call: "set",
+                parameter: core::iter::once(parameter),
+                top_trie_root_calculation_cache: None,
+                storage_top_trie_changes,
+                offchain_storage_changes: storage_diff::StorageDiff::empty(),
+                storage_read_cache: None,
+            })
+            .unwrap();
+
+            let success = match execution {
+                super::RuntimeHostVm::Finished(Ok(success)) => success,
+                super::RuntimeHostVm::Finished(Err(err)) => panic!("{}", err),
+                _ => panic!(),
+            };
+
+            outcomes.push(success.runtime_environment_updated);
+            storage_top_trie_changes = success.storage_top_trie_changes;
+            virtual_machine = success.virtual_machine.into_prototype();
+        }
+
+        assert_eq!(outcomes, [true, false, true]);
+        assert_eq!(
+            storage_top_trie_changes.diff_get(b":code"),
+            Some(Some(&b"new2"[..]))
+        );
+        assert_eq!(
+            storage_top_trie_changes.diff_get(b":foo_"),
+            Some(Some(&b"bar"[..]))
+        );
+    }
+
+    #[test]
+    fn trace_record_then_replay() {
+        let new_polkadot_vm = || {
+            host::HostVmPrototype::new(host::Config {
+                module: &include_bytes!("./vm/test-polkadot-runtime-v9160.wasm")[..],
+                heap_pages: vm::HeapPages::new(1024),
+                exec_hint: vm::ExecHint::Oneshot,
+                allow_unresolved_imports: false,
+            })
+            .unwrap()
+        };
+        let start = |virtual_machine, function_to_call, parameter: &'static [u8]| {
+            super::run(super::Config {
+                virtual_machine,
+                function_to_call,
+                parameter: core::iter::once(parameter),
+                top_trie_root_calculation_cache: None,
+                storage_top_trie_changes: storage_diff::StorageDiff::empty(),
+                offchain_storage_changes: storage_diff::StorageDiff::empty(),
+                storage_read_cache: None,
+            })
+            .unwrap()
+        };
+
+        // `AccountInfo` corresponding to an account whose nonce is 7.
+        let mut account_info = [0u8; 80];
+        account_info[0] = 7;
+
+        let mut trace = super::trace::Trace::new();
+        let mut execution = start(new_polkadot_vm(), "AccountNonceApi_account_nonce", &[0; 32]);
+        let recorded_output = loop {
+            execution = match execution {
+                super::RuntimeHostVm::Finished(Ok(success)) => {
+                    break success.virtual_machine.value().as_ref().to_vec()
+                }
+                super::RuntimeHostVm::Finished(Err(err)) => panic!("{}", err),
+                super::RuntimeHostVm::StorageGet(req) => {
+                    trace.inject_storage_value(req, Some(&account_info))
+                }
+                _ => panic!(),
+            }
+        };
+        assert_eq!(recorded_output, [7, 0, 0, 0]);
+        assert!(!trace.entries().is_empty());
+
+        // Replaying the same call gives the same output without any storage.
+        let replayed = super::trace::replay(
+            start(new_polkadot_vm(), "AccountNonceApi_account_nonce", &[0; 32]),
+            &trace,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(replayed.virtual_machine.value().as_ref(), recorded_output);
+
+        // A different account leads to a different storage key.
+        let divergence = super::trace::replay(
+            start(new_polkadot_vm(), "AccountNonceApi_account_nonce", &[1; 32]),
+            &trace,
+        );
+        assert!(matches!(
+            divergence,
+            Err((super::trace::ReplayError::Divergence { entry_index: 0 }, _))
+        ));
+
+        // A different runtime that performs different requests.
+        let other_runtime = host::HostVmPrototype::new(host::Config {
+            module: clear_prefix_module(),
+            heap_pages: vm::HeapPages::new(16),
+            exec_hint: vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+        let divergence = super::trace::replay(
+            start(other_runtime, "clear_prefix", &[b'a', b'b', 0, 0, 0, 0, 0]),
+            &trace,
+        );
+        assert!(matches!(
+            divergence,
+            Err((super::trace::ReplayError::Divergence { entry_index: 0 }, _))
+        ));
+
+        // A trace that is missing entries.
+        let truncated = super::trace::Trace::from_entries(Vec::new());
+        let exhausted = super::trace::replay(
+            start(new_polkadot_vm(), "AccountNonceApi_account_nonce", &[0; 32]),
+            &truncated,
+        );
+        assert!(matches!(
+            exhausted,
+            Err((super::trace::ReplayError::TraceExhausted, _))
+        ));
+    }
+}
@@ -0,0 +1,210 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Recording and replaying of the storage accesses of a runtime call.
+//!
+//! While a runtime call is in progress, the [`RuntimeHostVm`] regularly requests information
+//! about the storage. A [`Trace`] records, in order, each of these requests and the response
+//! that has been given back.
+//!
+//! A [`Trace`] can later be passed to [`replay`] in order to execute the same call again without
+//! having access to the storage that was used originally, which is useful in order to reproduce
+//! bugs in a deterministic way. The execution being replayed must issue exactly the same
+//! requests as the ones that have been recorded. If it doesn't, for example because the runtime
+//! code is different, the replay stops at the first divergence.
+//!
+//! > **Note**: Storage values found in the [`super::Config::storage_read_cache`] don't generate
+//! >           any request. If such a cache is used, the replayed execution must be started with
+//! >           a cache identical to the one used during the recording.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use smoldot::executor::runtime_host::{self, trace};
+//!
+//! # fn start_execution() -> runtime_host::RuntimeHostVm { unimplemented!() }
+//! let mut trace = trace::Trace::new();
+//!
+//! let mut execution = start_execution();
+//! let _result = loop {
+//!     execution = match execution {
+//!         runtime_host::RuntimeHostVm::Finished(result) => break result,
+//!         runtime_host::RuntimeHostVm::StorageGet(req) => {
+//!             trace.inject_storage_value(req, None)
+//!         }
+//!         runtime_host::RuntimeHostVm::PrefixKeys(req) => {
+//!             trace.inject_keys_ordered(req, core::iter::empty::<Vec<u8>>())
+//!         }
+//!         runtime_host::RuntimeHostVm::NextKey(req) => {
+//!             trace.inject_next_key(req, None::<Vec<u8>>)
+//!         }
+//!     }
+//! };
+//!
+//! // The same call can now be executed again without any storage.
+//! let _replayed_result = trace::replay(start_execution(), &trace);
+//! ```
+
+use super::{Error, NextKey, PrefixKeys, RuntimeHostVm, StorageGet, Success};
+use crate::executor::host;
+
+use alloc::vec::Vec;
+
+/// Ordered list of the storage requests performed by a runtime call, and their responses.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Trace {
+    entries: Vec<TraceEntry>,
+}
+
+impl Trace {
+    /// Builds a new empty [`Trace`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a [`Trace`] from a list of entries, for example entries that have previously been
+    /// obtained through [`Trace::entries`] and saved.
+    pub fn from_entries(entries: Vec<TraceEntry>) -> Self {
+        Trace { entries }
+    }
+
+    /// Returns the list of requests and responses recorded so far, in chronological order.
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    /// Calls [`StorageGet::inject_value_slice`], and records the request and the response.
+    pub fn inject_storage_value(
+        &mut self,
+        request: StorageGet,
+        value: Option<&[u8]>,
+    ) -> RuntimeHostVm {
+        self.entries.push(TraceEntry::StorageGet {
+            key: request.key_as_vec(),
+            value: value.map(|v| v.to_vec()),
+        });
+        request.inject_value_slice(value)
+    }
+
+    /// Calls [`PrefixKeys::inject_keys_ordered`], and records the request and the response.
+    pub fn inject_keys_ordered(
+        &mut self,
+        request: PrefixKeys,
+        keys: impl Iterator<Item = impl AsRef<[u8]>>,
+    ) -> RuntimeHostVm {
+        let keys = keys.map(|k| k.as_ref().to_vec()).collect::<Vec<_>>();
+        let prefix = request.prefix().as_ref().to_vec();
+        let outcome = request.inject_keys_ordered(keys.iter());
+        self.entries.push(TraceEntry::PrefixKeys { prefix, keys });
+        outcome
+    }
+
+    /// Calls [`NextKey::inject_key`], and records the request and the response.
+    pub fn inject_next_key(
+        &mut self,
+        request: NextKey,
+        next_key: Option<impl AsRef<[u8]>>,
+    ) -> RuntimeHostVm {
+        self.entries.push(TraceEntry::NextKey {
+            key: request.key().as_ref().to_vec(),
+            next_key: next_key.as_ref().map(|k| k.as_ref().to_vec()),
+        });
+        request.inject_key(next_key)
+    }
+}
+
+/// Storage request performed by a runtime call, and its response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEntry {
+    /// See [`RuntimeHostVm::StorageGet`].
+    StorageGet {
+        /// See [`StorageGet::key`].
+        key: Vec<u8>,
+        /// Value that has been injected.
+        value: Option<Vec<u8>>,
+    },
+    /// See [`RuntimeHostVm::PrefixKeys`].
+    PrefixKeys {
+        /// See [`PrefixKeys::prefix`].
+        prefix: Vec<u8>,
+        /// Keys that have been injected.
+        keys: Vec<Vec<u8>>,
+    },
+    /// See [`RuntimeHostVm::NextKey`].
+    NextKey {
+        /// See [`NextKey::key`].
+        key: Vec<u8>,
+        /// Key that has been injected.
+        next_key: Option<Vec<u8>>,
+    },
+}
+
+/// Drives the given execution to its end, answering its storage requests using the responses
+/// found in `trace`.
+///
+/// Returns an error if the execution performs a request that doesn't match the corresponding
+/// entry of the trace, if the execution performs more requests than there are entries, or if
+/// the execution finishes before all the entries have been used.
+pub fn replay(
+    mut execution: RuntimeHostVm,
+    trace: &Trace,
+) -> Result<Result<Success, Error>, (ReplayError, host::HostVmPrototype)> {
+    let mut entries = trace.entries.iter().enumerate();
+
+    loop {
+        execution = match (execution, entries.next()) {
+            (RuntimeHostVm::Finished(result), None) => return Ok(result),
+            (RuntimeHostVm::StorageGet(req), Some((_, TraceEntry::StorageGet { key, value })))
+                if req.key_as_vec() == *key =>
+            {
+                req.inject_value_slice(value.as_deref())
+            }
+            (
+                RuntimeHostVm::PrefixKeys(req),
+                Some((_, TraceEntry::PrefixKeys { prefix, keys })),
+            ) if req.prefix().as_ref() == &prefix[..] => req.inject_keys_ordered(keys.iter()),
+            (RuntimeHostVm::NextKey(req), Some((_, TraceEntry::NextKey { key, next_key })))
+                if req.key().as_ref() == &key[..] =>
+            {
+                req.inject_key(next_key.as_ref())
+            }
+            (execution, Some((entry_index, _))) => {
+                return Err((
+                    ReplayError::Divergence { entry_index },
+                    execution.into_prototype(),
+                ))
+            }
+            (execution, None) => {
+                return Err((ReplayError::TraceExhausted, execution.into_prototype()))
+            }
+        }
+    }
+}
+
+/// Error potentially returned by [`replay`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum ReplayError {
+    /// The execution has performed a request different from the one of the trace, or has
+    /// finished while the trace still contains entries.
+    #[display(fmt = "Execution diverges from the trace at entry #{}", entry_index)]
+    Divergence {
+        /// Index within [`Trace::entries`] of the first entry that doesn't match.
+        entry_index: usize,
+    },
+    /// The execution has performed more requests than there are entries in the trace.
+    TraceExhausted,
+}
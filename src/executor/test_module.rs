@@ -0,0 +1,296 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Builder of minimal Wasm modules, used by tests that need a runtime calling a specific host
+//! function.
+//!
+//! The modules built here import their memory as `env.memory` and export a `__heap_base` global
+//! equal to 1024, like actual runtimes do. Functions exported by the module take the usual
+//! `(ptr, len)` parameters of runtime entry points and return a pointer-size.
+
+use alloc::{string::String, vec::Vec};
+
+/// Type of a parameter or return value of a function.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ValType {
+    I32,
+    I64,
+}
+
+/// Wasm instruction. Only the instructions needed by the tests are supported.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Instr {
+    LocalGet(u32),
+    I32Const(i32),
+    I64Const(i64),
+    /// Loads an `i32` at the address on the stack plus the given offset.
+    I32Load {
+        offset: u32,
+    },
+    I32Add,
+    I32Sub,
+    I32Eq,
+    I64ExtendI32U,
+    I64Shl,
+    I64Or,
+    Select,
+    Drop,
+    /// Calls the function with the given index, as returned by
+    /// [`ModuleBuilder::import_function`].
+    Call(u32),
+}
+
+/// Instructions that push on the stack the pointer-size of the input of an exported function,
+/// in other words `(len << 32) | ptr`.
+pub(crate) fn input_pointer_size() -> [Instr; 7] {
+    [
+        Instr::LocalGet(0),
+        Instr::I64ExtendI32U,
+        Instr::LocalGet(1),
+        Instr::I64ExtendI32U,
+        Instr::I64Const(32),
+        Instr::I64Shl,
+        Instr::I64Or,
+    ]
+}
+
+/// Returns the value of an [`Instr::I64Const`] corresponding to the pointer-size of the given
+/// region of memory.
+pub(crate) fn pointer_size(ptr: u32, size: u32) -> i64 {
+    (i64::from(size) << 32) | i64::from(ptr)
+}
+
+/// Builder of a Wasm module. See [the module-level documentation](..).
+#[derive(Debug, Default)]
+pub(crate) struct ModuleBuilder {
+    types: Vec<(Vec<ValType>, Vec<ValType>)>,
+    /// Name and type index of each imported function.
+    imports: Vec<(String, u32)>,
+    /// Name, type index, and body of each exported function.
+    exports: Vec<(String, u32, Vec<Instr>)>,
+    /// Offset in memory and content of each data segment.
+    data: Vec<(u32, Vec<u8>)>,
+}
+
+impl ModuleBuilder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a function imported from the `env` module. Returns the index to pass to
+    /// [`Instr::Call`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ModuleBuilder::export_function`] has already been called, as functions
+    /// defined in the module are indexed after the imported functions.
+    ///
+    pub(crate) fn import_function(
+        &mut self,
+        name: &str,
+        params: &[ValType],
+        results: &[ValType],
+    ) -> u32 {
+        assert!(self.exports.is_empty());
+        let type_index = self.type_index(params, results);
+        self.imports.push((name.into(), type_index));
+        u32::try_from(self.imports.len() - 1).unwrap()
+    }
+
+    /// Adds a function with the given body, exported under the given name. The function has
+    /// the `(i32, i32) -> i64` signature of runtime entry points.
+    pub(crate) fn export_function(&mut self, name: &str, body: impl IntoIterator<Item = Instr>) {
+        let type_index = self.type_index(&[ValType::I32, ValType::I32], &[ValType::I64]);
+        self.exports
+            .push((name.into(), type_index, body.into_iter().collect()));
+    }
+
+    /// Adds a data segment that initializes the memory at the given offset.
+    pub(crate) fn data(&mut self, offset: u32, content: &[u8]) {
+        self.data.push((offset, content.to_vec()));
+    }
+
+    /// Returns the encoded Wasm module.
+    pub(crate) fn build(self) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut types = Vec::new();
+        leb128(self.types.len() as u64, &mut types);
+        for (params, results) in &self.types {
+            types.push(0x60);
+            for list in [params, results] {
+                leb128(list.len() as u64, &mut types);
+                types.extend(list.iter().map(|ty| match ty {
+                    ValType::I32 => 0x7f,
+                    ValType::I64 => 0x7e,
+                }));
+            }
+        }
+        section(1, types, &mut module);
+
+        let mut imports = Vec::new();
+        leb128(self.imports.len() as u64 + 1, &mut imports);
+        for (name, type_index) in &self.imports {
+            encode_name("env", &mut imports);
+            encode_name(name, &mut imports);
+            imports.push(0x00);
+            leb128(u64::from(*type_index), &mut imports);
+        }
+        encode_name("env", &mut imports);
+        encode_name("memory", &mut imports);
+        imports.extend_from_slice(&[0x02, 0x00, 0x01]);
+        section(2, imports, &mut module);
+
+        let mut functions = Vec::new();
+        leb128(self.exports.len() as u64, &mut functions);
+        for (_, type_index, _) in &self.exports {
+            leb128(u64::from(*type_index), &mut functions);
+        }
+        section(3, functions, &mut module);
+
+        // `__heap_base`, as an immutable `i32` equal to 1024.
+        section(
+            6,
+            vec![0x01, 0x7f, 0x00, 0x41, 0x80, 0x08, 0x0b],
+            &mut module,
+        );
+
+        let mut exports = Vec::new();
+        leb128(self.exports.len() as u64 + 1, &mut exports);
+        for (index, (name, _, _)) in self.exports.iter().enumerate() {
+            encode_name(name, &mut exports);
+            exports.push(0x00);
+            leb128((self.imports.len() + index) as u64, &mut exports);
+        }
+        encode_name("__heap_base", &mut exports);
+        exports.extend_from_slice(&[0x03, 0x00]);
+        section(7, exports, &mut module);
+
+        let mut code = Vec::new();
+        leb128(self.exports.len() as u64, &mut code);
+        for (_, _, instructions) in &self.exports {
+            // No local variable.
+            let mut body = vec![0x00];
+            for instruction in instructions {
+                encode_instruction(instruction, &mut body);
+            }
+            body.push(0x0b);
+            leb128(body.len() as u64, &mut code);
+            code.extend_from_slice(&body);
+        }
+        section(10, code, &mut module);
+
+        if !self.data.is_empty() {
+            let mut data = Vec::new();
+            leb128(self.data.len() as u64, &mut data);
+            for (offset, content) in &self.data {
+                data.extend_from_slice(&[0x00, 0x41]);
+                sleb128(i64::from(*offset), &mut data);
+                data.push(0x0b);
+                leb128(content.len() as u64, &mut data);
+                data.extend_from_slice(content);
+            }
+            section(11, data, &mut module);
+        }
+
+        module
+    }
+
+    fn type_index(&mut self, params: &[ValType], results: &[ValType]) -> u32 {
+        let index = match self
+            .types
+            .iter()
+            .position(|(p, r)| p == params && r == results)
+        {
+            Some(index) => index,
+            None => {
+                self.types.push((params.to_vec(), results.to_vec()));
+                self.types.len() - 1
+            }
+        };
+        u32::try_from(index).unwrap()
+    }
+}
+
+fn encode_instruction(instruction: &Instr, out: &mut Vec<u8>) {
+    match *instruction {
+        Instr::LocalGet(index) => {
+            out.push(0x20);
+            leb128(u64::from(index), out);
+        }
+        Instr::I32Const(value) => {
+            out.push(0x41);
+            sleb128(i64::from(value), out);
+        }
+        Instr::I64Const(value) => {
+            out.push(0x42);
+            sleb128(value, out);
+        }
+        Instr::I32Load { offset } => {
+            // Alignment of 4 bytes.
+            out.extend_from_slice(&[0x28, 0x02]);
+            leb128(u64::from(offset), out);
+        }
+        Instr::I32Add => out.push(0x6a),
+        Instr::I32Sub => out.push(0x6b),
+        Instr::I32Eq => out.push(0x46),
+        Instr::I64ExtendI32U => out.push(0xad),
+        Instr::I64Shl => out.push(0x86),
+        Instr::I64Or => out.push(0x84),
+        Instr::Select => out.push(0x1b),
+        Instr::Drop => out.push(0x1a),
+        Instr::Call(index) => {
+            out.push(0x10);
+            leb128(u64::from(index), out);
+        }
+    }
+}
+
+fn section(id: u8, content: Vec<u8>, out: &mut Vec<u8>) {
+    out.push(id);
+    leb128(content.len() as u64, out);
+    out.extend_from_slice(&content);
+}
+
+fn encode_name(name: &str, out: &mut Vec<u8>) {
+    leb128(name.len() as u64, out);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn leb128(mut value: u64, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn sleb128(mut value: i64, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
@@ -15,5 +15,268 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+pub mod authority_set;
 pub mod commit;
+pub mod finality_proof;
 pub mod warp_sync;
+
+use crate::{
+    finality::{justification::decode, signatures},
+    header,
+};
+
+use alloc::vec::Vec;
+
+/// Verifies that the given SCALE-encoded justification finalizes the block whose number and hash
+/// are `target`, and that it has been produced by the authorities set whose identifier is
+/// `set_id` and whose authorities are `authorities`.
+///
+/// This function doesn't modify any state and can be used to check a justification before
+/// trusting it.
+///
+/// Contrary to [`justification::verify::verify`](super::justification::verify::verify), which
+/// compares the number of signatures with the number of authorities, the weights of the
+/// authorities are taken into account: the authorities that have signed the justification must
+/// represent strictly more than two thirds of the total weight.
+///
+/// `randomness_seed` is used to verify the signatures. See [`signatures::verify_ed25519`].
+pub fn verify_justification(
+    scale_encoded_justification: &[u8],
+    set_id: u64,
+    authorities: &[header::GrandpaAuthority],
+    target: (u64, &[u8; 32]),
+    randomness_seed: [u8; 32],
+) -> Result<(), VerifyJustificationError> {
+    let justification = decode::decode_grandpa(scale_encoded_justification)
+        .map_err(VerifyJustificationError::Decode)?;
+
+    if u64::from(justification.target_number) != target.0 || justification.target_hash != target.1 {
+        return Err(VerifyJustificationError::WrongTarget {
+            justification_target_number: u64::from(justification.target_number),
+            justification_target_hash: *justification.target_hash,
+        });
+    }
+
+    let total_weight = authorities
+        .iter()
+        .fold(0u64, |sum, a| sum.saturating_add(a.weight.get()));
+    let mut signed_weight = 0u64;
+    let mut messages = Vec::with_capacity(justification.precommits.iter().count());
+
+    for (precommit_num, precommit) in justification.precommits.iter().enumerate() {
+        let authority = authorities
+            .iter()
+            .find(|a| a.public_key == *precommit.authority_public_key)
+            .ok_or(VerifyJustificationError::NotAuthority(
+                *precommit.authority_public_key,
+            ))?;
+
+        if justification
+            .precommits
+            .iter()
+            .skip(precommit_num + 1)
+            .any(|pc| pc.authority_public_key == precommit.authority_public_key)
+        {
+            return Err(VerifyJustificationError::DuplicateSignature(
+                *precommit.authority_public_key,
+            ));
+        }
+
+        // TODO: must check signed block ancestry using `votes_ancestries`
+
+        signed_weight = signed_weight.saturating_add(authority.weight.get());
+
+        let mut msg = Vec::with_capacity(1 + 32 + 4 + 8 + 8);
+        msg.push(1u8); // This `1` indicates which kind of message is being signed.
+        msg.extend_from_slice(&precommit.target_hash[..]);
+        msg.extend_from_slice(&u32::to_le_bytes(precommit.target_number)[..]);
+        msg.extend_from_slice(&u64::to_le_bytes(justification.round)[..]);
+        msg.extend_from_slice(&u64::to_le_bytes(set_id)[..]);
+        messages.push((precommit, msg));
+    }
+
+    // The threshold is the same as the one used by Substrate, and is equivalent to
+    // `signed_weight > total_weight * 2 / 3` without any risk of overflow.
+    if total_weight == 0 || signed_weight < total_weight - (total_weight - 1) / 3 {
+        return Err(VerifyJustificationError::InsufficientWeight {
+            signed_weight,
+            total_weight,
+        });
+    }
+
+    let signatures = messages
+        .iter()
+        .map(|(precommit, msg)| signatures::Ed25519Signature {
+            public_key: precommit.authority_public_key,
+            signature: precommit.signature,
+            message: msg,
+        })
+        .collect::<Vec<_>>();
+
+    match signatures::verify_ed25519(&signatures, randomness_seed) {
+        Ok(()) => Ok(()),
+        // If all the signatures are invalid, the justification has most likely been signed for
+        // a different set id.
+        Err(invalid) if invalid.len() == signatures.len() => {
+            Err(VerifyJustificationError::WrongSetId)
+        }
+        Err(_) => Err(VerifyJustificationError::BadSignature),
+    }
+}
+
+/// Error potentially returned by [`verify_justification`].
+#[derive(Debug, derive_more::Display)]
+pub enum VerifyJustificationError {
+    /// Failed to decode the justification.
+    Decode(decode::Error),
+    /// Justification finalizes a different block than the expected one.
+    #[display(
+        fmt = "Justification targets block #{} ({}) instead of the expected block",
+        justification_target_number,
+        "crate::informant::HashDisplay(justification_target_hash)"
+    )]
+    WrongTarget {
+        /// Number of the block targeted by the justification.
+        justification_target_number: u64,
+        /// Hash of the block targeted by the justification.
+        justification_target_hash: [u8; 32],
+    },
+    /// One of the public keys isn't in the list of authorities.
+    #[display(fmt = "One of the public keys isn't in the list of authorities")]
+    NotAuthority([u8; 32]),
+    /// One authority has produced two signatures.
+    #[display(fmt = "One authority has produced two signatures")]
+    DuplicateSignature([u8; 32]),
+    /// The authorities that have signed the justification don't represent more than two thirds
+    /// of the total weight.
+    #[display(
+        fmt = "Insufficient weight of signatures: {} out of {}",
+        signed_weight,
+        total_weight
+    )]
+    InsufficientWeight {
+        /// Sum of the weights of the authorities that have signed the justification.
+        signed_weight: u64,
+        /// Sum of the weights of all the authorities.
+        total_weight: u64,
+    },
+    /// None of the signatures are valid for the expected set id. The justification has most
+    /// likely been produced by a different authorities set.
+    WrongSetId,
+    /// Some of the signatures are invalid.
+    BadSignature,
+}
+
+/// Builds a justification targeting the given block, signed by the given keys. Used to test
+/// the verification of justifications.
+#[cfg(test)]
+pub(crate) fn build_justification(
+    keys: &[ed25519_zebra::SigningKey],
+    set_id: u64,
+    target: (u32, [u8; 32]),
+) -> Vec<u8> {
+    let round = 1u64;
+    let mut justification = Vec::new();
+    justification.extend_from_slice(&round.to_le_bytes());
+    justification.extend_from_slice(&target.1);
+    justification.extend_from_slice(&target.0.to_le_bytes());
+    justification.extend_from_slice(crate::util::encode_scale_compact_usize(keys.len()).as_ref());
+    for key in keys {
+        let mut msg = vec![1u8];
+        msg.extend_from_slice(&target.1);
+        msg.extend_from_slice(&target.0.to_le_bytes());
+        msg.extend_from_slice(&round.to_le_bytes());
+        msg.extend_from_slice(&set_id.to_le_bytes());
+
+        justification.extend_from_slice(&target.1);
+        justification.extend_from_slice(&target.0.to_le_bytes());
+        justification.extend_from_slice(&<[u8; 64]>::from(key.sign(&msg)));
+        justification
+            .extend_from_slice(&<[u8; 32]>::from(ed25519_zebra::VerificationKey::from(key)));
+    }
+    justification.push(0); // No votes ancestry.
+    justification
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VerifyJustificationError;
+    use crate::header;
+    use core::num::NonZeroU64;
+
+    fn public_key(key: &ed25519_zebra::SigningKey) -> [u8; 32] {
+        <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(key))
+    }
+
+    #[test]
+    fn verify_justification() {
+        let keys = (0..4u8)
+            .map(|n| ed25519_zebra::SigningKey::from([n; 32]))
+            .collect::<Vec<_>>();
+        let authorities = keys
+            .iter()
+            .map(|key| header::GrandpaAuthority {
+                public_key: public_key(key),
+                weight: NonZeroU64::new(1).unwrap(),
+            })
+            .collect::<Vec<_>>();
+        let target = (12, [0xaa; 32]);
+
+        // Valid justification signed by three authorities out of four.
+        let valid = super::build_justification(&keys[..3], 5, target);
+        super::verify_justification(&valid, 5, &authorities, (12, &target.1), [0; 32]).unwrap();
+
+        // Same justification, but verified against the wrong target.
+        assert!(matches!(
+            super::verify_justification(&valid, 5, &authorities, (13, &target.1), [0; 32]),
+            Err(VerifyJustificationError::WrongTarget { .. })
+        ));
+
+        // Justification signed by the previous set.
+        let wrong_set = super::build_justification(&keys[..3], 4, target);
+        assert!(matches!(
+            super::verify_justification(&wrong_set, 5, &authorities, (12, &target.1), [0; 32]),
+            Err(VerifyJustificationError::WrongSetId)
+        ));
+
+        // Not enough weight.
+        let insufficient = super::build_justification(&keys[..2], 5, target);
+        assert!(matches!(
+            super::verify_justification(&insufficient, 5, &authorities, (12, &target.1), [0; 32]),
+            Err(VerifyJustificationError::InsufficientWeight {
+                signed_weight: 2,
+                total_weight: 4
+            })
+        ));
+
+        // The same signatures are enough if the signers have more weight.
+        let mut weighted = authorities.clone();
+        weighted[0].weight = NonZeroU64::new(10).unwrap();
+        super::verify_justification(&insufficient, 5, &weighted, (12, &target.1), [0; 32]).unwrap();
+
+        // Justification containing a vote from a non-authority.
+        let outsider = ed25519_zebra::SigningKey::from([0xff; 32]);
+        let with_outsider =
+            super::build_justification(&[keys[0], keys[1], keys[2], outsider], 5, target);
+        assert!(matches!(
+            super::verify_justification(
+                &with_outsider,
+                5,
+                &authorities,
+                (12, &target.1),
+                [0; 32]
+            ),
+            Err(VerifyJustificationError::NotAuthority(key)) if key == public_key(&outsider)
+        ));
+
+        // Justification where one of the signatures is corrupted.
+        let mut corrupted = valid.clone();
+        // Offset of the first byte of the signature of the first precommit.
+        let offset = 8 + 32 + 4 + 1 + 32 + 4;
+        corrupted[offset] ^= 0x1;
+        assert!(matches!(
+            super::verify_justification(&corrupted, 5, &authorities, (12, &target.1), [0; 32]),
+            Err(VerifyJustificationError::BadSignature)
+        ));
+    }
+}
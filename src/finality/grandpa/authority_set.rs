@@ -0,0 +1,337 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tracking of the GrandPa authorities set of a chain.
+//!
+//! The list of GrandPa authorities, and the identifier of this list (the *set id*), change
+//! according to the digest items found in block headers. Two kinds of changes exist:
+//!
+//! - Scheduled changes, which are enacted once the block at `signal block number + delay` is
+//!   finalized.
+//! - Forced changes, which are enacted once the block at `signal block number + delay` is
+//!   imported, without waiting for finality.
+//!
+//! Forced changes take precedence over scheduled changes: a scheduled change signaled in the
+//! same block as a forced change is ignored, and enacting a forced change discards the pending
+//! scheduled change, if any.
+//!
+//! The [`AuthoritySetTracker`] follows a single chain of blocks, which must be passed to it in
+//! order with [`AuthoritySetTracker::import_header`], and must be notified of finality with
+//! [`AuthoritySetTracker::finalize`].
+
+use crate::header;
+
+use alloc::vec::Vec;
+
+/// Tracker of the current GrandPa authorities set. See [the module-level documentation](..).
+#[derive(Debug, Clone)]
+pub struct AuthoritySetTracker {
+    /// Number of the latest block passed to [`AuthoritySetTracker::import_header`], or the
+    /// initial block.
+    best_block_number: u64,
+    /// Number of the latest finalized block.
+    finalized_block_number: u64,
+    /// Identifier of [`AuthoritySetTracker::authorities`].
+    set_id: u64,
+    /// Current list of authorities.
+    authorities: Vec<header::GrandpaAuthority>,
+    /// Scheduled change waiting for the block with the given number to be finalized.
+    scheduled_change: Option<(u64, Vec<header::GrandpaAuthority>)>,
+    /// Forced change waiting for the block with the given number to be imported.
+    forced_change: Option<(u64, Vec<header::GrandpaAuthority>)>,
+}
+
+impl AuthoritySetTracker {
+    /// Initializes a new tracker.
+    ///
+    /// `block_number` is the number of a finalized block, and `set_id` and `authorities` the
+    /// authorities set in use after this block.
+    pub fn new(block_number: u64, set_id: u64, authorities: Vec<header::GrandpaAuthority>) -> Self {
+        AuthoritySetTracker {
+            best_block_number: block_number,
+            finalized_block_number: block_number,
+            set_id,
+            authorities,
+            scheduled_change: None,
+            forced_change: None,
+        }
+    }
+
+    /// Returns the identifier and list of authorities of the current set.
+    ///
+    /// This is the set that must finalize the children of the latest block that has been passed
+    /// to [`AuthoritySetTracker::import_header`].
+    pub fn current_authority_set(&self) -> (u64, &[header::GrandpaAuthority]) {
+        (self.set_id, &self.authorities)
+    }
+
+    /// Returns the block number and authorities of the scheduled change that hasn't been enacted
+    /// yet, if any.
+    pub fn pending_scheduled_change(&self) -> Option<(u64, &[header::GrandpaAuthority])> {
+        self.scheduled_change
+            .as_ref()
+            .map(|(number, list)| (*number, &list[..]))
+    }
+
+    /// Returns the block number and authorities of the forced change that hasn't been enacted
+    /// yet, if any.
+    pub fn pending_forced_change(&self) -> Option<(u64, &[header::GrandpaAuthority])> {
+        self.forced_change
+            .as_ref()
+            .map(|(number, list)| (*number, &list[..]))
+    }
+
+    /// Updates the tracker with a new block, which must be the child of the previously imported
+    /// block.
+    ///
+    /// On error, the state of the tracker is left unmodified.
+    pub fn import_header(&mut self, header: header::HeaderRef) -> Result<(), ImportError> {
+        if self.best_block_number.checked_add(1) != Some(header.number) {
+            return Err(ImportError::NonSequentialNumber);
+        }
+
+        let mut scheduled = None;
+        let mut forced = None;
+        for log in header.digest.logs() {
+            match log {
+                header::DigestItemRef::GrandpaConsensus(
+                    header::GrandpaConsensusLogRef::ScheduledChange(change),
+                ) if scheduled.is_none() => scheduled = Some(change),
+                header::DigestItemRef::GrandpaConsensus(
+                    header::GrandpaConsensusLogRef::ForcedChange { change, .. },
+                ) if forced.is_none() => forced = Some(change),
+                _ => {}
+            }
+        }
+
+        // A forced change takes precedence over a scheduled change in the same block.
+        if let Some(change) = forced {
+            if self.forced_change.is_some() {
+                return Err(ImportError::ChangeAlreadyPending);
+            }
+            let trigger = header
+                .number
+                .checked_add(u64::from(change.delay))
+                .ok_or(ImportError::TriggerBlockNumberOverflow)?;
+            self.forced_change = Some((trigger, change.next_authorities.map(Into::into).collect()));
+        } else if let Some(change) = scheduled {
+            if self.scheduled_change.is_some() {
+                return Err(ImportError::ChangeAlreadyPending);
+            }
+            let trigger = header
+                .number
+                .checked_add(u64::from(change.delay))
+                .ok_or(ImportError::TriggerBlockNumberOverflow)?;
+            self.scheduled_change =
+                Some((trigger, change.next_authorities.map(Into::into).collect()));
+        }
+
+        self.best_block_number = header.number;
+
+        if matches!(self.forced_change, Some((trigger, _)) if trigger == header.number) {
+            let (_, authorities) = self.forced_change.take().unwrap();
+            self.scheduled_change = None;
+            self.enact(authorities);
+        }
+
+        Ok(())
+    }
+
+    /// Notifies the tracker that the block with the given number has been finalized, potentially
+    /// enacting the pending scheduled change.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `block_number` is superior to the number of the latest imported block.
+    ///
+    pub fn finalize(&mut self, block_number: u64) {
+        assert!(block_number <= self.best_block_number);
+        self.finalized_block_number = self.finalized_block_number.max(block_number);
+        self.enact_scheduled_change();
+    }
+
+    fn enact_scheduled_change(&mut self) {
+        if matches!(self.scheduled_change, Some((trigger, _)) if trigger <= self.finalized_block_number)
+        {
+            let (_, authorities) = self.scheduled_change.take().unwrap();
+            self.enact(authorities);
+        }
+    }
+
+    fn enact(&mut self, authorities: Vec<header::GrandpaAuthority>) {
+        self.set_id += 1;
+        self.authorities = authorities;
+    }
+}
+
+/// Error potentially returned by [`AuthoritySetTracker::import_header`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum ImportError {
+    /// Block isn't the child of the latest imported block.
+    NonSequentialNumber,
+    /// Block signals a change while a change of the same kind is already pending.
+    ChangeAlreadyPending,
+    /// Block signals a change whose delay overflows the block number.
+    TriggerBlockNumberOverflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthoritySetTracker, ImportError};
+    use crate::header;
+    use core::num::NonZeroU64;
+
+    fn authority(n: u8) -> header::GrandpaAuthority {
+        header::GrandpaAuthority {
+            public_key: [n; 32],
+            weight: NonZeroU64::new(1).unwrap(),
+        }
+    }
+
+    fn block(number: u64, digest_items: &[header::DigestItem]) -> header::Header {
+        header::Header {
+            parent_hash: [0; 32],
+            number,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::from_slice(digest_items).unwrap().into(),
+        }
+    }
+
+    fn change(authority_index: u8, delay: u32) -> header::GrandpaScheduledChange {
+        header::GrandpaScheduledChange {
+            next_authorities: vec![authority(authority_index)],
+            delay,
+        }
+    }
+
+    #[test]
+    fn scheduled_change_enacted_on_finalization() {
+        let mut tracker = AuthoritySetTracker::new(0, 0, vec![authority(0)]);
+
+        // Block #1 schedules a change enacted at block #3.
+        tracker
+            .import_header(
+                (&block(
+                    1,
+                    &[header::DigestItem::GrandpaConsensus(
+                        header::GrandpaConsensusLog::ScheduledChange(change(1, 2)),
+                    )],
+                ))
+                    .into(),
+            )
+            .unwrap();
+        for number in 2..=5 {
+            tracker.import_header((&block(number, &[])).into()).unwrap();
+        }
+        assert_eq!(tracker.pending_scheduled_change().unwrap().0, 3);
+
+        // Importing the blocks doesn't enact the change.
+        assert_eq!(tracker.current_authority_set(), (0, &[authority(0)][..]));
+        tracker.finalize(2);
+        assert_eq!(tracker.current_authority_set(), (0, &[authority(0)][..]));
+        tracker.finalize(3);
+        assert_eq!(tracker.current_authority_set(), (1, &[authority(1)][..]));
+        assert!(tracker.pending_scheduled_change().is_none());
+        tracker.finalize(5);
+        assert_eq!(tracker.current_authority_set(), (1, &[authority(1)][..]));
+
+        assert!(matches!(
+            tracker.import_header((&block(7, &[])).into()),
+            Err(ImportError::NonSequentialNumber)
+        ));
+
+        // Change with a delay of zero, enacted as soon as the block is finalized.
+        tracker
+            .import_header(
+                (&block(
+                    6,
+                    &[header::DigestItem::GrandpaConsensus(
+                        header::GrandpaConsensusLog::ScheduledChange(change(2, 0)),
+                    )],
+                ))
+                    .into(),
+            )
+            .unwrap();
+        assert_eq!(tracker.current_authority_set().0, 1);
+        tracker.finalize(6);
+        assert_eq!(tracker.current_authority_set(), (2, &[authority(2)][..]));
+    }
+
+    #[test]
+    fn forced_change_enacted_on_import() {
+        let mut tracker = AuthoritySetTracker::new(10, 5, vec![authority(0)]);
+
+        let forced = |n, delay| {
+            header::DigestItem::GrandpaConsensus(header::GrandpaConsensusLog::ForcedChange {
+                reset_block_height: 0,
+                change: change(n, delay),
+            })
+        };
+        let scheduled = |n, delay| {
+            header::DigestItem::GrandpaConsensus(header::GrandpaConsensusLog::ScheduledChange(
+                change(n, delay),
+            ))
+        };
+
+        // A scheduled change is pending while a forced change is signaled.
+        tracker
+            .import_header((&block(11, &[scheduled(1, 5)])).into())
+            .unwrap();
+        assert!(matches!(
+            tracker.import_header((&block(12, &[scheduled(9, 1)])).into()),
+            Err(ImportError::ChangeAlreadyPending)
+        ));
+
+        // The forced change takes precedence over the scheduled change of the same block.
+        tracker
+            .import_header((&block(12, &[scheduled(9, 0), forced(2, 1)])).into())
+            .unwrap();
+        assert_eq!(tracker.current_authority_set(), (5, &[authority(0)][..]));
+
+        // The forced change is enacted without any finality, and discards the scheduled change.
+        tracker.import_header((&block(13, &[])).into()).unwrap();
+        assert_eq!(tracker.current_authority_set(), (6, &[authority(2)][..]));
+        assert!(tracker.pending_scheduled_change().is_none());
+        assert!(tracker.pending_forced_change().is_none());
+
+        tracker.finalize(13);
+        assert_eq!(tracker.current_authority_set(), (6, &[authority(2)][..]));
+    }
+
+    #[test]
+    fn block_number_overflow() {
+        let mut tracker = AuthoritySetTracker::new(u64::MAX - 1, 0, vec![authority(0)]);
+
+        let scheduled = header::DigestItem::GrandpaConsensus(
+            header::GrandpaConsensusLog::ScheduledChange(change(1, 1)),
+        );
+        assert!(matches!(
+            tracker.import_header((&block(u64::MAX, &[scheduled])).into()),
+            Err(ImportError::TriggerBlockNumberOverflow)
+        ));
+        assert!(tracker.pending_scheduled_change().is_none());
+
+        tracker
+            .import_header((&block(u64::MAX, &[])).into())
+            .unwrap();
+        assert!(matches!(
+            tracker.import_header((&block(0, &[])).into()),
+            Err(ImportError::NonSequentialNumber)
+        ));
+    }
+}
@@ -94,10 +94,10 @@ fn compact_commit(bytes: &[u8]) -> nom::IResult<&[u8], CompactCommitRef> {
             nom::sequence::tuple((
                 nom::bytes::complete::take(32u32),
                 nom::number::complete::le_u32,
-                nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+                nom::combinator::flat_map(crate::util::nom_scale_collection_len(36), |num_elems| {
                     nom::multi::many_m_n(num_elems, num_elems, unsigned_precommit)
                 }),
-                nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+                nom::combinator::flat_map(crate::util::nom_scale_collection_len(96), |num_elems| {
                     nom::multi::many_m_n(
                         num_elems,
                         num_elems,
@@ -373,4 +373,14 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn oversized_precommits_len() {
+        let mut encoded = vec![0; 8 + 8 + 32 + 4];
+        // Claims `u32::MAX` precommits while the message is only a few bytes long.
+        encoded.extend_from_slice(&[0b11, 0xff, 0xff, 0xff, 0xff]);
+        encoded.extend_from_slice(&[0; 36]);
+        encoded.push(0);
+        assert!(super::decode_grandpa_commit(&encoded).is_err());
+    }
 }
@@ -0,0 +1,391 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Generation of finality proofs, in order to serve light clients.
+//!
+//! A light client that knows the GrandPa authorities set of a certain identifier can be
+//! convinced that a block is finalized by providing it with the justifications of all the blocks
+//! that have changed the authorities set since then, followed with the justification of the
+//! block in question. Each justification is signed by the authorities set enacted by the
+//! previous one.
+//!
+//! [`generate_finality_proof`] assembles such a proof. It uses the same format as a GrandPa warp
+//! sync response (see [`crate::network::protocol::decode_grandpa_warp_sync_response`]), and can
+//! be verified with a [`super::warp_sync::Verifier`].
+
+use crate::{header, util};
+
+use alloc::vec::Vec;
+
+/// Configuration to pass to [`generate_finality_proof`].
+pub struct Config<'a, TChanges> {
+    /// SCALE-encoded header of the block whose finality must be proven.
+    pub block_scale_encoded_header: &'a [u8],
+
+    /// SCALE-encoded justification that finalizes the block of
+    /// [`Config::block_scale_encoded_header`], or `None` if the local node doesn't have any.
+    pub block_scale_encoded_justification: Option<&'a [u8]>,
+
+    /// Identifier of the authorities set known by the requester. The proof starts with the
+    /// justifications signed by this set.
+    pub from_set_id: u64,
+
+    /// Identifier of the authorities set that has signed
+    /// [`Config::block_scale_encoded_justification`].
+    pub block_authorities_set_id: u64,
+
+    /// Height of the latest finalized block known by the local node.
+    pub finalized_block_number: u64,
+
+    /// List of the finalized blocks that contain a change in the list of GrandPa authorities,
+    /// with their justification. No specific order is required. Changes that aren't necessary to
+    /// build the proof are ignored.
+    pub authorities_set_changes: TChanges,
+}
+
+/// Finalized block that contains a change in the list of GrandPa authorities.
+/// See [`Config::authorities_set_changes`].
+#[derive(Debug, Clone)]
+pub struct AuthoritiesSetChange<'a> {
+    /// Identifier of the authorities set that has finalized this block. The block enacts the set
+    /// whose identifier is `authorities_set_id + 1`.
+    pub authorities_set_id: u64,
+
+    /// SCALE-encoded header of the block.
+    pub scale_encoded_header: &'a [u8],
+
+    /// SCALE-encoded justification that finalizes the block.
+    pub scale_encoded_justification: &'a [u8],
+}
+
+/// Builds a proof that the block of [`Config::block_scale_encoded_header`] is finalized, for
+/// a requester that knows the authorities set of identifier [`Config::from_set_id`].
+///
+/// Returns an error if the block isn't finalized yet, or if the information necessary to build
+/// the proof isn't available.
+pub fn generate_finality_proof<'a>(
+    config: Config<'a, impl Iterator<Item = AuthoritiesSetChange<'a>>>,
+) -> Result<Vec<u8>, Error> {
+    let block_number = header::decode(config.block_scale_encoded_header)
+        .map_err(Error::InvalidHeader)?
+        .number;
+
+    if block_number > config.finalized_block_number {
+        return Err(Error::NotFinalized);
+    }
+
+    let block_scale_encoded_justification = config
+        .block_scale_encoded_justification
+        .ok_or(Error::JustificationUnavailable)?;
+
+    if config.from_set_id > config.block_authorities_set_id {
+        return Err(Error::RequesterSetTooRecent);
+    }
+
+    // The proof must go through every set between the one known by the requester and the one
+    // that has signed the block. Changes enacted by the block itself, or by its descendants, are
+    // irrelevant.
+    let required_set_ids = config.from_set_id..config.block_authorities_set_id;
+    let mut changes = config
+        .authorities_set_changes
+        .filter(|change| required_set_ids.contains(&change.authorities_set_id))
+        .collect::<Vec<_>>();
+    changes.sort_by_key(|change| change.authorities_set_id);
+    changes.dedup_by_key(|change| change.authorities_set_id);
+
+    // The proof must contain exactly one change per set, without any hole.
+    for (index, expected_set_id) in required_set_ids.enumerate() {
+        if changes
+            .get(index)
+            .map_or(true, |change| change.authorities_set_id != expected_set_id)
+        {
+            return Err(Error::MissingAuthoritiesSetChange {
+                authorities_set_id: expected_set_id,
+            });
+        }
+    }
+
+    for change in &changes {
+        if header::decode(change.scale_encoded_header)
+            .map_err(Error::InvalidHeader)?
+            .number
+            >= block_number
+        {
+            return Err(Error::ChangeAfterBlock);
+        }
+    }
+
+    let mut out = util::encode_scale_compact_usize(changes.len() + 1)
+        .as_ref()
+        .to_vec();
+    for change in &changes {
+        out.extend_from_slice(change.scale_encoded_header);
+        out.extend_from_slice(change.scale_encoded_justification);
+    }
+    out.extend_from_slice(config.block_scale_encoded_header);
+    out.extend_from_slice(block_scale_encoded_justification);
+    // The proof is complete, as it ends with the requested block.
+    out.push(1);
+    Ok(out)
+}
+
+/// Error potentially returned by [`generate_finality_proof`].
+#[derive(Debug, derive_more::Display)]
+pub enum Error {
+    /// Requested block isn't finalized yet. The proof isn't available.
+    #[display(fmt = "Block isn't finalized yet and its finality proof isn't available")]
+    NotFinalized,
+    /// Justification of the requested block isn't available.
+    JustificationUnavailable,
+    /// The block has been finalized by an authorities set older than the one known by the
+    /// requester, which can't verify its justification.
+    #[display(fmt = "Block finalized by an authorities set older than the requester's")]
+    RequesterSetTooRecent,
+    /// One of the authorities set changes necessary to build the proof is missing.
+    #[display(
+        fmt = "Missing the change of the authorities set of id {}",
+        authorities_set_id
+    )]
+    MissingAuthoritiesSetChange {
+        /// Identifier of the set that has finalized the missing change.
+        authorities_set_id: u64,
+    },
+    /// One of the authorities set changes necessary to build the proof is at a height superior
+    /// or equal to the one of the requested block.
+    #[display(fmt = "Authorities set change isn't an ancestor of the requested block")]
+    ChangeAfterBlock,
+    /// Failed to decode one of the headers.
+    InvalidHeader(header::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chain::chain_information::{ChainInformationFinality, ChainInformationFinalityRef};
+    use crate::finality::grandpa::warp_sync;
+    use crate::header;
+    use crate::network::protocol;
+    use core::num::NonZeroU64;
+
+    fn authority(key: &ed25519_zebra::SigningKey) -> header::GrandpaAuthority {
+        header::GrandpaAuthority {
+            public_key: <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(key)),
+            weight: NonZeroU64::new(1).unwrap(),
+        }
+    }
+
+    /// Builds the header of block `number`, optionally switching to the given authority.
+    fn build_header(number: u32, next_authority: Option<header::GrandpaAuthority>) -> Vec<u8> {
+        let digest_items = next_authority
+            .into_iter()
+            .map(|next_authority| {
+                header::DigestItem::GrandpaConsensus(header::GrandpaConsensusLog::ScheduledChange(
+                    header::GrandpaScheduledChange {
+                        next_authorities: vec![next_authority],
+                        delay: 0,
+                    },
+                ))
+            })
+            .collect::<Vec<_>>();
+        header::Header {
+            parent_hash: [0; 32],
+            number: u64::from(number),
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::from_slice(&digest_items).unwrap().into(),
+        }
+        .scale_encoding_vec()
+    }
+
+    /// Builds a justification of the given header signed by `key`, member of the set `set_id`.
+    fn build_justification(
+        scale_encoded_header: &[u8],
+        key: &ed25519_zebra::SigningKey,
+        set_id: u64,
+    ) -> Vec<u8> {
+        let hash = header::hash_from_scale_encoded_header(scale_encoded_header);
+        let number = u32::try_from(header::decode(scale_encoded_header).unwrap().number).unwrap();
+        super::super::build_justification(&[*key], set_id, (number, hash))
+    }
+
+    #[test]
+    fn proof_spanning_one_set_change() {
+        let keys = (0..2u8)
+            .map(|n| ed25519_zebra::SigningKey::from([n; 32]))
+            .collect::<Vec<_>>();
+
+        // Block 3 switches from the set 0 to the set 1, and block 5 is finalized by the set 1.
+        let change_header = build_header(3, Some(authority(&keys[1])));
+        let change_justification = build_justification(&change_header, &keys[0], 0);
+        let block_header = build_header(5, None);
+        let block_justification = build_justification(&block_header, &keys[1], 1);
+        let later_change_header = build_header(7, Some(authority(&keys[0])));
+
+        let proof = super::generate_finality_proof(super::Config {
+            block_scale_encoded_header: &block_header,
+            block_scale_encoded_justification: Some(&block_justification),
+            from_set_id: 0,
+            block_authorities_set_id: 1,
+            finalized_block_number: 8,
+            authorities_set_changes: [
+                // Irrelevant, as enacted after the requested block.
+                super::AuthoritiesSetChange {
+                    authorities_set_id: 1,
+                    scale_encoded_header: &later_change_header,
+                    scale_encoded_justification: &[],
+                },
+                super::AuthoritiesSetChange {
+                    authorities_set_id: 0,
+                    scale_encoded_header: &change_header,
+                    scale_encoded_justification: &change_justification,
+                },
+            ]
+            .into_iter(),
+        })
+        .unwrap();
+
+        let response = protocol::decode_grandpa_warp_sync_response(&proof).unwrap();
+        assert!(response.is_finished);
+        assert_eq!(response.fragments.len(), 2);
+        assert_eq!(response.fragments[0].scale_encoded_header, change_header);
+        assert_eq!(response.fragments[1].scale_encoded_header, block_header);
+
+        let initial_authorities = [authority(&keys[0])];
+        let mut verifier = warp_sync::Verifier::new(
+            ChainInformationFinalityRef::Grandpa {
+                after_finalized_block_authorities_set_id: 0,
+                finalized_triggered_authorities: &initial_authorities,
+                finalized_scheduled_change: None,
+            },
+            response.fragments,
+            response.is_finished,
+            [0; 32],
+        );
+        let (scale_encoded_header, finality) = loop {
+            match verifier.next().unwrap() {
+                warp_sync::Next::NotFinished(v) => verifier = v,
+                warp_sync::Next::Success {
+                    scale_encoded_header,
+                    chain_information_finality,
+                } => break (scale_encoded_header, chain_information_finality),
+                warp_sync::Next::EmptyProof => panic!(),
+            }
+        };
+        assert_eq!(scale_encoded_header, block_header);
+        assert!(matches!(
+            finality,
+            ChainInformationFinality::Grandpa {
+                after_finalized_block_authorities_set_id: 1,
+                ..
+            }
+        ));
+
+        // A requester that already knows the set 1 only receives the last justification.
+        let proof = super::generate_finality_proof(super::Config {
+            block_scale_encoded_header: &block_header,
+            block_scale_encoded_justification: Some(&block_justification),
+            from_set_id: 1,
+            block_authorities_set_id: 1,
+            finalized_block_number: 8,
+            authorities_set_changes: [super::AuthoritiesSetChange {
+                authorities_set_id: 0,
+                scale_encoded_header: &change_header,
+                scale_encoded_justification: &change_justification,
+            }]
+            .into_iter(),
+        })
+        .unwrap();
+        let response = protocol::decode_grandpa_warp_sync_response(&proof).unwrap();
+        assert_eq!(response.fragments.len(), 1);
+
+        // The change is necessary for a requester that only knows the set 0.
+        assert!(matches!(
+            super::generate_finality_proof(super::Config {
+                block_scale_encoded_header: &block_header,
+                block_scale_encoded_justification: Some(&block_justification),
+                from_set_id: 0,
+                block_authorities_set_id: 1,
+                finalized_block_number: 8,
+                authorities_set_changes: core::iter::empty(),
+            }),
+            Err(super::Error::MissingAuthoritiesSetChange {
+                authorities_set_id: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn requires_every_set_change() {
+        let change_headers = [build_header(2, None), build_header(4, None)];
+        let block_header = build_header(6, None);
+        let generate = |from_set_id, changes: &[usize]| {
+            super::generate_finality_proof(super::Config {
+                block_scale_encoded_header: &block_header,
+                block_scale_encoded_justification: Some(&[]),
+                from_set_id,
+                block_authorities_set_id: 2,
+                finalized_block_number: 6,
+                authorities_set_changes: changes.iter().map(|n| super::AuthoritiesSetChange {
+                    authorities_set_id: u64::try_from(*n).unwrap(),
+                    scale_encoded_header: &change_headers[*n],
+                    scale_encoded_justification: &[],
+                }),
+            })
+        };
+
+        assert!(matches!(
+            generate(0, &[]),
+            Err(super::Error::MissingAuthoritiesSetChange {
+                authorities_set_id: 0
+            })
+        ));
+        assert!(matches!(
+            generate(0, &[0]),
+            Err(super::Error::MissingAuthoritiesSetChange {
+                authorities_set_id: 1
+            })
+        ));
+        assert!(matches!(
+            generate(0, &[1]),
+            Err(super::Error::MissingAuthoritiesSetChange {
+                authorities_set_id: 0
+            })
+        ));
+        assert!(generate(0, &[1, 0]).is_ok());
+        assert!(generate(1, &[1]).is_ok());
+        assert!(generate(2, &[]).is_ok());
+        assert!(matches!(
+            generate(3, &[]),
+            Err(super::Error::RequesterSetTooRecent)
+        ));
+    }
+
+    #[test]
+    fn not_finalized() {
+        let block_header = build_header(5, None);
+        assert!(matches!(
+            super::generate_finality_proof(super::Config {
+                block_scale_encoded_header: &block_header,
+                block_scale_encoded_justification: None,
+                from_set_id: 0,
+                block_authorities_set_id: 0,
+                finalized_block_number: 4,
+                authorities_set_changes: core::iter::empty(),
+            }),
+            Err(super::Error::NotFinalized)
+        ));
+    }
+}
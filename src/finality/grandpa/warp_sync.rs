@@ -28,6 +28,7 @@ use crate::network::protocol::GrandpaWarpSyncResponseFragment;
 
 use alloc::vec::Vec;
 use core::fmt;
+use rand::{Rng as _, SeedableRng as _};
 
 #[derive(Debug)]
 pub enum Error {
@@ -85,6 +86,11 @@ pub struct Verifier {
     authorities_list: Vec<GrandpaAuthority>,
     fragments: Vec<GrandpaWarpSyncResponseFragment>,
     is_proof_complete: bool,
+    /// Results of signature verifications performed ahead of time and injected through
+    /// [`Verifier::inject_verified_fragment`]. Unordered.
+    verified_fragments: Vec<VerifiedFragment>,
+    /// Source of the randomness necessary to verify the signatures of the fragments.
+    randomness: rand_chacha::ChaCha20Rng,
 }
 
 impl Verifier {
@@ -92,6 +98,7 @@ impl Verifier {
         start_chain_information_finality: ChainInformationFinalityRef,
         warp_sync_response_fragments: Vec<GrandpaWarpSyncResponseFragment>,
         is_proof_complete: bool,
+        randomness_seed: [u8; 32],
     ) -> Self {
         let (wrong_chain_algorithm, authorities_list, authorities_set_id) =
             match start_chain_information_finality {
@@ -117,9 +124,72 @@ impl Verifier {
             authorities_list,
             fragments: warp_sync_response_fragments,
             is_proof_complete,
+            verified_fragments: Vec::new(),
+            randomness: rand_chacha::ChaCha20Rng::from_seed(randomness_seed),
         }
     }
 
+    /// Returns the list of signature verifications of the fragments that remain to be verified.
+    ///
+    /// Verifying the signatures of a fragment is the most CPU-intensive part of the verification
+    /// process. The authorities that have signed a fragment can be determined only by looking
+    /// at the headers of the fragments that precede it, so the signatures of all fragments can
+    /// be verified independently of each other, for example in parallel on multiple threads.
+    /// The API user is free to choose how many of these verifications run at the same time.
+    ///
+    /// The outcome of each verification must then be passed back through
+    /// [`Verifier::inject_verified_fragment`], in any order. [`Verifier::next`] still applies
+    /// the changes to the list of authorities in order, and only performs the signature
+    /// verification itself for fragments whose outcome hasn't been injected.
+    ///
+    /// The returned list might not contain all the remaining fragments, for example if one of
+    /// the headers can't be decoded. [`Verifier::next`] reports the error when reaching the
+    /// problematic fragment.
+    pub fn fragments_signatures_verifications(&mut self) -> Vec<FragmentSignaturesVerification> {
+        let mut out = Vec::new();
+        if self.wrong_chain_algorithm {
+            return out;
+        }
+
+        let mut authorities_set_id = self.authorities_set_id;
+        let mut authorities_list = self.authorities_list.clone();
+
+        for (fragment_index, fragment) in self.fragments.iter().enumerate().skip(self.index) {
+            let next_authorities = next_authorities(&fragment.scale_encoded_header);
+
+            out.push(FragmentSignaturesVerification {
+                fragment_index,
+                header_hash: header::hash_from_scale_encoded_header(&fragment.scale_encoded_header),
+                scale_encoded_justification: fragment.scale_encoded_justification.clone(),
+                authorities_set_id,
+                authorities_list: authorities_list.clone(),
+                randomness_seed: self.randomness.sample(rand::distributions::Standard),
+            });
+
+            match next_authorities {
+                Ok(Some(list)) => {
+                    authorities_list = list;
+                    authorities_set_id += 1;
+                }
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        out
+    }
+
+    /// Injects the outcome of a verification obtained through
+    /// [`Verifier::fragments_signatures_verifications`].
+    ///
+    /// Verifications that don't correspond to a fragment left to verify are ignored.
+    pub fn inject_verified_fragment(&mut self, verified: VerifiedFragment) {
+        if verified.fragment_index < self.index || verified.fragment_index >= self.fragments.len() {
+            return;
+        }
+
+        self.verified_fragments.push(verified);
+    }
+
     pub fn next(mut self) -> Result<Next, Error> {
         if self.wrong_chain_algorithm {
             return Err(Error::WrongChainAlgorithm);
@@ -135,44 +205,27 @@ impl Verifier {
         debug_assert!(self.fragments.len() > self.index);
         let fragment = &self.fragments[self.index];
 
-        let fragment_header_hash =
-            header::hash_from_scale_encoded_header(&fragment.scale_encoded_header);
-        let justification = finality::justification::decode::decode_partial_grandpa(
-            // TODO: don't use decode_partial but decode
-            &fragment.scale_encoded_justification,
-        )
-        .map_err(Error::InvalidJustification)?
-        .0;
-        if *justification.target_hash != fragment_header_hash {
-            return Err(Error::TargetHashMismatch {
-                justification_target_hash: *justification.target_hash,
-                justification_target_height: justification.target_number.into(), // TODO: some u32/u64 mismatch here; figure out
-                header_hash: fragment_header_hash,
-            });
+        // Use the outcome of the signatures verification if it has been performed ahead of time.
+        // The authorities are compared in order to make sure that the verification has been
+        // performed against the right list.
+        let verified = self.verified_fragments.iter().position(|v| {
+            v.fragment_index == self.index
+                && v.authorities_set_id == self.authorities_set_id
+                && v.authorities_list == self.authorities_list
+        });
+        match verified {
+            Some(pos) => self.verified_fragments.swap_remove(pos).result?,
+            None => verify_fragment_signatures(
+                &header::hash_from_scale_encoded_header(&fragment.scale_encoded_header),
+                &fragment.scale_encoded_justification,
+                self.authorities_set_id,
+                &self.authorities_list,
+                self.randomness.sample(rand::distributions::Standard),
+            )?,
         }
 
-        verify(VerifyConfig {
-            justification,
-            authorities_list: self.authorities_list.iter().map(|a| &a.public_key),
-            authorities_set_id: self.authorities_set_id,
-        })
-        .map_err(Error::Verify)?;
-
-        let authorities_list = header::decode(&fragment.scale_encoded_header)
-            .map_err(Error::InvalidHeader)?
-            .digest
-            .logs()
-            .find_map(|log_item| match log_item {
-                DigestItemRef::GrandpaConsensus(grandpa_log_item) => match grandpa_log_item {
-                    GrandpaConsensusLogRef::ScheduledChange(change)
-                    | GrandpaConsensusLogRef::ForcedChange { change, .. } => {
-                        Some(change.next_authorities)
-                    }
-                    _ => None,
-                },
-                _ => None,
-            })
-            .map(|next_authorities| next_authorities.map(GrandpaAuthority::from).collect());
+        let authorities_list =
+            next_authorities(&fragment.scale_encoded_header).map_err(Error::InvalidHeader)?;
 
         self.index += 1;
 
@@ -206,3 +259,266 @@ pub enum Next {
         chain_information_finality: ChainInformationFinality,
     },
 }
+
+/// Signature verification of a single fragment. See
+/// [`Verifier::fragments_signatures_verifications`].
+#[derive(Debug, Clone)]
+pub struct FragmentSignaturesVerification {
+    fragment_index: usize,
+    header_hash: [u8; 32],
+    scale_encoded_justification: Vec<u8>,
+    authorities_set_id: u64,
+    authorities_list: Vec<GrandpaAuthority>,
+    randomness_seed: [u8; 32],
+}
+
+impl FragmentSignaturesVerification {
+    /// Returns the index of the fragment within the list of fragments passed to
+    /// [`Verifier::new`].
+    pub fn fragment_index(&self) -> usize {
+        self.fragment_index
+    }
+
+    /// Performs the verification.
+    ///
+    /// The outcome must be passed to [`Verifier::inject_verified_fragment`].
+    pub fn verify(self) -> VerifiedFragment {
+        let result = verify_fragment_signatures(
+            &self.header_hash,
+            &self.scale_encoded_justification,
+            self.authorities_set_id,
+            &self.authorities_list,
+            self.randomness_seed,
+        );
+
+        VerifiedFragment {
+            fragment_index: self.fragment_index,
+            authorities_set_id: self.authorities_set_id,
+            authorities_list: self.authorities_list,
+            result,
+        }
+    }
+}
+
+/// Outcome of [`FragmentSignaturesVerification::verify`].
+#[derive(Debug)]
+pub struct VerifiedFragment {
+    fragment_index: usize,
+    authorities_set_id: u64,
+    authorities_list: Vec<GrandpaAuthority>,
+    result: Result<(), Error>,
+}
+
+impl VerifiedFragment {
+    /// Returns the index of the fragment within the list of fragments passed to
+    /// [`Verifier::new`].
+    pub fn fragment_index(&self) -> usize {
+        self.fragment_index
+    }
+}
+
+/// Verifies that the justification of a fragment targets the header of this fragment and is
+/// signed by the given authorities.
+fn verify_fragment_signatures(
+    header_hash: &[u8; 32],
+    scale_encoded_justification: &[u8],
+    authorities_set_id: u64,
+    authorities_list: &[GrandpaAuthority],
+    randomness_seed: [u8; 32],
+) -> Result<(), Error> {
+    let justification = finality::justification::decode::decode_partial_grandpa(
+        // TODO: don't use decode_partial but decode
+        scale_encoded_justification,
+    )
+    .map_err(Error::InvalidJustification)?
+    .0;
+    if *justification.target_hash != *header_hash {
+        return Err(Error::TargetHashMismatch {
+            justification_target_hash: *justification.target_hash,
+            justification_target_height: justification.target_number.into(), // TODO: some u32/u64 mismatch here; figure out
+            header_hash: *header_hash,
+        });
+    }
+
+    verify(VerifyConfig {
+        justification,
+        authorities_list: authorities_list.iter().map(|a| &a.public_key),
+        authorities_set_id,
+        randomness_seed,
+    })
+    .map_err(Error::Verify)
+}
+
+/// Returns the list of authorities that the given header switches to, if any.
+fn next_authorities(
+    scale_encoded_header: &[u8],
+) -> Result<Option<Vec<GrandpaAuthority>>, header::Error> {
+    Ok(header::decode(scale_encoded_header)?
+        .digest
+        .logs()
+        .find_map(|log_item| match log_item {
+            DigestItemRef::GrandpaConsensus(grandpa_log_item) => match grandpa_log_item {
+                GrandpaConsensusLogRef::ScheduledChange(change)
+                | GrandpaConsensusLogRef::ForcedChange { change, .. } => {
+                    Some(change.next_authorities)
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|next_authorities| next_authorities.map(GrandpaAuthority::from).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chain::chain_information::{ChainInformationFinality, ChainInformationFinalityRef};
+    use crate::header;
+    use crate::network::protocol::GrandpaWarpSyncResponseFragment;
+    use core::num::NonZeroU64;
+
+    fn authority(key: &ed25519_zebra::SigningKey) -> header::GrandpaAuthority {
+        header::GrandpaAuthority {
+            public_key: <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(key)),
+            weight: NonZeroU64::new(1).unwrap(),
+        }
+    }
+
+    /// Builds a list of fragments where fragment `n` is signed by `keys[n]` and switches to
+    /// `keys[n + 1]`.
+    fn build_fragments(keys: &[ed25519_zebra::SigningKey]) -> Vec<GrandpaWarpSyncResponseFragment> {
+        let mut fragments = Vec::new();
+        for (index, signing_keys) in keys.windows(2).enumerate() {
+            let number = u32::try_from(index).unwrap() + 1;
+            let digest_items = [header::DigestItem::GrandpaConsensus(
+                header::GrandpaConsensusLog::ScheduledChange(header::GrandpaScheduledChange {
+                    next_authorities: vec![authority(&signing_keys[1])],
+                    delay: 0,
+                }),
+            )];
+            let header = header::Header {
+                parent_hash: [0; 32],
+                number: u64::from(number),
+                state_root: [0; 32],
+                extrinsics_root: [0; 32],
+                digest: header::DigestRef::from_slice(&digest_items).unwrap().into(),
+            };
+            let hash = header.hash();
+
+            let mut msg = vec![1u8];
+            msg.extend_from_slice(&hash);
+            msg.extend_from_slice(&number.to_le_bytes());
+            msg.extend_from_slice(&1u64.to_le_bytes()); // Round.
+            msg.extend_from_slice(&u64::try_from(index).unwrap().to_le_bytes()); // Set id.
+            let signature = signing_keys[0].sign(&msg);
+
+            let mut justification = Vec::new();
+            justification.extend_from_slice(&1u64.to_le_bytes());
+            justification.extend_from_slice(&hash);
+            justification.extend_from_slice(&number.to_le_bytes());
+            justification.push(4); // One precommit.
+            justification.extend_from_slice(&hash);
+            justification.extend_from_slice(&number.to_le_bytes());
+            justification.extend_from_slice(&<[u8; 64]>::from(signature));
+            justification.extend_from_slice(&authority(&signing_keys[0]).public_key);
+            justification.push(0); // No votes ancestry.
+
+            fragments.push(GrandpaWarpSyncResponseFragment {
+                scale_encoded_header: header.scale_encoding_vec(),
+                scale_encoded_justification: justification,
+            });
+        }
+        fragments
+    }
+
+    fn run(
+        keys: &[ed25519_zebra::SigningKey],
+        fragments: Vec<GrandpaWarpSyncResponseFragment>,
+        verification_order: impl FnOnce(&mut Vec<super::FragmentSignaturesVerification>),
+    ) -> Result<ChainInformationFinality, super::Error> {
+        let initial_authorities = [authority(&keys[0])];
+        let mut verifier = super::Verifier::new(
+            ChainInformationFinalityRef::Grandpa {
+                after_finalized_block_authorities_set_id: 0,
+                finalized_triggered_authorities: &initial_authorities,
+                finalized_scheduled_change: None,
+            },
+            fragments,
+            true,
+            [0; 32],
+        );
+
+        let mut verifications = verifier.fragments_signatures_verifications();
+        verification_order(&mut verifications);
+        for verification in verifications {
+            verifier.inject_verified_fragment(verification.verify());
+        }
+
+        loop {
+            match verifier.next()? {
+                super::Next::NotFinished(v) => verifier = v,
+                super::Next::Success {
+                    chain_information_finality,
+                    ..
+                } => return Ok(chain_information_finality),
+                super::Next::EmptyProof => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn verification_order_doesnt_matter() {
+        let keys = (0..5u8)
+            .map(|n| ed25519_zebra::SigningKey::from([n; 32]))
+            .collect::<Vec<_>>();
+
+        let orders: [fn(&mut Vec<super::FragmentSignaturesVerification>); 4] = [
+            |_| {},
+            |v| v.reverse(),
+            |v| v.swap(0, 2),
+            // Verifications that are never performed are done by `next()` instead.
+            |v| v.truncate(1),
+        ];
+
+        for order in orders {
+            let finality = run(&keys, build_fragments(&keys), order).unwrap();
+            match finality {
+                ChainInformationFinality::Grandpa {
+                    after_finalized_block_authorities_set_id,
+                    finalized_triggered_authorities,
+                    ..
+                } => {
+                    assert_eq!(after_finalized_block_authorities_set_id, 4);
+                    assert_eq!(finalized_triggered_authorities, vec![authority(&keys[4])]);
+                }
+                _ => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn bad_signature_detected_regardless_of_order() {
+        let keys = (0..5u8)
+            .map(|n| ed25519_zebra::SigningKey::from([n; 32]))
+            .collect::<Vec<_>>();
+
+        let mut fragments = build_fragments(&keys);
+        // Corrupt the signature of the third fragment.
+        fragments[2].scale_encoded_justification[32 + 8 + 4 + 1 + 32 + 4] ^= 1;
+
+        let orders: [fn(&mut Vec<super::FragmentSignaturesVerification>); 3] =
+            [|_| {}, |v| v.reverse(), |v| v.clear()];
+        for order in orders {
+            let fragments = fragments
+                .iter()
+                .map(|f| GrandpaWarpSyncResponseFragment {
+                    scale_encoded_header: f.scale_encoded_header.clone(),
+                    scale_encoded_justification: f.scale_encoded_justification.clone(),
+                })
+                .collect();
+            assert!(matches!(
+                run(&keys, fragments, order),
+                Err(super::Error::Verify(_))
+            ));
+        }
+    }
+}
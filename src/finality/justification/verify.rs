@@ -15,7 +15,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::finality::justification::decode;
+use crate::finality::{justification::decode, signatures};
 
 use alloc::vec::Vec;
 
@@ -32,6 +32,10 @@ pub struct Config<'a, I> {
     /// the justification. Must implement `Iterator<Item = impl AsRef<[u8]>> + Clone`, where
     /// each item is the public key of an authority.
     pub authorities_list: I,
+
+    /// Seed used to generate the randomness necessary to verify the signatures of the
+    /// justification. See [`signatures::verify_ed25519`].
+    pub randomness_seed: [u8; 32],
 }
 
 // TODO: rewrite as a generator-style process?
@@ -48,13 +52,7 @@ pub fn verify(config: Config<impl Iterator<Item = impl AsRef<[u8]>> + Clone>) ->
         return Err(Error::NotEnoughSignatures);
     }
 
-    // Verifying all the signatures together brings better performances than verifying them one
-    // by one.
-    // Note that batched ed25519 verification has some issues. The code below uses a special
-    // flavour of ed25519 where ambiguities are removed.
-    // See https://docs.rs/ed25519-zebra/2.2.0/ed25519_zebra/batch/index.html and
-    // https://github.com/zcash/zips/blob/master/zip-0215.rst
-    let mut batch = ed25519_zebra::batch::Verifier::new();
+    let mut messages = Vec::with_capacity(config.justification.precommits.iter().count());
 
     for (precommit_num, precommit) in config.justification.precommits.iter().enumerate() {
         if !config
@@ -85,17 +83,19 @@ pub fn verify(config: Config<impl Iterator<Item = impl AsRef<[u8]>> + Clone>) ->
         msg.extend_from_slice(&u64::to_le_bytes(config.authorities_set_id)[..]);
         debug_assert_eq!(msg.len(), msg.capacity());
 
-        batch.queue(ed25519_zebra::batch::Item::from((
-            ed25519_zebra::VerificationKeyBytes::from(*precommit.authority_public_key),
-            ed25519_zebra::Signature::from(*precommit.signature),
-            &msg,
-        )));
+        messages.push((precommit, msg));
     }
 
     // Actual signatures verification performed here.
-    // TODO: thread_rng()?!?! what to do here?
-    batch
-        .verify(rand::thread_rng())
+    let signatures = messages
+        .iter()
+        .map(|(precommit, msg)| signatures::Ed25519Signature {
+            public_key: precommit.authority_public_key,
+            signature: precommit.signature,
+            message: msg,
+        })
+        .collect::<Vec<_>>();
+    signatures::verify_ed25519(&signatures, config.randomness_seed)
         .map_err(|_| Error::BadSignature)?;
 
     // TODO: must check that votes_ancestries doesn't contain any unused entry
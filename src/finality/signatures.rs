@@ -0,0 +1,165 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! Verification of ed25519 signatures emitted by GrandPa authorities.
+//!
+//! Verifying a justification requires verifying the signatures of at least two thirds of the
+//! authorities, which represents most of the CPU time spent verifying justifications.
+//!
+//! Use [`verify_ed25519`] in order to verify a batch of signatures and, if the batch is
+//! invalid, find out which signatures are invalid.
+
+use alloc::vec::Vec;
+use rand::SeedableRng as _;
+
+/// Signature to verify.
+#[derive(Debug, Copy, Clone)]
+pub struct Ed25519Signature<'a> {
+    /// Public key of the signer.
+    pub public_key: &'a [u8; 32],
+    /// Signature to verify.
+    pub signature: &'a [u8; 64],
+    /// Message that has been signed.
+    pub message: &'a [u8],
+}
+
+impl<'a> Ed25519Signature<'a> {
+    /// Verifies this signature alone.
+    pub fn verify(&self) -> bool {
+        ed25519_zebra::VerificationKey::try_from(*self.public_key)
+            .and_then(|key| {
+                key.verify(
+                    &ed25519_zebra::Signature::from(*self.signature),
+                    self.message,
+                )
+            })
+            .is_ok()
+    }
+}
+
+/// Verifies the given signatures.
+///
+/// All the signatures are first verified at once, which brings better performances than
+/// verifying them one by one. If the batch is invalid, the signatures are then verified one by
+/// one and the indices within `signatures` of the ones that are invalid are returned. The
+/// returned list is never empty.
+///
+/// Verifying a batch of signatures requires some randomness, which is generated from
+/// `randomness_seed`. This seed must be unpredictable by the emitters of the signatures, as
+/// otherwise they could craft invalid signatures that pass the batch verification.
+///
+/// Note that batched ed25519 verification has some issues. This uses a special flavour of
+/// ed25519 where ambiguities are removed.
+/// See <https://docs.rs/ed25519-zebra/2.2.0/ed25519_zebra/batch/index.html> and
+/// <https://github.com/zcash/zips/blob/master/zip-0215.rst>
+pub fn verify_ed25519(
+    signatures: &[Ed25519Signature],
+    randomness_seed: [u8; 32],
+) -> Result<(), Vec<usize>> {
+    let mut batch = ed25519_zebra::batch::Verifier::new();
+    for signature in signatures {
+        batch.queue(ed25519_zebra::batch::Item::from((
+            ed25519_zebra::VerificationKeyBytes::from(*signature.public_key),
+            ed25519_zebra::Signature::from(*signature.signature),
+            signature.message,
+        )));
+    }
+
+    if batch
+        .verify(rand_chacha::ChaCha20Rng::from_seed(randomness_seed))
+        .is_ok()
+    {
+        return Ok(());
+    }
+
+    let invalid = signatures
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| !s.verify())
+        .map(|(n, _)| n)
+        .collect::<Vec<_>>();
+
+    // Batch verification and individual verification are supposed to be consistent. Rather
+    // than returning an empty list in case of discrepancy, consider that all the signatures are
+    // invalid.
+    if invalid.is_empty() {
+        return Err((0..signatures.len()).collect());
+    }
+
+    Err(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ed25519Signature;
+
+    // Test vectors of RFC 8032.
+    const VECTORS: [(&str, &str, &str); 3] = [
+        (
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            "",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            "72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            "af82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    #[test]
+    fn batch_and_individual_match() {
+        let vectors = VECTORS
+            .iter()
+            .map(|(public_key, message, signature)| {
+                (
+                    <[u8; 32]>::try_from(hex::decode(public_key).unwrap()).unwrap(),
+                    hex::decode(message).unwrap(),
+                    <[u8; 64]>::try_from(hex::decode(signature).unwrap()).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Uses the message of the next vector in order to build invalid signatures.
+        for corrupted in [None, Some(0), Some(2)] {
+            let signatures = vectors
+                .iter()
+                .enumerate()
+                .map(|(n, (public_key, message, signature))| Ed25519Signature {
+                    public_key,
+                    signature,
+                    message: if corrupted == Some(n) {
+                        &vectors[(n + 1) % vectors.len()].1
+                    } else {
+                        message
+                    },
+                })
+                .collect::<Vec<_>>();
+
+            let expected = match corrupted {
+                None => Ok(()),
+                Some(n) => Err(vec![n]),
+            };
+            assert_eq!(super::verify_ed25519(&signatures, [0; 32]), expected);
+            assert_eq!(signatures.iter().all(|s| s.verify()), expected.is_ok());
+        }
+    }
+}
@@ -78,16 +78,22 @@ use core::{fmt, iter, slice};
 
 mod aura;
 mod babe;
+mod beefy;
 mod grandpa;
 mod tests;
 
 pub use aura::*;
 pub use babe::*;
+pub use beefy::*;
 pub use grandpa::*;
 
 /// Returns a hash of a SCALE-encoded header.
 ///
 /// Does not verify the validity of the header.
+///
+/// The hash is calculated directly over the bytes passed as parameter. If the header is already
+/// available in its SCALE-encoded form, this function should be preferred over decoding the
+/// header then calling [`HeaderRef::hash`], as it gives the same result without any decoding.
 pub fn hash_from_scale_encoded_header(header: impl AsRef<[u8]>) -> [u8; 32] {
     hash_from_scale_encoded_header_vectored(iter::once(header))
 }
@@ -119,9 +125,90 @@ pub fn extrinsics_root(transactions: &[impl AsRef<[u8]>]) -> [u8; 32] {
     trie::ordered_root(transactions)
 }
 
+/// Returns `true` if the [`HeaderRef::extrinsics_root`] of the given header matches the list of
+/// transactions passed as parameter.
+///
+/// This can be used in order to make sure that a block body received from an untrusted source
+/// corresponds to a header that is known to be valid, before executing that body.
+pub fn verify_extrinsics_root<'a>(
+    header: impl Into<HeaderRef<'a>>,
+    transactions: &[impl AsRef<[u8]>],
+) -> bool {
+    *header.into().extrinsics_root == extrinsics_root(transactions)
+}
+
+/// Configuration for [`verify_extrinsic_proof`].
+pub struct VerifyExtrinsicProofConfig<'a, I> {
+    /// Value of [`HeaderRef::extrinsics_root`] of the header of the block.
+    pub extrinsics_root: &'a [u8; 32],
+
+    /// Index within the body of the block of the extrinsic whose inclusion must be verified.
+    pub index: usize,
+
+    /// List of node values of nodes found in the trie of the extrinsics of the block. No
+    /// specific order is required. Similar to storage proofs, all the nodes between the root and
+    /// the node closest to the requested index have to be included.
+    pub proof: I,
+}
+
+/// Verifies a proof of the content of the trie of the extrinsics of a block, and returns the
+/// extrinsic found at the given index, or `None` if the block has no extrinsic at this index.
+///
+/// This makes it possible to verify that a block contains a specific extrinsic (or doesn't
+/// contain any extrinsic at a specific index) without downloading the entire body of the block.
+/// See also [`verify_extrinsic_inclusion`].
+///
+/// The trie of the extrinsics of a block is the trie whose root is built by
+/// [`extrinsics_root`]. Its keys are the SCALE-compact-encoded indices of the extrinsics.
+pub fn verify_extrinsic_proof<'a>(
+    config: VerifyExtrinsicProofConfig<impl Iterator<Item = &'a [u8]> + Clone>,
+) -> Result<Option<&'a [u8]>, trie::proof_verify::Error> {
+    trie::proof_verify::verify_proof(trie::proof_verify::VerifyProofConfig {
+        requested_key: util::encode_scale_compact_usize(config.index).as_ref(),
+        trie_root_hash: config.extrinsics_root,
+        proof: config.proof,
+    })
+}
+
+/// Verifies a proof of the content of the trie of the extrinsics of a block, and returns `true`
+/// if the extrinsic at the given index has the given hash, or `false` if the block contains a
+/// different extrinsic or no extrinsic at all at this index.
+///
+/// The hash of an extrinsic is the blake2 hash of its SCALE encoding, as found in the body of
+/// the block.
+///
+/// See [`verify_extrinsic_proof`].
+pub fn verify_extrinsic_inclusion<'a>(
+    config: VerifyExtrinsicProofConfig<impl Iterator<Item = &'a [u8]> + Clone>,
+    extrinsic_hash: &[u8; 32],
+) -> Result<bool, trie::proof_verify::Error> {
+    match verify_extrinsic_proof(config)? {
+        Some(extrinsic) => {
+            Ok(blake2_rfc::blake2b::blake2b(32, &[], extrinsic).as_bytes() == extrinsic_hash)
+        }
+        None => Ok(false),
+    }
+}
+
 /// Attempt to decode the given SCALE-encoded header.
+///
+/// Digest log items that aren't recognized lead to an error. See [`decode_with_options`].
 pub fn decode(scale_encoded: &[u8]) -> Result<HeaderRef, Error> {
-    let (header, remainder) = decode_partial(scale_encoded)?;
+    decode_with_options(scale_encoded, false)
+}
+
+/// Attempt to decode the given SCALE-encoded header.
+///
+/// If `lenient_digest` is `true`, digest log items that aren't recognized, such as log items of
+/// an unknown consensus engine, are decoded as [`DigestItemRef::Other`] instead of leading to
+/// an error. Items that are recognized but malformed, and items that are truncated, lead to an
+/// error no matter the value of `lenient_digest`.
+///
+/// > **Note**: Lenient decoding is meant to be used in order to display or store headers of
+/// >           chains that use non-standard digest log items. Consensus-critical code should
+/// >           use strict decoding.
+pub fn decode_with_options(scale_encoded: &[u8], lenient_digest: bool) -> Result<HeaderRef, Error> {
+    let (header, remainder) = decode_partial_with_options(scale_encoded, lenient_digest)?;
     if !remainder.is_empty() {
         return Err(Error::TooLong);
     }
@@ -133,7 +220,18 @@ pub fn decode(scale_encoded: &[u8]) -> Result<HeaderRef, Error> {
 ///
 /// Contrary to [`decode`], doesn't return an error if the slice is too long but returns the
 /// remainder.
-pub fn decode_partial(mut scale_encoded: &[u8]) -> Result<(HeaderRef, &[u8]), Error> {
+pub fn decode_partial(scale_encoded: &[u8]) -> Result<(HeaderRef, &[u8]), Error> {
+    decode_partial_with_options(scale_encoded, false)
+}
+
+/// Attempt to decode the given SCALE-encoded header.
+///
+/// Contrary to [`decode_with_options`], doesn't return an error if the slice is too long but
+/// returns the remainder.
+pub fn decode_partial_with_options(
+    mut scale_encoded: &[u8],
+    lenient_digest: bool,
+) -> Result<(HeaderRef, &[u8]), Error> {
     if scale_encoded.len() < 32 + 1 {
         return Err(Error::TooShort);
     }
@@ -156,7 +254,7 @@ pub fn decode_partial(mut scale_encoded: &[u8]) -> Result<(HeaderRef, &[u8]), Er
     let extrinsics_root: &[u8; 32] = TryFrom::try_from(&scale_encoded[0..32]).unwrap();
     scale_encoded = &scale_encoded[32..];
 
-    let (digest, remainder) = DigestRef::from_scale_bytes(scale_encoded)?;
+    let (digest, remainder) = DigestRef::from_scale_bytes(scale_encoded, lenient_digest)?;
 
     let header = HeaderRef {
         parent_hash,
@@ -209,7 +307,13 @@ pub enum Error {
     MutipleRuntimeEnvironmentUpdated,
     /// Found a Babe configuration change digest without an epoch change digest.
     UnexpectedBabeConfigDescriptor,
+    /// Unknown changes trie signal, or invalid changes trie configuration.
+    BadChangesTrieSignal,
     GrandpaConsensusLogDecodeError,
+    /// Failed to decode a BEEFY digest log item.
+    BeefyConsensusLogDecodeError,
+    /// There are multiple Merkle Mountain Range root digests in the block header.
+    MultipleMmrRoots,
     /// Unknown consensus engine specified in a digest log.
     #[display(fmt = "Unknown consensus engine specified in a digest log: {:?}", _0)]
     UnknownConsensusEngine([u8; 4]),
@@ -264,6 +368,10 @@ impl<'a> HeaderRef<'a> {
     }
 
     /// Builds the hash of the header.
+    ///
+    /// The SCALE encoding of the header is fed piece by piece to the hasher, and no intermediary
+    /// buffer is allocated. If the SCALE-encoded header is available, use
+    /// [`hash_from_scale_encoded_header`] instead.
     pub fn hash(&self) -> [u8; 32] {
         hash_from_scale_encoded_header_vectored(self.scale_encoding())
     }
@@ -302,6 +410,37 @@ pub struct Header {
 }
 
 impl Header {
+    /// Builds a new [`Header`] from its components.
+    ///
+    /// Returns an error if one of the hashes isn't 32 bytes long, or if the block number can't be
+    /// decoded back on the current platform. The SCALE encoding of the header, identical to the
+    /// one produced by Substrate, can then be obtained with [`Header::scale_encoding_vec`].
+    ///
+    /// Use [`DigestRef::empty`] in order to build a header without any digest log item.
+    pub fn new(
+        parent_hash: &[u8],
+        number: u64,
+        state_root: &[u8],
+        extrinsics_root: &[u8],
+        digest: Digest,
+    ) -> Result<Header, NewHeaderError> {
+        // TODO: remove this check once `decode` no longer goes through a `usize` for the number
+        if usize::try_from(number).is_err() {
+            return Err(NewHeaderError::BlockNumberTooLarge);
+        }
+
+        Ok(Header {
+            parent_hash: <[u8; 32]>::try_from(parent_hash)
+                .map_err(|_| NewHeaderError::BadParentHashLength)?,
+            number,
+            state_root: <[u8; 32]>::try_from(state_root)
+                .map_err(|_| NewHeaderError::BadStateRootLength)?,
+            extrinsics_root: <[u8; 32]>::try_from(extrinsics_root)
+                .map_err(|_| NewHeaderError::BadExtrinsicsRootLength)?,
+            digest,
+        })
+    }
+
     /// Returns an iterator to list of buffers which, when concatenated, produces the SCALE
     /// encoding of the header.
     pub fn scale_encoding(
@@ -316,6 +455,8 @@ impl Header {
     }
 
     /// Builds the hash of the header.
+    ///
+    /// See [`HeaderRef::hash`].
     pub fn hash(&self) -> [u8; 32] {
         HeaderRef::from(self).hash()
     }
@@ -333,6 +474,71 @@ impl<'a> From<HeaderRef<'a>> for Header {
     }
 }
 
+/// Error potentially returned by [`Header::new`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum NewHeaderError {
+    /// Parent hash isn't 32 bytes long.
+    BadParentHashLength,
+    /// State root isn't 32 bytes long.
+    BadStateRootLength,
+    /// Extrinsics root isn't 32 bytes long.
+    BadExtrinsicsRootLength,
+    /// Block number is too large to be decoded on this platform.
+    BlockNumberTooLarge,
+}
+
+/// Verifies that the given headers form a contiguous chain, in other words that the parent hash
+/// of each header is equal to the hash of the previous header, and that the number of each
+/// header is equal to the number of the previous header plus one.
+///
+/// This is a cheap check meant to be performed before importing multiple headers at once. It
+/// doesn't verify anything else, such as the validity of the headers themselves.
+///
+/// An empty list or a list containing a single header are considered contiguous.
+///
+/// On error, returns the index within `headers` of the first header that isn't a child of the
+/// previous header.
+pub fn verify_chain_contiguity(headers: &[Header]) -> Result<(), (usize, ContiguityError)> {
+    for (index, pair) in headers.windows(2).enumerate() {
+        let (parent, child) = (&pair[0], &pair[1]);
+
+        if child.parent_hash != parent.hash() {
+            return Err((index + 1, ContiguityError::ParentHashMismatch));
+        }
+
+        if parent.number.checked_add(1) != Some(child.number) {
+            return Err((
+                index + 1,
+                ContiguityError::NonConsecutiveNumber {
+                    parent: parent.number,
+                    child: child.number,
+                },
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Error potentially returned by [`verify_chain_contiguity`].
+#[derive(Debug, derive_more::Display, Clone, PartialEq, Eq)]
+pub enum ContiguityError {
+    /// Parent hash of the header doesn't match the hash of the previous header.
+    ParentHashMismatch,
+    /// Number of the header isn't equal to the number of the previous header plus one.
+    #[display(
+        fmt = "Header number {} doesn't follow parent number {}",
+        child,
+        parent
+    )]
+    NonConsecutiveNumber {
+        /// Number of the previous header.
+        parent: u64,
+        /// Number of the header.
+        child: u64,
+    },
+}
+
 /// Generic header digest.
 #[derive(Clone)]
 pub struct DigestRef<'a> {
@@ -424,6 +630,22 @@ impl<'a> DigestRef<'a> {
         }
     }
 
+    /// Returns the new list of Aura authorities if the digest contains a
+    /// [`AuraConsensusLogRef::AuthoritiesChange`] item.
+    ///
+    /// The new list applies starting from the children of the block this digest belongs to.
+    /// The block itself must be produced by the list of authorities in place before it.
+    ///
+    /// If there are multiple such items, the first one is returned.
+    pub fn aura_authorities_change(&self) -> Option<AuraAuthoritiesIter<'a>> {
+        self.logs().find_map(|item| match item {
+            DigestItemRef::AuraConsensus(AuraConsensusLogRef::AuthoritiesChange(list)) => {
+                Some(list)
+            }
+            _ => None,
+        })
+    }
+
     /// Returns the Babe seal digest item, if any.
     pub fn babe_seal(&self) -> Option<&'a [u8; 64]> {
         if let Some(babe_seal_index) = self.babe_seal_index {
@@ -487,6 +709,32 @@ impl<'a> DigestRef<'a> {
         self.has_runtime_environment_updated
     }
 
+    /// Returns the root of the Merkle Mountain Range found in the [`DigestItemRef::Beefy`] items,
+    /// if any.
+    ///
+    /// Returns `Ok(None)` if the header doesn't contain any such root, which is for example the
+    /// case of the blocks produced before the Merkle Mountain Range has been enabled on the
+    /// chain. Returns an error if a [`DigestItemRef::Beefy`] item can't be decoded.
+    pub fn mmr_root(&self) -> Result<Option<&'a [u8; 32]>, Error> {
+        let mut mmr_root = None;
+
+        for item in self.logs() {
+            let opaque = match item {
+                DigestItemRef::Beefy { opaque } => opaque,
+                _ => continue,
+            };
+
+            if let BeefyConsensusLogRef::MmrRoot(root) = BeefyConsensusLogRef::from_slice(opaque)? {
+                if mmr_root.is_some() {
+                    return Err(Error::MultipleMmrRoots);
+                }
+                mmr_root = Some(root);
+            }
+        }
+
+        Ok(mmr_root)
+    }
+
     /// If the last element of the list is a seal, removes it from the [`DigestRef`].
     pub fn pop_seal(&mut self) -> Option<Seal<'a>> {
         let seal_pos = self.babe_seal_index.or(self.aura_seal_index)?;
@@ -634,7 +882,10 @@ impl<'a> DigestRef<'a> {
                     has_runtime_environment_updated = true;
                 }
                 DigestItem::BabeSeal(_) => return Err(Error::SealIsntLastItem),
-                DigestItem::Beefy { .. } | DigestItem::PolkadotParachain { .. } => {}
+                DigestItem::Beefy { .. }
+                | DigestItem::PolkadotParachain { .. }
+                | DigestItem::ChangesTrieConfiguration(_)
+                | DigestItem::Other { .. } => {}
             }
         }
 
@@ -655,7 +906,9 @@ impl<'a> DigestRef<'a> {
     }
 
     /// Try to decode a list of digest items, from their SCALE encoding.
-    fn from_scale_bytes(scale_encoded: &'a [u8]) -> Result<(Self, &'a [u8]), Error> {
+    ///
+    /// See [`decode_with_options`] for the meaning of `lenient`.
+    fn from_scale_bytes(scale_encoded: &'a [u8], lenient: bool) -> Result<(Self, &'a [u8]), Error> {
         let (scale_encoded, digest_logs_len) =
             crate::util::nom_scale_compact_usize::<nom::error::Error<&[u8]>>(scale_encoded)
                 .map_err(|_| Error::DigestItemLenDecodeError)?;
@@ -671,7 +924,7 @@ impl<'a> DigestRef<'a> {
         // Iterate through the log items to see if anything is wrong.
         let mut next_digest = scale_encoded;
         for item_num in 0..digest_logs_len {
-            let (item, next) = decode_item(next_digest)?;
+            let (item, next) = decode_item(next_digest, lenient)?;
             next_digest = next;
 
             match item {
@@ -724,7 +977,10 @@ impl<'a> DigestRef<'a> {
                     has_runtime_environment_updated = true;
                 }
                 DigestItemRef::BabeSeal(_) => return Err(Error::SealIsntLastItem),
-                DigestItemRef::Beefy { .. } | DigestItemRef::PolkadotParachain { .. } => {}
+                DigestItemRef::Beefy { .. }
+                | DigestItemRef::PolkadotParachain { .. }
+                | DigestItemRef::ChangesTrieConfiguration(_)
+                | DigestItemRef::Other { .. } => {}
             }
         }
 
@@ -823,6 +1079,14 @@ impl Digest {
         }
     }
 
+    /// Returns the new list of Aura authorities if the digest contains a
+    /// [`AuraConsensusLogRef::AuthoritiesChange`] item.
+    ///
+    /// See [`DigestRef::aura_authorities_change`].
+    pub fn aura_authorities_change(&self) -> Option<AuraAuthoritiesIter> {
+        DigestRef::from(self).aura_authorities_change()
+    }
+
     /// Returns the Babe seal digest item, if any.
     pub fn babe_seal(&self) -> Option<&[u8; 64]> {
         DigestRef::from(self).babe_seal()
@@ -857,6 +1121,13 @@ impl Digest {
     pub fn has_runtime_environment_updated(&self) -> bool {
         self.has_runtime_environment_updated
     }
+
+    /// Returns the root of the Merkle Mountain Range stored in the header, if any.
+    ///
+    /// See [`DigestRef::mmr_root`].
+    pub fn mmr_root(&self) -> Result<Option<&[u8; 32]>, Error> {
+        DigestRef::from(self).mmr_root()
+    }
 }
 
 impl fmt::Debug for Digest {
@@ -913,8 +1184,10 @@ impl<'a> Iterator for LogsIter<'a> {
                     return None;
                 }
 
-                // Validity is guaranteed when the `DigestRef` is constructed.
-                let (item, new_pointer) = decode_item(*pointer).unwrap();
+                // Validity is guaranteed when the `DigestRef` is constructed. Lenient decoding
+                // only differs from strict decoding by accepting more items, and can thus be
+                // used no matter how the `DigestRef` has been constructed.
+                let (item, new_pointer) = decode_item(*pointer, true).unwrap();
                 *pointer = new_pointer;
                 *remaining_len -= 1;
 
@@ -958,7 +1231,7 @@ pub enum DigestItemRef<'a> {
     /// Item related to the BEEFY algorithm (Mountain Merkle Ranges). Allows proving that a block
     /// is a child of another.
     Beefy {
-        /// Smoldot doesn't interpret the content of the log item at the moment.
+        /// SCALE-encoded log item. Can be decoded using [`BeefyConsensusLogRef::from_slice`].
         opaque: &'a [u8],
     },
 
@@ -971,6 +1244,27 @@ pub enum DigestItemRef<'a> {
     /// Runtime of the chain has been updated in this block. This can include the runtime code or
     /// the heap pages.
     RuntimeEnvironmentUpdated,
+
+    /// Configuration of the changes trie has been modified in this block. `None` if the changes
+    /// trie has been disabled.
+    ///
+    /// Changes tries are a deprecated Substrate feature that some older chains used. Smoldot
+    /// doesn't interpret this item beyond decoding it.
+    ChangesTrieConfiguration(Option<ChangesTrieConfiguration>),
+
+    /// Item that smoldot doesn't recognize, such as a log item of an unknown consensus engine.
+    ///
+    /// Only ever produced when decoding a header with `lenient_digest` set to `true`. See
+    /// [`decode_with_options`].
+    Other {
+        /// Type of the item. `4` for consensus items, `5` for seals, `6` for pre-runtime items,
+        /// and `0` for items that don't belong to any consensus engine.
+        ty: u8,
+        /// Consensus engine the item belongs to. `None` if and only if `ty` is `0`.
+        engine_id: Option<&'a [u8; 4]>,
+        /// Smoldot doesn't interpret the content of the log item.
+        opaque: &'a [u8],
+    },
 }
 
 impl<'a> DigestItemRef<'a> {
@@ -1102,6 +1396,31 @@ impl<'a> DigestItemRef<'a> {
                 iter::once(ret)
             }
             DigestItemRef::RuntimeEnvironmentUpdated => iter::once(vec![8]),
+            DigestItemRef::ChangesTrieConfiguration(config) => {
+                // `7` is the digest item type, and `0` the "new configuration" signal.
+                let mut ret = vec![7, 0];
+                if let Some(config) = config {
+                    ret.push(1);
+                    ret.extend_from_slice(&config.digest_interval.to_le_bytes());
+                    ret.extend_from_slice(&config.digest_levels.to_le_bytes());
+                } else {
+                    ret.push(0);
+                }
+                iter::once(ret)
+            }
+            DigestItemRef::Other {
+                ty,
+                engine_id,
+                opaque,
+            } => {
+                let mut ret = vec![ty];
+                if let Some(engine_id) = engine_id {
+                    ret.extend_from_slice(engine_id);
+                }
+                ret.extend_from_slice(util::encode_scale_compact_usize(opaque.len()).as_ref());
+                ret.extend_from_slice(opaque);
+                iter::once(ret)
+            }
         }
     }
 }
@@ -1121,6 +1440,16 @@ impl<'a> From<&'a DigestItem> for DigestItemRef<'a> {
                 DigestItemRef::PolkadotParachain { opaque: &*opaque }
             }
             DigestItem::RuntimeEnvironmentUpdated => DigestItemRef::RuntimeEnvironmentUpdated,
+            DigestItem::ChangesTrieConfiguration(v) => DigestItemRef::ChangesTrieConfiguration(*v),
+            DigestItem::Other {
+                ty,
+                engine_id,
+                opaque,
+            } => DigestItemRef::Other {
+                ty: *ty,
+                engine_id: engine_id.as_ref(),
+                opaque,
+            },
         }
     }
 }
@@ -1155,6 +1484,31 @@ pub enum DigestItem {
     /// Runtime of the chain has been updated in this block. This can include the runtime code or
     /// the heap pages.
     RuntimeEnvironmentUpdated,
+
+    /// See [`DigestItemRef::ChangesTrieConfiguration`].
+    ChangesTrieConfiguration(Option<ChangesTrieConfiguration>),
+
+    /// See [`DigestItemRef::Other`].
+    Other {
+        /// See [`DigestItemRef::Other::ty`].
+        ty: u8,
+        /// See [`DigestItemRef::Other::engine_id`].
+        engine_id: Option<[u8; 4]>,
+        /// Smoldot doesn't interpret the content of the log item.
+        opaque: Vec<u8>,
+    },
+}
+
+/// Configuration of the changes trie of a chain.
+///
+/// See [`DigestItemRef::ChangesTrieConfiguration`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChangesTrieConfiguration {
+    /// Interval, in number of blocks, at which digest changes tries are created. A value of 0 or
+    /// 1 means that no digest changes tries are created.
+    pub digest_interval: u32,
+    /// Maximum number of levels of digest changes tries.
+    pub digest_levels: u32,
 }
 
 impl<'a> From<DigestItemRef<'a>> for DigestItem {
@@ -1182,17 +1536,45 @@ impl<'a> From<DigestItemRef<'a>> for DigestItem {
                 opaque: opaque.to_vec(),
             },
             DigestItemRef::RuntimeEnvironmentUpdated => DigestItem::RuntimeEnvironmentUpdated,
+            DigestItemRef::ChangesTrieConfiguration(v) => DigestItem::ChangesTrieConfiguration(v),
+            DigestItemRef::Other {
+                ty,
+                engine_id,
+                opaque,
+            } => DigestItem::Other {
+                ty,
+                engine_id: engine_id.copied(),
+                opaque: opaque.to_vec(),
+            },
         }
     }
 }
 
 /// Decodes a single digest log item. On success, returns the item and the data that remains
 /// after the item.
-fn decode_item(mut slice: &[u8]) -> Result<(DigestItemRef, &[u8]), Error> {
+///
+/// See [`decode_with_options`] for the meaning of `lenient`.
+fn decode_item(mut slice: &[u8], lenient: bool) -> Result<(DigestItemRef, &[u8]), Error> {
     let index = *slice.get(0).ok_or(Error::TooShort)?;
     slice = &slice[1..];
 
     match index {
+        0 if lenient => {
+            let (slice, len) =
+                crate::util::nom_scale_compact_usize::<nom::error::Error<&[u8]>>(slice)
+                    .map_err(|_| Error::DigestItemLenDecodeError)?;
+
+            if slice.len() < len {
+                return Err(Error::TooShort);
+            }
+
+            let item = DigestItemRef::Other {
+                ty: 0,
+                engine_id: None,
+                opaque: &slice[..len],
+            };
+            Ok((item, &slice[len..]))
+        }
         4 | 5 | 6 => {
             if slice.len() < 4 {
                 return Err(Error::TooShort);
@@ -1212,9 +1594,29 @@ fn decode_item(mut slice: &[u8]) -> Result<(DigestItemRef, &[u8]), Error> {
             let content = &slice[..len];
             slice = &slice[len..];
 
-            let item = decode_item_from_parts(index, engine_id, content)?;
+            let item = decode_item_from_parts(index, engine_id, content, lenient)?;
             Ok((item, slice))
         }
+        7 => {
+            // Changes trie signal. The only existing signal is `0`, which indicates a new
+            // changes trie configuration.
+            match slice {
+                [0, 0, rest @ ..] => Ok((DigestItemRef::ChangesTrieConfiguration(None), rest)),
+                [0, 1, rest @ ..] if rest.len() >= 8 => Ok((
+                    DigestItemRef::ChangesTrieConfiguration(Some(ChangesTrieConfiguration {
+                        digest_interval: u32::from_le_bytes(
+                            <[u8; 4]>::try_from(&rest[..4]).unwrap(),
+                        ),
+                        digest_levels: u32::from_le_bytes(
+                            <[u8; 4]>::try_from(&rest[4..8]).unwrap(),
+                        ),
+                    })),
+                    &rest[8..],
+                )),
+                [0, 1, ..] | [] | [0] => Err(Error::TooShort),
+                _ => Err(Error::BadChangesTrieSignal),
+            }
+        }
         8 => Ok((DigestItemRef::RuntimeEnvironmentUpdated, slice)),
         ty => Err(Error::UnknownDigestLogType(ty)),
     }
@@ -1225,6 +1627,7 @@ fn decode_item_from_parts<'a>(
     index: u8,
     engine_id: &'a [u8; 4],
     content: &'a [u8],
+    lenient: bool,
 ) -> Result<DigestItemRef<'a>, Error> {
     Ok(match (index, engine_id) {
         (_, b"pow_") => return Err(Error::PowIdeologicallyNotSupported),
@@ -1236,7 +1639,6 @@ fn decode_item_from_parts<'a>(
         }
         (4, b"BEEF") => DigestItemRef::Beefy { opaque: content },
         (4, b"POL1") => DigestItemRef::PolkadotParachain { opaque: content },
-        (4, e) => return Err(Error::UnknownConsensusEngine(*e)),
         // 5 = Seal
         (5, b"aura") => DigestItemRef::AuraSeal({
             TryFrom::try_from(content).map_err(|_| Error::BadAuraSealLength)?
@@ -1244,11 +1646,14 @@ fn decode_item_from_parts<'a>(
         (5, b"BABE") => DigestItemRef::BabeSeal({
             TryFrom::try_from(content).map_err(|_| Error::BadBabeSealLength)?
         }),
-        (5, e) => return Err(Error::UnknownConsensusEngine(*e)),
         // 6 = PreRuntime
         (6, b"aura") => DigestItemRef::AuraPreDigest(AuraPreDigest::from_slice(content)?),
         (6, b"BABE") => DigestItemRef::BabePreDigest(BabePreDigestRef::from_slice(content)?),
-        (6, e) => return Err(Error::UnknownConsensusEngine(*e)),
-        _ => unreachable!(),
+        (_, e) if !lenient => return Err(Error::UnknownConsensusEngine(*e)),
+        (_, e) => DigestItemRef::Other {
+            ty: index,
+            engine_id: Some(e),
+            opaque: content,
+        },
     })
 }
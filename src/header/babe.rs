@@ -388,6 +388,35 @@ impl BabeAllowedSlots {
         })
     }
 
+    /// Decodes a [`BabeAllowedSlots`] from a slice of bytes, or returns
+    /// [`BabeAllowedSlots::PrimarySlots`] if the slice is empty.
+    ///
+    /// Older runtimes don't provide the `allowed_slots` field, in which case only primary slot
+    /// claims are allowed.
+    pub fn from_slice_or_default(slice: &[u8]) -> Result<Self, Error> {
+        if slice.is_empty() {
+            Ok(BabeAllowedSlots::PrimarySlots)
+        } else {
+            BabeAllowedSlots::from_slice(slice)
+        }
+    }
+
+    /// Returns `true` if a block whose header contains the given pre-runtime digest is allowed
+    /// by this configuration.
+    pub fn allows(&self, pre_digest: &BabePreDigestRef) -> bool {
+        match (self, pre_digest) {
+            (_, BabePreDigestRef::Primary(_)) => true,
+            (
+                BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+                BabePreDigestRef::SecondaryPlain(_),
+            ) => true,
+            (BabeAllowedSlots::PrimaryAndSecondaryVrfSlots, BabePreDigestRef::SecondaryVRF(_)) => {
+                true
+            }
+            _ => false,
+        }
+    }
+
     /// Returns an iterator to list of buffers which, when concatenated, produces the SCALE
     /// encoding of that object.
     pub fn scale_encoding(&self) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone {
@@ -442,6 +471,40 @@ impl<'a> BabePreDigestRef<'a> {
         }
     }
 
+    /// Returns the index of the authority that has produced the block, within the list of
+    /// authorities of the epoch.
+    pub fn authority_index(&self) -> u32 {
+        match self {
+            BabePreDigestRef::Primary(digest) => digest.authority_index,
+            BabePreDigestRef::SecondaryPlain(digest) => digest.authority_index,
+            BabePreDigestRef::SecondaryVRF(digest) => digest.authority_index,
+        }
+    }
+
+    /// Returns the VRF output stored in the header, or `None` for
+    /// [`BabePreDigestRef::SecondaryPlain`], which doesn't contain any VRF.
+    ///
+    /// > **Note**: The VRF output isn't verified by this function.
+    pub fn vrf_output(&self) -> Option<&'a [u8; 32]> {
+        match self {
+            BabePreDigestRef::Primary(digest) => Some(digest.vrf_output),
+            BabePreDigestRef::SecondaryPlain(_) => None,
+            BabePreDigestRef::SecondaryVRF(digest) => Some(digest.vrf_output),
+        }
+    }
+
+    /// Returns the VRF proof stored in the header, or `None` for
+    /// [`BabePreDigestRef::SecondaryPlain`], which doesn't contain any VRF.
+    ///
+    /// > **Note**: The VRF proof isn't verified by this function.
+    pub fn vrf_proof(&self) -> Option<&'a [u8; 64]> {
+        match self {
+            BabePreDigestRef::Primary(digest) => Some(digest.vrf_proof),
+            BabePreDigestRef::SecondaryPlain(_) => None,
+            BabePreDigestRef::SecondaryVRF(digest) => Some(digest.vrf_proof),
+        }
+    }
+
     /// Returns an iterator to list of buffers which, when concatenated, produces the SCALE
     /// encoding of that object.
     pub fn scale_encoding(
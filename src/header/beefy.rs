@@ -0,0 +1,219 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::Error;
+use crate::util;
+
+use alloc::vec::Vec;
+use tiny_keccak::Hasher as _;
+
+/// A consensus log item for BEEFY.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BeefyConsensusLogRef<'a> {
+    /// The set of BEEFY authorities has changed.
+    AuthoritiesChange {
+        /// SCALE-encoded new set of authorities and its identifier. Smoldot doesn't interpret
+        /// the content of this field at the moment.
+        opaque: &'a [u8],
+    },
+
+    /// Note that the authority with given index is disabled until the next change.
+    OnDisabled(u32),
+
+    /// Root of the Merkle Mountain Range of the chain after this block has been appended to it.
+    ///
+    /// Leaves of the Merkle Mountain Range can be verified against this root with
+    /// [`verify_mmr_leaf_proof`].
+    MmrRoot(&'a [u8; 32]),
+}
+
+impl<'a> BeefyConsensusLogRef<'a> {
+    /// Decodes a [`BeefyConsensusLogRef`] from a slice of bytes.
+    pub fn from_slice(slice: &'a [u8]) -> Result<Self, Error> {
+        Ok(
+            nom::combinator::all_consuming(beefy_consensus_log_ref)(slice)
+                .map_err(|_: nom::Err<(&[u8], nom::error::ErrorKind)>| {
+                    Error::BeefyConsensusLogDecodeError
+                })?
+                .1,
+        )
+    }
+}
+
+/// Configuration for [`verify_mmr_leaf_proof`].
+pub struct MmrLeafProofConfig<'a> {
+    /// Root of the Merkle Mountain Range to verify the leaf against. Typically obtained through
+    /// [`super::DigestRef::mmr_root`].
+    pub mmr_root: &'a [u8; 32],
+
+    /// SCALE-encoded leaf whose presence in the Merkle Mountain Range must be verified. The
+    /// Keccak-256 hash of this leaf is the value actually stored in the Merkle Mountain Range.
+    pub scale_encoded_leaf: &'a [u8],
+
+    /// SCALE-encoded proof, containing the index of the leaf, the number of leaves of the
+    /// Merkle Mountain Range, and the list of hashes necessary to calculate its root.
+    pub scale_encoded_proof: &'a [u8],
+}
+
+/// Verifies that a leaf is part of a Merkle Mountain Range.
+///
+/// The Merkle Mountain Range is expected to use Keccak-256 as its hashing algorithm, which is the
+/// case for chains using BEEFY.
+pub fn verify_mmr_leaf_proof(config: MmrLeafProofConfig) -> Result<(), MmrProofVerifyError> {
+    let (leaf_index, leaf_count, proof_items) = nom::combinator::all_consuming(
+        mmr_leaf_proof::<nom::error::Error<&[u8]>>,
+    )(config.scale_encoded_proof)
+    .map_err(|_| MmrProofVerifyError::ProofDecodeError)?
+    .1;
+
+    if leaf_index >= leaf_count {
+        return Err(MmrProofVerifyError::LeafIndexOutOfRange);
+    }
+
+    let mut proof_items = proof_items
+        .chunks(32)
+        .map(|item| <&[u8; 32]>::try_from(item).unwrap());
+
+    // The Merkle Mountain Range consists of a list of perfect binary trees, called peaks, whose
+    // sizes correspond to the bits of the number of leaves, from the highest to the lowest.
+    // The proof contains, in order, the hashes of the peaks at the left of the one containing
+    // the leaf, the siblings of the nodes from the leaf up to its peak, then, if any, the
+    // peaks at the right of the one containing the leaf bagged together into a single hash.
+    let mut peaks = Vec::with_capacity(usize::try_from(leaf_count.count_ones()).unwrap());
+    let mut peak_first_leaf = 0u64;
+    for peak_height in (0..64).rev() {
+        let peak_num_leaves = 1u64 << peak_height;
+        if leaf_count & peak_num_leaves == 0 {
+            continue;
+        }
+
+        if leaf_index < peak_first_leaf {
+            let item = proof_items
+                .next()
+                .ok_or(MmrProofVerifyError::BadProofLength)?;
+            peaks.push(*item);
+            break;
+        } else if leaf_index >= peak_first_leaf + peak_num_leaves {
+            let item = proof_items
+                .next()
+                .ok_or(MmrProofVerifyError::BadProofLength)?;
+            peaks.push(*item);
+        } else {
+            let mut hash = keccak_256(&[config.scale_encoded_leaf]);
+            let mut index_in_peak = leaf_index - peak_first_leaf;
+            for _ in 0..peak_height {
+                let sibling = proof_items
+                    .next()
+                    .ok_or(MmrProofVerifyError::BadProofLength)?;
+                hash = if index_in_peak & 1 == 0 {
+                    keccak_256(&[&hash, sibling])
+                } else {
+                    keccak_256(&[sibling, &hash])
+                };
+                index_in_peak >>= 1;
+            }
+            peaks.push(hash);
+        }
+
+        peak_first_leaf += peak_num_leaves;
+    }
+
+    if proof_items.next().is_some() {
+        return Err(MmrProofVerifyError::BadProofLength);
+    }
+
+    // Peaks are bagged from right to left.
+    let mut calculated_root = peaks.pop().unwrap();
+    while let Some(left_peak) = peaks.pop() {
+        calculated_root = keccak_256(&[&calculated_root, &left_peak]);
+    }
+
+    if calculated_root != *config.mmr_root {
+        return Err(MmrProofVerifyError::RootMismatch);
+    }
+
+    Ok(())
+}
+
+/// Error potentially returned by [`verify_mmr_leaf_proof`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum MmrProofVerifyError {
+    /// Failed to decode the proof.
+    ProofDecodeError,
+    /// Index of the leaf is superior or equal to the number of leaves.
+    LeafIndexOutOfRange,
+    /// Number of hashes in the proof doesn't match the index of the leaf and the number of
+    /// leaves.
+    BadProofLength,
+    /// Root calculated from the leaf and the proof doesn't match the expected root.
+    RootMismatch,
+}
+
+fn keccak_256(data: &[&[u8]]) -> [u8; 32] {
+    let mut keccak = tiny_keccak::Keccak::v256();
+    for data in data {
+        keccak.update(data);
+    }
+    let mut out = [0; 32];
+    keccak.finalize(&mut out);
+    out
+}
+
+fn beefy_consensus_log_ref<
+    'a,
+    E: nom::error::ParseError<&'a [u8]> + nom::error::ContextError<&'a [u8]>,
+>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], BeefyConsensusLogRef<'a>, E> {
+    nom::error::context(
+        "beefy_consensus_log_ref",
+        nom::branch::alt((
+            nom::combinator::map(
+                nom::sequence::preceded(nom::bytes::complete::tag(&[1]), nom::combinator::rest),
+                |opaque| BeefyConsensusLogRef::AuthoritiesChange { opaque },
+            ),
+            nom::combinator::map(
+                nom::sequence::preceded(
+                    nom::bytes::complete::tag(&[2]),
+                    nom::number::complete::le_u32,
+                ),
+                BeefyConsensusLogRef::OnDisabled,
+            ),
+            nom::combinator::map(
+                nom::sequence::preceded(
+                    nom::bytes::complete::tag(&[3]),
+                    nom::bytes::complete::take(32u32),
+                ),
+                |root| BeefyConsensusLogRef::MmrRoot(<&[u8; 32]>::try_from(root).unwrap()),
+            ),
+        )),
+    )(bytes)
+}
+
+/// Decodes a Merkle Mountain Range leaf proof. Returns the index of the leaf, the number of
+/// leaves, and the concatenated list of hashes.
+fn mmr_leaf_proof<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (u64, u64, &'a [u8]), E> {
+    nom::sequence::tuple((
+        nom::number::complete::le_u64,
+        nom::number::complete::le_u64,
+        nom::combinator::flat_map(util::nom_scale_compact_usize, |num_items| {
+            nom::bytes::complete::take(num_items.saturating_mul(32))
+        }),
+    ))(bytes)
+}
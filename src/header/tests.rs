@@ -56,3 +56,550 @@ fn decode_reencode_kusama_7472481() {
     let decoded = super::decode(expected).unwrap();
     assert_eq!(decoded.scale_encoding_vec(), expected);
 }
+
+#[test]
+fn hash_matches_hash_from_scale_encoded_header() {
+    // Hashing the decoded header must give the same result as hashing the raw bytes.
+    for encoded in [
+        &include_bytes!("./tests-header-kusama-7472481")[..],
+        &include_bytes!("./tests-header-polkadot-512271")[..],
+    ] {
+        let decoded = super::decode(encoded).unwrap();
+        let expected = super::hash_from_scale_encoded_header(encoded);
+        assert_eq!(decoded.hash(), expected);
+        assert_eq!(super::Header::from(decoded).hash(), expected);
+        assert_eq!(
+            super::hash_from_scale_encoded_header_vectored(encoded.chunks(7)),
+            expected
+        );
+    }
+}
+
+#[test]
+fn verify_extrinsics_root_empty_body() {
+    // Genesis blocks always have an empty body.
+    let chain_spec = crate::chain_spec::ChainSpec::from_json_bytes(
+        &include_bytes!("../author/runtime/example-chain-specs.json")[..],
+    )
+    .unwrap();
+    let genesis = crate::calculate_genesis_block_header(&chain_spec);
+    assert!(super::verify_extrinsics_root(&genesis, &[] as &[Vec<u8>]));
+    assert!(!super::verify_extrinsics_root(&genesis, &[vec![0u8]]));
+}
+
+#[test]
+fn verify_extrinsics_root_mismatch() {
+    let header = super::decode(include_bytes!("./tests-header-polkadot-512271")).unwrap();
+    assert!(!super::verify_extrinsics_root(header, &[] as &[Vec<u8>]));
+}
+
+#[test]
+fn aura_authorities_oversized_len() {
+    // Number of authorities equal to `u64::MAX`, whose multiplication by the size of an
+    // authority overflows.
+    let mut encoded = crate::util::encode_scale_compact_u64(u64::MAX)
+        .as_ref()
+        .to_vec();
+    encoded.extend_from_slice(&[0; 32]);
+    assert!(super::AuraAuthoritiesIter::decode(&encoded).is_err());
+}
+
+#[test]
+fn babe_primary_pre_digest() {
+    // Polkadot block #512271 was authored in a primary slot.
+    let header = super::decode(include_bytes!("./tests-header-polkadot-512271")).unwrap();
+    let pre_digest = header.digest.babe_pre_runtime().unwrap();
+    assert!(pre_digest.is_primary());
+    assert_eq!(pre_digest.authority_index(), 75);
+    assert_eq!(pre_digest.slot_number(), 265598163);
+    assert_eq!(
+        &pre_digest.vrf_output().unwrap()[..],
+        &hex::decode("54477fa3adbbf1192f5b4c9211a99f487d5205ef594be2230ed0d1643f609a17").unwrap()[..]
+    );
+    assert_eq!(
+        &pre_digest.vrf_proof().unwrap()[..],
+        &hex::decode(
+            "61920f84902dfc4b5f8c26c924aa3fa3c009719073962088734ae2e079ac6401\
+             e4667c717dae2517385e61573559a4d866665e20733db0184737e99b72872b09"
+        )
+        .unwrap()[..]
+    );
+}
+
+#[test]
+fn babe_secondary_plain_pre_digest() {
+    // Kusama block #7472481 was authored in a secondary slot, without VRF.
+    let header = super::decode(include_bytes!("./tests-header-kusama-7472481")).unwrap();
+    let pre_digest = header.digest.babe_pre_runtime().unwrap();
+    assert!(!pre_digest.is_primary());
+    assert!(matches!(
+        pre_digest,
+        super::BabePreDigestRef::SecondaryPlain(_)
+    ));
+    assert_eq!(pre_digest.authority_index(), 140);
+    assert_eq!(pre_digest.slot_number(), 270170680);
+    assert!(pre_digest.vrf_output().is_none());
+    assert!(pre_digest.vrf_proof().is_none());
+}
+
+#[test]
+fn babe_allowed_slots() {
+    let primary = super::decode(include_bytes!("./tests-header-polkadot-512271")).unwrap();
+    let primary = primary.digest.babe_pre_runtime().unwrap();
+    let secondary = super::decode(include_bytes!("./tests-header-kusama-7472481")).unwrap();
+    let secondary = secondary.digest.babe_pre_runtime().unwrap();
+
+    assert!(super::BabeAllowedSlots::PrimarySlots.allows(&primary));
+    assert!(!super::BabeAllowedSlots::PrimarySlots.allows(&secondary));
+    assert!(super::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots.allows(&secondary));
+    assert!(!super::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots.allows(&secondary));
+
+    // Runtimes that don't provide the allowed slots default to primary slots only.
+    assert_eq!(
+        super::BabeAllowedSlots::from_slice_or_default(&[]).unwrap(),
+        super::BabeAllowedSlots::PrimarySlots
+    );
+    assert_eq!(
+        super::BabeAllowedSlots::from_slice_or_default(&[1]).unwrap(),
+        super::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots
+    );
+}
+
+#[test]
+fn changes_trie_configuration() {
+    let encoded = [7, 0, 1, 4, 0, 0, 0, 2, 0, 0, 0];
+    let mut digest = vec![4]; // Compact-encoded number of items.
+    digest.extend_from_slice(&encoded);
+
+    let (decoded, remain) = super::DigestRef::from_scale_bytes(&digest, false).unwrap();
+    assert!(remain.is_empty());
+    let item = decoded.logs().next().unwrap();
+    assert_eq!(
+        item,
+        super::DigestItemRef::ChangesTrieConfiguration(Some(super::ChangesTrieConfiguration {
+            digest_interval: 4,
+            digest_levels: 2,
+        }))
+    );
+    assert_eq!(
+        item.scale_encoding().fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        }),
+        &encoded[..]
+    );
+
+    // Disabling the changes trie.
+    let (decoded, _) = super::DigestRef::from_scale_bytes(&[4, 7, 0, 0], false).unwrap();
+    assert_eq!(
+        decoded.logs().next().unwrap(),
+        super::DigestItemRef::ChangesTrieConfiguration(None)
+    );
+
+    // Unknown signal, or truncated configuration.
+    assert!(super::DigestRef::from_scale_bytes(&[4, 7, 1, 0], false).is_err());
+    assert!(super::DigestRef::from_scale_bytes(&[4, 7, 0, 1, 4, 0, 0, 0], false).is_err());
+}
+
+#[test]
+fn lenient_digest_unknown_engine() {
+    let mut encoded = vec![0; 32]; // Parent hash.
+    encoded.push(4); // Block number.
+    encoded.extend_from_slice(&[1; 64]); // State root and extrinsics root.
+    encoded.push(12); // Compact-encoded number of items.
+    encoded.extend_from_slice(&[4, b'T', b'E', b'S', b'T', 12, 1, 2, 3]);
+    encoded.extend_from_slice(&[0, 8, 9, 9]);
+    encoded.extend_from_slice(&[5, b'T', b'E', b'S', b'T', 4, 7]);
+
+    assert!(matches!(
+        super::decode(&encoded),
+        Err(super::Error::UnknownConsensusEngine(e)) if e == *b"TEST"
+    ));
+
+    let decoded = super::decode_with_options(&encoded, true).unwrap();
+    assert_eq!(
+        decoded.digest.logs().collect::<Vec<_>>(),
+        vec![
+            super::DigestItemRef::Other {
+                ty: 4,
+                engine_id: Some(b"TEST"),
+                opaque: &[1, 2, 3],
+            },
+            super::DigestItemRef::Other {
+                ty: 0,
+                engine_id: None,
+                opaque: &[9, 9],
+            },
+            super::DigestItemRef::Other {
+                ty: 5,
+                engine_id: Some(b"TEST"),
+                opaque: &[7],
+            },
+        ]
+    );
+    assert!(decoded.digest.aura_seal().is_none());
+    assert_eq!(decoded.scale_encoding_vec(), encoded);
+    assert_eq!(
+        super::Header::from(decoded.clone()).scale_encoding_vec(),
+        encoded
+    );
+
+    // Truncated items are always an error.
+    let truncated = &encoded[..encoded.len() - 1];
+    assert!(matches!(
+        super::decode_with_options(truncated, true),
+        Err(super::Error::TooShort)
+    ));
+    assert!(
+        super::DigestRef::from_scale_bytes(&[4, 4, b'T', b'E', b'S', b'T', 12, 1, 2], true)
+            .is_err()
+    );
+    assert!(super::DigestRef::from_scale_bytes(&[4, 0, 8, 9], true).is_err());
+    assert!(super::DigestRef::from_scale_bytes(&[4, 0, 8, 9], false).is_err());
+
+    // Unknown item types can't be skipped, as their length isn't known.
+    assert!(matches!(
+        super::DigestRef::from_scale_bytes(&[4, 3, 0], true),
+        Err(super::Error::UnknownDigestLogType(3))
+    ));
+}
+
+#[test]
+fn mmr_root_digest() {
+    let mmr_root =
+        hex::decode("b4fcc6ce39ba4773932c10832f9ed33d3657752c85c476ef4905139ba0808018").unwrap();
+
+    // One BEEFY consensus item containing a `MmrRoot`, preceded with a BEEFY `OnDisabled`.
+    let digest = [
+        &[8, 4][..],
+        b"BEEF",
+        &[20, 2, 5, 0, 0, 0, 4],
+        b"BEEF",
+        &[132, 3],
+        &mmr_root,
+    ]
+    .concat();
+    let (decoded, _) = super::DigestRef::from_scale_bytes(&digest, false).unwrap();
+    assert_eq!(decoded.mmr_root().unwrap().unwrap()[..], mmr_root[..]);
+    assert_eq!(
+        super::Digest::from(decoded).mmr_root().unwrap().unwrap()[..],
+        mmr_root[..]
+    );
+
+    // Blocks without any BEEFY item don't have a root.
+    let header = super::decode(include_bytes!("./tests-header-polkadot-512271")).unwrap();
+    assert!(matches!(header.digest.mmr_root(), Ok(None)));
+
+    // Truncated root, which is accepted when decoding the header but not when decoding the root.
+    let digest = [&[4, 4][..], b"BEEF", &[12, 3, 1, 2]].concat();
+    let (decoded, _) = super::DigestRef::from_scale_bytes(&digest, false).unwrap();
+    assert!(matches!(
+        decoded.mmr_root(),
+        Err(super::Error::BeefyConsensusLogDecodeError)
+    ));
+
+    // Multiple roots.
+    let digest = [
+        &[8, 4][..],
+        b"BEEF",
+        &[132, 3],
+        &mmr_root,
+        &[4],
+        b"BEEF",
+        &[132, 3],
+        &mmr_root,
+    ]
+    .concat();
+    let (decoded, _) = super::DigestRef::from_scale_bytes(&digest, false).unwrap();
+    assert!(matches!(
+        decoded.mmr_root(),
+        Err(super::Error::MultipleMmrRoots)
+    ));
+}
+
+#[test]
+fn mmr_leaf_proof() {
+    // Merkle Mountain Range containing 7 leaves, where leaf `n` is `[n, n, n]`. It consists of
+    // three peaks containing respectively 4, 2, and 1 leaves.
+    let mmr_root: [u8; 32] =
+        hex::decode("b4fcc6ce39ba4773932c10832f9ed33d3657752c85c476ef4905139ba0808018")
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+    let encode_proof = |leaf_index: u64, leaf_count: u64, items: &[&str]| {
+        let mut proof = leaf_index.to_le_bytes().to_vec();
+        proof.extend_from_slice(&leaf_count.to_le_bytes());
+        proof.extend_from_slice(crate::util::encode_scale_compact_usize(items.len()).as_ref());
+        for item in items {
+            proof.extend_from_slice(&hex::decode(item).unwrap());
+        }
+        proof
+    };
+
+    // Leaf in the first peak. The two peaks at its right are bagged together.
+    let proof_leaf_2 = encode_proof(
+        2,
+        7,
+        &[
+            "e728fd2a44d89fcefccafaff8e346457b09944a9943717b9392d7c1f6b1cef37",
+            "fc48644f7203cb99e79dc6d3299d229e15684355981f9dd4fae33dd1addf9949",
+            "9a5077202de94084bb186be39fd1efb184a7cfd5378894ff74bd23a37a5fb549",
+        ],
+    );
+    // Leaf in the second peak.
+    let proof_leaf_5 = encode_proof(
+        5,
+        7,
+        &[
+            "c971c31879b1fae38a6b7d7635f4b31f76bbd6d953ca97930d5cca903d0a963d",
+            "25bdb33f00b737fba88b34c99ea7038784347944ef18ac59befa7a827436241e",
+            "0401ecfe96b0ac31905ef1eb2d45a568292234985f0b7bc70388d7f2eb2c4cd7",
+        ],
+    );
+    // Leaf that is a peak on its own.
+    let proof_leaf_6 = encode_proof(
+        6,
+        7,
+        &[
+            "c971c31879b1fae38a6b7d7635f4b31f76bbd6d953ca97930d5cca903d0a963d",
+            "f26b6a63cc9e17b41a954b683e7042b7d8aed18b88a3349b0dc87bac77dcde46",
+        ],
+    );
+
+    let verify = |leaf: &[u8], proof: &[u8]| {
+        super::verify_mmr_leaf_proof(super::MmrLeafProofConfig {
+            mmr_root: &mmr_root,
+            scale_encoded_leaf: leaf,
+            scale_encoded_proof: proof,
+        })
+    };
+
+    verify(&[2, 2, 2], &proof_leaf_2).unwrap();
+    verify(&[5, 5, 5], &proof_leaf_5).unwrap();
+    verify(&[6, 6, 6], &proof_leaf_6).unwrap();
+
+    assert!(matches!(
+        verify(&[3, 3, 3], &proof_leaf_2),
+        Err(super::MmrProofVerifyError::RootMismatch)
+    ));
+    assert!(matches!(
+        verify(&[2, 2, 2], &proof_leaf_2[..proof_leaf_2.len() - 1]),
+        Err(super::MmrProofVerifyError::ProofDecodeError)
+    ));
+    assert!(matches!(
+        verify(&[2, 2, 2], &encode_proof(2, 7, &[])),
+        Err(super::MmrProofVerifyError::BadProofLength)
+    ));
+    assert!(matches!(
+        verify(&[6, 6, 6], &encode_proof(7, 7, &[])),
+        Err(super::MmrProofVerifyError::LeafIndexOutOfRange)
+    ));
+}
+
+#[test]
+fn new_header_round_trip() {
+    let header = super::Header::new(
+        &[1; 32],
+        1234,
+        &[2; 32],
+        &super::extrinsics_root(&[&b"foo"[..]]),
+        super::DigestRef::empty().into(),
+    )
+    .unwrap();
+
+    let encoded = header.scale_encoding_vec();
+    // An empty digest is encoded as a list of length 0.
+    assert_eq!(encoded.len(), 32 + 2 + 32 + 32 + 1);
+    assert_eq!(*encoded.last().unwrap(), 0);
+
+    let decoded = super::decode(&encoded).unwrap();
+    assert_eq!(*decoded.parent_hash, [1; 32]);
+    assert_eq!(decoded.number, 1234);
+    assert_eq!(*decoded.state_root, [2; 32]);
+    assert_eq!(decoded.digest.logs().len(), 0);
+    assert_eq!(decoded.scale_encoding_vec(), encoded);
+
+    assert_eq!(decoded.hash(), header.hash());
+    assert_eq!(
+        super::hash_from_scale_encoded_header(&encoded),
+        header.hash()
+    );
+}
+
+#[test]
+fn new_header_with_digest_round_trip() {
+    let digest_items = [
+        super::DigestItem::AuraPreDigest(super::AuraPreDigest { slot_number: 42 }),
+        super::DigestItem::AuraSeal([5; 64]),
+    ];
+    let header = super::Header::new(
+        &[1; 32],
+        1,
+        &[2; 32],
+        &[3; 32],
+        super::DigestRef::from_slice(&digest_items).unwrap().into(),
+    )
+    .unwrap();
+
+    let encoded = header.scale_encoding_vec();
+    let decoded = super::decode(&encoded).unwrap();
+    assert_eq!(decoded.digest.aura_pre_runtime().unwrap().slot_number, 42);
+    assert_eq!(*decoded.digest.aura_seal().unwrap(), [5; 64]);
+    assert_eq!(decoded.hash(), header.hash());
+}
+
+#[test]
+fn new_header_bad_lengths() {
+    assert!(matches!(
+        super::Header::new(
+            &[1; 31],
+            1,
+            &[2; 32],
+            &[3; 32],
+            super::DigestRef::empty().into()
+        ),
+        Err(super::NewHeaderError::BadParentHashLength)
+    ));
+    assert!(matches!(
+        super::Header::new(
+            &[1; 32],
+            1,
+            &[2; 33],
+            &[3; 32],
+            super::DigestRef::empty().into()
+        ),
+        Err(super::NewHeaderError::BadStateRootLength)
+    ));
+    assert!(matches!(
+        super::Header::new(&[1; 32], 1, &[2; 32], &[], super::DigestRef::empty().into()),
+        Err(super::NewHeaderError::BadExtrinsicsRootLength)
+    ));
+}
+
+#[test]
+fn chain_contiguity() {
+    let mut chain = Vec::new();
+    let mut parent_hash = [0; 32];
+    for number in 10..15 {
+        let header = super::Header::new(
+            &parent_hash,
+            number,
+            &[1; 32],
+            &[2; 32],
+            super::DigestRef::empty().into(),
+        )
+        .unwrap();
+        parent_hash = header.hash();
+        chain.push(header);
+    }
+
+    assert!(super::verify_chain_contiguity(&[]).is_ok());
+    assert!(super::verify_chain_contiguity(&chain[..1]).is_ok());
+    assert!(super::verify_chain_contiguity(&chain).is_ok());
+
+    let mut broken = chain.clone();
+    broken[3].parent_hash = [0xff; 32];
+    assert_eq!(
+        super::verify_chain_contiguity(&broken),
+        Err((3, super::ContiguityError::ParentHashMismatch))
+    );
+
+    let mut broken = chain.clone();
+    broken.remove(2);
+    assert_eq!(
+        super::verify_chain_contiguity(&broken),
+        Err((2, super::ContiguityError::ParentHashMismatch))
+    );
+
+    // The parent hash of the last header is preserved, but its number is wrong.
+    let mut broken = chain;
+    broken[4].number = 20;
+    assert_eq!(
+        super::verify_chain_contiguity(&broken),
+        Err((
+            4,
+            super::ContiguityError::NonConsecutiveNumber {
+                parent: 13,
+                child: 20
+            }
+        ))
+    );
+}
+
+#[test]
+fn extrinsic_proof() {
+    let body: [&[u8]; 3] = [&b"foo"[..], &b"bar"[..], &[0x10; 40][..]];
+    let extrinsics_root = super::extrinsics_root(&body);
+
+    let keys = (0..body.len())
+        .map(|index| {
+            crate::util::encode_scale_compact_usize(index)
+                .as_ref()
+                .to_vec()
+        })
+        .collect::<Vec<_>>();
+    let proof_of = |index: usize| {
+        let key = crate::util::encode_scale_compact_usize(index)
+            .as_ref()
+            .to_vec();
+        let proof =
+            crate::trie::proof_generate::generate_read_proof(crate::trie::proof_generate::Config {
+                keys: core::iter::once(&key),
+                storage_entries: keys.iter().map(|k| &k[..]).zip(body.iter().copied()),
+                trie_root_hash: &extrinsics_root,
+            })
+            .unwrap();
+        crate::trie::decode_proof(&proof)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.to_vec())
+            .collect::<Vec<_>>()
+    };
+    fn config<'a>(
+        extrinsics_root: &'a [u8; 32],
+        index: usize,
+        proof: &'a [Vec<u8>],
+    ) -> super::VerifyExtrinsicProofConfig<'a, impl Iterator<Item = &'a [u8]> + Clone> {
+        super::VerifyExtrinsicProofConfig {
+            extrinsics_root,
+            index,
+            proof: proof.iter().map(|p| &p[..]),
+        }
+    }
+    let hash = |extrinsic: &[u8]| {
+        <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], extrinsic).as_bytes()).unwrap()
+    };
+
+    // Inclusion.
+    let proof = proof_of(2);
+    assert_eq!(
+        super::verify_extrinsic_proof(config(&extrinsics_root, 2, &proof)).unwrap(),
+        Some(body[2])
+    );
+    assert!(
+        super::verify_extrinsic_inclusion(config(&extrinsics_root, 2, &proof), &hash(body[2]))
+            .unwrap()
+    );
+    assert!(!super::verify_extrinsic_inclusion(
+        config(&extrinsics_root, 2, &proof),
+        &hash(body[0])
+    )
+    .unwrap());
+
+    // Non-inclusion.
+    let proof = proof_of(3);
+    assert_eq!(
+        super::verify_extrinsic_proof(config(&extrinsics_root, 3, &proof)).unwrap(),
+        None
+    );
+    assert!(!super::verify_extrinsic_inclusion(
+        config(&extrinsics_root, 3, &proof),
+        &hash(body[2])
+    )
+    .unwrap());
+
+    // The proof must cover the requested index.
+    let proof = proof_of(0);
+    assert!(super::verify_extrinsic_proof(config(&extrinsics_root, 2, &proof)).is_err());
+}
@@ -63,6 +63,52 @@ pub fn build_json_call_object_parameters(id_json: Option<&str>, method: MethodCa
     method.to_json_call_object_parameters(id_json)
 }
 
+/// Approximate cost of answering a call to a JSON-RPC method.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MethodCost {
+    /// Answering the call doesn't require much work, or the call starts a subscription.
+    Cheap,
+    /// Answering the call requires iterating over the storage, executing the runtime, or
+    /// downloading a large amount of data.
+    Expensive,
+}
+
+/// Returns the default cost of answering a call to the method with the given name or alias.
+///
+/// This is the classification to pass to
+/// [`RequestsSubscriptions::queue_client_request`](super::requests_subscriptions::RequestsSubscriptions::queue_client_request)
+/// in the absence of a better knowledge of the cost of each method. API users are free to use
+/// a different classification.
+///
+/// Calls that start a subscription are considered as cheap. The cost of generating the
+/// notifications of the subscription isn't accounted for here.
+///
+/// Names that don't correspond to any method are considered as cheap, as answering them
+/// consists in returning an error.
+pub fn method_cost(method_name: &str) -> MethodCost {
+    match method_name {
+        "chain_getBlock"
+        | "childstate_getKeys"
+        | "payment_queryInfo"
+        | "state_call"
+        | "state_callAt"
+        | "state_getKeys"
+        | "state_getKeysPaged"
+        | "state_getKeysPagedAt"
+        | "state_getMetadata"
+        | "state_getPairs"
+        | "state_getReadProof"
+        | "state_queryStorage"
+        | "state_queryStorageAt"
+        | "system_dryRun"
+        | "system_dryRunAt"
+        | "chainHead_unstable_body"
+        | "chainHead_unstable_call"
+        | "chainHead_unstable_storage" => MethodCost::Expensive,
+        _ => MethodCost::Cheap,
+    }
+}
+
 /// Error produced by [`parse_json_call`].
 #[derive(Debug, derive_more::Display)]
 pub enum ParseError<'a> {
@@ -181,6 +227,14 @@ macro_rules! define_methods {
                 [$(stringify!($name)),*].iter().copied()
             }
 
+            /// Returns a list of RPC method names of all the methods in the enum, including the
+            /// alternative names under which some of the methods can also be called.
+            ///
+            /// This is the list of names that [`parse_json_call`] recognizes.
+            pub fn method_names_and_aliases() -> impl Iterator<Item = &'static str> {
+                [$(stringify!($name) $($(, stringify!($alias))*)*),*].iter().copied()
+            }
+
             /// Returns the name of the method.
             pub fn name(&self) -> &'static str {
                 match self {
@@ -895,6 +949,7 @@ pub enum SystemPeerRole {
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum TransactionStatus {
     Future,
     Ready,
@@ -1039,3 +1094,122 @@ where
 {
     serde::Serialize::serialize(&format!("0x{:x}", *num), serializer)
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn rpc_methods_contains_all_methods() {
+        let response = super::Response::rpc_methods(super::RpcMethods {
+            version: 1,
+            methods: super::MethodCall::method_names_and_aliases()
+                .map(|n| n.into())
+                .collect(),
+        })
+        .to_json_response("5");
+
+        let listed = serde_json::from_str::<serde_json::Value>(&response).unwrap()["result"]
+            ["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m.as_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+
+        for method in super::MethodCall::method_names() {
+            assert!(listed.iter().any(|m| m == method), "{}", method);
+        }
+
+        for method in &listed {
+            // Every listed method must be dispatchable, in the sense that it is recognized no
+            // matter whether the parameters are correct.
+            let call = format!(
+                r#"{{"jsonrpc":"2.0","id":1,"method":"{}","params":[]}}"#,
+                method
+            );
+            assert!(
+                !matches!(
+                    super::parse_json_call(&call),
+                    Err(super::ParseError::Method {
+                        error: super::MethodError::UnknownMethod(_),
+                        ..
+                    })
+                ),
+                "{}",
+                method
+            );
+
+            // Subscriptions are listed alongside with their unsubscription counterpart.
+            if method.contains("_subscribe") {
+                let unsubscribe = method.replace("_subscribe", "_unsubscribe");
+                assert!(listed.contains(&unsubscribe), "{}", unsubscribe);
+            }
+        }
+    }
+
+    #[test]
+    fn method_cost() {
+        assert_eq!(
+            super::method_cost("chain_getHeader"),
+            super::MethodCost::Cheap
+        );
+        assert_eq!(
+            super::method_cost("chain_subscribeNewHeads"),
+            super::MethodCost::Cheap
+        );
+        assert_eq!(
+            super::method_cost("state_getPairs"),
+            super::MethodCost::Expensive
+        );
+        assert_eq!(super::method_cost("unknown"), super::MethodCost::Cheap);
+    }
+
+    #[test]
+    fn author_extrinsic_update_sequence() {
+        let sequence = [
+            super::TransactionStatus::Ready,
+            super::TransactionStatus::Broadcast(vec!["12D3KooW".to_owned()]),
+            super::TransactionStatus::InBlock(super::HashHexString([1; 32])),
+            super::TransactionStatus::Retracted(super::HashHexString([1; 32])),
+            super::TransactionStatus::InBlock(super::HashHexString([2; 32])),
+            super::TransactionStatus::Finalized(super::HashHexString([2; 32])),
+        ];
+
+        let notifications = sequence
+            .iter()
+            .map(|status| {
+                let notification = super::ServerToClient::author_extrinsicUpdate {
+                    subscription: "7".into(),
+                    result: status.clone(),
+                }
+                .to_json_call_object_parameters(None);
+                let notification =
+                    serde_json::from_str::<serde_json::Value>(&notification).unwrap();
+                assert_eq!(notification["method"], "author_extrinsicUpdate");
+                assert_eq!(notification["params"]["subscription"], "7");
+                notification["params"]["result"].clone()
+            })
+            .collect::<Vec<_>>();
+
+        let hash1 = format!("0x{}", "01".repeat(32));
+        let hash2 = format!("0x{}", "02".repeat(32));
+        assert_eq!(
+            notifications,
+            vec![
+                serde_json::json!("ready"),
+                serde_json::json!({ "broadcast": ["12D3KooW"] }),
+                serde_json::json!({ "inBlock": hash1 }),
+                serde_json::json!({ "retracted": hash1 }),
+                serde_json::json!({ "inBlock": hash2 }),
+                serde_json::json!({ "finalized": hash2 }),
+            ]
+        );
+
+        // Terminal states without data.
+        for (status, expected) in [
+            (super::TransactionStatus::Dropped, "dropped"),
+            (super::TransactionStatus::Invalid, "invalid"),
+        ] {
+            assert_eq!(serde_json::to_value(&status).unwrap(), expected);
+        }
+    }
+}
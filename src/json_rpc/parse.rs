@@ -17,11 +17,15 @@
 
 //! Parse JSON-RPC method calls and notifications, and build responses messages.
 
-use alloc::{borrow::Cow, string::String};
+use alloc::{borrow::Cow, string::String, vec::Vec};
 
 /// Parses a JSON-encoded RPC method call or notification.
+///
+/// No limit is enforced on the size or the complexity of `call_json`. Use
+/// [`parse_call_with_limits`] when `call_json` comes from an untrusted source.
 pub fn parse_call(call_json: &str) -> Result<Call, ParseError> {
-    let serde_call: SerdeCall = serde_json::from_str(call_json).map_err(ParseError)?;
+    let serde_call: SerdeCall =
+        serde_json::from_str(call_json).map_err(|err| ParseError(ParseErrorInner::Json(err)))?;
 
     if let Some(id) = &serde_call.id {
         // Because of https://github.com/serde-rs/json/issues/742, we can't use ̀`&str`.
@@ -34,7 +38,7 @@ pub fn parse_call(call_json: &str) -> Result<Call, ParseError> {
         }
 
         if let Err(err) = serde_json::from_str::<SerdeId>(id.get()) {
-            return Err(ParseError(err));
+            return Err(ParseError(ParseErrorInner::Json(err)));
         }
     }
 
@@ -45,6 +49,120 @@ pub fn parse_call(call_json: &str) -> Result<Call, ParseError> {
     })
 }
 
+/// Limits enforced by [`parse_call_with_limits`] and [`parse_batch`].
+///
+/// These limits protect against malicious JSON-RPC clients that send enormous or deeply-nested
+/// requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Maximum number of bytes of the JSON-encoded request.
+    pub max_request_bytes: usize,
+
+    /// Maximum number of objects and arrays that can be nested within each other. The request
+    /// object itself counts as one level, and a batch counts as one additional level.
+    pub max_nesting_depth: usize,
+
+    /// Maximum number of calls in a batch.
+    pub max_batch_len: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_request_bytes: 16 * 1024 * 1024,
+            max_nesting_depth: 64,
+            max_batch_len: 256,
+        }
+    }
+}
+
+/// Same as [`parse_call`], but first makes sure that `call_json` is within the given limits.
+///
+/// Batches are refused with an error. Use [`parse_batch`] in order to accept batches.
+pub fn parse_call_with_limits<'a>(
+    call_json: &'a str,
+    limits: &ParseLimits,
+) -> Result<Call<'a>, ParseError> {
+    check_limits(call_json, limits)?;
+    parse_call(call_json)
+}
+
+/// Parses a JSON-encoded batch of RPC method calls or notifications, or a single call, after
+/// having made sure that `batch_json` is within the given limits.
+///
+/// On success, contains one element per call of the batch. Each element is the result of
+/// parsing the call, as each call of a batch must individually be answered with an error if it
+/// is invalid. A single call that isn't part of a batch results in a list of one element.
+///
+/// An error is returned if the batch as a whole is invalid, for example if it isn't valid JSON,
+/// doesn't respect the limits, or is empty.
+pub fn parse_batch<'a>(
+    batch_json: &'a str,
+    limits: &ParseLimits,
+) -> Result<Vec<Result<Call<'a>, ParseError>>, ParseError> {
+    if !check_limits(batch_json, limits)? {
+        return Ok(vec![parse_call(batch_json)]);
+    }
+
+    let calls: Vec<&'a serde_json::value::RawValue> =
+        serde_json::from_str(batch_json).map_err(|err| ParseError(ParseErrorInner::Json(err)))?;
+    if calls.is_empty() {
+        return Err(ParseError(ParseErrorInner::EmptyBatch));
+    }
+
+    Ok(calls
+        .into_iter()
+        .map(|call| parse_call(call.get()))
+        .collect())
+}
+
+/// Makes sure that `json` respects the given limits, without allocating and without fully
+/// parsing it. Returns `true` if `json` is a batch.
+fn check_limits(json: &str, limits: &ParseLimits) -> Result<bool, ParseError> {
+    if json.len() > limits.max_request_bytes {
+        return Err(ParseError(ParseErrorInner::TooLarge));
+    }
+
+    let is_batch = json.trim_start().starts_with('[');
+
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut num_batch_separators = 0usize;
+
+    for byte in json.bytes() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                if depth > limits.max_nesting_depth {
+                    return Err(ParseError(ParseErrorInner::TooDeep));
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            b',' if is_batch && depth == 1 => {
+                num_batch_separators += 1;
+                if num_batch_separators >= limits.max_batch_len {
+                    return Err(ParseError(ParseErrorInner::BatchTooLong));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(is_batch)
+}
+
 /// Builds a JSON call.
 ///
 /// `method` must be the name of the method to call. `params_json` must be the JSON-formatted
@@ -77,7 +195,37 @@ pub struct Call<'a> {
 
 /// Error while parsing a call.
 #[derive(Debug, derive_more::Display)]
-pub struct ParseError(serde_json::Error);
+pub struct ParseError(ParseErrorInner);
+
+impl ParseError {
+    /// Returns the error that should be reported to the JSON-RPC client.
+    pub fn error_response(&self) -> ErrorResponse<'static> {
+        match self.0 {
+            ParseErrorInner::Json(_) => ErrorResponse::ParseError,
+            ParseErrorInner::TooLarge
+            | ParseErrorInner::TooDeep
+            | ParseErrorInner::BatchTooLong
+            | ParseErrorInner::EmptyBatch => ErrorResponse::InvalidRequest,
+        }
+    }
+}
+
+#[derive(Debug, derive_more::Display)]
+enum ParseErrorInner {
+    #[display(fmt = "{}", _0)]
+    Json(serde_json::Error),
+    /// See [`ParseLimits::max_request_bytes`].
+    #[display(fmt = "Request is too large")]
+    TooLarge,
+    /// See [`ParseLimits::max_nesting_depth`].
+    #[display(fmt = "Request is too deeply nested")]
+    TooDeep,
+    /// See [`ParseLimits::max_batch_len`].
+    #[display(fmt = "Batch contains too many calls")]
+    BatchTooLong,
+    #[display(fmt = "Batch is empty")]
+    EmptyBatch,
+}
 
 /// Builds a JSON response.
 ///
@@ -379,4 +527,100 @@ mod tests {
             super::parse_call(r#"{"jsonrpc":"2.0","id":{},"method":"foo","params":[]}"#).is_err()
         );
     }
+
+    #[test]
+    fn limits_too_large() {
+        let limits = super::ParseLimits {
+            max_request_bytes: 32,
+            ..Default::default()
+        };
+        let err = super::parse_call_with_limits(
+            r#"{"jsonrpc":"2.0","id":5,"method":"foo","params":[]}"#,
+            &limits,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.error_response(),
+            super::ErrorResponse::InvalidRequest
+        ));
+    }
+
+    #[test]
+    fn limits_too_deep() {
+        let limits = super::ParseLimits {
+            max_nesting_depth: 4,
+            ..Default::default()
+        };
+
+        let call = super::parse_call_with_limits(
+            r#"{"jsonrpc":"2.0","id":5,"method":"foo","params":[[{}]]}"#,
+            &limits,
+        )
+        .unwrap();
+        assert_eq!(call.params_json, "[[{}]]");
+
+        let err = super::parse_call_with_limits(
+            r#"{"jsonrpc":"2.0","id":5,"method":"foo","params":[[{"a":{}}]]}"#,
+            &limits,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.error_response(),
+            super::ErrorResponse::InvalidRequest
+        ));
+
+        // Brackets within strings aren't taken into account.
+        assert!(super::parse_call_with_limits(
+            r#"{"jsonrpc":"2.0","id":"[[[[[\"{{{{","method":"foo","params":[]}"#,
+            &limits,
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn limits_batch() {
+        let limits = super::ParseLimits {
+            max_batch_len: 3,
+            ..Default::default()
+        };
+        let call = r#"{"jsonrpc":"2.0","id":5,"method":"foo","params":[1,2,3,4,5]}"#;
+
+        let batch_json = format!("[{},{},{}]", call, call, call);
+        let batch = super::parse_batch(&batch_json, &limits).unwrap();
+        assert_eq!(batch.len(), 3);
+        assert!(batch
+            .iter()
+            .all(|call| call.as_ref().unwrap().method == "foo"));
+
+        let err = super::parse_batch(&format!("[{},{},{},{}]", call, call, call, call), &limits)
+            .unwrap_err();
+        assert!(matches!(
+            err.error_response(),
+            super::ErrorResponse::InvalidRequest
+        ));
+
+        // Batches are refused by `parse_call_with_limits`.
+        assert!(super::parse_call_with_limits(&format!("[{}]", call), &limits).is_err());
+
+        // Invalid calls within a batch are reported individually.
+        let batch_json = format!("[{},5]", call);
+        let batch = super::parse_batch(&batch_json, &limits).unwrap();
+        assert!(batch[0].is_ok());
+        assert!(batch[1].is_err());
+
+        // Single calls and empty batches.
+        assert_eq!(super::parse_batch(call, &limits).unwrap().len(), 1);
+        assert!(matches!(
+            super::parse_batch("[]", &limits)
+                .unwrap_err()
+                .error_response(),
+            super::ErrorResponse::InvalidRequest
+        ));
+        assert!(matches!(
+            super::parse_batch("[", &limits)
+                .unwrap_err()
+                .error_response(),
+            super::ErrorResponse::ParseError
+        ));
+    }
 }
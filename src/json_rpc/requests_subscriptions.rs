@@ -86,6 +86,19 @@
 //! enough, back-pressure will be applied onto [`RequestsSubscriptions::queue_client_request`],
 //! which in turn applies back-pressure onto the JSON-RPC clients.
 //!
+//! Each request is queued alongside with its [`methods::MethodCost`], which is decided by the
+//! API user, for example with [`methods::method_cost`]. Cheap requests are returned by
+//! [`RequestsSubscriptions::next_request`] before expensive requests, even if they have been
+//! queued later. This prevents a backlog of expensive requests from delaying cheap requests.
+//! In order for expensive requests to not be starved by a continuous flow of cheap requests,
+//! an expensive request is returned after [`Config::max_consecutive_cheap_requests`] cheap
+//! requests have been returned in a row. The total number of queued requests remains bounded by
+//! [`Config::max_requests_per_client`].
+//!
+//! The number of requests of each cost and the number of notifications sent back to each client
+//! are reported by [`RequestsSubscriptions::client_stats`]. Notifications are accounted
+//! separately from the request that has started their subscription.
+//!
 //! ## Subscriptions
 //!
 //! If a client-sent request requires starting a subscription, one of the
@@ -115,10 +128,12 @@ use core::{
     cmp, fmt, hash,
     num::NonZeroU32,
     ops,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
 };
 use futures::lock::Mutex;
 
+use super::methods;
+
 #[derive(Clone)]
 pub struct ClientId(u64, Weak<ClientInner>);
 
@@ -141,6 +156,11 @@ pub struct Config {
     /// Maximum number of clients that can be added at the same time. Any additional client will
     /// be rejected.
     pub max_clients: u32,
+
+    /// Maximum number of requests whose cost is [`methods::MethodCost::Cheap`] that
+    /// [`RequestsSubscriptions::next_request`] returns in a row while requests whose cost is
+    /// [`methods::MethodCost::Expensive`] are waiting.
+    pub max_consecutive_cheap_requests: NonZeroU32,
 }
 
 pub struct RequestsSubscriptions {
@@ -156,8 +176,25 @@ pub struct RequestsSubscriptions {
     /// using [`RequestsSubscriptions::set_max_clients`], in which case it would be impossible
     /// to update the size of this list.
     // TODO: what about entries of obsolete clients clogging the queue? how do we deal with this?
+    ///
+    /// Only contains requests whose [`methods::MethodCost`] is [`methods::MethodCost::Cheap`].
+    /// See also [`RequestsSubscriptions::unpulled_expensive_requests`].
     unpulled_requests: crossbeam_queue::SegQueue<(String, Weak<ClientInner>)>,
 
+    /// Same as [`RequestsSubscriptions::unpulled_requests`], but for requests whose
+    /// [`methods::MethodCost`] is [`methods::MethodCost::Expensive`]. Requests in this list are
+    /// only pulled if [`RequestsSubscriptions::unpulled_requests`] is empty or if
+    /// [`RequestsSubscriptions::consecutive_cheap_requests`] has reached
+    /// [`RequestsSubscriptions::max_consecutive_cheap_requests`].
+    unpulled_expensive_requests: crossbeam_queue::SegQueue<(String, Weak<ClientInner>)>,
+
+    /// Number of requests pulled from [`RequestsSubscriptions::unpulled_requests`] since the
+    /// latest request pulled from [`RequestsSubscriptions::unpulled_expensive_requests`].
+    consecutive_cheap_requests: AtomicUsize,
+
+    /// See [`Config::max_consecutive_cheap_requests`].
+    max_consecutive_cheap_requests: usize,
+
     /// Event notified whenever an element is pushed to [`RequestsSubscriptions::unpulled_requests`].
     new_unpulled_request: event_listener::Event,
 
@@ -197,6 +234,9 @@ impl RequestsSubscriptions {
             usize::try_from(config.max_subscriptions_per_client).unwrap_or(usize::max_value());
         let max_requests_per_client =
             usize::try_from(config.max_requests_per_client.get()).unwrap_or(usize::max_value());
+        let max_consecutive_cheap_requests =
+            usize::try_from(config.max_consecutive_cheap_requests.get())
+                .unwrap_or(usize::max_value());
 
         Self {
             clients: Mutex::new(Clients {
@@ -204,6 +244,9 @@ impl RequestsSubscriptions {
                 next_id: 0,
             }),
             unpulled_requests: crossbeam_queue::SegQueue::new(),
+            unpulled_expensive_requests: crossbeam_queue::SegQueue::new(),
+            consecutive_cheap_requests: AtomicUsize::new(0),
+            max_consecutive_cheap_requests,
             new_unpulled_request: event_listener::Event::new(),
             next_request_id: atomic::Atomic::new(0),
             next_subscription_id: atomic::Atomic::new(0),
@@ -244,6 +287,7 @@ impl RequestsSubscriptions {
             total_requests_in_fly_dec_or_dead: event_listener::Event::new(),
             dead: AtomicBool::new(false),
             total_requests_in_fly: AtomicUsize::new(0),
+            stats: ClientStatsCounters::default(),
             guarded: Mutex::new(ClientInnerGuarded {
                 pending_requests: hashbrown::HashSet::with_capacity_and_hasher(
                     self.max_requests_per_client,
@@ -284,6 +328,7 @@ impl RequestsSubscriptions {
             total_requests_in_fly_dec_or_dead: event_listener::Event::new(),
             dead: AtomicBool::new(false),
             total_requests_in_fly: AtomicUsize::new(0),
+            stats: ClientStatsCounters::default(),
             guarded: Mutex::new(ClientInnerGuarded {
                 pending_requests: hashbrown::HashSet::with_capacity_and_hasher(
                     self.max_requests_per_client,
@@ -403,6 +448,7 @@ impl RequestsSubscriptions {
 
                 match guarded_lock.responses_send_back.pop_front() {
                     Some(ResponseSendBack::Response(message)) => {
+                        client.stats.responses.fetch_add(1, Ordering::Relaxed);
                         let _new_val = client.total_requests_in_fly.fetch_sub(1, Ordering::Release);
                         debug_assert_ne!(_new_val, usize::max_value()); // Check for underflows
                         client
@@ -415,6 +461,7 @@ impl RequestsSubscriptions {
                             .notification_messages
                             .remove(&(sub_id, index))
                             .unwrap();
+                        client.stats.notifications.fetch_add(1, Ordering::Relaxed);
                         guarded_lock
                             .notification_messages_popped_or_dead
                             .notify_additional(1);
@@ -464,8 +511,16 @@ impl RequestsSubscriptions {
     /// Slots in the queue of requests are only reclaimed after
     /// [`RequestsSubscriptions::next_response`] has returned a response to a previous request.
     ///
+    /// The `cost` determines the order in which requests are returned by
+    /// [`RequestsSubscriptions::next_request`]. See [the module-level documentation](self).
+    ///
     /// Has no effect if the [`ClientId`] is stale or invalid.
-    pub async fn queue_client_request(&self, client: &ClientId, request: String) {
+    pub async fn queue_client_request(
+        &self,
+        client: &ClientId,
+        request: String,
+        cost: methods::MethodCost,
+    ) {
         let client = match client.1.upgrade() {
             Some(c) => c,
             None => return,
@@ -522,7 +577,7 @@ impl RequestsSubscriptions {
         // We can now insert the request.
         // Note that it is possible for `client.dead` to have become true in the meanwhile, but
         // this is not a problem as `unpulled_requests` is allowed to contain obsolete requests.
-        self.unpulled_requests
+        self.unpulled_requests_queue(cost)
             .push((request, Arc::downgrade(&client)));
         self.new_unpulled_request.notify_additional(1);
     }
@@ -538,6 +593,7 @@ impl RequestsSubscriptions {
         &self,
         client: &ClientId,
         request: String,
+        cost: methods::MethodCost,
     ) -> Result<(), TryQueueClientRequestError> {
         let client = match client.1.upgrade() {
             Some(c) => c,
@@ -570,7 +626,7 @@ impl RequestsSubscriptions {
         // We can now insert the request.
         // Note that it is possible for `client.dead` to have become true in the meanwhile, but
         // this is not a problem as `unpulled_requests` is allowed to contain obsolete requests.
-        self.unpulled_requests
+        self.unpulled_requests_queue(cost)
             .push((request, Arc::downgrade(&client)));
         self.new_unpulled_request.notify_additional(1);
         Ok(())
@@ -586,7 +642,7 @@ impl RequestsSubscriptions {
     pub async fn next_request(&self) -> (String, RequestId) {
         // Try to pull a request from the queue. If there is none, wait for
         // `new_unpulled_request`.
-        let (request_message, client) = loop {
+        let (request_message, cost, client) = loop {
             // Because `new_unpulled_request` is notified *after* new items are pushed to the queue,
             // we *must* check the queue after calling `new_unpulled_request.listen()` and before
             // sleeping.
@@ -600,8 +656,27 @@ impl RequestsSubscriptions {
             // - Try pull from queue again (mandatory to prevent race conditions).
             // - Actually wait for the notification, and jump back to step 1.
             let mut sleep_until = None;
-            let (request_message, client) = loop {
-                if let Some(item) = self.unpulled_requests.pop() {
+            let (request_message, cost, client) = loop {
+                // Cheap requests are pulled first, unless too many of them have been pulled in
+                // a row.
+                let cheap_first = self.consecutive_cheap_requests.load(Ordering::Relaxed)
+                    < self.max_consecutive_cheap_requests;
+                let pull_cheap = || {
+                    self.unpulled_requests
+                        .pop()
+                        .map(|(rq, client)| (rq, methods::MethodCost::Cheap, client))
+                };
+                let pull_expensive = || {
+                    self.unpulled_expensive_requests
+                        .pop()
+                        .map(|(rq, client)| (rq, methods::MethodCost::Expensive, client))
+                };
+                let item = if cheap_first {
+                    pull_cheap().or_else(pull_expensive)
+                } else {
+                    pull_expensive().or_else(pull_cheap)
+                };
+                if let Some(item) = item {
                     break item;
                 }
 
@@ -616,11 +691,26 @@ impl RequestsSubscriptions {
             // if not throw away the entry and pull another one.
             if let Some(client) = client.upgrade() {
                 if !client.dead.load(Ordering::Relaxed) {
-                    break (request_message, client);
+                    break (request_message, cost, client);
                 }
             }
         };
 
+        match cost {
+            methods::MethodCost::Cheap => {
+                self.consecutive_cheap_requests
+                    .fetch_add(1, Ordering::Relaxed);
+                client.stats.cheap_requests.fetch_add(1, Ordering::Relaxed);
+            }
+            methods::MethodCost::Expensive => {
+                self.consecutive_cheap_requests.store(0, Ordering::Relaxed);
+                client
+                    .stats
+                    .expensive_requests
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
         // Allocate a new identifier for this request.
         let request_id_num = self.next_request_id.fetch_add(1, Ordering::Relaxed);
 
@@ -637,6 +727,35 @@ impl RequestsSubscriptions {
         (request_message, request_id)
     }
 
+    /// Returns the queue where to push a request of the given cost.
+    fn unpulled_requests_queue(
+        &self,
+        cost: methods::MethodCost,
+    ) -> &crossbeam_queue::SegQueue<(String, Weak<ClientInner>)> {
+        match cost {
+            methods::MethodCost::Cheap => &self.unpulled_requests,
+            methods::MethodCost::Expensive => &self.unpulled_expensive_requests,
+        }
+    }
+
+    /// Returns statistics about the requests and notifications of the given client since it has
+    /// been added.
+    ///
+    /// Returns `None` if the [`ClientId`] is stale or invalid.
+    pub fn client_stats(&self, client: &ClientId) -> Option<ClientStats> {
+        let client = client.1.upgrade()?;
+        if client.dead.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        Some(ClientStats {
+            cheap_requests: client.stats.cheap_requests.load(Ordering::Relaxed),
+            expensive_requests: client.stats.expensive_requests.load(Ordering::Relaxed),
+            responses: client.stats.responses.load(Ordering::Relaxed),
+            notifications: client.stats.notifications.load(Ordering::Relaxed),
+        })
+    }
+
     /// Sets the response to a request previously returned by
     /// [`RequestsSubscriptions::next_request`].
     ///
@@ -956,6 +1075,25 @@ impl RequestsSubscriptions {
     }
 }
 
+/// Statistics about a client. See [`RequestsSubscriptions::client_stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientStats {
+    /// Number of requests of cost [`methods::MethodCost::Cheap`] returned by
+    /// [`RequestsSubscriptions::next_request`].
+    pub cheap_requests: u64,
+    /// Number of requests of cost [`methods::MethodCost::Expensive`] returned by
+    /// [`RequestsSubscriptions::next_request`].
+    pub expensive_requests: u64,
+    /// Number of responses to requests returned by [`RequestsSubscriptions::next_response`].
+    pub responses: u64,
+    /// Number of subscription notifications returned by [`RequestsSubscriptions::next_response`].
+    ///
+    /// Requests that start a subscription are accounted for in
+    /// [`ClientStats::cheap_requests`] or [`ClientStats::expensive_requests`], while the
+    /// notifications of this subscription are accounted for here.
+    pub notifications: u64,
+}
+
 /// Error returned by [`RequestsSubscriptions::try_queue_client_request`].
 #[derive(Debug, derive_more::Display, Clone)]
 #[display(fmt = "Queue of unpulled requests full")]
@@ -1011,6 +1149,19 @@ struct ClientInner {
     ///
     /// All listeners are also notified when [`ClientInner::dead`] is set to `true`.
     total_requests_in_fly_dec_or_dead: event_listener::Event,
+
+    /// Counters returned by [`RequestsSubscriptions::client_stats`]. Atomic so that they can be
+    /// updated without locking [`ClientInner::guarded`].
+    stats: ClientStatsCounters,
+}
+
+/// See [`ClientInner::stats`] and [`ClientStats`].
+#[derive(Default)]
+struct ClientStatsCounters {
+    cheap_requests: AtomicU64,
+    expensive_requests: AtomicU64,
+    responses: AtomicU64,
+    notifications: AtomicU64,
 }
 
 struct ClientInnerGuarded {
@@ -1122,3 +1273,139 @@ macro_rules! traits_impl {
 traits_impl!(ClientId);
 traits_impl!(RequestId);
 traits_impl!(SubscriptionId);
+
+#[cfg(test)]
+mod tests {
+    use super::methods::MethodCost;
+    use core::num::NonZeroU32;
+
+    fn state_machine(max_consecutive_cheap_requests: u32) -> super::RequestsSubscriptions {
+        super::RequestsSubscriptions::new(super::Config {
+            max_requests_per_client: NonZeroU32::new(8).unwrap(),
+            max_subscriptions_per_client: 8,
+            max_clients: 1,
+            max_consecutive_cheap_requests: NonZeroU32::new(max_consecutive_cheap_requests)
+                .unwrap(),
+        })
+    }
+
+    #[test]
+    fn cheap_requests_pulled_first() {
+        futures::executor::block_on(async {
+            let state_machine = state_machine(8);
+
+            let client = state_machine.add_client().await.unwrap();
+            for (request, cost) in [
+                ("expensive1", MethodCost::Expensive),
+                ("expensive2", MethodCost::Expensive),
+                ("cheap1", MethodCost::Cheap),
+                ("cheap2", MethodCost::Cheap),
+                ("cheap3", MethodCost::Cheap),
+            ] {
+                state_machine
+                    .queue_client_request(&client, request.to_owned(), cost)
+                    .await;
+            }
+
+            let mut pulled = Vec::new();
+            for _ in 0..5 {
+                pulled.push(state_machine.next_request().await.0);
+            }
+
+            assert_eq!(
+                pulled,
+                vec!["cheap1", "cheap2", "cheap3", "expensive1", "expensive2"]
+            );
+        });
+    }
+
+    #[test]
+    fn expensive_requests_not_starved() {
+        futures::executor::block_on(async {
+            let state_machine = state_machine(2);
+
+            let client = state_machine.add_client().await.unwrap();
+            for (request, cost) in [
+                ("expensive1", MethodCost::Expensive),
+                ("expensive2", MethodCost::Expensive),
+                ("cheap1", MethodCost::Cheap),
+                ("cheap2", MethodCost::Cheap),
+                ("cheap3", MethodCost::Cheap),
+                ("cheap4", MethodCost::Cheap),
+                ("cheap5", MethodCost::Cheap),
+            ] {
+                state_machine
+                    .queue_client_request(&client, request.to_owned(), cost)
+                    .await;
+            }
+
+            let mut pulled = Vec::new();
+            for _ in 0..7 {
+                pulled.push(state_machine.next_request().await.0);
+            }
+
+            assert_eq!(
+                pulled,
+                vec![
+                    "cheap1",
+                    "cheap2",
+                    "expensive1",
+                    "cheap3",
+                    "cheap4",
+                    "expensive2",
+                    "cheap5"
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn notifications_accounted_separately() {
+        futures::executor::block_on(async {
+            let state_machine = state_machine(8);
+
+            let client = state_machine.add_client().await.unwrap();
+            state_machine
+                .queue_client_request(&client, "subscribe".to_owned(), MethodCost::Cheap)
+                .await;
+            state_machine
+                .queue_client_request(&client, "expensive".to_owned(), MethodCost::Expensive)
+                .await;
+
+            let (_, subscribe_request) = state_machine.next_request().await;
+            let (_, expensive_request) = state_machine.next_request().await;
+            let subscription = state_machine
+                .start_subscription(&subscribe_request, 4)
+                .await
+                .unwrap();
+            state_machine
+                .respond(&subscribe_request, "subscribed".to_owned())
+                .await;
+            state_machine
+                .respond(&expensive_request, "response".to_owned())
+                .await;
+            for notification in ["notif1", "notif2", "notif3"] {
+                state_machine
+                    .push_notification(&subscription, notification.to_owned())
+                    .await;
+            }
+
+            for _ in 0..5 {
+                let _ = state_machine.next_response(&client).await;
+            }
+
+            assert_eq!(
+                state_machine.client_stats(&client).unwrap(),
+                super::ClientStats {
+                    cheap_requests: 1,
+                    expensive_requests: 1,
+                    responses: 2,
+                    notifications: 3,
+                }
+            );
+
+            state_machine.remove_client(&client).await;
+            assert!(state_machine.client_stats(&client).is_none());
+        });
+    }
+}
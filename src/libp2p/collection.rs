@@ -119,7 +119,7 @@
 use super::connection::{established, handshake, NoiseKey};
 use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 use core::{
-    iter, mem,
+    mem,
     num::NonZeroUsize,
     ops::{Add, Sub},
     pin::Pin,
@@ -157,6 +157,10 @@ pub struct Config {
     /// and must be aborted.
     pub handshake_timeout: Duration,
 
+    /// Amount of time after which an inbound substream whose protocol hasn't been negotiated
+    /// is considered to have taken too long and is reset.
+    pub inbound_negotiation_timeout: Duration,
+
     /// Name of the ping protocol on the network.
     pub ping_protocol: String,
 
@@ -190,13 +194,18 @@ pub struct NotificationProtocolConfig {
 
     /// Optional alternative names for this protocol. Can represent different versions.
     ///
-    /// Negotiated in order in which they are passed.
-    // TODO: presently unused
+    /// These names are accepted for incoming substreams, and tried in the order in which they
+    /// are passed for outgoing substreams if the remote doesn't support
+    /// [`NotificationProtocolConfig::protocol_name`].
     pub fallback_protocol_names: Vec<String>,
 
     /// Maximum size, in bytes, of the handshake that can be received.
     pub max_handshake_size: usize,
 
+    /// Maximum time the remote can take to accept an outbound substream and send back its
+    /// handshake. See [`established::ConfigNotifications::handshake_timeout`].
+    pub handshake_timeout: Duration,
+
     /// Maximum size, in bytes, of a notification that can be received.
     pub max_notification_size: usize,
 }
@@ -226,6 +235,9 @@ pub struct Network<TConn, TNow> {
     /// See [`Config::handshake_timeout`].
     handshake_timeout: Duration,
 
+    /// See [`Config::inbound_negotiation_timeout`].
+    inbound_negotiation_timeout: Duration,
+
     /// See [`Config::noise_key`].
     noise_key: NoiseKey,
 
@@ -323,6 +335,7 @@ where
 
         Network {
             handshake_timeout: config.handshake_timeout,
+            inbound_negotiation_timeout: config.inbound_negotiation_timeout,
             noise_key: config.noise_key,
             notification_protocols,
             request_response_protocols: config.request_response_protocols,
@@ -363,7 +376,10 @@ where
 
         let connection_index = guarded.connections.insert(Arc::new(Mutex::new(Connection {
             connection: ConnectionInner::Handshake {
-                handshake: handshake::HealthyHandshake::new(is_initiator),
+                handshake: handshake::HealthyHandshake::new(
+                    is_initiator,
+                    guarded.randomness_seeds.gen(),
+                ),
                 randomness_seed: guarded.randomness_seeds.gen(),
                 timeout: when_connected + self.handshake_timeout,
             },
@@ -1126,18 +1142,14 @@ where
             notifications_protocols: self
                 .notification_protocols
                 .iter()
-                .flat_map(|net| {
-                    let max_handshake_size = net.config.max_handshake_size;
-                    let max_notification_size = net.config.max_notification_size;
-                    iter::once(&net.config.protocol_name)
-                        .chain(net.config.fallback_protocol_names.iter())
-                        .map(move |name| {
-                            established::ConfigNotifications {
-                                name: name.clone(), // TODO: cloning :-/
-                                max_handshake_size,
-                                max_notification_size,
-                            }
-                        })
+                .map(|net| {
+                    established::ConfigNotifications {
+                        name: net.config.protocol_name.clone(), // TODO: cloning :-/
+                        fallback_names: net.config.fallback_protocol_names.clone(),
+                        max_handshake_size: net.config.max_handshake_size,
+                        handshake_timeout: net.config.handshake_timeout,
+                        max_notification_size: net.config.max_notification_size,
+                    }
                 })
                 .collect(),
             request_protocols: self.request_response_protocols.clone(),
@@ -1145,6 +1157,7 @@ where
             ping_protocol: self.ping_protocol.clone(), // TODO: cloning :-/
             ping_interval: Duration::from_secs(20),    // TODO: hardcoded
             ping_timeout: Duration::from_secs(10),     // TODO: hardcoded
+            inbound_negotiation_timeout: self.inbound_negotiation_timeout,
             first_out_ping: now.clone() + Duration::from_secs(2), // TODO: hardcoded
         }
     }
@@ -112,6 +112,8 @@ struct Inner<TNow, TRqUd, TNotifUd> {
     ping_interval: Duration,
     /// See [`Config::ping_timeout`].
     ping_timeout: Duration,
+    /// See [`Config::inbound_negotiation_timeout`].
+    inbound_negotiation_timeout: Duration,
 
     /// Buffer used for intermediary data. When it is necessary, data is first copied here before
     /// being turned into a `Vec`.
@@ -213,25 +215,32 @@ where
                     // These requests are automatically accepted.
                     // TODO: add a limit to the number of substreams
 
-                    let supported_protocols = self
-                        .inner
-                        .request_protocols
-                        .iter()
-                        .filter(|p| p.inbound_allowed)
-                        .map(|p| p.name.clone())
-                        .chain(
-                            self.inner
-                                .notifications_protocols
-                                .iter()
-                                .map(|p| p.name.clone()),
-                        )
-                        .chain(iter::once(self.inner.ping_protocol.clone()))
-                        .collect::<Vec<_>>();
+                    let supported_protocols =
+                        self.inner
+                            .request_protocols
+                            .iter()
+                            .filter(|p| p.inbound_allowed)
+                            .flat_map(|p| iter::once(&p.name).chain(p.fallback_names.iter()))
+                            .chain(
+                                self.inner.notifications_protocols.iter().flat_map(|p| {
+                                    iter::once(&p.name).chain(p.fallback_names.iter())
+                                }),
+                            )
+                            .cloned()
+                            .chain(iter::once(self.inner.ping_protocol.clone()))
+                            .collect::<Vec<_>>();
+
+                    // The substream will only be processed the next time `read_write` is called.
+                    // Make sure that this happens before the negotiation times out.
+                    let negotiation_timeout =
+                        read_write.now.clone() + self.inner.inbound_negotiation_timeout;
+                    read_write.wake_up_after(&negotiation_timeout);
 
                     self.inner
                         .yamux
                         .accept_pending_substream(Some(substream::Substream::ingoing(
                             supported_protocols,
+                            negotiation_timeout,
                         )));
                     self.encryption
                         .consume_inbound_data(yamux_decode.bytes_read);
@@ -480,7 +489,7 @@ where
                     } else if let Some(protocol_index) = inner
                         .request_protocols
                         .iter()
-                        .position(|p| p.name == protocol)
+                        .position(|p| p.matches(&protocol))
                     {
                         substream.set_inbound_ty(substream::InboundTy::Request {
                             protocol_index,
@@ -495,7 +504,7 @@ where
                     } else if let Some(protocol_index) = inner
                         .notifications_protocols
                         .iter()
-                        .position(|p| p.name == protocol)
+                        .position(|p| p.matches(&protocol))
                     {
                         substream.set_inbound_ty(substream::InboundTy::Notifications {
                             protocol_index,
@@ -634,6 +643,9 @@ where
             .yamux
             .open_substream(Some(substream::Substream::request_out(
                 self.inner.request_protocols[protocol_index].name.clone(), // TODO: clone :-/
+                self.inner.request_protocols[protocol_index]
+                    .fallback_names
+                    .clone(), // TODO: clone :-/
                 timeout,
                 if has_length_prefix {
                     Some(request)
@@ -692,7 +704,7 @@ where
         // TODO: turn this assert into something that can't panic?
         assert!(handshake.len() <= max_handshake_size);
 
-        let timeout = now + Duration::from_secs(20); // TODO:
+        let timeout = now + self.inner.notifications_protocols[protocol_index].handshake_timeout;
 
         let substream =
             self.inner
@@ -702,6 +714,9 @@ where
                     self.inner.notifications_protocols[protocol_index]
                         .name
                         .clone(), // TODO: clone :-/,
+                    self.inner.notifications_protocols[protocol_index]
+                        .fallback_names
+                        .clone(), // TODO: clone :-/
                     handshake,
                     max_handshake_size,
                     user_data,
@@ -1067,6 +1082,7 @@ impl ConnectionPrototype {
                 ping_protocol: config.ping_protocol,
                 ping_interval: config.ping_interval,
                 ping_timeout: config.ping_timeout,
+                inbound_negotiation_timeout: config.inbound_negotiation_timeout,
                 intermediary_buffer: vec![0u8; 2048].into_boxed_slice(),
             },
         }
@@ -1095,6 +1111,10 @@ pub struct Config<TNow> {
     pub ping_interval: Duration,
     /// Time after which an outgoing ping is considered failed.
     pub ping_timeout: Duration,
+    /// Maximum time the remote can take to negotiate a protocol on a substream that it has
+    /// opened. Substreams whose negotiation takes longer are reset, and an
+    /// [`Event::InboundError`] is generated.
+    pub inbound_negotiation_timeout: Duration,
     /// Entropy used for the randomness specific to this connection.
     pub randomness_seed: [u8; 16],
 }
@@ -1105,6 +1125,11 @@ pub struct ConfigRequestResponse {
     /// Name of the protocol transferred on the wire.
     pub name: String,
 
+    /// Alternative names of the protocol, in addition to [`ConfigRequestResponse::name`].
+    /// Accepted for incoming substreams, and tried in order for outgoing substreams if the
+    /// remote doesn't support [`ConfigRequestResponse::name`].
+    pub fallback_names: Vec<String>,
+
     /// Configuration related to sending out requests through this protocol.
     ///
     /// > **Note**: This is used even if `inbound_allowed` is `false` when performing outgoing
@@ -1117,6 +1142,13 @@ pub struct ConfigRequestResponse {
     pub inbound_allowed: bool,
 }
 
+impl ConfigRequestResponse {
+    /// Returns `true` if `protocol` is the name or one of the fallback names of this protocol.
+    pub fn matches(&self, protocol: &str) -> bool {
+        self.name == protocol || self.fallback_names.iter().any(|n| n == protocol)
+    }
+}
+
 /// See [`ConfigRequestResponse::inbound_config`].
 #[derive(Debug, Clone)]
 pub enum ConfigRequestResponseIn {
@@ -1135,9 +1167,26 @@ pub struct ConfigNotifications {
     /// Name of the protocol transferred on the wire.
     pub name: String,
 
+    /// Alternative names of the protocol, in addition to [`ConfigNotifications::name`].
+    /// Accepted for incoming substreams, and tried in order for outgoing substreams if the
+    /// remote doesn't support [`ConfigNotifications::name`].
+    pub fallback_names: Vec<String>,
+
     /// Maximum size, in bytes, of the handshake that can be received.
     pub max_handshake_size: usize,
 
+    /// Maximum time the remote can take to negotiate the protocol and send back its handshake
+    /// when opening an outbound substream. If this is exceeded, the opening fails with
+    /// [`NotificationsOutErr::Timeout`].
+    pub handshake_timeout: Duration,
+
     /// Maximum size, in bytes, of a notification that can be received.
     pub max_notification_size: usize,
 }
+
+impl ConfigNotifications {
+    /// Returns `true` if `protocol` is the name or one of the fallback names of this protocol.
+    pub fn matches(&self, protocol: &str) -> bool {
+        self.name == protocol || self.fallback_names.iter().any(|n| n == protocol)
+    }
+}
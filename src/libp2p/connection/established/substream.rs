@@ -41,7 +41,12 @@ pub struct Substream<TNow, TRqUd, TNotifUd> {
 // TODO: remove `protocol_index` fields?
 enum SubstreamInner<TNow, TRqUd, TNotifUd> {
     /// Protocol negotiation in progress in an incoming substream.
-    InboundNegotiating(multistream_select::InProgress<vec::IntoIter<String>, String>),
+    InboundNegotiating {
+        /// When the negotiation will time out if the remote hasn't finished it.
+        timeout: TNow,
+        /// State of the protocol negotiation.
+        negotiation: multistream_select::InProgress<vec::IntoIter<String>, String>,
+    },
     /// Protocol negotiation in an incoming substream has finished, and an
     /// [`Event::InboundNegotiated`] has been emitted. Now waiting for the remote to indicate the
     /// type of substream.
@@ -70,6 +75,9 @@ enum SubstreamInner<TNow, TRqUd, TNotifUd> {
     /// A notifications protocol has been negotiated on a substream. Either a successful handshake
     /// or an abrupt closing is now expected.
     NotificationsOutHandshakeRecv {
+        /// When the opening will time out in the absence of handshake. Same value as
+        /// [`SubstreamInner::NotificationsOutNegotiating::timeout`].
+        timeout: TNow,
         /// Buffer for the incoming handshake.
         handshake_in: leb128::FramedInProgress,
         /// Handshake payload to write out.
@@ -243,14 +251,20 @@ where
     /// can happen at any point.
     ///
     /// This flow is also true if you call [`Substream::reset`] at any point.
-    pub fn ingoing(supported_protocols: Vec<String>) -> Self {
+    ///
+    /// If the remote hasn't finished negotiating a protocol before `negotiation_timeout`, an
+    /// [`Event::InboundError`] containing [`InboundError::NegotiationTimeout`] is generated.
+    pub fn ingoing(supported_protocols: Vec<String>, negotiation_timeout: TNow) -> Self {
         let negotiation =
             multistream_select::InProgress::new(multistream_select::Config::Listener {
                 supported_protocols: supported_protocols.into_iter(),
             });
 
         Substream {
-            inner: SubstreamInner::InboundNegotiating(negotiation),
+            inner: SubstreamInner::InboundNegotiating {
+                timeout: negotiation_timeout,
+                negotiation,
+            },
         }
     }
 
@@ -259,6 +273,12 @@ where
     /// After the remote has sent back a handshake or after an error occured, an
     /// [`Event::NotificationsOutResult`] event will be generated locally.
     ///
+    /// The `timeout` covers both the protocol negotiation and the reception of the remote's
+    /// handshake.
+    ///
+    /// The protocols of `fallback_protocols` are tried, in order, if the remote doesn't support
+    /// `requested_protocol`.
+    ///
     /// If this event contains an `Ok`, then [`Substream::write_notification_unbounded`],
     /// [`Substream::notification_substream_queued_bytes`] and
     /// [`Substream::close_notifications_substream`] can be used, and
@@ -267,6 +287,7 @@ where
     pub fn notifications_out(
         timeout: TNow,
         requested_protocol: String,
+        fallback_protocols: Vec<String>,
         handshake: Vec<u8>,
         max_handshake_size: usize,
         user_data: TNotifUd,
@@ -275,6 +296,7 @@ where
 
         let negotiation = multistream_select::InProgress::new(multistream_select::Config::Dialer {
             requested_protocol,
+            fallback_protocols: fallback_protocols.into_iter(),
         });
 
         Substream {
@@ -296,8 +318,12 @@ where
     /// If the `request` is `None`, then nothing at all will be written out, not even a length
     /// prefix. If the `request` is `Some`, then a length prefix will be written out. Consequently,
     /// `Some(&[])` writes a single `0` for the request.
+    ///
+    /// The protocols of `fallback_protocols` are tried, in order, if the remote doesn't support
+    /// `requested_protocol`.
     pub fn request_out(
         requested_protocol: String,
+        fallback_protocols: Vec<String>,
         timeout: TNow,
         request: Option<Vec<u8>>,
         max_response_size: usize,
@@ -305,6 +331,7 @@ where
     ) -> Self {
         let negotiation = multistream_select::InProgress::new(multistream_select::Config::Dialer {
             requested_protocol,
+            fallback_protocols: fallback_protocols.into_iter(),
         });
 
         Substream {
@@ -333,6 +360,7 @@ where
     pub fn ping_out(ping_protocol_name: String) -> Self {
         let negotiation = multistream_select::InProgress::new(multistream_select::Config::Dialer {
             requested_protocol: ping_protocol_name,
+            fallback_protocols: Vec::new().into_iter(),
         });
 
         Substream {
@@ -390,22 +418,40 @@ where
         Option<Event<TRqUd, TNotifUd>>,
     ) {
         match self.inner {
-            SubstreamInner::InboundNegotiating(nego) => match nego.read_write(read_write) {
-                Ok(multistream_select::Negotiation::InProgress(nego)) => {
-                    (Some(SubstreamInner::InboundNegotiating(nego)), None)
+            SubstreamInner::InboundNegotiating {
+                timeout,
+                negotiation,
+            } => {
+                if timeout < read_write.now {
+                    return (
+                        None,
+                        Some(Event::InboundError(InboundError::NegotiationTimeout)),
+                    );
                 }
-                Ok(multistream_select::Negotiation::Success(protocol)) => (
-                    Some(SubstreamInner::InboundNegotiatingApiWait),
-                    Some(Event::InboundNegotiated(protocol)),
-                ),
-                Ok(multistream_select::Negotiation::NotAvailable) => {
-                    (Some(SubstreamInner::InboundFailed), None)
+
+                read_write.wake_up_after(&timeout);
+
+                match negotiation.read_write(read_write) {
+                    Ok(multistream_select::Negotiation::InProgress(negotiation)) => (
+                        Some(SubstreamInner::InboundNegotiating {
+                            timeout,
+                            negotiation,
+                        }),
+                        None,
+                    ),
+                    Ok(multistream_select::Negotiation::Success(protocol)) => (
+                        Some(SubstreamInner::InboundNegotiatingApiWait),
+                        Some(Event::InboundNegotiated(protocol)),
+                    ),
+                    Ok(multistream_select::Negotiation::NotAvailable) => {
+                        (Some(SubstreamInner::InboundFailed), None)
+                    }
+                    Err(err) => (
+                        None,
+                        Some(Event::InboundError(InboundError::NegotiationError(err))),
+                    ),
                 }
-                Err(err) => (
-                    None,
-                    Some(Event::InboundError(InboundError::NegotiationError(err))),
-                ),
-            },
+            }
             SubstreamInner::InboundNegotiatingApiWait => {
                 (Some(SubstreamInner::InboundNegotiatingApiWait), None)
             }
@@ -462,6 +508,7 @@ where
 
                         (
                             Some(SubstreamInner::NotificationsOutHandshakeRecv {
+                                timeout,
                                 handshake_in: leb128::FramedInProgress::new(max_handshake_size),
                                 handshake_out,
                                 user_data,
@@ -498,10 +545,21 @@ where
                 )
             }
             SubstreamInner::NotificationsOutHandshakeRecv {
+                timeout,
                 handshake_in,
                 mut handshake_out,
                 user_data,
             } => {
+                if timeout < read_write.now {
+                    return (
+                        Some(SubstreamInner::NotificationsOutNegotiationFailed),
+                        Some(Event::NotificationsOutResult {
+                            result: Err((NotificationsOutErr::Timeout, user_data)),
+                        }),
+                    );
+                }
+
+                read_write.wake_up_after(&timeout);
                 read_write.write_from_vec_deque(&mut handshake_out);
 
                 let incoming_buffer = match read_write.incoming_buffer {
@@ -521,6 +579,7 @@ where
                 if !handshake_out.is_empty() {
                     return (
                         Some(SubstreamInner::NotificationsOutHandshakeRecv {
+                            timeout,
                             handshake_in,
                             handshake_out,
                             user_data,
@@ -547,6 +606,7 @@ where
                         read_write.advance_read(num_read);
                         (
                             Some(SubstreamInner::NotificationsOutHandshakeRecv {
+                                timeout,
                                 handshake_in,
                                 handshake_out,
                                 user_data,
@@ -726,6 +786,16 @@ where
                             None,
                         )
                     }
+                    // The length prefix is checked before any byte of the response is buffered.
+                    // Returning `None` resets the substream, meaning that the rest of the
+                    // response is never read.
+                    Err(leb128::FramedError::MaxLengthExceeded { max_allowed }) => (
+                        None,
+                        Some(Event::Response {
+                            user_data,
+                            response: Err(RequestError::ResponseTooLarge { max_allowed }),
+                        }),
+                    ),
                     Err(err) => (
                         None,
                         Some(Event::Response {
@@ -1110,7 +1180,7 @@ where
 
     pub fn reset(self) -> Option<Event<TRqUd, TNotifUd>> {
         match self.inner {
-            SubstreamInner::InboundNegotiating(_) => None,
+            SubstreamInner::InboundNegotiating { .. } => None,
             SubstreamInner::InboundNegotiatingApiWait => None,
             SubstreamInner::InboundFailed => None,
             SubstreamInner::RequestOutNegotiating { user_data, .. }
@@ -1388,7 +1458,9 @@ where
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self.inner {
             SubstreamInner::InboundFailed => f.debug_tuple("incoming-negotiation-failed").finish(),
-            SubstreamInner::InboundNegotiating(_) => f.debug_tuple("incoming-negotiating").finish(),
+            SubstreamInner::InboundNegotiating { .. } => {
+                f.debug_tuple("incoming-negotiating").finish()
+            }
             SubstreamInner::InboundNegotiatingApiWait => {
                 f.debug_tuple("incoming-negotiated-api-wait").finish()
             }
@@ -1551,6 +1623,8 @@ pub enum InboundTy {
 pub enum InboundError {
     /// Error during protocol negotiation.
     NegotiationError(multistream_select::Error),
+    /// Remote hasn't finished negotiating a protocol in time.
+    NegotiationTimeout,
     /// Error while receiving an inbound request.
     RequestInLebError(leb128::FramedError),
     /// Unexpected end of file while receiving an inbound request.
@@ -1593,6 +1667,16 @@ pub enum RequestError {
     NegotiationError(multistream_select::Error),
     /// Error while receiving the response.
     ResponseLebError(leb128::FramedError),
+    /// Remote has announced a response larger than the maximum allowed by the protocol. The
+    /// substream has been reset without reading the response.
+    #[display(
+        fmt = "Response exceeds the maximum size of {} bytes allowed by the protocol",
+        max_allowed
+    )]
+    ResponseTooLarge {
+        /// Maximum size of the response allowed by the protocol.
+        max_allowed: usize,
+    },
 }
 
 /// Error potentially returned by [`Substream::respond_in_request`].
@@ -1627,3 +1711,139 @@ pub enum NotificationsInClosedErr {
     /// Substream has been reset.
     SubstreamReset,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, InboundError, NotificationsOutErr, RequestError, Substream};
+    use crate::libp2p::read_write::ReadWrite;
+
+    type TestSubstream = Substream<u32, (), ()>;
+
+    /// Calls [`Substream::read_write`] at the given time, with the given incoming data, and
+    /// with a large outgoing buffer.
+    fn read_write(
+        substream: TestSubstream,
+        now: u32,
+        incoming: &[u8],
+    ) -> (Option<TestSubstream>, Option<Event<(), ()>>, Option<u32>) {
+        let mut out1 = [0; 1024];
+        let mut out2 = [0; 0];
+        let mut rw = ReadWrite {
+            now,
+            incoming_buffer: Some(incoming),
+            outgoing_buffer: Some((&mut out1, &mut out2)),
+            read_bytes: 0,
+            written_bytes: 0,
+            wake_up_after: None,
+            wake_up_future: None,
+        };
+        let (substream, event) = substream.read_write(&mut rw);
+        (substream, event, rw.wake_up_after)
+    }
+
+    #[test]
+    fn inbound_negotiation_timeout() {
+        let substream = Substream::ingoing(vec!["/foo".into()], 5);
+
+        // The remote never sends anything.
+        let (substream, event, wake_up_after) = read_write(substream, 1, &[]);
+        assert!(event.is_none());
+        assert_eq!(wake_up_after, Some(5));
+
+        let (substream, event, _) = read_write(substream.unwrap(), 6, &[]);
+        assert!(substream.is_none());
+        assert!(matches!(
+            event,
+            Some(Event::InboundError(InboundError::NegotiationTimeout))
+        ));
+    }
+
+    #[test]
+    fn notifications_out_handshake_timeout() {
+        let substream =
+            Substream::notifications_out(5, "/foo".into(), Vec::new(), vec![1, 2, 3], 16, ());
+
+        // The remote accepts the protocol, but never sends back its handshake.
+        let mut incoming = vec![19];
+        incoming.extend_from_slice(b"/multistream/1.0.0\n");
+        incoming.push(5);
+        incoming.extend_from_slice(b"/foo\n");
+        let (substream, event, wake_up_after) = read_write(substream, 1, &incoming);
+        assert!(event.is_none());
+        assert_eq!(wake_up_after, Some(5));
+
+        let (substream, event, wake_up_after) = read_write(substream.unwrap(), 2, &[]);
+        assert!(event.is_none());
+        assert_eq!(wake_up_after, Some(5));
+        let substream = substream.unwrap();
+        assert_eq!(
+            format!("{:?}", substream),
+            "notifications-out-handshake-recv"
+        );
+
+        let (_, event, _) = read_write(substream, 6, &[]);
+        assert!(matches!(
+            event,
+            Some(Event::NotificationsOutResult {
+                result: Err((NotificationsOutErr::Timeout, ()))
+            })
+        ));
+    }
+
+    #[test]
+    fn request_out_response_too_large() {
+        let substream =
+            Substream::request_out("/foo".into(), Vec::new(), 5, Some(vec![1, 2, 3]), 16, ());
+
+        let mut incoming = vec![19];
+        incoming.extend_from_slice(b"/multistream/1.0.0\n");
+        incoming.push(5);
+        incoming.extend_from_slice(b"/foo\n");
+        let (substream, event, _) = read_write(substream, 1, &incoming);
+        assert!(event.is_none());
+
+        // The remote announces a response of 1000 bytes, which is above the limit. The
+        // substream is reset as soon as the length prefix has been received.
+        let (substream, event, _) = read_write(substream.unwrap(), 2, &[0xe8, 0x07]);
+        assert!(substream.is_none());
+        assert!(matches!(
+            event,
+            Some(Event::Response {
+                response: Err(RequestError::ResponseTooLarge { max_allowed: 16 }),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn request_out_fallback_protocol() {
+        let substream = Substream::request_out(
+            "/foo/2".into(),
+            vec!["/foo/1".into()],
+            5,
+            Some(vec![1, 2, 3]),
+            16,
+            (),
+        );
+
+        // The remote refuses `/foo/2` and accepts `/foo/1`.
+        let mut incoming = vec![19];
+        incoming.extend_from_slice(b"/multistream/1.0.0\n");
+        incoming.push(3);
+        incoming.extend_from_slice(b"na\n");
+        incoming.push(7);
+        incoming.extend_from_slice(b"/foo/1\n");
+        let (substream, event, _) = read_write(substream, 1, &incoming);
+        assert!(event.is_none());
+
+        let (substream, event, _) = read_write(substream.unwrap(), 2, &[0xe8, 0x07]);
+        assert!(substream.is_none());
+        assert!(matches!(
+            event,
+            Some(Event::Response {
+                response: Err(RequestError::ResponseTooLarge { max_allowed: 16 }),
+                ..
+            })
+        ));
+    }
+}
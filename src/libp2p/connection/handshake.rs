@@ -41,7 +41,7 @@ use super::{
 };
 
 use alloc::{boxed::Box, vec};
-use core::{fmt, iter};
+use core::{fmt, iter, option};
 
 mod tests;
 
@@ -64,8 +64,8 @@ pub enum Handshake {
 
 impl Handshake {
     /// Shortcut for [`HealthyHandshake::new`] wrapped in a [`Handshake`].
-    pub fn new(is_initiator: bool) -> Self {
-        HealthyHandshake::new(is_initiator).into()
+    pub fn new(is_initiator: bool, randomness_seed: [u8; 32]) -> Self {
+        HealthyHandshake::new(is_initiator, randomness_seed).into()
     }
 }
 
@@ -76,8 +76,9 @@ pub struct HealthyHandshake {
 
 enum NegotiationState {
     EncryptionProtocol {
-        negotiation: multistream_select::InProgress<iter::Once<&'static str>, &'static str>,
+        negotiation: multistream_select::InProgress<option::IntoIter<&'static str>, &'static str>,
         is_initiator: bool,
+        randomness_seed: [u8; 32],
     },
     Encryption {
         handshake: Box<noise::HandshakeInProgress>,
@@ -85,7 +86,7 @@ enum NegotiationState {
     Multiplexing {
         peer_id: PeerId,
         encryption: Box<noise::Noise>,
-        negotiation: multistream_select::InProgress<iter::Once<&'static str>, &'static str>,
+        negotiation: multistream_select::InProgress<option::IntoIter<&'static str>, &'static str>,
     },
 }
 
@@ -94,14 +95,18 @@ impl HealthyHandshake {
     ///
     /// Must pass `true` if the connection has been opened by the local machine, or `false` if it
     /// has been opened by the remote.
-    pub fn new(is_initiator: bool) -> Self {
+    ///
+    /// The randomness seed is used to generate the ephemeral key of the Noise handshake, and
+    /// must be generated using a cryptographically secure source of randomness.
+    pub fn new(is_initiator: bool, randomness_seed: [u8; 32]) -> Self {
         let negotiation = multistream_select::InProgress::new(if is_initiator {
             multistream_select::Config::Dialer {
                 requested_protocol: noise::PROTOCOL_NAME,
+                fallback_protocols: None.into_iter(),
             }
         } else {
             multistream_select::Config::Listener {
-                supported_protocols: iter::once(noise::PROTOCOL_NAME),
+                supported_protocols: Some(noise::PROTOCOL_NAME).into_iter(),
             }
         });
 
@@ -109,6 +114,7 @@ impl HealthyHandshake {
             state: NegotiationState::EncryptionProtocol {
                 negotiation,
                 is_initiator,
+                randomness_seed,
             },
         }
     }
@@ -128,6 +134,7 @@ impl HealthyHandshake {
                 NegotiationState::EncryptionProtocol {
                     negotiation,
                     is_initiator,
+                    randomness_seed,
                 } => {
                     // Earliest point of the handshake. The encryption is being negotiated.
                     // Delegating read/write to the negotiation.
@@ -141,6 +148,7 @@ impl HealthyHandshake {
                                 state: NegotiationState::EncryptionProtocol {
                                     negotiation: updated,
                                     is_initiator,
+                                    randomness_seed,
                                 },
                             }))
                         }
@@ -149,6 +157,7 @@ impl HealthyHandshake {
                             // continue. This Noise key is requested from the user.
                             Ok(Handshake::NoiseKeyRequired(NoiseKeyRequired {
                                 is_initiator,
+                                randomness_seed,
                             }))
                         }
                         multistream_select::Negotiation::NotAvailable => {
@@ -175,10 +184,11 @@ impl HealthyHandshake {
                                 multistream_select::InProgress::new(if cipher.is_initiator() {
                                     multistream_select::Config::Dialer {
                                         requested_protocol: yamux::PROTOCOL_NAME,
+                                        fallback_protocols: None.into_iter(),
                                     }
                                 } else {
                                     multistream_select::Config::Listener {
-                                        supported_protocols: iter::once(yamux::PROTOCOL_NAME),
+                                        supported_protocols: Some(yamux::PROTOCOL_NAME).into_iter(),
                                     }
                                 });
 
@@ -310,6 +320,7 @@ impl fmt::Debug for HealthyHandshake {
 /// key in order to proceed.
 pub struct NoiseKeyRequired {
     is_initiator: bool,
+    randomness_seed: [u8; 32],
 }
 
 impl NoiseKeyRequired {
@@ -320,6 +331,7 @@ impl NoiseKeyRequired {
                 handshake: Box::new(noise::HandshakeInProgress::new(
                     noise_key,
                     self.is_initiator,
+                    &self.randomness_seed,
                 )),
             },
         }
@@ -69,8 +69,13 @@ pub enum Config<I, P> {
     /// Local node is the dialing side and requests the specific protocol.
     Dialer {
         /// Name of the protocol to try negotiate. The multistream-select negotiation will
-        /// ultimately succeed if and only if the remote supports this protocol.
+        /// ultimately succeed if and only if the remote supports this protocol or one of the
+        /// protocols of [`Config::Dialer::fallback_protocols`].
         requested_protocol: P,
+        /// List of protocol names to try negotiate, in order, if the remote doesn't support
+        /// [`Config::Dialer::requested_protocol`]. In case of success, the negotiated protocol is
+        /// either [`Config::Dialer::requested_protocol`] or one of the protocols in this list.
+        fallback_protocols: I,
     },
     /// Local node is the listening side.
     Listener {
@@ -125,6 +130,9 @@ enum InProgressState<P> {
     SendProtocolRequest {
         /// Number of bytes of the request already written out.
         num_bytes_written: usize,
+        /// `true` if the handshake of the remote has already been received, in other words if
+        /// this request is sent after the remote has refused a previous one.
+        handshake_received: bool,
     },
     SendProtocolOk {
         /// Number of bytes of the response already written out.
@@ -154,7 +162,17 @@ where
     pub fn new(config: Config<I, P>) -> Self {
         // Length, in bytes, of the longest protocol name.
         let max_proto_name_len = match &config {
-            Config::Dialer { requested_protocol } => requested_protocol.as_ref().len(),
+            Config::Dialer {
+                requested_protocol,
+                fallback_protocols,
+            } => cmp::max(
+                requested_protocol.as_ref().len(),
+                fallback_protocols
+                    .clone()
+                    .map(|p| p.as_ref().len())
+                    .max()
+                    .unwrap_or(0),
+            ),
             Config::Listener {
                 supported_protocols,
             } => supported_protocols
@@ -237,6 +255,7 @@ where
                         (true, Config::Dialer { .. }) => {
                             self.state = InProgressState::SendProtocolRequest {
                                 num_bytes_written: 0,
+                                handshake_received: false,
                             }
                         }
                         (true, Config::Listener { .. }) => {
@@ -248,8 +267,11 @@ where
                 (
                     InProgressState::SendProtocolRequest {
                         mut num_bytes_written,
+                        handshake_received,
                     },
-                    Some(Config::Dialer { requested_protocol }),
+                    Some(Config::Dialer {
+                        requested_protocol, ..
+                    }),
                 ) => {
                     if read_write.outgoing_buffer.is_none() {
                         return Err(Error::WriteClosed);
@@ -263,10 +285,15 @@ where
                     let done = message.write_out(num_bytes_written, read_write);
                     num_bytes_written += read_write.written_bytes - written_before;
 
-                    if done {
+                    if done && handshake_received {
+                        self.state = InProgressState::ProtocolRequestAnswerExpected;
+                    } else if done {
                         self.state = InProgressState::HandshakeExpected;
                     } else {
-                        self.state = InProgressState::SendProtocolRequest { num_bytes_written };
+                        self.state = InProgressState::SendProtocolRequest {
+                            num_bytes_written,
+                            handshake_received,
+                        };
                         break;
                     }
                 }
@@ -472,7 +499,12 @@ where
                     }
 
                     let frame = match self.recv_buffer {
-                        leb128::Framed::Finished(f) => f,
+                        leb128::Framed::Finished(frame) => {
+                            self.recv_buffer = leb128::Framed::InProgress(
+                                leb128::FramedInProgress::new(self.max_frame_len),
+                            );
+                            frame
+                        }
                         leb128::Framed::InProgress(f) => {
                             // No frame is available.
                             debug_assert_eq!(read_write.incoming_buffer_available(), 0);
@@ -482,10 +514,13 @@ where
                         }
                     };
 
-                    // Extract `config` to get the protocol name. All the paths below return,
-                    // thereby `config` doesn't need to be put back in `self`.
-                    let requested_protocol = match cfg.take() {
-                        Some(Config::Dialer { requested_protocol }) => requested_protocol,
+                    // Extract `config` to get the protocol name. All the paths below either
+                    // return or put back a config in `self`.
+                    let (requested_protocol, mut fallback_protocols) = match cfg.take() {
+                        Some(Config::Dialer {
+                            requested_protocol,
+                            fallback_protocols,
+                        }) => (requested_protocol, fallback_protocols),
                         _ => unreachable!(),
                     };
 
@@ -496,7 +531,20 @@ where
                         // Because of the order of checks, a protocol named `na` will never be
                         // successfully negotiated. Debugging is expected to be less confusing if
                         // the negotiation always fails.
-                        return Ok(Negotiation::NotAvailable);
+                        match fallback_protocols.next() {
+                            Some(requested_protocol) => {
+                                *cfg = Some(Config::Dialer {
+                                    requested_protocol,
+                                    fallback_protocols,
+                                });
+                                self.state = InProgressState::SendProtocolRequest {
+                                    num_bytes_written: 0,
+                                    handshake_received: true,
+                                };
+                                continue;
+                            }
+                            None => return Ok(Negotiation::NotAvailable),
+                        }
                     }
                     if &frame[..frame.len() - 1] != requested_protocol.as_ref().as_bytes() {
                         return Err(Error::UnexpectedProtocolRequestAnswer);
@@ -668,6 +716,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::{super::super::read_write::ReadWrite, Config, MessageOut, Negotiation};
+    use alloc::{vec, vec::Vec};
     use core::iter;
 
     #[test]
@@ -715,108 +764,144 @@ mod tests {
         // TODO: all encoding testing
     }
 
-    #[test]
-    fn negotiation_basic_works() {
-        fn test_with_buffer_sizes(size1: usize, size2: usize) {
-            let mut negotiation1 = Negotiation::new(Config::<iter::Once<_>, _>::Dialer {
-                requested_protocol: "/foo",
-            });
-            let mut negotiation2 = Negotiation::new(Config::Listener {
-                supported_protocols: iter::once("/foo"),
-            });
-
-            let mut buf_1_to_2 = Vec::new();
-            let mut buf_2_to_1 = Vec::new();
-
-            while !matches!(
-                (&negotiation1, &negotiation2),
-                (Negotiation::Success(_), Negotiation::Success(_))
-            ) {
-                match negotiation1 {
-                    Negotiation::InProgress(nego) => {
-                        if buf_1_to_2.is_empty() {
-                            buf_1_to_2.resize(size1, 0);
-                            let mut read_write = ReadWrite {
-                                now: 0,
-                                incoming_buffer: Some(&buf_2_to_1),
-                                outgoing_buffer: Some((&mut buf_1_to_2, &mut [])),
-                                read_bytes: 0,
-                                written_bytes: 0,
-                                wake_up_after: None,
-                                wake_up_future: None,
-                            };
-                            negotiation1 = nego.read_write(&mut read_write).unwrap();
-                            let (read_bytes, written_bytes) =
-                                (read_write.read_bytes, read_write.written_bytes);
-                            for _ in 0..read_bytes {
-                                buf_2_to_1.remove(0);
-                            }
-                            buf_1_to_2.truncate(written_bytes);
-                        } else {
-                            let mut read_write = ReadWrite {
-                                now: 0,
-                                incoming_buffer: Some(&buf_2_to_1),
-                                outgoing_buffer: Some((&mut [], &mut [])),
-                                read_bytes: 0,
-                                written_bytes: 0,
-                                wake_up_after: None,
-                                wake_up_future: None,
-                            };
-                            negotiation1 = nego.read_write(&mut read_write).unwrap();
-                            for _ in 0..read_write.read_bytes {
-                                buf_2_to_1.remove(0);
-                            }
+    /// Runs a negotiation between a dialer and a listener until both sides succeed, and returns
+    /// the protocol negotiated by both sides.
+    fn negotiate<'a>(
+        dialer: Config<vec::IntoIter<&'a str>, &'a str>,
+        listener: Config<vec::IntoIter<&'a str>, &'a str>,
+        size1: usize,
+        size2: usize,
+    ) -> (&'a str, &'a str) {
+        let mut negotiation1 = Negotiation::new(dialer);
+        let mut negotiation2 = Negotiation::new(listener);
+
+        let mut buf_1_to_2 = Vec::new();
+        let mut buf_2_to_1 = Vec::new();
+
+        while !matches!(
+            (&negotiation1, &negotiation2),
+            (Negotiation::Success(_), Negotiation::Success(_))
+        ) {
+            match negotiation1 {
+                Negotiation::InProgress(nego) => {
+                    if buf_1_to_2.is_empty() {
+                        buf_1_to_2.resize(size1, 0);
+                        let mut read_write = ReadWrite {
+                            now: 0,
+                            incoming_buffer: Some(&buf_2_to_1),
+                            outgoing_buffer: Some((&mut buf_1_to_2, &mut [])),
+                            read_bytes: 0,
+                            written_bytes: 0,
+                            wake_up_after: None,
+                            wake_up_future: None,
+                        };
+                        negotiation1 = nego.read_write(&mut read_write).unwrap();
+                        let (read_bytes, written_bytes) =
+                            (read_write.read_bytes, read_write.written_bytes);
+                        for _ in 0..read_bytes {
+                            buf_2_to_1.remove(0);
+                        }
+                        buf_1_to_2.truncate(written_bytes);
+                    } else {
+                        let mut read_write = ReadWrite {
+                            now: 0,
+                            incoming_buffer: Some(&buf_2_to_1),
+                            outgoing_buffer: Some((&mut [], &mut [])),
+                            read_bytes: 0,
+                            written_bytes: 0,
+                            wake_up_after: None,
+                            wake_up_future: None,
+                        };
+                        negotiation1 = nego.read_write(&mut read_write).unwrap();
+                        for _ in 0..read_write.read_bytes {
+                            buf_2_to_1.remove(0);
                         }
                     }
-                    Negotiation::Success(_) => {}
-                    Negotiation::NotAvailable => panic!(),
                 }
+                Negotiation::Success(_) => {}
+                Negotiation::NotAvailable => panic!(),
+            }
 
-                match negotiation2 {
-                    Negotiation::InProgress(nego) => {
-                        if buf_2_to_1.is_empty() {
-                            buf_2_to_1.resize(size2, 0);
-                            let mut read_write = ReadWrite {
-                                now: 0,
-                                incoming_buffer: Some(&buf_1_to_2),
-                                outgoing_buffer: Some((&mut buf_2_to_1, &mut [])),
-                                read_bytes: 0,
-                                written_bytes: 0,
-                                wake_up_after: None,
-                                wake_up_future: None,
-                            };
-                            negotiation2 = nego.read_write(&mut read_write).unwrap();
-                            let (read_bytes, written_bytes) =
-                                (read_write.read_bytes, read_write.written_bytes);
-                            for _ in 0..read_bytes {
-                                buf_1_to_2.remove(0);
-                            }
-                            buf_2_to_1.truncate(written_bytes);
-                        } else {
-                            let mut read_write = ReadWrite {
-                                now: 0,
-                                incoming_buffer: Some(&buf_1_to_2),
-                                outgoing_buffer: Some((&mut [], &mut [])),
-                                read_bytes: 0,
-                                written_bytes: 0,
-                                wake_up_after: None,
-                                wake_up_future: None,
-                            };
-                            negotiation2 = nego.read_write(&mut read_write).unwrap();
-                            for _ in 0..read_write.read_bytes {
-                                buf_1_to_2.remove(0);
-                            }
+            match negotiation2 {
+                Negotiation::InProgress(nego) => {
+                    if buf_2_to_1.is_empty() {
+                        buf_2_to_1.resize(size2, 0);
+                        let mut read_write = ReadWrite {
+                            now: 0,
+                            incoming_buffer: Some(&buf_1_to_2),
+                            outgoing_buffer: Some((&mut buf_2_to_1, &mut [])),
+                            read_bytes: 0,
+                            written_bytes: 0,
+                            wake_up_after: None,
+                            wake_up_future: None,
+                        };
+                        negotiation2 = nego.read_write(&mut read_write).unwrap();
+                        let (read_bytes, written_bytes) =
+                            (read_write.read_bytes, read_write.written_bytes);
+                        for _ in 0..read_bytes {
+                            buf_1_to_2.remove(0);
+                        }
+                        buf_2_to_1.truncate(written_bytes);
+                    } else {
+                        let mut read_write = ReadWrite {
+                            now: 0,
+                            incoming_buffer: Some(&buf_1_to_2),
+                            outgoing_buffer: Some((&mut [], &mut [])),
+                            read_bytes: 0,
+                            written_bytes: 0,
+                            wake_up_after: None,
+                            wake_up_future: None,
+                        };
+                        negotiation2 = nego.read_write(&mut read_write).unwrap();
+                        for _ in 0..read_write.read_bytes {
+                            buf_1_to_2.remove(0);
                         }
                     }
-                    Negotiation::Success(_) => {}
-                    Negotiation::NotAvailable => panic!(),
                 }
+                Negotiation::Success(_) => {}
+                Negotiation::NotAvailable => panic!(),
             }
         }
 
-        test_with_buffer_sizes(256, 256);
-        test_with_buffer_sizes(1, 1);
-        test_with_buffer_sizes(1, 2048);
-        test_with_buffer_sizes(2048, 1);
+        match (negotiation1, negotiation2) {
+            (Negotiation::Success(p1), Negotiation::Success(p2)) => (p1, p2),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn negotiation_basic_works() {
+        for (size1, size2) in [(256, 256), (1, 1), (1, 2048), (2048, 1)] {
+            let negotiated = negotiate(
+                Config::Dialer {
+                    requested_protocol: "/foo",
+                    fallback_protocols: Vec::new().into_iter(),
+                },
+                Config::Listener {
+                    supported_protocols: vec!["/foo"].into_iter(),
+                },
+                size1,
+                size2,
+            );
+            assert_eq!(negotiated, ("/foo", "/foo"));
+        }
+    }
+
+    #[test]
+    fn negotiation_dialer_fallback() {
+        for (size1, size2) in [(256, 256), (1, 1), (1, 2048), (2048, 1)] {
+            let negotiated = negotiate(
+                Config::Dialer {
+                    requested_protocol: "/foo/2",
+                    fallback_protocols: vec!["/bar", "/foo/1"].into_iter(),
+                },
+                Config::Listener {
+                    supported_protocols: vec!["/baz", "/foo/1"].into_iter(),
+                },
+                size1,
+                size2,
+            );
+            assert_eq!(negotiated, ("/foo/1", "/foo/1"));
+        }
     }
 }
@@ -49,6 +49,13 @@
 //! [`HandshakeInProgress`], passing a [`NoiseKey`]. This [`NoiseKey`] is typically generated at
 //! startup and doesn't need to be persisted after a restart.
 //!
+//! Apart from [`NoiseKey::new`] and [`UnsignedNoiseKey::random`], which use the entropy source
+//! of the operating system, this module never generates randomness by itself. Generating a
+//! [`NoiseKey`] with [`NoiseKey::new_with_seed`] or an ephemeral key for a handshake requires a
+//! randomness seed, which must be provided by the API user, for example using the entropy source
+//! of the platform (e.g. `crypto.getRandomValues` in browsers). The same seed always produces
+//! the same keys.
+//!
 //! Use [`HandshakeInProgress::read_write`] when data is received from the wire or when the remote
 //! is ready to receive more data. At every call, a [`NoiseHandshake`] is returned, potentially
 //! indicating the end of the handshake.
@@ -69,6 +76,7 @@ use crate::libp2p::{
 use alloc::{boxed::Box, collections::VecDeque, vec, vec::Vec};
 use core::{cmp, fmt, iter};
 use prost::Message as _;
+use rand::SeedableRng as _;
 
 mod payload_proto {
     // File generated by the build script.
@@ -94,7 +102,8 @@ pub const PROTOCOL_NAME: &str = "/noise";
 /// The signature requires access to the libp2p private key. As such, there are two possible
 /// ways to create a [`NoiseKey`]:
 ///
-/// - The easier way, by passing the libp2p private key to [`NoiseKey::new`].
+/// - The easier way, by passing the libp2p private key to [`NoiseKey::new`], or to
+/// [`NoiseKey::new_with_seed`] alongside with a randomness seed.
 /// - The slightly more complex way, by first creating an [`UnsignedNoiseKey`], then passing a
 /// a signature. This second method doesn't require direct access to the private key but only
 /// to a method of signing a message, which makes it for example possible to use a hardware
@@ -110,8 +119,20 @@ pub struct NoiseKey {
 
 impl NoiseKey {
     /// Generates a new private and public key pair signed with the given libp2p ed25519 key.
+    ///
+    /// The private key is generated using the entropy source of the operating system. Use
+    /// [`NoiseKey::new_with_seed`] in order to provide the randomness yourself.
     pub fn new(libp2p_ed25519_private_key: &[u8; 32]) -> Self {
-        let unsigned = UnsignedNoiseKey::random();
+        Self::new_with_seed(libp2p_ed25519_private_key, &rand::random())
+    }
+
+    /// Same as [`NoiseKey::new`], but the private key is derived from `randomness_seed`. See
+    /// [`UnsignedNoiseKey::new`].
+    pub fn new_with_seed(
+        libp2p_ed25519_private_key: &[u8; 32],
+        randomness_seed: &[u8; 32],
+    ) -> Self {
+        let unsigned = UnsignedNoiseKey::new(randomness_seed);
 
         let (libp2p_public_key, signature) = {
             // Creating a `SecretKey` can fail only if the length isn't 32 bytes.
@@ -142,16 +163,29 @@ pub struct UnsignedNoiseKey {
 }
 
 impl UnsignedNoiseKey {
-    /// Generates a new private and public key pair.
-    pub fn random() -> Self {
+    /// Generates a new private and public key pair from the given randomness seed.
+    ///
+    /// The seed must be generated using a cryptographically secure source of randomness. Passing
+    /// the same seed always generates the same key pair.
+    pub fn new(randomness_seed: &[u8; 32]) -> Self {
         UnsignedNoiseKey {
-            // TODO: can panic if there's no RNG
-            key: snow::Builder::new(noise_params())
-                .generate_keypair()
-                .unwrap(),
+            key: snow::Builder::with_resolver(
+                noise_params(),
+                Box::new(SeededResolver(*randomness_seed)),
+            )
+            .generate_keypair()
+            .unwrap(),
         }
     }
 
+    /// Generates a new private and public key pair using the entropy source of the operating
+    /// system.
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    pub fn random() -> Self {
+        Self::new(&rand::random())
+    }
+
     /// Returns the data that has to be signed.
     pub fn payload_to_sign(&'_ self) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + '_ {
         iter::once(&b"noise-libp2p-static-key:"[..]).chain(iter::once(&self.key.public[..]))
@@ -500,16 +534,23 @@ enum RxPayload {
 impl NoiseHandshake {
     /// Shortcut function that calls [`HandshakeInProgress::new`] and wraps it into a
     /// [`NoiseHandshake`].
-    pub fn new(key: &NoiseKey, is_initiator: bool) -> Self {
-        NoiseHandshake::InProgress(HandshakeInProgress::new(key, is_initiator))
+    pub fn new(key: &NoiseKey, is_initiator: bool, randomness_seed: &[u8; 32]) -> Self {
+        NoiseHandshake::InProgress(HandshakeInProgress::new(key, is_initiator, randomness_seed))
     }
 }
 
 impl HandshakeInProgress {
     /// Initializes a new noise handshake state machine.
-    pub fn new(key: &NoiseKey, is_initiator: bool) -> Self {
+    ///
+    /// The ephemeral key of the handshake is derived from `randomness_seed`, which must be
+    /// generated using a cryptographically secure source of randomness and must never be reused.
+    pub fn new(key: &NoiseKey, is_initiator: bool, randomness_seed: &[u8; 32]) -> Self {
         let inner = {
-            let builder = snow::Builder::new(noise_params()).local_private_key(&key.key.private);
+            let builder = snow::Builder::with_resolver(
+                noise_params(),
+                Box::new(SeededResolver(*randomness_seed)),
+            )
+            .local_private_key(&key.key.private);
             if is_initiator {
                 builder.build_initiator()
             } else {
@@ -807,6 +848,60 @@ pub enum HandshakeError {
     SignatureVerificationFailed(SignatureVerifyFailed),
 }
 
+/// Cryptographic primitives used by `snow`. Identical to the default ones, except that the
+/// random number generator is seeded with a value provided by the API user.
+struct SeededResolver([u8; 32]);
+
+impl snow::resolvers::CryptoResolver for SeededResolver {
+    fn resolve_rng(&self) -> Option<Box<dyn snow::types::Random>> {
+        Some(Box::new(SeededRng(rand_chacha::ChaCha20Rng::from_seed(
+            self.0,
+        ))))
+    }
+
+    fn resolve_dh(&self, choice: &snow::params::DHChoice) -> Option<Box<dyn snow::types::Dh>> {
+        snow::resolvers::DefaultResolver.resolve_dh(choice)
+    }
+
+    fn resolve_hash(
+        &self,
+        choice: &snow::params::HashChoice,
+    ) -> Option<Box<dyn snow::types::Hash>> {
+        snow::resolvers::DefaultResolver.resolve_hash(choice)
+    }
+
+    fn resolve_cipher(
+        &self,
+        choice: &snow::params::CipherChoice,
+    ) -> Option<Box<dyn snow::types::Cipher>> {
+        snow::resolvers::DefaultResolver.resolve_cipher(choice)
+    }
+}
+
+/// Random number generator returned by [`SeededResolver`].
+struct SeededRng(rand_chacha::ChaCha20Rng);
+
+impl rand::RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+impl rand::CryptoRng for SeededRng {}
+impl snow::types::Random for SeededRng {}
+
 /// Error while decoding data.
 #[derive(Debug, derive_more::Display)]
 #[display(fmt = "Error while decrypting the Noise payload")]
@@ -818,7 +913,14 @@ pub struct PayloadDecodeError(prost::DecodeError);
 
 #[cfg(test)]
 mod tests {
-    use super::{NoiseHandshake, NoiseKey, ReadWrite};
+    use super::{NoiseHandshake, NoiseKey, ReadWrite, UnsignedNoiseKey};
+
+    #[test]
+    fn deterministic_keys() {
+        let payload = |seed: &[u8; 32]| UnsignedNoiseKey::new(seed).payload_to_sign_as_vec();
+        assert_eq!(payload(&[1; 32]), payload(&[1; 32]));
+        assert_ne!(payload(&[1; 32]), payload(&[2; 32]));
+    }
 
     #[test]
     fn handshake_basic_works() {
@@ -826,8 +928,8 @@ mod tests {
             let key1 = NoiseKey::new(&rand::random());
             let key2 = NoiseKey::new(&rand::random());
 
-            let mut handshake1 = NoiseHandshake::new(&key1, true);
-            let mut handshake2 = NoiseHandshake::new(&key2, false);
+            let mut handshake1 = NoiseHandshake::new(&key1, true, &rand::random());
+            let mut handshake2 = NoiseHandshake::new(&key2, false, &rand::random());
 
             let mut buf_1_to_2 = Vec::new();
             let mut buf_2_to_1 = Vec::new();
@@ -54,10 +54,11 @@ impl Multiaddr {
     }
 
     /// Returns the list of components of the multiaddress.
-    pub fn iter(&'_ self) -> impl Iterator<Item = ProtocolRef<'_>> + '_ {
+    pub fn iter(&'_ self) -> impl Iterator<Item = ProtocolRef<'_>> + iter::FusedIterator + '_ {
         let mut iter =
             nom::combinator::iterator(&self.bytes[..], protocol::<nom::error::Error<&'_ [u8]>>);
-        iter::from_fn(move || (&mut iter).next())
+        // The nom iterator panics if polled again after it has returned `None`.
+        iter::from_fn(move || (&mut iter).next()).fuse()
     }
 
     /// Pops the last protocol from the list.
@@ -184,6 +185,8 @@ pub enum ParseError {
     NotBase58,
     InvalidDomainName,
     InvalidMultihash(multihash::FromBytesError),
+    /// Only the `u` (base64url) multibase prefix is supported.
+    InvalidMultibase,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -195,10 +198,13 @@ pub enum ProtocolRef<'a> {
     Ip4([u8; 4]),
     Ip6([u8; 16]),
     P2p(Cow<'a, [u8]>), // TODO: a bit hacky
+    /// Multihash of the certificate of a WebRTC node.
+    Certhash(Cow<'a, [u8]>), // TODO: a bit hacky
     Quic,
     Tcp(u16),
     Tls,
     Udp(u16),
+    WebRtc,
     Ws,
     // TODO: remove support for `/wss` in a long time (https://github.com/paritytech/smoldot/issues/1940)
     Wss,
@@ -246,6 +252,18 @@ impl<'a> ProtocolRef<'a> {
                 }
                 Ok(ProtocolRef::P2p(Cow::Owned(decoded)))
             }
+            "certhash" => {
+                let s = iter.next().ok_or(ParseError::UnexpectedEof)?;
+                // The certificate hash is a multibase-encoded multihash. The `u` prefix
+                // corresponds to base64url without padding, which is what is used in practice.
+                let encoded = s.strip_prefix('u').ok_or(ParseError::InvalidMultibase)?;
+                let decoded = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD)
+                    .map_err(|_| ParseError::InvalidMultibase)?;
+                if let Err(err) = multihash::MultihashRef::from_bytes(&decoded) {
+                    return Err(ParseError::InvalidMultihash(err));
+                }
+                Ok(ProtocolRef::Certhash(Cow::Owned(decoded)))
+            }
             "tcp" => {
                 let port = iter.next().ok_or(ParseError::UnexpectedEof)?;
                 Ok(ProtocolRef::Tcp(
@@ -259,6 +277,7 @@ impl<'a> ProtocolRef<'a> {
                     port.parse().map_err(|_| ParseError::InvalidPort)?,
                 ))
             }
+            "webrtc" => Ok(ProtocolRef::WebRtc),
             "ws" => Ok(ProtocolRef::Ws),
             "wss" => Ok(ProtocolRef::Wss),
             _ => Err(ParseError::UnrecognizedProtocol),
@@ -280,6 +299,8 @@ impl<'a> ProtocolRef<'a> {
             ProtocolRef::Tcp(_) => 6,
             ProtocolRef::Tls => 448,
             ProtocolRef::Udp(_) => 273,
+            ProtocolRef::WebRtc => 280,
+            ProtocolRef::Certhash(_) => 466,
             ProtocolRef::Ws => 477,
             ProtocolRef::Wss => 478,
         };
@@ -297,7 +318,7 @@ impl<'a> ProtocolRef<'a> {
             }
             ProtocolRef::Ip4(ip) => ip.to_vec(),
             ProtocolRef::Ip6(ip) => ip.to_vec(),
-            ProtocolRef::P2p(multihash) => {
+            ProtocolRef::P2p(multihash) | ProtocolRef::Certhash(multihash) => {
                 // TODO: what if not a valid multihash? the enum variant can be constructed by the user
                 let mut out = Vec::with_capacity(multihash.len() + 4);
                 out.extend(crate::util::leb128::encode_usize(multihash.len()));
@@ -333,6 +354,15 @@ impl<'a> fmt::Display for ProtocolRef<'a> {
             ProtocolRef::Tcp(port) => write!(f, "/tcp/{}", port),
             ProtocolRef::Tls => write!(f, "/tls"),
             ProtocolRef::Udp(port) => write!(f, "/udp/{}", port),
+            ProtocolRef::WebRtc => write!(f, "/webrtc"),
+            ProtocolRef::Certhash(multihash) => {
+                // Base64url doesn't have `/` in its characters set.
+                write!(
+                    f,
+                    "/certhash/u{}",
+                    base64::encode_config(multihash, base64::URL_SAFE_NO_PAD)
+                )
+            }
             ProtocolRef::Ws => write!(f, "/ws"),
             ProtocolRef::Wss => write!(f, "/wss"),
         }
@@ -457,6 +487,7 @@ fn protocol<'a, E: nom::error::ParseError<&'a [u8]>>(
                 ProtocolRef::DnsAddr,
             )(bytes),
             273 => nom::combinator::map(nom::number::complete::be_u16, ProtocolRef::Udp)(bytes),
+            280 => Ok((bytes, ProtocolRef::WebRtc)),
             421 => nom::combinator::map(
                 nom::combinator::verify(
                     nom::multi::length_data(crate::util::leb128::nom_leb128_usize),
@@ -465,6 +496,13 @@ fn protocol<'a, E: nom::error::ParseError<&'a [u8]>>(
                 |b| ProtocolRef::P2p(Cow::Borrowed(b)),
             )(bytes),
             448 => Ok((bytes, ProtocolRef::Tls)),
+            466 => nom::combinator::map(
+                nom::combinator::verify(
+                    nom::multi::length_data(crate::util::leb128::nom_leb128_usize),
+                    |s| multihash::MultihashRef::from_bytes(s).is_ok(),
+                ),
+                |b| ProtocolRef::Certhash(Cow::Borrowed(b)),
+            )(bytes),
             460 => Ok((bytes, ProtocolRef::Quic)),
             477 => Ok((bytes, ProtocolRef::Ws)),
             478 => Ok((bytes, ProtocolRef::Wss)),
@@ -519,5 +557,47 @@ mod tests {
         check_invalid("/ws/1.2.3.4");
         check_invalid("/tcp/65536");
         check_invalid("/p2p/blablabla");
+        check_invalid("/certhash/uEiA");
+        check_invalid("/certhash/zQmSomething");
+    }
+
+    #[test]
+    fn iter_can_be_polled_after_end() {
+        let addr = "/ip4/1.2.3.4/tcp/30333".parse::<Multiaddr>().unwrap();
+        let mut iter = addr.iter();
+        assert!(iter.next().is_some());
+        assert!(iter.next().is_some());
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn webrtc_certhash() {
+        let hash = super::multihash::MultihashRef::sha2_256(&[0x5a; 32]).to_vec();
+        let addr = format!(
+            "/ip4/127.0.0.1/udp/30333/webrtc/certhash/u{}",
+            base64::encode_config(&hash, base64::URL_SAFE_NO_PAD)
+        );
+        let parsed = addr.parse::<Multiaddr>().unwrap();
+        assert_eq!(parsed.to_string(), addr);
+        assert_eq!(Multiaddr::try_from(parsed.to_vec()).unwrap(), parsed);
+        assert_eq!(
+            parsed.iter().last(),
+            Some(super::ProtocolRef::Certhash(hash.into()))
+        );
+
+        // Certificate hashes that use an unsupported hash algorithm are rejected.
+        let mut sha512 = vec![0x13, 0x40];
+        sha512.extend_from_slice(&[0; 64]);
+        let addr = format!(
+            "/webrtc/certhash/u{}",
+            base64::encode_config(&sha512, base64::URL_SAFE_NO_PAD)
+        );
+        assert!(matches!(
+            addr.parse::<Multiaddr>(),
+            Err(super::ParseError::InvalidMultihash(
+                super::multihash::FromBytesError::UnsupportedCode(0x13)
+            ))
+        ));
     }
 }
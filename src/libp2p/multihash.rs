@@ -19,18 +19,69 @@
 //! the data depends on the code.
 //!
 //! See <https://github.com/multiformats/multihash>
+//!
+//! Only the hash algorithms listed in [`HashAlgorithm`] are supported. Multihashes that use a
+//! different code are rejected when decoding.
+//!
+//! This module also contains [`CidRef`], a minimal implementation of content identifiers (CIDs),
+//! which consist of a multihash and a code indicating the format of the content being hashed.
+//! See <https://github.com/multiformats/cid>
 
 use alloc::vec::Vec;
 use core::fmt;
 
 use crate::util;
 
+/// Hash algorithm of a multihash.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// The data of the multihash is the content itself, without any hashing. The data can have
+    /// any length.
+    Identity,
+    /// The data of the multihash is the 32 bytes SHA2-256 hash of the content.
+    Sha2_256,
+    /// The data of the multihash is the 32 bytes Blake2b hash of the content.
+    Blake2b256,
+}
+
+impl HashAlgorithm {
+    /// Returns the multihash code of this algorithm.
+    pub fn code(&self) -> u32 {
+        match self {
+            HashAlgorithm::Identity => 0x0,
+            HashAlgorithm::Sha2_256 => 0x12,
+            HashAlgorithm::Blake2b256 => 0xb220,
+        }
+    }
+
+    /// Returns the algorithm corresponding to the given multihash code, or `None` if the code
+    /// isn't supported.
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0x0 => Some(HashAlgorithm::Identity),
+            0x12 => Some(HashAlgorithm::Sha2_256),
+            0xb220 => Some(HashAlgorithm::Blake2b256),
+            _ => None,
+        }
+    }
+
+    /// Returns the length of the data of multihashes that use this algorithm, or `None` if the
+    /// length can be anything.
+    pub fn digest_len(&self) -> Option<usize> {
+        match self {
+            HashAlgorithm::Identity => None,
+            HashAlgorithm::Sha2_256 | HashAlgorithm::Blake2b256 => Some(32),
+        }
+    }
+}
+
 /// A multihash made of a code and a slice of data.
 ///
 /// This type is a *reference* to a multihash stored somewhere else, such as in a `Vec<u8>`. You
 /// are supposed to store a `MultihashRef` for long term usage. Instead, store a `Vec<u8>` for
 /// example. The `MultihashRef` can be constructed from that `Vec<u8>` if it needs decoding.
-pub struct MultihashRef<'a>(u32, &'a [u8]);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MultihashRef<'a>(HashAlgorithm, &'a [u8]);
 
 impl<'a> MultihashRef<'a> {
     /// Builds a multihash from the "identity" hash algorithm code and the provided data.
@@ -38,12 +89,27 @@ impl<'a> MultihashRef<'a> {
     /// Calling [`MultihashRef::data`] on the returned value will always yield back the same data
     /// as was passed as parameter.
     pub fn identity(data: &'a [u8]) -> Self {
-        MultihashRef(0, data)
+        MultihashRef(HashAlgorithm::Identity, data)
+    }
+
+    /// Builds a multihash from the "sha2-256" hash algorithm code and the provided hash.
+    pub fn sha2_256(hash: &'a [u8; 32]) -> Self {
+        MultihashRef(HashAlgorithm::Sha2_256, hash)
+    }
+
+    /// Builds a multihash from the "blake2b-256" hash algorithm code and the provided hash.
+    pub fn blake2b_256(hash: &'a [u8; 32]) -> Self {
+        MultihashRef(HashAlgorithm::Blake2b256, hash)
+    }
+
+    /// Returns the hash algorithm of this multihash.
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.0
     }
 
     /// Returns the code stored in this multihash.
     pub fn hash_algorithm_code(&self) -> u32 {
-        self.0
+        self.0.code()
     }
 
     /// Returns the data stored in this multihash.
@@ -54,18 +120,29 @@ impl<'a> MultihashRef<'a> {
     /// Checks whether `input` is a valid multihash.
     pub fn from_bytes(input: &'a [u8]) -> Result<MultihashRef, FromBytesError> {
         match nom::combinator::all_consuming(multihash::<nom::error::Error<&[u8]>>)(input) {
-            Ok((_rest, multihash)) => {
+            Ok((_rest, (code, data))) => {
                 debug_assert!(_rest.is_empty());
-                Ok(multihash)
+                MultihashRef::from_code_and_data(code, data)
             }
             Err(_) => Err(FromBytesError::DecodeError),
         }
     }
 
+    /// Checks whether `code` is a supported hash algorithm and that `data` is valid for this
+    /// algorithm.
+    fn from_code_and_data(code: u32, data: &'a [u8]) -> Result<Self, FromBytesError> {
+        let algorithm =
+            HashAlgorithm::from_code(code).ok_or(FromBytesError::UnsupportedCode(code))?;
+        if matches!(algorithm.digest_len(), Some(len) if len != data.len()) {
+            return Err(FromBytesError::InvalidDigestLength);
+        }
+        Ok(MultihashRef(algorithm, data))
+    }
+
     /// Returns an iterator to a list of buffers that, when concatenated together, form the
     /// binary representation of this multihash.
     pub fn as_bytes(&'_ self) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + '_ {
-        let code = util::leb128::encode(self.0).collect::<arrayvec::ArrayVec<u8, 40>>(); // TODO: actual length?
+        let code = util::leb128::encode(self.0.code()).collect::<arrayvec::ArrayVec<u8, 40>>(); // TODO: actual length?
         let len = util::leb128::encode_usize(self.1.len()).collect::<arrayvec::ArrayVec<u8, 40>>(); // TODO: actual length?
         [either::Left(code), either::Left(len), either::Right(self.1)].into_iter()
     }
@@ -81,10 +158,15 @@ impl<'a> MultihashRef<'a> {
 }
 
 /// Error when turning bytes into a [`MultihashRef`].
-#[derive(Debug, derive_more::Display, Clone)]
+#[derive(Debug, derive_more::Display, Clone, PartialEq, Eq)]
 pub enum FromBytesError {
     /// The multihash is invalid.
     DecodeError,
+    /// The hash algorithm code isn't supported.
+    #[display(fmt = "Unsupported multihash code: 0x{:x}", _0)]
+    UnsupportedCode(u32),
+    /// The length of the data doesn't match the hash algorithm.
+    InvalidDigestLength,
 }
 
 impl<'a> fmt::Debug for MultihashRef<'a> {
@@ -108,16 +190,214 @@ impl<'a> TryFrom<&'a [u8]> for MultihashRef<'a> {
     }
 }
 
+/// Content identifier made of a content type code and a multihash of the content.
+///
+/// Both versions 0 and 1 of CIDs are supported. Version 0 CIDs consist only of a SHA2-256
+/// multihash, and implicitly use the "dag-pb" (`0x70`) content type.
+///
+/// Similar to [`MultihashRef`], this type is a *reference* to a CID stored somewhere else.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CidRef<'a> {
+    version: CidVersion,
+    content_type: u32,
+    multihash: MultihashRef<'a>,
+}
+
+/// Version of a [`CidRef`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CidVersion {
+    /// Legacy version, where the CID is a SHA2-256 multihash.
+    V0,
+    /// Current version.
+    V1,
+}
+
+impl<'a> CidRef<'a> {
+    /// Builds a version 1 CID from a content type code and a multihash.
+    pub fn new(content_type: u32, multihash: MultihashRef<'a>) -> Self {
+        CidRef {
+            version: CidVersion::V1,
+            content_type,
+            multihash,
+        }
+    }
+
+    /// Returns the version of this CID.
+    pub fn version(&self) -> CidVersion {
+        self.version
+    }
+
+    /// Returns the type of the content the CID refers to, as a multicodec code.
+    pub fn content_type(&self) -> u32 {
+        self.content_type
+    }
+
+    /// Returns the multihash of the content the CID refers to.
+    pub fn multihash(&self) -> &MultihashRef<'a> {
+        &self.multihash
+    }
+
+    /// Checks whether `input` is a valid CID.
+    pub fn from_bytes(input: &'a [u8]) -> Result<CidRef<'a>, CidFromBytesError> {
+        // Version 0 CIDs are always 34 bytes SHA2-256 multihashes.
+        if input.len() == 34 && input[0] == 0x12 && input[1] == 0x20 {
+            return Ok(CidRef {
+                version: CidVersion::V0,
+                content_type: 0x70,
+                multihash: MultihashRef::from_bytes(input).map_err(CidFromBytesError::Multihash)?,
+            });
+        }
+
+        let result: nom::IResult<_, _, nom::error::Error<&[u8]>> =
+            nom::combinator::all_consuming(nom::sequence::tuple((
+                util::leb128::nom_leb128_usize,
+                util::leb128::nom_leb128_usize,
+                multihash,
+            )))(input);
+        let (version, content_type, (code, data)) = match result {
+            Ok((_, v)) => v,
+            Err(_) => return Err(CidFromBytesError::DecodeError),
+        };
+
+        if version != 1 {
+            return Err(CidFromBytesError::UnsupportedVersion);
+        }
+
+        Ok(CidRef {
+            version: CidVersion::V1,
+            content_type: u32::try_from(content_type)
+                .map_err(|_| CidFromBytesError::DecodeError)?,
+            multihash: MultihashRef::from_code_and_data(code, data)
+                .map_err(CidFromBytesError::Multihash)?,
+        })
+    }
+
+    /// Turns this CID into a `Vec<u8>`.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(7 + 7 + 7 + 7 + self.multihash.data().len());
+        if matches!(self.version, CidVersion::V1) {
+            out.extend(util::leb128::encode(1u32));
+            out.extend(util::leb128::encode(self.content_type));
+        }
+        for slice in self.multihash.as_bytes() {
+            out.extend_from_slice(slice.as_ref());
+        }
+        out
+    }
+}
+
+/// Error when turning bytes into a [`CidRef`].
+#[derive(Debug, derive_more::Display, Clone, PartialEq, Eq)]
+pub enum CidFromBytesError {
+    /// The CID is invalid.
+    DecodeError,
+    /// The CID version isn't supported.
+    UnsupportedVersion,
+    /// The multihash contained in the CID is invalid.
+    #[display(fmt = "{}", _0)]
+    Multihash(FromBytesError),
+}
+
+impl<'a> fmt::Debug for CidRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CidRef")
+            .field("version", &self.version)
+            .field("content_type", &self.content_type)
+            .field("multihash", &self.multihash)
+            .finish()
+    }
+}
+
 fn multihash<'a, E: nom::error::ParseError<&'a [u8]>>(
     bytes: &'a [u8],
-) -> nom::IResult<&'a [u8], MultihashRef<'a>, E> {
-    nom::combinator::map(
-        nom::sequence::tuple((
-            nom::combinator::map_opt(crate::util::leb128::nom_leb128_usize, |c| {
-                u32::try_from(c).ok()
-            }),
-            nom::multi::length_data(crate::util::leb128::nom_leb128_usize),
-        )),
-        |(code, data)| MultihashRef(code, data),
-    )(bytes)
+) -> nom::IResult<&'a [u8], (u32, &'a [u8]), E> {
+    nom::sequence::tuple((
+        nom::combinator::map_opt(crate::util::leb128::nom_leb128_usize, |c| {
+            u32::try_from(c).ok()
+        }),
+        nom::multi::length_data(crate::util::leb128::nom_leb128_usize),
+    ))(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CidFromBytesError, CidRef, CidVersion, FromBytesError, HashAlgorithm, MultihashRef,
+    };
+
+    #[test]
+    fn round_trip_identity() {
+        let multihash = MultihashRef::identity(b"hello world");
+        let encoded = multihash.to_vec();
+        assert_eq!(encoded[..2], [0x0, 11]);
+        let decoded = MultihashRef::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded, multihash);
+        assert_eq!(decoded.hash_algorithm(), HashAlgorithm::Identity);
+        assert_eq!(decoded.data(), b"hello world");
+    }
+
+    #[test]
+    fn round_trip_sha2_256() {
+        let multihash = MultihashRef::sha2_256(&[0xab; 32]);
+        let encoded = multihash.to_vec();
+        assert_eq!(encoded[..2], [0x12, 0x20]);
+        let decoded = MultihashRef::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded, multihash);
+        assert_eq!(decoded.hash_algorithm(), HashAlgorithm::Sha2_256);
+    }
+
+    #[test]
+    fn round_trip_blake2b_256() {
+        let multihash = MultihashRef::blake2b_256(&[0xcd; 32]);
+        let encoded = multihash.to_vec();
+        // The code `0xb220` is LEB128-encoded on three bytes.
+        assert_eq!(encoded[..4], [0xa0, 0xe4, 0x02, 0x20]);
+        let decoded = MultihashRef::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded, multihash);
+        assert_eq!(decoded.hash_algorithm(), HashAlgorithm::Blake2b256);
+    }
+
+    #[test]
+    fn unsupported_code() {
+        // `0x13` is sha2-512, which isn't supported.
+        let mut encoded = vec![0x13, 0x40];
+        encoded.extend_from_slice(&[0; 64]);
+        assert_eq!(
+            MultihashRef::from_bytes(&encoded),
+            Err(FromBytesError::UnsupportedCode(0x13))
+        );
+    }
+
+    #[test]
+    fn invalid_digest_length() {
+        let mut encoded = vec![0x12, 0x10];
+        encoded.extend_from_slice(&[0; 16]);
+        assert_eq!(
+            MultihashRef::from_bytes(&encoded),
+            Err(FromBytesError::InvalidDigestLength)
+        );
+    }
+
+    #[test]
+    fn cid_round_trip() {
+        let cid = CidRef::new(0x55, MultihashRef::blake2b_256(&[0x1; 32]));
+        let encoded = cid.to_vec();
+        assert_eq!(encoded[..2], [0x1, 0x55]);
+        let decoded = CidRef::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded, cid);
+        assert_eq!(decoded.version(), CidVersion::V1);
+        assert_eq!(decoded.content_type(), 0x55);
+
+        // A version 0 CID is a plain SHA2-256 multihash.
+        let encoded = MultihashRef::sha2_256(&[0x2; 32]).to_vec();
+        let decoded = CidRef::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded.version(), CidVersion::V0);
+        assert_eq!(decoded.content_type(), 0x70);
+        assert_eq!(decoded.to_vec(), encoded);
+
+        assert_eq!(
+            CidRef::from_bytes(&[0x2, 0x55, 0x0, 0x0]),
+            Err(CidFromBytesError::UnsupportedVersion)
+        );
+    }
 }
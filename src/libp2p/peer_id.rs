@@ -135,15 +135,10 @@ impl PeerId {
             }
             out
         } else {
-            let mut out = Vec::with_capacity(34);
-            out.push(0x12);
-            out.push(0x32);
-
             let mut hasher = sha2::Sha256::new();
             hasher.update(&key_enc);
-            out.extend_from_slice(hasher.finalize().as_slice());
-
-            out
+            let hash: [u8; 32] = hasher.finalize().into();
+            multihash::MultihashRef::sha2_256(&hash).to_vec()
         };
 
         PeerId { multihash: out }
@@ -157,16 +152,16 @@ impl PeerId {
             Ok(hash) => {
                 // For a PeerId to be valid, it must use either the "identity" multihash code (0x0)
                 // or the "sha256" multihash code (0x12).
-                if hash.hash_algorithm_code() == 0 {
-                    if let Err(err) = PublicKey::from_protobuf_encoding(hash.data()) {
-                        Err(FromBytesError::InvalidPublicKey(err))
-                    } else {
-                        Ok(())
+                match hash.hash_algorithm() {
+                    multihash::HashAlgorithm::Identity => {
+                        if let Err(err) = PublicKey::from_protobuf_encoding(hash.data()) {
+                            Err(FromBytesError::InvalidPublicKey(err))
+                        } else {
+                            Ok(())
+                        }
                     }
-                } else if hash.hash_algorithm_code() == 0x12 {
-                    Ok(())
-                } else {
-                    Err(FromBytesError::InvalidMultihashAlgorithm)
+                    multihash::HashAlgorithm::Sha2_256 => Ok(()),
+                    _ => Err(FromBytesError::InvalidMultihashAlgorithm),
                 }
             }
             Err(err) => Err(FromBytesError::DecodeError(err)),
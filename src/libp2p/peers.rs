@@ -90,6 +90,10 @@ pub struct Config {
     /// and must be aborted.
     pub handshake_timeout: Duration,
 
+    /// Amount of time after which an inbound substream whose protocol hasn't been negotiated
+    /// is considered to have taken too long and is reset.
+    pub inbound_negotiation_timeout: Duration,
+
     /// Key used for the encryption layer.
     /// This is a Noise static key, according to the Noise specification.
     /// Signed using the actual libp2p key.
@@ -136,6 +140,7 @@ where
                 request_response_protocols: config.request_response_protocols,
                 ping_protocol: config.ping_protocol,
                 handshake_timeout: config.handshake_timeout,
+                inbound_negotiation_timeout: config.inbound_negotiation_timeout,
                 randomness_seed: randomness.sample(rand::distributions::Standard),
                 pending_api_events_buffer_size: config.pending_api_events_buffer_size,
             }),
@@ -287,6 +292,9 @@ where
                     {
                         let actual_peer_index = guarded.peer_index_or_insert(&peer_id);
 
+                        // Only outgoing connections have an expected peer.
+                        let inbound = guarded.connections[local_connection_index].0.is_none();
+
                         if let Some(expected_peer_index) =
                             guarded.connections[local_connection_index].0
                         {
@@ -377,6 +385,8 @@ where
                         }
 
                         return Event::Connected {
+                            id: connection_id,
+                            inbound,
                             num_peer_connections,
                             peer_id,
                         };
@@ -417,6 +427,7 @@ where
                     // Only produce a `Disconnected` event if connection wasn't handshaking.
                     if was_established {
                         return Event::Disconnected {
+                            id: connection_id,
                             num_peer_connections,
                             peer_id,
                             peer_is_desired,
@@ -659,9 +670,10 @@ where
                     unreachable!();
                 }
 
-                collection::Event::PingOutSuccess { .. } => {
-                    // We don't care about or report successful pings at the moment.
+                collection::Event::PingOutSuccess { id, .. } => {
+                    let id = *id;
                     guarded.pending_inner_event = None;
+                    return Event::PingOutSuccess { id };
                 }
 
                 collection::Event::PingOutFailed { id, .. } => {
@@ -1100,19 +1112,27 @@ where
     ///
     /// Individual errors that would have occured when calling [`Peers::queue_notification`] are
     /// silently discarded.
+    ///
+    /// Returns the number of peers the notification has successfully been queued to.
     // TODO: consider returning the peers we successfully sent to
     pub async fn broadcast_notification(
         &self,
         notifications_protocol_index: usize,
         notification: impl Into<Vec<u8>>,
-    ) {
+    ) -> usize {
         // TODO: implement this better
         let notification = notification.into();
+        let mut num_queued = 0;
         for peer in self.peers_list().await {
-            let _ = self
+            if self
                 .queue_notification(&peer, notifications_protocol_index, notification.clone())
-                .await;
+                .await
+                .is_ok()
+            {
+                num_queued += 1;
+            }
         }
+        num_queued
     }
 
     /// Sends a request to the given peer, and waits for a response.
@@ -1213,6 +1233,16 @@ where
         self.inner.read_write(connection_id, read_write).await
     }
 
+    /// Switches the connection to a state where it will shut down soon.
+    ///
+    /// An [`Event::Disconnected`] is later generated if the connection was established.
+    ///
+    /// Has no effect if this connection was already shutting down, or if the [`ConnectionId`]
+    /// is invalid.
+    pub async fn start_shutdown(&self, connection_id: ConnectionId) {
+        self.inner.start_shutdown(connection_id).await
+    }
+
     /// Returns an iterator to the list of [`PeerId`]s that we have an established connection
     /// with.
     pub async fn peers_list(&self) -> impl Iterator<Item = PeerId> {
@@ -1236,6 +1266,43 @@ where
             .into_iter()
     }
 
+    /// Returns the list of [`PeerId`]s that have at least one notifications substream open or
+    /// being opened with the local node, in either direction, or that have sent a request that
+    /// hasn't been answered yet.
+    pub async fn peers_with_substreams(&self) -> impl Iterator<Item = PeerId> {
+        let guarded = self.guarded.lock().await;
+
+        let mut peer_indices = guarded
+            .peers_notifications_out
+            .iter()
+            .filter(|(_, state)| !matches!(state.open, NotificationsOutOpenState::Closed))
+            .map(|((peer_index, _), _)| *peer_index)
+            .chain(
+                guarded
+                    .peers_notifications_in
+                    .iter()
+                    .map(|(peer_index, _)| *peer_index),
+            )
+            .collect::<BTreeSet<_>>();
+
+        // TODO: O(n^2)
+        for (_, (connection_id, _)) in guarded.requests_in.iter() {
+            if let Some(((peer_index, _), _)) = guarded
+                .connections_by_peer
+                .iter()
+                .find(|((_, c), _)| c == connection_id)
+            {
+                peer_indices.insert(*peer_index);
+            }
+        }
+
+        peer_indices
+            .into_iter()
+            .map(|peer_index| guarded.peers[peer_index].peer_id.clone())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
     /// Returns the number of connections we have a substream with.
     pub async fn num_outgoing_substreams(&self, notifications_protocol_index: usize) -> usize {
         let guarded = self.guarded.lock().await;
@@ -1374,9 +1441,16 @@ pub struct RequestId(usize);
 pub enum Event<TConn> {
     /// Established a new connection to the given peer.
     Connected {
+        /// Identifier of the connection that has been established.
+        id: ConnectionId,
+
         /// Identity of the peer on the other side of the connection.
         peer_id: PeerId,
 
+        /// `true` if the connection has been added with [`Peers::add_incoming_connection`],
+        /// `false` if it has been added with [`Peers::add_outgoing_connection`].
+        inbound: bool,
+
         /// Number of other established connections with the same peer, including the one that
         /// has just been established.
         num_peer_connections: NonZeroU32,
@@ -1384,6 +1458,10 @@ pub enum Event<TConn> {
 
     /// A connection has stopped.
     Disconnected {
+        /// Identifier of the connection that has stopped. Always corresponds to a connection
+        /// previously reported with an [`Event::Connected`].
+        id: ConnectionId,
+
         /// Identity of the peer on the other side of the connection.
         peer_id: PeerId,
 
@@ -1537,6 +1615,13 @@ pub enum Event<TConn> {
         /// If `Ok`, the substream has been closed gracefully. If `Err`, a problem happened.
         outcome: Result<(), NotificationsInClosedErr>,
     },
+
+    /// An outgoing ping has succeeded on the given connection. This event is generated
+    /// automatically over time for each established connection.
+    PingOutSuccess {
+        /// Identifier of the connection the ping has been sent on.
+        id: ConnectionId,
+    },
 }
 
 /// See [`Peers::set_peer_notifications_out_desired`].
@@ -0,0 +1,553 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Storage items and constants described by the metadata of a runtime.
+//!
+//! The runtimes built using the Substrate framework are divided in pallets, each pallet
+//! declaring storage items and constants. The metadata of the runtime describes, for each
+//! pallet, the list of its storage items, and, for storage maps, the hashing algorithm used for
+//! their keys.
+//!
+//! The storage key of an item is the concatenation of the twox128 hash of the pallet name, the
+//! twox128 hash of the item name, and, for maps, the hash of each of the SCALE-encoded keys of
+//! the map. Use [`storage_key`] to compute it.
+//!
+//! Contrary to storage items, the values of the constants of the pallets, such as the
+//! existential deposit, aren't found in the storage but directly in the metadata. Use
+//! [`pallet_constant`] to retrieve them.
+//!
+//! # Example
+//!
+//! ```
+//! use smoldot::metadata::{storage_key, StorageHasher};
+//!
+//! // `System.Account` storage item of the "Alice" account.
+//! let alice = [
+//!     0xd4, 0x35, 0x93, 0xc7, 0x15, 0xfd, 0xd3, 0x1c, 0x61, 0x14, 0x1a, 0xbd, 0x04, 0xa9,
+//!     0x9f, 0xd6, 0x82, 0x2c, 0x85, 0x58, 0x85, 0x4c, 0xcd, 0xe3, 0x9a, 0x56, 0x84, 0xe7,
+//!     0xa5, 0x6d, 0xa2, 0x7d,
+//! ];
+//! let key = storage_key("System", "Account", [(StorageHasher::Blake2_128Concat, &alice[..])]);
+//! assert_eq!(key.len(), 16 + 16 + 16 + 32);
+//! ```
+
+use alloc::vec::Vec;
+use core::hash::Hasher as _;
+
+/// Hashing algorithm applied to a key of a storage map.
+///
+/// The variants of this enum correspond to the hashers that can be found in the metadata.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum StorageHasher {
+    /// 16 bytes blake2b hash of the key.
+    Blake2_128,
+    /// 32 bytes blake2b hash of the key.
+    Blake2_256,
+    /// 16 bytes blake2b hash of the key, followed with the key itself.
+    Blake2_128Concat,
+    /// 16 bytes xxhash of the key.
+    Twox128,
+    /// 32 bytes xxhash of the key.
+    Twox256,
+    /// 8 bytes xxhash of the key, followed with the key itself.
+    Twox64Concat,
+    /// The key itself, without any hashing.
+    Identity,
+}
+
+impl StorageHasher {
+    /// Returns `true` if the output of the hasher contains the key that has been hashed, in which
+    /// case the key can be retrieved from the storage key.
+    pub fn is_reversible(&self) -> bool {
+        matches!(
+            self,
+            StorageHasher::Blake2_128Concat | StorageHasher::Twox64Concat | StorageHasher::Identity
+        )
+    }
+
+    /// Appends to `out` the output of the hasher when applied to `key`.
+    fn hash_into(&self, key: &[u8], out: &mut Vec<u8>) {
+        match self {
+            StorageHasher::Blake2_128 => {
+                out.extend_from_slice(blake2_rfc::blake2b::blake2b(16, &[], key).as_bytes())
+            }
+            StorageHasher::Blake2_256 => {
+                out.extend_from_slice(blake2_rfc::blake2b::blake2b(32, &[], key).as_bytes())
+            }
+            StorageHasher::Blake2_128Concat => {
+                out.extend_from_slice(blake2_rfc::blake2b::blake2b(16, &[], key).as_bytes());
+                out.extend_from_slice(key);
+            }
+            StorageHasher::Twox128 => twox(key, 2, out),
+            StorageHasher::Twox256 => twox(key, 4, out),
+            StorageHasher::Twox64Concat => {
+                twox(key, 1, out);
+                out.extend_from_slice(key);
+            }
+            StorageHasher::Identity => out.extend_from_slice(key),
+        }
+    }
+}
+
+/// Returns the storage key of the given storage item.
+///
+/// `keys` must be empty for plain storage values, and contain, for storage maps, each of the
+/// SCALE-encoded keys of the map together with the hashing algorithm that the metadata
+/// indicates for this key. Double maps, and more generally maps with multiple keys, expect one
+/// element per key in the order indicated by the metadata.
+///
+/// Passing only some of the keys of a map, or none at all, returns the prefix shared by all the
+/// storage keys of the entries that start with these keys.
+pub fn storage_key<'a>(
+    pallet_name: &str,
+    item_name: &str,
+    keys: impl IntoIterator<Item = (StorageHasher, &'a [u8])>,
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(32);
+    StorageHasher::Twox128.hash_into(pallet_name.as_bytes(), &mut out);
+    StorageHasher::Twox128.hash_into(item_name.as_bytes(), &mut out);
+    for (hasher, key) in keys {
+        hasher.hash_into(key, &mut out);
+    }
+    out
+}
+
+/// Constant of a pallet found in the metadata. See [`pallet_constant`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PalletConstant<'a> {
+    /// SCALE-encoded value of the constant.
+    pub value: &'a [u8],
+    /// Identifier of the type of the constant in the types registry of the metadata.
+    pub type_id: u32,
+}
+
+/// Error potentially returned by [`pallet_constant`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum PalletConstantError {
+    /// Failed to decode the metadata.
+    InvalidMetadata,
+    /// Only versions 14 and 15 of the metadata format are supported.
+    #[display(fmt = "Unsupported metadata version: {}", _0)]
+    UnsupportedVersion(u8),
+    /// The metadata doesn't contain any pallet with the given name.
+    PalletNotFound,
+    /// The pallet doesn't have any constant with the given name.
+    ConstantNotFound,
+}
+
+/// Finds in the metadata the constant of the given pallet.
+///
+/// `metadata` must be the metadata of the runtime without its length prefix, in other words
+/// starting with the `meta` magic number. Versions 14 and 15 of the metadata format are
+/// supported.
+///
+/// The type of the constant can be found in the types registry of the metadata under the
+/// returned [`PalletConstant::type_id`].
+pub fn pallet_constant<'a>(
+    metadata: &'a [u8],
+    pallet_name: &str,
+    constant_name: &str,
+) -> Result<PalletConstant<'a>, PalletConstantError> {
+    let (bytes, version) = nom::sequence::preceded(
+        nom::bytes::complete::tag(b"meta"),
+        nom::number::complete::u8,
+    )(metadata)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| PalletConstantError::InvalidMetadata)?;
+
+    if version != 14 && version != 15 {
+        return Err(PalletConstantError::UnsupportedVersion(version));
+    }
+
+    // The list of pallets directly follows the types registry, which is skipped.
+    let (bytes, ()) = skip_collection(5, portable_type)(bytes)
+        .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| PalletConstantError::InvalidMetadata)?;
+    let (mut bytes, num_pallets) = crate::util::nom_scale_collection_len(7)(bytes)
+        .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| PalletConstantError::InvalidMetadata)?;
+
+    for _ in 0..num_pallets {
+        let (rest, (name, constants)) =
+            pallet(version)(bytes).map_err(|_: nom::Err<nom::error::Error<&[u8]>>| {
+                PalletConstantError::InvalidMetadata
+            })?;
+        bytes = rest;
+
+        if name != pallet_name.as_bytes() {
+            continue;
+        }
+
+        let (mut constants, num_constants) = crate::util::nom_scale_collection_len(4)(constants)
+            .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| {
+                PalletConstantError::InvalidMetadata
+            })?;
+        for _ in 0..num_constants {
+            let (rest, (name, type_id, value)) = nom::sequence::terminated(
+                nom::sequence::tuple((
+                    crate::util::nom_bytes_decode,
+                    type_id,
+                    crate::util::nom_bytes_decode,
+                )),
+                skip_collection(1, skip_bytes),
+            )(constants)
+            .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| {
+                PalletConstantError::InvalidMetadata
+            })?;
+            constants = rest;
+
+            if name == constant_name.as_bytes() {
+                return Ok(PalletConstant { value, type_id });
+            }
+        }
+
+        return Err(PalletConstantError::ConstantNotFound);
+    }
+
+    Err(PalletConstantError::PalletNotFound)
+}
+
+/// Decodes a pallet of the metadata. Returns the name of the pallet and the undecoded list of
+/// its constants.
+fn pallet<'a, E: nom::error::ParseError<&'a [u8]>>(
+    version: u8,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], (&'a [u8], &'a [u8]), E> {
+    move |bytes| {
+        let (bytes, (name, _, _, _, constants, _, _)) = nom::sequence::tuple((
+            crate::util::nom_bytes_decode,
+            crate::util::nom_option_decode(nom::sequence::tuple((
+                skip_bytes,
+                skip_collection(6, storage_entry),
+            ))),
+            crate::util::nom_option_decode(type_id),
+            crate::util::nom_option_decode(type_id),
+            nom::combinator::recognize(skip_collection(
+                4,
+                nom::combinator::map(
+                    nom::sequence::tuple((
+                        skip_bytes,
+                        type_id,
+                        skip_bytes,
+                        skip_collection(1, skip_bytes),
+                    )),
+                    |_| (),
+                ),
+            )),
+            crate::util::nom_option_decode(type_id),
+            nom::number::complete::u8,
+        ))(bytes)?;
+
+        // Version 15 adds the documentation of the pallet.
+        let (bytes, ()) = if version >= 15 {
+            skip_collection(1, skip_bytes)(bytes)?
+        } else {
+            (bytes, ())
+        };
+
+        Ok((bytes, (name, constants)))
+    }
+}
+
+/// Skips a storage entry of a pallet.
+fn storage_entry<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (), E> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            skip_bytes,
+            nom::number::complete::u8,
+            nom::branch::alt((
+                nom::combinator::map(
+                    nom::sequence::preceded(nom::bytes::complete::tag(&[0]), type_id),
+                    |_| (),
+                ),
+                nom::combinator::map(
+                    nom::sequence::preceded(
+                        nom::bytes::complete::tag(&[1]),
+                        nom::sequence::tuple((
+                            skip_collection(
+                                1,
+                                nom::combinator::map(nom::number::complete::u8, |_| ()),
+                            ),
+                            type_id,
+                            type_id,
+                        )),
+                    ),
+                    |_| (),
+                ),
+            )),
+            skip_bytes,
+            skip_collection(1, skip_bytes),
+        )),
+        |_| (),
+    )(bytes)
+}
+
+/// Skips a type of the types registry of the metadata.
+fn portable_type<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (), E> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            type_id,
+            // Path.
+            skip_collection(1, skip_bytes),
+            // Type parameters.
+            skip_collection(
+                2,
+                nom::combinator::map(
+                    nom::sequence::tuple((skip_bytes, crate::util::nom_option_decode(type_id))),
+                    |_| (),
+                ),
+            ),
+            type_def,
+            // Documentation.
+            skip_collection(1, skip_bytes),
+        )),
+        |_| (),
+    )(bytes)
+}
+
+/// Skips the definition of a type of the types registry.
+fn type_def<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (), E> {
+    nom::branch::alt((
+        // Composite.
+        nom::sequence::preceded(nom::bytes::complete::tag(&[0]), skip_collection(4, field)),
+        // Variant.
+        nom::sequence::preceded(
+            nom::bytes::complete::tag(&[1]),
+            skip_collection(
+                4,
+                nom::combinator::map(
+                    nom::sequence::tuple((
+                        skip_bytes,
+                        skip_collection(4, field),
+                        nom::number::complete::u8,
+                        skip_collection(1, skip_bytes),
+                    )),
+                    |_| (),
+                ),
+            ),
+        ),
+        // Sequence.
+        nom::combinator::map(
+            nom::sequence::preceded(nom::bytes::complete::tag(&[2]), type_id),
+            |_| (),
+        ),
+        // Array.
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[3]),
+                nom::sequence::tuple((nom::number::complete::le_u32, type_id)),
+            ),
+            |_| (),
+        ),
+        // Tuple.
+        nom::sequence::preceded(
+            nom::bytes::complete::tag(&[4]),
+            skip_collection(1, nom::combinator::map(type_id, |_| ())),
+        ),
+        // Primitive.
+        nom::combinator::map(
+            nom::sequence::preceded(nom::bytes::complete::tag(&[5]), nom::number::complete::u8),
+            |_| (),
+        ),
+        // Compact.
+        nom::combinator::map(
+            nom::sequence::preceded(nom::bytes::complete::tag(&[6]), type_id),
+            |_| (),
+        ),
+        // Bit sequence.
+        nom::combinator::map(
+            nom::sequence::preceded(
+                nom::bytes::complete::tag(&[7]),
+                nom::sequence::tuple((type_id, type_id)),
+            ),
+            |_| (),
+        ),
+    ))(bytes)
+}
+
+/// Skips a field of a composite type or of a variant.
+fn field<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (), E> {
+    nom::combinator::map(
+        nom::sequence::tuple((
+            crate::util::nom_option_decode(skip_bytes),
+            type_id,
+            crate::util::nom_option_decode(skip_bytes),
+            skip_collection(1, skip_bytes),
+        )),
+        |_| (),
+    )(bytes)
+}
+
+/// Decodes a SCALE-compact-encoded identifier of a type of the types registry.
+fn type_id<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], u32, E> {
+    nom::combinator::map_opt(crate::util::nom_scale_compact_usize, |id| {
+        u32::try_from(id).ok()
+    })(bytes)
+}
+
+/// Skips a SCALE-encoded vec of bytes or string.
+fn skip_bytes<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], (), E> {
+    nom::combinator::map(crate::util::nom_bytes_decode, |_| ())(bytes)
+}
+
+/// Returns a parser that skips a SCALE-encoded collection whose items are skipped with `item`.
+/// Each item must occupy at least `min_item_size` bytes.
+fn skip_collection<'a, E: nom::error::ParseError<&'a [u8]>>(
+    min_item_size: usize,
+    mut item: impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], (), E>,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], (), E> {
+    move |bytes| {
+        let (mut bytes, num_items) = crate::util::nom_scale_collection_len(min_item_size)(bytes)?;
+        for _ in 0..num_items {
+            bytes = item(bytes)?.0;
+        }
+        Ok((bytes, ()))
+    }
+}
+
+/// Appends to `out` the concatenation of the xxhashes of `data` using the seeds from `0` to
+/// `num_hashes - 1`.
+fn twox(data: &[u8], num_hashes: u64, out: &mut Vec<u8>) {
+    for seed in 0..num_hashes {
+        let mut hasher = twox_hash::XxHash::with_seed(seed);
+        hasher.write(data);
+        out.extend_from_slice(&hasher.finish().to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pallet_constant, storage_key, PalletConstantError, StorageHasher};
+
+    const ALICE: [u8; 32] = [
+        0xd4, 0x35, 0x93, 0xc7, 0x15, 0xfd, 0xd3, 0x1c, 0x61, 0x14, 0x1a, 0xbd, 0x04, 0xa9, 0x9f,
+        0xd6, 0x82, 0x2c, 0x85, 0x58, 0x85, 0x4c, 0xcd, 0xe3, 0x9a, 0x56, 0x84, 0xe7, 0xa5, 0x6d,
+        0xa2, 0x7d,
+    ];
+
+    #[test]
+    fn system_account() {
+        assert_eq!(
+            storage_key("System", "Account", []),
+            hex::decode("26aa394eea5630e07c48ae0c9558cef7b99d880ec681799c0cf30e8886371da9")
+                .unwrap()
+        );
+
+        assert_eq!(
+            storage_key(
+                "System",
+                "Account",
+                [(StorageHasher::Blake2_128Concat, &ALICE[..])]
+            ),
+            hex::decode(
+                "26aa394eea5630e07c48ae0c9558cef7b99d880ec681799c0cf30e8886371da9\
+                 de1e86a9a8c739864cf3cc5ec2bea59f\
+                 d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn double_map() {
+        // `Staking.ErasStakers` for the era 5 and the "Alice" account.
+        let era = 5u32.to_le_bytes();
+        assert_eq!(
+            storage_key(
+                "Staking",
+                "ErasStakers",
+                [
+                    (StorageHasher::Twox64Concat, &era[..]),
+                    (StorageHasher::Twox64Concat, &ALICE[..])
+                ]
+            ),
+            hex::decode(
+                "5f3e4907f716ac89b6347d15ececedca8bde0a0ea8864605e3b68ed9cb2da01b\
+                 39b9d2792f8bd4c305000000\
+                 518366b5b1bc7c99d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn other_hashers() {
+        let prefix = storage_key("System", "Account", []);
+        let with_key = |hasher| storage_key("System", "Account", [(hasher, &b"abc"[..])]);
+
+        assert_eq!(with_key(StorageHasher::Identity)[32..], b"abc"[..]);
+        assert_eq!(
+            with_key(StorageHasher::Blake2_256)[32..],
+            hex::decode("bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319")
+                .unwrap()
+        );
+        assert_eq!(with_key(StorageHasher::Twox128).len(), 48);
+        assert_eq!(with_key(StorageHasher::Twox256).len(), 64);
+        assert_eq!(with_key(StorageHasher::Twox256)[..32], prefix[..]);
+        assert_eq!(
+            with_key(StorageHasher::Blake2_128)[32..],
+            with_key(StorageHasher::Blake2_128Concat)[32..48]
+        );
+    }
+
+    #[test]
+    fn polkadot_constants() {
+        let vm = crate::executor::host::HostVmPrototype::new(crate::executor::host::Config {
+            module: &include_bytes!("executor/vm/test-polkadot-runtime-v9160.wasm")[..],
+            heap_pages: crate::executor::DEFAULT_HEAP_PAGES,
+            exec_hint: crate::executor::vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+        let (output, _) =
+            crate::executor::call_runtime(vm, "Metadata_metadata", &[], |_| None).unwrap();
+        let metadata = crate::remove_metadata_length_prefix(&output).unwrap();
+
+        let existential_deposit =
+            pallet_constant(metadata, "Balances", "ExistentialDeposit").unwrap();
+        assert_eq!(existential_deposit.value, 10_000_000_000u128.to_le_bytes());
+
+        let block_hash_count = pallet_constant(metadata, "System", "BlockHashCount").unwrap();
+        assert_eq!(block_hash_count.value, 2400u32.to_le_bytes());
+
+        // Constants of the same type share the same type identifier.
+        let max_locks = pallet_constant(metadata, "Balances", "MaxLocks").unwrap();
+        assert_eq!(max_locks.type_id, block_hash_count.type_id);
+        assert_ne!(existential_deposit.type_id, block_hash_count.type_id);
+
+        assert_eq!(
+            pallet_constant(metadata, "Foo", "ExistentialDeposit"),
+            Err(PalletConstantError::PalletNotFound)
+        );
+        assert_eq!(
+            pallet_constant(metadata, "Balances", "Foo"),
+            Err(PalletConstantError::ConstantNotFound)
+        );
+        assert_eq!(
+            pallet_constant(&metadata[1..], "Balances", "ExistentialDeposit"),
+            Err(PalletConstantError::InvalidMetadata)
+        );
+    }
+}
@@ -183,6 +183,66 @@ impl<K, V, TNow, const ENTRIES_PER_BUCKET: usize> KBuckets<K, V, TNow, ENTRIES_P
             .iter_mut()
             .flat_map(|b| b.entries.iter_mut().map(|(k, v)| (&*k, v)))
     }
+
+    /// Returns a snapshot of the content of all the non-empty buckets, ordered by increasing
+    /// distance to the local key.
+    ///
+    /// This is a read-only traversal of the k-buckets, and is typically useful for diagnostic
+    /// purposes.
+    pub fn snapshot(&self) -> Vec<BucketSnapshot<'_, K, V, TNow>> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, bucket)| !bucket.entries.is_empty())
+            .map(|(distance, bucket)| BucketSnapshot {
+                distance: u8::try_from(distance).unwrap(),
+                entries: bucket
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (key, value))| BucketSnapshotEntry {
+                        key,
+                        value,
+                        state: if index < bucket.num_connected_entries {
+                            PeerState::Connected
+                        } else {
+                            PeerState::Disconnected
+                        },
+                    })
+                    .collect(),
+                pending_entry_expiration: bucket.pending_entry.as_ref(),
+            })
+            .collect()
+    }
+}
+
+/// Content of a bucket. See [`KBuckets::snapshot`].
+#[derive(Debug)]
+pub struct BucketSnapshot<'a, K, V, TNow> {
+    /// Log2 of the distance between the local key and the keys of this bucket. The hash of the
+    /// keys of the entries of this bucket differs from the hash of the local key at bit
+    /// `distance`, and all the bits above are identical.
+    pub distance: u8,
+
+    /// List of entries in the bucket, ordered by decreasing importance. Entries in the
+    /// [`PeerState::Connected`] state are always before the ones in the
+    /// [`PeerState::Disconnected`] state.
+    pub entries: Vec<BucketSnapshotEntry<'a, K, V>>,
+
+    /// If `Some`, the last entry in [`BucketSnapshot::entries`] is going to get kicked out after
+    /// this moment if a new entry is inserted.
+    pub pending_entry_expiration: Option<&'a TNow>,
+}
+
+/// Entry in a [`BucketSnapshot`].
+#[derive(Debug)]
+pub struct BucketSnapshotEntry<'a, K, V> {
+    /// Key of the entry, as passed when it has been inserted.
+    pub key: &'a K,
+    /// Value associated with the key.
+    pub value: &'a V,
+    /// Current state of the entry.
+    pub state: PeerState,
 }
 
 impl<K, V, TNow, const ENTRIES_PER_BUCKET: usize> fmt::Debug
@@ -366,6 +426,7 @@ where
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum PeerState {
     Connected,
     Disconnected,
@@ -558,5 +619,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn snapshot_reflects_insertions() {
+        let local_key = vec![0u8; 4];
+        let mut buckets =
+            super::KBuckets::<_, _, _, 32>::new(local_key.clone(), Duration::from_secs(1));
+
+        let mut inserted = Vec::new();
+        for n in 0..32u8 {
+            let key = vec![n; 4];
+            if key == local_key {
+                continue;
+            }
+
+            let state = if n % 2 == 0 {
+                super::PeerState::Connected
+            } else {
+                super::PeerState::Disconnected
+            };
+
+            if buckets
+                .entry(&key)
+                .or_insert(n, &Duration::new(0, 0), state)
+                .is_ok()
+            {
+                inserted.push((key, n, state));
+            }
+        }
+
+        let snapshot = buckets.snapshot();
+        assert_eq!(
+            snapshot.iter().map(|b| b.entries.len()).sum::<usize>(),
+            inserted.len()
+        );
+        assert!(snapshot.windows(2).all(|w| w[0].distance < w[1].distance));
+
+        let local_key_hashed = super::Key::new(&local_key);
+        for (key, value, state) in inserted {
+            let expected_distance =
+                super::distance_log2(&local_key_hashed, &super::Key::new(&key)).unwrap();
+            let bucket = snapshot
+                .iter()
+                .find(|b| b.distance == expected_distance)
+                .unwrap();
+            let entry = bucket.entries.iter().find(|e| *e.key == key).unwrap();
+            assert_eq!(*entry.value, value);
+            assert_eq!(entry.state, state);
+        }
+
+        for bucket in &snapshot {
+            let num_connected = bucket
+                .entries
+                .iter()
+                .take_while(|e| e.state == super::PeerState::Connected)
+                .count();
+            assert!(bucket.entries[num_connected..]
+                .iter()
+                .all(|e| e.state == super::PeerState::Disconnected));
+        }
+    }
+
     // TODO: a lot of tests
 }
@@ -16,6 +16,7 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use super::{schema, ProtobufDecodeError};
+use crate::{finality::justification, header};
 
 use alloc::{borrow::ToOwned as _, vec::Vec};
 use core::{iter, num::NonZeroU32};
@@ -284,6 +285,59 @@ pub fn decode_block_response(
     Ok(blocks)
 }
 
+/// Verifies that the fields of a [`BlockData`] received from the network are consistent with
+/// each other.
+///
+/// If a header is present, this function verifies that the hash of the header matches
+/// [`BlockData::hash`], that the body (if any) matches the extrinsics root of the header, and
+/// that the GRANDPA justification (if any) can be decoded and targets this block.
+///
+/// Fields that are `None` aren't checked. In particular, the body isn't checked if it wasn't
+/// requested. Since the body and justifications can only be verified against a header, nothing
+/// is checked if [`BlockData::header`] is `None`.
+///
+/// > **Note**: This function doesn't verify the signatures of the justification, as this requires
+/// >           knowing the list of authorities of the chain.
+pub fn verify_block_data(block: &BlockData) -> Result<(), VerifyBlockDataError> {
+    let scale_encoded_header = match &block.header {
+        Some(h) => h,
+        None => return Ok(()),
+    };
+
+    let decoded_header =
+        header::decode(scale_encoded_header).map_err(VerifyBlockDataError::InvalidHeader)?;
+
+    if header::hash_from_scale_encoded_header(scale_encoded_header) != block.hash {
+        return Err(VerifyBlockDataError::InvalidHash);
+    }
+
+    if let Some(body) = &block.body {
+        let calculated = header::extrinsics_root(&body[..]);
+        if calculated != *decoded_header.extrinsics_root {
+            return Err(VerifyBlockDataError::ExtrinsicsRootMismatch {
+                calculated,
+                in_header: *decoded_header.extrinsics_root,
+            });
+        }
+    }
+
+    for (consensus_engine, scale_encoded_justification) in block.justifications.iter().flatten() {
+        if consensus_engine != b"FRNK" {
+            continue;
+        }
+
+        let decoded = justification::decode::decode_grandpa(scale_encoded_justification)
+            .map_err(VerifyBlockDataError::InvalidGrandpaJustification)?;
+        if *decoded.target_hash != block.hash
+            || u64::from(decoded.target_number) != decoded_header.number
+        {
+            return Err(VerifyBlockDataError::JustificationTargetMismatch);
+        }
+    }
+
+    Ok(())
+}
+
 /// Block sent in a block response.
 ///
 /// > **Note**: Assuming that this response comes from the network, the information in this struct
@@ -347,10 +401,33 @@ pub enum DecodeBlockResponseError {
     InvalidJustifications,
 }
 
+/// Error potentially returned by [`verify_block_data`].
+#[derive(Debug, derive_more::Display)]
+pub enum VerifyBlockDataError {
+    /// The header has an invalid format.
+    #[display(fmt = "Invalid header: {}", _0)]
+    InvalidHeader(header::Error),
+    /// The hash of the header doesn't match the hash provided by the remote.
+    InvalidHash,
+    /// The header has an extrinsics root that doesn't match the body.
+    #[display(fmt = "The header has an extrinsics root that doesn't match the body")]
+    ExtrinsicsRootMismatch {
+        /// Extrinsics root that was calculated from the body.
+        calculated: [u8; 32],
+        /// Extrinsics root found in the header.
+        in_header: [u8; 32],
+    },
+    /// The GRANDPA justification has an invalid format.
+    #[display(fmt = "Invalid GRANDPA justification: {}", _0)]
+    InvalidGrandpaJustification(justification::decode::Error),
+    /// The GRANDPA justification doesn't target the block it was provided with.
+    JustificationTargetMismatch,
+}
+
 fn justifications<'a, E: nom::error::ParseError<&'a [u8]>>(
     bytes: &'a [u8],
 ) -> nom::IResult<&'a [u8], Vec<([u8; 4], Vec<u8>)>, E> {
-    nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+    nom::combinator::flat_map(crate::util::nom_scale_collection_len(5), |num_elems| {
         nom::multi::many_m_n(
             num_elems,
             num_elems,
@@ -369,3 +446,97 @@ fn justifications<'a, E: nom::error::ParseError<&'a [u8]>>(
         )
     })(bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::header;
+
+    fn grandpa_justification(target_hash: &[u8; 32], target_number: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&1u64.to_le_bytes());
+        out.extend_from_slice(target_hash);
+        out.extend_from_slice(&target_number.to_le_bytes());
+        out.push(0); // Number of precommits.
+        out.push(0); // Number of votes ancestries.
+        out
+    }
+
+    fn valid_block() -> super::BlockData {
+        let body = vec![vec![1, 2, 3], vec![4, 5]];
+        let header = header::Header {
+            parent_hash: [1; 32],
+            number: 5,
+            state_root: [2; 32],
+            extrinsics_root: header::extrinsics_root(&body[..]),
+            digest: header::DigestRef::empty().into(),
+        };
+
+        super::BlockData {
+            hash: header.hash(),
+            header: Some(header.scale_encoding_vec()),
+            body: Some(body),
+            justifications: Some(vec![(*b"FRNK", grandpa_justification(&header.hash(), 5))]),
+        }
+    }
+
+    #[test]
+    fn valid_block_data() {
+        let block = valid_block();
+        super::verify_block_data(&block).unwrap();
+
+        let encoded =
+            super::build_block_response(vec![block.clone()]).fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+        let decoded = super::decode_block_response(&encoded).unwrap();
+        assert_eq!(decoded, vec![block]);
+        super::verify_block_data(&decoded[0]).unwrap();
+    }
+
+    #[test]
+    fn tampered_body() {
+        let mut block = valid_block();
+        block.body.as_mut().unwrap()[1].push(6);
+        assert!(matches!(
+            super::verify_block_data(&block),
+            Err(super::VerifyBlockDataError::ExtrinsicsRootMismatch { .. })
+        ));
+
+        let mut block = valid_block();
+        block.body.as_mut().unwrap().pop();
+        assert!(matches!(
+            super::verify_block_data(&block),
+            Err(super::VerifyBlockDataError::ExtrinsicsRootMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn body_not_requested() {
+        let mut block = valid_block();
+        block.body = None;
+        super::verify_block_data(&block).unwrap();
+    }
+
+    #[test]
+    fn malformed_justification() {
+        let mut block = valid_block();
+        block.justifications.as_mut().unwrap()[0].1.push(0);
+        assert!(matches!(
+            super::verify_block_data(&block),
+            Err(super::VerifyBlockDataError::InvalidGrandpaJustification(_))
+        ));
+
+        let mut block = valid_block();
+        block.justifications = Some(vec![(*b"FRNK", grandpa_justification(&[0; 32], 5))]);
+        assert!(matches!(
+            super::verify_block_data(&block),
+            Err(super::VerifyBlockDataError::JustificationTargetMismatch)
+        ));
+
+        // Justifications of unknown consensus engines are ignored.
+        let mut block = valid_block();
+        block.justifications = Some(vec![(*b"abcd", vec![0xff])]);
+        super::verify_block_data(&block).unwrap();
+    }
+}
@@ -79,7 +79,7 @@ pub fn decode_call_proof_response(
     // The proof itself is a SCALE-encoded `Vec<Vec<u8>>`.
     // Each inner `Vec<u8>` is a node value in the storage trie.
     let (_, decoded) = nom::combinator::all_consuming(nom::combinator::flat_map(
-        crate::util::nom_scale_compact_usize,
+        crate::util::nom_scale_collection_len(1),
         |num_elems| {
             nom::multi::many_m_n(
                 num_elems,
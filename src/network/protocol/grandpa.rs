@@ -311,21 +311,25 @@ fn catch_up(bytes: &[u8]) -> nom::IResult<&[u8], CatchUpRef> {
             nom::sequence::tuple((
                 nom::number::complete::le_u64,
                 nom::number::complete::le_u64,
-                nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
-                    nom::multi::many_m_n(num_elems, num_elems, prevote)
-                }),
-                nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
-                    nom::multi::many_m_n(num_elems, num_elems, |s| {
-                        crate::finality::justification::decode::PrecommitRef::decode_partial(s)
-                            .map(|(a, b)| (b, a))
-                            .map_err(|_| {
-                                nom::Err::Failure(nom::error::make_error(
-                                    s,
-                                    nom::error::ErrorKind::Verify,
-                                ))
-                            })
-                    })
-                }),
+                nom::combinator::flat_map(
+                    crate::util::nom_scale_collection_len(132),
+                    |num_elems| nom::multi::many_m_n(num_elems, num_elems, prevote),
+                ),
+                nom::combinator::flat_map(
+                    crate::util::nom_scale_collection_len(132),
+                    |num_elems| {
+                        nom::multi::many_m_n(num_elems, num_elems, |s| {
+                            crate::finality::justification::decode::PrecommitRef::decode_partial(s)
+                                .map(|(a, b)| (b, a))
+                                .map_err(|_| {
+                                    nom::Err::Failure(nom::error::make_error(
+                                        s,
+                                        nom::error::ErrorKind::Verify,
+                                    ))
+                                })
+                        })
+                    },
+                ),
                 nom::bytes::complete::take(32u32),
                 nom::number::complete::le_u32,
             )),
@@ -90,7 +90,7 @@ pub fn decode_grandpa_warp_sync_response(
 }
 
 fn decode_fragments(bytes: &[u8]) -> nom::IResult<&[u8], Vec<GrandpaWarpSyncResponseFragment>> {
-    nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+    nom::combinator::flat_map(crate::util::nom_scale_collection_len(1), |num_elems| {
         nom::multi::many_m_n(num_elems, num_elems, decode_fragment)
     })(bytes)
 }
@@ -15,10 +15,10 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-// TODO: also implement the "proof" way to request the state
 // TODO: support child trie requests
 
 use super::{schema, ProtobufDecodeError};
+use crate::trie::{self, compact_proof, proof_verify};
 
 use alloc::{vec, vec::Vec};
 use core::iter;
@@ -36,6 +36,13 @@ pub struct StateRequestConfig {
     /// >           requests that start where the previous response has ended.
     // TODO: use a slice, maybe?
     pub start_key: Vec<u8>,
+
+    /// If `true`, the response is requested to contain a proof of its entries, which can then
+    /// be checked with [`verify_state_response_chunk`].
+    ///
+    /// > **Note**: Substrate doesn't send any entry alongside the proof. The entries are instead
+    /// >           found in the proof.
+    pub include_proof: bool,
 }
 
 /// Builds the bytes corresponding to a state request.
@@ -46,7 +53,7 @@ pub fn build_state_request(config: StateRequestConfig) -> impl Iterator<Item = i
     let request = schema::StateRequest {
         block: config.block_hash.to_vec(),
         start: vec![config.start_key],
-        no_proof: true,
+        no_proof: !config.include_proof,
     };
 
     let request_bytes = {
@@ -80,6 +87,169 @@ pub fn decode_state_response(
     Ok(entries)
 }
 
+/// Decodes a response to a state request, including the proof and the `complete` flag.
+///
+/// The entries of the response are only checked against the proof when calling
+/// [`verify_state_response_chunk`].
+pub fn decode_state_response_chunk(
+    response_bytes: &[u8],
+) -> Result<StateResponseChunk, DecodeStateResponseError> {
+    let response = schema::StateResponse::decode(response_bytes)
+        .map_err(ProtobufDecodeError)
+        .map_err(DecodeStateResponseError::ProtobufDecode)?;
+
+    // Only the main trie is supported at the moment. Entries of child tries are ignored.
+    let (entries, complete) = match response
+        .entries
+        .into_iter()
+        .find(|e| e.state_root.is_empty())
+    {
+        Some(top_trie) => (
+            top_trie
+                .entries
+                .into_iter()
+                .map(|entry| StateResponseEntry {
+                    key: entry.key,
+                    value: entry.value,
+                })
+                .collect(),
+            top_trie.complete,
+        ),
+        None => (Vec::new(), false),
+    };
+
+    Ok(StateResponseChunk {
+        entries,
+        proof: response.proof,
+        complete,
+    })
+}
+
+/// Chunk of the state of a block, as sent in a state response.
+///
+/// > **Note**: Assuming that this response comes from the network, the information in this struct
+/// >           can be erroneous and shouldn't be trusted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateResponseChunk {
+    /// Entries of the storage, supposedly ordered by key.
+    ///
+    /// When a proof is requested, the response typically doesn't contain any entry, and the
+    /// entries are instead found in [`StateResponseChunk::proof`].
+    pub entries: Vec<StateResponseEntry>,
+    /// SCALE-encoded compact trie proof (see [the `compact_proof` module](compact_proof)) of
+    /// the entries. Empty if no proof was requested.
+    pub proof: Vec<u8>,
+    /// `true` if the source pretends that there isn't any storage entry after the ones in
+    /// [`StateResponseChunk::entries`].
+    pub complete: bool,
+}
+
+/// Configuration for [`verify_state_response_chunk`].
+pub struct VerifyStateResponseChunkConfig<'a> {
+    /// Merkle value of the root of the storage trie of the block the request was made against.
+    pub state_trie_root: &'a [u8; 32],
+    /// Value that was passed as [`StateRequestConfig::start_key`]. Must be empty for the first
+    /// chunk, and equal to the key of the last entry of the previous chunk for the next ones.
+    pub start_key: &'a [u8],
+    /// Chunk to verify.
+    pub chunk: &'a StateResponseChunk,
+}
+
+/// Verifies, using its proof, that a chunk contains all the storage entries that directly follow
+/// [`VerifyStateResponseChunkConfig::start_key`], in order and without any gap.
+///
+/// If [`StateResponseChunk::entries`] is empty, the entries are extracted from the proof, as
+/// done by Substrate. Otherwise, they must match the entries found in the proof, and
+/// [`StateResponseChunk::complete`] must be accurate.
+pub fn verify_state_response_chunk(
+    config: VerifyStateResponseChunkConfig,
+) -> Result<VerifiedStateResponseChunk, VerifyStateResponseChunkError> {
+    let compact_proof = trie::decode_proof(&config.chunk.proof)
+        .map_err(|()| VerifyStateResponseChunkError::ProofDecodeError)?;
+
+    let proof = compact_proof::decode_and_verify(compact_proof::Config {
+        trie_root_hash: config.state_trie_root,
+        proof: compact_proof.into_iter(),
+    })
+    .map_err(VerifyStateResponseChunkError::InvalidCompactProof)?;
+
+    let proven = proof_verify::entries_after(proof_verify::EntriesAfterConfig {
+        key_before: config.start_key,
+        trie_root_hash: config.state_trie_root,
+        proof: proof.iter().map(|node_value| &node_value[..]),
+    })
+    .map_err(VerifyStateResponseChunkError::InvalidProof)?;
+
+    if !config.chunk.entries.is_empty() {
+        // The entries of the chunk must be exactly the ones found in the proof.
+        if config.chunk.entries.len() != proven.entries.len() {
+            return Err(VerifyStateResponseChunkError::NotContiguous);
+        }
+        for (entry, (key, value)) in config.chunk.entries.iter().zip(proven.entries.iter()) {
+            if entry.key != *key {
+                return Err(VerifyStateResponseChunkError::NotContiguous);
+            }
+            if entry.value != *value {
+                return Err(VerifyStateResponseChunkError::ValueMismatch);
+            }
+        }
+        if config.chunk.complete && !proven.complete {
+            return Err(VerifyStateResponseChunkError::NotComplete);
+        }
+    }
+
+    if proven.entries.is_empty() && !proven.complete {
+        // A chunk with no entry that isn't complete doesn't make any progress.
+        return Err(VerifyStateResponseChunkError::EmptyChunk);
+    }
+
+    Ok(VerifiedStateResponseChunk {
+        entries: proven
+            .entries
+            .into_iter()
+            .map(|(key, value)| StateResponseEntry {
+                key,
+                value: value.to_vec(),
+            })
+            .collect(),
+        complete: proven.complete,
+    })
+}
+
+/// Chunk of the state of a block whose entries have been verified. Returned by
+/// [`verify_state_response_chunk`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedStateResponseChunk {
+    /// Storage entries that directly follow [`VerifyStateResponseChunkConfig::start_key`],
+    /// ordered by key.
+    pub entries: Vec<StateResponseEntry>,
+    /// `true` if there isn't any storage entry after the ones in
+    /// [`VerifiedStateResponseChunk::entries`]. If `false`, the next chunk can be requested by
+    /// passing the key of the last entry as [`StateRequestConfig::start_key`].
+    pub complete: bool,
+}
+
+/// Error potentially returned by [`verify_state_response_chunk`].
+#[derive(Debug, derive_more::Display)]
+pub enum VerifyStateResponseChunkError {
+    /// Failed to decode the proof.
+    ProofDecodeError,
+    /// Failed to decode the compact proof or to match it with the state trie root.
+    #[display(fmt = "{}", _0)]
+    InvalidCompactProof(compact_proof::Error),
+    /// Proof doesn't contain the information necessary to verify the chunk.
+    #[display(fmt = "{}", _0)]
+    InvalidProof(proof_verify::Error),
+    /// The entries of the chunk don't directly follow the start key and each other.
+    NotContiguous,
+    /// The value of an entry doesn't match the one in the proof.
+    ValueMismatch,
+    /// The chunk is marked as complete, but there exists storage entries after it.
+    NotComplete,
+    /// The chunk is neither complete nor contains any entry.
+    EmptyChunk,
+}
+
 /// Entry sent in a state response.
 ///
 /// > **Note**: Assuming that this response comes from the network, the information in this struct
@@ -98,3 +268,179 @@ pub enum DecodeStateResponseError {
     /// Error while decoding the protobuf encoding.
     ProtobufDecode(ProtobufDecodeError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::schema;
+    use crate::trie;
+    use prost::Message as _;
+
+    fn storage() -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..10u8)
+            .map(|n| (vec![n * 13, n], vec![n; 40 + usize::from(n)]))
+            .collect()
+    }
+
+    /// Builds the bytes of a state response containing the given entries and a compact proof of
+    /// the given keys.
+    fn build_response(
+        storage: &[(Vec<u8>, Vec<u8>)],
+        state_trie_root: &[u8; 32],
+        entries: &[(Vec<u8>, Vec<u8>)],
+        proven_keys: &[&[u8]],
+        complete: bool,
+    ) -> Vec<u8> {
+        let proof = trie::proof_generate::generate_read_proof(trie::proof_generate::Config {
+            keys: proven_keys.iter(),
+            storage_entries: storage.iter().map(|(k, v)| (&k[..], &v[..])),
+            trie_root_hash: state_trie_root,
+        })
+        .unwrap();
+        let proof = trie::decode_proof(&proof).unwrap();
+
+        let compact = trie::compact_proof::encode(state_trie_root, &proof);
+        let mut proof = crate::util::encode_scale_compact_usize(compact.len())
+            .as_ref()
+            .to_vec();
+        for node in compact {
+            proof.extend_from_slice(crate::util::encode_scale_compact_usize(node.len()).as_ref());
+            proof.extend_from_slice(&node);
+        }
+
+        let response = schema::StateResponse {
+            entries: if entries.is_empty() {
+                Vec::new()
+            } else {
+                vec![schema::KeyValueStateEntry {
+                    state_root: Vec::new(),
+                    entries: entries
+                        .iter()
+                        .map(|(key, value)| schema::StateEntry {
+                            key: key.clone(),
+                            value: value.clone(),
+                        })
+                        .collect(),
+                    complete,
+                }]
+            },
+            proof,
+        };
+
+        let mut buf = Vec::with_capacity(response.encoded_len());
+        response.encode(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn two_chunks() {
+        let storage = storage();
+        let state_trie_root = trie::trie_root(&storage);
+        let all_keys = storage.iter().map(|(k, _)| &k[..]).collect::<Vec<_>>();
+
+        // First chunk: the first six entries, starting from the beginning of the state. As done
+        // by Substrate, the response doesn't contain any entry besides the proof.
+        let response = build_response(&storage, &state_trie_root, &[], &all_keys[..6], false);
+        let chunk1 = super::decode_state_response_chunk(&response).unwrap();
+        assert!(chunk1.entries.is_empty());
+        let verified1 = super::verify_state_response_chunk(super::VerifyStateResponseChunkConfig {
+            state_trie_root: &state_trie_root,
+            start_key: &[],
+            chunk: &chunk1,
+        })
+        .unwrap();
+        assert_eq!(verified1.entries.len(), 6);
+        assert!(!verified1.complete);
+
+        // Second chunk: the rest of the entries.
+        let start_key = &verified1.entries.last().unwrap().key;
+        let response = build_response(&storage, &state_trie_root, &[], &all_keys[5..], false);
+        let chunk2 = super::decode_state_response_chunk(&response).unwrap();
+        let verified2 = super::verify_state_response_chunk(super::VerifyStateResponseChunkConfig {
+            state_trie_root: &state_trie_root,
+            start_key,
+            chunk: &chunk2,
+        })
+        .unwrap();
+        assert!(verified2.complete);
+
+        let assembled = verified1
+            .entries
+            .into_iter()
+            .chain(verified2.entries)
+            .map(|entry| (entry.key, entry.value))
+            .collect::<Vec<_>>();
+        assert_eq!(assembled, storage);
+    }
+
+    #[test]
+    fn entries_checked_against_proof() {
+        let storage = storage();
+        let state_trie_root = trie::trie_root(&storage);
+        let all_keys = storage.iter().map(|(k, _)| &k[..]).collect::<Vec<_>>();
+
+        let verify = |response: &[u8]| {
+            let chunk = super::decode_state_response_chunk(response).unwrap();
+            super::verify_state_response_chunk(super::VerifyStateResponseChunkConfig {
+                state_trie_root: &state_trie_root,
+                start_key: &[],
+                chunk: &chunk,
+            })
+        };
+
+        // Entries matching the proof.
+        let response = build_response(
+            &storage,
+            &state_trie_root,
+            &storage[..6],
+            &all_keys[..6],
+            false,
+        );
+        assert_eq!(verify(&response).unwrap().entries.len(), 6);
+
+        // Entry number 3 is missing from the chunk.
+        let entries = storage[..3]
+            .iter()
+            .chain(storage[4..6].iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        let response = build_response(&storage, &state_trie_root, &entries, &all_keys[..6], false);
+        assert!(matches!(
+            verify(&response),
+            Err(super::VerifyStateResponseChunkError::NotContiguous)
+        ));
+
+        // The value of entry number 2 is wrong.
+        let mut entries = storage[..6].to_vec();
+        entries[2].1[0] ^= 0xff;
+        let response = build_response(&storage, &state_trie_root, &entries, &all_keys[..6], false);
+        assert!(matches!(
+            verify(&response),
+            Err(super::VerifyStateResponseChunkError::ValueMismatch)
+        ));
+
+        // The chunk pretends to be complete while it isn't.
+        let response = build_response(
+            &storage,
+            &state_trie_root,
+            &storage[..6],
+            &all_keys[..6],
+            true,
+        );
+        assert!(matches!(
+            verify(&response),
+            Err(super::VerifyStateResponseChunkError::NotComplete)
+        ));
+
+        // The proof is for a different state.
+        let other_root = trie::trie_root(&storage[1..]);
+        let chunk = super::decode_state_response_chunk(&response).unwrap();
+        assert!(matches!(
+            super::verify_state_response_chunk(super::VerifyStateResponseChunkConfig {
+                state_trie_root: &other_root,
+                start_key: &[],
+                chunk: &chunk,
+            }),
+            Err(super::VerifyStateResponseChunkError::InvalidCompactProof(_))
+        ));
+    }
+}
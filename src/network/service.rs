@@ -26,9 +26,10 @@ use crate::util::{self, SipHasherBuild};
 
 use alloc::{
     borrow::Cow,
+    collections::BTreeSet,
     format,
-    string::{String, ToString as _},
-    vec::Vec,
+    string::String,
+    vec::{self, Vec},
 };
 use core::{
     fmt, iter, mem,
@@ -46,8 +47,17 @@ pub use crate::libp2p::{
     collection::ReadWrite,
     peers::{ConnectionId, InboundError},
 };
+pub use ip_filter::IpRange;
+pub use stats::{MessagesStats, ProtocolsStats};
 
 mod addresses;
+mod announces_filter;
+mod connections_limits;
+mod idle_connections;
+mod ip_filter;
+mod observed_addrs;
+mod penalties;
+mod stats;
 
 /// Configuration for a [`ChainNetwork`].
 pub struct Config<TNow> {
@@ -86,6 +96,10 @@ pub struct Config<TNow> {
     /// and must be aborted.
     pub handshake_timeout: Duration,
 
+    /// Amount of time after which an inbound substream whose protocol hasn't been negotiated
+    /// is considered to have taken too long and is reset.
+    pub inbound_negotiation_timeout: Duration,
+
     /// Maximum number of addresses kept in memory per network identity.
     ///
     /// > **Note**: As the number of network identities kept in memory is capped, having a
@@ -107,6 +121,69 @@ pub struct Config<TNow> {
     /// This value is important if [`ChainNetwork::next_event`] is called at a slower than the
     /// calls to [`ChainNetwork::read_write`] generate events.
     pub pending_api_events_buffer_size: NonZeroUsize,
+
+    /// Maximum number of block announces to remember in order to detect duplicates.
+    ///
+    /// Block announces received from a peer that are identical to a block announce received
+    /// from the same peer less than [`Config::block_announces_dedup_window`] ago are silently
+    /// discarded. A value of 0 disables this mechanism.
+    pub block_announces_dedup_capacity: usize,
+
+    /// See [`Config::block_announces_dedup_capacity`].
+    ///
+    /// Must be short enough that a block legitimately announced again, for example after a
+    /// reorg, isn't ignored.
+    pub block_announces_dedup_window: Duration,
+
+    /// List of IP address ranges that the local node never connects to and never accepts
+    /// connections from.
+    ///
+    /// Addresses belonging to these ranges are ignored when passed to [`ChainNetwork::discover`]
+    /// or discovered through Kademlia, and incoming connections from these ranges are refused by
+    /// [`ChainNetwork::add_incoming_connection`].
+    ///
+    /// See also [`IpRange::LOOPBACK`] and [`IpRange::PRIVATE`].
+    pub blocked_ip_ranges: Vec<IpRange>,
+
+    /// Maximum number of established incoming connections, not counting the connections that
+    /// use one of the [`Config::reserved_slots`].
+    ///
+    /// Since the identity of the remote isn't known before the handshake is finished, this limit
+    /// is enforced once an incoming connection is established. If the limit is exceeded, the
+    /// least valuable incoming connection that isn't with a peer of [`Config::reserved_peers`]
+    /// is closed. Connections with peers that have a slot in more chains are more valuable, and,
+    /// in case of equality, older connections are more valuable.
+    pub max_inbound_connections: usize,
+
+    /// Maximum number of established outgoing connections, plus outgoing connections being
+    /// opened, not counting the connections that use one of the [`Config::reserved_slots`].
+    ///
+    /// [`ChainNetwork::next_start_connect`] doesn't start any new connection as long as this
+    /// limit is reached.
+    pub max_outbound_connections: usize,
+
+    /// List of peers that are explicitly trusted by the local node.
+    ///
+    /// Connections with these peers, incoming or outgoing, use one of the
+    /// [`Config::reserved_slots`] if any is free, and then don't count towards
+    /// [`Config::max_inbound_connections`] and [`Config::max_outbound_connections`].
+    /// Connections with these peers are never closed in order to enforce
+    /// [`Config::max_inbound_connections`].
+    pub reserved_peers: Vec<PeerId>,
+
+    /// Number of connections, in addition to [`Config::max_inbound_connections`] and
+    /// [`Config::max_outbound_connections`], that are reserved to the peers of
+    /// [`Config::reserved_peers`].
+    pub reserved_slots: usize,
+
+    /// Duration after which an established connection is closed if it doesn't have any open
+    /// notifications substream and no request or notification has been exchanged on it.
+    /// `None` if idle connections are never closed.
+    ///
+    /// Connections with peers of [`Config::reserved_peers`], connections on which a ping has
+    /// succeeded in the past few seconds, and connections that are still handshaking are never
+    /// closed because of this timeout.
+    pub idle_connection_timeout: Option<Duration>,
 }
 
 /// Configuration for a specific overlay network.
@@ -134,7 +211,19 @@ pub struct ChainConfig {
     /// Height of the best block according to the local node.
     pub best_number: u64,
     /// Hash of the genesis block (i.e. block number 0) according to the local node.
+    ///
+    /// The names of all the protocols of this chain, with the exception of Kademlia, are derived
+    /// from this value, in the form
+    /// `/<hex genesis hash>/<protocol>`. Substreams opened by peers using the protocol name of a
+    /// different genesis hash are refused.
     pub genesis_hash: [u8; 32],
+
+    /// If `true`, the legacy protocol names, derived from [`ChainConfig::protocol_id`] rather
+    /// than [`ChainConfig::genesis_hash`], are accepted as well for incoming substreams, and are
+    /// tried for outgoing substreams if the remote doesn't support the protocol names derived
+    /// from the genesis hash.
+    pub accept_legacy_protocol_names: bool,
+
     pub role: protocol::Role,
 }
 
@@ -165,10 +254,13 @@ pub struct ChainNetwork<TNow> {
     /// See [`Config::max_addresses_per_peer`].
     max_addresses_per_peer: NonZeroUsize,
 
+    /// See [`Config::blocked_ip_ranges`].
+    blocked_ip_ranges: Vec<IpRange>,
+
     /// Extra fields protected by a `Mutex` and that relate to the logic in
     /// [`ChainNetwork::next_event`]. Must only be locked within that method and is kept locked
     /// throughout that method.
-    next_event_guarded: Mutex<NextEventGuarded>,
+    next_event_guarded: Mutex<NextEventGuarded<TNow>>,
 
     /// Extra fields protected by a `Mutex` and that are briefly accessed.
     ephemeral_guarded: Mutex<EphemeralGuarded<TNow>>,
@@ -176,6 +268,12 @@ pub struct ChainNetwork<TNow> {
     /// Number of chains. Equal to the length of [`EphemeralGuarded::chains`].
     num_chains: usize,
 
+    /// For each chain, statistics about the messages exchanged on its protocols. See
+    /// [`ChainNetwork::protocol_stats`].
+    ///
+    /// The `Vec` always has the same length as [`Config::chains`].
+    stats: Vec<stats::ChainStats>,
+
     /// Generator for randomness.
     randomness: Mutex<rand_chacha::ChaCha20Rng>,
 
@@ -185,7 +283,7 @@ pub struct ChainNetwork<TNow> {
 }
 
 /// See [`ChainNetwork::next_event_guarded`].
-struct NextEventGuarded {
+struct NextEventGuarded<TNow> {
     /// In the [`ChainNetwork::next_event`] function, an event is grabbed from the underlying
     /// [`peers::Peers`]. This event might lead to some asynchronous post-processing being
     /// needed. Because the user can interrupt the future returned by [`ChainNetwork::next_event`]
@@ -200,6 +298,10 @@ struct NextEventGuarded {
     /// Some substreams might have been opened and have been left out of this map if their
     /// handshake was invalid, or had a different genesis hash, or similar problem.
     open_chains: hashbrown::HashSet<(PeerId, usize), SipHasherBuild>,
+
+    /// Filter used to discard duplicate block announces.
+    /// See [`Config::block_announces_dedup_capacity`].
+    block_announces_filter: announces_filter::AnnouncesFilter<TNow>,
 }
 
 /// See [`ChainNetwork::ephemeral_guarded`].
@@ -216,6 +318,20 @@ struct EphemeralGuarded<TNow> {
     /// List of all open connections.
     connections: hashbrown::HashSet<PeerId, SipHasherBuild>,
 
+    /// Established connections, and limits on their number. See
+    /// [`Config::max_inbound_connections`] and [`Config::max_outbound_connections`].
+    connections_limits: connections_limits::ConnectionsLimits<ConnectionId>,
+
+    /// Established connections and their latest activity. See
+    /// [`Config::idle_connection_timeout`].
+    idle_connections: idle_connections::IdleConnections<ConnectionId, TNow>,
+
+    /// Addresses of the local node, as reported by remotes through the identify protocol.
+    observed_addrs: observed_addrs::ObservedAddrs,
+
+    /// Peers that have misbehaved and that shouldn't be assigned a slot.
+    penalties: penalties::Penalties<TNow>,
+
     /// For each item in [`Config::chains`], the corresponding chain state.
     ///
     /// The `Vec` always has the same length as [`Config::chains`].
@@ -249,9 +365,137 @@ struct EphemeralGuardedChain<TNow> {
 
 // Update this when a new request response protocol is added.
 const REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN: usize = 5;
+/// Duration during which a peer that has misbehaved, for example by sending a response larger
+/// than allowed by the protocol, isn't assigned any slot. See [`ChainNetwork::penalize`].
+const MISBEHAVIOR_PENALTY: Duration = Duration::from_secs(5 * 60);
+/// Duration after a successful outgoing ping during which a connection isn't considered as idle.
+/// See [`Config::idle_connection_timeout`].
+// TODO: hardcoded; the ping interval itself is hardcoded in the `collection` module
+const IDLE_CONNECTION_RECENT_PING: Duration = Duration::from_secs(5);
 // Update this when a new notifications protocol is added.
 const NOTIFICATIONS_PROTOCOLS_PER_CHAIN: usize = 3;
 
+/// Builds the name of a protocol of the given chain, and its legacy name if
+/// [`ChainConfig::accept_legacy_protocol_names`] is `true`.
+///
+/// `legacy_name` is the full legacy name of the protocol, or `None` if the legacy name is
+/// `/<protocol_id>/<suffix>`.
+fn protocol_names(
+    chain: &ChainConfig,
+    suffix: &str,
+    legacy_name: Option<&str>,
+) -> (String, Vec<String>) {
+    let name = format!("/{}/{}", hex::encode(chain.genesis_hash), suffix);
+    let fallback_names = if chain.accept_legacy_protocol_names {
+        vec![match legacy_name {
+            Some(n) => n.to_owned(),
+            None => format!("/{}/{}", chain.protocol_id, suffix),
+        }]
+    } else {
+        Vec::new()
+    };
+    (name, fallback_names)
+}
+
+/// Returns the list of notification protocols of the given chain.
+///
+/// See [`NOTIFICATIONS_PROTOCOLS_PER_CHAIN`].
+fn chain_notification_protocols(
+    chain: &ChainConfig,
+) -> impl Iterator<Item = peers::NotificationProtocolConfig> {
+    let (block_announces, block_announces_fallbacks) =
+        protocol_names(chain, "block-announces/1", None);
+    let (transactions, transactions_fallbacks) = protocol_names(chain, "transactions/1", None);
+    let (grandpa, grandpa_fallbacks) =
+        protocol_names(chain, "grandpa/1", Some("/paritytech/grandpa/1"));
+
+    iter::once(peers::NotificationProtocolConfig {
+        protocol_name: block_announces,
+        fallback_protocol_names: block_announces_fallbacks,
+        max_handshake_size: 1024 * 1024, // TODO: arbitrary
+        handshake_timeout: Duration::from_secs(20),
+        max_notification_size: 1024 * 1024,
+    })
+    .chain(iter::once(peers::NotificationProtocolConfig {
+        protocol_name: transactions,
+        fallback_protocol_names: transactions_fallbacks,
+        max_handshake_size: 4,
+        handshake_timeout: Duration::from_secs(20),
+        max_notification_size: 16 * 1024 * 1024,
+    }))
+    .chain({
+        // The `has_grandpa_protocol` flag controls whether the chain uses GrandPa.
+        // Note, however, that GrandPa is technically left enabled (but unused) on all
+        // chains, in order to make the rest of the code of this module more
+        // comprehensible.
+        iter::once(peers::NotificationProtocolConfig {
+            protocol_name: grandpa,
+            fallback_protocol_names: grandpa_fallbacks,
+            max_handshake_size: 4,
+            handshake_timeout: Duration::from_secs(20),
+            max_notification_size: 1024 * 1024,
+        })
+    })
+}
+
+/// Returns the list of request-response protocols of the given chain.
+///
+/// See [`REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN`].
+fn chain_request_response_protocols(
+    chain: &ChainConfig,
+) -> impl Iterator<Item = peers::ConfigRequestResponse> {
+    let (sync, sync_fallbacks) = protocol_names(chain, "sync/2", None);
+    let (light, light_fallbacks) = protocol_names(chain, "light/2", None);
+    let (warp, warp_fallbacks) = protocol_names(chain, "sync/warp", None);
+    let (state, state_fallbacks) = protocol_names(chain, "state/2", None);
+
+    // TODO: limits are arbitrary
+    // Note that the maximum response sizes must be large enough to fit the largest legitimate
+    // response of each protocol, such as a state response, which Substrate caps to 16 MiB. Peers
+    // that send larger responses are penalized. See `ChainNetwork::request`.
+    iter::once(peers::ConfigRequestResponse {
+        name: sync,
+        fallback_names: sync_fallbacks,
+        inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 1024 },
+        max_response_size: 16 * 1024 * 1024,
+        inbound_allowed: chain.allow_inbound_block_requests,
+    })
+    .chain(iter::once(peers::ConfigRequestResponse {
+        name: light,
+        fallback_names: light_fallbacks,
+        inbound_config: peers::ConfigRequestResponseIn::Payload {
+            max_size: 1024 * 512,
+        },
+        max_response_size: 10 * 1024 * 1024,
+        // TODO: make this configurable
+        inbound_allowed: false,
+    }))
+    .chain(iter::once(peers::ConfigRequestResponse {
+        name: format!("/{}/kad", chain.protocol_id),
+        fallback_names: Vec::new(),
+        inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 1024 },
+        max_response_size: 1024 * 1024,
+        // TODO: `false` here means we don't insert ourselves in the DHT, which is the polite thing to do for as long as Kad isn't implemented
+        inbound_allowed: false,
+    }))
+    .chain(iter::once(peers::ConfigRequestResponse {
+        name: warp,
+        fallback_names: warp_fallbacks,
+        inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 32 },
+        max_response_size: 16 * 1024 * 1024,
+        // We don't support inbound warp sync requests (yet).
+        inbound_allowed: false,
+    }))
+    .chain(iter::once(peers::ConfigRequestResponse {
+        name: state,
+        fallback_names: state_fallbacks,
+        inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 1024 },
+        max_response_size: 16 * 1024 * 1024,
+        // We don't support inbound state requests (yet).
+        inbound_allowed: false,
+    }))
+}
+
 impl<TNow> ChainNetwork<TNow>
 where
     TNow: Clone + Add<Duration, Output = TNow> + Sub<TNow, Output = Duration> + Ord,
@@ -263,81 +507,24 @@ where
         let notification_protocols = config
             .chains
             .iter()
-            .flat_map(|chain| {
-                iter::once(peers::NotificationProtocolConfig {
-                    protocol_name: format!("/{}/block-announces/1", chain.protocol_id),
-                    fallback_protocol_names: Vec::new(),
-                    max_handshake_size: 1024 * 1024, // TODO: arbitrary
-                    max_notification_size: 1024 * 1024,
-                })
-                .chain(iter::once(peers::NotificationProtocolConfig {
-                    protocol_name: format!("/{}/transactions/1", chain.protocol_id),
-                    fallback_protocol_names: Vec::new(),
-                    max_handshake_size: 4,
-                    max_notification_size: 16 * 1024 * 1024,
-                }))
-                .chain({
-                    // The `has_grandpa_protocol` flag controls whether the chain uses GrandPa.
-                    // Note, however, that GrandPa is technically left enabled (but unused) on all
-                    // chains, in order to make the rest of the code of this module more
-                    // comprehensible.
-                    iter::once(peers::NotificationProtocolConfig {
-                        protocol_name: "/paritytech/grandpa/1".to_string(),
-                        fallback_protocol_names: Vec::new(),
-                        max_handshake_size: 4,
-                        max_notification_size: 1024 * 1024,
-                    })
-                })
-            })
+            .flat_map(chain_notification_protocols)
             .collect();
 
         // The order of protocols here is important, as it defines the values of `protocol_index`
         // to pass to libp2p or that libp2p produces.
         let request_response_protocols = iter::once(peers::ConfigRequestResponse {
             name: "/ipfs/id/1.0.0".into(),
+            fallback_names: Vec::new(),
             inbound_config: peers::ConfigRequestResponseIn::Empty,
             max_response_size: 4096,
             inbound_allowed: true,
         })
-        .chain(config.chains.iter().flat_map(|chain| {
-            // TODO: limits are arbitrary
-            iter::once(peers::ConfigRequestResponse {
-                name: format!("/{}/sync/2", chain.protocol_id),
-                inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 1024 },
-                max_response_size: 16 * 1024 * 1024,
-                inbound_allowed: chain.allow_inbound_block_requests,
-            })
-            .chain(iter::once(peers::ConfigRequestResponse {
-                name: format!("/{}/light/2", chain.protocol_id),
-                inbound_config: peers::ConfigRequestResponseIn::Payload {
-                    max_size: 1024 * 512,
-                },
-                max_response_size: 10 * 1024 * 1024,
-                // TODO: make this configurable
-                inbound_allowed: false,
-            }))
-            .chain(iter::once(peers::ConfigRequestResponse {
-                name: format!("/{}/kad", chain.protocol_id),
-                inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 1024 },
-                max_response_size: 1024 * 1024,
-                // TODO: `false` here means we don't insert ourselves in the DHT, which is the polite thing to do for as long as Kad isn't implemented
-                inbound_allowed: false,
-            }))
-            .chain(iter::once(peers::ConfigRequestResponse {
-                name: format!("/{}/sync/warp", chain.protocol_id),
-                inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 32 },
-                max_response_size: 16 * 1024 * 1024,
-                // We don't support inbound warp sync requests (yet).
-                inbound_allowed: false,
-            }))
-            .chain(iter::once(peers::ConfigRequestResponse {
-                name: format!("/{}/state/2", chain.protocol_id),
-                inbound_config: peers::ConfigRequestResponseIn::Payload { max_size: 1024 },
-                max_response_size: 16 * 1024 * 1024,
-                // We don't support inbound state requests (yet).
-                inbound_allowed: false,
-            }))
-        }))
+        .chain(
+            config
+                .chains
+                .iter()
+                .flat_map(chain_request_response_protocols),
+        )
         .collect();
 
         let mut randomness = rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed);
@@ -379,14 +566,24 @@ where
                 notification_protocols,
                 ping_protocol: "/ipfs/ping/1.0.0".into(),
                 handshake_timeout: config.handshake_timeout,
+                inbound_negotiation_timeout: config.inbound_negotiation_timeout,
             }),
             num_chains: chains.len(),
+            stats: chains
+                .iter()
+                .map(|_| stats::ChainStats::default())
+                .collect(),
             next_event_guarded: Mutex::new(NextEventGuarded {
                 to_process_pre_event: None,
                 open_chains: hashbrown::HashSet::with_capacity_and_hasher(
                     config.peers_capacity * chains.len(),
                     SipHasherBuild::new(randomness.gen()),
                 ),
+                block_announces_filter: announces_filter::AnnouncesFilter::new(
+                    config.block_announces_dedup_capacity,
+                    config.block_announces_dedup_window,
+                    SipHasherBuild::new(randomness.gen()),
+                ),
             }),
             ephemeral_guarded: Mutex::new(EphemeralGuarded {
                 num_pending_per_peer: hashbrown::HashMap::with_capacity_and_hasher(
@@ -398,10 +595,29 @@ where
                     config.peers_capacity,
                     SipHasherBuild::new(randomness.gen()),
                 ),
+                idle_connections: idle_connections::IdleConnections::new(
+                    config.idle_connection_timeout,
+                    IDLE_CONNECTION_RECENT_PING,
+                    config.reserved_peers.iter().cloned(),
+                    SipHasherBuild::new(randomness.gen()),
+                ),
+                connections_limits: connections_limits::ConnectionsLimits::new(
+                    config.max_inbound_connections,
+                    config.max_outbound_connections,
+                    config.reserved_slots,
+                    config.reserved_peers.into_iter(),
+                    SipHasherBuild::new(randomness.gen()),
+                ),
+                observed_addrs: observed_addrs::ObservedAddrs::new(16, 2),
+                penalties: penalties::Penalties::new(
+                    MISBEHAVIOR_PENALTY,
+                    SipHasherBuild::new(randomness.gen()),
+                ),
                 chains,
             }),
             handshake_timeout: config.handshake_timeout,
             max_addresses_per_peer: config.max_addresses_per_peer,
+            blocked_ip_ranges: config.blocked_ip_ranges,
             randomness: Mutex::new(randomness),
             start_connect_needed: event_listener::Event::new(),
         }
@@ -411,6 +627,87 @@ where
         1 + chain_index * REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN + protocol
     }
 
+    /// Sends a request to the given peer and waits for the response.
+    ///
+    /// If the peer announces a response larger than the maximum allowed by the protocol, the
+    /// response is never read, and the peer is penalized with [`ChainNetwork::penalize`].
+    async fn request(
+        &self,
+        now: TNow,
+        target: &PeerId,
+        protocol_index: usize,
+        request_data: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, peers::RequestError> {
+        self.ephemeral_guarded
+            .lock()
+            .await
+            .idle_connections
+            .report_activity(target, &now);
+
+        let result = self
+            .inner
+            .request(target, protocol_index, request_data, now.clone() + timeout)
+            .await;
+
+        if let Err(peers::RequestError::Connection(
+            connection::established::RequestError::ResponseTooLarge { .. },
+        )) = &result
+        {
+            self.penalize(now, target).await;
+        }
+
+        result
+    }
+
+    /// Penalizes the given peer, for example because it has sent invalid data.
+    ///
+    /// The slots of the peer are unassigned, and it isn't assigned any slot by
+    /// [`ChainNetwork::assign_slots`] during [`MISBEHAVIOR_PENALTY`]. Penalizing a peer that is
+    /// already penalized extends the penalty.
+    pub async fn penalize(&self, now: TNow, target: &PeerId) {
+        let mut ephemeral_guarded = self.ephemeral_guarded.lock().await;
+        ephemeral_guarded.penalties.penalize(&now, target);
+        for chain_index in 0..ephemeral_guarded.chains.len() {
+            // TODO: report as event or something; this is complicated because of futures cancellation issues, and because of concerns shown in `assign_slots`
+            self.unassign_slot(&mut *ephemeral_guarded, chain_index, target)
+                .await;
+        }
+    }
+
+    /// Updates [`ChainNetwork::stats`] after an event has been pulled from the underlying
+    /// state machine.
+    fn record_received(&self, event: &peers::Event<multiaddr::Multiaddr>) {
+        match event {
+            peers::Event::NotificationsIn {
+                notifications_protocol_index,
+                notification,
+                ..
+            } => {
+                let protocol =
+                    match notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN {
+                        0 => stats::Protocol::BlockAnnounces,
+                        1 => stats::Protocol::Transactions,
+                        2 => stats::Protocol::Grandpa,
+                        _ => unreachable!(),
+                    };
+                self.stats[notifications_protocol_index / NOTIFICATIONS_PROTOCOLS_PER_CHAIN]
+                    .record_received(protocol, notification.len());
+            }
+            peers::Event::RequestIn {
+                protocol_index,
+                request_payload,
+                ..
+            } if *protocol_index != 0
+                && ((*protocol_index - 1) % REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN) == 0 =>
+            {
+                self.stats[(*protocol_index - 1) / REQUEST_RESPONSE_PROTOCOLS_PER_CHAIN]
+                    .record_received(stats::Protocol::Sync, request_payload.len());
+            }
+            _ => {}
+        }
+    }
+
     /// Returns the number of established TCP connections, both incoming and outgoing.
     // TODO: note about race
     pub async fn num_established_connections(&self) -> usize {
@@ -449,14 +746,22 @@ where
     /// The `remote_addr` is the address used to reach back the remote. In the case of TCP, it
     /// contains the TCP dialing port of the remote. The remote can ask, through the `identify`
     /// libp2p protocol, its own address, in which case we send it.
+    ///
+    /// Returns an error if `remote_addr` belongs to one of the ranges of
+    /// [`Config::blocked_ip_ranges`]. The connection should then be closed.
     pub async fn add_incoming_connection(
         &self,
         when_connected: TNow,
         remote_addr: multiaddr::Multiaddr,
-    ) -> ConnectionId {
-        self.inner
+    ) -> Result<ConnectionId, BlockedAddressError> {
+        if ip_filter::is_blocked(&self.blocked_ip_ranges, &remote_addr) {
+            return Err(BlockedAddressError());
+        }
+
+        Ok(self
+            .inner
             .add_incoming_connection(when_connected, remote_addr)
-            .await
+            .await)
     }
 
     /// Modifies the best block of the local node. See [`ChainConfig::best_hash`] and
@@ -512,10 +817,12 @@ where
         });
 
         // Now sending out.
-        let _ = self
+        let num_bytes = packet.len();
+        let num_sent = self
             .inner
             .broadcast_notification(chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 2, packet)
             .await;
+        self.stats[chain_index].record_broadcast(stats::Protocol::Grandpa, num_sent, num_bytes);
 
         // Update the locally-stored state, but only after the notification has been broadcasted.
         // This way, if the user cancels the future while `broadcast_notification` is executing,
@@ -527,6 +834,112 @@ where
             .unwrap() = grandpa_state;
     }
 
+    /// Sends an identify request to the given peer.
+    ///
+    /// On success, the listen addresses advertised by the peer are inserted in the k-buckets of
+    /// the chains in which the peer is already known, similar to [`ChainNetwork::discover`], and
+    /// the address at which the peer sees the local node is recorded. See
+    /// [`ChainNetwork::observed_addresses`].
+    ///
+    /// Advertised and observed addresses belonging to one of the ranges of
+    /// [`Config::blocked_ip_ranges`] are ignored. In particular, a node that doesn't want remotes
+    /// to make it connect to its own machine should include [`IpRange::LOOPBACK`] in this list.
+    pub async fn identify_request(
+        &self,
+        now: TNow,
+        target: &peer_id::PeerId,
+        timeout: Duration,
+    ) -> Result<
+        protocol::IdentifyResponse<
+            'static,
+            vec::IntoIter<multiaddr::Multiaddr>,
+            vec::IntoIter<String>,
+        >,
+        IdentifyRequestError,
+    > {
+        let response = self
+            .request(now, target, 0, Vec::new(), timeout)
+            .map_err(IdentifyRequestError::Request)
+            .await?;
+
+        self.process_identify_response(target, &response).await
+    }
+
+    /// Decodes the response to an identify request sent to `peer_id`, and updates the state of
+    /// the service. See [`ChainNetwork::identify_request`].
+    async fn process_identify_response(
+        &self,
+        peer_id: &peer_id::PeerId,
+        response: &[u8],
+    ) -> Result<
+        protocol::IdentifyResponse<
+            'static,
+            vec::IntoIter<multiaddr::Multiaddr>,
+            vec::IntoIter<String>,
+        >,
+        IdentifyRequestError,
+    > {
+        let response =
+            protocol::decode_identify_response(response).map_err(IdentifyRequestError::Decode)?;
+
+        if PeerId::from_public_key(&peer_id::PublicKey::Ed25519(*response.ed25519_public_key))
+            != *peer_id
+        {
+            return Err(IdentifyRequestError::PublicKeyMismatch);
+        }
+
+        let listen_addrs = response.listen_addrs.collect::<Vec<_>>();
+
+        let mut lock = self.ephemeral_guarded.lock().await;
+        let lock = &mut *lock; // Avoids borrow checker issues.
+
+        // The identify protocol isn't specific to a chain. The addresses are only inserted in
+        // the chains the peer is known to belong to.
+        for chain in &mut lock.chains {
+            if let Some(kbuckets_addrs) = chain.kbuckets.get_mut(peer_id) {
+                for addr in &listen_addrs {
+                    if kbuckets_addrs.len() >= self.max_addresses_per_peer.get() {
+                        break;
+                    }
+
+                    if ip_filter::is_blocked(&self.blocked_ip_ranges, addr) {
+                        continue;
+                    }
+
+                    kbuckets_addrs.insert_discovered(addr.clone());
+                }
+            }
+        }
+
+        if response.observed_addr.iter().next().is_some()
+            && !ip_filter::is_blocked(&self.blocked_ip_ranges, &response.observed_addr)
+        {
+            lock.observed_addrs
+                .insert(peer_id, response.observed_addr.clone().into_owned());
+        }
+
+        Ok(protocol::IdentifyResponse {
+            listen_addrs: listen_addrs.into_iter(),
+            ..response
+        })
+    }
+
+    /// Returns the list of addresses of the local node, as reported by enough distinct remotes
+    /// through the identify protocol. See [`ChainNetwork::identify_request`].
+    ///
+    /// These addresses are the ones at which remotes see the local node, for example after
+    /// having gone through a NAT. They are advertised to the remotes that send identify
+    /// requests to the local node.
+    pub async fn observed_addresses(&self) -> Vec<multiaddr::Multiaddr> {
+        self.ephemeral_guarded
+            .lock()
+            .await
+            .observed_addrs
+            .confirmed()
+            .cloned()
+            .collect()
+    }
+
     /// Sends a blocks request to the given peer.
     // TODO: more docs
     pub async fn blocks_request(
@@ -561,17 +974,6 @@ where
                 });
             }
 
-            if block
-                .header
-                .as_ref()
-                .map_or(false, |h| header::decode(h).is_err())
-            {
-                return Err(BlocksRequestError::Entry {
-                    index: block_index,
-                    error: BlocksRequestResponseEntryError::InvalidHeader,
-                });
-            }
-
             match (block.body.is_some(), requested_fields.body) {
                 (false, true) => {
                     return Err(BlocksRequestError::Entry {
@@ -588,28 +990,12 @@ where
             // Note: the presence of a justification isn't checked and can't be checked, as not
             // all blocks have a justification in the first place.
 
-            if block.header.as_ref().map_or(false, |h| {
-                header::hash_from_scale_encoded_header(&h) != block.hash
-            }) {
+            if let Err(error) = protocol::verify_block_data(block) {
                 return Err(BlocksRequestError::Entry {
                     index: block_index,
-                    error: BlocksRequestResponseEntryError::InvalidHash,
+                    error: BlocksRequestResponseEntryError::Inconsistent(error),
                 });
             }
-
-            if let (Some(header), Some(body)) = (&block.header, &block.body) {
-                let decoded_header = header::decode(header).unwrap();
-                let expected = header::extrinsics_root(&body[..]);
-                if expected != *decoded_header.extrinsics_root {
-                    return Err(BlocksRequestError::Entry {
-                        index: block_index,
-                        error: BlocksRequestResponseEntryError::InvalidExtrinsicsRoot {
-                            calculated: expected,
-                            in_header: *decoded_header.extrinsics_root,
-                        },
-                    });
-                }
-            }
         }
 
         match request_start {
@@ -645,17 +1031,21 @@ where
             a
         });
 
+        self.stats[chain_index].record_sent(stats::Protocol::Sync, request_data.len());
+
         let response = self
-            .inner
             .request(
+                now,
                 target,
                 self.protocol_index(chain_index, 0),
                 request_data,
-                now + timeout,
+                timeout,
             )
             .map_err(BlocksRequestError::Request)
             .await?;
 
+        self.stats[chain_index].record_received(stats::Protocol::Sync, response.len());
+
         protocol::decode_block_response(&response).map_err(BlocksRequestError::Decode)
     }
 
@@ -670,12 +1060,12 @@ where
         let request_data = begin_hash.to_vec();
 
         let response = self
-            .inner
             .request(
+                now,
                 target,
                 self.protocol_index(chain_index, 3),
                 request_data,
-                now + timeout,
+                timeout,
             )
             .map_err(GrandpaWarpSyncRequestError::Request)
             .await?;
@@ -687,27 +1077,28 @@ where
     /// Sends a state request to a peer.
     ///
     /// A state request makes it possible to download the storage of the chain at a given block.
-    /// The response is not unverified by this function. In other words, the peer is free to send
-    /// back erroneous data. It is the responsibility of the API user to verify the storage by
-    /// calculating the state trie root hash and comparing it with the value stored in the
-    /// block's header.
+    /// The response contains a proof, which is verified against `state_trie_root`, the state
+    /// trie root found in the header of the block.
     ///
     /// Because response have a size limit, it is unlikely that a single request will return the
     /// entire storage of the chain at once. Instead, call this function multiple times, each call
-    /// passing a `start_key` that follows the last key of the previous response.
-    // TODO: does an empty response mean that `start_key` is the last key of the storage? unclear
-    pub async fn state_request_unchecked(
+    /// passing as `start_key` the key of the last entry of the previous response, until
+    /// [`protocol::VerifiedStateResponseChunk::complete`] is `true`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn state_request(
         &self,
         now: TNow,
         target: &peer_id::PeerId,
         chain_index: usize,
         block_hash: [u8; 32],
+        state_trie_root: &[u8; 32],
         start_key: &[u8],
         timeout: Duration,
-    ) -> Result<Vec<protocol::StateResponseEntry>, StateRequestError> {
+    ) -> Result<protocol::VerifiedStateResponseChunk, StateRequestError> {
         let request_data = protocol::build_state_request(protocol::StateRequestConfig {
             block_hash,
             start_key: start_key.to_vec(),
+            include_proof: true,
         })
         .fold(Vec::new(), |mut a, b| {
             a.extend_from_slice(b.as_ref());
@@ -715,17 +1106,24 @@ where
         });
 
         let response = self
-            .inner
             .request(
+                now,
                 target,
                 self.protocol_index(chain_index, 4),
                 request_data,
-                now + timeout,
+                timeout,
             )
             .map_err(StateRequestError::Request)
             .await?;
 
-        protocol::decode_state_response(&response).map_err(StateRequestError::Decode)
+        let chunk =
+            protocol::decode_state_response_chunk(&response).map_err(StateRequestError::Decode)?;
+        protocol::verify_state_response_chunk(protocol::VerifyStateResponseChunkConfig {
+            state_trie_root,
+            start_key,
+            chunk: &chunk,
+        })
+        .map_err(StateRequestError::Verify)
     }
 
     /// Sends a storage request to the given peer.
@@ -745,12 +1143,12 @@ where
             });
 
         let response = self
-            .inner
             .request(
+                now,
                 target,
                 self.protocol_index(chain_index, 1),
                 request_data,
-                now + timeout,
+                timeout,
             )
             .map_err(StorageProofRequestError::Request)
             .await?;
@@ -783,12 +1181,12 @@ where
             });
 
         let response = self
-            .inner
             .request(
+                now,
                 target,
                 self.protocol_index(chain_index, 1),
                 request_data,
-                now + timeout,
+                timeout,
             )
             .map_err(CallProofRequestError::Request)
             .await?;
@@ -796,6 +1194,35 @@ where
         protocol::decode_call_proof_response(&response).map_err(CallProofRequestError::Decode)
     }
 
+    /// Sends a block announce to the given peer.
+    ///
+    /// Must be passed the SCALE-encoded header of the announced block. `is_best` indicates
+    /// whether this block is the new best block of the local node.
+    ///
+    /// An error is returned if no block announces substream is open with this peer.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range.
+    ///
+    pub async fn send_block_announce(
+        &self,
+        target: &peer_id::PeerId,
+        chain_index: usize,
+        scale_encoded_header: &[u8],
+        is_best: bool,
+    ) -> Result<(), QueueNotificationError> {
+        let mut val = Vec::with_capacity(scale_encoded_header.len() + 1);
+        val.extend_from_slice(scale_encoded_header);
+        val.push(if is_best { 1 } else { 0 });
+        let num_bytes = val.len();
+        self.inner
+            .queue_notification(target, chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN, val)
+            .await?;
+        self.stats[chain_index].record_sent(stats::Protocol::BlockAnnounces, num_bytes);
+        Ok(())
+    }
+
     ///
     ///
     /// Must be passed the SCALE-encoded transaction.
@@ -809,18 +1236,46 @@ where
         let mut val = Vec::with_capacity(1 + extrinsic.len());
         val.extend_from_slice(util::encode_scale_compact_usize(1).as_ref());
         val.extend_from_slice(extrinsic);
+        let num_bytes = val.len();
         self.inner
             .queue_notification(
                 target,
                 chain_index * NOTIFICATIONS_PROTOCOLS_PER_CHAIN + 1,
                 val,
             )
-            .await
+            .await?;
+        self.stats[chain_index].record_sent(stats::Protocol::Transactions, num_bytes);
+        Ok(())
+    }
+
+    /// Returns statistics about the messages exchanged on the protocols of the given chain
+    /// since the creation of the service or the latest call to
+    /// [`ChainNetwork::reset_protocol_stats`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range.
+    ///
+    pub fn protocol_stats(&self, chain_index: usize) -> ProtocolsStats {
+        self.stats[chain_index].snapshot()
+    }
+
+    /// Sets all the statistics returned by [`ChainNetwork::protocol_stats`] for the given chain
+    /// back to zero.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `chain_index` is out of range.
+    ///
+    pub fn reset_protocol_stats(&self, chain_index: usize) {
+        self.stats[chain_index].reset();
     }
 
     /// Inserts the given list of nodes into the list of known nodes held within the state machine.
     ///
     /// The service might, but without guarantee, try to connect to these nodes in the future.
+    ///
+    /// Addresses belonging to one of the ranges of [`Config::blocked_ip_ranges`] are ignored.
     pub async fn discover(
         &self,
         now: &TNow,
@@ -838,7 +1293,10 @@ where
         let kbuckets = &mut lock.chains[chain_index].kbuckets;
 
         for (peer_id, discovered_addrs) in list {
-            let mut discovered_addrs = discovered_addrs.into_iter().peekable();
+            let mut discovered_addrs = discovered_addrs
+                .into_iter()
+                .filter(|addr| !ip_filter::is_blocked(&self.blocked_ip_ranges, addr))
+                .peekable();
 
             // Check whether there is any address in the iterator at all before inserting the
             // node in the buckets.
@@ -1011,6 +1469,30 @@ where
         let mut guarded = self.next_event_guarded.lock().await;
         let guarded = &mut *guarded;
 
+        {
+            let mut ephemeral_guarded = self.ephemeral_guarded.lock().await;
+            let ephemeral_guarded = &mut *ephemeral_guarded;
+            ephemeral_guarded.penalties.remove_expired(&now);
+
+            // Close the connections that have been idle for too long.
+            let peers_with_substreams = self
+                .inner
+                .peers_with_substreams()
+                .await
+                .collect::<BTreeSet<_>>();
+            let idle_connections = ephemeral_guarded
+                .idle_connections
+                .idle_connections(&now, |peer_id| peers_with_substreams.contains(peer_id));
+            // Because this is an asynchronous operation, this is done before any modification
+            // to `ephemeral_guarded`. Shutting down a connection multiple times has no effect.
+            for connection_id in &idle_connections {
+                self.inner.start_shutdown(*connection_id).await;
+            }
+            for connection_id in &idle_connections {
+                ephemeral_guarded.idle_connections.remove(connection_id);
+            }
+        }
+
         loop {
             // It might be that a previous call to `next_event` has been interrupted. If that is
             // the case, an event will have been left in `to_process_pre_event`. Only pull a new
@@ -1019,21 +1501,54 @@ where
                 Some(ev) => ev,
                 ev @ None => {
                     let new_event = self.inner.next_event().await;
+                    self.record_received(&new_event);
                     ev.insert(new_event)
                 }
             };
 
+            // Requests, notifications, and substreams opening or closing reset the idle timeout
+            // of the connections with the peer.
+            match inner_event {
+                peers::Event::RequestIn { peer_id, .. }
+                | peers::Event::DesiredInNotification { peer_id, .. }
+                | peers::Event::DesiredOutNotification { peer_id, .. }
+                | peers::Event::NotificationsOutResult { peer_id, .. }
+                | peers::Event::NotificationsOutClose { peer_id, .. }
+                | peers::Event::NotificationsIn { peer_id, .. }
+                | peers::Event::NotificationsInClose { peer_id, .. } => {
+                    self.ephemeral_guarded
+                        .lock()
+                        .await
+                        .idle_connections
+                        .report_activity(peer_id, &now);
+                }
+                _ => {}
+            }
+
             // `inner_event` is a mutable reference to `guarded.to_process_pre_event`. All the
             // branches below must clear `to_process_pre_event` after all potentially-cancellable
             // asynchronous operations are finished.
             match inner_event {
                 peers::Event::Connected {
+                    id,
                     peer_id,
+                    inbound,
                     num_peer_connections,
-                    ..
                 } if num_peer_connections.get() == 1 => {
                     let mut ephemeral_guarded = self.ephemeral_guarded.lock().await;
 
+                    self.enforce_connections_limits(
+                        &mut *ephemeral_guarded,
+                        *id,
+                        peer_id,
+                        *inbound,
+                    )
+                    .await;
+
+                    ephemeral_guarded
+                        .idle_connections
+                        .insert(*id, peer_id.clone(), &now);
+
                     let _was_inserted = ephemeral_guarded.connections.insert(peer_id.clone());
                     debug_assert!(_was_inserted);
 
@@ -1042,11 +1557,28 @@ where
                         _ => unreachable!(),
                     };
                 }
-                peers::Event::Connected { .. } => {
+                peers::Event::Connected {
+                    id,
+                    peer_id,
+                    inbound,
+                    ..
+                } => {
+                    let mut ephemeral_guarded = self.ephemeral_guarded.lock().await;
+                    self.enforce_connections_limits(
+                        &mut *ephemeral_guarded,
+                        *id,
+                        peer_id,
+                        *inbound,
+                    )
+                    .await;
+                    ephemeral_guarded
+                        .idle_connections
+                        .insert(*id, peer_id.clone(), &now);
                     guarded.to_process_pre_event = None;
                 }
 
                 peers::Event::Disconnected {
+                    id,
                     peer_id,
                     num_peer_connections,
                     peer_is_desired,
@@ -1077,6 +1609,11 @@ where
                     let _was_in = ephemeral_guarded.connections.remove(peer_id);
                     debug_assert!(_was_in);
 
+                    if ephemeral_guarded.connections_limits.remove(id) {
+                        self.start_connect_needed.notify_additional(1);
+                    }
+                    ephemeral_guarded.idle_connections.remove(id);
+
                     // Update the k-buckets.
                     // TODO: `Disconnected` is only generated for connections that weren't handshaking, so this is not correct
                     for chain in &mut ephemeral_guarded.chains {
@@ -1099,12 +1636,18 @@ where
                     };
                 }
                 peers::Event::Disconnected {
+                    id,
                     peer_id,
                     user_data: address,
                     ..
                 } => {
                     let mut ephemeral_guarded = self.ephemeral_guarded.lock().await;
 
+                    if ephemeral_guarded.connections_limits.remove(id) {
+                        self.start_connect_needed.notify_additional(1);
+                    }
+                    ephemeral_guarded.idle_connections.remove(id);
+
                     // Update the k-buckets.
                     // TODO: `Disconnected` is only generated for connections that weren't handshaking, so this is not correct
                     for chain in &mut ephemeral_guarded.chains {
@@ -1186,6 +1729,7 @@ where
                                     config,
                                     request: BlocksRequestIn {
                                         service: self,
+                                        chain_index,
                                         request_id,
                                     },
                                 },
@@ -1374,14 +1918,19 @@ where
                         })
                     };
 
-                    let _ = self
+                    let num_bytes = notification.len();
+                    if self
                         .inner
                         .queue_notification(
                             peer_id,
                             *notifications_protocol_index,
                             notification.clone(),
                         )
-                        .await;
+                        .await
+                        .is_ok()
+                    {
+                        self.stats[chain_index].record_sent(stats::Protocol::Grandpa, num_bytes);
+                    }
 
                     guarded.to_process_pre_event = None;
                 }
@@ -1439,19 +1988,24 @@ where
                 peers::Event::NotificationsOutResult {
                     notifications_protocol_index,
                     peer_id,
-                    result: Err(_),
+                    result: Err(error),
                 } if *notifications_protocol_index % NOTIFICATIONS_PROTOCOLS_PER_CHAIN == 0 => {
                     let chain_index =
                         *notifications_protocol_index / NOTIFICATIONS_PROTOCOLS_PER_CHAIN;
 
+                    let mut ephemeral_guarded = self.ephemeral_guarded.lock().await;
+
+                    // A remote that doesn't answer the handshake in time is considered as
+                    // misbehaving, so that the slot isn't immediately assigned to it again.
+                    if matches!(error, peers::NotificationsOutErr::Timeout) {
+                        ephemeral_guarded.penalties.penalize(&now, peer_id);
+                    }
+
                     let unassigned_slot_ty = self
-                        .unassign_slot(
-                            &mut *self.ephemeral_guarded.lock().await,
-                            chain_index,
-                            peer_id,
-                        )
+                        .unassign_slot(&mut ephemeral_guarded, chain_index, peer_id)
                         .await
                         .unwrap();
+                    drop(ephemeral_guarded);
 
                     // As a slot has been unassigned, wake up the discovery process in order for
                     // it to be filled.
@@ -1477,7 +2031,19 @@ where
                 }
 
                 // Other protocol.
-                peers::Event::NotificationsOutResult { result: Err(_), .. } => {
+                peers::Event::NotificationsOutResult {
+                    peer_id,
+                    result: Err(error),
+                    ..
+                } => {
+                    if matches!(error, peers::NotificationsOutErr::Timeout) {
+                        self.ephemeral_guarded
+                            .lock()
+                            .await
+                            .penalties
+                            .penalize(&now, peer_id);
+                    }
+
                     guarded.to_process_pre_event = None;
                 }
 
@@ -1612,17 +2178,41 @@ where
                         continue;
                     }
 
-                    // Check the format of the block announce.
-                    if let Err(err) = protocol::decode_block_announce(notification) {
-                        return Event::ProtocolError {
-                            error: ProtocolError::BadBlockAnnounce(err),
-                            peer_id: match guarded.to_process_pre_event.take().unwrap() {
-                                peers::Event::NotificationsIn { peer_id, .. } => peer_id,
-                                _ => unreachable!(),
-                            },
-                        };
+                    // Discard block announces that are identical to one recently received from
+                    // the same peer. This is done before decoding the announce in order to
+                    // reduce the cost of a peer sending the same announce repeatedly.
+                    if !guarded.block_announces_filter.insert(
+                        &now,
+                        chain_index,
+                        peer_id,
+                        blake2_rfc::blake2b::blake2b(32, &[], notification)
+                            .as_bytes()
+                            .try_into()
+                            .unwrap(),
+                    ) {
+                        guarded.to_process_pre_event = None;
+                        continue;
                     }
 
+                    // Check the format of the block announce.
+                    let announced_number = match protocol::decode_block_announce(notification) {
+                        Ok(announce) => announce.header.number,
+                        Err(err) => {
+                            return Event::ProtocolError {
+                                error: ProtocolError::BadBlockAnnounce(err),
+                                peer_id: match guarded.to_process_pre_event.take().unwrap() {
+                                    peers::Event::NotificationsIn { peer_id, .. } => peer_id,
+                                    _ => unreachable!(),
+                                },
+                            };
+                        }
+                    };
+
+                    let is_better_than_local_best = announced_number
+                        > self.ephemeral_guarded.lock().await.chains[chain_index]
+                            .chain_config
+                            .best_number;
+
                     return match guarded.to_process_pre_event.take().unwrap() {
                         peers::Event::NotificationsIn {
                             peer_id,
@@ -1632,6 +2222,7 @@ where
                             chain_index,
                             peer_id,
                             announce: EncodedBlockAnnounce(notification),
+                            is_better_than_local_best,
                         },
                         _ => unreachable!(),
                     };
@@ -1895,6 +2486,15 @@ where
                 peers::Event::DesiredInNotificationCancel { .. } => {
                     guarded.to_process_pre_event = None;
                 }
+
+                peers::Event::PingOutSuccess { id } => {
+                    self.ephemeral_guarded
+                        .lock()
+                        .await
+                        .idle_connections
+                        .report_ping(id, &now);
+                    guarded.to_process_pre_event = None;
+                }
             }
 
             debug_assert!(guarded.to_process_pre_event.is_none());
@@ -1955,14 +2555,13 @@ where
         let request_data = kademlia::build_find_node_request(close_to_key);
         // The timeout needs to be long enough to potentially download the maximum
         // response size of 1 MiB. Assuming a 128 kiB/sec connection, that's 8 seconds.
-        let timeout = now + Duration::from_secs(8);
         let response = self
-            .inner
             .request(
+                now,
                 target,
                 self.protocol_index(chain_index, 2),
                 request_data,
-                timeout,
+                Duration::from_secs(8),
             )
             .await
             .map_err(KademliaFindNodeError::RequestFailed)?;
@@ -1997,6 +2596,13 @@ where
             let unfulfilled_desired_peers = self.inner.unfulfilled_desired_peers().await;
 
             for peer_id in unfulfilled_desired_peers {
+                if !pending
+                    .connections_limits
+                    .can_dial(&peer_id, pending.pending_ids.len())
+                {
+                    continue;
+                }
+
                 // TODO: allow more than one simultaneous dial per peer, and distribute the dials so that we don't just return the same peer multiple times in a row while there are other peers waiting
                 let entry = match pending.num_pending_per_peer.entry(peer_id) {
                     hashbrown::hash_map::Entry::Occupied(_) => continue,
@@ -2088,10 +2694,14 @@ where
     /// slots only for them to be immediately assigned again in this function. If you naively
     /// log the value returned by this function and the value returned by `next_event`, you might
     /// see the slot assignments and de-assignments in the wrong order.
+    ///
+    /// Peers that have recently sent a response larger than allowed by the protocol aren't
+    /// assigned any slot.
     // TODO: docs
     // TODO: when to call this?
     pub async fn assign_slots(&self, chain_index: usize) -> Option<PeerId> {
         let mut lock = self.ephemeral_guarded.lock().await;
+        let lock = &mut *lock;
         let chain = &mut lock.chains[chain_index];
 
         let list = {
@@ -2114,6 +2724,11 @@ where
                 continue;
             }
 
+            // Don't assign slots to peers that have misbehaved.
+            if lock.penalties.is_penalized(peer_id) {
+                continue;
+            }
+
             // It is now guaranteed that this peer will be assigned an outbound slot.
 
             // The peer is marked as desired before inserting it in `out_peers`, to handle
@@ -2134,6 +2749,42 @@ where
         None
     }
 
+    /// Updates [`EphemeralGuarded::connections_limits`] after a connection has been established,
+    /// and closes a connection if [`Config::max_inbound_connections`] is exceeded.
+    async fn enforce_connections_limits(
+        &self,
+        ephemeral_guarded: &mut EphemeralGuarded<TNow>,
+        connection_id: ConnectionId,
+        peer_id: &PeerId,
+        inbound: bool,
+    ) {
+        let chains = &ephemeral_guarded.chains;
+        let to_evict = ephemeral_guarded.connections_limits.eviction_candidate(
+            &connection_id,
+            peer_id,
+            inbound,
+            |peer_id| {
+                chains
+                    .iter()
+                    .filter(|c| c.in_peers.contains(peer_id) || c.out_peers.contains(peer_id))
+                    .count()
+            },
+        );
+
+        // Because this is an asynchronous operation, this is done before any modification to
+        // `ephemeral_guarded`. Shutting down a connection multiple times has no effect.
+        if let Some(to_evict) = to_evict {
+            self.inner.start_shutdown(to_evict).await;
+        }
+
+        ephemeral_guarded
+            .connections_limits
+            .insert(connection_id, peer_id.clone(), inbound);
+        if let Some(to_evict) = to_evict {
+            ephemeral_guarded.connections_limits.remove(&to_evict);
+        }
+    }
+
     /// Removes the slot assignment of the given peer, if any.
     async fn unassign_slot(
         &self,
@@ -2249,7 +2900,14 @@ pub enum Event<'a, TNow> {
         peer_id: peer_id::PeerId,
         /// Index of the chain the block relates to.
         chain_index: usize,
+        /// The block announce. Its header is guaranteed to successfully decode.
         announce: EncodedBlockAnnounce,
+        /// `true` if the number of the announced block is strictly superior to the number of
+        /// the local best block, as indicated by [`ChainConfig::best_number`] or the latest call
+        /// to [`ChainNetwork::set_local_best_block`].
+        ///
+        /// Can be used in order to immediately start downloading the announced block.
+        is_better_than_local_best: bool,
     },
 
     /// Received a GrandPa commit message from the network.
@@ -2432,6 +3090,8 @@ where
     ///
     /// Has no effect if the connection that sends the request no longer exists.
     pub async fn respond(self, agent_version: &str) {
+        let listen_addrs = self.service.observed_addresses().await;
+
         let response = {
             protocol::build_identify_response(protocol::IdentifyResponse {
                 protocol_version: "/substrate/1.0".into(), // TODO: same value as in Substrate
@@ -2439,7 +3099,7 @@ where
                 ed25519_public_key: Cow::Borrowed(
                     self.service.inner.noise_key().libp2p_public_ed25519_key(),
                 ),
-                listen_addrs: iter::empty(), // TODO:
+                listen_addrs: listen_addrs.iter(),
                 observed_addr: Cow::Borrowed(&self.observed_addr),
                 protocols: self
                     .service
@@ -2478,6 +3138,7 @@ impl<'a, TNow> fmt::Debug for IdentifyRequestIn<'a, TNow> {
 #[must_use]
 pub struct BlocksRequestIn<'a, TNow> {
     service: &'a ChainNetwork<TNow>,
+    chain_index: usize,
     request_id: peers::RequestId,
 }
 
@@ -2503,7 +3164,11 @@ where
             Err(())
         };
 
-        let _ = self.service.inner.respond(self.request_id, response).await;
+        if let Ok(response) = &response {
+            self.service.stats[self.chain_index].record_sent(stats::Protocol::Sync, response.len());
+        }
+
+        self.service.inner.respond(self.request_id, response).await;
     }
 }
 
@@ -2527,6 +3192,17 @@ pub enum KademliaFindNodeError {
     DecodeError(kademlia::DecodeFindNodeResponseError),
 }
 
+/// Error returned by [`ChainNetwork::identify_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum IdentifyRequestError {
+    /// Error while waiting for the response from the peer.
+    Request(peers::RequestError),
+    /// Error while decoding the response returned by the peer.
+    Decode(protocol::DecodeIdentifyResponseError),
+    /// Public key in the response doesn't match the identity of the peer.
+    PublicKeyMismatch,
+}
+
 /// Error returned by [`ChainNetwork::blocks_request`].
 #[derive(Debug, derive_more::Display)]
 pub enum BlocksRequestError {
@@ -2555,19 +3231,10 @@ pub enum BlocksRequestError {
 pub enum BlocksRequestResponseEntryError {
     /// One of the requested fields is missing from the block.
     MissingField,
-    /// The header has an extrinsics root that doesn't match the body. Can only happen if both the
-    /// header and body were requested.
-    #[display(fmt = "The header has an extrinsics root that doesn't match the body")]
-    InvalidExtrinsicsRoot {
-        /// Extrinsics root that was calculated from the body.
-        calculated: [u8; 32],
-        /// Extrinsics root found in the header.
-        in_header: [u8; 32],
-    },
-    /// The header has an invalid format.
-    InvalidHeader,
-    /// The hash of the header doesn't match the hash provided by the remote.
-    InvalidHash,
+    /// The fields of the block aren't consistent with each other. For example, the body doesn't
+    /// match the extrinsics root of the header.
+    #[display(fmt = "{}", _0)]
+    Inconsistent(protocol::VerifyBlockDataError),
 }
 
 /// Error returned by [`ChainNetwork::storage_proof_request`].
@@ -2595,6 +3262,11 @@ impl CallProofRequestError {
     }
 }
 
+/// Error returned by [`ChainNetwork::add_incoming_connection`].
+#[derive(Debug, Clone, derive_more::Display)]
+#[display(fmt = "Remote address belongs to a blocked IP range")]
+pub struct BlockedAddressError();
+
 /// Error returned by [`ChainNetwork::grandpa_warp_sync_request`].
 #[derive(Debug, derive_more::Display)]
 pub enum GrandpaWarpSyncRequestError {
@@ -2602,11 +3274,12 @@ pub enum GrandpaWarpSyncRequestError {
     Decode(protocol::DecodeGrandpaWarpSyncResponseError),
 }
 
-/// Error returned by [`ChainNetwork::state_request_unchecked`].
+/// Error returned by [`ChainNetwork::state_request`].
 #[derive(Debug, derive_more::Display)]
 pub enum StateRequestError {
     Request(peers::RequestError),
     Decode(protocol::DecodeStateResponseError),
+    Verify(protocol::VerifyStateResponseChunkError),
 }
 
 /// See [`Event::ProtocolError`].
@@ -2625,3 +3298,652 @@ pub enum ProtocolError {
     /// Error while decoding a received blocks request.
     BadBlocksRequest(protocol::DecodeBlockRequestError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        chain_notification_protocols, chain_request_response_protocols, connection, peer_id,
+        ChainConfig, ChainNetwork, Config, ConnectionId, Event, GrandpaState, IpRange,
+        NotificationsOutErr, PeerId, ProtocolError, ReadWrite,
+    };
+    use crate::{header, libp2p::multiaddr, network::protocol};
+    use alloc::borrow::Cow;
+    use core::{iter, num::NonZeroUsize, time::Duration};
+    use futures::FutureExt as _;
+
+    fn chain_config(genesis_hash: [u8; 32], accept_legacy_protocol_names: bool) -> ChainConfig {
+        ChainConfig {
+            protocol_id: "dot".to_owned(),
+            grandpa_protocol_config: None,
+            allow_inbound_block_requests: true,
+            in_slots: 25,
+            out_slots: 25,
+            best_hash: genesis_hash,
+            best_number: 0,
+            genesis_hash,
+            accept_legacy_protocol_names,
+            role: protocol::Role::Light,
+        }
+    }
+
+    /// Configuration of a network with a single chain and the given libp2p private key. Tests
+    /// override the fields they exercise.
+    fn network_config(key: [u8; 32]) -> Config<Duration> {
+        Config {
+            now: Duration::new(0, 0),
+            connections_capacity: 16,
+            peers_capacity: 16,
+            randomness_seed: [0; 32],
+            chains: vec![chain_config([1; 32], false)],
+            noise_key: connection::NoiseKey::new_with_seed(&key, &[0; 32]),
+            handshake_timeout: Duration::from_secs(5),
+            inbound_negotiation_timeout: Duration::from_secs(10),
+            max_addresses_per_peer: NonZeroUsize::new(5).unwrap(),
+            pending_api_events_buffer_size: NonZeroUsize::new(16).unwrap(),
+            block_announces_dedup_capacity: 0,
+            block_announces_dedup_window: Duration::from_secs(12),
+            blocked_ip_ranges: Vec::new(),
+            max_inbound_connections: 16,
+            max_outbound_connections: 16,
+            reserved_peers: Vec::new(),
+            reserved_slots: 0,
+            idle_connection_timeout: None,
+        }
+    }
+
+    #[test]
+    fn mismatched_genesis_rejected() {
+        let local = chain_config([1; 32], false);
+        let remote = chain_config([2; 32], false);
+
+        for (local, remote) in
+            chain_notification_protocols(&local).zip(chain_notification_protocols(&remote))
+        {
+            assert!(local
+                .protocol_name
+                .starts_with(&format!("/{}/", hex::encode([1; 32]))));
+            assert!(local.fallback_protocol_names.is_empty());
+            assert_ne!(local.protocol_name, remote.protocol_name);
+        }
+
+        for (local, remote) in
+            chain_request_response_protocols(&local).zip(chain_request_response_protocols(&remote))
+        {
+            assert!(local.matches(&local.name));
+            if local.name == "/dot/kad" {
+                continue;
+            }
+            assert!(!local.matches(&remote.name));
+            assert!(!local.matches(&format!("/dot/{}", &local.name[66..])));
+        }
+    }
+
+    #[test]
+    fn legacy_names_optionally_accepted() {
+        let legacy = chain_config([1; 32], true);
+        let remote = chain_config([2; 32], true);
+
+        for (local, remote) in
+            chain_request_response_protocols(&legacy).zip(chain_request_response_protocols(&remote))
+        {
+            assert!(local.matches(&local.name));
+            if local.name == "/dot/kad" {
+                assert!(local.fallback_names.is_empty());
+                continue;
+            }
+            assert!(local.matches(&format!("/dot/{}", &local.name[66..])));
+            assert!(!local.matches(&remote.name));
+        }
+
+        let names = chain_notification_protocols(&legacy)
+            .flat_map(|p| p.fallback_protocol_names)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "/dot/block-announces/1",
+                "/dot/transactions/1",
+                "/paritytech/grandpa/1"
+            ]
+        );
+    }
+
+    #[test]
+    fn blocked_ip_ranges_neither_dialed_nor_accepted() {
+        futures::executor::block_on(async {
+            let now = Duration::new(0, 0);
+            let network = ChainNetwork::new(Config {
+                blocked_ip_ranges: IpRange::PRIVATE.to_vec(),
+                ..network_config([0; 32])
+            });
+
+            let blocked_peer = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([1; 32]));
+            let allowed_peer = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([2; 32]));
+
+            // A peer whose only address is blocked isn't inserted and thus never dialed.
+            network
+                .discover(
+                    &now,
+                    0,
+                    iter::once((
+                        blocked_peer,
+                        iter::once("/ip4/10.1.2.3/tcp/30333".parse().unwrap()),
+                    )),
+                )
+                .await;
+            assert!(network.assign_slots(0).await.is_none());
+
+            network
+                .discover(
+                    &now,
+                    0,
+                    iter::once((
+                        allowed_peer.clone(),
+                        vec![
+                            "/ip4/192.168.0.1/tcp/30333".parse().unwrap(),
+                            "/ip4/1.2.3.4/tcp/30333".parse().unwrap(),
+                        ],
+                    )),
+                )
+                .await;
+            assert_eq!(network.assign_slots(0).await, Some(allowed_peer.clone()));
+            assert!(network.assign_slots(0).await.is_none());
+
+            let start_connect = network.next_start_connect(|| now).await;
+            assert_eq!(start_connect.expected_peer_id, allowed_peer);
+            assert_eq!(
+                start_connect.multiaddr.to_string(),
+                "/ip4/1.2.3.4/tcp/30333"
+            );
+
+            // Incoming connections from blocked ranges are refused.
+            assert!(network
+                .add_incoming_connection(now, "/ip4/172.16.5.5/tcp/1234".parse().unwrap())
+                .await
+                .is_err());
+            assert!(network
+                .add_incoming_connection(now, "/ip4/5.6.7.8/tcp/1234".parse().unwrap())
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn identify_addresses_learned() {
+        futures::executor::block_on(async {
+            let now = Duration::new(0, 0);
+            let network = ChainNetwork::new(Config {
+                blocked_ip_ranges: IpRange::LOOPBACK.to_vec(),
+                ..network_config([0; 32])
+            });
+
+            let identify_response = |remote_key: [u8; 32], observed_addr: &str| {
+                let listen_addrs: [multiaddr::Multiaddr; 2] = [
+                    "/ip4/127.0.0.1/tcp/30333".parse().unwrap(),
+                    "/ip4/1.2.3.4/tcp/30333".parse().unwrap(),
+                ];
+                let observed_addr: multiaddr::Multiaddr = observed_addr.parse().unwrap();
+                protocol::build_identify_response(protocol::IdentifyResponse {
+                    protocol_version: "/substrate/1.0".into(),
+                    agent_version: "test".into(),
+                    ed25519_public_key: Cow::Owned(remote_key),
+                    listen_addrs: listen_addrs.iter(),
+                    observed_addr: Cow::Borrowed(&observed_addr),
+                    protocols: iter::empty(),
+                })
+                .fold(Vec::new(), |mut a, b| {
+                    a.extend_from_slice(b.as_ref());
+                    a
+                })
+            };
+
+            let remote1 = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([1; 32]));
+            let remote2 = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([2; 32]));
+
+            // The peer must be known to the chain for its addresses to be inserted.
+            network
+                .discover(
+                    &now,
+                    0,
+                    iter::once((
+                        remote1.clone(),
+                        iter::once("/ip4/5.6.7.8/tcp/30333".parse().unwrap()),
+                    )),
+                )
+                .await;
+
+            let response = network
+                .process_identify_response(
+                    &remote1,
+                    &identify_response([1; 32], "/ip4/9.9.9.9/tcp/30333"),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.listen_addrs.len(), 2);
+
+            // The advertised listen address is now a dial candidate, after the address that was
+            // already known. The loopback address isn't.
+            for expected in ["/ip4/5.6.7.8/tcp/30333", "/ip4/1.2.3.4/tcp/30333"] {
+                assert_eq!(network.assign_slots(0).await, Some(remote1.clone()));
+                let start_connect = network.next_start_connect(|| now).await;
+                assert_eq!(start_connect.expected_peer_id, remote1);
+                assert_eq!(start_connect.multiaddr.to_string(), expected);
+                network.pending_outcome_err(start_connect.id, true).await;
+            }
+
+            // The observed address is only advertised once confirmed by a second peer.
+            assert!(network.observed_addresses().await.is_empty());
+            network
+                .process_identify_response(
+                    &remote2,
+                    &identify_response([2; 32], "/ip4/127.0.0.1/tcp/30333"),
+                )
+                .await
+                .unwrap();
+            assert!(network.observed_addresses().await.is_empty());
+            network
+                .process_identify_response(
+                    &remote2,
+                    &identify_response([2; 32], "/ip4/9.9.9.9/tcp/30333"),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                network.observed_addresses().await,
+                ["/ip4/9.9.9.9/tcp/30333"
+                    .parse::<multiaddr::Multiaddr>()
+                    .unwrap()]
+            );
+
+            // The public key must match the identity of the peer.
+            assert!(matches!(
+                network
+                    .process_identify_response(
+                        &remote2,
+                        &identify_response([1; 32], "/ip4/9.9.9.9/tcp/30333"),
+                    )
+                    .await,
+                Err(super::IdentifyRequestError::PublicKeyMismatch)
+            ));
+        });
+    }
+
+    /// Transfers data between two connections and processes the events of the two services until
+    /// no more progress is made. Returns the number of [`Event::ChainConnected`] generated by each
+    /// service.
+    ///
+    /// Messages between the services and their connections are sometimes only delivered during
+    /// the next call to [`ChainNetwork::read_write`] or [`ChainNetwork::next_event`], so a few
+    /// consecutive rounds without any data transferred or event generated are necessary in order
+    /// to consider that no more progress can be made.
+    async fn exchange(
+        now: Duration,
+        network1: (&ChainNetwork<Duration>, ConnectionId),
+        network2: (&ChainNetwork<Duration>, ConnectionId),
+    ) -> [usize; 2] {
+        exchange_with_events(now, network1, network2, [true, true], |_, _| {}).await
+    }
+
+    /// Same as [`exchange`], but events are only processed on the networks whose entry in
+    /// `process_events` is `true`. Each event is passed to `on_event` along with the index of
+    /// the network that has generated it.
+    async fn exchange_with_events(
+        now: Duration,
+        (network1, connection1): (&ChainNetwork<Duration>, ConnectionId),
+        (network2, connection2): (&ChainNetwork<Duration>, ConnectionId),
+        process_events: [bool; 2],
+        mut on_event: impl FnMut(usize, &Event<'_, Duration>),
+    ) -> [usize; 2] {
+        // `buffers[n]` contains the data destined to the connection of `network{n+1}`.
+        let mut buffers = [Vec::new(), Vec::new()];
+        let mut chain_connected = [0, 0];
+        let mut idle_rounds = 0;
+
+        while idle_rounds < 4 {
+            let mut progress = false;
+
+            for (index, (network, connection)) in [(network1, connection1), (network2, connection2)]
+                .into_iter()
+                .enumerate()
+            {
+                // Events are processed before calling `read_write`, as processing events can
+                // queue data to send out.
+                while let Some(event) = process_events[index]
+                    .then(|| network.next_event(now).now_or_never())
+                    .flatten()
+                {
+                    progress = true;
+                    if matches!(event, Event::ChainConnected { .. }) {
+                        chain_connected[index] += 1;
+                    }
+                    on_event(index, &event);
+                }
+
+                let mut outgoing = vec![0; 65536];
+                let mut read_write = ReadWrite {
+                    now,
+                    incoming_buffer: Some(&buffers[index]),
+                    outgoing_buffer: Some((&mut outgoing, &mut [])),
+                    read_bytes: 0,
+                    written_bytes: 0,
+                    wake_up_after: None,
+                    wake_up_future: None,
+                };
+                network
+                    .read_write(connection, &mut read_write)
+                    .await
+                    .unwrap();
+                let (read_bytes, written_bytes) = (read_write.read_bytes, read_write.written_bytes);
+                buffers[index].drain(..read_bytes);
+                buffers[1 - index].extend_from_slice(&outgoing[..written_bytes]);
+                progress |= read_bytes != 0 || written_bytes != 0;
+            }
+
+            if progress {
+                idle_rounds = 0;
+            } else {
+                idle_rounds += 1;
+            }
+        }
+
+        chain_connected
+    }
+
+    #[test]
+    fn notifications_sent_counted_in_stats() {
+        futures::executor::block_on(async {
+            let now = Duration::new(0, 0);
+            let with_grandpa = |key: [u8; 32]| Config {
+                chains: vec![ChainConfig {
+                    grandpa_protocol_config: Some(GrandpaState {
+                        round_number: 1,
+                        set_id: 0,
+                        commit_finalized_height: 0,
+                    }),
+                    ..chain_config([1; 32], false)
+                }],
+                ..network_config(key)
+            };
+
+            let network1 = ChainNetwork::new(with_grandpa([1; 32]));
+            let network2 = ChainNetwork::new(with_grandpa([2; 32]));
+            let peer_id1 = PeerId::from_public_key(&peer_id::PublicKey::Ed25519(
+                *network1.noise_key().libp2p_public_ed25519_key(),
+            ));
+            let peer_id2 = PeerId::from_public_key(&peer_id::PublicKey::Ed25519(
+                *network2.noise_key().libp2p_public_ed25519_key(),
+            ));
+
+            // Nothing is counted as long as no substream is open.
+            network2
+                .set_local_grandpa_state(
+                    0,
+                    GrandpaState {
+                        round_number: 2,
+                        set_id: 0,
+                        commit_finalized_height: 0,
+                    },
+                )
+                .await;
+            assert!(network2
+                .send_block_announce(&peer_id1, 0, &[0; 8], true)
+                .await
+                .is_err());
+            assert_eq!(network2.protocol_stats(0), Default::default());
+
+            // `network1` connects to `network2`. Because `network1` opens its GrandPa substream
+            // as soon as the block announces substreams are open, before `network2` considers the
+            // chain as connected, only `network2` has an outbound GrandPa substream afterwards.
+            network1
+                .discover(
+                    &now,
+                    0,
+                    iter::once((
+                        peer_id2.clone(),
+                        iter::once("/ip4/1.2.3.4/tcp/30333".parse().unwrap()),
+                    )),
+                )
+                .await;
+            assert_eq!(network1.assign_slots(0).await, Some(peer_id2.clone()));
+            let start_connect = network1.next_start_connect(|| now).await;
+            let connection1 = network1.pending_outcome_ok(start_connect.id).await;
+            let connection2 = network2
+                .add_incoming_connection(now, "/ip4/5.6.7.8/tcp/1234".parse().unwrap())
+                .await
+                .unwrap();
+            let chain_connected =
+                exchange(now, (&network1, connection1), (&network2, connection2)).await;
+            assert_eq!(chain_connected, [1, 1]);
+            network1.reset_protocol_stats(0);
+            network2.reset_protocol_stats(0);
+
+            // GrandPa neighbor packet broadcast to the connected peer.
+            network2
+                .set_local_grandpa_state(
+                    0,
+                    GrandpaState {
+                        round_number: 3,
+                        set_id: 0,
+                        commit_finalized_height: 0,
+                    },
+                )
+                .await;
+            let grandpa = network2.protocol_stats(0).grandpa;
+            assert_eq!(grandpa.messages_sent, 1);
+            assert_ne!(grandpa.bytes_sent, 0);
+
+            // Block announce.
+            let header = header::HeaderRef {
+                parent_hash: &[1; 32],
+                number: 1,
+                state_root: &[2; 32],
+                extrinsics_root: &[3; 32],
+                digest: header::DigestRef::empty(),
+            }
+            .scale_encoding_vec();
+            network2
+                .send_block_announce(&peer_id1, 0, &header, true)
+                .await
+                .unwrap();
+            let block_announces = network2.protocol_stats(0).block_announces;
+            assert_eq!(block_announces.messages_sent, 1);
+            assert_eq!(
+                block_announces.bytes_sent,
+                u64::try_from(header.len() + 1).unwrap()
+            );
+
+            // The messages are also counted on the receiving side.
+            exchange(now, (&network1, connection1), (&network2, connection2)).await;
+            let received = network1.protocol_stats(0);
+            assert_eq!(received.grandpa.messages_received, 1);
+            assert_eq!(received.grandpa.bytes_received, grandpa.bytes_sent);
+            assert_eq!(received.block_announces.messages_received, 1);
+            assert_eq!(
+                received.block_announces.bytes_received,
+                block_announces.bytes_sent
+            );
+        });
+    }
+
+    #[test]
+    fn notifications_handshake_timeout_penalized() {
+        futures::executor::block_on(async {
+            let now = Duration::new(0, 0);
+            let network1 = ChainNetwork::new(network_config([1; 32]));
+            let network2 = ChainNetwork::new(network_config([2; 32]));
+            let peer_id2 = PeerId::from_public_key(&peer_id::PublicKey::Ed25519(
+                *network2.noise_key().libp2p_public_ed25519_key(),
+            ));
+
+            network1
+                .discover(
+                    &now,
+                    0,
+                    iter::once((
+                        peer_id2.clone(),
+                        iter::once("/ip4/1.2.3.4/tcp/30333".parse().unwrap()),
+                    )),
+                )
+                .await;
+            assert_eq!(network1.assign_slots(0).await, Some(peer_id2.clone()));
+            let start_connect = network1.next_start_connect(|| now).await;
+            let connection1 = network1.pending_outcome_ok(start_connect.id).await;
+            let connection2 = network2
+                .add_incoming_connection(now, "/ip4/5.6.7.8/tcp/1234".parse().unwrap())
+                .await
+                .unwrap();
+
+            // `network2` never processes its events, and thus never accepts the block announces
+            // substream opened by `network1`.
+            let chain_connected = exchange_with_events(
+                now,
+                (&network1, connection1),
+                (&network2, connection2),
+                [true, false],
+                |_, _| {},
+            )
+            .await;
+            assert_eq!(chain_connected, [0, 0]);
+
+            let now = now + Duration::from_secs(21);
+            let mut outgoing = vec![0; 65536];
+            let mut read_write = ReadWrite {
+                now,
+                incoming_buffer: Some(&[]),
+                outgoing_buffer: Some((&mut outgoing, &mut [])),
+                read_bytes: 0,
+                written_bytes: 0,
+                wake_up_after: None,
+                wake_up_future: None,
+            };
+            network1
+                .read_write(connection1, &mut read_write)
+                .await
+                .unwrap();
+
+            match network1.next_event(now).await {
+                Event::ChainConnectAttemptFailed {
+                    peer_id,
+                    error:
+                        NotificationsOutErr::Substream(super::peers::NotificationsOutErr::Timeout),
+                    ..
+                } => assert_eq!(peer_id, peer_id2),
+                _ => panic!(),
+            }
+
+            // The slot has been freed, but isn't assigned again to the penalized peer.
+            assert!(network1.assign_slots(0).await.is_none());
+        });
+    }
+
+    #[test]
+    fn block_announces_compared_to_local_best() {
+        futures::executor::block_on(async {
+            let now = Duration::new(0, 0);
+            let with_grandpa = |key: [u8; 32]| Config {
+                chains: vec![ChainConfig {
+                    grandpa_protocol_config: Some(GrandpaState {
+                        round_number: 1,
+                        set_id: 0,
+                        commit_finalized_height: 0,
+                    }),
+                    ..chain_config([1; 32], false)
+                }],
+                ..network_config(key)
+            };
+
+            let network1 = ChainNetwork::new(with_grandpa([1; 32]));
+            let network2 = ChainNetwork::new(with_grandpa([2; 32]));
+            let peer_id1 = PeerId::from_public_key(&peer_id::PublicKey::Ed25519(
+                *network1.noise_key().libp2p_public_ed25519_key(),
+            ));
+            let peer_id2 = PeerId::from_public_key(&peer_id::PublicKey::Ed25519(
+                *network2.noise_key().libp2p_public_ed25519_key(),
+            ));
+
+            network1
+                .discover(
+                    &now,
+                    0,
+                    iter::once((
+                        peer_id2.clone(),
+                        iter::once("/ip4/1.2.3.4/tcp/30333".parse().unwrap()),
+                    )),
+                )
+                .await;
+            assert_eq!(network1.assign_slots(0).await, Some(peer_id2.clone()));
+            let start_connect = network1.next_start_connect(|| now).await;
+            let connection1 = network1.pending_outcome_ok(start_connect.id).await;
+            let connection2 = network2
+                .add_incoming_connection(now, "/ip4/5.6.7.8/tcp/1234".parse().unwrap())
+                .await
+                .unwrap();
+            let chain_connected =
+                exchange(now, (&network1, connection1), (&network2, connection2)).await;
+            assert_eq!(chain_connected, [1, 1]);
+
+            // Sends a block announce notification from `network2` to `network1`, and returns
+            // the events related to it generated by `network1`.
+            let announce = |notification: Vec<u8>| {
+                let (network1, network2, peer_id1) = (&network1, &network2, &peer_id1);
+                async move {
+                    network2
+                        .inner
+                        .queue_notification(peer_id1, 0, notification)
+                        .await
+                        .unwrap();
+
+                    let mut events = Vec::new();
+                    exchange_with_events(
+                        now,
+                        (network1, connection1),
+                        (network2, connection2),
+                        [true, true],
+                        |index, event| match (index, event) {
+                            (
+                                0,
+                                Event::BlockAnnounce {
+                                    is_better_than_local_best,
+                                    ..
+                                },
+                            ) => events.push(Ok(*is_better_than_local_best)),
+                            (
+                                0,
+                                Event::ProtocolError {
+                                    error: ProtocolError::BadBlockAnnounce(_),
+                                    ..
+                                },
+                            ) => events.push(Err(())),
+                            _ => {}
+                        },
+                    )
+                    .await;
+                    events
+                }
+            };
+            // The header is followed with `is_best` and an empty list of additional data.
+            let header = |number: u64| {
+                let mut notification = header::HeaderRef {
+                    parent_hash: &[1; 32],
+                    number,
+                    state_root: &[2; 32],
+                    extrinsics_root: &[3; 32],
+                    digest: header::DigestRef::empty(),
+                }
+                .scale_encoding_vec();
+                notification.extend_from_slice(&[1, 0]);
+                notification
+            };
+
+            assert_eq!(announce(header(1)).await, [Ok(true)]);
+            assert_eq!(announce(header(0)).await, [Ok(false)]);
+
+            network1.set_local_best_block(0, [4; 32], 5).await;
+            assert_eq!(announce(header(5)).await, [Ok(false)]);
+            assert_eq!(announce(header(6)).await, [Ok(true)]);
+
+            // Announces whose header can't be decoded are reported as protocol errors.
+            assert_eq!(announce(vec![0; 8]).await, [Err(())]);
+        });
+    }
+}
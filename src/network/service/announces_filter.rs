@@ -0,0 +1,153 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::libp2p::PeerId;
+use crate::util::SipHasherBuild;
+
+use alloc::collections::VecDeque;
+use core::{
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+/// Index of the chain, identity of the sender, and hash of the announce.
+type Key = (usize, PeerId, [u8; 32]);
+
+/// Bounded-size filter that detects block announces that have already been received from the
+/// same peer recently.
+///
+/// Announces are identified by the index of the chain, the identity of the sender, and the hash
+/// of the announce. An announce is considered as a duplicate only if an identical one has been
+/// received less than a certain amount of time ago, in order to not suppress announces that are
+/// legitimately repeated, for example after a reorg.
+pub(super) struct AnnouncesFilter<TNow> {
+    /// Entries in the filter, in the order in which they have been inserted, alongside with
+    /// their expiration time.
+    entries: VecDeque<(TNow, Key)>,
+
+    /// Keys found in [`AnnouncesFilter::entries`], and the expiration time of the most recent
+    /// corresponding entry.
+    keys: hashbrown::HashMap<Key, TNow, SipHasherBuild>,
+
+    /// Maximum number of entries.
+    capacity: usize,
+
+    /// Duration during which an announce is considered a duplicate.
+    window: Duration,
+}
+
+impl<TNow> AnnouncesFilter<TNow>
+where
+    TNow: Clone + Add<Duration, Output = TNow> + Sub<TNow, Output = Duration> + Ord,
+{
+    /// Creates a new empty filter.
+    ///
+    /// A `capacity` of 0 disables the filter.
+    pub(super) fn new(capacity: usize, window: Duration, hasher: SipHasherBuild) -> Self {
+        AnnouncesFilter {
+            entries: VecDeque::with_capacity(capacity),
+            keys: hashbrown::HashMap::with_capacity_and_hasher(capacity, hasher),
+            capacity,
+            window,
+        }
+    }
+
+    /// Registers the given announce. Returns `false` if an identical announce has been inserted
+    /// less than `window` ago, in which case the announce should be discarded.
+    pub(super) fn insert(
+        &mut self,
+        now: &TNow,
+        chain_index: usize,
+        peer_id: &PeerId,
+        announce_hash: [u8; 32],
+    ) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+
+        // Remove the entries that have expired.
+        while matches!(self.entries.front(), Some((exp, _)) if *exp <= *now) {
+            self.pop_front();
+        }
+
+        let key = (chain_index, peer_id.clone(), announce_hash);
+        if matches!(self.keys.get(&key), Some(exp) if *exp > *now) {
+            return false;
+        }
+
+        while self.entries.len() >= self.capacity {
+            self.pop_front();
+        }
+
+        let expiration = now.clone() + self.window;
+        self.keys.insert(key.clone(), expiration.clone());
+        self.entries.push_back((expiration, key));
+        true
+    }
+
+    /// Removes the oldest entry of [`AnnouncesFilter::entries`].
+    fn pop_front(&mut self) {
+        let (expiration, key) = self.entries.pop_front().unwrap();
+        // The same key might have been inserted again later, in which case the corresponding
+        // entry in `keys` must be kept.
+        if self.keys.get(&key) == Some(&expiration) {
+            self.keys.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnnouncesFilter;
+    use crate::{libp2p::PeerId, util::SipHasherBuild};
+    use core::time::Duration;
+
+    #[test]
+    fn duplicate_dropped_within_window() {
+        let mut filter =
+            AnnouncesFilter::new(16, Duration::from_secs(10), SipHasherBuild::new([0; 16]));
+        let peer = PeerId::from_public_key(&crate::libp2p::peer_id::PublicKey::Ed25519([0; 32]));
+
+        assert!(filter.insert(&Duration::from_secs(0), 0, &peer, [1; 32]));
+        assert!(!filter.insert(&Duration::from_secs(5), 0, &peer, [1; 32]));
+
+        // Different chain or different announce.
+        assert!(filter.insert(&Duration::from_secs(5), 1, &peer, [1; 32]));
+        assert!(filter.insert(&Duration::from_secs(5), 0, &peer, [2; 32]));
+
+        // After the window.
+        assert!(filter.insert(&Duration::from_secs(10), 0, &peer, [1; 32]));
+        assert!(!filter.insert(&Duration::from_secs(11), 0, &peer, [1; 32]));
+    }
+
+    #[test]
+    fn capacity_respected() {
+        let mut filter =
+            AnnouncesFilter::new(2, Duration::from_secs(10), SipHasherBuild::new([0; 16]));
+        let peer = PeerId::from_public_key(&crate::libp2p::peer_id::PublicKey::Ed25519([0; 32]));
+
+        assert!(filter.insert(&Duration::from_secs(0), 0, &peer, [1; 32]));
+        assert!(filter.insert(&Duration::from_secs(0), 0, &peer, [2; 32]));
+        assert!(filter.insert(&Duration::from_secs(0), 0, &peer, [3; 32]));
+        assert_eq!(filter.entries.len(), 2);
+        assert_eq!(filter.keys.len(), 2);
+
+        // `[1; 32]` has been evicted and is no longer considered as a duplicate.
+        assert!(filter.insert(&Duration::from_secs(0), 0, &peer, [1; 32]));
+        assert!(!filter.insert(&Duration::from_secs(0), 0, &peer, [3; 32]));
+    }
+}
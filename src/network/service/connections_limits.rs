@@ -0,0 +1,264 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::libp2p::PeerId;
+use crate::util::SipHasherBuild;
+
+use alloc::collections::BTreeMap;
+use core::cmp;
+
+/// Tracks the established connections and decides which connections must be refused or closed
+/// in order to respect the limits on the number of inbound and outbound connections.
+///
+/// Connections with reserved peers use a reserved slot, if any is free, and don't count towards
+/// these limits. Connections with reserved peers are never evicted, even if they don't use a
+/// reserved slot.
+pub(super) struct ConnectionsLimits<TConnId> {
+    /// Maximum number of inbound connections that don't use a reserved slot.
+    max_inbound: usize,
+    /// Maximum number of outbound connections that don't use a reserved slot.
+    max_outbound: usize,
+    /// Number of reserved slots.
+    reserved_slots: usize,
+    /// List of peers allowed to use a reserved slot.
+    reserved_peers: hashbrown::HashSet<PeerId, SipHasherBuild>,
+
+    /// List of established connections.
+    connections: BTreeMap<TConnId, Connection>,
+    /// Number of entries in [`ConnectionsLimits::connections`] that are inbound and don't use a
+    /// reserved slot.
+    num_inbound: usize,
+    /// Number of entries in [`ConnectionsLimits::connections`] that are outbound and don't use a
+    /// reserved slot.
+    num_outbound: usize,
+    /// Number of entries in [`ConnectionsLimits::connections`] that use a reserved slot.
+    num_reserved: usize,
+
+    /// Value to store in [`Connection::established_order`] for the next connection.
+    next_established_order: u64,
+}
+
+struct Connection {
+    peer_id: PeerId,
+    inbound: bool,
+    uses_reserved_slot: bool,
+    /// Increases with each connection. Used to determine which connection is the most recent.
+    established_order: u64,
+}
+
+impl<TConnId: Ord + Clone> ConnectionsLimits<TConnId> {
+    /// Creates a new empty collection.
+    pub(super) fn new(
+        max_inbound: usize,
+        max_outbound: usize,
+        reserved_slots: usize,
+        reserved_peers: impl Iterator<Item = PeerId>,
+        hasher: SipHasherBuild,
+    ) -> Self {
+        let mut reserved_peers_set = hashbrown::HashSet::with_hasher(hasher);
+        reserved_peers_set.extend(reserved_peers);
+
+        ConnectionsLimits {
+            max_inbound,
+            max_outbound,
+            reserved_slots,
+            reserved_peers: reserved_peers_set,
+            connections: BTreeMap::new(),
+            num_inbound: 0,
+            num_outbound: 0,
+            num_reserved: 0,
+            next_established_order: 0,
+        }
+    }
+
+    /// Returns `true` if a new outbound connection towards the given peer is allowed.
+    ///
+    /// `num_pending` is the number of outbound connections that are being opened but not yet
+    /// established, and that will count towards the limits once established.
+    pub(super) fn can_dial(&self, peer_id: &PeerId, num_pending: usize) -> bool {
+        if self.uses_reserved_slot(peer_id) {
+            return true;
+        }
+
+        self.num_outbound.saturating_add(num_pending) < self.max_outbound
+    }
+
+    /// Returns the connection that must be closed if a new connection with the given parameters
+    /// is established, or `None` if no connection needs to be closed. The returned connection
+    /// can be the new connection itself.
+    ///
+    /// Only inbound connections are ever evicted, as the number of outbound connections is
+    /// limited by [`ConnectionsLimits::can_dial`].
+    ///
+    /// The least valuable connection is evicted. A connection is less valuable than another if
+    /// `value` returns a lower value for its peer, or, for equal values, if it has been
+    /// established more recently. Connections with reserved peers are never evicted.
+    ///
+    /// This method doesn't modify the state of `self`. [`ConnectionsLimits::insert`] and
+    /// [`ConnectionsLimits::remove`] must be called afterwards.
+    pub(super) fn eviction_candidate(
+        &self,
+        connection_id: &TConnId,
+        peer_id: &PeerId,
+        inbound: bool,
+        value: impl Fn(&PeerId) -> usize,
+    ) -> Option<TConnId> {
+        if !inbound || self.uses_reserved_slot(peer_id) || self.num_inbound < self.max_inbound {
+            return None;
+        }
+
+        let new_connection = if self.reserved_peers.contains(peer_id) {
+            None
+        } else {
+            Some((connection_id, value(peer_id), self.next_established_order))
+        };
+
+        self.connections
+            .iter()
+            .filter(|(_, c)| c.inbound && !self.reserved_peers.contains(&c.peer_id))
+            .map(|(id, c)| (id, value(&c.peer_id), c.established_order))
+            .chain(new_connection)
+            .min_by_key(|(_, value, order)| (*value, cmp::Reverse(*order)))
+            .map(|(id, _, _)| id.clone())
+    }
+
+    /// Inserts a newly-established connection.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the connection is already present.
+    ///
+    pub(super) fn insert(&mut self, connection_id: TConnId, peer_id: PeerId, inbound: bool) {
+        let uses_reserved_slot = self.uses_reserved_slot(&peer_id);
+        if uses_reserved_slot {
+            self.num_reserved += 1;
+        } else if inbound {
+            self.num_inbound += 1;
+        } else {
+            self.num_outbound += 1;
+        }
+
+        let established_order = self.next_established_order;
+        self.next_established_order += 1;
+
+        let _prev_value = self.connections.insert(
+            connection_id,
+            Connection {
+                peer_id,
+                inbound,
+                uses_reserved_slot,
+                established_order,
+            },
+        );
+        assert!(_prev_value.is_none());
+    }
+
+    /// Removes a connection. Returns `true` if this frees a slot that can be used by
+    /// [`ConnectionsLimits::can_dial`]. Has no effect if the connection isn't present.
+    pub(super) fn remove(&mut self, connection_id: &TConnId) -> bool {
+        match self.connections.remove(connection_id) {
+            Some(Connection {
+                uses_reserved_slot: true,
+                ..
+            }) => {
+                self.num_reserved -= 1;
+                true
+            }
+            Some(Connection { inbound: true, .. }) => {
+                self.num_inbound -= 1;
+                false
+            }
+            Some(Connection { .. }) => {
+                self.num_outbound -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if a new connection with the given peer would use a reserved slot.
+    fn uses_reserved_slot(&self, peer_id: &PeerId) -> bool {
+        self.reserved_peers.contains(peer_id) && self.num_reserved < self.reserved_slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConnectionsLimits;
+    use crate::libp2p::{peer_id, PeerId};
+    use crate::util::SipHasherBuild;
+
+    fn peer(n: u8) -> PeerId {
+        PeerId::from_public_key(&peer_id::PublicKey::Ed25519([n; 32]))
+    }
+
+    #[test]
+    fn inbound_eviction_respects_reservations() {
+        // Two inbound connections, and one reserved slot. Peers 1 and 2 are reserved.
+        let mut limits = ConnectionsLimits::new(
+            2,
+            2,
+            1,
+            [peer(1), peer(2)].into_iter(),
+            SipHasherBuild::new([0; 16]),
+        );
+
+        // Peer 1 uses the reserved slot, and peer 2 falls back to a regular slot.
+        for (id, n) in [(1, 1), (2, 2), (3, 3)] {
+            assert_eq!(limits.eviction_candidate(&id, &peer(n), true, |_| 0), None);
+            limits.insert(id, peer(n), true);
+        }
+
+        // Above the limit. Peer 3 is the only one that isn't reserved.
+        assert_eq!(
+            limits.eviction_candidate(&4, &peer(4), true, |_| 0),
+            Some(4)
+        );
+        assert_eq!(
+            limits.eviction_candidate(&4, &peer(4), true, |p| usize::from(*p == peer(4))),
+            Some(3)
+        );
+        limits.insert(4, peer(4), true);
+        limits.remove(&3);
+
+        // A reserved peer is never evicted, even if it doesn't use a reserved slot.
+        assert_eq!(
+            limits.eviction_candidate(&5, &peer(2), true, |p| usize::from(*p != peer(2))),
+            Some(4)
+        );
+        limits.insert(5, peer(2), true);
+        limits.remove(&4);
+        assert_eq!(
+            limits.eviction_candidate(&6, &peer(6), true, |_| 0),
+            Some(6)
+        );
+
+        // Outbound connections are limited separately.
+        assert!(limits.can_dial(&peer(7), 1));
+        assert!(!limits.can_dial(&peer(7), 2));
+        limits.insert(7, peer(7), false);
+        limits.insert(8, peer(8), false);
+        assert_eq!(limits.eviction_candidate(&9, &peer(9), false, |_| 0), None);
+        assert!(!limits.can_dial(&peer(9), 0));
+
+        // Freeing the reserved slot makes it possible to dial a reserved peer again.
+        assert!(!limits.can_dial(&peer(1), 0));
+        assert!(limits.remove(&1));
+        assert!(limits.can_dial(&peer(1), 0));
+        assert!(!limits.can_dial(&peer(9), 0));
+    }
+}
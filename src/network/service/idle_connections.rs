@@ -0,0 +1,252 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::libp2p::PeerId;
+use crate::util::SipHasherBuild;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{ops::Add, time::Duration};
+
+/// Tracks the moment of the latest activity on each established connection, in order to find
+/// the connections that have been idle for too long.
+///
+/// Only established connections are tracked. Connections that are still handshaking are
+/// never considered as idle, and are instead subject to the handshake timeout.
+///
+/// Connections with reserved peers and connections on which a ping has recently succeeded are
+/// never considered as idle.
+pub(super) struct IdleConnections<TConnId, TNow> {
+    /// Duration after which a connection without any activity is considered as idle. `None` if
+    /// connections are never considered as idle.
+    timeout: Option<Duration>,
+
+    /// Duration after a successful ping during which a connection isn't considered as idle.
+    recent_ping_duration: Duration,
+
+    /// List of peers whose connections are never considered as idle.
+    reserved_peers: hashbrown::HashSet<PeerId, SipHasherBuild>,
+
+    /// List of established connections.
+    connections: BTreeMap<TConnId, Connection<TNow>>,
+}
+
+struct Connection<TNow> {
+    peer_id: PeerId,
+    /// Moment when the connection has been established or when the latest activity with the
+    /// peer has happened, whichever is the most recent.
+    last_activity: TNow,
+    /// Moment when the latest ping has succeeded on this connection, if any.
+    last_ping: Option<TNow>,
+}
+
+impl<TConnId, TNow> IdleConnections<TConnId, TNow>
+where
+    TConnId: Ord + Clone,
+    TNow: Clone + Add<Duration, Output = TNow> + Ord,
+{
+    /// Creates a new empty collection.
+    pub(super) fn new(
+        timeout: Option<Duration>,
+        recent_ping_duration: Duration,
+        reserved_peers: impl Iterator<Item = PeerId>,
+        hasher: SipHasherBuild,
+    ) -> Self {
+        let mut reserved_peers_set = hashbrown::HashSet::with_hasher(hasher);
+        reserved_peers_set.extend(reserved_peers);
+
+        IdleConnections {
+            timeout,
+            recent_ping_duration,
+            reserved_peers: reserved_peers_set,
+            connections: BTreeMap::new(),
+        }
+    }
+
+    /// Inserts a newly-established connection. `now` is considered as the moment of the latest
+    /// activity on this connection.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the connection is already present.
+    ///
+    pub(super) fn insert(&mut self, connection_id: TConnId, peer_id: PeerId, now: &TNow) {
+        let _prev_value = self.connections.insert(
+            connection_id,
+            Connection {
+                peer_id,
+                last_activity: now.clone(),
+                last_ping: None,
+            },
+        );
+        assert!(_prev_value.is_none());
+    }
+
+    /// Removes a connection. Has no effect if the connection isn't present.
+    pub(super) fn remove(&mut self, connection_id: &TConnId) {
+        self.connections.remove(connection_id);
+    }
+
+    /// Notes that some activity, such as a request or a notification, has happened with the
+    /// given peer. This resets the idle timeout of all the connections with this peer.
+    pub(super) fn report_activity(&mut self, peer_id: &PeerId, now: &TNow) {
+        // TODO: O(n)
+        for connection in self.connections.values_mut() {
+            if connection.peer_id == *peer_id && connection.last_activity < *now {
+                connection.last_activity = now.clone();
+            }
+        }
+    }
+
+    /// Notes that a ping has succeeded on the given connection. The connection isn't considered
+    /// as idle for the next `recent_ping_duration` passed to [`IdleConnections::new`].
+    ///
+    /// Has no effect if the connection isn't present.
+    pub(super) fn report_ping(&mut self, connection_id: &TConnId, now: &TNow) {
+        if let Some(connection) = self.connections.get_mut(connection_id) {
+            connection.last_ping = Some(now.clone());
+        }
+    }
+
+    /// Returns the list of connections that have been idle for longer than the timeout and that
+    /// should be closed.
+    ///
+    /// `has_substreams` must return `true` if there exists any substream with the given peer.
+    /// Connections with such peers are never considered as idle.
+    ///
+    /// This method doesn't modify the state of `self`. [`IdleConnections::remove`] must be
+    /// called afterwards.
+    pub(super) fn idle_connections(
+        &self,
+        now: &TNow,
+        has_substreams: impl Fn(&PeerId) -> bool,
+    ) -> Vec<TConnId> {
+        let timeout = match self.timeout {
+            Some(t) => t,
+            None => return Vec::new(),
+        };
+
+        self.connections
+            .iter()
+            .filter(|(_, c)| c.last_activity.clone() + timeout <= *now)
+            .filter(|(_, c)| !self.reserved_peers.contains(&c.peer_id))
+            .filter(|(_, c)| {
+                c.last_ping
+                    .as_ref()
+                    .map_or(true, |p| p.clone() + self.recent_ping_duration <= *now)
+            })
+            .filter(|(_, c)| !has_substreams(&c.peer_id))
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdleConnections;
+    use crate::{libp2p::PeerId, util::SipHasherBuild};
+    use core::time::Duration;
+
+    fn peer(n: u8) -> PeerId {
+        PeerId::from_public_key(&crate::libp2p::peer_id::PublicKey::Ed25519([n; 32]))
+    }
+
+    #[test]
+    fn idle_connection_closed_after_timeout() {
+        let mut idle = IdleConnections::new(
+            Some(Duration::from_secs(10)),
+            Duration::from_secs(4),
+            [peer(3)].into_iter(),
+            SipHasherBuild::new([0; 16]),
+        );
+
+        idle.insert(0, peer(0), &Duration::from_secs(0));
+        idle.insert(1, peer(1), &Duration::from_secs(0));
+        idle.insert(2, peer(2), &Duration::from_secs(0));
+        idle.insert(3, peer(3), &Duration::from_secs(0));
+
+        // Peer 1 sends a request, while peer 2 has a substream open.
+        idle.report_activity(&peer(1), &Duration::from_secs(5));
+        let has_substreams = |p: &PeerId| *p == peer(2);
+
+        assert!(idle
+            .idle_connections(&Duration::from_secs(9), has_substreams)
+            .is_empty());
+        assert_eq!(
+            idle.idle_connections(&Duration::from_secs(10), has_substreams),
+            vec![0]
+        );
+
+        idle.remove(&0);
+        assert_eq!(
+            idle.idle_connections(&Duration::from_secs(15), has_substreams),
+            vec![1]
+        );
+
+        // Once its substreams are closed, the connection with peer 2 becomes idle as well.
+        idle.report_activity(&peer(2), &Duration::from_secs(15));
+        assert_eq!(
+            idle.idle_connections(&Duration::from_secs(25), |_| false),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn recently_pinged_not_idle() {
+        let mut idle = IdleConnections::new(
+            Some(Duration::from_secs(10)),
+            Duration::from_secs(4),
+            core::iter::empty(),
+            SipHasherBuild::new([0; 16]),
+        );
+
+        idle.insert(0, peer(0), &Duration::from_secs(0));
+        idle.insert(1, peer(1), &Duration::from_secs(0));
+
+        // A ping doesn't count as activity, but exempts the connection for a while.
+        idle.report_ping(&1, &Duration::from_secs(8));
+        assert_eq!(
+            idle.idle_connections(&Duration::from_secs(11), |_| false),
+            vec![0]
+        );
+        assert_eq!(
+            idle.idle_connections(&Duration::from_secs(12), |_| false),
+            vec![0, 1]
+        );
+
+        // Pings on connections that have been removed are ignored.
+        idle.remove(&0);
+        idle.report_ping(&0, &Duration::from_secs(12));
+        assert_eq!(
+            idle.idle_connections(&Duration::from_secs(12), |_| false),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn disabled() {
+        let mut idle = IdleConnections::new(
+            None,
+            Duration::from_secs(4),
+            core::iter::empty(),
+            SipHasherBuild::new([0; 16]),
+        );
+        idle.insert(0, peer(0), &Duration::from_secs(0));
+        assert!(idle
+            .idle_connections(&Duration::from_secs(1000), |_| false)
+            .is_empty());
+    }
+}
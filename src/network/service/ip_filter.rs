@@ -0,0 +1,166 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::libp2p::multiaddr::{Multiaddr, ProtocolRef};
+
+/// Range of IP addresses, in the CIDR notation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum IpRange {
+    /// Range of IPv4 addresses.
+    V4 {
+        /// Address whose `prefix_len` first bits are compared.
+        address: [u8; 4],
+        /// Number of bits of the prefix. Values above 32 are treated as 32.
+        prefix_len: u8,
+    },
+    /// Range of IPv6 addresses.
+    V6 {
+        /// Address whose `prefix_len` first bits are compared.
+        address: [u8; 16],
+        /// Number of bits of the prefix. Values above 128 are treated as 128.
+        prefix_len: u8,
+    },
+}
+
+impl IpRange {
+    /// Loopback addresses (`127.0.0.0/8` and `::1/128`).
+    ///
+    /// > **Note**: These addresses are typically used when testing, and should only be blocked
+    /// >           in production.
+    pub const LOOPBACK: [IpRange; 2] = [
+        IpRange::V4 {
+            address: [127, 0, 0, 0],
+            prefix_len: 8,
+        },
+        IpRange::V6 {
+            address: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+            prefix_len: 128,
+        },
+    ];
+
+    /// Private addresses, as defined in RFC1918 (`10.0.0.0/8`, `172.16.0.0/12` and
+    /// `192.168.0.0/16`) and RFC4193 (`fc00::/7`).
+    pub const PRIVATE: [IpRange; 4] = [
+        IpRange::V4 {
+            address: [10, 0, 0, 0],
+            prefix_len: 8,
+        },
+        IpRange::V4 {
+            address: [172, 16, 0, 0],
+            prefix_len: 12,
+        },
+        IpRange::V4 {
+            address: [192, 168, 0, 0],
+            prefix_len: 16,
+        },
+        IpRange::V6 {
+            address: [0xfc, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            prefix_len: 7,
+        },
+    ];
+
+    /// Returns `true` if the given multiaddress starts with an IP address that belongs to this
+    /// range.
+    ///
+    /// Multiaddresses that don't start with an IP address, such as DNS addresses, never belong
+    /// to any range.
+    pub fn contains(&self, multiaddr: &Multiaddr) -> bool {
+        match (self, multiaddr.iter().next()) {
+            (
+                IpRange::V4 {
+                    address,
+                    prefix_len,
+                },
+                Some(ProtocolRef::Ip4(ip)),
+            ) => prefix_matches(address, &ip, *prefix_len),
+            (
+                IpRange::V6 {
+                    address,
+                    prefix_len,
+                },
+                Some(ProtocolRef::Ip6(ip)),
+            ) => prefix_matches(address, &ip, *prefix_len),
+            _ => false,
+        }
+    }
+}
+
+/// Returns `true` if the `prefix_len` first bits of `a` and `b` are equal.
+fn prefix_matches(a: &[u8], b: &[u8], prefix_len: u8) -> bool {
+    debug_assert_eq!(a.len(), b.len());
+    let prefix_len = usize::from(prefix_len).min(a.len() * 8);
+
+    let full_bytes = prefix_len / 8;
+    if a[..full_bytes] != b[..full_bytes] {
+        return false;
+    }
+
+    let remaining_bits = prefix_len % 8;
+    if remaining_bits == 0 {
+        return true;
+    }
+
+    let mask = 0xffu8 << (8 - remaining_bits);
+    (a[full_bytes] & mask) == (b[full_bytes] & mask)
+}
+
+/// Returns `true` if the given multiaddress belongs to any of the ranges.
+pub(super) fn is_blocked(ranges: &[IpRange], multiaddr: &Multiaddr) -> bool {
+    ranges.iter().any(|range| range.contains(multiaddr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IpRange;
+
+    #[test]
+    fn ranges() {
+        let contains = |range: &IpRange, addr: &str| range.contains(&addr.parse().unwrap());
+
+        let range = IpRange::V4 {
+            address: [172, 16, 0, 0],
+            prefix_len: 12,
+        };
+        assert!(contains(&range, "/ip4/172.16.0.1/tcp/30333"));
+        assert!(contains(&range, "/ip4/172.31.255.255/tcp/30333"));
+        assert!(!contains(&range, "/ip4/172.32.0.1/tcp/30333"));
+        assert!(!contains(&range, "/ip6/::1/tcp/30333"));
+        assert!(!contains(&range, "/dns/example.com/tcp/30333"));
+
+        assert!(IpRange::LOOPBACK
+            .iter()
+            .any(|r| contains(r, "/ip6/::1/tcp/30333")));
+        assert!(IpRange::PRIVATE
+            .iter()
+            .any(|r| contains(r, "/ip6/fd00::1/tcp/30333")));
+        assert!(!IpRange::PRIVATE
+            .iter()
+            .any(|r| contains(r, "/ip4/8.8.8.8/tcp/30333")));
+
+        let everything = IpRange::V4 {
+            address: [1, 2, 3, 4],
+            prefix_len: 0,
+        };
+        assert!(contains(&everything, "/ip4/8.8.8.8/tcp/30333"));
+        let exact = IpRange::V4 {
+            address: [1, 2, 3, 4],
+            prefix_len: 200,
+        };
+        assert!(contains(&exact, "/ip4/1.2.3.4/tcp/30333"));
+        assert!(!contains(&exact, "/ip4/1.2.3.5/tcp/30333"));
+    }
+}
@@ -0,0 +1,144 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::multiaddr;
+use crate::libp2p::PeerId;
+
+use alloc::{vec, vec::Vec};
+
+/// Bounded-size list of the addresses of the local node, as reported by remotes.
+///
+/// A single remote could lie about the address it sees the local node at. For this reason, an
+/// address is only considered as confirmed once a certain number of distinct remotes have
+/// reported it. Each remote counts only for the address it has most recently reported.
+pub(super) struct ObservedAddrs {
+    /// List of addresses, and the remotes that have reported them. The list of remotes is never
+    /// empty.
+    entries: Vec<(multiaddr::Multiaddr, Vec<PeerId>)>,
+
+    /// Maximum number of entries in [`ObservedAddrs::entries`].
+    capacity: usize,
+
+    /// Number of distinct remotes that must have reported an address for it to be confirmed.
+    /// Remotes beyond this number aren't stored.
+    confirmations: usize,
+}
+
+impl ObservedAddrs {
+    /// Creates a new empty list.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `confirmations` is 0.
+    ///
+    pub(super) fn new(capacity: usize, confirmations: usize) -> Self {
+        assert_ne!(confirmations, 0);
+        ObservedAddrs {
+            entries: Vec::with_capacity(capacity),
+            capacity,
+            confirmations,
+        }
+    }
+
+    /// Registers the fact that `reporter` sees the local node at the given address.
+    pub(super) fn insert(&mut self, reporter: &PeerId, addr: multiaddr::Multiaddr) {
+        // Remove the previous report of this remote.
+        for (_, reporters) in &mut self.entries {
+            reporters.retain(|r| r != reporter);
+        }
+        self.entries.retain(|(_, reporters)| !reporters.is_empty());
+
+        if let Some((_, reporters)) = self.entries.iter_mut().find(|(a, _)| *a == addr) {
+            if reporters.len() < self.confirmations {
+                reporters.push(reporter.clone());
+            }
+            return;
+        }
+
+        if self.entries.len() >= self.capacity {
+            // Evict the address reported by the fewest remotes. In case of equality, the oldest
+            // entry is evicted.
+            match self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (_, reporters))| reporters.len())
+            {
+                Some((index, (_, reporters))) if reporters.len() < self.confirmations => {
+                    self.entries.remove(index);
+                }
+                // All the entries are confirmed. Ignore the new address.
+                _ => return,
+            }
+        }
+
+        self.entries.push((addr, vec![reporter.clone()]));
+    }
+
+    /// Returns the list of addresses that have been reported by enough distinct remotes.
+    pub(super) fn confirmed(&'_ self) -> impl Iterator<Item = &'_ multiaddr::Multiaddr> + '_ {
+        self.entries
+            .iter()
+            .filter(|(_, reporters)| reporters.len() >= self.confirmations)
+            .map(|(addr, _)| addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{multiaddr::Multiaddr, ObservedAddrs};
+    use crate::libp2p::{peer_id, PeerId};
+
+    fn peer(n: u8) -> PeerId {
+        PeerId::from_public_key(&peer_id::PublicKey::Ed25519([n; 32]))
+    }
+
+    #[test]
+    fn confirmed_by_distinct_peers() {
+        let mut observed = ObservedAddrs::new(4, 2);
+        let addr1: Multiaddr = "/ip4/1.2.3.4/tcp/30333".parse().unwrap();
+        let addr2: Multiaddr = "/ip4/5.6.7.8/tcp/30333".parse().unwrap();
+
+        observed.insert(&peer(1), addr1.clone());
+        observed.insert(&peer(1), addr1.clone());
+        assert_eq!(observed.confirmed().count(), 0);
+
+        observed.insert(&peer(2), addr1.clone());
+        assert_eq!(observed.confirmed().collect::<Vec<_>>(), [&addr1]);
+
+        // Reports replace the previous report of the same peer.
+        observed.insert(&peer(2), addr2.clone());
+        assert_eq!(observed.confirmed().count(), 0);
+        observed.insert(&peer(1), addr2.clone());
+        assert_eq!(observed.confirmed().collect::<Vec<_>>(), [&addr2]);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut observed = ObservedAddrs::new(2, 2);
+        let confirmed: Multiaddr = "/ip4/1.2.3.4/tcp/30333".parse().unwrap();
+        observed.insert(&peer(1), confirmed.clone());
+        observed.insert(&peer(2), confirmed.clone());
+
+        for n in 3..20 {
+            observed.insert(&peer(n), format!("/ip4/9.9.9.{}/tcp/1", n).parse().unwrap());
+            assert!(observed.entries.len() <= 2);
+        }
+
+        assert_eq!(observed.confirmed().collect::<Vec<_>>(), [&confirmed]);
+    }
+}
@@ -0,0 +1,91 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::libp2p::PeerId;
+use crate::util::SipHasherBuild;
+
+use core::{ops::Add, time::Duration};
+
+/// List of peers that have misbehaved and that shouldn't be assigned a slot for a certain
+/// amount of time.
+pub(super) struct Penalties<TNow> {
+    /// For each penalized peer, the moment when the penalty expires.
+    peers: hashbrown::HashMap<PeerId, TNow, SipHasherBuild>,
+
+    /// Duration of a penalty.
+    duration: Duration,
+}
+
+impl<TNow> Penalties<TNow>
+where
+    TNow: Clone + Add<Duration, Output = TNow> + Ord,
+{
+    /// Creates a new empty list.
+    pub(super) fn new(duration: Duration, hasher: SipHasherBuild) -> Self {
+        Penalties {
+            peers: hashbrown::HashMap::with_hasher(hasher),
+            duration,
+        }
+    }
+
+    /// Penalizes the given peer. If the peer was already penalized, the penalty is extended.
+    pub(super) fn penalize(&mut self, now: &TNow, peer_id: &PeerId) {
+        self.peers
+            .insert(peer_id.clone(), now.clone() + self.duration);
+    }
+
+    /// Returns `true` if the given peer has been penalized and its penalty hasn't been removed
+    /// with [`Penalties::remove_expired`] yet.
+    pub(super) fn is_penalized(&self, peer_id: &PeerId) -> bool {
+        self.peers.contains_key(peer_id)
+    }
+
+    /// Removes the penalties that have expired.
+    pub(super) fn remove_expired(&mut self, now: &TNow) {
+        self.peers.retain(|_, expiration| *expiration > *now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Penalties;
+    use crate::{libp2p::PeerId, util::SipHasherBuild};
+    use core::time::Duration;
+
+    #[test]
+    fn penalty_expires() {
+        let mut penalties = Penalties::new(Duration::from_secs(10), SipHasherBuild::new([0; 16]));
+        let peer1 = PeerId::from_public_key(&crate::libp2p::peer_id::PublicKey::Ed25519([0; 32]));
+        let peer2 = PeerId::from_public_key(&crate::libp2p::peer_id::PublicKey::Ed25519([1; 32]));
+
+        penalties.penalize(&Duration::from_secs(0), &peer1);
+        penalties.penalize(&Duration::from_secs(5), &peer2);
+        assert!(penalties.is_penalized(&peer1));
+        assert!(penalties.is_penalized(&peer2));
+
+        penalties.remove_expired(&Duration::from_secs(10));
+        assert!(!penalties.is_penalized(&peer1));
+        assert!(penalties.is_penalized(&peer2));
+
+        // Penalizing again extends the penalty.
+        penalties.penalize(&Duration::from_secs(12), &peer2);
+        penalties.remove_expired(&Duration::from_secs(20));
+        assert!(penalties.is_penalized(&peer2));
+        penalties.remove_expired(&Duration::from_secs(22));
+        assert!(!penalties.is_penalized(&peer2));
+    }
+}
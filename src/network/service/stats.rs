@@ -0,0 +1,206 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Statistics about the messages exchanged on the protocols of a chain.
+///
+/// See [`super::ChainNetwork::protocol_stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtocolsStats {
+    /// Block requests and responses.
+    pub sync: MessagesStats,
+    /// Block announces notifications.
+    pub block_announces: MessagesStats,
+    /// Transactions notifications.
+    pub transactions: MessagesStats,
+    /// GrandPa notifications.
+    pub grandpa: MessagesStats,
+}
+
+/// Number of messages and bytes exchanged on a protocol.
+///
+/// The counters wrap around on overflow, which, considering that they are 64 bits, is never
+/// expected to happen in practice.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MessagesStats {
+    /// Number of messages sent to remotes.
+    pub messages_sent: u64,
+    /// Number of messages received from remotes.
+    pub messages_received: u64,
+    /// Total size in bytes of the messages sent to remotes.
+    pub bytes_sent: u64,
+    /// Total size in bytes of the messages received from remotes.
+    pub bytes_received: u64,
+}
+
+/// Protocol whose messages are counted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Protocol {
+    Sync,
+    BlockAnnounces,
+    Transactions,
+    Grandpa,
+}
+
+/// Counters of the messages exchanged on the protocols of a chain.
+///
+/// The counters are atomic rather than protected by a `Mutex`, so that they can be updated
+/// without any asynchronous operation.
+#[derive(Default)]
+pub(super) struct ChainStats {
+    sync: Counters,
+    block_announces: Counters,
+    transactions: Counters,
+    grandpa: Counters,
+}
+
+#[derive(Default)]
+struct Counters {
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl ChainStats {
+    /// Records a message of `num_bytes` bytes sent to a remote.
+    pub(super) fn record_sent(&self, protocol: Protocol, num_bytes: usize) {
+        let counters = self.counters(protocol);
+        counters.messages_sent.fetch_add(1, Ordering::Relaxed);
+        counters.bytes_sent.fetch_add(
+            u64::try_from(num_bytes).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Records the same message of `num_bytes` bytes sent to `num_remotes` remotes.
+    pub(super) fn record_broadcast(
+        &self,
+        protocol: Protocol,
+        num_remotes: usize,
+        num_bytes: usize,
+    ) {
+        let num_remotes = u64::try_from(num_remotes).unwrap_or(u64::MAX);
+        let counters = self.counters(protocol);
+        counters
+            .messages_sent
+            .fetch_add(num_remotes, Ordering::Relaxed);
+        counters.bytes_sent.fetch_add(
+            u64::try_from(num_bytes)
+                .unwrap_or(u64::MAX)
+                .saturating_mul(num_remotes),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Records a message of `num_bytes` bytes received from a remote.
+    pub(super) fn record_received(&self, protocol: Protocol, num_bytes: usize) {
+        let counters = self.counters(protocol);
+        counters.messages_received.fetch_add(1, Ordering::Relaxed);
+        counters.bytes_received.fetch_add(
+            u64::try_from(num_bytes).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Returns the current value of the counters.
+    pub(super) fn snapshot(&self) -> ProtocolsStats {
+        ProtocolsStats {
+            sync: self.sync.snapshot(),
+            block_announces: self.block_announces.snapshot(),
+            transactions: self.transactions.snapshot(),
+            grandpa: self.grandpa.snapshot(),
+        }
+    }
+
+    /// Sets all the counters back to zero.
+    pub(super) fn reset(&self) {
+        for counters in [
+            &self.sync,
+            &self.block_announces,
+            &self.transactions,
+            &self.grandpa,
+        ] {
+            counters.messages_sent.store(0, Ordering::Relaxed);
+            counters.messages_received.store(0, Ordering::Relaxed);
+            counters.bytes_sent.store(0, Ordering::Relaxed);
+            counters.bytes_received.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn counters(&self, protocol: Protocol) -> &Counters {
+        match protocol {
+            Protocol::Sync => &self.sync,
+            Protocol::BlockAnnounces => &self.block_announces,
+            Protocol::Transactions => &self.transactions,
+            Protocol::Grandpa => &self.grandpa,
+        }
+    }
+}
+
+impl Counters {
+    fn snapshot(&self) -> MessagesStats {
+        MessagesStats {
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChainStats, MessagesStats, Protocol, ProtocolsStats};
+
+    #[test]
+    fn protocols_counted_independently() {
+        let stats = ChainStats::default();
+
+        stats.record_sent(Protocol::Transactions, 100);
+        stats.record_sent(Protocol::Transactions, 20);
+        stats.record_received(Protocol::Grandpa, 7);
+        stats.record_sent(Protocol::Grandpa, 3);
+
+        assert_eq!(
+            stats.snapshot(),
+            ProtocolsStats {
+                transactions: MessagesStats {
+                    messages_sent: 2,
+                    messages_received: 0,
+                    bytes_sent: 120,
+                    bytes_received: 0,
+                },
+                grandpa: MessagesStats {
+                    messages_sent: 1,
+                    messages_received: 1,
+                    bytes_sent: 3,
+                    bytes_received: 7,
+                },
+                ..Default::default()
+            }
+        );
+
+        stats.reset();
+        assert_eq!(stats.snapshot(), ProtocolsStats::default());
+
+        stats.record_received(Protocol::Sync, 5);
+        assert_eq!(stats.snapshot().sync.bytes_received, 5);
+        assert_eq!(stats.snapshot().grandpa, MessagesStats::default());
+    }
+}
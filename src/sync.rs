@@ -50,6 +50,9 @@
 
 pub mod all;
 pub mod all_forks;
+pub mod block_gaps;
 pub mod optimistic;
 pub mod para;
+pub mod pipeline;
+pub mod requests_scheduler;
 pub mod warp_sync;
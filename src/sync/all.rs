@@ -34,7 +34,7 @@ use crate::{
     chain::{blocks_tree, chain_information},
     executor::{host, storage_diff, vm::ExecHint},
     header,
-    sync::{all_forks, optimistic, warp_sync},
+    sync::{all_forks, block_gaps, optimistic, warp_sync},
     verify,
 };
 
@@ -45,6 +45,7 @@ use core::{
     ops,
     time::Duration,
 };
+use rand::{Rng as _, SeedableRng as _};
 
 /// Configuration for the [`AllSync`].
 // TODO: review these fields
@@ -67,6 +68,12 @@ pub struct Config {
     /// See [`all_forks::Config::max_disjoint_headers`] for more information.
     pub max_disjoint_headers: usize,
 
+    /// Maximum number of blocks between the current best block and the point where a fork
+    /// diverges from the best chain. `None` for no limit.
+    ///
+    /// See [`all_forks::Config::max_fork_depth`] for more information.
+    pub max_fork_depth: Option<u64>,
+
     /// Maximum number of simultaneous pending requests made towards the same block.
     ///
     /// See [`all_forks::Config::max_requests_per_block`] for more information.
@@ -83,9 +90,20 @@ pub struct Config {
     /// block requests.
     pub download_ahead_blocks: NonZeroU32,
 
+    /// Minimum number of sources that must be synced with the local node before
+    /// [`AllSync::sync_readiness`] returns [`SyncReadiness::Ready`].
+    ///
+    /// A source is considered as synced if its best block is at most
+    /// [`SYNCED_SOURCE_MAX_DISTANCE`] blocks away from the local best block.
+    pub min_peers_for_ready: usize,
+
     /// If `Some`, the block bodies and storage are also synchronized. Contains the extra
     /// configuration.
     pub full: Option<ConfigFull>,
+
+    /// Seed used by the randomness necessary to verify the signatures of justifications and of
+    /// warp sync proofs. See [`crate::finality::signatures::verify_ed25519`].
+    pub randomness_seed: [u8; 32],
 }
 
 /// See [`Config::full`].
@@ -95,6 +113,10 @@ pub struct ConfigFull {
     pub finalized_runtime: host::HostVmPrototype,
 }
 
+/// Maximum difference between the best block number of a source and the local best block number
+/// for this source to be considered as synced. See [`Config::min_peers_for_ready`].
+pub const SYNCED_SOURCE_MAX_DISTANCE: u64 = 2;
+
 /// Identifier for a source in the [`AllSync`].
 //
 // Implementation note: this is an index in `AllSync::sources`.
@@ -116,6 +138,11 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
     /// Initializes a new state machine.
     pub fn new(config: Config) -> Self {
         let is_full = config.full.is_some();
+        let mut randomness = rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed);
+        let warp_sync_start = {
+            let finalized_block_header = config.chain_information.as_ref().finalized_block_header;
+            (finalized_block_header.number, finalized_block_header.hash())
+        };
 
         AllSync {
             inner: if let Some(config_full) = config.full {
@@ -128,12 +155,16 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                         full: Some(optimistic::ConfigFull {
                             finalized_runtime: config_full.finalized_runtime,
                         }),
+                        randomness_seed: randomness.sample(rand::distributions::Standard),
                     }),
                 }
             } else {
                 match warp_sync::warp_sync(warp_sync::Config {
                     start_chain_information: config.chain_information,
                     sources_capacity: config.sources_capacity,
+                    randomness_seed: randomness.sample(rand::distributions::Standard),
+                    // TODO: the finalized blocks of the sources aren't known at the moment
+                    target_quorum: None,
                 }) {
                     Ok(inner) => AllSyncInner::GrandpaWarpSync { inner },
                     Err((chain_information, warp_sync::WarpSyncInitError::NotGrandpa)) => {
@@ -146,6 +177,7 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                                 blocks_capacity: config.blocks_capacity,
                                 download_ahead_blocks: config.download_ahead_blocks,
                                 full: None,
+                                randomness_seed: randomness.sample(rand::distributions::Standard),
                             }),
                         }
                     }
@@ -158,7 +190,14 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 sources_capacity: config.sources_capacity,
                 blocks_capacity: config.blocks_capacity,
                 max_disjoint_headers: config.max_disjoint_headers,
+                max_fork_depth: config.max_fork_depth,
                 max_requests_per_block: config.max_requests_per_block,
+                min_peers_for_ready: config.min_peers_for_ready,
+                warp_sync_start,
+                block_gaps: block_gaps::BlockGaps::new(),
+                preferred_fork: None,
+                catch_up_mode: None,
+                randomness,
             },
         }
     }
@@ -225,6 +264,70 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// Returns the hash and number of the best block.
+    ///
+    /// Contrary to [`AllSync::best_block_header`], this doesn't decode anything and, except
+    /// during the warp syncing, doesn't hash anything. It is consequently appropriate to call
+    /// this method frequently.
+    ///
+    /// > **Note**: This value is provided only for informative purposes. Keep in mind that this
+    /// >           best block might be reverted in the future.
+    pub fn best_block(&self) -> ([u8; 32], u64) {
+        match &self.inner {
+            AllSyncInner::AllForks(sync) => sync.best_block(),
+            AllSyncInner::Optimistic { inner } => inner.best_block(),
+            AllSyncInner::GrandpaWarpSync { .. } => self.finalized_block(),
+            AllSyncInner::Poisoned => unreachable!(),
+        }
+    }
+
+    /// Returns the hash and number of the finalized block.
+    ///
+    /// See [`AllSync::best_block`].
+    pub fn finalized_block(&self) -> ([u8; 32], u64) {
+        match &self.inner {
+            AllSyncInner::AllForks(sync) => sync.finalized_block(),
+            AllSyncInner::Optimistic { inner } => inner.finalized_block(),
+            AllSyncInner::GrandpaWarpSync { .. } => {
+                let header = self.finalized_block_header();
+                (header.hash(), header.number)
+            }
+            AllSyncInner::Poisoned => unreachable!(),
+        }
+    }
+
+    /// Returns the ranges of finalized blocks that have been skipped by the warp sync and that
+    /// haven't been imported with [`AllSync::import_historical_block`] yet, as
+    /// `(first missing block, last missing block)` tuples, both inclusive.
+    ///
+    /// The blocks right after the end of each gap are known ahead of the blocks of the gap, and
+    /// gaps are expected to be filled by downloading blocks in descending order. Blocks can
+    /// however also be imported in ascending order from the start of the gap.
+    pub fn block_gaps(&self) -> Vec<(u64, u64)> {
+        self.shared.block_gaps.gaps().collect()
+    }
+
+    /// Imports a block that belongs to one of the ends of one of the gaps returned by
+    /// [`AllSync::block_gaps`], shrinking that gap.
+    ///
+    /// The block must be directly connected to the known blocks around the gap, in other words
+    /// either be the child of the block before the gap, or be the parent of the block after the
+    /// gap.
+    ///
+    /// > **Note**: The header isn't verified beyond checking that it connects to the chain, as
+    /// >           it is an ancestor of a block that has already been finalized.
+    pub fn import_historical_block(
+        &mut self,
+        scale_encoded_header: &[u8],
+    ) -> Result<(), HistoricalBlockError> {
+        let header =
+            header::decode(scale_encoded_header).map_err(HistoricalBlockError::InvalidHeader)?;
+        self.shared
+            .block_gaps
+            .import(header)
+            .map_err(HistoricalBlockError::Gap)
+    }
+
     /// Returns consensus information about the current best block of the chain.
     pub fn best_block_consensus(&self) -> chain_information::ChainInformationConsensusRef {
         match &self.inner {
@@ -298,6 +401,36 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// Returns whether the local best block can be trusted to be the head of the chain, based on
+    /// the best blocks reported by the sources.
+    ///
+    /// Contrary to [`AllSync::is_near_head_of_chain_heuristic`], the return value of this method
+    /// is deterministic and can be relied upon, for example in order to delay serving queries
+    /// until enough sources agree with the local node. The readiness can switch back to not
+    /// ready at any time, for example if sources are removed.
+    pub fn sync_readiness(&self) -> SyncReadiness {
+        let local_best = self.best_block_number();
+
+        let mut num_synced_peers = 0;
+        let mut num_peers_ahead = 0;
+        for source_id in self.sources() {
+            let (source_best, _) = self.source_best_block(source_id);
+            if source_best > local_best.saturating_add(SYNCED_SOURCE_MAX_DISTANCE) {
+                num_peers_ahead += 1;
+            } else if source_best.saturating_add(SYNCED_SOURCE_MAX_DISTANCE) >= local_best {
+                num_synced_peers += 1;
+            }
+        }
+
+        if num_peers_ahead != 0 && num_peers_ahead >= num_synced_peers {
+            SyncReadiness::BestBlockNotRecent { num_peers_ahead }
+        } else if num_synced_peers < self.shared.min_peers_for_ready {
+            SyncReadiness::NotEnoughPeers { num_synced_peers }
+        } else {
+            SyncReadiness::Ready
+        }
+    }
+
     /// Adds a new source to the sync state machine.
     ///
     /// Must be passed the best block number and hash of the source, as usually reported by the
@@ -605,6 +738,41 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// Sets the block whose descendants should be downloaded in priority, or `None` to remove
+    /// the preference.
+    ///
+    /// Sync requests are then only directed towards sources whose best block descends from this
+    /// block, or whose ancestry isn't known yet. See
+    /// [`all_forks::AllForksSync::set_preferred_fork`].
+    ///
+    /// > **Note**: The preference is only taken into account once the initial warp syncing or
+    /// >           optimistic syncing is over.
+    pub fn set_preferred_fork(&mut self, fork: Option<(u64, [u8; 32])>) {
+        self.shared.preferred_fork = fork;
+        if let AllSyncInner::AllForks(sync) = &mut self.inner {
+            sync.set_preferred_fork(fork);
+        }
+    }
+
+    /// Enables or disables the "catch up" mode, where the syncing focuses on advancing the
+    /// finalized block rather than on exploring the non-finalized forks. See
+    /// [`all_forks::AllForksSync::set_catch_up_mode`].
+    ///
+    /// > **Note**: Similar to [`AllSync::set_preferred_fork`], the mode is only taken into
+    /// >           account once the initial warp syncing or optimistic syncing is over.
+    pub fn set_catch_up_mode(&mut self, min_sources: Option<NonZeroU32>) {
+        self.shared.catch_up_mode = min_sources;
+        if let AllSyncInner::AllForks(sync) = &mut self.inner {
+            sync.set_catch_up_mode(min_sources);
+        }
+    }
+
+    /// Returns the value passed to the latest call to [`AllSync::set_catch_up_mode`], or `None`
+    /// if it has never been called.
+    pub fn catch_up_mode(&self) -> Option<NonZeroU32> {
+        self.shared.catch_up_mode
+    }
+
     /// Returns true if the source has earlier announced the block passed as parameter or one of
     /// its descendants.
     ///
@@ -987,6 +1155,85 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// Shuts down the state machine.
+    ///
+    /// The justifications that have been received but not verified yet are verified, in order
+    /// for the finality of the chain to be as recent as possible. Blocks and warp sync fragments
+    /// that are waiting to be verified are discarded. Then, all the sources are removed and all
+    /// the requests in progress are cancelled.
+    ///
+    /// > **Note**: Verifications that have already been started, such as a
+    /// >           [`BlockVerification`], aren't tied to the [`AllSync`] anymore. They must be
+    /// >           either finished or cancelled before the [`AllSync`] can be shut down.
+    pub fn shutdown(self) -> Shutdown<TRq, TSrc, TBl> {
+        let mut finalized_blocks = Vec::new();
+
+        let mut sync = self;
+        loop {
+            match sync.process_one() {
+                ProcessOne::AllSync(s) => {
+                    sync = s;
+                    break;
+                }
+                ProcessOne::VerifyJustification(verify) => {
+                    let (s, outcome) = verify.perform();
+                    sync = s;
+                    if let JustificationVerifyOutcome::NewFinalized {
+                        finalized_blocks: mut blocks,
+                        ..
+                    } = outcome
+                    {
+                        // Both lists are in decreasing block number.
+                        blocks.append(&mut finalized_blocks);
+                        finalized_blocks = blocks;
+                    }
+                }
+                ProcessOne::VerifyHeader(verify) => {
+                    sync = verify.cancel();
+                    break;
+                }
+                ProcessOne::VerifyBodyHeader(verify) => {
+                    sync = verify.cancel();
+                    break;
+                }
+                ProcessOne::VerifyWarpSyncFragment(verify) => {
+                    sync = verify.cancel();
+                    break;
+                }
+            }
+        }
+
+        let source_ids = sync.sources().collect::<Vec<_>>();
+        let mut sources = Vec::with_capacity(source_ids.len());
+        let mut cancelled_requests = Vec::new();
+        for source_id in source_ids {
+            let (user_data, requests) = sync.remove_source(source_id);
+            cancelled_requests.extend(requests);
+            sources.push((source_id, user_data));
+        }
+
+        // Requests that are tracked by this state machine rather than by the underlying syncing
+        // strategy aren't returned by `remove_source`.
+        for (request_id, request) in mem::take(&mut sync.shared.requests) {
+            match request {
+                RequestMapping::Inline(_, _, user_data) => {
+                    cancelled_requests.push((RequestId(request_id), user_data))
+                }
+                RequestMapping::AllForks(_) | RequestMapping::Optimistic(_) => {
+                    debug_assert!(false)
+                }
+            }
+        }
+        cancelled_requests.sort_unstable_by_key(|(id, _)| *id);
+
+        Shutdown {
+            chain_information: sync.as_chain_information().into(),
+            finalized_blocks,
+            sources,
+            cancelled_requests,
+        }
+    }
+
     /// Injects a block announcement made by a source into the state machine.
     pub fn block_announce(
         &mut self,
@@ -1610,6 +1857,25 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
     }
 }
 
+/// Output of [`AllSync::shutdown`].
+#[derive(Debug)]
+pub struct Shutdown<TRq, TSrc, TBl> {
+    /// Information about the latest finalized block and its ancestors. Can be persisted and
+    /// later passed back through [`Config::chain_information`].
+    pub chain_information: chain_information::ValidChainInformation,
+
+    /// Blocks that have been finalized while verifying the pending justifications, in
+    /// decreasing block number.
+    pub finalized_blocks: Vec<Block<TBl>>,
+
+    /// List of sources that were within the state machine.
+    pub sources: Vec<(SourceId, TSrc)>,
+
+    /// List of the requests that were in progress and that have been cancelled, ordered by
+    /// [`RequestId`].
+    pub cancelled_requests: Vec<(RequestId, TRq)>,
+}
+
 /// Outcome of calling [`AllSync::process_one`].
 pub enum ProcessOne<TRq, TSrc, TBl> {
     /// No block ready to be processed.
@@ -1728,6 +1994,17 @@ impl<TRq, TSrc, TBl> HeaderVerify<TRq, TSrc, TBl> {
         }
     }
 
+    /// Do not actually proceed with the verification. The block stays in the list of blocks
+    /// waiting to be verified.
+    pub fn cancel(self) -> AllSync<TRq, TSrc, TBl> {
+        match self.inner {
+            HeaderVerifyInner::AllForks(verify) => AllSync {
+                inner: AllSyncInner::AllForks(verify.cancel()),
+                shared: self.shared,
+            },
+        }
+    }
+
     /// Perform the verification.
     pub fn perform(
         self,
@@ -1737,15 +2014,18 @@ impl<TRq, TSrc, TBl> HeaderVerify<TRq, TSrc, TBl> {
         match self.inner {
             HeaderVerifyInner::AllForks(verify) => {
                 match verify.perform(now_from_unix_epoch, user_data) {
-                    all_forks::HeaderVerifyOutcome::Success { is_new_best, sync } => {
-                        HeaderVerifyOutcome::Success {
-                            is_new_best,
-                            sync: AllSync {
-                                inner: AllSyncInner::AllForks(sync),
-                                shared: self.shared,
-                            },
-                        }
-                    }
+                    all_forks::HeaderVerifyOutcome::Success {
+                        is_new_best,
+                        reorg,
+                        sync,
+                    } => HeaderVerifyOutcome::Success {
+                        is_new_best,
+                        reorg,
+                        sync: AllSync {
+                            inner: AllSyncInner::AllForks(sync),
+                            shared: self.shared,
+                        },
+                    },
                     all_forks::HeaderVerifyOutcome::Error {
                         sync,
                         error,
@@ -1762,6 +2042,18 @@ impl<TRq, TSrc, TBl> HeaderVerify<TRq, TSrc, TBl> {
                             all_forks::HeaderVerifyError::ConsensusMismatch => {
                                 HeaderVerifyError::ConsensusMismatch
                             }
+                            all_forks::HeaderVerifyError::ConflictingScheduledChange {
+                                pending_trigger_block_height,
+                            } => HeaderVerifyError::ConflictingScheduledChange {
+                                pending_trigger_block_height,
+                            },
+                            all_forks::HeaderVerifyError::ForkTooDeep {
+                                fork_point_height,
+                                best_block_height,
+                            } => HeaderVerifyError::ForkTooDeep {
+                                fork_point_height,
+                                best_block_height,
+                            },
                         },
                         user_data,
                     },
@@ -1777,6 +2069,9 @@ pub enum HeaderVerifyOutcome<TRq, TSrc, TBl> {
     Success {
         /// True if the newly-verified block is considered the new best block.
         is_new_best: bool,
+        /// If the newly-verified block is the new best block but doesn't descend from the
+        /// previous best block, contains the blocks retracted from and enacted in the best chain.
+        reorg: Option<blocks_tree::Reorg>,
         /// State machine yielded back. Use to continue the processing.
         sync: AllSync<TRq, TSrc, TBl>,
     },
@@ -1797,8 +2092,63 @@ pub enum HeaderVerifyOutcome<TRq, TSrc, TBl> {
 pub enum HeaderVerifyError {
     /// Block uses a different consensus than the rest of the chain.
     ConsensusMismatch,
+    /// The block schedules a change in the list of GrandPa authorities while another scheduled
+    /// change is still pending.
+    #[display(
+        fmt = "Block schedules a GrandPa authorities change while the one triggered at \
+               block #{} is still pending.",
+        pending_trigger_block_height
+    )]
+    ConflictingScheduledChange {
+        /// Height of the block where the pending change is triggered.
+        pending_trigger_block_height: u64,
+    },
     /// The block verification has failed. The block is invalid and should be thrown away.
     VerificationFailed(verify::header_only::Error),
+    /// The block belongs to a fork that diverges from the best chain further than
+    /// [`Config::max_fork_depth`]. The block hasn't been verified.
+    ///
+    /// The sources that know this block, as reported by [`AllSync::knows_non_finalized_block`],
+    /// should be considered as misbehaving.
+    #[display(
+        fmt = "Block belongs to a fork diverging at block #{} while the best block is #{}",
+        fork_point_height,
+        best_block_height
+    )]
+    ForkTooDeep {
+        /// Height of the common ancestor of the block and of the current best block.
+        fork_point_height: u64,
+        /// Height of the current best block.
+        best_block_height: u64,
+    },
+}
+
+/// Outcome of [`AllSync::sync_readiness`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncReadiness {
+    /// At least [`Config::min_peers_for_ready`] sources are synced with the local node, and the
+    /// local best block isn't lagging behind.
+    Ready,
+    /// Less than [`Config::min_peers_for_ready`] sources are synced with the local node.
+    NotEnoughPeers {
+        /// Number of sources whose best block is close to the local best block.
+        num_synced_peers: usize,
+    },
+    /// At least as many sources report a best block ahead of the local best block as sources
+    /// that are synced with the local node.
+    BestBlockNotRecent {
+        /// Number of sources whose best block is ahead of the local best block.
+        num_peers_ahead: usize,
+    },
+}
+
+/// Error returned by [`AllSync::import_historical_block`].
+#[derive(Debug, derive_more::Display)]
+pub enum HistoricalBlockError {
+    /// Error while decoding the header.
+    InvalidHeader(header::Error),
+    /// Block doesn't fill any of the gaps.
+    Gap(block_gaps::ImportError),
 }
 
 // TODO: should be used by the optimistic syncing as well
@@ -1830,6 +2180,7 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
                     all_forks::JustificationVerifyOutcome::NewFinalized {
                         finalized_blocks,
                         updates_best_block,
+                        reorg,
                     },
                 ) => (
                     AllSync {
@@ -1847,6 +2198,7 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
                             })
                             .collect(),
                         updates_best_block,
+                        reorg,
                     },
                 ),
                 (sync, all_forks::JustificationVerifyOutcome::Error(error)) => (
@@ -1879,6 +2231,7 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
                             })
                             .collect(),
                         updates_best_block: false,
+                        reorg: None,
                     },
                 ),
                 (inner, optimistic::JustificationVerification::Reset { error, .. }) => (
@@ -1905,6 +2258,9 @@ pub enum JustificationVerifyOutcome<TBl> {
         /// This can happen if the previous best block isn't a descendant of the now finalized
         /// block.
         updates_best_block: bool,
+        /// If the previous best block isn't a descendant of the now finalized block, contains
+        /// the blocks retracted from and enacted in the best chain.
+        reorg: Option<blocks_tree::Reorg>,
     },
     /// Problem while verifying justification.
     Error(blocks_tree::JustificationVerifyError),
@@ -1949,6 +2305,12 @@ impl<TRq, TSrc, TBl> WarpSyncFragmentVerify<TRq, TSrc, TBl> {
 
         (self.inner, error)
     }
+
+    /// Do not actually proceed with the verification. The fragment will be verified again the
+    /// next time [`AllSync::process_one`] is called.
+    pub fn cancel(self) -> AllSync<TRq, TSrc, TBl> {
+        self.inner
+    }
 }
 
 pub struct HeaderBodyVerify<TRq, TSrc, TBl> {
@@ -1977,6 +2339,19 @@ impl<TRq, TSrc, TBl> HeaderBodyVerify<TRq, TSrc, TBl> {
         }
     }
 
+    /// Do not actually proceed with the verification. The block stays in the list of blocks
+    /// waiting to be verified.
+    pub fn cancel(self) -> AllSync<TRq, TSrc, TBl> {
+        match self.inner {
+            HeaderBodyVerifyInner::Optimistic(verify) => AllSync {
+                inner: AllSyncInner::Optimistic {
+                    inner: verify.cancel(),
+                },
+                shared: self.shared,
+            },
+        }
+    }
+
     /// Start the verification process.
     pub fn start(
         self,
@@ -2244,8 +2619,26 @@ struct Shared<TRq> {
     blocks_capacity: usize,
     /// Value passed through [`Config::max_disjoint_headers`].
     max_disjoint_headers: usize,
+    /// Value passed through [`Config::max_fork_depth`].
+    max_fork_depth: Option<u64>,
     /// Value passed through [`Config::max_requests_per_block`].
     max_requests_per_block: NonZeroU32,
+    /// Value passed through [`Config::min_peers_for_ready`].
+    min_peers_for_ready: usize,
+
+    /// Number and hash of the finalized block found in [`Config::chain_information`]. Used to
+    /// determine the gap of blocks skipped by the warp sync.
+    warp_sync_start: (u64, [u8; 32]),
+    /// Ranges of finalized blocks that have been skipped by the warp sync and not downloaded yet.
+    block_gaps: block_gaps::BlockGaps,
+    /// Value passed to the latest call to [`AllSync::set_preferred_fork`]. Passed to the
+    /// all-forks state machine when switching to it.
+    preferred_fork: Option<(u64, [u8; 32])>,
+    /// Value passed to the latest call to [`AllSync::set_catch_up_mode`]. Passed to the
+    /// all-forks state machine when switching to it.
+    catch_up_mode: Option<NonZeroU32>,
+    /// Source of the seeds passed to the state machines that are created later.
+    randomness: rand_chacha::ChaCha20Rng,
 }
 
 impl<TRq> Shared<TRq> {
@@ -2260,15 +2653,32 @@ impl<TRq> Shared<TRq> {
         Option<Vec<u8>>,
         Option<Vec<u8>>,
     ) {
+        {
+            let finalized_block_header = grandpa.chain_information.as_ref().finalized_block_header;
+            if finalized_block_header.number > self.warp_sync_start.0 {
+                self.block_gaps.insert(
+                    self.warp_sync_start,
+                    (
+                        finalized_block_header.number,
+                        *finalized_block_header.parent_hash,
+                    ),
+                );
+            }
+        }
+
         let mut all_forks = all_forks::AllForksSync::new(all_forks::Config {
             chain_information: grandpa.chain_information,
             sources_capacity: self.sources_capacity,
             blocks_capacity: self.blocks_capacity,
             max_disjoint_headers: self.max_disjoint_headers,
+            max_fork_depth: self.max_fork_depth,
             max_requests_per_block: self.max_requests_per_block,
             full: false,
             banned_blocks: iter::empty(), // TODO: not implemented, should be passed by config after the optimistic sync supports banned blocks too
+            randomness_seed: self.randomness.sample(rand::distributions::Standard),
         });
+        all_forks.set_preferred_fork(self.preferred_fork);
+        all_forks.set_catch_up_mode(self.catch_up_mode);
 
         debug_assert!(self
             .sources
@@ -2345,3 +2755,203 @@ fn optimistic_request_convert(
         request_justification: true,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AllSync, Config, ProcessOne, RequestDetail, SyncReadiness};
+    use crate::{
+        chain::chain_information,
+        chain_spec::ChainSpec,
+        network::protocol::{GrandpaWarpSyncResponse, GrandpaWarpSyncResponseFragment},
+    };
+    use core::num::{NonZeroU32, NonZeroU64};
+
+    /// Builds an [`AllSync`] starting at the genesis block of the example chain. Returns it
+    /// alongside with the hash of the genesis block.
+    fn new_sync<TRq, TSrc>(min_peers_for_ready: usize) -> (AllSync<TRq, TSrc, ()>, [u8; 32]) {
+        let spec =
+            ChainSpec::from_json_bytes(&include_bytes!("../chain_spec/example.json")[..]).unwrap();
+        let chain_information = chain_information::ValidChainInformation::try_from(
+            spec.as_chain_information().unwrap(),
+        )
+        .unwrap();
+        let genesis_hash = chain_information.as_ref().finalized_block_header.hash();
+
+        let sync = AllSync::new(Config {
+            chain_information,
+            sources_capacity: 16,
+            blocks_capacity: 16,
+            max_disjoint_headers: 16,
+            max_fork_depth: None,
+            max_requests_per_block: NonZeroU32::new(3).unwrap(),
+            download_ahead_blocks: NonZeroU32::new(16).unwrap(),
+            min_peers_for_ready,
+            full: None,
+            randomness_seed: [0; 32],
+        });
+
+        (sync, genesis_hash)
+    }
+
+    #[test]
+    fn sync_readiness_threshold() {
+        let (mut sync, _) = new_sync::<(), ()>(2);
+        assert_eq!(sync.best_block_number(), 0);
+        assert_eq!(
+            sync.sync_readiness(),
+            SyncReadiness::NotEnoughPeers {
+                num_synced_peers: 0
+            }
+        );
+
+        let source1 = sync.add_source((), 1, [1; 32]);
+        assert_eq!(
+            sync.sync_readiness(),
+            SyncReadiness::NotEnoughPeers {
+                num_synced_peers: 1
+            }
+        );
+
+        let source2 = sync.add_source((), 0, [0; 32]);
+        assert_eq!(sync.sync_readiness(), SyncReadiness::Ready);
+
+        // A source far ahead doesn't count as synced, but doesn't prevent readiness as long as
+        // most sources agree with the local best block.
+        let source3 = sync.add_source((), 1000, [2; 32]);
+        assert_eq!(sync.sync_readiness(), SyncReadiness::Ready);
+
+        // Dropping below the threshold reverts to not ready.
+        let _ = sync.remove_source(source1);
+        assert_eq!(
+            sync.sync_readiness(),
+            SyncReadiness::BestBlockNotRecent { num_peers_ahead: 1 }
+        );
+        let _ = sync.remove_source(source3);
+        assert_eq!(
+            sync.sync_readiness(),
+            SyncReadiness::NotEnoughPeers {
+                num_synced_peers: 1
+            }
+        );
+
+        let _source4 = sync.add_source((), 2, [3; 32]);
+        assert_eq!(sync.sync_readiness(), SyncReadiness::Ready);
+        let _ = sync.remove_source(source2);
+        assert_eq!(
+            sync.sync_readiness(),
+            SyncReadiness::NotEnoughPeers {
+                num_synced_peers: 1
+            }
+        );
+    }
+
+    #[test]
+    fn shutdown_mid_sync() {
+        let (mut sync, genesis_hash) = new_sync::<u32, &'static str>(1);
+
+        let source1 = sync.add_source("foo", 1000, [1; 32]);
+        let source2 = sync.add_source("bar", 1000, [1; 32]);
+        let request1 = sync.add_request(
+            source1,
+            RequestDetail::BlocksRequest {
+                first_block_hash: Some([1; 32]),
+                first_block_height: 1000,
+                ascending: false,
+                num_blocks: NonZeroU64::new(1).unwrap(),
+                request_headers: true,
+                request_bodies: false,
+                request_justification: true,
+            },
+            1,
+        );
+        let request2 = sync.add_request(
+            source2,
+            RequestDetail::GrandpaWarpSync {
+                sync_start_block_hash: genesis_hash,
+            },
+            2,
+        );
+
+        let shutdown = sync.shutdown();
+        assert_eq!(
+            shutdown
+                .chain_information
+                .as_ref()
+                .finalized_block_header
+                .hash(),
+            genesis_hash
+        );
+        assert!(shutdown.finalized_blocks.is_empty());
+        assert_eq!(shutdown.sources, vec![(source1, "foo"), (source2, "bar")]);
+        assert_eq!(
+            shutdown.cancelled_requests,
+            vec![(request1, 1), (request2, 2)]
+        );
+    }
+
+    #[test]
+    fn shutdown_with_pending_verification() {
+        let (mut sync, genesis_hash) = new_sync::<u32, &'static str>(1);
+
+        let source1 = sync.add_source("foo", 1000, [1; 32]);
+        let source2 = sync.add_source("bar", 1000, [1; 32]);
+        let request1 = sync.add_request(
+            source1,
+            RequestDetail::BlocksRequest {
+                first_block_hash: Some([1; 32]),
+                first_block_height: 1000,
+                ascending: false,
+                num_blocks: NonZeroU64::new(1).unwrap(),
+                request_headers: true,
+                request_bodies: false,
+                request_justification: true,
+            },
+            1,
+        );
+        let request2 = sync.add_request(
+            source2,
+            RequestDetail::GrandpaWarpSync {
+                sync_start_block_hash: genesis_hash,
+            },
+            2,
+        );
+
+        // The warp sync response is queued, but the fragment it contains isn't verified yet.
+        let (user_data, _) = sync.grandpa_warp_sync_response(
+            request2,
+            Some(GrandpaWarpSyncResponse {
+                fragments: vec![GrandpaWarpSyncResponseFragment {
+                    scale_encoded_header: vec![0; 8],
+                    scale_encoded_justification: vec![0; 8],
+                }],
+                is_finished: false,
+            }),
+        );
+        assert_eq!(user_data, 2);
+
+        // Cancelling a verification leaves the fragment pending.
+        let sync = match sync.process_one() {
+            ProcessOne::VerifyWarpSyncFragment(verify) => verify.cancel(),
+            _ => panic!(),
+        };
+        let sync = match sync.process_one() {
+            ProcessOne::VerifyWarpSyncFragment(verify) => verify.cancel(),
+            _ => panic!(),
+        };
+
+        // The pending fragment is discarded rather than verified, and the finalized block is
+        // left untouched.
+        let shutdown = sync.shutdown();
+        assert_eq!(
+            shutdown
+                .chain_information
+                .as_ref()
+                .finalized_block_header
+                .hash(),
+            genesis_hash
+        );
+        assert!(shutdown.finalized_blocks.is_empty());
+        assert_eq!(shutdown.sources, vec![(source1, "foo"), (source2, "bar")]);
+        assert_eq!(shutdown.cancelled_requests, vec![(request1, 1)]);
+    }
+}
@@ -135,6 +135,27 @@ pub struct Config<TBannedBlocksIter> {
     // due to the internal processing of the state machine.
     pub max_disjoint_headers: usize,
 
+    /// Maximum number of blocks between the current best block and the point where a fork
+    /// diverges from the best chain. `None` for no limit.
+    ///
+    /// Headers whose parent is on a fork that diverges from the best chain more than this
+    /// number of blocks below the current best block fail to verify with
+    /// [`HeaderVerifyError::ForkTooDeep`]. A fork whose fork point is exactly this number of
+    /// blocks below the best block is still accepted.
+    ///
+    /// Such forks can only become the best chain or be finalized through a very large re-org.
+    /// Setting a limit prevents a malicious source from making the node keep track of an
+    /// arbitrary number of deep forks. The sources that have announced such a block should be
+    /// penalized.
+    ///
+    /// > **Note**: The depth is measured from the best block rather than from the latest
+    /// >           finalized block. Since the fork point of a non-finalized block is always the
+    /// >           latest finalized block or one of its descendants, the distance between the
+    /// >           fork point and the latest finalized block doesn't indicate how unlikely the
+    /// >           fork is to ever be finalized, whereas the distance between the fork point and
+    /// >           the best block is the size of the re-org necessary for this to happen.
+    pub max_fork_depth: Option<u64>,
+
     /// Maximum number of simultaneous pending requests made towards the same block.
     ///
     /// Should be set according to the failure rate of requests. For example if requests have a
@@ -151,6 +172,10 @@ pub struct Config<TBannedBlocksIter> {
     /// If true, the block bodies and storage are also synchronized.
     pub full: bool,
 
+    /// Seed used by the randomness necessary to verify the signatures of justifications. See
+    /// [`crate::finality::signatures::verify_ed25519`].
+    pub randomness_seed: [u8; 32],
+
     /// List of block hashes that are known to be bad and shouldn't be downloaded or verified.
     ///
     /// > **Note**: This list is typically filled with a list of blocks found in the chain
@@ -181,6 +206,15 @@ struct Inner<TRq, TSrc> {
 
     /// Same value as [`Config::banned_blocks`].
     banned_blocks: hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>,
+
+    /// Number and hash of the block set with [`AllForksSync::set_preferred_fork`], if any.
+    preferred_fork: Option<(u64, [u8; 32])>,
+
+    /// Value passed to the latest call to [`AllForksSync::set_catch_up_mode`].
+    catch_up_mode: Option<NonZeroU32>,
+
+    /// Same value as [`Config::max_fork_depth`].
+    max_fork_depth: Option<u64>,
 }
 
 struct PendingBlock {
@@ -207,6 +241,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         let chain = blocks_tree::NonFinalizedTree::new(blocks_tree::Config {
             chain_information: config.chain_information,
             blocks_capacity: config.blocks_capacity,
+            randomness_seed: config.randomness_seed,
         });
 
         Self {
@@ -221,10 +256,19 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                 }),
                 pending_justifications_verify: Vec::new().into_iter(),
                 banned_blocks: config.banned_blocks.collect(),
+                preferred_fork: None,
+                catch_up_mode: None,
+                max_fork_depth: config.max_fork_depth,
             },
         }
     }
 
+    /// Returns the total number of blocks held in memory, both verified and unverified, not
+    /// including the finalized block.
+    pub fn tree_size(&self) -> usize {
+        self.chain.len() + self.inner.blocks.num_unverified_blocks()
+    }
+
     /// Builds a [`chain_information::ChainInformationRef`] struct corresponding to the current
     /// latest finalized block. Can later be used to reconstruct a chain.
     pub fn as_chain_information(&self) -> chain_information::ValidChainInformationRef {
@@ -233,10 +277,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
 
     /// Returns the header of the finalized block.
     pub fn finalized_block_header(&self) -> header::HeaderRef {
-        self.chain
-            .as_chain_information()
-            .as_ref()
-            .finalized_block_header
+        self.chain.finalized_block_header()
     }
 
     /// Returns the header of the best block.
@@ -263,6 +304,19 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.chain.best_block_hash()
     }
 
+    /// Returns the hash and number of the best block.
+    ///
+    /// > **Note**: This value is provided only for informative purposes. Keep in mind that this
+    /// >           best block might be reverted in the future.
+    pub fn best_block(&self) -> ([u8; 32], u64) {
+        self.chain.best_block()
+    }
+
+    /// Returns the hash and number of the finalized block.
+    pub fn finalized_block(&self) -> ([u8; 32], u64) {
+        self.chain.finalized_block()
+    }
+
     /// Returns the header of all known non-finalized blocks in the chain without any specific
     /// order.
     pub fn non_finalized_blocks_unordered(
@@ -281,6 +335,30 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.chain.iter_ancestry_order()
     }
 
+    /// Returns the path going from the block whose hash is `from` to the block whose hash is
+    /// `to`, both of which must be either non-finalized blocks or the latest finalized block.
+    ///
+    /// This is typically used in order to determine which blocks are retracted and enacted
+    /// during a re-org.
+    ///
+    /// See [`blocks_tree::NonFinalizedTree::ancestry_path`].
+    pub fn ancestry_path(
+        &self,
+        from: &[u8; 32],
+        to: &[u8; 32],
+    ) -> Result<blocks_tree::AncestryPath, blocks_tree::AncestryPathError> {
+        self.chain.ancestry_path(from, to)
+    }
+
+    /// Returns the block with the highest number that doesn't descend from a GrandPa
+    /// authorities change that must be finalized first, or the latest finalized block if there
+    /// is no such block.
+    ///
+    /// See [`blocks_tree::NonFinalizedTree::grandpa_best_candidate`].
+    pub fn grandpa_best_candidate(&self) -> (u64, &[u8; 32]) {
+        self.chain.grandpa_best_candidate()
+    }
+
     /// Inform the [`AllForksSync`] of a new potential source of blocks.
     ///
     /// The `user_data` parameter is opaque and decided entirely by the user. It can later be
@@ -424,30 +502,240 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.inner.blocks.source_num_ongoing_requests(source_id)
     }
 
+    /// Sets the block whose descendants should be downloaded in priority, or `None` to remove
+    /// the preference.
+    ///
+    /// When a preferred fork is set, [`AllForksSync::desired_requests`] never returns requests
+    /// towards sources whose best block is known to not descend from the given block, and
+    /// returns requests towards sources whose best block is known to descend from the given
+    /// block before requests towards sources whose ancestry can't be determined yet. See
+    /// [`AllForksSync::source_on_preferred_fork`].
+    ///
+    /// This is typically used during a contentious fork, in order to only follow one side of
+    /// the fork.
+    pub fn set_preferred_fork(&mut self, fork: Option<(u64, [u8; 32])>) {
+        self.inner.preferred_fork = fork;
+    }
+
+    /// Returns the value passed to the latest call to [`AllForksSync::set_preferred_fork`], or
+    /// `None` if it has never been called.
+    pub fn preferred_fork(&self) -> Option<(u64, &[u8; 32])> {
+        self.inner
+            .preferred_fork
+            .as_ref()
+            .map(|(number, hash)| (*number, hash))
+    }
+
+    /// Enables or disables the "catch up" mode.
+    ///
+    /// In catch up mode, the syncing focuses on advancing the finalized block as quickly as
+    /// possible rather than on exploring all the non-finalized forks. Only the blocks that are
+    /// the best block of at least `min_sources` sources, or an ancestor of such a best block,
+    /// are requested and verified, as these are the blocks whose justifications make the
+    /// finalized block progress. The requests concerning other forks are deferred until the
+    /// catch up mode is disabled.
+    ///
+    /// Requiring several sources to agree prevents a single source from making the node follow
+    /// a chain of its choice by announcing a fake high best block. If no block is known to be
+    /// supported by at least `min_sources` sources, for example because the sources are on
+    /// different forks or because the ancestry of their best blocks hasn't been downloaded yet,
+    /// the state machine behaves as if the catch up mode was disabled. The API user is also
+    /// encouraged to disable the catch up mode after a timeout if the finalized block doesn't
+    /// progress.
+    ///
+    /// Within the blocks being caught up, the requests closest to the finalized block are
+    /// returned first by [`AllForksSync::desired_requests`], and the blocks that come with a
+    /// justification are verified first by [`AllForksSync::process_one`], as they are the ones
+    /// that let the justifications be verified the soonest.
+    ///
+    /// Justifications are verified exactly as they are outside of the catch up mode. Invalid
+    /// justifications are still rejected.
+    ///
+    /// This is typically enabled after the node has been offline for a long time, and disabled
+    /// once the finalized block is close to the head of the chain.
+    ///
+    /// Pass `None` to disable the catch up mode, which is the default.
+    pub fn set_catch_up_mode(&mut self, min_sources: Option<NonZeroU32>) {
+        self.inner.catch_up_mode = min_sources;
+    }
+
+    /// Returns the value passed to the latest call to [`AllForksSync::set_catch_up_mode`], or
+    /// `None` if it has never been called.
+    pub fn catch_up_mode(&self) -> Option<NonZeroU32> {
+        self.inner.catch_up_mode
+    }
+
+    /// Returns the hashes of the unverified blocks that are the best block of, or an ancestor of
+    /// the best block of, at least `min_sources` sources.
+    ///
+    /// These are the blocks that are downloaded and verified in priority when in catch up mode.
+    /// Returns `None` if there isn't any such block, in which case the catch up mode has no
+    /// effect.
+    fn catch_up_chain(
+        &self,
+        min_sources: NonZeroU32,
+    ) -> Option<hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>> {
+        // Number of sources whose best block is equal to or descends from each block.
+        let mut num_sources = hashbrown::HashMap::<[u8; 32], u32, fnv::FnvBuildHasher>::with_hasher(
+            Default::default(),
+        );
+
+        for source_id in self.inner.blocks.sources() {
+            let (mut number, hash) = self.inner.blocks.source_best_block(source_id);
+            let mut hash = *hash;
+            loop {
+                *num_sources.entry(hash).or_insert(0) += 1;
+                match self
+                    .inner
+                    .blocks
+                    .unverified_block_parent_hash(number, &hash)
+                {
+                    Some(parent_hash) => {
+                        number -= 1;
+                        hash = *parent_hash;
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        let out = num_sources
+            .into_iter()
+            .filter(|(_, n)| *n >= min_sources.get())
+            .map(|(hash, _)| hash)
+            .collect::<hashbrown::HashSet<_, _>>();
+
+        if out.is_empty() {
+            None
+        } else {
+            Some(out)
+        }
+    }
+
+    /// Returns `Some(true)` if the best block of the given source is known to be equal to or to
+    /// descend from the block passed to [`AllForksSync::set_preferred_fork`], `Some(false)` if it
+    /// is known to not descend from it, and `None` if this can't be determined yet, for example
+    /// because the ancestry of the best block of the source hasn't been downloaded yet.
+    ///
+    /// Always returns `Some(true)` if no preferred fork is set.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is invalid.
+    ///
+    pub fn source_on_preferred_fork(&self, source_id: SourceId) -> Option<bool> {
+        let (fork_number, fork_hash) = match &self.inner.preferred_fork {
+            Some(fork) => fork,
+            None => return Some(true),
+        };
+
+        let finalized_block_number = self.chain.finalized_block_header().number;
+        let finalized_block_hash = self.chain.finalized_block_hash();
+
+        let (mut number, hash) = self.inner.blocks.source_best_block(source_id);
+        let mut hash = *hash;
+
+        // Walk up the ancestry of the best block of the source, first through the unverified
+        // blocks, then through the tree of verified blocks.
+        loop {
+            if number <= *fork_number {
+                return Some(number == *fork_number && hash == *fork_hash);
+            }
+
+            if hash == finalized_block_hash || self.chain.contains_non_finalized_block(&hash) {
+                return if *fork_hash == finalized_block_hash
+                    || self.chain.contains_non_finalized_block(fork_hash)
+                {
+                    let path = self.chain.ancestry_path(fork_hash, &hash).unwrap();
+                    Some(path.retracted.is_empty())
+                } else if *fork_number > finalized_block_number {
+                    // Verified blocks can't descend from a block that isn't verified.
+                    Some(false)
+                } else {
+                    // The fork block is an ancestor of the finalized block, or on a fork that
+                    // has been abandoned. The finalized blocks aren't tracked.
+                    None
+                };
+            }
+
+            match self
+                .inner
+                .blocks
+                .unverified_block_parent_hash(number, &hash)
+            {
+                Some(parent_hash) => {
+                    number -= 1;
+                    hash = *parent_hash;
+                }
+                None => return None,
+            }
+        }
+    }
+
     /// Returns the details of a request to start towards a source.
     ///
     /// This method doesn't modify the state machine in any way. [`AllForksSync::add_request`]
     /// must be called in order for the request to actually be marked as started.
+    ///
+    /// If a preferred fork has been set with [`AllForksSync::set_preferred_fork`], the requests
+    /// are ordered according to [`AllForksSync::source_on_preferred_fork`].
+    ///
+    /// If the catch up mode is enabled with [`AllForksSync::set_catch_up_mode`], only the
+    /// requests concerning the blocks supported by enough sources are returned, starting with
+    /// the requests closest to the finalized block.
     pub fn desired_requests(
         &'_ self,
     ) -> impl Iterator<Item = (SourceId, &'_ TSrc, RequestParams)> + '_ {
         // TODO: need to periodically query for justifications of non-finalized blocks that change GrandPa authorities
 
-        self.inner
-            .blocks
-            .desired_requests()
-            .filter(move |rq| {
-                !self
-                    .chain
-                    .contains_non_finalized_block(&rq.request_params.first_block_hash)
-            })
-            .map(move |rq| {
-                (
-                    rq.source_id,
-                    &self.inner.blocks[rq.source_id],
-                    rq.request_params,
-                )
-            })
+        let requests = self.inner.blocks.desired_requests().filter(move |rq| {
+            !self
+                .chain
+                .contains_non_finalized_block(&rq.request_params.first_block_hash)
+        });
+
+        let requests = match self
+            .inner
+            .catch_up_mode
+            .and_then(|min_sources| self.catch_up_chain(min_sources))
+        {
+            Some(catch_up_chain) => {
+                // The responses to the requests closest to the finalized block are the first
+                // that can be verified, alongside with the justifications they contain. Since
+                // the sorting is stable, the order of the requests is otherwise preserved.
+                let mut requests = requests
+                    .filter(|rq| catch_up_chain.contains(&rq.request_params.first_block_hash))
+                    .collect::<Vec<_>>();
+                requests.sort_by_key(|rq| rq.request_params.first_block_height);
+                either::Left(requests.into_iter())
+            }
+            None => either::Right(requests),
+        };
+
+        let requests = if self.inner.preferred_fork.is_some() {
+            // Requests towards sources on the preferred fork are put first, followed with the
+            // requests towards sources whose ancestry is unknown. Since the sorting is stable,
+            // the order of the requests is otherwise preserved.
+            let mut requests = requests
+                .filter_map(|rq| match self.source_on_preferred_fork(rq.source_id) {
+                    Some(true) => Some((false, rq)),
+                    None => Some((true, rq)),
+                    Some(false) => None,
+                })
+                .collect::<Vec<_>>();
+            requests.sort_by_key(|(is_unknown, _)| *is_unknown);
+            either::Left(requests.into_iter().map(|(_, rq)| rq))
+        } else {
+            either::Right(requests)
+        };
+
+        requests.map(move |rq| {
+            (
+                rq.source_id,
+                &self.inner.blocks[rq.source_id],
+                rq.request_params,
+            )
+        })
     }
 
     /// Inserts a new request in the data structure.
@@ -741,6 +1029,9 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
     ///
     /// This method takes ownership of the [`AllForksSync`] and starts a verification
     /// process. The [`AllForksSync`] is yielded back at the end of this process.
+    ///
+    /// In catch up mode, only the blocks supported by enough sources are verified, starting with
+    /// the ones that come with a justification. See [`AllForksSync::set_catch_up_mode`].
     pub fn process_one(mut self) -> ProcessOne<TBl, TRq, TSrc> {
         if let Some(justification_to_verify) = self.inner.pending_justifications_verify.next() {
             return ProcessOne::JustificationVerify(JustificationVerify {
@@ -749,17 +1040,49 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
             });
         }
 
-        let block = self.inner.blocks.unverified_leaves().find(|block| {
-            block.parent_block_hash == self.chain.finalized_block_hash()
-                || self
+        let catch_up_chain = self
+            .inner
+            .catch_up_mode
+            .and_then(|min_sources| self.catch_up_chain(min_sources));
+
+        // Blocks whose parent is in the chain can be verified. The common ancestor of their
+        // parent and of the best block is the fork point, which is kept for the fork depth check.
+        let best_block_hash = self.chain.best_block_hash();
+        let block = {
+            let mut verifiable_blocks = self.inner.blocks.unverified_leaves().filter_map(|block| {
+                let (fork_point_height, _) = self
                     .chain
-                    .contains_non_finalized_block(&block.parent_block_hash)
-        });
+                    .common_ancestor(&best_block_hash, &block.parent_block_hash)
+                    .ok()?;
+                Some((block, fork_point_height))
+            });
 
-        if let Some(block) = block {
+            match &catch_up_chain {
+                Some(chain) => {
+                    let verifiable_blocks = verifiable_blocks
+                        .filter(|(block, _)| chain.contains(&block.block_hash))
+                        .collect::<Vec<_>>();
+                    let with_justifications = verifiable_blocks.iter().position(|(block, _)| {
+                        !self
+                            .inner
+                            .blocks
+                            .unverified_block_user_data(block.block_number, &block.block_hash)
+                            .justifications
+                            .is_empty()
+                    });
+                    verifiable_blocks
+                        .into_iter()
+                        .nth(with_justifications.unwrap_or(0))
+                }
+                None => verifiable_blocks.next(),
+            }
+        };
+
+        if let Some((block, fork_point_height)) = block {
             ProcessOne::HeaderVerify(HeaderVerify {
                 parent: self,
                 block_to_verify: block,
+                fork_point_height,
             })
         } else {
             ProcessOne::AllSync { sync: self }
@@ -818,6 +1141,17 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
             }
         }*/
     }*/
+
+    /// Removes the unverified blocks that don't descend from the finalized block.
+    ///
+    /// Blocks that are concerned by an ongoing request are kept until the request is over.
+    fn remove_abandoned_unverified_blocks(&mut self) {
+        let finalized_block_hash = self.chain.finalized_block_hash();
+        let _ = self
+            .inner
+            .blocks
+            .remove_abandoned_unverified_blocks(&finalized_block_hash);
+    }
 }
 
 impl<TBl, TRq, TSrc> ops::Index<SourceId> for AllForksSync<TBl, TRq, TSrc> {
@@ -991,6 +1325,10 @@ impl<TBl, TRq, TSrc> FinishAncestrySearch<TBl, TRq, TSrc> {
             );
         }
 
+        // Blocks targeted by the request might have been kept alive by it despite not
+        // descending from the finalized block.
+        self.inner.remove_abandoned_unverified_blocks();
+
         self.inner
     }
 }
@@ -1261,6 +1599,8 @@ pub struct HeaderVerify<TBl, TRq, TSrc> {
     parent: AllForksSync<TBl, TRq, TSrc>,
     /// Block that can be verified.
     block_to_verify: pending_blocks::TreeRoot,
+    /// Height of the common ancestor of the parent of the block and of the best block.
+    fork_point_height: u64,
 }
 
 impl<TBl, TRq, TSrc> HeaderVerify<TBl, TRq, TSrc> {
@@ -1293,35 +1633,62 @@ impl<TBl, TRq, TSrc> HeaderVerify<TBl, TRq, TSrc> {
             .unwrap()
             .scale_encoding_vec();
 
-        let result = match self
-            .parent
-            .chain
-            .verify_header(to_verify_scale_encoded_header, now_from_unix_epoch)
-        {
-            Ok(blocks_tree::HeaderVerifySuccess::Insert {
-                insert,
-                is_new_best,
-                ..
-            }) => {
-                // TODO: cloning the header :-/
-                let block = Block {
-                    header: insert.header().into(),
-                    user_data,
-                };
-                insert.insert(block);
-                Ok(is_new_best)
-            }
-            Err(blocks_tree::HeaderVerifyError::VerificationFailed(error)) => {
-                Err((HeaderVerifyError::VerificationFailed(error), user_data))
-            }
-            Err(blocks_tree::HeaderVerifyError::ConsensusMismatch) => {
-                Err((HeaderVerifyError::ConsensusMismatch, user_data))
+        let previous_best_hash = self.parent.chain.best_block_hash();
+
+        // Check the depth of the fork the block belongs to before verifying it.
+        let fork_point_height = self.fork_point_height;
+        let fork_too_deep = self.parent.inner.max_fork_depth.and_then(|max_fork_depth| {
+            let best_block_height = self.parent.chain.best_block_header().number;
+            if best_block_height - fork_point_height > max_fork_depth {
+                Some(HeaderVerifyError::ForkTooDeep {
+                    fork_point_height,
+                    best_block_height,
+                })
+            } else {
+                None
             }
-            Ok(blocks_tree::HeaderVerifySuccess::Duplicate)
-            | Err(
-                blocks_tree::HeaderVerifyError::BadParent { .. }
-                | blocks_tree::HeaderVerifyError::InvalidHeader(_),
-            ) => unreachable!(),
+        });
+
+        let result = match fork_too_deep {
+            Some(error) => Err((error, user_data)),
+            None => match self
+                .parent
+                .chain
+                .verify_header(to_verify_scale_encoded_header, now_from_unix_epoch)
+            {
+                Ok(blocks_tree::HeaderVerifySuccess::Insert {
+                    insert,
+                    is_new_best,
+                    ..
+                }) => {
+                    // TODO: cloning the header :-/
+                    let block = Block {
+                        header: insert.header().into(),
+                        user_data,
+                    };
+                    insert.insert(block);
+                    Ok(is_new_best)
+                }
+                Err(blocks_tree::HeaderVerifyError::VerificationFailed(error)) => {
+                    Err((HeaderVerifyError::VerificationFailed(error), user_data))
+                }
+                Err(blocks_tree::HeaderVerifyError::ConsensusMismatch) => {
+                    Err((HeaderVerifyError::ConsensusMismatch, user_data))
+                }
+                Err(blocks_tree::HeaderVerifyError::ConflictingScheduledChange {
+                    pending_trigger_block_height,
+                }) => Err((
+                    HeaderVerifyError::ConflictingScheduledChange {
+                        pending_trigger_block_height,
+                    },
+                    user_data,
+                )),
+                Ok(blocks_tree::HeaderVerifySuccess::Duplicate)
+                | Err(
+                    blocks_tree::HeaderVerifyError::BadParent { .. }
+                    | blocks_tree::HeaderVerifyError::InvalidHeader(_),
+                ) => unreachable!(),
+            },
         };
 
         // Remove the verified block from `pending_blocks`.
@@ -1355,10 +1722,23 @@ impl<TBl, TRq, TSrc> HeaderVerify<TBl, TRq, TSrc> {
         self.parent.inner.pending_justifications_verify = justifications.into_iter();
 
         match result {
-            Ok(is_new_best) => HeaderVerifyOutcome::Success {
-                is_new_best,
-                sync: self.parent,
-            },
+            Ok(is_new_best) => {
+                let reorg = if is_new_best {
+                    self.parent
+                        .chain
+                        .ancestry_path(&previous_best_hash, &self.block_to_verify.block_hash)
+                        .ok()
+                        .and_then(|path| path.into_reorg())
+                } else {
+                    None
+                };
+
+                HeaderVerifyOutcome::Success {
+                    is_new_best,
+                    reorg,
+                    sync: self.parent,
+                }
+            }
             Err((error, user_data)) => HeaderVerifyOutcome::Error {
                 sync: self.parent,
                 error,
@@ -1397,6 +1777,7 @@ impl<TBl, TRq, TSrc> JustificationVerify<TBl, TRq, TSrc> {
             Ok(success) => {
                 let finalized_blocks_iter = success.apply();
                 let updates_best_block = finalized_blocks_iter.updates_best_block();
+                let reorg = finalized_blocks_iter.reorg().cloned();
                 let finalized_blocks = finalized_blocks_iter
                     .map(|b| (b.header, b.user_data))
                     .collect::<Vec<_>>();
@@ -1404,9 +1785,11 @@ impl<TBl, TRq, TSrc> JustificationVerify<TBl, TRq, TSrc> {
                     .inner
                     .blocks
                     .set_finalized_block_height(finalized_blocks.last().unwrap().0.number);
+                self.parent.remove_abandoned_unverified_blocks();
                 JustificationVerifyOutcome::NewFinalized {
                     finalized_blocks,
                     updates_best_block,
+                    reorg,
                 }
             }
             Err(err) => JustificationVerifyOutcome::Error(err),
@@ -1446,6 +1829,9 @@ pub enum HeaderVerifyOutcome<TBl, TRq, TSrc> {
     Success {
         /// True if the newly-verified block is considered the new best block.
         is_new_best: bool,
+        /// If the newly-verified block is the new best block but doesn't descend from the
+        /// previous best block, contains the blocks retracted from and enacted in the best chain.
+        reorg: Option<blocks_tree::Reorg>,
         /// State machine yielded back. Use to continue the processing.
         sync: AllForksSync<TBl, TRq, TSrc>,
     },
@@ -1466,8 +1852,35 @@ pub enum HeaderVerifyOutcome<TBl, TRq, TSrc> {
 pub enum HeaderVerifyError {
     /// Block uses a different consensus than the rest of the chain.
     ConsensusMismatch,
+    /// The block schedules a change in the list of GrandPa authorities while another scheduled
+    /// change is still pending.
+    #[display(
+        fmt = "Block schedules a GrandPa authorities change while the one triggered at \
+               block #{} is still pending.",
+        pending_trigger_block_height
+    )]
+    ConflictingScheduledChange {
+        /// Height of the block where the pending change is triggered.
+        pending_trigger_block_height: u64,
+    },
     /// The block verification has failed. The block is invalid and should be thrown away.
     VerificationFailed(verify::header_only::Error),
+    /// The block belongs to a fork that diverges from the best chain further than
+    /// [`Config::max_fork_depth`]. The block hasn't been verified.
+    ///
+    /// The sources that know this block, as reported by
+    /// [`AllForksSync::knows_non_finalized_block`], should be considered as misbehaving.
+    #[display(
+        fmt = "Block belongs to a fork diverging at block #{} while the best block is #{}",
+        fork_point_height,
+        best_block_height
+    )]
+    ForkTooDeep {
+        /// Height of the common ancestor of the block and of the current best block.
+        fork_point_height: u64,
+        /// Height of the current best block.
+        best_block_height: u64,
+    },
 }
 
 /// Information about the outcome of verifying a justification.
@@ -1483,6 +1896,11 @@ pub enum JustificationVerifyOutcome<TBl> {
         /// This can happen if the previous best block isn't a descendant of the now finalized
         /// block.
         updates_best_block: bool,
+        /// If the previous best block isn't a descendant of the now finalized block, contains
+        /// the blocks retracted from and enacted in the best chain. The retracted blocks have
+        /// been pruned, while the common ancestor is one of the blocks in `finalized_blocks`
+        /// or the previously-finalized block.
+        reorg: Option<blocks_tree::Reorg>,
     },
     /// Problem while verifying justification.
     Error(blocks_tree::JustificationVerifyError),
@@ -1530,3 +1948,361 @@ pub enum BlockBodyVerify<TBl, TRq, TSrc> {
     /// order to continue.
     FinalizedStorageNextKey(StorageNextKey<TBl, TRq, TSrc>),*/
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AllForksSync, Config};
+    use crate::{chain::chain_information, header};
+    use core::{
+        iter,
+        num::{NonZeroU32, NonZeroU64},
+        time::Duration,
+    };
+
+    /// Builds an [`AllForksSync`] whose finalized block is an empty genesis block, and returns
+    /// it alongside with the header of this genesis block.
+    fn new_sync(
+        finality: chain_information::ChainInformationFinality,
+        max_fork_depth: Option<u64>,
+    ) -> (AllForksSync<(), (), ()>, header::Header) {
+        let genesis = header::Header {
+            parent_hash: [0; 32],
+            number: 0,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+
+        let sync = AllForksSync::new(Config {
+            chain_information: chain_information::ValidChainInformation::try_from(
+                chain_information::ChainInformation {
+                    finalized_block_header: genesis.clone(),
+                    consensus: chain_information::ChainInformationConsensus::AllAuthorized,
+                    finality,
+                },
+            )
+            .unwrap(),
+            sources_capacity: 16,
+            blocks_capacity: 16,
+            max_disjoint_headers: 16,
+            max_fork_depth,
+            max_requests_per_block: NonZeroU32::new(3).unwrap(),
+            full: false,
+            banned_blocks: iter::empty(),
+            randomness_seed: [0; 32],
+        });
+
+        (sync, genesis)
+    }
+
+    #[test]
+    fn preferred_fork_sources() {
+        let (mut sync, genesis) = new_sync(
+            chain_information::ChainInformationFinality::Outsourced,
+            None,
+        );
+        let genesis_hash = genesis.hash();
+
+        let child = |parent_hash, state_root| header::Header {
+            parent_hash,
+            number: 0,
+            state_root,
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+        let block_1a = header::Header {
+            number: 1,
+            ..child(genesis_hash, [1; 32])
+        };
+        let block_1b = header::Header {
+            number: 1,
+            ..child(genesis_hash, [2; 32])
+        };
+        let block_2a = header::Header {
+            number: 2,
+            ..child(block_1a.hash(), [3; 32])
+        };
+        let block_2b = header::Header {
+            number: 2,
+            ..child(block_1b.hash(), [4; 32])
+        };
+        let block_3a = header::Header {
+            number: 3,
+            ..child(block_2a.hash(), [5; 32])
+        };
+
+        // Source on the preferred fork. The header of block 1a is unknown, but its hash is.
+        let source_a = sync.add_source((), 0, genesis_hash);
+        let _ = sync.block_announce(source_a, block_2a.scale_encoding_vec(), true);
+        // Source on a competing fork.
+        let source_b = sync.add_source((), 0, genesis_hash);
+        let _ = sync.block_announce(source_b, block_2b.scale_encoding_vec(), true);
+        // Source on a descendant of the preferred fork.
+        let source_c = sync.add_source((), 0, genesis_hash);
+        let _ = sync.block_announce(source_c, block_3a.scale_encoding_vec(), true);
+        // Source whose best block has an unknown ancestry.
+        let source_d = sync.add_source((), 5, [0xff; 32]);
+
+        // Without any preferred fork, all sources are considered as on the preferred fork.
+        for source in [source_a, source_b, source_c, source_d] {
+            assert_eq!(sync.source_on_preferred_fork(source), Some(true));
+        }
+        assert!(sync
+            .desired_requests()
+            .any(|(source, _, _)| source == source_b));
+
+        sync.set_preferred_fork(Some((1, block_1a.hash())));
+        assert_eq!(sync.source_on_preferred_fork(source_a), Some(true));
+        assert_eq!(sync.source_on_preferred_fork(source_b), Some(false));
+        assert_eq!(sync.source_on_preferred_fork(source_c), Some(true));
+        assert_eq!(sync.source_on_preferred_fork(source_d), None);
+
+        // Sources on the preferred fork come first, followed with the sources of unknown
+        // ancestry, while the sources on the competing fork are excluded.
+        let requests_sources = sync
+            .desired_requests()
+            .map(|(source, _, _)| source)
+            .collect::<Vec<_>>();
+        assert!(!requests_sources.is_empty());
+        assert!(!requests_sources.contains(&source_b));
+        let first_unknown = requests_sources
+            .iter()
+            .position(|s| *s == source_d)
+            .unwrap();
+        assert!(requests_sources[..first_unknown]
+            .iter()
+            .all(|s| *s == source_a || *s == source_c));
+        assert!(requests_sources[first_unknown..]
+            .iter()
+            .all(|s| *s == source_d));
+        assert!(requests_sources.contains(&source_a));
+
+        // Removing the preference restores the initial behavior.
+        sync.set_preferred_fork(None);
+        assert_eq!(sync.source_on_preferred_fork(source_b), Some(true));
+    }
+
+    #[test]
+    fn catch_up_mode() {
+        let authority_key = ed25519_zebra::SigningKey::from([1; 32]);
+        let (mut sync, genesis) = new_sync(
+            chain_information::ChainInformationFinality::Grandpa {
+                after_finalized_block_authorities_set_id: 0,
+                finalized_triggered_authorities: vec![header::GrandpaAuthority {
+                    public_key: <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(
+                        &authority_key,
+                    )),
+                    weight: NonZeroU64::new(1).unwrap(),
+                }],
+                finalized_scheduled_change: None,
+            },
+            None,
+        );
+        let genesis_hash = genesis.hash();
+        assert_eq!(sync.catch_up_mode(), None);
+
+        let child = |parent_hash, number, state_root| header::Header {
+            parent_hash,
+            number,
+            state_root,
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+        let block_1a = child(genesis_hash, 1, [1; 32]);
+        let block_2a = child(block_1a.hash(), 2, [2; 32]);
+        let block_3a = child(block_2a.hash(), 3, [3; 32]);
+        let block_4a = child(block_3a.hash(), 4, [4; 32]);
+        // Block announced by a single source, much higher than the others, and whose ancestry
+        // is unknown.
+        let block_9c = child([0xcc; 32], 9, [5; 32]);
+        let justification = |header: &header::Header, key: &ed25519_zebra::SigningKey| {
+            crate::finality::grandpa::build_justification(
+                &[*key],
+                0,
+                (u32::try_from(header.number).unwrap(), header.hash()),
+            )
+        };
+
+        let source_a = sync.add_source((), 0, genesis_hash);
+        let _ = sync.block_announce(source_a, block_4a.scale_encoding_vec(), true);
+        let source_b = sync.add_source((), 0, genesis_hash);
+        let _ = sync.block_announce(source_b, block_4a.scale_encoding_vec(), true);
+        let source_c = sync.add_source((), 0, genesis_hash);
+        let _ = sync.block_announce(source_c, block_9c.scale_encoding_vec(), true);
+
+        // Outside of the catch up mode, the claim of the single source is explored as well.
+        assert!(sync
+            .desired_requests()
+            .any(|(source, _, rq)| source == source_c && rq.first_block_hash == [0xcc; 32]));
+
+        // If no block is supported by enough sources, the catch up mode has no effect.
+        sync.set_catch_up_mode(Some(NonZeroU32::new(3).unwrap()));
+        assert!(sync
+            .desired_requests()
+            .any(|(_, _, rq)| rq.first_block_hash == [0xcc; 32]));
+
+        // In catch up mode, only the chain announced by enough sources is downloaded, and the
+        // single source isn't followed.
+        sync.set_catch_up_mode(Some(NonZeroU32::new(2).unwrap()));
+        assert_eq!(sync.catch_up_mode(), NonZeroU32::new(2));
+        let (source, _, request) = sync.desired_requests().next().unwrap();
+        assert!(sync
+            .desired_requests()
+            .all(|(_, _, rq)| rq.first_block_hash == block_3a.hash()));
+
+        // The response contains a valid justification of block 2a and a justification of
+        // block 3a signed by an authority that isn't part of the authorities set.
+        let request_id = sync.add_request(source, request, ());
+        let (_, mut ancestry_search) = sync.finish_ancestry_search(request_id);
+        for (header, justification) in [
+            (
+                &block_3a,
+                Some(justification(
+                    &block_3a,
+                    &ed25519_zebra::SigningKey::from([2; 32]),
+                )),
+            ),
+            (&block_2a, Some(justification(&block_2a, &authority_key))),
+            (&block_1a, None),
+        ] {
+            ancestry_search = match ancestry_search.add_block(
+                &header.scale_encoding_vec(),
+                justification.map(|j| (*b"FRNK", j)).into_iter(),
+            ) {
+                Ok(super::AddBlock::UnknownBlock(vacant)) => vacant.insert(()),
+                _ => panic!(),
+            };
+        }
+        let mut sync = ancestry_search.finish();
+
+        // Verifying the blocks finalizes block 2a, even though the best block claimed by the
+        // single source hasn't been downloaded.
+        let mut verified_blocks = Vec::new();
+        let mut justifications_outcomes = Vec::new();
+        let mut sync = loop {
+            sync = match sync.process_one() {
+                super::ProcessOne::AllSync { sync } => break sync,
+                super::ProcessOne::HeaderVerify(verify) => {
+                    verified_blocks.push(*verify.hash());
+                    match verify.perform(Duration::new(0, 0), ()) {
+                        super::HeaderVerifyOutcome::Success { sync, .. } => sync,
+                        super::HeaderVerifyOutcome::Error { .. } => panic!(),
+                    }
+                }
+                super::ProcessOne::JustificationVerify(verify) => {
+                    let (sync, outcome) = verify.perform();
+                    justifications_outcomes.push(outcome);
+                    sync
+                }
+            };
+        };
+        assert_eq!(
+            verified_blocks,
+            vec![
+                block_1a.hash(),
+                block_2a.hash(),
+                block_3a.hash(),
+                block_4a.hash()
+            ]
+        );
+        assert!(matches!(
+            &justifications_outcomes[0],
+            super::JustificationVerifyOutcome::NewFinalized { finalized_blocks, .. }
+                if finalized_blocks[0].0.hash() == block_2a.hash()
+        ));
+
+        // The invalid justification is rejected and the finalized block doesn't move further.
+        assert!(matches!(
+            &justifications_outcomes[1],
+            super::JustificationVerifyOutcome::Error(_)
+        ));
+        assert_eq!(justifications_outcomes.len(), 2);
+        assert_eq!(sync.finalized_block_header().hash(), block_2a.hash());
+        assert_eq!(sync.best_block_hash(), block_4a.hash());
+
+        // Disabling the catch up mode resumes the exploration of the claim of the single source.
+        sync.set_catch_up_mode(None);
+        assert!(sync
+            .desired_requests()
+            .any(|(source, _, rq)| source == source_c && rq.first_block_hash == [0xcc; 32]));
+    }
+
+    #[test]
+    fn max_fork_depth() {
+        let (mut sync, genesis) = new_sync(
+            chain_information::ChainInformationFinality::Outsourced,
+            Some(2),
+        );
+        let genesis_hash = genesis.hash();
+
+        let child = |parent: &header::Header, state_root| header::Header {
+            parent_hash: parent.hash(),
+            number: parent.number + 1,
+            state_root,
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+        let block_1a = child(&genesis, [1; 32]);
+        let block_2a = child(&block_1a, [2; 32]);
+        let block_3a = child(&block_2a, [3; 32]);
+        // Diverges from the best chain two blocks below the best block.
+        let block_2b = child(&block_1a, [4; 32]);
+        // Diverges from the best chain three blocks below the best block.
+        let block_1c = child(&genesis, [5; 32]);
+
+        let source = sync.add_source((), 0, genesis_hash);
+
+        // Announces `header` and verifies it, returning the outcome of the verification.
+        fn announce_and_verify(
+            mut sync: AllForksSync<(), (), ()>,
+            source: super::SourceId,
+            header: &header::Header,
+        ) -> (
+            AllForksSync<(), (), ()>,
+            Result<(), super::HeaderVerifyError>,
+        ) {
+            assert!(matches!(
+                sync.block_announce(source, header.scale_encoding_vec(), false),
+                super::BlockAnnounceOutcome::HeaderVerify
+            ));
+            let verify = match sync.process_one() {
+                super::ProcessOne::HeaderVerify(verify) => verify,
+                _ => panic!(),
+            };
+            assert_eq!(*verify.hash(), header.hash());
+            match verify.perform(Duration::new(0, 0), ()) {
+                super::HeaderVerifyOutcome::Success { sync, .. } => (sync, Ok(())),
+                super::HeaderVerifyOutcome::Error { sync, error, .. } => (sync, Err(error)),
+            }
+        }
+
+        let (sync, result) = announce_and_verify(sync, source, &block_1a);
+        assert!(result.is_ok());
+        let (sync, result) = announce_and_verify(sync, source, &block_2a);
+        assert!(result.is_ok());
+        let (sync, result) = announce_and_verify(sync, source, &block_3a);
+        assert!(result.is_ok());
+        assert_eq!(sync.best_block_hash(), block_3a.hash());
+
+        // A fork whose fork point is exactly at the limit is accepted.
+        let (sync, result) = announce_and_verify(sync, source, &block_2b);
+        assert!(result.is_ok());
+
+        // A fork whose fork point is below the limit is rejected, and the source that has
+        // announced it can be found.
+        let (sync, result) = announce_and_verify(sync, source, &block_1c);
+        assert!(matches!(
+            result,
+            Err(super::HeaderVerifyError::ForkTooDeep {
+                fork_point_height: 0,
+                best_block_height: 3,
+            })
+        ));
+        assert_eq!(
+            sync.knows_non_finalized_block(1, &block_1c.hash())
+                .collect::<Vec<_>>(),
+            vec![source]
+        );
+        assert_eq!(sync.best_block_hash(), block_3a.hash());
+    }
+}
@@ -471,6 +471,65 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
             .map(|(_, _, bl)| bl.user_data)
     }
 
+    /// Removes from the collection all the unverified blocks that are known to not descend from
+    /// the finalized block, and returns their user data.
+    ///
+    /// `finalized_block_hash` must be the hash of the block whose height has last been passed to
+    /// [`PendingBlocks::set_finalized_block_height`]. A block is known to not descend from the
+    /// finalized block if its ancestry, as known by this collection, reaches a block whose parent
+    /// has the same height as the finalized block but a different hash.
+    ///
+    /// Blocks that are targeted by an ongoing request are never removed. Call this method again
+    /// after the request has been finished with [`PendingBlocks::finish_request`] in order to
+    /// remove them.
+    ///
+    /// > **Note**: Blocks whose ancestry is unknown are kept, as they might descend from the
+    /// >           finalized block.
+    pub fn remove_abandoned_unverified_blocks(
+        &mut self,
+        finalized_block_hash: &[u8; 32],
+    ) -> Vec<TBl> {
+        let finalized_block_height = self.sources.finalized_block_height();
+
+        // Blocks are iterated in increasing height, meaning that the parent of a block, if it is
+        // in the collection, is always visited before the block itself.
+        let mut abandoned = BTreeSet::new();
+        for (height, hash, _) in self.blocks.iter() {
+            let is_abandoned = match self.blocks.parent_hash(height, hash) {
+                Some(parent_hash) if height - 1 == finalized_block_height => {
+                    parent_hash != finalized_block_hash
+                }
+                Some(parent_hash) => abandoned.contains(&(height - 1, *parent_hash)),
+                None => false,
+            };
+
+            if is_abandoned {
+                abandoned.insert((height, *hash));
+            }
+        }
+
+        let to_remove = abandoned
+            .into_iter()
+            .filter(|(height, hash)| {
+                self.blocks_requests
+                    .range(
+                        (*height, *hash, RequestId(usize::min_value()))
+                            ..=(*height, *hash, RequestId(usize::max_value())),
+                    )
+                    .next()
+                    .is_none()
+            })
+            .collect::<Vec<_>>();
+
+        to_remove
+            .into_iter()
+            .map(|(height, hash)| {
+                self.sources.remove_known_block(height, &hash);
+                self.blocks.remove(height, &hash).user_data
+            })
+            .collect()
+    }
+
     /// Inserts an unverified block in the collection.
     ///
     /// Returns the previous user data associated to this block, if any.
@@ -505,6 +564,14 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         self.blocks.contains(height, hash)
     }
 
+    /// Returns the parent hash of the given block.
+    ///
+    /// Returns `None` if either the block isn't in the data structure or its parent hash isn't
+    /// known.
+    pub fn unverified_block_parent_hash(&self, height: u64, hash: &[u8; 32]) -> Option<&[u8; 32]> {
+        self.blocks.parent_hash(height, hash)
+    }
+
     /// Gives access to the user data stored for this block.
     ///
     /// # Panic
@@ -1124,3 +1191,72 @@ pub struct RequestParams {
     /// than requested.
     pub num_blocks: NonZeroU64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, PendingBlocks, RequestParams, UnverifiedBlockState};
+    use core::num::{NonZeroU32, NonZeroU64};
+
+    #[test]
+    fn abandoned_fork_reclaimed() {
+        let mut blocks = PendingBlocks::<u8, (), ()>::new(Config {
+            blocks_capacity: 16,
+            sources_capacity: 16,
+            finalized_block_height: 0,
+            verify_bodies: false,
+            max_requests_per_block: NonZeroU32::new(1).unwrap(),
+        });
+
+        // Two forks starting at the genesis block, and a block whose ancestry is unknown.
+        for (height, hash, parent_hash) in [
+            (1, [0x1a; 32], [0; 32]),
+            (2, [0x2a; 32], [0x1a; 32]),
+            (1, [0x1b; 32], [0; 32]),
+            (2, [0x2b; 32], [0x1b; 32]),
+            (3, [0x3b; 32], [0x2b; 32]),
+            (4, [0x4b; 32], [0x3b; 32]),
+            (3, [0x3c; 32], [0x2c; 32]),
+        ] {
+            blocks.insert_unverified_block(
+                height,
+                hash,
+                UnverifiedBlockState::HeaderKnown { parent_hash },
+                hash[0],
+            );
+        }
+        blocks.insert_unverified_block(2, [0x2d; 32], UnverifiedBlockState::HeightHashKnown, 0x2d);
+
+        // Block 3b is being downloaded, which also concerns its parents.
+        let source = blocks.add_source((), 4, [0x4b; 32]);
+        blocks.add_known_block_to_source(source, 3, [0x3b; 32]);
+        let request = blocks.add_request(
+            source,
+            RequestParams {
+                first_block_height: 3,
+                first_block_hash: [0x3b; 32],
+                num_blocks: NonZeroU64::new(1).unwrap(),
+            },
+            (),
+        );
+
+        // Finalize block 1a.
+        assert_eq!(blocks.set_finalized_block_height(1).len(), 2);
+        let mut removed = blocks.remove_abandoned_unverified_blocks(&[0x1a; 32]);
+        removed.sort_unstable();
+        assert_eq!(removed, [0x4b]);
+        assert_eq!(blocks.num_unverified_blocks(), 5);
+
+        // Blocks concerned by the request are reclaimed once it is over.
+        blocks.finish_request(request);
+        let mut removed = blocks.remove_abandoned_unverified_blocks(&[0x1a; 32]);
+        removed.sort_unstable();
+        assert_eq!(removed, [0x2b, 0x3b]);
+        assert!(!blocks.source_knows_non_finalized_block(source, 3, &[0x3b; 32]));
+
+        // The descendants of the finalized block and the blocks of unknown ancestry remain.
+        assert_eq!(blocks.num_unverified_blocks(), 3);
+        assert!(blocks.contains_unverified_block(2, &[0x2a; 32]));
+        assert!(blocks.contains_unverified_block(2, &[0x2d; 32]));
+        assert!(blocks.contains_unverified_block(3, &[0x3c; 32]));
+    }
+}
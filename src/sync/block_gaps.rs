@@ -0,0 +1,242 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tracking of the ranges of finalized blocks missing from the local storage.
+//!
+//! After a warp sync, the local node knows about the latest finalized block but not about its
+//! ancestors, down to the block the warp sync started from. The [`BlockGaps`] keeps track of
+//! these missing ranges of blocks ("gaps"), so that they can later be downloaded and filled.
+//!
+//! Each gap is bounded by two known blocks: the block right before the gap, whose hash is known,
+//! and the block right after the gap, whose parent hash is known. A gap can be filled from both
+//! ends, by importing either the first missing block, whose parent hash must match the hash of
+//! the block right before the gap, or the last missing block, whose hash must match the parent
+//! hash of the block right after the gap. A gap disappears once all of its blocks have been
+//! imported and the two ends meet.
+
+use crate::header;
+
+use alloc::collections::BTreeMap;
+
+/// Collection of gaps of finalized blocks. See [the module-level documentation](..).
+#[derive(Debug, Clone, Default)]
+pub struct BlockGaps {
+    /// List of gaps, indexed by the number of the first missing block.
+    gaps: BTreeMap<u64, Gap>,
+}
+
+#[derive(Debug, Clone)]
+struct Gap {
+    /// Number of the last missing block. Always superior or equal to the key in
+    /// [`BlockGaps::gaps`].
+    last_missing: u64,
+    /// Hash of the block right before the first missing block.
+    before_hash: [u8; 32],
+    /// Hash of the last missing block, in other words the parent hash of the block right after
+    /// the gap.
+    last_missing_hash: [u8; 32],
+}
+
+impl BlockGaps {
+    /// Builds a new empty [`BlockGaps`].
+    pub fn new() -> Self {
+        BlockGaps {
+            gaps: BTreeMap::new(),
+        }
+    }
+
+    /// Inserts a new gap between two known blocks.
+    ///
+    /// `before` is the number and hash of the block right before the gap. `after` is the number
+    /// and parent hash of the block right after the gap.
+    ///
+    /// Has no effect if the two blocks are direct parent and child, in other words if the gap
+    /// is empty.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `after.0 <= before.0`.
+    /// Panics if the new gap overlaps with an existing one.
+    ///
+    pub fn insert(&mut self, before: (u64, [u8; 32]), after: (u64, [u8; 32])) {
+        assert!(after.0 > before.0);
+        if after.0 == before.0 + 1 {
+            return;
+        }
+
+        let first_missing = before.0 + 1;
+        let last_missing = after.0 - 1;
+        assert!(!matches!(
+            self.gaps.range(..=last_missing).next_back(),
+            Some((_, gap)) if gap.last_missing >= first_missing
+        ));
+
+        self.gaps.insert(
+            first_missing,
+            Gap {
+                last_missing,
+                before_hash: before.1,
+                last_missing_hash: after.1,
+            },
+        );
+    }
+
+    /// Returns the list of gaps, as `(first missing block, last missing block)` tuples, both
+    /// inclusive, ordered by increasing block number.
+    pub fn gaps(&'_ self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.gaps
+            .iter()
+            .map(|(first_missing, gap)| (*first_missing, gap.last_missing))
+    }
+
+    /// Returns `true` if there isn't any gap.
+    pub fn is_empty(&self) -> bool {
+        self.gaps.is_empty()
+    }
+
+    /// Imports a block that belongs to one of the ends of a gap, shrinking the gap.
+    ///
+    /// The block must either be the first missing block of a gap and have a parent hash equal to
+    /// the hash of the block before the gap, or be the last missing block of a gap and have a
+    /// hash equal to the parent hash of the block after the gap. If the block is the only
+    /// missing block of a gap, both conditions must be satisfied.
+    pub fn import(&mut self, header: header::HeaderRef) -> Result<(), ImportError> {
+        let (first_missing, gap) = match self.gaps.range(..=header.number).next_back() {
+            Some((first_missing, gap)) if gap.last_missing >= header.number => {
+                (*first_missing, gap)
+            }
+            _ => return Err(ImportError::NotInGap),
+        };
+
+        let hash = header.hash();
+        let matches_first =
+            header.number == first_missing && *header.parent_hash == gap.before_hash;
+        let matches_last = header.number == gap.last_missing && hash == gap.last_missing_hash;
+
+        if first_missing == gap.last_missing {
+            // Last block of the gap. It needs to be connected on both ends.
+            if !matches_first || !matches_last {
+                return Err(ImportError::HashMismatch);
+            }
+            self.gaps.remove(&first_missing);
+        } else if matches_last {
+            let gap = self.gaps.get_mut(&first_missing).unwrap();
+            gap.last_missing -= 1;
+            gap.last_missing_hash = *header.parent_hash;
+        } else if matches_first {
+            let mut gap = self.gaps.remove(&first_missing).unwrap();
+            gap.before_hash = hash;
+            self.gaps.insert(first_missing + 1, gap);
+        } else if header.number == first_missing || header.number == gap.last_missing {
+            return Err(ImportError::HashMismatch);
+        } else {
+            return Err(ImportError::NotGapBoundary);
+        }
+
+        Ok(())
+    }
+}
+
+/// Error returned by [`BlockGaps::import`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum ImportError {
+    /// Block doesn't belong to any gap.
+    NotInGap,
+    /// Block is in the middle of a gap rather than at one of its ends.
+    NotGapBoundary,
+    /// Block doesn't connect to the known blocks around the gap.
+    HashMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockGaps, ImportError};
+    use crate::header;
+
+    /// Builds a chain of headers, where the header at index `n` has the number `n`.
+    fn build_chain(len: u64) -> Vec<header::Header> {
+        let mut chain: Vec<header::Header> = Vec::new();
+        for number in 0..len {
+            chain.push(header::Header {
+                parent_hash: chain.last().map_or([0; 32], |h| h.hash()),
+                number,
+                state_root: [number as u8; 32],
+                extrinsics_root: [0; 32],
+                digest: header::DigestRef::empty().into(),
+            });
+        }
+        chain
+    }
+
+    #[test]
+    fn fill_from_both_ends() {
+        let chain = build_chain(12);
+        let mut gaps = BlockGaps::new();
+        gaps.insert((2, chain[2].hash()), (10, chain[10].parent_hash));
+        assert_eq!(gaps.gaps().collect::<Vec<_>>(), [(3, 9)]);
+
+        // Blocks outside of the gap or in its middle can't be imported.
+        assert!(matches!(
+            gaps.import((&chain[10]).into()),
+            Err(ImportError::NotInGap)
+        ));
+        assert!(matches!(
+            gaps.import((&chain[5]).into()),
+            Err(ImportError::NotGapBoundary)
+        ));
+
+        // Block from a different chain.
+        let mut forked = chain[9].clone();
+        forked.state_root = [0xff; 32];
+        assert!(matches!(
+            gaps.import((&forked).into()),
+            Err(ImportError::HashMismatch)
+        ));
+
+        gaps.import((&chain[9]).into()).unwrap();
+        gaps.import((&chain[3]).into()).unwrap();
+        gaps.import((&chain[8]).into()).unwrap();
+        gaps.import((&chain[4]).into()).unwrap();
+        gaps.import((&chain[5]).into()).unwrap();
+        gaps.import((&chain[7]).into()).unwrap();
+        assert_eq!(gaps.gaps().collect::<Vec<_>>(), [(6, 6)]);
+
+        // The last missing block must connect on both sides.
+        let mut forked = chain[6].clone();
+        forked.parent_hash = [0xff; 32];
+        assert!(matches!(
+            gaps.import((&forked).into()),
+            Err(ImportError::HashMismatch)
+        ));
+        assert!(!gaps.is_empty());
+
+        gaps.import((&chain[6]).into()).unwrap();
+        assert!(gaps.is_empty());
+        assert!(matches!(
+            gaps.import((&chain[6]).into()),
+            Err(ImportError::NotInGap)
+        ));
+    }
+
+    #[test]
+    fn empty_gap_ignored() {
+        let chain = build_chain(3);
+        let mut gaps = BlockGaps::new();
+        gaps.insert((1, chain[1].hash()), (2, chain[2].parent_hash));
+        assert!(gaps.is_empty());
+    }
+}
@@ -93,6 +93,10 @@ pub struct Config {
     /// block requests.
     pub download_ahead_blocks: NonZeroU32,
 
+    /// Seed used by the randomness necessary to verify the signatures of justifications. See
+    /// [`crate::finality::signatures::verify_ed25519`].
+    pub randomness_seed: [u8; 32],
+
     /// If `Some`, the block bodies and storage are also synchronized. Contains the extra
     /// configuration.
     pub full: Option<ConfigFull>,
@@ -248,6 +252,7 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         let blocks_tree_config = blocks_tree::Config {
             chain_information: config.chain_information,
             blocks_capacity: config.blocks_capacity,
+            randomness_seed: config.randomness_seed,
         };
 
         let chain = blocks_tree::NonFinalizedTree::new(blocks_tree_config.clone());
@@ -316,6 +321,19 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         self.chain.best_block_hash()
     }
 
+    /// Returns the hash and number of the best block.
+    ///
+    /// > **Note**: This value is provided only for informative purposes. Keep in mind that this
+    /// >           best block might be reverted in the future.
+    pub fn best_block(&self) -> ([u8; 32], u64) {
+        self.chain.best_block()
+    }
+
+    /// Returns the hash and number of the finalized block.
+    pub fn finalized_block(&self) -> ([u8; 32], u64) {
+        self.chain.finalized_block()
+    }
+
     /// Returns consensus information about the current best block of the chain.
     pub fn best_block_consensus(&self) -> chain_information::ChainInformationConsensusRef {
         self.chain.best_block_consensus()
@@ -736,6 +754,15 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
         self.inner.finalized_runtime.is_some()
     }
 
+    /// Do not actually proceed with the verification. The block stays in the verification
+    /// queue.
+    pub fn cancel(self) -> OptimisticSync<TRq, TSrc, TBl> {
+        OptimisticSync {
+            chain: self.chain,
+            inner: self.inner,
+        }
+    }
+
     /// Returns the SCALE-encoded header of the block about to be verified.
     fn header(&self) -> &[u8] {
         &self
@@ -1065,7 +1092,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                     continue 'verif_steps;
                 }
 
-                // The three variants below correspond to problems during the verification.
+                // The four variants below correspond to problems during the verification.
                 //
                 // When that happens:
                 //
@@ -1100,6 +1127,40 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                         reason: ResetCause::InvalidHeader(error),
                     };
                 }
+                Inner::Step1(blocks_tree::BodyVerifyStep1::ConflictingScheduledChange {
+                    chain: old_chain,
+                    pending_trigger_block_height,
+                }) => {
+                    if let Some(source) = shared.inner.sources.get_mut(&shared.source_id) {
+                        source.banned = true;
+                    }
+
+                    // If all sources are banned, unban them.
+                    if shared.inner.sources.iter().all(|(_, s)| s.banned) {
+                        for src in shared.inner.sources.values_mut() {
+                            src.banned = false;
+                        }
+                    }
+
+                    let chain = blocks_tree::NonFinalizedTree::new(
+                        shared.inner.finalized_chain_information.clone(),
+                    );
+
+                    let mut inner = shared.inner.with_requests_obsoleted(&chain);
+                    inner.best_to_finalized_storage_diff = Default::default();
+                    inner.best_runtime = None;
+                    inner.top_trie_root_calculation_cache = None;
+
+                    break BlockVerification::Reset {
+                        previous_best_height: old_chain.best_block_header().number,
+                        sync: OptimisticSync { chain, inner },
+                        reason: ResetCause::HeaderError(
+                            blocks_tree::HeaderVerifyError::ConflictingScheduledChange {
+                                pending_trigger_block_height,
+                            },
+                        ),
+                    };
+                }
                 Inner::Step1(
                     blocks_tree::BodyVerifyStep1::Duplicate(old_chain)
                     | blocks_tree::BodyVerifyStep1::BadParent {
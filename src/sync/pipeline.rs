@@ -0,0 +1,873 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Pipelined import of a range of consecutive blocks.
+//!
+//! Importing a block consists of four stages: downloading its header, verifying its header,
+//! downloading its body, and executing its body. The first and third stages mostly consist in
+//! waiting for the network, while the second and fourth stages mostly consist in CPU work.
+//!
+//! Rather than performing these stages one after the other for each block, the [`Pipeline`]
+//! makes it possible to overlap them: the header of a block can be verified while the headers
+//! of the next blocks are being downloaded, and the body of a block can be executed while the
+//! bodies of the next blocks are being downloaded.
+//!
+//! Headers are verified one at a time and in increasing order, as verifying a header requires
+//! its parent to have been verified. Similarly, bodies are executed one at a time and in
+//! increasing order. Downloads, on the other hand, can happen in parallel.
+//!
+//! # Usage
+//!
+//! Like the other state machines of this crate, the [`Pipeline`] doesn't perform any
+//! networking or verification by itself. Call [`Pipeline::next_task`] in order to obtain the
+//! tasks that can be started, perform them, then report their outcome by calling the
+//! corresponding method. Multiple tasks can be in progress at the same time.
+//!
+//! # Back-pressure
+//!
+//! The number of blocks between each pair of stages is bounded, as configured with
+//! [`Config::max_pending_headers`] and [`Config::max_pending_bodies`]. Once a limit is reached,
+//! [`Pipeline::next_task`] stops returning tasks of the stage that precedes the limit, until the
+//! stage that follows it has made progress. For example, if bodies are executed more slowly
+//! than they are downloaded, no new body download is started and, eventually, no new header
+//! download is started either.
+//!
+//! # Failures
+//!
+//! Failing to download a header or body simply puts the download back in the queue.
+//!
+//! Failing to verify a header or execute a body means that the block is invalid. The block and
+//! all the blocks above it are removed from the pipeline, and the tasks that concern these
+//! blocks are returned so that they can be cancelled. The import then resumes from the invalid
+//! block, which is downloaded again.
+//!
+//! A block that is deterministically invalid is downloaded again forever. It is the
+//! responsibility of the API user to stop driving the pipeline, for example after the same
+//! height has failed multiple times.
+//!
+//! # Scope
+//!
+//! This module is only a building block. None of the syncing strategies of this crate uses it
+//! at the moment, as [`super::optimistic`] and [`super::all_forks`] download the header and the
+//! body of a block through the same request.
+
+use alloc::{collections::VecDeque, vec::Vec};
+use core::num::NonZeroUsize;
+
+/// Configuration for the [`Pipeline`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Height of the first block to import.
+    pub start_height: u64,
+
+    /// Height of the last block to import, inclusive.
+    pub target_height: u64,
+
+    /// Maximum number of blocks whose header is being downloaded, or has been downloaded but
+    /// whose body download hasn't started yet.
+    ///
+    /// This bounds the memory used to store headers that are waiting for the next stages.
+    pub max_pending_headers: NonZeroUsize,
+
+    /// Maximum number of blocks whose body is being downloaded, or has been downloaded but
+    /// hasn't been executed yet.
+    ///
+    /// This bounds the memory used to store bodies that are waiting for their execution.
+    pub max_pending_bodies: NonZeroUsize,
+}
+
+/// Identifier of a task in progress within a [`Pipeline`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+/// Task that the API user must perform. See [`Pipeline::next_task`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Task {
+    /// The header of the given block must be downloaded. Report the outcome with
+    /// [`Pipeline::header_download_finished`].
+    HeaderDownload {
+        /// Height of the block.
+        height: u64,
+    },
+    /// The given header must be verified. Report the outcome with
+    /// [`Pipeline::header_verify_finished`].
+    HeaderVerify {
+        /// Height of the block.
+        height: u64,
+        /// SCALE-encoded header, as provided to [`Pipeline::header_download_finished`].
+        scale_encoded_header: Vec<u8>,
+    },
+    /// The body of the given block must be downloaded. Report the outcome with
+    /// [`Pipeline::body_download_finished`].
+    BodyDownload {
+        /// Height of the block.
+        height: u64,
+        /// SCALE-encoded header of the block, whose verification has succeeded.
+        scale_encoded_header: Vec<u8>,
+    },
+    /// The body of the given block must be executed. Report the outcome with
+    /// [`Pipeline::body_execute_finished`].
+    BodyExecute {
+        /// Height of the block.
+        height: u64,
+        /// SCALE-encoded header of the block.
+        scale_encoded_header: Vec<u8>,
+        /// List of SCALE-encoded extrinsics of the block, as provided to
+        /// [`Pipeline::body_download_finished`].
+        body: Vec<Vec<u8>>,
+    },
+}
+
+/// Pipelined import of blocks. See [the module-level documentation](..).
+#[derive(Debug)]
+pub struct Pipeline {
+    /// Blocks being imported, ordered by increasing height. The first element has a height
+    /// of [`Pipeline::first_height`]. Blocks are pushed at the back when the download of their
+    /// header starts, and popped from the front once their body has been executed.
+    blocks: VecDeque<Block>,
+
+    /// Height of the first element of [`Pipeline::blocks`], or, if it is empty, of the next
+    /// block to import.
+    first_height: u64,
+
+    /// See [`Config::target_height`].
+    target_height: u64,
+
+    /// See [`Config::max_pending_headers`].
+    max_pending_headers: usize,
+
+    /// See [`Config::max_pending_bodies`].
+    max_pending_bodies: usize,
+
+    /// Identifier to assign to the next task.
+    next_task_id: TaskId,
+}
+
+#[derive(Debug)]
+struct Block {
+    /// SCALE-encoded header of the block, if it has been downloaded.
+    scale_encoded_header: Option<Vec<u8>>,
+    state: BlockState,
+}
+
+#[derive(Debug)]
+enum BlockState {
+    /// Header must be downloaded, and the download hasn't started.
+    HeaderDownloadQueued,
+    HeaderDownloading(TaskId),
+    HeaderDownloaded,
+    HeaderVerifying(TaskId),
+    HeaderVerified,
+    /// Body must be downloaded again after a failed download.
+    BodyDownloadQueued,
+    BodyDownloading(TaskId),
+    BodyDownloaded(Vec<Vec<u8>>),
+    Executing(TaskId),
+}
+
+impl BlockState {
+    /// Returns the task currently in progress for this block, if any.
+    fn task(&self) -> Option<TaskId> {
+        match self {
+            BlockState::HeaderDownloading(id)
+            | BlockState::HeaderVerifying(id)
+            | BlockState::BodyDownloading(id)
+            | BlockState::Executing(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the header of the block hasn't been handed to the body stages yet.
+    fn in_header_stages(&self) -> bool {
+        matches!(
+            self,
+            BlockState::HeaderDownloadQueued
+                | BlockState::HeaderDownloading(_)
+                | BlockState::HeaderDownloaded
+                | BlockState::HeaderVerifying(_)
+                | BlockState::HeaderVerified
+        )
+    }
+}
+
+impl Pipeline {
+    /// Initializes a new [`Pipeline`].
+    ///
+    /// The pipeline is empty, in other words [`Pipeline::is_finished`] returns `true`, if
+    /// [`Config::target_height`] is inferior to [`Config::start_height`].
+    pub fn new(config: Config) -> Self {
+        Pipeline {
+            blocks: VecDeque::with_capacity(
+                config.max_pending_headers.get() + config.max_pending_bodies.get(),
+            ),
+            first_height: config.start_height,
+            target_height: config.target_height,
+            max_pending_headers: config.max_pending_headers.get(),
+            max_pending_bodies: config.max_pending_bodies.get(),
+            next_task_id: TaskId(0),
+        }
+    }
+
+    /// Returns the height of the next block to be executed. All the blocks below this height
+    /// have been fully imported.
+    pub fn next_to_execute(&self) -> u64 {
+        self.first_height
+    }
+
+    /// Returns `true` if all the blocks up to [`Config::target_height`] have been imported.
+    pub fn is_finished(&self) -> bool {
+        self.first_height > self.target_height
+    }
+
+    /// Returns the number of tasks currently in progress.
+    pub fn num_tasks_in_progress(&self) -> usize {
+        self.blocks
+            .iter()
+            .filter(|b| b.state.task().is_some())
+            .count()
+    }
+
+    /// Returns the next task that the API user must start, or `None` if no task can be started
+    /// at the moment. This method should be called repeatedly until it returns `None`, then
+    /// again after each task has finished.
+    ///
+    /// Tasks that are closer to the end of the pipeline are returned first, in order for the
+    /// blocks to leave the pipeline as soon as possible.
+    pub fn next_task(&mut self) -> Option<(TaskId, Task)> {
+        // Body execution, only for the lowest block and if no other execution is in progress.
+        if let Some(block) = self.blocks.front_mut() {
+            if matches!(block.state, BlockState::BodyDownloaded(_)) {
+                let task_id = Self::allocate_task_id(&mut self.next_task_id);
+                let body =
+                    match core::mem::replace(&mut block.state, BlockState::Executing(task_id)) {
+                        BlockState::BodyDownloaded(body) => body,
+                        _ => unreachable!(),
+                    };
+                return Some((
+                    task_id,
+                    Task::BodyExecute {
+                        height: self.first_height,
+                        scale_encoded_header: block.scale_encoded_header.clone().unwrap(),
+                        body,
+                    },
+                ));
+            }
+        }
+
+        // Header verification, for the lowest block whose header isn't verified yet, if no
+        // other verification is in progress.
+        if let Some(index) = self.blocks.iter().position(|b| {
+            matches!(
+                b.state,
+                BlockState::HeaderDownloadQueued
+                    | BlockState::HeaderDownloading(_)
+                    | BlockState::HeaderDownloaded
+                    | BlockState::HeaderVerifying(_)
+            )
+        }) {
+            if matches!(self.blocks[index].state, BlockState::HeaderDownloaded) {
+                let task_id = Self::allocate_task_id(&mut self.next_task_id);
+                let block = &mut self.blocks[index];
+                block.state = BlockState::HeaderVerifying(task_id);
+                return Some((
+                    task_id,
+                    Task::HeaderVerify {
+                        height: self.first_height + u64::try_from(index).unwrap(),
+                        scale_encoded_header: block.scale_encoded_header.clone().unwrap(),
+                    },
+                ));
+            }
+        }
+
+        // Body download, for the lowest block whose header is verified, if the body stages
+        // aren't full.
+        let num_pending_bodies = self
+            .blocks
+            .iter()
+            .filter(|b| !b.state.in_header_stages())
+            .count();
+        let body_queued = self
+            .blocks
+            .iter()
+            .position(|b| matches!(b.state, BlockState::BodyDownloadQueued));
+        let body_to_download = body_queued.or_else(|| {
+            if num_pending_bodies < self.max_pending_bodies {
+                self.blocks
+                    .iter()
+                    .position(|b| matches!(b.state, BlockState::HeaderVerified))
+            } else {
+                None
+            }
+        });
+        if let Some(index) = body_to_download {
+            let task_id = Self::allocate_task_id(&mut self.next_task_id);
+            let block = &mut self.blocks[index];
+            block.state = BlockState::BodyDownloading(task_id);
+            return Some((
+                task_id,
+                Task::BodyDownload {
+                    height: self.first_height + u64::try_from(index).unwrap(),
+                    scale_encoded_header: block.scale_encoded_header.clone().unwrap(),
+                },
+            ));
+        }
+
+        // Header download, either of a block whose previous download has failed, or of a new
+        // block if the header stages aren't full.
+        if let Some(index) = self
+            .blocks
+            .iter()
+            .position(|b| matches!(b.state, BlockState::HeaderDownloadQueued))
+        {
+            let task_id = Self::allocate_task_id(&mut self.next_task_id);
+            self.blocks[index].state = BlockState::HeaderDownloading(task_id);
+            return Some((
+                task_id,
+                Task::HeaderDownload {
+                    height: self.first_height + u64::try_from(index).unwrap(),
+                },
+            ));
+        }
+
+        let num_pending_headers = self
+            .blocks
+            .iter()
+            .filter(|b| b.state.in_header_stages())
+            .count();
+        let next_height = self.first_height + u64::try_from(self.blocks.len()).unwrap();
+        if num_pending_headers < self.max_pending_headers && next_height <= self.target_height {
+            let task_id = Self::allocate_task_id(&mut self.next_task_id);
+            self.blocks.push_back(Block {
+                scale_encoded_header: None,
+                state: BlockState::HeaderDownloading(task_id),
+            });
+            return Some((
+                task_id,
+                Task::HeaderDownload {
+                    height: next_height,
+                },
+            ));
+        }
+
+        None
+    }
+
+    /// Reports the outcome of a [`Task::HeaderDownload`].
+    ///
+    /// On failure, the header will be returned again by [`Pipeline::next_task`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`TaskId`] is invalid or doesn't correspond to a header download.
+    ///
+    pub fn header_download_finished(&mut self, task_id: TaskId, result: Result<Vec<u8>, ()>) {
+        let block = self.block_by_task(task_id);
+        assert!(matches!(block.state, BlockState::HeaderDownloading(_)));
+        match result {
+            Ok(scale_encoded_header) => {
+                block.scale_encoded_header = Some(scale_encoded_header);
+                block.state = BlockState::HeaderDownloaded;
+            }
+            Err(()) => block.state = BlockState::HeaderDownloadQueued,
+        }
+    }
+
+    /// Reports the outcome of a [`Task::HeaderVerify`].
+    ///
+    /// On failure, the block and all the blocks above it are removed from the pipeline, and the
+    /// list of tasks concerning these blocks that are still in progress is returned. These tasks
+    /// must be cancelled and must no longer be reported. The block will then be downloaded
+    /// again.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`TaskId`] is invalid or doesn't correspond to a header verification.
+    ///
+    pub fn header_verify_finished(
+        &mut self,
+        task_id: TaskId,
+        result: Result<(), ()>,
+    ) -> Vec<TaskId> {
+        let index = self.block_index_by_task(task_id);
+        assert!(matches!(
+            self.blocks[index].state,
+            BlockState::HeaderVerifying(_)
+        ));
+        match result {
+            Ok(()) => {
+                self.blocks[index].state = BlockState::HeaderVerified;
+                Vec::new()
+            }
+            Err(()) => self.truncate(index),
+        }
+    }
+
+    /// Reports the outcome of a [`Task::BodyDownload`].
+    ///
+    /// On failure, the body will be returned again by [`Pipeline::next_task`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`TaskId`] is invalid or doesn't correspond to a body download.
+    ///
+    pub fn body_download_finished(&mut self, task_id: TaskId, result: Result<Vec<Vec<u8>>, ()>) {
+        let block = self.block_by_task(task_id);
+        assert!(matches!(block.state, BlockState::BodyDownloading(_)));
+        block.state = match result {
+            Ok(body) => BlockState::BodyDownloaded(body),
+            Err(()) => BlockState::BodyDownloadQueued,
+        };
+    }
+
+    /// Reports the outcome of a [`Task::BodyExecute`].
+    ///
+    /// On success, the block leaves the pipeline. On failure, all the blocks of the pipeline are
+    /// removed, and the list of tasks that are still in progress is returned. These tasks must be
+    /// cancelled and must no longer be reported. The block will then be downloaded again.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`TaskId`] is invalid or doesn't correspond to a body execution.
+    ///
+    pub fn body_execute_finished(
+        &mut self,
+        task_id: TaskId,
+        result: Result<(), ()>,
+    ) -> Vec<TaskId> {
+        let index = self.block_index_by_task(task_id);
+        assert!(matches!(self.blocks[index].state, BlockState::Executing(_)));
+        debug_assert_eq!(index, 0);
+        match result {
+            Ok(()) => {
+                self.blocks.pop_front();
+                self.first_height += 1;
+                Vec::new()
+            }
+            Err(()) => self.truncate(index),
+        }
+    }
+
+    /// Removes the block at the given index and all the blocks above it. Returns the tasks in
+    /// progress for the removed blocks, except for the block at the given index.
+    fn truncate(&mut self, index: usize) -> Vec<TaskId> {
+        let obsolete = self
+            .blocks
+            .iter()
+            .skip(index + 1)
+            .filter_map(|b| b.state.task())
+            .collect();
+        self.blocks.truncate(index);
+        obsolete
+    }
+
+    fn block_index_by_task(&self, task_id: TaskId) -> usize {
+        self.blocks
+            .iter()
+            .position(|b| b.state.task() == Some(task_id))
+            .unwrap()
+    }
+
+    fn block_by_task(&mut self, task_id: TaskId) -> &mut Block {
+        let index = self.block_index_by_task(task_id);
+        &mut self.blocks[index]
+    }
+
+    fn allocate_task_id(next_task_id: &mut TaskId) -> TaskId {
+        let id = *next_task_id;
+        next_task_id.0 += 1;
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Pipeline, Task, TaskId};
+    use alloc::collections::BTreeSet;
+    use core::{iter, num::NonZeroUsize};
+
+    fn header_of(height: u64) -> Vec<u8> {
+        height.to_le_bytes().to_vec()
+    }
+
+    fn body_of(height: u64) -> Vec<Vec<u8>> {
+        vec![height.to_le_bytes().to_vec(); 2]
+    }
+
+    /// Drives the pipeline until completion, where each task takes exactly one tick.
+    ///
+    /// `verify` and `execute` are called for each header verification and body execution and
+    /// must return whether it succeeds. Returns the number of ticks, the list of executed blocks
+    /// in order, and for each tick the list of tasks in progress during that tick.
+    fn simulate(
+        config: Config,
+        mut verify: impl FnMut(u64) -> bool,
+        mut execute: impl FnMut(u64) -> bool,
+    ) -> (u32, Vec<u64>, Vec<Vec<Task>>) {
+        let mut pipeline = Pipeline::new(config);
+        let mut executed = Vec::new();
+        let mut timeline = Vec::new();
+        let mut ticks = 0;
+
+        loop {
+            let mut started = Vec::new();
+            while let Some(task) = pipeline.next_task() {
+                started.push(task);
+            }
+            if started.is_empty() {
+                assert!(pipeline.is_finished());
+                break;
+            }
+            assert_eq!(pipeline.num_tasks_in_progress(), started.len());
+            ticks += 1;
+            timeline.push(started.iter().map(|(_, t)| t.clone()).collect());
+
+            let mut obsolete = BTreeSet::<TaskId>::new();
+            for (task_id, task) in started {
+                if obsolete.contains(&task_id) {
+                    continue;
+                }
+
+                match task {
+                    Task::HeaderDownload { height } => {
+                        pipeline.header_download_finished(task_id, Ok(header_of(height)))
+                    }
+                    Task::HeaderVerify {
+                        height,
+                        scale_encoded_header,
+                    } => {
+                        assert_eq!(scale_encoded_header, header_of(height));
+                        let result = if verify(height) { Ok(()) } else { Err(()) };
+                        obsolete.extend(pipeline.header_verify_finished(task_id, result));
+                    }
+                    Task::BodyDownload {
+                        height,
+                        scale_encoded_header,
+                    } => {
+                        assert_eq!(scale_encoded_header, header_of(height));
+                        pipeline.body_download_finished(task_id, Ok(body_of(height)))
+                    }
+                    Task::BodyExecute {
+                        height,
+                        scale_encoded_header,
+                        body,
+                    } => {
+                        assert_eq!(scale_encoded_header, header_of(height));
+                        assert_eq!(body, body_of(height));
+                        assert_eq!(pipeline.next_to_execute(), height);
+                        let result = if execute(height) {
+                            executed.push(height);
+                            Ok(())
+                        } else {
+                            Err(())
+                        };
+                        obsolete.extend(pipeline.body_execute_finished(task_id, result));
+                    }
+                }
+            }
+        }
+
+        assert_eq!(pipeline.num_tasks_in_progress(), 0);
+        (ticks, executed, timeline)
+    }
+
+    #[test]
+    fn stages_overlap() {
+        let (ticks, executed, timeline) = simulate(
+            Config {
+                start_height: 10,
+                target_height: 59,
+                max_pending_headers: NonZeroUsize::new(4).unwrap(),
+                max_pending_bodies: NonZeroUsize::new(4).unwrap(),
+            },
+            |_| true,
+            |_| true,
+        );
+
+        // All the blocks are executed exactly once and in order.
+        assert_eq!(executed, (10..=59).collect::<Vec<_>>());
+
+        // Performing the four stages sequentially would take 4 ticks per block. Overlapping
+        // them makes the import roughly as long as the slowest stage, which is one tick per
+        // block because of the sequential verifications and executions.
+        assert!(ticks < 50 + 8, "{}", ticks);
+
+        // At some point, all four stages are in progress at the same time.
+        assert!(timeline.iter().any(|tasks| {
+            tasks
+                .iter()
+                .any(|t| matches!(t, Task::HeaderDownload { .. }))
+                && tasks.iter().any(|t| matches!(t, Task::HeaderVerify { .. }))
+                && tasks.iter().any(|t| matches!(t, Task::BodyDownload { .. }))
+                && tasks.iter().any(|t| matches!(t, Task::BodyExecute { .. }))
+        }));
+
+        // There is never more than one verification or execution at a time.
+        assert!(timeline.iter().all(|tasks| {
+            tasks
+                .iter()
+                .filter(|t| matches!(t, Task::HeaderVerify { .. }))
+                .count()
+                <= 1
+                && tasks
+                    .iter()
+                    .filter(|t| matches!(t, Task::BodyExecute { .. }))
+                    .count()
+                    <= 1
+        }));
+    }
+
+    #[test]
+    fn back_pressure() {
+        let mut pipeline = Pipeline::new(Config {
+            start_height: 1,
+            target_height: 100,
+            max_pending_headers: NonZeroUsize::new(3).unwrap(),
+            max_pending_bodies: NonZeroUsize::new(2).unwrap(),
+        });
+
+        // Only three header downloads can be started.
+        let mut tasks = Vec::new();
+        while let Some(task) = pipeline.next_task() {
+            tasks.push(task);
+        }
+        assert_eq!(
+            tasks.iter().map(|(_, t)| t.clone()).collect::<Vec<_>>(),
+            vec![
+                Task::HeaderDownload { height: 1 },
+                Task::HeaderDownload { height: 2 },
+                Task::HeaderDownload { height: 3 },
+            ]
+        );
+
+        // Verify the three headers, and start downloading their bodies. Never finish the
+        // body downloads.
+        for (task_id, task) in tasks {
+            match task {
+                Task::HeaderDownload { height } => {
+                    pipeline.header_download_finished(task_id, Ok(header_of(height)))
+                }
+                _ => unreachable!(),
+            }
+        }
+        let mut body_downloads = Vec::new();
+        while let Some((task_id, task)) = pipeline.next_task() {
+            match task {
+                Task::HeaderVerify { .. } => {
+                    assert_eq!(pipeline.header_verify_finished(task_id, Ok(())), Vec::new())
+                }
+                Task::BodyDownload { height, .. } => body_downloads.push((task_id, height)),
+                Task::HeaderDownload { height } => {
+                    pipeline.header_download_finished(task_id, Ok(header_of(height)))
+                }
+                Task::BodyExecute { .. } => unreachable!(),
+            }
+        }
+
+        // Only two bodies can be downloaded at the same time. Since the third verified header
+        // can't move to the body stages, only two new headers have been downloaded and the
+        // pipeline is now stalled.
+        assert_eq!(
+            body_downloads.iter().map(|(_, h)| *h).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(pipeline.num_tasks_in_progress(), 2);
+        assert!(pipeline.next_task().is_none());
+
+        // Executing the first block unblocks the pipeline.
+        pipeline.body_download_finished(body_downloads[0].0, Ok(body_of(1)));
+        let (task_id, task) = pipeline.next_task().unwrap();
+        assert!(matches!(task, Task::BodyExecute { height: 1, .. }));
+        assert!(pipeline.next_task().is_none());
+        assert_eq!(pipeline.body_execute_finished(task_id, Ok(())), Vec::new());
+        assert!(matches!(
+            pipeline.next_task(),
+            Some((_, Task::BodyDownload { height: 3, .. }))
+        ));
+    }
+
+    #[test]
+    fn verification_failure_cancels_downstream() {
+        let mut failed_once = false;
+        let (_, executed, timeline) = simulate(
+            Config {
+                start_height: 1,
+                target_height: 20,
+                max_pending_headers: NonZeroUsize::new(4).unwrap(),
+                max_pending_bodies: NonZeroUsize::new(4).unwrap(),
+            },
+            |height| {
+                if height == 8 && !failed_once {
+                    failed_once = true;
+                    false
+                } else {
+                    true
+                }
+            },
+            |_| true,
+        );
+        assert!(failed_once);
+
+        // Despite the failure, all the blocks are executed exactly once and in order.
+        assert_eq!(executed, (1..=20).collect::<Vec<_>>());
+
+        // The header of block 8 has been downloaded and verified twice.
+        let num_verifications = timeline
+            .iter()
+            .flatten()
+            .filter(|t| matches!(t, Task::HeaderVerify { height: 8, .. }))
+            .count();
+        assert_eq!(num_verifications, 2);
+    }
+
+    #[test]
+    fn failure_returns_obsolete_tasks() {
+        let mut pipeline = Pipeline::new(Config {
+            start_height: 1,
+            target_height: 100,
+            max_pending_headers: NonZeroUsize::new(4).unwrap(),
+            max_pending_bodies: NonZeroUsize::new(4).unwrap(),
+        });
+
+        let (first_download, _) = pipeline.next_task().unwrap();
+        let other_downloads = (0..3)
+            .map(|_| pipeline.next_task().unwrap().0)
+            .collect::<Vec<_>>();
+        assert!(pipeline.next_task().is_none());
+
+        pipeline.header_download_finished(first_download, Ok(header_of(1)));
+        let (verify, task) = pipeline.next_task().unwrap();
+        assert!(matches!(task, Task::HeaderVerify { height: 1, .. }));
+
+        // The failure makes the three other header downloads obsolete.
+        assert_eq!(
+            pipeline.header_verify_finished(verify, Err(())),
+            other_downloads
+        );
+        assert_eq!(pipeline.num_tasks_in_progress(), 0);
+        assert_eq!(pipeline.next_to_execute(), 1);
+
+        // The import restarts from the invalid block.
+        assert!(matches!(
+            pipeline.next_task(),
+            Some((_, Task::HeaderDownload { height: 1 }))
+        ));
+    }
+
+    #[test]
+    fn execution_failure_cancels_all() {
+        let mut pipeline = Pipeline::new(Config {
+            start_height: 1,
+            target_height: 100,
+            max_pending_headers: NonZeroUsize::new(2).unwrap(),
+            max_pending_bodies: NonZeroUsize::new(2).unwrap(),
+        });
+
+        // Download the first two headers, then start all the tasks that can be started,
+        // finishing only the header verifications.
+        let header_downloads = iter::from_fn(|| pipeline.next_task()).collect::<Vec<_>>();
+        assert_eq!(header_downloads.len(), 2);
+        for (task_id, task) in header_downloads {
+            match task {
+                Task::HeaderDownload { height } => {
+                    pipeline.header_download_finished(task_id, Ok(header_of(height)))
+                }
+                _ => unreachable!(),
+            }
+        }
+        let mut in_progress = Vec::new();
+        while let Some((task_id, task)) = pipeline.next_task() {
+            match task {
+                Task::HeaderVerify { .. } => {
+                    assert_eq!(pipeline.header_verify_finished(task_id, Ok(())), Vec::new())
+                }
+                _ => in_progress.push((task_id, task)),
+            }
+        }
+
+        // Bring block 1 to the execution stage, while the next blocks are in the other stages.
+        let body_download = in_progress
+            .iter()
+            .position(|(_, t)| matches!(t, Task::BodyDownload { height: 1, .. }))
+            .unwrap();
+        pipeline.body_download_finished(in_progress.remove(body_download).0, Ok(body_of(1)));
+        let (execute, task) = pipeline.next_task().unwrap();
+        assert!(matches!(task, Task::BodyExecute { height: 1, .. }));
+        assert!(pipeline.next_task().is_none());
+
+        let mut in_progress = in_progress
+            .into_iter()
+            .map(|(task_id, _)| task_id)
+            .collect::<Vec<_>>();
+        assert_eq!(in_progress.len(), 3);
+
+        // The failure of the lowest block makes all the other tasks obsolete.
+        in_progress.sort();
+        assert_eq!(
+            pipeline.body_execute_finished(execute, Err(())),
+            in_progress
+        );
+        assert_eq!(pipeline.num_tasks_in_progress(), 0);
+        assert_eq!(pipeline.next_to_execute(), 1);
+
+        // The import restarts from the block whose execution has failed.
+        assert!(matches!(
+            pipeline.next_task(),
+            Some((_, Task::HeaderDownload { height: 1 }))
+        ));
+    }
+
+    #[test]
+    fn execution_failure_retried() {
+        let mut failed_once = false;
+        let (_, executed, timeline) = simulate(
+            Config {
+                start_height: 1,
+                target_height: 20,
+                max_pending_headers: NonZeroUsize::new(4).unwrap(),
+                max_pending_bodies: NonZeroUsize::new(4).unwrap(),
+            },
+            |_| true,
+            |height| {
+                if height == 8 && !failed_once {
+                    failed_once = true;
+                    false
+                } else {
+                    true
+                }
+            },
+        );
+        assert!(failed_once);
+        assert_eq!(executed, (1..=20).collect::<Vec<_>>());
+
+        // Block 8 has been downloaded and executed twice.
+        let tasks = timeline.iter().flatten().collect::<Vec<_>>();
+        assert_eq!(
+            tasks
+                .iter()
+                .filter(|t| matches!(t, Task::HeaderDownload { height: 8 }))
+                .count(),
+            2
+        );
+        assert_eq!(
+            tasks
+                .iter()
+                .filter(|t| matches!(t, Task::BodyExecute { height: 8, .. }))
+                .count(),
+            2
+        );
+    }
+}
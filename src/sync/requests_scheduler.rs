@@ -0,0 +1,469 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Distribution of requests between the sources of a syncing state machine.
+//!
+//! The [`RequestsScheduler`] keeps track of a list of sources (typically peers of the
+//! peer-to-peer network) and decides which source the next request should be sent to.
+//!
+//! Each source is associated with an estimate of its latency and of its success rate, which
+//! are updated whenever a request finishes. When asked for a source, the scheduler picks the one
+//! with the lowest expected cost, where the expected cost is the estimated latency divided by
+//! the success rate and multiplied by the number of requests already in progress with that
+//! source plus one. In other words, a source twice as fast as another one is expected to be
+//! assigned twice as many simultaneous requests. Ties are broken by picking the source that has
+//! been assigned a request the least recently, in a round-robin fashion.
+//!
+//! The number of simultaneous requests assigned to each source is capped by
+//! [`Config::max_in_flight_per_source`]. Sources that have reached this limit are never picked.
+//!
+//! Sources can be marked as reserved with [`RequestsScheduler::set_source_reserved`], which is
+//! typically done for the peers the API user trusts, such as reserved nodes. Reserved sources
+//! are always picked before the other sources, no matter their expected cost, unless they have
+//! all reached [`Config::max_in_flight_per_source`] or unless their success rate has dropped
+//! below [`RESERVED_MIN_SUCCESS_RATE_PERMILLE`]. In other words, the other sources are only used
+//! as a fallback.
+//!
+//! > **Note**: This module doesn't access any clock. It is the responsibility of the API user
+//! >           to measure how long each request took and report it through
+//! >           [`RequestsScheduler::request_finished`].
+//!
+//! # Scope
+//!
+//! The syncing state machines of this crate don't consult the [`RequestsScheduler`]. Both
+//! [`super::all_forks::AllForksSync::desired_requests`] and
+//! [`super::optimistic::OptimisticSync::desired_requests`] yield every source that a request
+//! could be sent to, and leave the choice to the API user. The [`RequestsScheduler`] is a tool
+//! that the API user can use to make this choice, as only the API user can measure the latency
+//! of the requests.
+
+use core::{cmp, num::NonZeroU32, time::Duration};
+
+/// Configuration for a new [`RequestsScheduler`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Number of sources to pre-allocate space for.
+    pub sources_capacity: usize,
+
+    /// Maximum number of requests that can be in progress at the same time with a single
+    /// source.
+    pub max_in_flight_per_source: NonZeroU32,
+
+    /// Latency assumed for sources for which no request has succeeded yet.
+    pub initial_latency_estimate: Duration,
+}
+
+/// Minimum success rate, in thousandths, that a reserved source must have in order to be picked
+/// before the non-reserved sources. See [`RequestsScheduler::set_source_reserved`].
+pub const RESERVED_MIN_SUCCESS_RATE_PERMILLE: u32 = 500;
+
+/// Identifier for a source in the [`RequestsScheduler`].
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct SourceId(usize);
+
+/// Outcome of a request, reported with [`RequestsScheduler::request_finished`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RequestOutcome {
+    /// Request has succeeded after the given duration.
+    Success {
+        /// Time between the moment the request has been sent and the moment the response has
+        /// been received.
+        latency: Duration,
+    },
+    /// Request has failed or has been refused by the source.
+    Failure,
+}
+
+/// Snapshot of the state of a source in the [`RequestsScheduler`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceStats {
+    /// `true` if the source has been marked as reserved with
+    /// [`RequestsScheduler::set_source_reserved`].
+    pub reserved: bool,
+    /// Number of requests currently in progress with this source.
+    pub in_flight: u32,
+    /// Total number of requests that have been assigned to this source.
+    pub total_assigned: u64,
+    /// Total number of requests with this source that have succeeded.
+    pub total_succeeded: u64,
+    /// Total number of requests with this source that have failed.
+    pub total_failed: u64,
+    /// Current estimate of the latency of this source.
+    pub latency_estimate: Duration,
+    /// Current estimate of the success rate of this source, in thousandths. `1000` means that
+    /// all requests are expected to succeed.
+    pub success_rate_permille: u32,
+}
+
+/// Collection of sources and their requests statistics. See [the module-level
+/// documentation](self).
+pub struct RequestsScheduler<TSrc> {
+    /// List of sources. Indices are used as [`SourceId`]s.
+    sources: slab::Slab<Source<TSrc>>,
+
+    /// See [`Config::max_in_flight_per_source`].
+    max_in_flight_per_source: NonZeroU32,
+
+    /// See [`Config::initial_latency_estimate`], in microseconds.
+    initial_latency_estimate_us: u64,
+
+    /// Value to store in [`Source::last_assigned`] the next time a request is assigned.
+    next_assignment_index: u64,
+}
+
+struct Source<TSrc> {
+    user_data: TSrc,
+    /// See [`RequestsScheduler::set_source_reserved`].
+    reserved: bool,
+    in_flight: u32,
+    total_assigned: u64,
+    total_succeeded: u64,
+    total_failed: u64,
+    /// Exponential moving average of the latency of the successful requests, in microseconds.
+    /// `None` if no request has succeeded yet.
+    latency_estimate_us: Option<u64>,
+    /// Exponential moving average of the success rate, in thousandths.
+    success_rate_permille: u32,
+    /// Value of [`RequestsScheduler::next_assignment_index`] when a request was last assigned
+    /// to this source. `0` if never.
+    last_assigned: u64,
+}
+
+impl<TSrc> RequestsScheduler<TSrc> {
+    /// Initializes a new empty scheduler.
+    pub fn new(config: Config) -> Self {
+        RequestsScheduler {
+            sources: slab::Slab::with_capacity(config.sources_capacity),
+            max_in_flight_per_source: config.max_in_flight_per_source,
+            initial_latency_estimate_us: duration_to_us(config.initial_latency_estimate),
+            next_assignment_index: 1,
+        }
+    }
+
+    /// Adds a new source to the scheduler. The source has no request in progress and isn't
+    /// reserved.
+    pub fn add_source(&mut self, user_data: TSrc) -> SourceId {
+        SourceId(self.sources.insert(Source {
+            user_data,
+            reserved: false,
+            in_flight: 0,
+            total_assigned: 0,
+            total_succeeded: 0,
+            total_failed: 0,
+            latency_estimate_us: None,
+            success_rate_permille: 1000,
+            last_assigned: 0,
+        }))
+    }
+
+    /// Removes a source from the scheduler. Requests in progress with this source are silently
+    /// forgotten.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is out of range.
+    ///
+    pub fn remove_source(&mut self, source_id: SourceId) -> TSrc {
+        self.sources.remove(source_id.0).user_data
+    }
+
+    /// Returns the user data associated to the given source.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is out of range.
+    ///
+    pub fn source_user_data(&self, source_id: SourceId) -> &TSrc {
+        &self.sources[source_id.0].user_data
+    }
+
+    /// Returns the user data associated to the given source.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is out of range.
+    ///
+    pub fn source_user_data_mut(&mut self, source_id: SourceId) -> &mut TSrc {
+        &mut self.sources[source_id.0].user_data
+    }
+
+    /// Marks the given source as reserved or not. Requests are preferably assigned to reserved
+    /// sources. See [the module-level documentation](self).
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is out of range.
+    ///
+    pub fn set_source_reserved(&mut self, source_id: SourceId, reserved: bool) {
+        self.sources[source_id.0].reserved = reserved;
+    }
+
+    /// Picks the source the next request should be sent to, and marks a request as in progress
+    /// with this source.
+    ///
+    /// Returns `None` if there is no source or if all sources have reached
+    /// [`Config::max_in_flight_per_source`].
+    ///
+    /// [`RequestsScheduler::request_finished`] must later be called with the returned source.
+    pub fn assign_request(&mut self) -> Option<SourceId> {
+        let max_in_flight = self.max_in_flight_per_source.get();
+        let initial_latency_estimate_us = self.initial_latency_estimate_us;
+
+        // Since `false` is lower than `true`, the preferred sources come first.
+        let (source_id, _) = self
+            .sources
+            .iter()
+            .filter(|(_, source)| source.in_flight < max_in_flight)
+            .min_by_key(|(_, source)| {
+                (
+                    !source.is_preferred(),
+                    source.expected_cost(initial_latency_estimate_us),
+                    source.last_assigned,
+                )
+            })?;
+
+        let source = &mut self.sources[source_id];
+        source.in_flight += 1;
+        source.total_assigned += 1;
+        source.last_assigned = self.next_assignment_index;
+        self.next_assignment_index += 1;
+        Some(SourceId(source_id))
+    }
+
+    /// Reports that a request previously assigned with [`RequestsScheduler::assign_request`]
+    /// has finished, and updates the statistics of the source.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is out of range.
+    /// Panics if there is no request in progress with this source.
+    ///
+    pub fn request_finished(&mut self, source_id: SourceId, outcome: RequestOutcome) {
+        let source = &mut self.sources[source_id.0];
+        assert!(source.in_flight > 0);
+        source.in_flight -= 1;
+
+        match outcome {
+            RequestOutcome::Success { latency } => {
+                let latency_us = duration_to_us(latency);
+                source.total_succeeded += 1;
+                source.latency_estimate_us = Some(match source.latency_estimate_us {
+                    Some(previous) => moving_average(previous, latency_us),
+                    None => latency_us,
+                });
+                source.success_rate_permille = u32::try_from(moving_average(
+                    u64::from(source.success_rate_permille),
+                    1000,
+                ))
+                .unwrap();
+            }
+            RequestOutcome::Failure => {
+                source.total_failed += 1;
+                source.success_rate_permille =
+                    u32::try_from(moving_average(u64::from(source.success_rate_permille), 0))
+                        .unwrap();
+            }
+        }
+    }
+
+    /// Returns the number of requests currently in progress with the given source.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is out of range.
+    ///
+    pub fn source_num_in_flight(&self, source_id: SourceId) -> u32 {
+        self.sources[source_id.0].in_flight
+    }
+
+    /// Returns the current statistics of all the sources, in no particular order.
+    ///
+    /// This can be used in order to observe how requests are distributed.
+    pub fn distribution(&'_ self) -> impl Iterator<Item = (SourceId, SourceStats)> + '_ {
+        self.sources.iter().map(move |(id, source)| {
+            (
+                SourceId(id),
+                SourceStats {
+                    reserved: source.reserved,
+                    in_flight: source.in_flight,
+                    total_assigned: source.total_assigned,
+                    total_succeeded: source.total_succeeded,
+                    total_failed: source.total_failed,
+                    latency_estimate: Duration::from_micros(
+                        source
+                            .latency_estimate_us
+                            .unwrap_or(self.initial_latency_estimate_us),
+                    ),
+                    success_rate_permille: source.success_rate_permille,
+                },
+            )
+        })
+    }
+}
+
+impl<TSrc> Source<TSrc> {
+    /// Returns `true` if this source must be picked before the non-preferred ones.
+    fn is_preferred(&self) -> bool {
+        self.reserved && self.success_rate_permille >= RESERVED_MIN_SUCCESS_RATE_PERMILLE
+    }
+
+    /// Returns the expected cost of assigning one more request to this source. Lower is better.
+    fn expected_cost(&self, initial_latency_estimate_us: u64) -> u128 {
+        let latency_us = u128::from(
+            self.latency_estimate_us
+                .unwrap_or(initial_latency_estimate_us),
+        );
+        // The success rate is clamped to avoid dividing by zero. A source whose requests all
+        // fail thus remains usable as a last resort.
+        let success_rate = u128::from(cmp::max(self.success_rate_permille, 1));
+        latency_us * 1000 / success_rate * u128::from(self.in_flight + 1)
+    }
+}
+
+/// Exponential moving average giving a weight of one eighth to the new sample.
+fn moving_average(previous: u64, sample: u64) -> u64 {
+    ((u128::from(previous) * 7 + u128::from(sample)) / 8) as u64
+}
+
+fn duration_to_us(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, RequestOutcome, RequestsScheduler};
+    use core::{num::NonZeroU32, time::Duration};
+
+    #[test]
+    fn single_source_gets_all_requests_up_to_cap() {
+        let mut scheduler = RequestsScheduler::new(Config {
+            sources_capacity: 1,
+            max_in_flight_per_source: NonZeroU32::new(4).unwrap(),
+            initial_latency_estimate: Duration::from_millis(100),
+        });
+
+        let source = scheduler.add_source(());
+        for _ in 0..4 {
+            assert_eq!(scheduler.assign_request(), Some(source));
+        }
+        assert_eq!(scheduler.assign_request(), None);
+        assert_eq!(scheduler.source_num_in_flight(source), 4);
+
+        scheduler.request_finished(source, RequestOutcome::Failure);
+        assert_eq!(scheduler.assign_request(), Some(source));
+        assert_eq!(scheduler.assign_request(), None);
+    }
+
+    #[test]
+    fn faster_sources_get_more_requests() {
+        const MAX_TOTAL_IN_FLIGHT: usize = 12;
+
+        let mut scheduler = RequestsScheduler::new(Config {
+            sources_capacity: 3,
+            max_in_flight_per_source: NonZeroU32::new(8).unwrap(),
+            initial_latency_estimate: Duration::from_millis(100),
+        });
+
+        // The user data of each source is its actual latency, in milliseconds.
+        let fast = scheduler.add_source(10);
+        let medium = scheduler.add_source(20);
+        let slow = scheduler.add_source(40);
+
+        // Simulate two seconds of syncing. Each entry is `(finish time in ms, source)`.
+        let mut now = 0;
+        let mut in_progress = Vec::new();
+        while now < 2000 {
+            while in_progress.len() < MAX_TOTAL_IN_FLIGHT {
+                let source = scheduler.assign_request().unwrap();
+                in_progress.push((now + *scheduler.source_user_data(source), source));
+            }
+
+            let (pos, _) = in_progress
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (finish, _))| *finish)
+                .unwrap();
+            let (finish, source) = in_progress.remove(pos);
+            now = finish;
+            scheduler.request_finished(
+                source,
+                RequestOutcome::Success {
+                    latency: Duration::from_millis(*scheduler.source_user_data(source)),
+                },
+            );
+        }
+
+        let stats = |id| scheduler.distribution().find(|(s, _)| *s == id).unwrap().1;
+
+        let (fast, medium, slow) = (stats(fast), stats(medium), stats(slow));
+        assert!(fast.total_succeeded > medium.total_succeeded);
+        assert!(medium.total_succeeded > slow.total_succeeded);
+        assert!(slow.total_succeeded > 0);
+        assert!(fast.in_flight <= 8);
+        assert_eq!(fast.latency_estimate, Duration::from_millis(10));
+        assert_eq!(slow.latency_estimate, Duration::from_millis(40));
+        assert_eq!(
+            usize::try_from(fast.in_flight + medium.in_flight + slow.in_flight).unwrap(),
+            MAX_TOTAL_IN_FLIGHT - 1
+        );
+    }
+
+    #[test]
+    fn reserved_sources_first() {
+        let mut scheduler = RequestsScheduler::new(Config {
+            sources_capacity: 2,
+            max_in_flight_per_source: NonZeroU32::new(2).unwrap(),
+            initial_latency_estimate: Duration::from_millis(100),
+        });
+
+        let general = scheduler.add_source(());
+        let reserved = scheduler.add_source(());
+        scheduler.set_source_reserved(reserved, true);
+
+        // The general source is only picked once the reserved source is saturated.
+        assert_eq!(scheduler.assign_request(), Some(reserved));
+        assert_eq!(scheduler.assign_request(), Some(reserved));
+        assert_eq!(scheduler.assign_request(), Some(general));
+        assert_eq!(scheduler.assign_request(), Some(general));
+        assert_eq!(scheduler.assign_request(), None);
+
+        // The reserved source is still preferred, even though it is much slower.
+        for (source, latency) in [(general, 1), (general, 1), (reserved, 500), (reserved, 500)] {
+            scheduler.request_finished(
+                source,
+                RequestOutcome::Success {
+                    latency: Duration::from_millis(latency),
+                },
+            );
+        }
+
+        // The reserved source loses its priority once its success rate drops.
+        for _ in 0..6 {
+            assert_eq!(scheduler.assign_request(), Some(reserved));
+            scheduler.request_finished(reserved, RequestOutcome::Failure);
+        }
+        assert_eq!(scheduler.assign_request(), Some(general));
+
+        let stats = scheduler
+            .distribution()
+            .find(|(s, _)| *s == reserved)
+            .unwrap()
+            .1;
+        assert!(stats.reserved);
+        assert!(stats.success_rate_permille < super::RESERVED_MIN_SUCCESS_RATE_PERMILLE);
+    }
+}
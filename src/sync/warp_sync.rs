@@ -53,6 +53,17 @@
 //!
 //! At the end of the process, a [`Success`] is returned and can be used to kick-off another
 //! syncing phase.
+//!
+//! # Target selection
+//!
+//! The warp sync proof downloaded from a source leads to the latest block that this source
+//! considers as finalized. In order to not blindly trust a single source, a
+//! [`Config::target_quorum`] can be provided. The finalized blocks advertised by the sources
+//! must then be reported through [`InProgressWarpSync::set_source_finalized`], and are
+//! collected in a [`TargetSelection`]. The block returned by
+//! [`VirtualMachineParamsGet::warp_sync_header`] is only accepted by
+//! [`VirtualMachineParamsGet::set_virtual_machine_params`] if it matches the one returned by
+//! [`TargetSelection::target`].
 
 use crate::{
     chain::chain_information::{
@@ -71,9 +82,13 @@ use crate::{
 };
 
 use alloc::vec::Vec;
-use core::ops;
+use core::{num::NonZeroUsize, ops};
+use rand::{Rng as _, SeedableRng as _};
+
+pub use target::{TargetSelection, TargetSelectionError};
+pub use warp_sync::{Error as FragmentError, FragmentSignaturesVerification, VerifiedFragment};
 
-pub use warp_sync::Error as FragmentError;
+mod target;
 
 /// Problem encountered during a call to [`warp_sync()`].
 #[derive(Debug, derive_more::Display)]
@@ -89,6 +104,26 @@ pub enum Error {
     /// Parameters produced by the runtime are incoherent.
     #[display(fmt = "{}", _0)]
     InvalidChain(chain_information::ValidityError),
+    /// No block to warp sync to can be designated from the finalized blocks advertised by the
+    /// sources.
+    #[display(fmt = "{}", _0)]
+    TargetSelection(TargetSelectionError),
+    /// The block that has been warp synced to isn't the one designated by the sources.
+    #[display(
+        fmt = "Warp synced to block #{} while the sources designate block #{}",
+        number,
+        target_number
+    )]
+    TargetMismatch {
+        /// Number of the block that has been warp synced to.
+        number: u64,
+        /// Hash of the block that has been warp synced to.
+        hash: [u8; 32],
+        /// Number of the block designated by the sources.
+        target_number: u64,
+        /// Hash of the block designated by the sources.
+        target_hash: [u8; 32],
+    },
 }
 
 /// The configuration for [`warp_sync()`].
@@ -97,6 +132,14 @@ pub struct Config {
     pub start_chain_information: ValidChainInformation,
     /// The initial capacity of the list of sources.
     pub sources_capacity: usize,
+    /// Seed used by the randomness necessary to verify the signatures of the fragments. See
+    /// [`crate::finality::signatures::verify_ed25519`].
+    pub randomness_seed: [u8; 32],
+    /// If `Some`, number of sources that must advertise the block that is warp synced to. See
+    /// [the module-level documentation](..).
+    ///
+    /// If `None`, the block that the proof provided by a source leads to is trusted.
+    pub target_quorum: Option<NonZeroUsize>,
 }
 
 /// Initializes the warp sync state machine.
@@ -118,6 +161,8 @@ pub fn warp_sync<TSrc>(
     Ok(InProgressWarpSync::WaitingForSources(WaitingForSources {
         state: PreVerificationState {
             start_chain_information: config.start_chain_information,
+            randomness: rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed),
+            target: config.target_quorum.map(TargetSelection::new),
         },
         sources: slab::Slab::with_capacity(config.sources_capacity),
         previous_verifier_values: None,
@@ -235,6 +280,8 @@ impl<TSrc> WarpSync<TSrc> {
                                             PreVerificationState {
                                                 start_chain_information: state
                                                     .start_chain_information,
+                                                randomness: state.randomness,
+                                                target: state.target,
                                             },
                                             None,
                                         ),
@@ -285,6 +332,8 @@ impl<TSrc> WarpSync<TSrc> {
                             state.sources,
                             PreVerificationState {
                                 start_chain_information: state.start_chain_information,
+                                randomness: state.randomness,
+                                target: state.target,
                             },
                             None,
                         )),
@@ -358,6 +407,51 @@ impl<TSrc> InProgressWarpSync<TSrc> {
         sources.iter().map(|(id, _)| SourceId(id))
     }
 
+    /// Sets the finalized block advertised by the given source. See
+    /// [the module-level documentation](..).
+    ///
+    /// Has no effect if [`Config::target_quorum`] was `None`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the source wasn't added to the list earlier.
+    ///
+    pub fn set_source_finalized(&mut self, source_id: SourceId, number: u64, hash: [u8; 32]) {
+        let target = match self {
+            Self::StorageGet(storage_get) => {
+                assert!(storage_get.state.sources.contains(source_id.0));
+                &mut storage_get.state.target
+            }
+            Self::NextKey(next_key) => {
+                assert!(next_key.state.sources.contains(source_id.0));
+                &mut next_key.state.target
+            }
+            Self::Verifier(verifier) => {
+                assert!(verifier.sources.contains(source_id.0));
+                &mut verifier.state.target
+            }
+            Self::WarpSyncRequest(warp_sync_request) => {
+                assert!(warp_sync_request.sources.contains(source_id.0));
+                &mut warp_sync_request.state.target
+            }
+            Self::VirtualMachineParamsGet(virtual_machine_params_get) => {
+                assert!(virtual_machine_params_get
+                    .state
+                    .sources
+                    .contains(source_id.0));
+                &mut virtual_machine_params_get.state.target
+            }
+            Self::WaitingForSources(waiting_for_sources) => {
+                assert!(waiting_for_sources.sources.contains(source_id.0));
+                &mut waiting_for_sources.state.target
+            }
+        };
+
+        if let Some(target) = target {
+            target.set_source_finalized(source_id, number, hash);
+        }
+    }
+
     fn warp_sync_request_from_next_source(
         mut sources: slab::Slab<Source<TSrc>>,
         state: PreVerificationState,
@@ -551,6 +645,8 @@ impl<TSrc> StorageGet<TSrc> {
             self.state.sources,
             PreVerificationState {
                 start_chain_information: self.state.start_chain_information,
+                randomness: self.state.randomness,
+                target: self.state.target,
             },
             None,
         )
@@ -641,6 +737,9 @@ impl<TSrc> Verifier<TSrc> {
     pub fn remove_source(mut self, to_remove: SourceId) -> (TSrc, InProgressWarpSync<TSrc>) {
         debug_assert!(self.sources.contains(to_remove.0));
         let removed = self.sources.remove(to_remove.0).user_data;
+        if let Some(target) = &mut self.state.target {
+            target.remove_source(to_remove);
+        }
 
         if to_remove == self.warp_sync_source_id {
             let next_state = InProgressWarpSync::warp_sync_request_from_next_source(
@@ -664,6 +763,22 @@ impl<TSrc> Verifier<TSrc> {
         )
     }
 
+    /// Returns the list of signature verifications of the fragments in queue. They can be
+    /// performed in parallel and in any order, after which their outcome must be passed to
+    /// [`Verifier::inject_verified_fragment`].
+    ///
+    /// Calling this function is optional. [`Verifier::next`] verifies the signatures itself if
+    /// no outcome has been injected.
+    pub fn fragments_signatures_verifications(&mut self) -> Vec<FragmentSignaturesVerification> {
+        self.verifier.fragments_signatures_verifications()
+    }
+
+    /// Injects the outcome of a verification returned by
+    /// [`Verifier::fragments_signatures_verifications`].
+    pub fn inject_verified_fragment(&mut self, verified: VerifiedFragment) {
+        self.verifier.inject_verified_fragment(verified)
+    }
+
     /// Verifies the next warp sync fragment in queue.
     pub fn next(self) -> (InProgressWarpSync<TSrc>, Result<(), FragmentError>) {
         match self.verifier.next() {
@@ -697,6 +812,8 @@ impl<TSrc> Verifier<TSrc> {
                         start_chain_information: self.state.start_chain_information,
                         sources: self.sources,
                         warp_sync_source_id: self.warp_sync_source_id,
+                        randomness: self.state.randomness,
+                        target: self.state.target,
                     },
                 }),
                 Ok(()),
@@ -718,6 +835,8 @@ impl<TSrc> Verifier<TSrc> {
                                 start_chain_information: self.state.start_chain_information,
                                 sources: self.sources,
                                 warp_sync_source_id: self.warp_sync_source_id,
+                                randomness: self.state.randomness,
+                                target: self.state.target,
                             },
                         }),
                         Ok(()),
@@ -748,6 +867,10 @@ impl<TSrc> Verifier<TSrc> {
 
 struct PreVerificationState {
     start_chain_information: ValidChainInformation,
+    /// Source of the randomness necessary to verify the signatures of the fragments.
+    randomness: rand_chacha::ChaCha20Rng,
+    /// `None` if [`Config::target_quorum`] was `None`.
+    target: Option<TargetSelection>,
 }
 
 struct PostVerificationState<TSrc> {
@@ -756,12 +879,18 @@ struct PostVerificationState<TSrc> {
     start_chain_information: ValidChainInformation,
     sources: slab::Slab<Source<TSrc>>,
     warp_sync_source_id: SourceId,
+    randomness: rand_chacha::ChaCha20Rng,
+    /// See [`PreVerificationState::target`].
+    target: Option<TargetSelection>,
 }
 
 impl<TSrc> PostVerificationState<TSrc> {
     fn remove_source(mut self, to_remove: SourceId) -> (TSrc, StateRemoveSourceResult<TSrc>) {
         debug_assert!(self.sources.contains(to_remove.0));
         let removed = self.sources.remove(to_remove.0).user_data;
+        if let Some(target) = &mut self.target {
+            target.remove_source(to_remove);
+        }
 
         if to_remove == self.warp_sync_source_id {
             (
@@ -771,6 +900,8 @@ impl<TSrc> PostVerificationState<TSrc> {
                         self.sources,
                         PreVerificationState {
                             start_chain_information: self.start_chain_information,
+                            randomness: self.randomness,
+                            target: self.target,
                         },
                         None,
                     ),
@@ -837,6 +968,9 @@ impl<TSrc> GrandpaWarpSyncRequest<TSrc> {
     pub fn remove_source(mut self, to_remove: SourceId) -> (TSrc, InProgressWarpSync<TSrc>) {
         debug_assert!(self.sources.contains(to_remove.0));
         let removed = self.sources.remove(to_remove.0).user_data;
+        if let Some(target) = &mut self.state.target {
+            target.remove_source(to_remove);
+        }
 
         if to_remove == self.source_id {
             let next_state = InProgressWarpSync::warp_sync_request_from_next_source(
@@ -864,16 +998,19 @@ impl<TSrc> GrandpaWarpSyncRequest<TSrc> {
             Some(response) => {
                 let final_set_of_fragments = response.is_finished;
 
+                let randomness_seed = self.state.randomness.sample(rand::distributions::Standard);
                 let verifier = match &self.previous_verifier_values {
                     Some((_, chain_information_finality)) => warp_sync::Verifier::new(
                         chain_information_finality.into(),
                         response.fragments,
                         final_set_of_fragments,
+                        randomness_seed,
                     ),
                     None => warp_sync::Verifier::new(
                         self.state.start_chain_information.as_ref().finality,
                         response.fragments,
                         final_set_of_fragments,
+                        randomness_seed,
                     ),
                 };
 
@@ -933,6 +1070,8 @@ impl<TSrc> VirtualMachineParamsGet<TSrc> {
             self.state.sources,
             PreVerificationState {
                 start_chain_information: self.state.start_chain_information,
+                randomness: self.state.randomness,
+                target: self.state.target,
             },
             None,
         )
@@ -940,6 +1079,12 @@ impl<TSrc> VirtualMachineParamsGet<TSrc> {
 
     /// Set the code and heappages from storage using the keys `:code` and `:heappages`
     /// respectively. Also allows setting an execution hint for the virtual machine.
+    ///
+    /// If [`Config::target_quorum`] is `Some`, the header returned by
+    /// [`VirtualMachineParamsGet::warp_sync_header`] is first compared with the one designated
+    /// by the sources. If no block can be designated, the state machine stays in this state and
+    /// [`Error::TargetSelection`] is returned. If the designated block is a different one, the
+    /// warp sync restarts from another source and [`Error::TargetMismatch`] is returned.
     pub fn set_virtual_machine_params(
         self,
         code: Option<impl AsRef<[u8]>>,
@@ -947,6 +1092,40 @@ impl<TSrc> VirtualMachineParamsGet<TSrc> {
         exec_hint: ExecHint,
         allow_unresolved_imports: bool,
     ) -> (WarpSync<TSrc>, Option<Error>) {
+        if let Some(target) = &self.state.target {
+            let (target_number, target_hash) = match target.target() {
+                Ok(t) => t,
+                Err(err) => {
+                    return (
+                        WarpSync::InProgress(InProgressWarpSync::VirtualMachineParamsGet(self)),
+                        Some(Error::TargetSelection(err)),
+                    )
+                }
+            };
+
+            let number = self.state.header.number;
+            let hash = self.state.header.hash();
+            if number != target_number || hash != target_hash {
+                return (
+                    WarpSync::InProgress(InProgressWarpSync::warp_sync_request_from_next_source(
+                        self.state.sources,
+                        PreVerificationState {
+                            start_chain_information: self.state.start_chain_information,
+                            randomness: self.state.randomness,
+                            target: self.state.target,
+                        },
+                        None,
+                    )),
+                    Some(Error::TargetMismatch {
+                        number,
+                        hash,
+                        target_number,
+                        target_hash,
+                    }),
+                );
+            }
+        }
+
         let code = match code {
             Some(code) => code.as_ref().to_vec(),
             None => {
@@ -955,6 +1134,8 @@ impl<TSrc> VirtualMachineParamsGet<TSrc> {
                         self.state.sources,
                         PreVerificationState {
                             start_chain_information: self.state.start_chain_information,
+                            randomness: self.state.randomness,
+                            target: self.state.target,
                         },
                         None,
                     )),
@@ -973,6 +1154,8 @@ impl<TSrc> VirtualMachineParamsGet<TSrc> {
                                 self.state.sources,
                                 PreVerificationState {
                                     start_chain_information: self.state.start_chain_information,
+                                    randomness: self.state.randomness,
+                                    target: self.state.target,
                                 },
                                 None,
                             ),
@@ -1012,6 +1195,8 @@ impl<TSrc> VirtualMachineParamsGet<TSrc> {
                     self.state.sources,
                     PreVerificationState {
                         start_chain_information: self.state.start_chain_information,
+                        randomness: self.state.randomness,
+                        target: self.state.target,
                     },
                     None,
                 )),
@@ -1054,6 +1239,9 @@ impl<TSrc> WaitingForSources<TSrc> {
     pub fn remove_source(mut self, to_remove: SourceId) -> (TSrc, InProgressWarpSync<TSrc>) {
         debug_assert!(self.sources.contains(to_remove.0));
         let removed = self.sources.remove(to_remove.0).user_data;
+        if let Some(target) = &mut self.state.target {
+            target.remove_source(to_remove);
+        }
         (removed, InProgressWarpSync::WaitingForSources(self))
     }
 }
@@ -1065,3 +1253,118 @@ struct Source<TSrc> {
     /// currently in a `WarpSyncRequest`.
     already_tried: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        warp_sync, Config, Error, InProgressWarpSync, TargetSelectionError,
+        VirtualMachineParamsGet, WarpSync,
+    };
+    use crate::{
+        chain::chain_information, chain_spec::ChainSpec, executor::vm::ExecHint,
+        network::protocol::GrandpaWarpSyncResponse,
+    };
+    use core::num::NonZeroUsize;
+
+    /// Submits an empty proof, which makes the warp sync target the start block.
+    fn submit_empty_proof(sync: InProgressWarpSync<()>) -> VirtualMachineParamsGet<()> {
+        let request = match sync {
+            InProgressWarpSync::WarpSyncRequest(request) => request,
+            _ => panic!(),
+        };
+
+        let verifier = match request.handle_response(Some(GrandpaWarpSyncResponse {
+            fragments: Vec::new(),
+            is_finished: true,
+        })) {
+            InProgressWarpSync::Verifier(verifier) => verifier,
+            _ => panic!(),
+        };
+
+        match verifier.next() {
+            (InProgressWarpSync::VirtualMachineParamsGet(params_get), Ok(())) => params_get,
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn warp_sync_header_checked_against_target() {
+        let spec =
+            ChainSpec::from_json_bytes(&include_bytes!("../chain_spec/example.json")[..]).unwrap();
+        let chain_information = chain_information::ValidChainInformation::try_from(
+            spec.as_chain_information().unwrap(),
+        )
+        .unwrap();
+        let genesis_hash = chain_information.as_ref().finalized_block_header.hash();
+
+        let mut request = match warp_sync::<()>(Config {
+            start_chain_information: chain_information,
+            sources_capacity: 16,
+            randomness_seed: [0; 32],
+            target_quorum: Some(NonZeroUsize::new(2).unwrap()),
+        }) {
+            Ok(InProgressWarpSync::WaitingForSources(waiting)) => waiting.add_source(()),
+            _ => panic!(),
+        };
+        let source1 = request.current_source().0;
+        let source2 = request.add_source(());
+
+        // No block is advertised by enough sources.
+        let params_get = submit_empty_proof(InProgressWarpSync::WarpSyncRequest(request));
+        let mut sync = match params_get.set_virtual_machine_params(
+            None::<&[u8]>,
+            None::<&[u8]>,
+            ExecHint::CompileAheadOfTime,
+            false,
+        ) {
+            (
+                WarpSync::InProgress(sync @ InProgressWarpSync::VirtualMachineParamsGet(_)),
+                Some(Error::TargetSelection(TargetSelectionError::NoQuorum { .. })),
+            ) => sync,
+            _ => panic!(),
+        };
+
+        // The sources agree on the block that has been warp synced to. The target check passes
+        // and the missing runtime code is reported instead.
+        sync.set_source_finalized(source1, 0, genesis_hash);
+        sync.set_source_finalized(source2, 0, genesis_hash);
+        let params_get = match sync {
+            InProgressWarpSync::VirtualMachineParamsGet(params_get) => params_get,
+            _ => panic!(),
+        };
+        let mut sync = match params_get.set_virtual_machine_params(
+            None::<&[u8]>,
+            None::<&[u8]>,
+            ExecHint::CompileAheadOfTime,
+            false,
+        ) {
+            (WarpSync::InProgress(sync), Some(Error::MissingCode)) => sync,
+            _ => panic!(),
+        };
+
+        // The sources designate a different block. The warp sync restarts.
+        sync.set_source_finalized(source1, 5, [1; 32]);
+        sync.set_source_finalized(source2, 5, [1; 32]);
+        let params_get = submit_empty_proof(sync);
+        match params_get.set_virtual_machine_params(
+            None::<&[u8]>,
+            None::<&[u8]>,
+            ExecHint::CompileAheadOfTime,
+            false,
+        ) {
+            (
+                WarpSync::InProgress(InProgressWarpSync::WarpSyncRequest(_)),
+                Some(Error::TargetMismatch {
+                    number: 0,
+                    hash,
+                    target_number: 5,
+                    target_hash,
+                }),
+            ) => {
+                assert_eq!(hash, genesis_hash);
+                assert_eq!(target_hash, [1; 32]);
+            }
+            _ => panic!(),
+        }
+    }
+}
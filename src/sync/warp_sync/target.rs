@@ -0,0 +1,185 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Selection of the block to warp sync to.
+//!
+//! Relying on the finalized block advertised by a single source makes it possible for this
+//! source, if malicious, to make the local node warp sync to a block of its choice. The
+//! [`TargetSelection`] collects the finalized blocks advertised by all the sources, and only
+//! designates a target once enough sources agree on it.
+
+use super::SourceId;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::num::NonZeroUsize;
+
+/// Collection of the finalized blocks advertised by the sources of a warp sync.
+#[derive(Debug, Clone)]
+pub struct TargetSelection {
+    /// Number of sources that must advertise the same block for this block to be a target.
+    quorum: NonZeroUsize,
+    /// For each source, the number and hash of the finalized block it advertises.
+    finalized: BTreeMap<SourceId, (u64, [u8; 32])>,
+}
+
+impl TargetSelection {
+    /// Initializes a new empty collection.
+    ///
+    /// `quorum` is the number of sources that must advertise the same finalized block before this
+    /// block can be used as a target. A value of 1 means that any source can be trusted.
+    pub fn new(quorum: NonZeroUsize) -> Self {
+        TargetSelection {
+            quorum,
+            finalized: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the finalized block advertised by the given source, replacing the one that it
+    /// previously advertised, if any.
+    pub fn set_source_finalized(&mut self, source_id: SourceId, number: u64, hash: [u8; 32]) {
+        self.finalized.insert(source_id, (number, hash));
+    }
+
+    /// Removes the given source. Has no effect if the source wasn't known.
+    pub fn remove_source(&mut self, source_id: SourceId) {
+        self.finalized.remove(&source_id);
+    }
+
+    /// Returns the number and hash of the block to warp sync to.
+    ///
+    /// The target is the block with the highest number that is advertised by at least
+    /// [`TargetSelection::new`]'s `quorum` sources. Blocks advertised by fewer sources, even with
+    /// a higher number, are ignored, as these sources might simply be ahead of the others.
+    ///
+    /// An error is returned if no block reaches the quorum, or if sources advertise different
+    /// finalized blocks at the height of the target, as only one block can be finalized at a
+    /// given height.
+    pub fn target(&self) -> Result<(u64, [u8; 32]), TargetSelectionError> {
+        // Number of sources advertising each block, ordered by block number.
+        let mut votes = BTreeMap::<(u64, [u8; 32]), usize>::new();
+        for block in self.finalized.values() {
+            *votes.entry(*block).or_insert(0) += 1;
+        }
+
+        let (&(number, hash), _) = votes
+            .iter()
+            .rev()
+            .find(|(_, num_sources)| **num_sources >= self.quorum.get())
+            .ok_or(TargetSelectionError::NoQuorum {
+                quorum: self.quorum,
+            })?;
+
+        let conflicting = votes
+            .range((number, [0; 32])..=(number, [0xff; 32]))
+            .map(|((_, h), _)| *h)
+            .collect::<Vec<_>>();
+        if conflicting.len() >= 2 {
+            return Err(TargetSelectionError::Disagreement {
+                number,
+                hashes: conflicting,
+            });
+        }
+
+        Ok((number, hash))
+    }
+}
+
+/// Error potentially returned by [`TargetSelection::target`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum TargetSelectionError {
+    /// Not enough sources advertise the same finalized block. More sources are required.
+    #[display(
+        fmt = "No finalized block is advertised by at least {} sources",
+        quorum
+    )]
+    NoQuorum {
+        /// Number of sources that must agree.
+        quorum: NonZeroUsize,
+    },
+    /// Sources advertise different finalized blocks at the same height.
+    #[display(
+        fmt = "Sources advertise {} different finalized blocks at height {}",
+        "hashes.len()",
+        number
+    )]
+    Disagreement {
+        /// Height of the conflicting blocks.
+        number: u64,
+        /// Hashes of the conflicting blocks, ordered by hash.
+        hashes: Vec<[u8; 32]>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::SourceId, TargetSelection, TargetSelectionError};
+    use core::num::NonZeroUsize;
+
+    #[test]
+    fn agreeing_sources() {
+        let mut selection = TargetSelection::new(NonZeroUsize::new(2).unwrap());
+        assert!(matches!(
+            selection.target(),
+            Err(TargetSelectionError::NoQuorum { .. })
+        ));
+
+        selection.set_source_finalized(SourceId(0), 100, [1; 32]);
+        assert!(matches!(
+            selection.target(),
+            Err(TargetSelectionError::NoQuorum { .. })
+        ));
+
+        // A single source advertising a higher block is ignored.
+        selection.set_source_finalized(SourceId(1), 100, [1; 32]);
+        selection.set_source_finalized(SourceId(2), 5000, [2; 32]);
+        assert_eq!(selection.target(), Ok((100, [1; 32])));
+
+        // Once enough sources have caught up, the target moves forward.
+        selection.set_source_finalized(SourceId(0), 105, [3; 32]);
+        assert!(matches!(
+            selection.target(),
+            Err(TargetSelectionError::NoQuorum { .. })
+        ));
+        selection.set_source_finalized(SourceId(1), 105, [3; 32]);
+        assert_eq!(selection.target(), Ok((105, [3; 32])));
+
+        selection.remove_source(SourceId(1));
+        assert!(matches!(
+            selection.target(),
+            Err(TargetSelectionError::NoQuorum { .. })
+        ));
+    }
+
+    #[test]
+    fn disagreeing_sources() {
+        let mut selection = TargetSelection::new(NonZeroUsize::new(2).unwrap());
+        selection.set_source_finalized(SourceId(0), 100, [1; 32]);
+        selection.set_source_finalized(SourceId(1), 100, [1; 32]);
+        selection.set_source_finalized(SourceId(2), 100, [2; 32]);
+        assert_eq!(
+            selection.target(),
+            Err(TargetSelectionError::Disagreement {
+                number: 100,
+                hashes: vec![[1; 32], [2; 32]],
+            })
+        );
+
+        // The disagreement is resolved once the faulty source is removed.
+        selection.remove_source(SourceId(2));
+        assert_eq!(selection.target(), Ok((100, [1; 32])));
+    }
+}
@@ -58,12 +58,26 @@
 //! validated. Validation should be performed using the [`validate`](../validate) module, and
 //! the result reported with [`LightPool::set_validation_result`].
 //!
+//! # Watching transactions
+//!
+//! Use [`LightPool::watch_transaction`] to start tracking the status of a transaction. Whenever
+//! the status of a watched transaction changes as a result of a call that modifies the pool
+//! (for example [`LightPool::set_best_block`] or [`LightPool::set_finalized_block`]), a
+//! [`TransactionStatus`] is queued and can be obtained with [`LightPool::next_status_update`].
+//!
+//! A watched transaction typically goes through [`TransactionStatus::Ready`], then
+//! [`TransactionStatus::InBlock`], then [`TransactionStatus::Finalized`]. If the block the
+//! transaction is included in is retracted because of a re-org, the status goes back to
+//! [`TransactionStatus::Ready`]. [`TransactionStatus::Finalized`],
+//! [`TransactionStatus::Invalid`] and [`TransactionStatus::Dropped`] are final: no other status
+//! update is generated for this transaction afterwards, and the transaction is no longer watched.
+//!
 
 use super::validate::ValidTransaction;
 use crate::chain::fork_tree;
 
 use alloc::{
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, VecDeque},
     vec::Vec,
 };
 use core::{fmt, iter};
@@ -150,6 +164,10 @@ pub struct LightPool<TTx, TBl, TErr> {
     /// minus height of the block that was passed as [`Config::finalized_block_hash`].
     /// Always a finalized block.
     blocks_tree_root_relative_height: u64,
+
+    /// Status updates of watched transactions that haven't been pulled yet with
+    /// [`LightPool::next_status_update`].
+    status_updates: VecDeque<(TransactionId, TransactionStatus<TErr>)>,
 }
 
 impl<TTx, TBl, TErr> LightPool<TTx, TBl, TErr>
@@ -174,6 +192,7 @@ where
             finalized_block_index: None,
             blocks_tree_root_hash: config.finalized_block_hash,
             blocks_tree_root_relative_height: 0,
+            status_updates: VecDeque::new(),
         }
     }
 
@@ -193,6 +212,7 @@ where
             user_data,
             finalized_chain_validation: None,
             best_chain_validation: None,
+            watched: None,
         }));
 
         let _was_inserted = self.by_hash.insert((hash, tx_id));
@@ -211,6 +231,11 @@ where
     pub fn remove_transaction(&mut self, id: TransactionId) -> (Vec<u8>, TTx) {
         let tx = self.transactions.remove(id.0); // Panics if `id` is invalid.
 
+        if tx.watched.is_some() {
+            self.status_updates
+                .push_back((id, TransactionStatus::Dropped));
+        }
+
         let blocks_included = self
             .included_transactions
             .range((id, [0; 32])..=(id, [0xff; 32]))
@@ -492,6 +517,8 @@ where
             .insert((id, *block_hash_validated_against), result);
         self.transactions_by_validation
             .insert((*block_hash_validated_against, id));
+
+        self.update_watched_status(id);
     }
 
     /// Adds a block to the collection of blocks.
@@ -635,6 +662,7 @@ where
         }
 
         self.best_block_index = new_best_block_index;
+        self.update_watched_statuses();
 
         SetBestBlock {
             retracted_transactions,
@@ -780,6 +808,7 @@ where
             }
         }
 
+        self.update_watched_statuses();
         included_transactions.into_iter()
     }
 
@@ -945,6 +974,7 @@ where
             ));
         }
 
+        self.update_watched_statuses();
         out.into_iter()
     }
 
@@ -1015,6 +1045,18 @@ where
                 // `remove_transaction` does.
                 let tx = self.transactions.remove(tx_id.0);
 
+                // The status of watched transactions is normally updated when the block is
+                // finalized, but the body of the block might have been known only afterwards.
+                if tx.watched.is_some() {
+                    self.status_updates.push_back((
+                        *tx_id,
+                        TransactionStatus::Finalized {
+                            block_hash: pruned.user_data.hash,
+                            index: *index_in_block,
+                        },
+                    ));
+                }
+
                 let blocks_included = self
                     .included_transactions
                     .range((*tx_id, [0; 32])..=(*tx_id, [0xff; 32]))
@@ -1094,6 +1136,129 @@ where
         either::Left(return_value.into_iter())
     }
 
+    /// Starts watching the status of the given transaction.
+    ///
+    /// The current status of the transaction, if any, is immediately queued and can be obtained
+    /// with [`LightPool::next_status_update`]. Has no effect if the transaction is already
+    /// watched.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the transaction with the given id is invalid.
+    ///
+    pub fn watch_transaction(&mut self, id: TransactionId) {
+        let tx = &mut self.transactions[id.0];
+        if tx.watched.is_some() {
+            return;
+        }
+
+        tx.watched = Some(None);
+        self.update_watched_status(id);
+    }
+
+    /// Stops watching the status of the given transaction. Status updates of that transaction
+    /// that have already been queued are still returned by [`LightPool::next_status_update`].
+    ///
+    /// Has no effect if the transaction isn't watched.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the transaction with the given id is invalid.
+    ///
+    pub fn unwatch_transaction(&mut self, id: TransactionId) {
+        self.transactions[id.0].watched = None;
+    }
+
+    /// Returns `true` if the given transaction is being watched.
+    ///
+    /// Transactions stop being watched after a final status has been generated.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the transaction with the given id is invalid.
+    ///
+    pub fn is_watched(&self, id: TransactionId) -> bool {
+        self.transactions[id.0].watched.is_some()
+    }
+
+    /// Pulls the oldest status update of a watched transaction that hasn't been pulled yet.
+    ///
+    /// > **Note**: Since [`TransactionId`]s can be re-used, the identifier returned alongside
+    /// >           with [`TransactionStatus::Dropped`] or [`TransactionStatus::Finalized`] might
+    /// >           already be assigned to a different transaction.
+    pub fn next_status_update(&mut self) -> Option<(TransactionId, TransactionStatus<TErr>)> {
+        self.status_updates.pop_front()
+    }
+
+    /// Updates the status of all the watched transactions.
+    fn update_watched_statuses(&mut self) {
+        // TODO: don't allocate a Vec here
+        let watched = self
+            .transactions
+            .iter()
+            .filter(|(_, tx)| tx.watched.is_some())
+            .map(|(id, _)| TransactionId(id))
+            .collect::<Vec<_>>();
+
+        for id in watched {
+            self.update_watched_status(id);
+        }
+    }
+
+    /// Compares the current status of the given transaction with the status that was last
+    /// reported, and queues a status update if they differ. Does nothing if the transaction
+    /// isn't watched.
+    fn update_watched_status(&mut self, id: TransactionId) {
+        let last_reported = match &self.transactions[id.0].watched {
+            Some(last_reported) => *last_reported,
+            None => return,
+        };
+
+        // Find the block of the best chain the transaction is included in, if any.
+        let included = self
+            .included_transactions
+            .range((id, [0; 32])..=(id, [0xff; 32]))
+            .map(|(_, block_hash)| (*block_hash, *self.blocks_by_id.get(block_hash).unwrap()))
+            .find(|(_, block_index)| {
+                matches!(self.best_block_index, Some(best_idx)
+                    if self.blocks_tree.is_ancestor(*block_index, best_idx))
+            });
+
+        let status = if let Some((block_hash, block_index)) = included {
+            let index = *self
+                .transactions_by_inclusion
+                .get(&(block_hash, id))
+                .unwrap();
+            let is_finalized = matches!(self.finalized_block_index, Some(finalized_idx)
+                if self.blocks_tree.is_ancestor(block_index, finalized_idx));
+            if is_finalized {
+                TransactionStatus::Finalized { block_hash, index }
+            } else {
+                TransactionStatus::InBlock { block_hash, index }
+            }
+        } else {
+            match &self.transactions[id.0].best_chain_validation {
+                Some(Err(err)) => TransactionStatus::Invalid(err.clone()),
+                Some(Ok(_)) => TransactionStatus::Ready,
+                // A transaction that has been retracted from the best chain but isn't validated
+                // yet is reported as ready, as it is waiting to be included again.
+                None if last_reported.is_some() => TransactionStatus::Ready,
+                None => return,
+            }
+        };
+
+        let status_kind = StatusKind::from(&status);
+        if last_reported == Some(status_kind) {
+            return;
+        }
+
+        self.transactions[id.0].watched = match status_kind {
+            StatusKind::Finalized | StatusKind::Invalid | StatusKind::Dropped => None,
+            _ => Some(Some(status_kind)),
+        };
+        self.status_updates.push_back((id, status));
+    }
+
     /// Returns the number of blocks between the oldest block stored in this data structure and
     /// the finalized block.
     pub fn oldest_block_finality_lag(&self) -> usize {
@@ -1154,6 +1319,60 @@ pub struct SetBestBlock {
     pub included_transactions: Vec<(TransactionId, [u8; 32], usize)>,
 }
 
+/// Status of a watched transaction. See [`LightPool::watch_transaction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionStatus<TErr> {
+    /// Transaction has been successfully validated and isn't included in any block of the best
+    /// chain. It is waiting to be included in a block.
+    Ready,
+
+    /// Transaction has been included in a block of the best chain.
+    InBlock {
+        /// Hash of the block the transaction is included in.
+        block_hash: [u8; 32],
+        /// Index of the transaction within the body of that block.
+        index: usize,
+    },
+
+    /// Transaction has been included in a finalized block. This is a final status.
+    Finalized {
+        /// Hash of the block the transaction is included in.
+        block_hash: [u8; 32],
+        /// Index of the transaction within the body of that block.
+        index: usize,
+    },
+
+    /// Validation of the transaction against the best block has failed. This is a final status.
+    Invalid(TErr),
+
+    /// Transaction has been removed from the pool using [`LightPool::remove_transaction`]. This
+    /// is a final status.
+    Dropped,
+}
+
+/// Same as [`TransactionStatus`], but without the associated data except for the block.
+/// Stored in [`Transaction::watched`] in order to detect changes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum StatusKind {
+    Ready,
+    InBlock([u8; 32]),
+    Finalized,
+    Invalid,
+    Dropped,
+}
+
+impl<'a, TErr> From<&'a TransactionStatus<TErr>> for StatusKind {
+    fn from(status: &'a TransactionStatus<TErr>) -> Self {
+        match status {
+            TransactionStatus::Ready => StatusKind::Ready,
+            TransactionStatus::InBlock { block_hash, .. } => StatusKind::InBlock(*block_hash),
+            TransactionStatus::Finalized { .. } => StatusKind::Finalized,
+            TransactionStatus::Invalid(_) => StatusKind::Invalid,
+            TransactionStatus::Dropped => StatusKind::Dropped,
+        }
+    }
+}
+
 /// Entry in [`LightPool::transactions`].
 struct Transaction<TTx, TErr> {
     /// Bytes corresponding to the SCALE-encoded transaction.
@@ -1169,6 +1388,10 @@ struct Transaction<TTx, TErr> {
     /// Cache of the validation status of the transaction against the highest block of the best
     /// chain that has one.
     best_chain_validation: Option<Result<Validation, TErr>>,
+
+    /// `None` if the transaction isn't watched. `Some(None)` if the transaction is watched but
+    /// no status has been reported yet. Otherwise, contains the last status that was reported.
+    watched: Option<Option<StatusKind>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -17,10 +17,10 @@
 
 #![cfg(test)]
 
-use core::num::NonZeroU64;
+use core::{iter, num::NonZeroU64};
 
 use super::super::validate;
-use super::{Config, LightPool};
+use super::{Config, LightPool, TransactionStatus};
 
 #[test]
 fn regular_path() {
@@ -310,3 +310,183 @@ fn longevity_works_finalized_base() {
 }
 
 // TODO: more tests
+
+#[test]
+fn watch_full_lifecycle() {
+    let mut pool = LightPool::<_, _, ()>::new(Config {
+        blocks_capacity: 16,
+        finalized_block_hash: [0; 32],
+        transactions_capacity: 16,
+    });
+
+    let tx_id = pool.add_unvalidated(vec![0], ());
+    pool.watch_transaction(tx_id);
+    assert!(pool.next_status_update().is_none());
+
+    pool.set_validation_result(
+        tx_id,
+        &[0; 32],
+        Ok(validate::ValidTransaction {
+            longevity: NonZeroU64::new(64).unwrap(),
+            priority: 1,
+            propagate: true,
+            provides: Vec::new(),
+            requires: Vec::new(),
+        }),
+    );
+    assert_eq!(
+        pool.next_status_update(),
+        Some((tx_id, TransactionStatus::Ready))
+    );
+
+    pool.add_block([1; 32], &[0; 32], ());
+    let _ = pool.set_best_block(&[1; 32]);
+    assert!(pool.next_status_update().is_none());
+    let _ = pool.set_block_body(&[1; 32], vec![vec![0]].into_iter());
+    assert_eq!(
+        pool.next_status_update(),
+        Some((
+            tx_id,
+            TransactionStatus::InBlock {
+                block_hash: [1; 32],
+                index: 0
+            }
+        ))
+    );
+
+    // Re-org towards a fork that doesn't include the transaction.
+    pool.add_block([2; 32], &[0; 32], ());
+    let _ = pool.set_block_body(&[2; 32], iter::empty::<Vec<u8>>());
+    let _ = pool.set_best_block(&[2; 32]);
+    assert_eq!(
+        pool.next_status_update(),
+        Some((tx_id, TransactionStatus::Ready))
+    );
+
+    pool.add_block([3; 32], &[2; 32], ());
+    let _ = pool.set_best_block(&[3; 32]);
+    let _ = pool.set_block_body(&[3; 32], vec![vec![5], vec![0]].into_iter());
+    assert_eq!(
+        pool.next_status_update(),
+        Some((
+            tx_id,
+            TransactionStatus::InBlock {
+                block_hash: [3; 32],
+                index: 1
+            }
+        ))
+    );
+    assert!(pool.next_status_update().is_none());
+
+    let _ = pool.set_finalized_block(&[3; 32]).count();
+    assert_eq!(
+        pool.next_status_update(),
+        Some((
+            tx_id,
+            TransactionStatus::Finalized {
+                block_hash: [3; 32],
+                index: 1
+            }
+        ))
+    );
+    assert!(!pool.is_watched(tx_id));
+
+    // The stream is complete after finalization.
+    let _ = pool.prune_finalized_with_body().count();
+    assert!(pool.next_status_update().is_none());
+}
+
+#[test]
+fn watch_invalid_and_dropped() {
+    let mut pool = LightPool::<_, (), u32>::new(Config {
+        blocks_capacity: 16,
+        finalized_block_hash: [0; 32],
+        transactions_capacity: 16,
+    });
+
+    let invalid_tx = pool.add_unvalidated(vec![0], ());
+    let dropped_tx = pool.add_unvalidated(vec![1], ());
+    pool.watch_transaction(invalid_tx);
+    pool.watch_transaction(dropped_tx);
+
+    pool.set_validation_result(invalid_tx, &[0; 32], Err(12));
+    assert_eq!(
+        pool.next_status_update(),
+        Some((invalid_tx, TransactionStatus::Invalid(12)))
+    );
+    assert!(!pool.is_watched(invalid_tx));
+
+    pool.remove_transaction(dropped_tx);
+    assert_eq!(
+        pool.next_status_update(),
+        Some((dropped_tx, TransactionStatus::Dropped))
+    );
+    assert!(pool.next_status_update().is_none());
+}
+
+#[test]
+fn watch_existing_transaction_transitions() {
+    let mut pool = LightPool::<_, _, ()>::new(Config {
+        blocks_capacity: 16,
+        finalized_block_hash: [0; 32],
+        transactions_capacity: 16,
+    });
+
+    let valid = || {
+        Ok(validate::ValidTransaction {
+            longevity: NonZeroU64::new(64).unwrap(),
+            priority: 1,
+            propagate: true,
+            provides: Vec::new(),
+            requires: Vec::new(),
+        })
+    };
+
+    // Transaction validated before anyone watches it.
+    let tx_id = pool.add_unvalidated(vec![0], ());
+    pool.set_validation_result(tx_id, &[0; 32], valid());
+    assert!(pool.next_status_update().is_none());
+
+    // Watching an existing transaction immediately reports its current status.
+    pool.watch_transaction(tx_id);
+    assert_eq!(
+        pool.next_status_update(),
+        Some((tx_id, TransactionStatus::Ready))
+    );
+    pool.watch_transaction(tx_id);
+    assert!(pool.next_status_update().is_none());
+
+    // Validating the transaction again against a new best block doesn't report anything.
+    pool.add_block([1; 32], &[0; 32], ());
+    let _ = pool.set_block_body(&[1; 32], iter::empty::<Vec<u8>>());
+    let _ = pool.set_best_block(&[1; 32]);
+    pool.set_validation_result(tx_id, &[1; 32], valid());
+    assert!(pool.next_status_update().is_none());
+
+    // Included, then retracted and not validated again yet.
+    pool.add_block([2; 32], &[1; 32], ());
+    let _ = pool.set_block_body(&[2; 32], vec![vec![0]].into_iter());
+    let _ = pool.set_best_block(&[2; 32]);
+    assert_eq!(
+        pool.next_status_update(),
+        Some((
+            tx_id,
+            TransactionStatus::InBlock {
+                block_hash: [2; 32],
+                index: 0
+            }
+        ))
+    );
+    pool.add_block([3; 32], &[1; 32], ());
+    let _ = pool.set_block_body(&[3; 32], iter::empty::<Vec<u8>>());
+    pool.add_block([4; 32], &[3; 32], ());
+    let _ = pool.set_block_body(&[4; 32], iter::empty::<Vec<u8>>());
+    let _ = pool.set_best_block(&[4; 32]);
+    assert_eq!(
+        pool.next_status_update(),
+        Some((tx_id, TransactionStatus::Ready))
+    );
+    pool.set_validation_result(tx_id, &[4; 32], valid());
+    assert!(pool.next_status_update().is_none());
+    assert!(pool.is_watched(tx_id));
+}
@@ -66,6 +66,9 @@
 //! validated. Validation should be performed using the [`validate`](../validate) module, and
 //! the result reported with [`Pool::set_validation_result`].
 //!
+//! Use [`Pool::revalidate_all`] after a runtime upgrade in order to validate again all the
+//! transactions that aren't included in any block, and remove the ones that are no longer valid.
+//!
 //! Use [`Pool::remove_included`] when a block has been finalized to remove from the pool the
 //! transactions that are present in the finalized block and below.
 //!
@@ -78,7 +81,7 @@
 //! - Sending transactions to other peers.
 //!
 
-// TODO: this code is completely untested
+// TODO: this code is mostly untested
 
 use super::validate::{TransactionValidityError, ValidTransaction};
 
@@ -86,6 +89,8 @@ use alloc::{collections::BTreeSet, vec::Vec};
 use core::fmt;
 use hashbrown::HashSet;
 
+mod tests;
+
 /// Identifier of a transaction stored within the [`Pool`].
 ///
 /// Identifiers can be re-used by the pool. In other words, a transaction id can compare equal to
@@ -114,6 +119,23 @@ pub struct Config {
     /// Non-finalized blocks should be added to the pool after initialization using
     /// [`Pool::append_block`].
     pub finalized_block_height: u64,
+
+    /// Maximum number of transactions that aren't included in any block that the pool can hold.
+    ///
+    /// When a transaction is added with [`Pool::add_unvalidated`] and the pool exceeds this
+    /// limit, other non-included transactions are evicted, starting with the ones with the lowest
+    /// priority and, between transactions of equal priority, the oldest ones.
+    ///
+    /// Transactions that have been included in a block don't count towards this limit.
+    pub max_transactions: usize,
+
+    /// Maximum number of blocks a transaction that isn't included in any block can stay in the
+    /// pool.
+    ///
+    /// The age of a transaction is the difference between the current best block height and the
+    /// best block height at the time when the transaction was added. Transactions whose age is
+    /// strictly superior to this value are evicted when [`Pool::add_unvalidated`] is called.
+    pub max_age: u64,
 }
 
 /// Data structure containing transactions. See the module-level documentation for more info.
@@ -135,6 +157,12 @@ pub struct Pool<TTx> {
 
     /// Height of the latest best block, as known from the pool.
     best_block_height: u64,
+
+    /// See [`Config::max_transactions`].
+    max_transactions: usize,
+
+    /// See [`Config::max_age`].
+    max_age: u64,
 }
 
 impl<TTx> Pool<TTx> {
@@ -146,6 +174,8 @@ impl<TTx> Pool<TTx> {
             by_hash: BTreeSet::new(),
             by_height: BTreeSet::new(),
             best_block_height: config.finalized_block_height,
+            max_transactions: config.max_transactions,
+            max_age: config.max_age,
         }
     }
 
@@ -160,8 +190,24 @@ impl<TTx> Pool<TTx> {
     }
 
     /// Inserts a new unvalidated transaction in the pool.
-    pub fn add_unvalidated(&mut self, scale_encoded: Vec<u8>, user_data: TTx) -> TransactionId {
-        self.add_unvalidated_inner(scale_encoded, None, user_data)
+    ///
+    /// After the insertion, transactions that aren't included in any block and that are either
+    /// older than [`Config::max_age`] or in excess of [`Config::max_transactions`] are removed
+    /// from the pool and returned in [`AddUnvalidated::evicted`]. See [`Config::max_transactions`]
+    /// for the order in which transactions are evicted.
+    ///
+    /// The newly-inserted transaction is never evicted by this function. Similarly, a validated
+    /// transaction is never evicted if one of the tags it provides is required by another
+    /// non-included transaction of higher priority. Because of this, the number of transactions
+    /// in the pool might still exceed [`Config::max_transactions`] after this function returns.
+    pub fn add_unvalidated(
+        &mut self,
+        scale_encoded: Vec<u8>,
+        user_data: TTx,
+    ) -> AddUnvalidated<TTx> {
+        let id = self.add_unvalidated_inner(scale_encoded, None, user_data);
+        let evicted = self.evict(id);
+        AddUnvalidated { id, evicted }
     }
 
     /// Inserts a new unvalidated transaction in the pool.
@@ -177,6 +223,7 @@ impl<TTx> Pool<TTx> {
             scale_encoded: scale_encoded.into(),
             validation: None,
             included_block_height,
+            inserted_block_height: self.best_block_height,
             user_data,
         }));
 
@@ -194,6 +241,77 @@ impl<TTx> Pool<TTx> {
         tx_id
     }
 
+    /// Removes from the pool the non-included transactions that are too old or in excess of
+    /// [`Config::max_transactions`]. The transaction `keep` is never removed.
+    ///
+    /// See [`Pool::add_unvalidated`].
+    fn evict(&mut self, keep: TransactionId) -> Vec<(TransactionId, TTx)> {
+        let mut evicted = Vec::new();
+
+        // Transactions that are candidates for eviction, ordered from the first to evict to the
+        // last to evict.
+        // Transactions that aren't validated or whose validation has failed are considered as
+        // having the lowest priority.
+        // TODO: O(n log n) every time a transaction is inserted :-/
+        let mut candidates = self
+            .transactions
+            .iter()
+            .filter(|(id, tx)| *id != keep.0 && tx.included_block_height.is_none())
+            .map(|(id, tx)| {
+                let priority = match &tx.validation {
+                    Some((_, Ok(valid))) => valid.priority,
+                    _ => 0,
+                };
+                (priority, tx.inserted_block_height, TransactionId(id))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+
+        // Number of transactions in the pool that aren't included in any block.
+        let mut num_non_included = self.transactions.len() - self.by_height.len();
+
+        for (_, inserted_block_height, tx_id) in candidates {
+            let too_old =
+                self.best_block_height.saturating_sub(inserted_block_height) > self.max_age;
+            if !too_old && num_non_included <= self.max_transactions {
+                continue;
+            }
+
+            if self.is_required_by_higher_priority(tx_id) {
+                continue;
+            }
+
+            let user_data = self.remove(tx_id);
+            num_non_included -= 1;
+            evicted.push((tx_id, user_data));
+        }
+
+        evicted
+    }
+
+    /// Returns `true` if one of the tags provided by the given transaction is required by a
+    /// non-included transaction of higher priority.
+    fn is_required_by_higher_priority(&self, id: TransactionId) -> bool {
+        let (priority, provides) = match &self.transactions[id.0].validation {
+            Some((_, Ok(valid))) => (valid.priority, &valid.provides),
+            _ => return false,
+        };
+
+        // TODO: O(n) :-/
+        self.transactions.iter().any(|(other_id, other)| {
+            if other_id == id.0 || other.included_block_height.is_some() {
+                return false;
+            }
+
+            match &other.validation {
+                Some((_, Ok(valid))) if valid.priority > priority => {
+                    valid.requires.iter().any(|tag| provides.contains(tag))
+                }
+                _ => false,
+            }
+        })
+    }
+
     /// Removes from the pool the transaction with the given identifier.
     ///
     /// # Panic
@@ -331,6 +449,18 @@ impl<TTx> Pool<TTx> {
         self.transactions.get(id.0)?.included_block_height
     }
 
+    /// Returns the outcome of the latest validation of the given transaction, and the height of
+    /// the block it has been validated against.
+    ///
+    /// Returns `None` if the identifier is invalid or the transaction isn't validated.
+    pub fn validation(
+        &self,
+        id: TransactionId,
+    ) -> Option<(u64, &Result<ValidTransaction, TransactionValidityError>)> {
+        let (block_height, result) = self.transactions.get(id.0)?.validation.as_ref()?;
+        Some((*block_height, result))
+    }
+
     /// Returns the bytes associated with a given transaction.
     ///
     /// Returns `None` if the identifier is invalid.
@@ -438,7 +568,7 @@ impl<TTx> Pool<TTx> {
 
         // Must cancel validation results against blocks that have been retracted.
         // TODO: this is O(n), do better
-        for (_, transaction) in &mut self.transactions {
+        for (tx_id, transaction) in &mut self.transactions {
             let best_block_height = self.best_block_height;
             if transaction
                 .validation
@@ -446,6 +576,7 @@ impl<TTx> Pool<TTx> {
                 .map_or(false, |(b, _)| *b > best_block_height)
             {
                 transaction.validation = None;
+                self.not_validated.insert(TransactionId(tx_id));
             }
         }
 
@@ -483,6 +614,54 @@ impl<TTx> Pool<TTx> {
         }
 
         tx.validation = Some((block_number_validated_against, result));
+        self.not_validated.remove(&id);
+    }
+
+    /// Validates again all the transactions that aren't included in any block against the
+    /// current best block, for example after the runtime has been upgraded.
+    ///
+    /// `validator` is called with the SCALE encoding and the user data of each of these
+    /// transactions, and must return the outcome of validating it against the current best
+    /// block. See [the `validate` module](../validate).
+    ///
+    /// Transactions whose validation fails are removed from the pool and returned, together with
+    /// the validation error. The validation of the other transactions, including their tags,
+    /// priority, and longevity, is replaced with the new one. Transactions that were waiting to
+    /// be validated are validated as well.
+    ///
+    /// Transactions included in a block are assumed to always succeed validation and are left
+    /// untouched.
+    pub fn revalidate_all(
+        &mut self,
+        mut validator: impl FnMut(&[u8], &TTx) -> Result<ValidTransaction, TransactionValidityError>,
+    ) -> Vec<(TransactionId, TTx, TransactionValidityError)> {
+        let best_block_height = self.best_block_height;
+
+        // TODO: O(n) :-/
+        let to_revalidate = self
+            .transactions
+            .iter()
+            .filter(|(_, tx)| tx.included_block_height.is_none())
+            .map(|(id, _)| TransactionId(id))
+            .collect::<Vec<_>>();
+
+        let mut dropped = Vec::new();
+
+        for tx_id in to_revalidate {
+            let tx = &self.transactions[tx_id.0];
+            match validator(&tx.scale_encoded, &tx.user_data) {
+                Ok(valid) => {
+                    self.transactions[tx_id.0].validation = Some((best_block_height, Ok(valid)));
+                    self.not_validated.remove(&tx_id);
+                }
+                Err(error) => {
+                    let user_data = self.remove(tx_id);
+                    dropped.push((tx_id, user_data, error));
+                }
+            }
+        }
+
+        dropped
     }
 }
 
@@ -498,6 +677,17 @@ impl<TTx: fmt::Debug> fmt::Debug for Pool<TTx> {
     }
 }
 
+/// Outcome of [`Pool::add_unvalidated`].
+#[derive(Debug)]
+pub struct AddUnvalidated<TTx> {
+    /// Identifier of the newly-inserted transaction.
+    pub id: TransactionId,
+
+    /// List of transactions that have been removed from the pool in order to respect
+    /// [`Config::max_transactions`] and [`Config::max_age`], and their user data.
+    pub evicted: Vec<(TransactionId, TTx)>,
+}
+
 /// Wraps around [`Pool`] while a new best block is being inserted. See [`Pool::append_block`].
 #[must_use]
 pub struct AppendBlock<TTx> {
@@ -634,6 +824,10 @@ struct Transaction<TTx> {
     /// If `Some`, the height of the block at which the transaction has been included.
     included_block_height: Option<u64>,
 
+    /// Height of the best block at the time when the transaction has been inserted in the pool.
+    /// Used to determine the age of the transaction.
+    inserted_block_height: u64,
+
     /// User data chosen by the user.
     user_data: TTx,
 }
@@ -0,0 +1,188 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use core::num::NonZeroU64;
+
+use super::super::validate::{InvalidTransaction, TransactionValidityError, ValidTransaction};
+use super::{Config, Pool};
+
+fn valid(priority: u64, provides: &[u8], requires: &[u8]) -> ValidTransaction {
+    ValidTransaction {
+        priority,
+        requires: requires.iter().map(|t| vec![*t]).collect(),
+        provides: provides.iter().map(|t| vec![*t]).collect(),
+        longevity: NonZeroU64::new(u64::MAX).unwrap(),
+        propagate: true,
+    }
+}
+
+#[test]
+fn evicts_lowest_priority_past_capacity() {
+    let mut pool = Pool::new(Config {
+        capacity: 16,
+        finalized_block_height: 0,
+        max_transactions: 3,
+        max_age: 100,
+    });
+
+    let tx1 = pool.add_unvalidated(vec![1], 1);
+    let tx2 = pool.add_unvalidated(vec![2], 2);
+    let tx3 = pool.add_unvalidated(vec![3], 3);
+    assert!(tx1.evicted.is_empty() && tx2.evicted.is_empty() && tx3.evicted.is_empty());
+
+    pool.set_validation_result(tx1.id, 0, Ok(valid(20, &[1], &[])));
+    pool.set_validation_result(tx2.id, 0, Ok(valid(10, &[2], &[])));
+    pool.set_validation_result(tx3.id, 0, Ok(valid(30, &[3], &[])));
+
+    let tx4 = pool.add_unvalidated(vec![4], 4);
+    assert_eq!(tx4.evicted, vec![(tx2.id, 2)]);
+    assert_eq!(pool.len(), 3);
+    assert!(pool.user_data(tx4.id).is_some());
+}
+
+#[test]
+fn evicts_oldest_between_equal_priorities() {
+    let mut pool = Pool::new(Config {
+        capacity: 16,
+        finalized_block_height: 0,
+        max_transactions: 2,
+        max_age: 100,
+    });
+
+    let tx1 = pool.add_unvalidated(vec![1], 1);
+    pool = pool.append_block().finish();
+    let tx2 = pool.add_unvalidated(vec![2], 2);
+
+    // Both transactions are unvalidated and thus have the same priority.
+    let tx3 = pool.add_unvalidated(vec![3], 3);
+    assert_eq!(tx3.evicted, vec![(tx1.id, 1)]);
+    assert!(pool.user_data(tx2.id).is_some());
+}
+
+#[test]
+fn dependency_of_higher_priority_not_evicted() {
+    let mut pool = Pool::new(Config {
+        capacity: 16,
+        finalized_block_height: 0,
+        max_transactions: 3,
+        max_age: 100,
+    });
+
+    let tx1 = pool.add_unvalidated(vec![1], 1);
+    let tx2 = pool.add_unvalidated(vec![2], 2);
+    let tx3 = pool.add_unvalidated(vec![3], 3);
+
+    // `tx1` has the lowest priority but provides a tag required by `tx3`.
+    pool.set_validation_result(tx1.id, 0, Ok(valid(10, &[1], &[])));
+    pool.set_validation_result(tx2.id, 0, Ok(valid(20, &[2], &[])));
+    pool.set_validation_result(tx3.id, 0, Ok(valid(30, &[3], &[1])));
+
+    let tx4 = pool.add_unvalidated(vec![4], 4);
+    assert_eq!(tx4.evicted, vec![(tx2.id, 2)]);
+    assert!(pool.user_data(tx1.id).is_some());
+}
+
+#[test]
+fn evicts_too_old() {
+    let mut pool = Pool::new(Config {
+        capacity: 16,
+        finalized_block_height: 0,
+        max_transactions: 16,
+        max_age: 2,
+    });
+
+    let tx1 = pool.add_unvalidated(vec![1], 1);
+    pool = pool.append_block().finish();
+    let tx2 = pool.add_unvalidated(vec![2], 2);
+    pool = pool.append_block().finish();
+    pool = pool.append_block().finish();
+
+    let tx3 = pool.add_unvalidated(vec![3], 3);
+    assert_eq!(tx3.evicted, vec![(tx1.id, 1)]);
+    assert!(pool.user_data(tx2.id).is_some());
+    assert_eq!(pool.len(), 2);
+}
+
+#[test]
+fn revalidate_all_drops_invalid() {
+    let mut pool = Pool::<u8>::new(Config {
+        capacity: 16,
+        finalized_block_height: 0,
+        max_transactions: 16,
+        max_age: 100,
+    });
+
+    let tx1 = pool.add_unvalidated(vec![1], 1);
+    let tx2 = pool.add_unvalidated(vec![2], 2);
+    let tx3 = pool.add_unvalidated(vec![3], 3);
+    pool.set_validation_result(tx1.id, 0, Ok(valid(10, &[1], &[])));
+    pool.set_validation_result(tx2.id, 0, Ok(valid(10, &[2], &[])));
+
+    // `tx4` is included in a block and is thus never re-validated.
+    let mut block = pool.append_block();
+    let tx4 = match block.block_transaction(&[4]) {
+        super::AppendBlockTransaction::Unknown(vacant) => vacant.insert(4),
+        _ => unreachable!(),
+    };
+    let mut pool = block.finish();
+
+    // After a runtime upgrade, `tx1` is no longer valid and `tx2` has a different priority and
+    // different tags.
+    let mut num_validations = 0;
+    let dropped = pool.revalidate_all(|scale_encoded, user_data| {
+        num_validations += 1;
+        assert_eq!(scale_encoded, [*user_data]);
+        match *user_data {
+            1 => Err(TransactionValidityError::Invalid(
+                InvalidTransaction::BadProof,
+            )),
+            2 => Ok(valid(50, &[5], &[6])),
+            3 => Ok(valid(20, &[3], &[])),
+            _ => unreachable!(),
+        }
+    });
+    assert_eq!(num_validations, 3);
+
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].0, tx1.id);
+    assert_eq!(dropped[0].1, 1);
+    assert!(matches!(
+        dropped[0].2,
+        TransactionValidityError::Invalid(InvalidTransaction::BadProof)
+    ));
+
+    assert_eq!(pool.len(), 3);
+    assert!(pool.user_data(tx1.id).is_none());
+    assert_eq!(
+        pool.validation(tx2.id),
+        Some((1, &Ok(valid(50, &[5], &[6]))))
+    );
+    assert_eq!(
+        pool.validation(tx3.id),
+        Some((1, &Ok(valid(20, &[3], &[]))))
+    );
+    assert_eq!(pool.unvalidated_transactions().len(), 1);
+    assert_eq!(pool.unvalidated_transactions().next().unwrap().0, tx4);
+
+    // The slot of the dropped transaction can be reused.
+    let tx5 = pool.add_unvalidated(vec![5], 5);
+    assert_eq!(pool.unvalidated_transactions().len(), 2);
+    pool.set_validation_result(tx5.id, 1, Ok(valid(10, &[5], &[])));
+    assert_eq!(pool.unvalidated_transactions().len(), 1);
+}
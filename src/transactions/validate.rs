@@ -326,6 +326,7 @@ pub fn validate_transaction(
                 top_trie_root_calculation_cache: None,
                 storage_top_trie_changes: storage_diff::StorageDiff::empty(),
                 offchain_storage_changes: storage_diff::StorageDiff::empty(),
+                storage_read_cache: None,
             });
 
             // Information used later, after `Core_initialize_block` is done.
@@ -362,6 +363,7 @@ pub fn validate_transaction(
                 top_trie_root_calculation_cache: None,
                 storage_top_trie_changes: storage_diff::StorageDiff::empty(),
                 offchain_storage_changes: storage_diff::StorageDiff::empty(),
+                storage_read_cache: None,
             });
 
             match vm {
@@ -449,6 +451,7 @@ impl Query {
                     ),
                     storage_top_trie_changes: success.storage_top_trie_changes,
                     offchain_storage_changes: success.offchain_storage_changes,
+                    storage_read_cache: None,
                     top_trie_root_calculation_cache: Some(success.top_trie_root_calculation_cache),
                 });
 
@@ -768,7 +771,7 @@ fn unknown_transaction(bytes: &[u8]) -> nom::IResult<&[u8], UnknownTransaction>
 }
 
 fn tags(bytes: &[u8]) -> nom::IResult<&[u8], Vec<Vec<u8>>> {
-    nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
+    nom::combinator::flat_map(crate::util::nom_scale_collection_len(1), |num_elems| {
         nom::multi::many_m_n(
             num_elems,
             num_elems,
@@ -92,8 +92,12 @@ use core::{iter, mem};
 mod nibble;
 
 pub mod calculate_root;
+pub mod compact_proof;
 pub mod node_value;
+pub mod parallel_root;
 pub mod prefix_proof;
+pub mod proof_diff;
+pub mod proof_generate;
 pub mod proof_node_decode;
 pub mod proof_verify;
 pub mod trie_structure;
@@ -273,6 +277,62 @@ pub fn ordered_root(entries: &[impl AsRef<[u8]>]) -> [u8; 32] {
     }
 }
 
+/// Prefix of the keys of the top trie under which the roots of the default child tries are
+/// stored. The rest of the key is the identifier of the child trie.
+pub const DEFAULT_CHILD_STORAGE_PREFIX: &[u8] = b":child_storage:default:";
+
+/// Outcome of [`child_tries_roots`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChildTriesRoots<'a> {
+    /// For each child trie identifier, the Merkle value of the root of this child trie.
+    pub roots: BTreeMap<&'a [u8], [u8; 32]>,
+
+    /// List of keys of the top trie that start with [`DEFAULT_CHILD_STORAGE_PREFIX`] but that
+    /// have an empty child trie identifier or whose value isn't 32 bytes long. These keys are
+    /// ignored. The API user is encouraged to print a warning if this list isn't empty.
+    pub malformed: Vec<&'a [u8]>,
+}
+
+/// Finds, amongst the `(key, value)` entries of a top trie, the roots of all the default child
+/// tries.
+///
+/// Entries whose key doesn't start with [`DEFAULT_CHILD_STORAGE_PREFIX`] are ignored. In order
+/// to avoid iterating over the entire storage, it is encouraged to only pass the entries whose
+/// key starts with this prefix.
+pub fn child_tries_roots<'a>(
+    entries: impl Iterator<Item = (&'a [u8], &'a [u8])>,
+) -> ChildTriesRoots<'a> {
+    let mut roots = BTreeMap::new();
+    let mut malformed = Vec::new();
+
+    for (key, value) in entries {
+        let child_trie = match key.strip_prefix(DEFAULT_CHILD_STORAGE_PREFIX) {
+            Some(c) => c,
+            None => continue,
+        };
+
+        match <[u8; 32]>::try_from(value) {
+            Ok(root) if !child_trie.is_empty() => {
+                roots.insert(child_trie, root);
+            }
+            _ => malformed.push(key),
+        }
+    }
+
+    ChildTriesRoots { roots, malformed }
+}
+
+/// Decodes a SCALE-encoded list of node values, such as a proof generated by
+/// [`proof_generate::generate_read_proof`], into the list of node values.
+pub(crate) fn decode_proof(scale_encoded_proof: &[u8]) -> Result<Vec<&[u8]>, ()> {
+    nom::combinator::all_consuming(nom::combinator::flat_map(
+        util::nom_scale_collection_len(1),
+        |num_elems| nom::multi::many_m_n(num_elems, num_elems, util::nom_bytes_decode),
+    ))(scale_encoded_proof)
+    .map(|(_, proof)| proof)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| ())
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -281,4 +341,35 @@ mod tests {
         let expected = blake2_rfc::blake2b::blake2b(32, &[], &[0x0]);
         assert_eq!(obtained, expected.as_bytes());
     }
+
+    #[test]
+    fn child_tries_roots() {
+        let child1 = super::trie_root(&[(b"foo", b"bar")]);
+        let child2 = super::trie_root(&[
+            (&b"hello"[..], &b"world"[..]),
+            (&b"hello2"[..], &b"world2"[..]),
+        ]);
+
+        let entries = [
+            (&b":code"[..], &b"runtime"[..]),
+            (&b":child_storage:default:child1"[..], &child1[..]),
+            (&b":child_storage:default:child2"[..], &child2[..]),
+            // Value of the wrong length.
+            (&b":child_storage:default:child3"[..], &b"not a root"[..]),
+            // Empty child trie identifier.
+            (&b":child_storage:default:"[..], &child1[..]),
+        ];
+
+        let obtained = super::child_tries_roots(entries.iter().copied());
+        assert_eq!(obtained.roots.len(), 2);
+        assert_eq!(obtained.roots[&b"child1"[..]], child1);
+        assert_eq!(obtained.roots[&b"child2"[..]], child2);
+        assert_eq!(
+            obtained.malformed,
+            vec![
+                &b":child_storage:default:child3"[..],
+                &b":child_storage:default:"[..]
+            ]
+        );
+    }
 }
@@ -0,0 +1,300 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decoding of compact trie proofs.
+//!
+//! A compact trie proof is a variant of a trie proof (see [the `proof_verify`
+//! module](super::proof_verify)) where the node values are ordered and where the Merkle values
+//! of the children that can be recalculated from the rest of the proof are omitted.
+//!
+//! # Details
+//!
+//! The node values of a compact proof are ordered depth-first, starting from the root node, with
+//! the children of each node ordered by their child index. When a child of a node is also
+//! present in the proof, its Merkle value in the parent's node value is replaced with an empty
+//! value. The Merkle value of this child is recalculated when decoding the proof.
+//!
+//! Since the Merkle value of the root node is calculated while decoding, decoding a compact proof
+//! against a known trie root hash also verifies that the proof matches this trie root.
+//!
+//! Compact proofs are used in particular by Substrate in responses to state requests.
+
+use super::{node_value, proof_node_decode};
+
+use alloc::vec::Vec;
+
+/// Configuration to pass to [`decode_and_verify`].
+pub struct Config<'a, I> {
+    /// Merkle value (or node value) of the root node of the trie.
+    ///
+    /// > **Note**: The Merkle value and node value are always the same for the root node.
+    pub trie_root_hash: &'a [u8; 32],
+
+    /// List of node values of the compact proof, in order.
+    pub proof: I,
+}
+
+/// Decodes a compact proof and verifies that it matches the given trie root hash.
+///
+/// On success, returns the list of node values of the nodes of the proof, with the Merkle values
+/// of all the children filled. This list can then be passed to the functions of [the
+/// `proof_verify` module](super::proof_verify).
+///
+/// Elements of the proof that follow the root node are ignored. They typically belong to child
+/// tries.
+pub fn decode_and_verify<'a>(
+    config: Config<'_, impl Iterator<Item = &'a [u8]>>,
+) -> Result<Vec<Vec<u8>>, Error> {
+    let mut output = Vec::new();
+
+    // Nodes between the root (first element) and the node currently being decoded (last
+    // element) whose omitted children haven't all been decoded yet.
+    let mut stack: Vec<StackEntry<'a>> = Vec::new();
+
+    for compact_node_value in config.proof {
+        let decoded =
+            proof_node_decode::decode(compact_node_value).map_err(Error::InvalidNodeValue)?;
+
+        let mut children: [Option<node_value::Output>; 16] = Default::default();
+        let mut omitted_children = 0u16;
+        for (child_index, child) in decoded.children.iter().enumerate() {
+            match child {
+                Some([]) => omitted_children |= 1 << child_index,
+                Some(c) => children[child_index] = Some(node_value::Output::from_bytes(c)),
+                None => {}
+            }
+        }
+
+        stack.push(StackEntry {
+            decoded,
+            children,
+            omitted_children,
+        });
+
+        // Pop from the stack all the nodes whose children have now all been decoded, and fill
+        // their Merkle value in their parent.
+        while stack.last().map_or(false, |e| e.omitted_children == 0) {
+            let entry = stack.pop().unwrap();
+            let node_value = node_value::calculate_node_value(node_value::Config {
+                ty: node_value::NodeTy::NonRoot {
+                    partial_key: entry.decoded.partial_key,
+                },
+                children: entry.children.iter().map(|c| c.as_ref()),
+                stored_value: entry.decoded.storage_value,
+            });
+
+            let parent = match stack.last_mut() {
+                Some(p) => p,
+                None => {
+                    // This is the root node.
+                    if blake2_rfc::blake2b::blake2b(32, &[], &node_value).as_bytes()
+                        != &config.trie_root_hash[..]
+                    {
+                        return Err(Error::TrieRootMismatch);
+                    }
+                    output.push(node_value);
+                    return Ok(output);
+                }
+            };
+
+            let merkle_value = if node_value.len() >= 32 {
+                node_value::Output::from_bytes(
+                    blake2_rfc::blake2b::blake2b(32, &[], &node_value).as_bytes(),
+                )
+            } else {
+                node_value::Output::from_bytes(&node_value)
+            };
+
+            // Omitted children are filled in order of their child index.
+            let child_index = usize::try_from(parent.omitted_children.trailing_zeros()).unwrap();
+            parent.children[child_index] = Some(merkle_value);
+            parent.omitted_children &= !(1 << child_index);
+
+            output.push(node_value);
+        }
+    }
+
+    Err(Error::Incomplete)
+}
+
+struct StackEntry<'a> {
+    /// Decoded node value, as found in the compact proof.
+    decoded: proof_node_decode::Decoded<'a>,
+    /// Merkle values of the children of the node. `None` if there is no child at this index or
+    /// if the child has been omitted and hasn't been decoded yet.
+    children: [Option<node_value::Output>; 16],
+    /// Bitmap of the children whose Merkle value is omitted and hasn't been decoded yet.
+    omitted_children: u16,
+}
+
+/// Possible error returned by [`decode_and_verify`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum Error {
+    /// One of the node values in the proof has an invalid format.
+    #[display(fmt = "A node of the proof has an invalid format: {}", _0)]
+    InvalidNodeValue(proof_node_decode::Error),
+    /// The proof ends before the root node could be decoded.
+    Incomplete,
+    /// The root node decoded from the proof doesn't match the expected trie root hash.
+    TrieRootMismatch,
+}
+
+/// Turns a regular trie proof into a compact proof. Used to test the decoding.
+#[cfg(test)]
+pub(crate) fn encode(trie_root_hash: &[u8; 32], proof: &[impl AsRef<[u8]>]) -> Vec<Vec<u8>> {
+    use alloc::collections::BTreeMap;
+
+    fn encode_node(
+        node_value: &[u8],
+        entries_by_hash: &BTreeMap<[u8; 32], &[u8]>,
+        output: &mut Vec<Vec<u8>>,
+    ) {
+        let decoded = proof_node_decode::decode(node_value).unwrap();
+
+        let children = decoded
+            .children
+            .iter()
+            .map(|child| {
+                child.map(|c| match <[u8; 32]>::try_from(c) {
+                    Ok(hash) if entries_by_hash.contains_key(&hash) => {
+                        node_value::Output::from_bytes(&[])
+                    }
+                    _ => node_value::Output::from_bytes(c),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        output.push(node_value::calculate_node_value(node_value::Config {
+            ty: node_value::NodeTy::NonRoot {
+                partial_key: decoded.partial_key.clone(),
+            },
+            children: children.iter().map(|c| c.as_ref()),
+            stored_value: decoded.storage_value,
+        }));
+
+        for child in decoded.children.iter().flatten() {
+            if let Some(node_value) = <[u8; 32]>::try_from(*child)
+                .ok()
+                .and_then(|hash| entries_by_hash.get(&hash))
+            {
+                encode_node(node_value, entries_by_hash, output);
+            }
+        }
+    }
+
+    let entries_by_hash = proof
+        .iter()
+        .map(|node_value| {
+            let hash = <[u8; 32]>::try_from(
+                blake2_rfc::blake2b::blake2b(32, &[], node_value.as_ref()).as_bytes(),
+            )
+            .unwrap();
+            (hash, node_value.as_ref())
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mut output = Vec::new();
+    encode_node(
+        entries_by_hash.get(trie_root_hash).unwrap(),
+        &entries_by_hash,
+        &mut output,
+    );
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{decode_proof, proof_generate, proof_verify, trie_root};
+
+    #[test]
+    fn decode_matches_regular_proof() {
+        let storage = (0..40u8)
+            .map(|n| (vec![n * 5, n], vec![n; usize::from(n) * 2]))
+            .collect::<Vec<_>>();
+        let trie_root_hash = trie_root(&storage);
+
+        let keys = [&storage[3].0[..], &storage[17].0[..], &storage[18].0[..]];
+        let proof = proof_generate::generate_read_proof(proof_generate::Config {
+            keys: keys.iter(),
+            storage_entries: storage.iter().map(|(k, v)| (&k[..], &v[..])),
+            trie_root_hash: &trie_root_hash,
+        })
+        .unwrap();
+        let proof = decode_proof(&proof).unwrap();
+
+        let compact = super::encode(&trie_root_hash, &proof);
+        assert_eq!(compact.len(), proof.len());
+        assert!(
+            compact.iter().map(|n| n.len()).sum::<usize>() < proof.iter().map(|n| n.len()).sum()
+        );
+
+        let decoded = super::decode_and_verify(super::Config {
+            trie_root_hash: &trie_root_hash,
+            proof: compact.iter().map(|n| &n[..]),
+        })
+        .unwrap();
+
+        let mut decoded_sorted = decoded.clone();
+        decoded_sorted.sort();
+        let mut proof_sorted = proof.iter().map(|n| n.to_vec()).collect::<Vec<_>>();
+        proof_sorted.sort();
+        assert_eq!(decoded_sorted, proof_sorted);
+
+        for key in keys {
+            let value = proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+                requested_key: key,
+                trie_root_hash: &trie_root_hash,
+                proof: decoded.iter().map(|n| &n[..]),
+            })
+            .unwrap();
+            let expected = storage.iter().find(|(k, _)| k == key).map(|(_, v)| &v[..]);
+            assert_eq!(value, expected);
+        }
+    }
+
+    #[test]
+    fn wrong_root_or_truncated_refused() {
+        let storage = (0..40u8)
+            .map(|n| (vec![n * 5, n], vec![n; usize::from(n) * 2]))
+            .collect::<Vec<_>>();
+        let trie_root_hash = trie_root(&storage);
+
+        let proof = proof_generate::generate_read_proof(proof_generate::Config {
+            keys: [&storage[3].0[..]].iter(),
+            storage_entries: storage.iter().map(|(k, v)| (&k[..], &v[..])),
+            trie_root_hash: &trie_root_hash,
+        })
+        .unwrap();
+        let compact = super::encode(&trie_root_hash, &decode_proof(&proof).unwrap());
+
+        assert!(matches!(
+            super::decode_and_verify(super::Config {
+                trie_root_hash: &[0; 32],
+                proof: compact.iter().map(|n| &n[..]),
+            }),
+            Err(super::Error::TrieRootMismatch)
+        ));
+
+        assert!(matches!(
+            super::decode_and_verify(super::Config {
+                trie_root_hash: &trie_root_hash,
+                proof: compact[..compact.len() - 1].iter().map(|n| &n[..]),
+            }),
+            Err(super::Error::Incomplete)
+        ));
+    }
+}
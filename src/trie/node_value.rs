@@ -126,10 +126,6 @@ where
     TPKey: ExactSizeIterator<Item = Nibble>,
     TVal: AsRef<[u8]>,
 {
-    assert_eq!(config.children.len(), 16);
-
-    let has_children = config.children.clone().any(|c| c.is_some());
-
     // This value will be used as the sink for all the components of the merkle value.
     let mut merkle_value_sink = if matches!(config.ty, NodeTy::Root { .. }) {
         HashOrInline::Hasher(blake2_rfc::blake2b::Blake2b::new(32))
@@ -137,6 +133,45 @@ where
         HashOrInline::Inline(ArrayVec::new())
     };
 
+    encode_node_value(config, |data| merkle_value_sink.update(data));
+    merkle_value_sink.finalize()
+}
+
+/// Calculates the node value of a node given the information about this node.
+///
+/// Contrary to [`calculate_merkle_root`], the node value is never hashed. This is notably useful
+/// in order to build trie proofs, which consist in lists of node values.
+///
+/// # Panic
+///
+/// Panics if `config.children.len() != 16`.
+///
+pub fn calculate_node_value<'a, TChIter, TPKey, TVal>(
+    config: Config<TChIter, TPKey, TVal>,
+) -> Vec<u8>
+where
+    TChIter: ExactSizeIterator<Item = Option<&'a Output>> + Clone,
+    TPKey: ExactSizeIterator<Item = Nibble>,
+    TVal: AsRef<[u8]>,
+{
+    let mut node_value = Vec::new();
+    encode_node_value(config, |data| node_value.extend_from_slice(data));
+    node_value
+}
+
+/// Pushes to `merkle_value_sink` the components of the node value of the given node.
+fn encode_node_value<'a, TChIter, TPKey, TVal>(
+    config: Config<TChIter, TPKey, TVal>,
+    mut merkle_value_sink: impl FnMut(&[u8]),
+) where
+    TChIter: ExactSizeIterator<Item = Option<&'a Output>> + Clone,
+    TPKey: ExactSizeIterator<Item = Nibble>,
+    TVal: AsRef<[u8]>,
+{
+    assert_eq!(config.children.len(), 16);
+
+    let has_children = config.children.clone().any(|c| c.is_some());
+
     // For node value calculation purposes, the root key is treated the same as the partial key.
     let mut partial_key = match config.ty {
         NodeTy::Root { key } => key,
@@ -164,28 +199,28 @@ where
         let mut pk_len = partial_key.len();
         if pk_len >= 63 {
             pk_len -= 63;
-            merkle_value_sink.update(&[(two_msb << 6) + 63]);
+            merkle_value_sink(&[(two_msb << 6) + 63]);
             while pk_len > 255 {
                 pk_len -= 255;
-                merkle_value_sink.update(&[255]);
+                merkle_value_sink(&[255]);
             }
-            merkle_value_sink.update(&[u8::try_from(pk_len).unwrap()]);
+            merkle_value_sink(&[u8::try_from(pk_len).unwrap()]);
         } else {
-            merkle_value_sink.update(&[(two_msb << 6) + u8::try_from(pk_len).unwrap()]);
+            merkle_value_sink(&[(two_msb << 6) + u8::try_from(pk_len).unwrap()]);
         }
     }
 
     // Turn the partial key into bytes with a weird encoding and push it to `merkle_value_sink`.
     if partial_key.len() % 2 != 0 {
         // next().unwrap() can't panic, otherwise `len() % 2` would have returned 0.
-        merkle_value_sink.update(&[u8::from(partial_key.next().unwrap())]);
+        merkle_value_sink(&[u8::from(partial_key.next().unwrap())]);
     }
     {
         let mut previous = None;
         for nibble in partial_key {
             if let Some(prev) = previous.take() {
                 let val = (u8::from(prev) << 4) | u8::from(nibble);
-                merkle_value_sink.update(&[val]);
+                merkle_value_sink(&[val]);
             } else {
                 previous = Some(nibble);
             }
@@ -199,19 +234,20 @@ where
     // We take a shortcut and end the calculation now.
     if !has_children {
         if let Some(stored_value) = config.stored_value {
-            // Doing something like `merkle_value_sink.update(stored_value.encode());` would be
+            // Doing something like `merkle_value_sink(stored_value.encode());` would be
             // quite expensive because we would duplicate the storage value. Instead, we do the
             // encoding manually by pushing the length then the value.
-            merkle_value_sink
-                .update(util::encode_scale_compact_usize(stored_value.as_ref().len()).as_ref());
-            merkle_value_sink.update(stored_value.as_ref());
+            merkle_value_sink(
+                util::encode_scale_compact_usize(stored_value.as_ref().len()).as_ref(),
+            );
+            merkle_value_sink(stored_value.as_ref());
         }
 
-        return merkle_value_sink.finalize();
+        return;
     }
 
     // If there is any child, we a `u16` where each bit is `1` if there exists a child there.
-    merkle_value_sink.update({
+    merkle_value_sink({
         let mut children_bitmap = 0u16;
         for (child_index, child) in config.children.clone().enumerate() {
             if child.is_some() {
@@ -223,12 +259,11 @@ where
 
     // Add our own stored value.
     if let Some(stored_value) = config.stored_value {
-        // Doing something like `merkle_value_sink.update(stored_value.encode());` would be
+        // Doing something like `merkle_value_sink(stored_value.encode());` would be
         // quite expensive because we would duplicate the storage value. Instead, we do the
         // encoding manually by pushing the length then the value.
-        merkle_value_sink
-            .update(util::encode_scale_compact_usize(stored_value.as_ref().len()).as_ref());
-        merkle_value_sink.update(stored_value.as_ref());
+        merkle_value_sink(util::encode_scale_compact_usize(stored_value.as_ref().len()).as_ref());
+        merkle_value_sink(stored_value.as_ref());
     }
 
     // Finally, push the merkle values of all the children.
@@ -238,15 +273,14 @@ where
             None => continue,
         };
 
-        // Doing something like `merkle_value_sink.update(child_merkle_value.encode());` would be
+        // Doing something like `merkle_value_sink(child_merkle_value.encode());` would be
         // expensive because we would duplicate the merkle value. Instead, we do the encoding
         // manually by pushing the length then the value.
-        merkle_value_sink
-            .update(util::encode_scale_compact_usize(child_merkle_value.as_ref().len()).as_ref());
-        merkle_value_sink.update(child_merkle_value.as_ref());
+        merkle_value_sink(
+            util::encode_scale_compact_usize(child_merkle_value.as_ref().len()).as_ref(),
+        );
+        merkle_value_sink(child_merkle_value.as_ref());
     }
-
-    merkle_value_sink.finalize()
 }
 
 /// Output of the calculation.
@@ -0,0 +1,350 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Calculation of the root of a radix-16 Merkle-Patricia trie split into independent tasks.
+//!
+//! See the parent module documentation for an explanation of what the trie is.
+//!
+//! Contrary to [`calculate_root`](super::calculate_root), this module is designed for tries
+//! containing a large number of entries. The Merkle values of the children of the root node are
+//! calculated independently from each other, then combined in order to obtain the Merkle value
+//! of the root node. The result is identical to the one of a sequential calculation.
+//!
+//! This module doesn't spawn any thread or task itself. Instead, the list of tasks is passed to
+//! a function provided by the user, which is responsible for executing them, for example by
+//! distributing them over multiple threads.
+//!
+//! # Usage
+//!
+//! ```
+//! use smoldot::trie::parallel_root;
+//!
+//! let entries = vec![
+//!     (b"foo".to_vec(), b"bar".to_vec()),
+//!     (b"hello".to_vec(), b"world".to_vec()),
+//! ];
+//!
+//! let trie_root = parallel_root::trie_root(&entries, 1, |tasks| {
+//!     // Tasks are here executed sequentially, but they could also be spawned in the background.
+//!     tasks.into_iter().map(|task| task.run()).collect()
+//! });
+//!
+//! assert_eq!(trie_root, smoldot::trie::trie_root(&entries));
+//! ```
+
+use super::{nibble::Nibble, node_value};
+
+use alloc::vec::Vec;
+
+/// Calculates the Merkle value of the root of a trie containing the entries passed as parameter.
+/// The entries passed as parameter are `(key, value)` and must be ordered by key.
+///
+/// If the number of entries is inferior to `min_entries_for_parallelism`, the calculation is
+/// performed sequentially and `execute` isn't called. Otherwise, `execute` is called once with
+/// the list of tasks to execute, and must return the outputs of [`SubtreeTask::run`], in any
+/// order.
+///
+/// # Panic
+///
+/// Panics if the entries aren't ordered by key, or if two entries have the same key.
+/// Panics if `execute` doesn't return exactly one output for each task.
+///
+pub fn trie_root<'a, TKey, TVal>(
+    entries: &'a [(TKey, TVal)],
+    min_entries_for_parallelism: usize,
+    execute: impl FnOnce(Vec<SubtreeTask<'a, TKey, TVal>>) -> Vec<SubtreeMerkleValue>,
+) -> [u8; 32]
+where
+    TKey: AsRef<[u8]>,
+    TVal: AsRef<[u8]>,
+{
+    assert!(entries
+        .windows(2)
+        .all(|w| w[0].0.as_ref() < w[1].0.as_ref()));
+
+    if entries.is_empty() {
+        return super::empty_trie_merkle_value();
+    }
+
+    if entries.len() < min_entries_for_parallelism {
+        return node_merkle_value(entries, 0, true).into();
+    }
+
+    let key_len = node_key_len(entries, 0);
+    let (stored_value, descendants) = split_stored_value(entries, key_len);
+
+    let tasks = children_entries(descendants, key_len)
+        .into_iter()
+        .map(|(child_index, entries)| SubtreeTask {
+            entries,
+            depth: key_len + 1,
+            child_index,
+        })
+        .collect::<Vec<_>>();
+    let num_tasks = tasks.len();
+
+    let mut children: [Option<node_value::Output>; 16] = Default::default();
+    let outputs = execute(tasks);
+    assert_eq!(outputs.len(), num_tasks);
+    for output in outputs {
+        let child = &mut children[usize::from(output.child_index)];
+        assert!(child.is_none());
+        *child = Some(output.merkle_value);
+    }
+
+    finish_node(
+        entries[0].0.as_ref(),
+        0,
+        key_len,
+        true,
+        &children,
+        stored_value,
+    )
+    .into()
+}
+
+/// Calculation of the Merkle value of one of the children of the root node of the trie.
+///
+/// See [`trie_root`].
+#[must_use]
+pub struct SubtreeTask<'a, TKey, TVal> {
+    /// Entries whose key starts with the key of the child.
+    entries: &'a [(TKey, TVal)],
+    /// Number of nibbles of the key of the child, including its index within its parent.
+    depth: usize,
+    /// Index of the child within the root node.
+    child_index: u8,
+}
+
+impl<'a, TKey, TVal> SubtreeTask<'a, TKey, TVal>
+where
+    TKey: AsRef<[u8]>,
+    TVal: AsRef<[u8]>,
+{
+    /// Returns the number of entries in the subtree. Can be used as an estimation of the cost of
+    /// the task.
+    pub fn num_entries(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Performs the calculation.
+    pub fn run(self) -> SubtreeMerkleValue {
+        SubtreeMerkleValue {
+            child_index: self.child_index,
+            merkle_value: node_merkle_value(self.entries, self.depth, false),
+        }
+    }
+}
+
+/// Output of [`SubtreeTask::run`].
+pub struct SubtreeMerkleValue {
+    child_index: u8,
+    merkle_value: node_value::Output,
+}
+
+/// Calculates the Merkle value of the node that is the closest common ancestor of all the
+/// entries.
+///
+/// All the entries must share the same `depth` first nibbles. `entries` must not be empty.
+fn node_merkle_value<TKey, TVal>(
+    entries: &[(TKey, TVal)],
+    depth: usize,
+    is_root: bool,
+) -> node_value::Output
+where
+    TKey: AsRef<[u8]>,
+    TVal: AsRef<[u8]>,
+{
+    let key_len = node_key_len(entries, depth);
+    let (stored_value, descendants) = split_stored_value(entries, key_len);
+
+    let mut children: [Option<node_value::Output>; 16] = Default::default();
+    for (child_index, child_entries) in children_entries(descendants, key_len) {
+        children[usize::from(child_index)] =
+            Some(node_merkle_value(child_entries, key_len + 1, false));
+    }
+
+    finish_node(
+        entries[0].0.as_ref(),
+        depth,
+        key_len,
+        is_root,
+        &children,
+        stored_value,
+    )
+}
+
+/// Calculates the Merkle value of a node whose key is the first `key_len` nibbles of `key`, and
+/// whose partial key starts at nibble `depth`.
+fn finish_node(
+    key: &[u8],
+    depth: usize,
+    key_len: usize,
+    is_root: bool,
+    children: &[Option<node_value::Output>; 16],
+    stored_value: Option<&[u8]>,
+) -> node_value::Output {
+    let partial_key = (depth..key_len).map(|n| nibble_at(key, n));
+    node_value::calculate_merkle_root(node_value::Config {
+        ty: if is_root {
+            node_value::NodeTy::Root { key: partial_key }
+        } else {
+            node_value::NodeTy::NonRoot { partial_key }
+        },
+        children: children.iter().map(|c| c.as_ref()),
+        stored_value,
+    })
+}
+
+/// Returns the number of nibbles of the key of the closest common ancestor of all the entries.
+///
+/// All the entries must share the same `depth` first nibbles. `entries` must not be empty.
+fn node_key_len<TKey: AsRef<[u8]>, TVal>(entries: &[(TKey, TVal)], depth: usize) -> usize {
+    // Since the entries are ordered, the prefix shared by the first and last entries is shared
+    // by all the entries.
+    let first = entries[0].0.as_ref();
+    let last = entries[entries.len() - 1].0.as_ref();
+    let max_len = first.len().min(last.len()) * 2;
+
+    let mut key_len = depth;
+    while key_len < max_len && nibble_at(first, key_len) == nibble_at(last, key_len) {
+        key_len += 1;
+    }
+    key_len
+}
+
+/// Splits the entries between the storage value of the node whose key is `key_len` long, if
+/// any, and the entries of its descendants.
+fn split_stored_value<TKey: AsRef<[u8]>, TVal: AsRef<[u8]>>(
+    entries: &[(TKey, TVal)],
+    key_len: usize,
+) -> (Option<&[u8]>, &[(TKey, TVal)]) {
+    // Since the entries are ordered, the node's own entry is necessarily the first one.
+    if entries[0].0.as_ref().len() * 2 == key_len {
+        (Some(entries[0].1.as_ref()), &entries[1..])
+    } else {
+        (None, entries)
+    }
+}
+
+/// Groups the entries by value of their nibble at index `key_len`.
+fn children_entries<TKey: AsRef<[u8]>, TVal>(
+    mut entries: &[(TKey, TVal)],
+    key_len: usize,
+) -> Vec<(u8, &[(TKey, TVal)])> {
+    let mut out = Vec::with_capacity(16);
+    while !entries.is_empty() {
+        let child_index = nibble_at(entries[0].0.as_ref(), key_len);
+        let len = entries
+            .iter()
+            .position(|(k, _)| nibble_at(k.as_ref(), key_len) != child_index)
+            .unwrap_or(entries.len());
+        out.push((u8::from(child_index), &entries[..len]));
+        entries = &entries[len..];
+    }
+    out
+}
+
+/// Returns the nibble at the given index of the key.
+fn nibble_at(key: &[u8], index: usize) -> Nibble {
+    let byte = key[index / 2];
+    let nibble = match index % 2 {
+        0 => byte >> 4,
+        _ => byte & 0xf,
+    };
+    Nibble::try_from(nibble).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng as _, SeedableRng as _};
+
+    #[test]
+    fn matches_sequential() {
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0);
+
+        let mut trie = super::super::Trie::new();
+        for _ in 0..50_000 {
+            let key_len = rng.gen_range(0..=40);
+            let key = (0..key_len).map(|_| rng.gen::<u8>()).collect::<Vec<_>>();
+            let value_len = rng.gen_range(0..=64);
+            let value = (0..value_len).map(|_| rng.gen::<u8>()).collect::<Vec<_>>();
+            trie.insert(&key, value);
+        }
+        // Also insert keys sharing a long prefix, in order to have a deeper trie.
+        for n in 0..1000u32 {
+            let mut key = b"shared prefix".to_vec();
+            key.extend_from_slice(&n.to_be_bytes());
+            trie.insert(&key, n.to_le_bytes().to_vec());
+        }
+
+        let entries = trie.entries.clone().into_iter().collect::<Vec<_>>();
+
+        let parallel = super::trie_root(&entries, 1, |tasks| {
+            assert!(tasks.len() > 1);
+            std::thread::scope(|scope| {
+                tasks
+                    .into_iter()
+                    .map(|task| scope.spawn(move || task.run()))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .rev()
+                    .map(|thread| thread.join().unwrap())
+                    .collect()
+            })
+        });
+
+        assert_eq!(parallel, trie.root_merkle_value(None));
+    }
+
+    #[test]
+    fn small_trie_no_task() {
+        let entries = [
+            (&b""[..], &b"empty key"[..]),
+            (&b"a"[..], &b"foo"[..]),
+            (&b"ab"[..], &b"bar"[..]),
+            (&b"b"[..], &[0x42; 80][..]),
+        ];
+
+        let root = super::trie_root(&entries, 5, |_| panic!());
+        assert_eq!(root, crate::trie::trie_root(&entries));
+        assert_eq!(
+            super::trie_root(&entries, 0, |tasks| tasks
+                .into_iter()
+                .map(|t| t.run())
+                .collect()),
+            root
+        );
+    }
+
+    #[test]
+    fn common_prefix() {
+        let entries = [(&b"abc"[..], &b"1"[..]), (&b"abd"[..], &b"2"[..])];
+        assert_eq!(
+            super::trie_root(&entries, 0, |tasks| tasks
+                .into_iter()
+                .map(|t| t.run())
+                .collect()),
+            crate::trie::trie_root(&entries)
+        );
+
+        assert_eq!(
+            super::trie_root(&[] as &[(&[u8], &[u8])], 0, |_| panic!()),
+            crate::trie::empty_trie_merkle_value()
+        );
+    }
+}
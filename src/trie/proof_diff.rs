@@ -0,0 +1,231 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Differences between two tries, determined through trie proofs.
+//!
+//! Given a proof built from an old state and a proof built from a new state, both covering the
+//! same list of keys, [`diff_keys`] finds out which of these keys have a different storage value
+//! in these two states.
+//!
+//! This makes it possible to find out the changes made to some keys without having access to
+//! the storage, for example when only a remote node has access to it.
+
+use super::proof_verify;
+
+use alloc::{collections::BTreeSet, vec::Vec};
+
+/// Configuration to pass to [`diff_keys`].
+pub struct Config<'a, TKeys, TProofOld, TProofNew> {
+    /// List of keys to compare. Can contain the same key multiple times.
+    pub keys: TKeys,
+
+    /// Merkle value of the root node of the old trie.
+    pub old_trie_root_hash: &'a [u8; 32],
+
+    /// List of node values of nodes found in the old trie. Must contain the proof of all the
+    /// keys in [`Config::keys`]. See [`proof_verify::VerifyProofConfig::proof`].
+    pub old_proof: TProofOld,
+
+    /// Merkle value of the root node of the new trie.
+    pub new_trie_root_hash: &'a [u8; 32],
+
+    /// List of node values of nodes found in the new trie. Must contain the proof of all the
+    /// keys in [`Config::keys`]. See [`proof_verify::VerifyProofConfig::proof`].
+    pub new_proof: TProofNew,
+}
+
+/// Verifies both proofs and returns the list of keys whose storage value differs between the
+/// old and the new trie, ordered by key.
+///
+/// Keys that have the same storage value in both tries, including keys that have no storage
+/// value in either trie, aren't part of the list.
+///
+/// Returns an error if one of the proofs is invalid or doesn't cover all the keys.
+pub fn diff_keys<'b>(
+    config: Config<
+        '_,
+        impl Iterator<Item = impl AsRef<[u8]>>,
+        impl Iterator<Item = &'b [u8]> + Clone,
+        impl Iterator<Item = &'b [u8]> + Clone,
+    >,
+) -> Result<Vec<KeyDiff<'b>>, Error> {
+    let keys = config
+        .keys
+        .map(|key| key.as_ref().to_vec())
+        .collect::<BTreeSet<_>>();
+
+    let mut diff = Vec::new();
+
+    for key in keys {
+        let old_value = proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+            requested_key: &key,
+            trie_root_hash: config.old_trie_root_hash,
+            proof: config.old_proof.clone(),
+        })
+        .map_err(Error::OldProof)?;
+
+        let new_value = proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+            requested_key: &key,
+            trie_root_hash: config.new_trie_root_hash,
+            proof: config.new_proof.clone(),
+        })
+        .map_err(Error::NewProof)?;
+
+        if old_value != new_value {
+            diff.push(KeyDiff {
+                key,
+                old_value,
+                new_value,
+            });
+        }
+    }
+
+    Ok(diff)
+}
+
+/// Key whose storage value differs between the old and the new trie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDiff<'a> {
+    /// Key in question.
+    pub key: Vec<u8>,
+    /// Storage value of the key in the old trie, or `None` if the key has no storage value.
+    pub old_value: Option<&'a [u8]>,
+    /// Storage value of the key in the new trie, or `None` if the key has no storage value.
+    pub new_value: Option<&'a [u8]>,
+}
+
+/// Error potentially returned by [`diff_keys`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum Error {
+    /// Failed to verify the proof of the old trie.
+    #[display(fmt = "Failed to verify the proof of the old trie: {}", _0)]
+    OldProof(proof_verify::Error),
+    /// Failed to verify the proof of the new trie.
+    #[display(fmt = "Failed to verify the proof of the new trie: {}", _0)]
+    NewProof(proof_verify::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{decode_proof, proof_generate, trie_root};
+
+    /// Builds a proof of the given keys, and decodes it into a list of node values.
+    fn proof(storage: &[(Vec<u8>, Vec<u8>)], keys: &[&[u8]]) -> Vec<Vec<u8>> {
+        let encoded = proof_generate::generate_read_proof(proof_generate::Config {
+            keys: keys.iter(),
+            storage_entries: storage.iter().map(|(k, v)| (&k[..], &v[..])),
+            trie_root_hash: &trie_root(storage),
+        })
+        .unwrap();
+
+        decode_proof(&encoded)
+            .unwrap()
+            .into_iter()
+            .map(|n| n.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn overlapping_keys() {
+        let old_storage = [
+            (b"modified".to_vec(), vec![1; 40]),
+            (b"removed".to_vec(), vec![2]),
+            (b"unchanged".to_vec(), vec![3; 50]),
+        ];
+        let new_storage = [
+            (b"added".to_vec(), vec![4]),
+            (b"modified".to_vec(), vec![5; 40]),
+            (b"unchanged".to_vec(), vec![3; 50]),
+        ];
+
+        let keys = [
+            &b"added"[..],
+            b"modified",
+            b"nowhere",
+            b"removed",
+            b"unchanged",
+            b"modified",
+        ];
+        let old_proof = proof(&old_storage, &keys);
+        let new_proof = proof(&new_storage, &keys);
+        let old_trie_root_hash = trie_root(&old_storage);
+        let new_trie_root_hash = trie_root(&new_storage);
+
+        let diff = super::diff_keys(super::Config {
+            keys: keys.iter(),
+            old_trie_root_hash: &old_trie_root_hash,
+            old_proof: old_proof.iter().map(|n| &n[..]),
+            new_trie_root_hash: &new_trie_root_hash,
+            new_proof: new_proof.iter().map(|n| &n[..]),
+        })
+        .unwrap();
+
+        assert_eq!(
+            diff,
+            vec![
+                super::KeyDiff {
+                    key: b"added".to_vec(),
+                    old_value: None,
+                    new_value: Some(&[4][..]),
+                },
+                super::KeyDiff {
+                    key: b"modified".to_vec(),
+                    old_value: Some(&[1; 40][..]),
+                    new_value: Some(&[5; 40][..]),
+                },
+                super::KeyDiff {
+                    key: b"removed".to_vec(),
+                    old_value: Some(&[2][..]),
+                    new_value: None,
+                },
+            ]
+        );
+
+        // Swapping the proofs reverses the transitions.
+        let reverse_diff = super::diff_keys(super::Config {
+            keys: keys.iter(),
+            old_trie_root_hash: &new_trie_root_hash,
+            old_proof: new_proof.iter().map(|n| &n[..]),
+            new_trie_root_hash: &old_trie_root_hash,
+            new_proof: old_proof.iter().map(|n| &n[..]),
+        })
+        .unwrap();
+        assert_eq!(reverse_diff.len(), 3);
+        assert_eq!(reverse_diff[0].key, b"added");
+        assert_eq!(reverse_diff[0].old_value, Some(&[4][..]));
+        assert_eq!(reverse_diff[0].new_value, None);
+    }
+
+    #[test]
+    fn proof_not_covering_keys() {
+        let storage = (0..64u8)
+            .map(|n| (vec![n, n], vec![n; 40]))
+            .collect::<Vec<_>>();
+        let trie_root_hash = trie_root(&storage);
+        let full_proof = proof(&storage, &[&[3, 3], &[60, 60]]);
+        let partial_proof = proof(&storage, &[&[3, 3]]);
+
+        let result = super::diff_keys(super::Config {
+            keys: [&[3, 3][..], &[60, 60]].iter(),
+            old_trie_root_hash: &trie_root_hash,
+            old_proof: full_proof.iter().map(|n| &n[..]),
+            new_trie_root_hash: &trie_root_hash,
+            new_proof: partial_proof.iter().map(|n| &n[..]),
+        });
+        assert!(matches!(result, Err(super::Error::NewProof(_))));
+    }
+}
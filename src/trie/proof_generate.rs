@@ -0,0 +1,386 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Generation of a trie proof.
+//!
+//! This is the counterpart of the [`super::proof_verify`] module. Given the entire content of
+//! the storage, [`generate_read_proof`] builds a proof that some keys have a certain storage
+//! value, or lack a storage value.
+//!
+//! The proof consists in the list of node values of the nodes between the root node of the trie
+//! and the nodes closest to each of the requested keys. Nodes whose node value is shorter than
+//! 32 bytes are directly found within the node value of their parent and are thus not included.
+//! If multiple keys share some of these nodes, the nodes are only included once.
+
+use super::{nibble, node_value};
+use crate::util;
+
+use alloc::{collections::BTreeSet, vec::Vec};
+
+/// Configuration to pass to [`generate_read_proof`].
+pub struct Config<'a, TKeys, TEntries> {
+    /// List of keys whose storage value (or absence of storage value) must be proven. Can
+    /// contain the same key multiple times.
+    pub keys: TKeys,
+
+    /// List of all the entries of the storage, as `(key, value)` tuples. No specific order is
+    /// required. Each key must only appear once.
+    pub storage_entries: TEntries,
+
+    /// Merkle value of the root node of the trie built from
+    /// [`Config::storage_entries`]. Used as a sanity check.
+    pub trie_root_hash: &'a [u8; 32],
+}
+
+/// Builds a proof that can be verified with [`super::proof_verify::verify_proof`].
+///
+/// The proof is returned as a SCALE-encoded list of node values, which is the format of the
+/// storage proofs sent over the networking protocol.
+///
+/// The proof contains the minimal set of node values necessary to verify all the requested keys,
+/// including the ones that are prefixes of each other. It is never larger than the proofs of
+/// each key generated individually then merged together.
+///
+/// Returns an error if the root of the trie built from [`Config::storage_entries`] doesn't
+/// match [`Config::trie_root_hash`].
+pub fn generate_read_proof<'a>(
+    config: Config<
+        'a,
+        impl Iterator<Item = impl AsRef<[u8]>>,
+        impl Iterator<Item = (&'a [u8], &'a [u8])>,
+    >,
+) -> Result<Vec<u8>, Error> {
+    let mut entries = config
+        .storage_entries
+        .map(|(key, value)| {
+            (
+                nibble::bytes_to_nibbles(key.iter().copied()).collect::<Vec<_>>(),
+                value,
+            )
+        })
+        .collect::<Vec<_>>();
+    entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+    let requested_keys = config
+        .keys
+        .map(|key| nibble::bytes_to_nibbles(key.as_ref().iter().copied()).collect::<Vec<_>>())
+        .collect::<BTreeSet<_>>();
+
+    let mut proof = BTreeSet::new();
+    let trie_root_hash = node_merkle_value(
+        &entries,
+        0,
+        &requested_keys.iter().map(|k| &k[..]).collect::<Vec<_>>(),
+        &mut proof,
+    );
+
+    if trie_root_hash.as_ref() != &config.trie_root_hash[..] {
+        return Err(Error::TrieRootMismatch);
+    }
+
+    let mut encoded = util::encode_scale_compact_usize(proof.len())
+        .as_ref()
+        .to_vec();
+    for node_value in proof {
+        encoded.extend_from_slice(util::encode_scale_compact_usize(node_value.len()).as_ref());
+        encoded.extend_from_slice(&node_value);
+    }
+    Ok(encoded)
+}
+
+/// Error potentially returned by [`generate_read_proof`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum Error {
+    /// Trie root calculated from the storage entries doesn't match the one passed in the
+    /// configuration.
+    TrieRootMismatch,
+}
+
+/// Calculates the Merkle value of the node that is the closest common ancestor of all the
+/// `entries`, which must be ordered and all start with the same `depth` nibbles.
+///
+/// `requested_keys` must contain the requested keys that start with these `depth` nibbles. The
+/// node values that are necessary to prove these keys are added to `proof`.
+///
+/// If `depth` is 0, the node is considered as the root node of the trie.
+fn node_merkle_value(
+    entries: &[(Vec<nibble::Nibble>, &[u8])],
+    depth: usize,
+    requested_keys: &[&[nibble::Nibble]],
+    proof: &mut BTreeSet<Vec<u8>>,
+) -> node_value::Output {
+    // The key of the node is the longest prefix shared by all the entries. Since the entries
+    // are ordered, this is the prefix shared by the first and last entries.
+    let node_key_len = match (entries.first(), entries.last()) {
+        (Some((first, _)), Some((last, _))) => {
+            depth
+                + first[depth..]
+                    .iter()
+                    .zip(last[depth..].iter())
+                    .take_while(|(a, b)| a == b)
+                    .count()
+        }
+        _ => 0,
+    };
+    let node_key = entries
+        .first()
+        .map_or(&[][..], |(key, _)| &key[..node_key_len]);
+
+    // Since the entries are ordered, the entry corresponding to the node itself, if any, is the
+    // first one.
+    let (stored_value, descendants) = match entries.first() {
+        Some((key, value)) if key.len() == node_key_len => (Some(*value), &entries[1..]),
+        _ => (None, entries),
+    };
+
+    let mut children = Vec::with_capacity(16);
+    let mut remaining_descendants = descendants;
+    for child_index in nibble::all_nibbles() {
+        let num_child_entries = remaining_descendants
+            .iter()
+            .take_while(|(key, _)| key[node_key_len] == child_index)
+            .count();
+        let (child_entries, next) = remaining_descendants.split_at(num_child_entries);
+        remaining_descendants = next;
+
+        if child_entries.is_empty() {
+            children.push(None);
+            continue;
+        }
+
+        let child_requested_keys = requested_keys
+            .iter()
+            .filter(|key| key.len() > node_key_len)
+            .filter(|key| key[..node_key_len] == *node_key && key[node_key_len] == child_index)
+            .copied()
+            .collect::<Vec<_>>();
+
+        children.push(Some(node_merkle_value(
+            child_entries,
+            node_key_len + 1,
+            &child_requested_keys,
+            proof,
+        )));
+    }
+    debug_assert!(remaining_descendants.is_empty());
+
+    let node_config = || node_value::Config {
+        ty: if depth == 0 {
+            node_value::NodeTy::Root {
+                key: node_key.iter().copied(),
+            }
+        } else {
+            node_value::NodeTy::NonRoot {
+                partial_key: node_key[depth..].iter().copied(),
+            }
+        },
+        children: children.iter().map(|child| child.as_ref()),
+        stored_value,
+    };
+
+    // The node value is needed in the proof if the node is on the path of one of the requested
+    // keys and isn't directly included in the node value of its parent.
+    if !requested_keys.is_empty() {
+        let node_value = node_value::calculate_node_value(node_config());
+        if depth == 0 || node_value.len() >= 32 {
+            proof.insert(node_value);
+        }
+    }
+
+    node_value::calculate_merkle_root(node_config())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{decode_proof, proof_verify, trie_root};
+    use alloc::collections::BTreeSet;
+    use core::iter;
+
+    #[test]
+    fn generate_then_verify() {
+        // Mixes short values, whose nodes are inlined in their parent, and long values.
+        let storage = [(vec![1], vec![0xff; 40]), (vec![1, 8], vec![1])]
+            .into_iter()
+            .chain((0..128u8).map(|n| {
+                let key = vec![n % 7, n, n % 3];
+                let value = vec![n; usize::from(n % 5) * 16];
+                (key, value)
+            }))
+            .collect::<Vec<_>>();
+        let trie_root_hash = trie_root(&storage);
+
+        let present_keys = [&[1][..], &[1, 8], &[0, 7, 1], &[6, 125, 2]];
+        let absent_keys = [
+            // No child at the requested index.
+            &[0xff][..],
+            // Key whose node would be the parent of existing nodes.
+            &[5, 103],
+            // Diverges from an existing partial key.
+            &[1, 8, 0],
+            &[2, 2, 3],
+            &[],
+        ];
+
+        let generate = |keys: &[&[u8]]| {
+            super::generate_read_proof(super::Config {
+                keys: keys.iter(),
+                storage_entries: storage.iter().map(|(k, v)| (&k[..], &v[..])),
+                trie_root_hash: &trie_root_hash,
+            })
+            .unwrap()
+        };
+
+        let all_keys = present_keys
+            .iter()
+            .chain(absent_keys.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        let proof = generate(&all_keys);
+        let decoded_proof = decode_proof(&proof).unwrap();
+
+        for key in present_keys {
+            let expected = storage
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| &v[..])
+                .unwrap();
+            let value = proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+                requested_key: key,
+                trie_root_hash: &trie_root_hash,
+                proof: decoded_proof.iter().copied(),
+            })
+            .unwrap();
+            assert_eq!(value, Some(expected));
+        }
+
+        for key in absent_keys {
+            let value = proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+                requested_key: key,
+                trie_root_hash: &trie_root_hash,
+                proof: decoded_proof.iter().copied(),
+            })
+            .unwrap();
+            assert_eq!(value, None);
+        }
+
+        // Repeating keys doesn't duplicate entries in the proof.
+        let duplicated_keys = all_keys
+            .iter()
+            .chain(all_keys.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(generate(&duplicated_keys), proof);
+
+        // A proof for a single key is smaller, and doesn't necessarily prove the other keys.
+        let single_key_proof = generate(&[&[6, 125, 2]]);
+        assert!(decode_proof(&single_key_proof).unwrap().len() < decoded_proof.len());
+    }
+
+    #[test]
+    fn minimal_multi_key_proof() {
+        // Keys are sorted so that a key is never inserted in the trie after a branch node has
+        // been created at the same location.
+        let mut storage = (0..64u8)
+            .map(|n| (vec![n % 4, n % 3, n], vec![n; 40]))
+            .chain([
+                (vec![2], vec![0xff; 40]),
+                (vec![2, 2], vec![0xfe; 40]),
+                (vec![2, 2, 2, 2], vec![0xfd; 40]),
+            ])
+            .collect::<Vec<_>>();
+        storage.sort();
+        let trie_root_hash = trie_root(&storage);
+
+        // Keys that are prefixes of each other, present or absent.
+        let keys = [
+            &[][..],
+            &[2],
+            &[2, 2],
+            &[2, 2, 2],
+            &[2, 2, 2, 2],
+            &[2, 2, 2, 2, 2],
+            &[3, 0, 3],
+            &[3, 0],
+            &[3, 0, 3, 0],
+        ];
+
+        let generate = |keys: &[&[u8]]| {
+            super::generate_read_proof(super::Config {
+                keys: keys.iter(),
+                storage_entries: storage.iter().map(|(k, v)| (&k[..], &v[..])),
+                trie_root_hash: &trie_root_hash,
+            })
+            .unwrap()
+        };
+
+        fn verify<'a>(
+            trie_root_hash: &[u8; 32],
+            proof: &[&'a [u8]],
+            key: &[u8],
+        ) -> Result<Option<&'a [u8]>, proof_verify::Error> {
+            proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+                requested_key: key,
+                trie_root_hash,
+                proof: proof.iter().copied(),
+            })
+        }
+
+        let proof = generate(&keys);
+        let decoded_proof = decode_proof(&proof).unwrap();
+
+        // Merging the proofs of each key generated individually.
+        let individual_proofs = keys.iter().map(|k| generate(&[*k])).collect::<Vec<_>>();
+        let merged = individual_proofs
+            .iter()
+            .flat_map(|p| decode_proof(p).unwrap())
+            .collect::<BTreeSet<_>>();
+        let merged_size = merged.iter().map(|n| n.len()).sum::<usize>();
+        let proof_size = decoded_proof.iter().map(|n| n.len()).sum::<usize>();
+        assert_eq!(decoded_proof.len(), merged.len());
+        assert!(proof_size <= merged_size);
+
+        for key in keys {
+            let expected = storage.iter().find(|(k, _)| k == key).map(|(_, v)| &v[..]);
+            assert_eq!(
+                verify(&trie_root_hash, &decoded_proof, key).unwrap(),
+                expected
+            );
+        }
+
+        // Removing any node value from the proof makes the verification of some key fail.
+        for index in 0..decoded_proof.len() {
+            let mut truncated = decoded_proof.clone();
+            truncated.remove(index);
+            assert!(keys
+                .iter()
+                .any(|key| verify(&trie_root_hash, &truncated, key).is_err()));
+        }
+    }
+
+    #[test]
+    fn root_mismatch() {
+        let storage = [(&b"foo"[..], &b"bar"[..])];
+        assert!(matches!(
+            super::generate_read_proof(super::Config {
+                keys: iter::once(b"foo"),
+                storage_entries: storage.iter().copied(),
+                trie_root_hash: &[0; 32],
+            }),
+            Err(super::Error::TrieRootMismatch)
+        ));
+    }
+}
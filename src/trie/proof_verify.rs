@@ -47,7 +47,7 @@
 
 use super::{nibble, proof_node_decode};
 
-use alloc::vec::Vec;
+use alloc::{collections::BTreeMap, vec::Vec};
 
 /// Configuration to pass to [`verify_proof`].
 pub struct VerifyProofConfig<'a, I> {
@@ -122,34 +122,15 @@ pub fn trie_node_info<'a, 'b>(
         impl Iterator<Item = &'b [u8]> + Clone,
     >,
 ) -> Result<TrieNodeInfo<'b>, Error> {
-    // The proof contains node values, while Merkle values will be needed. Create a list of
-    // Merkle values, one per entry in `config.proof`.
-    let merkle_values = config
-        .proof
-        .clone()
-        .map(|proof_entry| -> arrayvec::ArrayVec<u8, 32> {
-            if proof_entry.len() >= 32 {
-                blake2_rfc::blake2b::blake2b(32, &[], proof_entry)
-                    .as_bytes()
-                    .iter()
-                    .copied()
-                    .collect()
-            } else {
-                proof_entry.iter().copied().collect()
-            }
-        })
-        .collect::<Vec<_>>();
+    // The proof contains node values, while Merkle values will be needed.
+    let entries_by_hash = index_proof(config.proof);
 
     // Find the expected trie root in the proof and put it in `node_value`. This is the start
     // point of the verification.
     // `node_value` is updated as the decoding progresses.
-    let mut node_value = {
-        let proof_iter = merkle_values
-            .iter()
-            .position(|v| v[..] == config.trie_root_hash[..])
-            .ok_or(Error::TrieRootNotFound)?;
-        config.proof.clone().nth(proof_iter).unwrap()
-    };
+    let mut node_value = *entries_by_hash
+        .get(config.trie_root_hash)
+        .ok_or(Error::TrieRootNotFound)?;
 
     // Number of nibbles that have been found during the iteration below.
     // Used only for debugging purposes.
@@ -204,14 +185,13 @@ pub fn trie_node_info<'a, 'b>(
                 // case, the child isn't part of `proof` but directly in the node.
                 node_value = child;
             } else {
-                // Find the entry in `proof` matching this Merkle value and update
-                // `proof_iter`.
-                let proof_iter = merkle_values.iter().position(|v| v[..] == *child).ok_or(
-                    Error::MissingProofEntry {
+                // Find the entry in `proof` matching this Merkle value.
+                node_value = *<&[u8; 32]>::try_from(child)
+                    .ok()
+                    .and_then(|hash| entries_by_hash.get(hash))
+                    .ok_or(Error::MissingProofEntry {
                         closest_ancestor_nibbles: iter_nibbles,
-                    },
-                )?;
-                node_value = config.proof.clone().nth(proof_iter).unwrap();
+                    })?;
             }
 
             // Jump to the next node.
@@ -267,7 +247,320 @@ impl Children {
     }
 }
 
-/// Possible error returned by [`verify_proof`]
+/// Configuration to pass to [`iter_entries`].
+pub struct IterEntriesConfig<'a, I> {
+    /// Merkle value (or node value) of the root node of the trie.
+    ///
+    /// > **Note**: The Merkle value and node value are always the same for the root node.
+    pub trie_root_hash: &'a [u8; 32],
+
+    /// List of node values of nodes found in the trie. No specific order is required.
+    pub proof: I,
+}
+
+/// Builds an iterator to all the storage entries found in the proof, in lexicographic order of
+/// their keys.
+///
+/// Contrary to [`verify_proof`], which only looks at the nodes between the root and a single
+/// key, this walks through all the nodes of the proof that are reachable from the root. Node
+/// values are only decoded when the iterator reaches them, meaning that the storage entries can
+/// be processed while the proof is being verified.
+///
+/// Entries of the proof that aren't reachable from the root are silently ignored, for the same
+/// reasons as explained in [the module-level documentation](self). Children of a node whose node
+/// value is missing from the proof are skipped, as proofs typically only contain a subset of the
+/// trie.
+///
+/// Returns an error if the trie root can't be found in the proof.
+pub fn iter_entries<'b>(
+    config: IterEntriesConfig<'_, impl Iterator<Item = &'b [u8]>>,
+) -> Result<EntriesIter<'b>, Error> {
+    Ok(EntriesIter {
+        traversal: Traversal::new(config.trie_root_hash, config.proof, None)?,
+    })
+}
+
+/// Iterator returned by [`iter_entries`].
+///
+/// Yields the keys and storage values of all the nodes of the proof that have a storage value,
+/// in lexicographic order of their keys.
+pub struct EntriesIter<'b> {
+    traversal: Traversal<'b>,
+}
+
+impl<'b> Iterator for EntriesIter<'b> {
+    type Item = Result<(Vec<u8>, &'b [u8]), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.traversal.next()? {
+                Ok(TraversalItem::Entry(key, value)) => return Some(Ok((key, value))),
+                // The child isn't part of the proof.
+                Ok(TraversalItem::MissingNode { .. }) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Configuration to pass to [`next_key`].
+pub struct NextKeyConfig<'a, I> {
+    /// The function returns the smallest key, strictly superior to this one, that has a storage
+    /// value.
+    pub key_before: &'a [u8],
+
+    /// Merkle value (or node value) of the root node of the trie.
+    ///
+    /// > **Note**: The Merkle value and node value are always the same for the root node.
+    pub trie_root_hash: &'a [u8; 32],
+
+    /// List of node values of nodes found in the trie. No specific order is required. All the
+    /// nodes that must be traversed in order to find the next key have to be included in the
+    /// list.
+    pub proof: I,
+}
+
+/// Finds, in the proof, the smallest key with a storage value that is strictly superior to
+/// [`NextKeyConfig::key_before`] in lexicographic order.
+///
+/// Returns `Ok(None)` if the proof proves that there isn't any such key.
+///
+/// Contrary to [`iter_entries`], the children of nodes that are missing from the proof aren't
+/// skipped. If the proof doesn't contain all the nodes that are necessary in order to determine
+/// the next key, [`Error::MissingProofEntry`] is returned. Only the nodes between the root and
+/// the next key, and the nodes in between that might contain a closer key, are accessed.
+pub fn next_key<'b>(
+    config: NextKeyConfig<'_, impl Iterator<Item = &'b [u8]>>,
+) -> Result<Option<Vec<u8>>, Error> {
+    let key_before = nibble::bytes_to_nibbles(config.key_before.iter().copied()).collect();
+    let mut traversal = Traversal::new(config.trie_root_hash, config.proof, Some(key_before))?;
+
+    // Because nodes are visited in lexicographic order of their keys, the first entry that is
+    // found is the one being searched for.
+    match traversal.next() {
+        Some(Ok(TraversalItem::Entry(key, _))) => Ok(Some(key)),
+        Some(Ok(TraversalItem::MissingNode {
+            closest_ancestor_nibbles,
+        })) => Err(Error::MissingProofEntry {
+            closest_ancestor_nibbles,
+        }),
+        Some(Err(err)) => Err(err),
+        None => Ok(None),
+    }
+}
+
+/// Configuration to pass to [`entries_after`].
+pub struct EntriesAfterConfig<'a, I> {
+    /// The function returns the storage entries whose key is strictly superior to this one.
+    pub key_before: &'a [u8],
+
+    /// Merkle value (or node value) of the root node of the trie.
+    ///
+    /// > **Note**: The Merkle value and node value are always the same for the root node.
+    pub trie_root_hash: &'a [u8; 32],
+
+    /// List of node values of nodes found in the trie. No specific order is required.
+    pub proof: I,
+}
+
+/// Finds, in the proof, the storage entries that directly follow [`EntriesAfterConfig::key_before`]
+/// in lexicographic order, without any gap.
+///
+/// Contrary to [`iter_entries`], the iteration stops at the first node that is missing from the
+/// proof, as the proof can't tell whether there exists any storage entry within this node's
+/// subtree. This makes this function suitable for verifying proofs of a range of keys.
+pub fn entries_after<'b>(
+    config: EntriesAfterConfig<'_, impl Iterator<Item = &'b [u8]>>,
+) -> Result<EntriesAfter<'b>, Error> {
+    let key_before = nibble::bytes_to_nibbles(config.key_before.iter().copied()).collect();
+    let traversal = Traversal::new(config.trie_root_hash, config.proof, Some(key_before))?;
+
+    let mut entries = Vec::new();
+    for item in traversal {
+        match item? {
+            TraversalItem::Entry(key, value) => entries.push((key, value)),
+            TraversalItem::MissingNode { .. } => {
+                return Ok(EntriesAfter {
+                    entries,
+                    complete: false,
+                })
+            }
+        }
+    }
+
+    Ok(EntriesAfter {
+        entries,
+        complete: true,
+    })
+}
+
+/// Outcome of [`entries_after`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntriesAfter<'b> {
+    /// Keys and storage values of the entries that directly follow
+    /// [`EntriesAfterConfig::key_before`], in lexicographic order.
+    pub entries: Vec<(Vec<u8>, &'b [u8])>,
+
+    /// `true` if the proof proves that there isn't any storage entry after the ones in
+    /// [`EntriesAfter::entries`]. `false` if the iteration has stopped at a node missing from the
+    /// proof.
+    pub complete: bool,
+}
+
+/// Walks through the nodes of a proof that are reachable from the root, in lexicographic order
+/// of their keys. Shared by [`iter_entries`], [`next_key`], and [`entries_after`].
+struct Traversal<'b> {
+    /// Entries of the proof, indexed by their hash.
+    entries_by_hash: BTreeMap<[u8; 32], &'b [u8]>,
+
+    /// If `Some`, only the entries whose key is strictly superior to this one are yielded, and
+    /// the subtrees that are known to be inferior to it aren't visited.
+    key_before: Option<Vec<nibble::Nibble>>,
+
+    /// Nodes to visit, in reverse order. Each entry contains the key of the parent followed with
+    /// the child index (i.e. the key of the node minus its partial key), and the Merkle value
+    /// of the node, or its node value in the case of the root node.
+    /// Because children are pushed in reverse order, nodes are popped in lexicographic order of
+    /// their keys.
+    to_visit: Vec<(Vec<nibble::Nibble>, &'b [u8])>,
+}
+
+/// Item yielded by a [`Traversal`].
+enum TraversalItem<'b> {
+    /// Key and storage value of a node.
+    Entry(Vec<u8>, &'b [u8]),
+    /// The node value of a node isn't in the proof. Its subtree is skipped.
+    MissingNode {
+        /// Number of nibbles in the key of the parent of the missing node.
+        closest_ancestor_nibbles: usize,
+    },
+}
+
+impl<'b> Traversal<'b> {
+    fn new(
+        trie_root_hash: &[u8; 32],
+        proof: impl Iterator<Item = &'b [u8]>,
+        key_before: Option<Vec<nibble::Nibble>>,
+    ) -> Result<Self, Error> {
+        let entries_by_hash = index_proof(proof);
+
+        let root_node_value = *entries_by_hash
+            .get(trie_root_hash)
+            .ok_or(Error::TrieRootNotFound)?;
+
+        Ok(Traversal {
+            entries_by_hash,
+            key_before,
+            to_visit: vec![(Vec::new(), root_node_value)],
+        })
+    }
+}
+
+impl<'b> Iterator for Traversal<'b> {
+    type Item = Result<TraversalItem<'b>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((mut key, merkle_value)) = self.to_visit.pop() {
+            // The root node is the only one with an empty key, and is stored as a node value.
+            let node_value = if merkle_value.len() < 32 || key.is_empty() {
+                // Node values smaller than 32 bytes are inlined in their parent.
+                merkle_value
+            } else {
+                match <&[u8; 32]>::try_from(merkle_value)
+                    .ok()
+                    .and_then(|hash| self.entries_by_hash.get(hash))
+                {
+                    Some(node_value) => *node_value,
+                    None => {
+                        return Some(Ok(TraversalItem::MissingNode {
+                            closest_ancestor_nibbles: key.len() - 1,
+                        }))
+                    }
+                }
+            };
+
+            let decoded = match proof_node_decode::decode(node_value) {
+                Ok(d) => d,
+                Err(err) => {
+                    // Stop the iteration after an error.
+                    self.to_visit.clear();
+                    return Some(Err(Error::InvalidNodeValue(err)));
+                }
+            };
+            key.extend(decoded.partial_key);
+
+            // Compare the key of the node with `key_before`.
+            let (node_superior, first_child) = match &self.key_before {
+                None => (true, 0),
+                Some(key_before) => {
+                    let common_len = core::cmp::min(key.len(), key_before.len());
+                    let node_superior = match key[..common_len].cmp(&key_before[..common_len]) {
+                        // All the nodes of this subtree are inferior to `key_before`.
+                        core::cmp::Ordering::Less => continue,
+                        core::cmp::Ordering::Greater => true,
+                        core::cmp::Ordering::Equal => key.len() > key_before.len(),
+                    };
+
+                    // Children whose index is inferior to the nibble of `key_before` that
+                    // follows the key of this node are known to be inferior to `key_before` and
+                    // are not visited.
+                    let first_child = if node_superior || key.len() == key_before.len() {
+                        0
+                    } else {
+                        usize::from(u8::from(key_before[key.len()]))
+                    };
+
+                    (node_superior, first_child)
+                }
+            };
+
+            for child_index in (first_child..16).rev() {
+                if let Some(child) = decoded.children[child_index] {
+                    let mut child_key = key.clone();
+                    child_key.push(
+                        nibble::Nibble::try_from(u8::try_from(child_index).unwrap()).unwrap(),
+                    );
+                    self.to_visit.push((child_key, child));
+                }
+            }
+
+            match decoded.storage_value {
+                Some(_) if node_superior && key.len() % 2 == 1 => {
+                    self.to_visit.clear();
+                    return Some(Err(Error::StorageValueAtOddKey));
+                }
+                Some(value) if node_superior => {
+                    return Some(Ok(TraversalItem::Entry(
+                        nibble::nibbles_to_bytes_extend(key.iter().copied()).collect(),
+                        value,
+                    )))
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// Indexes all the entries of the proof by their hash, so that the node value of a child can be
+/// found from its Merkle value.
+///
+/// Note that entries whose node value is smaller than 32 bytes are never referred to by hash,
+/// apart from the root node.
+fn index_proof<'b>(proof: impl Iterator<Item = &'b [u8]>) -> BTreeMap<[u8; 32], &'b [u8]> {
+    proof
+        .map(|proof_entry| {
+            let hash =
+                <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], proof_entry).as_bytes())
+                    .unwrap();
+            (hash, proof_entry)
+        })
+        .collect()
+}
+
+/// Possible error returned by [`verify_proof`], [`iter_entries`], [`next_key`], or
+/// [`entries_after`].
 #[derive(Debug, Clone, derive_more::Display)]
 pub enum Error {
     /// Trie root wasn't found in the proof.
@@ -285,6 +578,11 @@ pub enum Error {
         /// Number of nibbles in the key of the closest ancestor that was found in the proof.
         closest_ancestor_nibbles: usize,
     },
+    /// A node of the proof whose key has an odd number of nibbles has a storage value.
+    #[display(
+        fmt = "A node of the proof whose key has an odd number of nibbles has a storage value"
+    )]
+    StorageValueAtOddKey,
 }
 
 #[cfg(test)]
@@ -383,4 +681,185 @@ mod tests {
 
         assert_eq!(obtained, Some(&[80, 82, 127, 41, 119, 1, 0, 0][..]));
     }
+
+    /// Small trie built by hand, containing the keys `[0x12]`, `[0x12, 0x34]` and `[0x15]`.
+    /// The node values of the leaves are smaller than 32 bytes and thus inlined in their
+    /// parents, and the root node value has an extra child at `[0x16]` in its bitmap whose node
+    /// value is missing from the proof.
+    ///
+    /// Returns the trie root, the root node value, and the node value at `[0x12]`.
+    fn hand_built_trie() -> ([u8; 32], Vec<u8>, Vec<u8>) {
+        let leaf_34 = [0x41, 0x04, 0x04, 0xbb];
+        let branch_2 = {
+            let mut n = vec![0xc0, 0x08, 0x00, 40 << 2];
+            n.extend_from_slice(&[0xaa; 40]);
+            n.push(u8::try_from(leaf_34.len() << 2).unwrap());
+            n.extend_from_slice(&leaf_34);
+            n
+        };
+        let branch_2_hash = blake2_rfc::blake2b::blake2b(32, &[], &branch_2);
+        let leaf_5 = [0x40, 0x04, 0xcc];
+        let root = {
+            let mut n = vec![0x81, 0x01, 0x64, 0x00];
+            n.push(32 << 2);
+            n.extend_from_slice(branch_2_hash.as_bytes());
+            n.push(u8::try_from(leaf_5.len() << 2).unwrap());
+            n.extend_from_slice(&leaf_5);
+            n.push(32 << 2);
+            n.extend_from_slice(&[0xff; 32]);
+            n
+        };
+        let trie_root =
+            <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], &root).as_bytes()).unwrap();
+        (trie_root, root, branch_2)
+    }
+
+    #[test]
+    fn iter_entries_works() {
+        let (trie_root, root, branch_2) = hand_built_trie();
+
+        // Entry that isn't reachable from the root, and that must be ignored.
+        let unrelated = vec![0x41, 0x05, 0x08, 0x11, 0x22];
+
+        let proof = [unrelated, branch_2.clone(), root];
+        let obtained = super::iter_entries(super::IterEntriesConfig {
+            trie_root_hash: &trie_root,
+            proof: proof.iter().map(|p| &p[..]),
+        })
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+        assert_eq!(
+            obtained,
+            vec![
+                (vec![0x12], &[0xaa; 40][..]),
+                (vec![0x12, 0x34], &[0xbb][..]),
+                (vec![0x15], &[0xcc][..]),
+            ]
+        );
+
+        // Removing the node value of the root must lead to an error.
+        assert!(matches!(
+            super::iter_entries(super::IterEntriesConfig {
+                trie_root_hash: &trie_root,
+                proof: [&branch_2[..]].into_iter(),
+            }),
+            Err(super::Error::TrieRootNotFound)
+        ));
+    }
+
+    #[test]
+    fn next_key_works() {
+        let (trie_root, root, branch_2) = hand_built_trie();
+        let proof = [branch_2, root.clone()];
+        let next_key = |key_before: &[u8]| {
+            super::next_key(super::NextKeyConfig {
+                key_before,
+                trie_root_hash: &trie_root,
+                proof: proof.iter().map(|p| &p[..]),
+            })
+        };
+
+        assert_eq!(next_key(&[]).unwrap(), Some(vec![0x12]));
+        assert_eq!(next_key(&[0x11, 0xff]).unwrap(), Some(vec![0x12]));
+        assert_eq!(next_key(&[0x12]).unwrap(), Some(vec![0x12, 0x34]));
+        assert_eq!(next_key(&[0x12, 0x34]).unwrap(), Some(vec![0x15]));
+        assert_eq!(next_key(&[0x13]).unwrap(), Some(vec![0x15]));
+
+        // The subtree at `[0x16]` isn't in the proof.
+        assert!(matches!(
+            next_key(&[0x15]),
+            Err(super::Error::MissingProofEntry { .. })
+        ));
+        assert!(matches!(
+            next_key(&[0x16, 0x01]),
+            Err(super::Error::MissingProofEntry { .. })
+        ));
+
+        // No key is superior to `[0x17]`.
+        assert_eq!(next_key(&[0x17]).unwrap(), None);
+        assert_eq!(next_key(&[0x20]).unwrap(), None);
+
+        // Without the node value at `[0x12]`, the keys below it can't be determined, but the
+        // keys after it still can.
+        let next_key_without_branch = |key_before: &[u8]| {
+            super::next_key(super::NextKeyConfig {
+                key_before,
+                trie_root_hash: &trie_root,
+                proof: [&root[..]].into_iter(),
+            })
+        };
+        assert!(matches!(
+            next_key_without_branch(&[0x11]),
+            Err(super::Error::MissingProofEntry { .. })
+        ));
+        assert_eq!(next_key_without_branch(&[0x13]).unwrap(), Some(vec![0x15]));
+
+        assert!(matches!(
+            super::next_key(super::NextKeyConfig {
+                key_before: &[],
+                trie_root_hash: &[0; 32],
+                proof: proof.iter().map(|p| &p[..]),
+            }),
+            Err(super::Error::TrieRootNotFound)
+        ));
+    }
+
+    #[test]
+    fn entries_after_works() {
+        let (trie_root, root, branch_2) = hand_built_trie();
+        let proof = [branch_2, root.clone()];
+        let entries_after = |key_before: &[u8]| {
+            super::entries_after(super::EntriesAfterConfig {
+                key_before,
+                trie_root_hash: &trie_root,
+                proof: proof.iter().map(|p| &p[..]),
+            })
+            .unwrap()
+        };
+
+        // The subtree at `[0x16]` isn't in the proof, and the iteration stops there.
+        assert_eq!(
+            entries_after(&[]),
+            super::EntriesAfter {
+                entries: vec![
+                    (vec![0x12], &[0xaa; 40][..]),
+                    (vec![0x12, 0x34], &[0xbb][..]),
+                    (vec![0x15], &[0xcc][..]),
+                ],
+                complete: false,
+            }
+        );
+        assert_eq!(
+            entries_after(&[0x12, 0x34]),
+            super::EntriesAfter {
+                entries: vec![(vec![0x15], &[0xcc][..])],
+                complete: false,
+            }
+        );
+        assert_eq!(
+            entries_after(&[0x17]),
+            super::EntriesAfter {
+                entries: Vec::new(),
+                complete: true,
+            }
+        );
+
+        // Without the node value at `[0x12]`, nothing after `[0x11]` can be found, but the
+        // entries after `[0x13]` still can.
+        let without_branch = |key_before: &[u8]| {
+            super::entries_after(super::EntriesAfterConfig {
+                key_before,
+                trie_root_hash: &trie_root,
+                proof: [&root[..]].into_iter(),
+            })
+            .unwrap()
+        };
+        assert!(without_branch(&[0x11]).entries.is_empty());
+        assert_eq!(
+            without_branch(&[0x13]).entries,
+            vec![(vec![0x15], &[0xcc][..])]
+        );
+    }
 }
@@ -21,6 +21,7 @@
 use core::str;
 
 pub(crate) mod leb128;
+pub(crate) mod scale;
 
 /// Implementation of the `BuildHasher` trait for the sip hasher.
 ///
@@ -62,7 +63,10 @@ pub(crate) fn nom_option_decode<'a, O, E: nom::error::ParseError<&'a [u8]>>(
 pub(crate) fn nom_bytes_decode<'a, E: nom::error::ParseError<&'a [u8]>>(
     bytes: &'a [u8],
 ) -> nom::IResult<&'a [u8], &'a [u8], E> {
-    nom::multi::length_data(crate::util::nom_scale_compact_usize)(bytes)
+    match scale::decode_length_prefixed(bytes) {
+        Ok((value, rest)) => Ok((rest, value)),
+        Err(err) => Err(nom_error_from_scale(bytes, err)),
+    }
 }
 
 /// Decodes a SCALE-encoded string.
@@ -90,109 +94,47 @@ pub(crate) fn nom_bool_decode<'a, E: nom::error::ParseError<&'a [u8]>>(
 
 /// Decodes a SCALE-compact-encoded usize.
 ///
+/// Non-canonical encodings are rejected. See [`scale::decode_compact_usize`].
+///
 /// > **Note**: When using this function outside of a `nom` "context", you might have to explicit
 /// >           the type of `E`. Use `nom::error::Error<&[u8]>`.
 pub(crate) fn nom_scale_compact_usize<'a, E: nom::error::ParseError<&'a [u8]>>(
     bytes: &'a [u8],
 ) -> nom::IResult<&'a [u8], usize, E> {
-    if bytes.is_empty() {
-        return Err(nom::Err::Error(nom::error::make_error(
-            bytes,
-            nom::error::ErrorKind::Eof,
-        )));
+    match scale::decode_compact_usize(bytes) {
+        Ok((value, rest)) => Ok((rest, value)),
+        Err(err) => Err(nom_error_from_scale(bytes, err)),
     }
+}
 
-    match bytes[0] & 0b11 {
-        0b00 => {
-            let value = bytes[0] >> 2;
-            Ok((&bytes[1..], usize::from(value)))
-        }
-        0b01 => {
-            if bytes.len() < 2 {
-                return Err(nom::Err::Error(nom::error::make_error(
-                    bytes,
-                    nom::error::ErrorKind::Eof,
-                )));
-            }
-
-            let byte0 = u16::from(bytes[0] >> 2);
-            let byte1 = u16::from(bytes[1]);
-            let value = (byte1 << 6) | byte0;
-            Ok((&bytes[2..], usize::from(value)))
-        }
-        0b10 => {
-            if bytes.len() < 4 {
-                return Err(nom::Err::Error(nom::error::make_error(
-                    bytes,
-                    nom::error::ErrorKind::Eof,
-                )));
-            }
+/// Decodes a SCALE-compact-encoded number of elements of a collection whose elements each occupy
+/// at least `min_item_size` bytes.
+///
+/// Lengths that exceed what the rest of the input can possibly contain are rejected. See
+/// [`scale::decode_collection_len`].
+pub(crate) fn nom_scale_collection_len<'a, E: nom::error::ParseError<&'a [u8]>>(
+    min_item_size: usize,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], usize, E> {
+    move |bytes| match scale::decode_collection_len(bytes, min_item_size) {
+        Ok((value, rest)) => Ok((rest, value)),
+        Err(err) => Err(nom_error_from_scale(bytes, err)),
+    }
+}
 
-            // The code below uses `checked_shl` because using plain `<<` sometimes panics with
-            // "attempt to shift left with overflow", even though it is mathematically impossible
-            // for this to happen. I strongly suspect a miscompilation when using `<<` instead of
-            // `checked_sub`, but haven't managed to isolate the problem in a reproducible case.
-            let byte0 = u32::from(bytes[0] >> 2);
-            let byte1 = u32::from(bytes[1]).checked_shl(6).unwrap();
-            let byte2 = u32::from(bytes[2]).checked_shl(14).unwrap();
-            let byte3 = u32::from(bytes[3]).checked_shl(22).unwrap();
-
-            let value = byte3 | byte2 | byte1 | byte0;
-            let value = match usize::try_from(value) {
-                Ok(v) => v,
-                Err(_) => {
-                    return Err(nom::Err::Error(nom::error::make_error(
-                        bytes,
-                        nom::error::ErrorKind::Satisfy,
-                    )))
-                }
-            };
-            Ok((&bytes[4..], value))
+/// Converts a [`scale::Error`] into a `nom` error.
+fn nom_error_from_scale<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+    err: scale::Error,
+) -> nom::Err<E> {
+    let kind = match err {
+        scale::Error::UnexpectedEof => nom::error::ErrorKind::Eof,
+        scale::Error::NonCanonicalCompact | scale::Error::CompactOverflow => {
+            nom::error::ErrorKind::Satisfy
         }
-        0b11 => {
-            let num_bytes = usize::from(bytes[0] >> 2) + 4;
-
-            if bytes.len() < num_bytes + 1 {
-                return Err(nom::Err::Error(nom::error::make_error(
-                    bytes,
-                    nom::error::ErrorKind::Eof,
-                )));
-            }
-
-            // Value is invalid if highest byte is 0.
-            if bytes[num_bytes] == 0 {
-                return Err(nom::Err::Error(nom::error::make_error(
-                    bytes,
-                    nom::error::ErrorKind::Satisfy,
-                )));
-            }
-
-            let mut out_value = 0;
-            let mut shift = 0u32;
-            for byte_index in 1..=num_bytes {
-                out_value |= match 1usize
-                    .checked_shl(shift)
-                    .and_then(|shl| usize::from(bytes[byte_index]).checked_mul(shl))
-                {
-                    Some(v) => v,
-                    None => {
-                        // Overflow. The SCALE-encoded value is too large to fit a `usize`.
-                        return Err(nom::Err::Error(nom::error::make_error(
-                            bytes,
-                            nom::error::ErrorKind::Satisfy,
-                        )));
-                    }
-                };
-
-                // Overflows aren't properly handled because `out_value` is expected to overflow
-                // way sooner than `shift`.
-                shift += 8;
-            }
+        scale::Error::CollectionTooLarge => nom::error::ErrorKind::TooLarge,
+    };
 
-            Ok((&bytes[num_bytes + 1..], out_value))
-        }
-        _ => unreachable!(),
-    }
+    nom::Err::Error(nom::error::make_error(bytes, kind))
 }
 
 macro_rules! encode_scale_compact {
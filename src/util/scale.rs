@@ -0,0 +1,260 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Decoding of SCALE-encoded primitives.
+//!
+//! See <https://docs.substrate.io/reference/scale-codec/>.
+//!
+//! Contrary to a naive implementation, the functions in this module reject non-canonical
+//! encodings. In the case of compact numbers, a value must always be encoded in the smallest
+//! possible mode. In other words, there is only one accepted encoding per value, in accordance
+//! with the reference SCALE codec implementation.
+
+/// Decodes a SCALE-compact-encoded `u64` at the start of the given slice.
+///
+/// Returns the decoded value and the rest of the slice.
+pub(crate) fn decode_compact_u64(input: &[u8]) -> Result<(u64, &[u8]), Error> {
+    let first_byte = *input.first().ok_or(Error::UnexpectedEof)?;
+
+    match first_byte & 0b11 {
+        0b00 => Ok((u64::from(first_byte >> 2), &input[1..])),
+        0b01 => {
+            if input.len() < 2 {
+                return Err(Error::UnexpectedEof);
+            }
+
+            let value = u64::from(u16::from_le_bytes([input[0], input[1]]) >> 2);
+            if value < (1 << 6) {
+                return Err(Error::NonCanonicalCompact);
+            }
+
+            Ok((value, &input[2..]))
+        }
+        0b10 => {
+            if input.len() < 4 {
+                return Err(Error::UnexpectedEof);
+            }
+
+            let value =
+                u64::from(u32::from_le_bytes([input[0], input[1], input[2], input[3]]) >> 2);
+            if value < (1 << 14) {
+                return Err(Error::NonCanonicalCompact);
+            }
+
+            Ok((value, &input[4..]))
+        }
+        0b11 => {
+            let num_bytes = usize::from(first_byte >> 2) + 4;
+            if num_bytes > 8 {
+                return Err(Error::CompactOverflow);
+            }
+
+            if input.len() < num_bytes + 1 {
+                return Err(Error::UnexpectedEof);
+            }
+
+            let mut value_bytes = [0; 8];
+            value_bytes[..num_bytes].copy_from_slice(&input[1..=num_bytes]);
+            let value = u64::from_le_bytes(value_bytes);
+
+            // The value must be encoded with the minimum number of bytes, and must not fit in
+            // the smaller modes.
+            if input[num_bytes] == 0 || value < (1 << 30) {
+                return Err(Error::NonCanonicalCompact);
+            }
+
+            Ok((value, &input[num_bytes + 1..]))
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Decodes a SCALE-compact-encoded `usize` at the start of the given slice.
+///
+/// Returns the decoded value and the rest of the slice.
+pub(crate) fn decode_compact_usize(input: &[u8]) -> Result<(usize, &[u8]), Error> {
+    let (value, rest) = decode_compact_u64(input)?;
+    let value = usize::try_from(value).map_err(|_| Error::CompactOverflow)?;
+    Ok((value, rest))
+}
+
+/// Decodes a SCALE-encoded compact length followed with that number of bytes, at the start of
+/// the given slice.
+///
+/// Returns the bytes whose length is indicated by the prefix, and the rest of the slice.
+pub(crate) fn decode_length_prefixed(input: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    let (len, rest) = decode_compact_usize(input)?;
+    if rest.len() < len {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(rest.split_at(len))
+}
+
+/// Decodes a SCALE-compact-encoded number of elements of a collection, at the start of the given
+/// slice.
+///
+/// Each element of the collection is known to occupy at least `min_item_size` bytes once
+/// encoded. Lengths that couldn't possibly fit in the rest of the input are rejected, which makes
+/// it safe to pre-allocate a container of the returned length.
+///
+/// Returns the number of elements and the rest of the slice.
+///
+/// # Panic
+///
+/// Panics if `min_item_size` is 0.
+///
+pub(crate) fn decode_collection_len(
+    input: &[u8],
+    min_item_size: usize,
+) -> Result<(usize, &[u8]), Error> {
+    assert_ne!(min_item_size, 0);
+    let (len, rest) = decode_compact_usize(input)?;
+    if len > rest.len() / min_item_size {
+        return Err(Error::CollectionTooLarge);
+    }
+    Ok((len, rest))
+}
+
+/// Error potentially returned by the functions of this module.
+#[derive(Debug, derive_more::Display, Clone, PartialEq, Eq)]
+pub(crate) enum Error {
+    /// Input is too short.
+    UnexpectedEof,
+    /// Compact number doesn't use the smallest possible encoding.
+    NonCanonicalCompact,
+    /// Compact number is too large to fit in the requested type.
+    CompactOverflow,
+    /// Number of elements of a collection is larger than what the input can possibly contain.
+    CollectionTooLarge,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_collection_len, decode_compact_u64, decode_length_prefixed, Error};
+
+    #[test]
+    fn mode_boundaries() {
+        for value in [
+            0u64,
+            63,
+            64,
+            (1 << 14) - 1,
+            1 << 14,
+            (1 << 30) - 1,
+            1 << 30,
+            (1 << 32) - 1,
+            1 << 32,
+            u64::MAX,
+        ] {
+            let mut encoded = crate::util::encode_scale_compact_u64(value)
+                .as_ref()
+                .to_vec();
+            encoded.push(0xff);
+            assert_eq!(decode_compact_u64(&encoded), Ok((value, &[0xff][..])));
+        }
+    }
+
+    #[test]
+    fn modes_lengths() {
+        assert_eq!(decode_compact_u64(&[63 << 2]), Ok((63, &[][..])));
+        assert_eq!(decode_compact_u64(&[0b01, 1]), Ok((64, &[][..])));
+        assert_eq!(decode_compact_u64(&[0b10, 0, 1, 0]), Ok((1 << 14, &[][..])));
+        assert_eq!(
+            decode_compact_u64(&[0b11, 0, 0, 0, 0b100_0000]),
+            Ok((1 << 30, &[][..]))
+        );
+    }
+
+    #[test]
+    fn non_canonical_rejected() {
+        // 0 encoded in two-bytes mode.
+        assert_eq!(
+            decode_compact_u64(&[0b01, 0]),
+            Err(Error::NonCanonicalCompact)
+        );
+        // 63 encoded in two-bytes mode.
+        assert_eq!(
+            decode_compact_u64(&[(63 << 2) | 0b01, 0]),
+            Err(Error::NonCanonicalCompact)
+        );
+        // 2^14 - 1 encoded in four-bytes mode.
+        assert_eq!(
+            decode_compact_u64(&[0b1111_1110, 0xff, 0, 0]),
+            Err(Error::NonCanonicalCompact)
+        );
+        // 2^30 - 1 encoded in big-integer mode.
+        assert_eq!(
+            decode_compact_u64(&[0b11, 0xff, 0xff, 0xff, 0x3f]),
+            Err(Error::NonCanonicalCompact)
+        );
+        // 2^32 encoded with a superfluous zero byte.
+        assert_eq!(
+            decode_compact_u64(&[0b1011, 0, 0, 0, 0, 1, 0]),
+            Err(Error::NonCanonicalCompact)
+        );
+    }
+
+    #[test]
+    fn overflow_and_eof() {
+        assert_eq!(
+            decode_compact_u64(&[0b10111, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+            Err(Error::CompactOverflow)
+        );
+        assert_eq!(decode_compact_u64(&[]), Err(Error::UnexpectedEof));
+        assert_eq!(decode_compact_u64(&[0b01]), Err(Error::UnexpectedEof));
+        assert_eq!(decode_compact_u64(&[0b10, 0, 1]), Err(Error::UnexpectedEof));
+        assert_eq!(
+            decode_compact_u64(&[0b11, 0, 0, 0]),
+            Err(Error::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn length_prefixed() {
+        assert_eq!(
+            decode_length_prefixed(&[3 << 2, 1, 2, 3, 4]),
+            Ok((&[1, 2, 3][..], &[4][..]))
+        );
+        assert_eq!(
+            decode_length_prefixed(&[3 << 2, 1, 2]),
+            Err(Error::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn collection_len() {
+        assert_eq!(
+            decode_collection_len(&[2 << 2, 1, 2, 3, 4], 2),
+            Ok((2, &[1, 2, 3, 4][..]))
+        );
+        assert_eq!(
+            decode_collection_len(&[3 << 2, 1, 2, 3, 4], 2),
+            Err(Error::CollectionTooLarge)
+        );
+        // Length prefix of `u32::MAX` elements in an input of a few bytes.
+        assert_eq!(
+            decode_collection_len(&[0b11, 0xff, 0xff, 0xff, 0xff, 0], 1),
+            Err(Error::CollectionTooLarge)
+        );
+        // Length prefix of `u64::MAX` elements, which would overflow when multiplied.
+        let mut encoded = crate::util::encode_scale_compact_u64(u64::MAX)
+            .as_ref()
+            .to_vec();
+        encoded.extend_from_slice(&[0; 32]);
+        assert!(decode_collection_len(&encoded, 32).is_err());
+    }
+}
@@ -65,9 +65,22 @@
 //! No matter the validity of a block, keep in mind that a reorg (a "reorganization") might
 //! happen, and many valid blocks don't get finalized.
 //!
+//! # Time
+//!
+//! Verifying a block requires knowing the current time, as blocks whose slot is in the future
+//! must be rejected.
+//!
+//! The code in this module never reads the time from the operating system. Instead, all the
+//! verification functions are passed the current time as a parameter, in the form of a
+//! `now_from_unix_epoch` field or parameter. This makes the verification entirely deterministic
+//! and lets tests pass a fixed or manually-advanced time. It is the responsibility of the API
+//! user to obtain this value, for example from the system clock or, in a browser environment,
+//! from JavaScript.
+//!
 
 pub mod aura;
 pub mod babe;
+pub mod diagnostic;
 pub mod header_body;
 pub mod header_only;
 pub mod inherents;
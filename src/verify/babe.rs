@@ -166,6 +166,15 @@ pub struct VerifyConfig<'a> {
     /// The [`chain_information::BabeEpochInformationRef::start_slot_number`] must be `None` if
     /// and only if the [`chain_information::BabeEpochInformationRef::epoch_index`] is `0`.
     pub parent_block_next_epoch: chain_information::BabeEpochInformationRef<'a>,
+
+    /// If `true`, the signature found in the seal of the header and the VRF proof of primary
+    /// slot claims aren't verified. The presence of the seal, the authority index, and the other
+    /// fields of the header are still verified.
+    ///
+    /// > **Note**: This makes it possible for anyone to produce blocks that pass the
+    /// >           verification. It must only be set to `true` if the source of the header is
+    /// >           trusted, for example a database previously filled by the local node.
+    pub skip_seal_verification: bool,
 }
 
 /// Information yielded back after successfully verifying a block.
@@ -231,25 +240,28 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
     // TODO: handle OnDisabled
 
     // Gather the BABE-related information from the header.
-    let (authority_index, slot_number, primary_slot_claim, vrf_output_and_proof) =
-        match config.header.digest.babe_pre_runtime() {
-            Some(header::BabePreDigestRef::Primary(digest)) => (
-                digest.authority_index,
-                digest.slot_number,
-                true,
-                Some((*digest.vrf_output, *digest.vrf_proof)),
-            ),
-            Some(header::BabePreDigestRef::SecondaryPlain(digest)) => {
-                (digest.authority_index, digest.slot_number, false, None)
-            }
-            Some(header::BabePreDigestRef::SecondaryVRF(digest)) => (
-                digest.authority_index,
-                digest.slot_number,
-                false,
-                Some((*digest.vrf_output, *digest.vrf_proof)),
-            ),
-            None => return Err(VerifyError::MissingPreRuntimeDigest),
-        };
+    let pre_digest = match config.header.digest.babe_pre_runtime() {
+        Some(pre_digest) => pre_digest,
+        None => return Err(VerifyError::MissingPreRuntimeDigest),
+    };
+    let (authority_index, slot_number, primary_slot_claim, vrf_output_and_proof) = match &pre_digest
+    {
+        header::BabePreDigestRef::Primary(digest) => (
+            digest.authority_index,
+            digest.slot_number,
+            true,
+            Some((*digest.vrf_output, *digest.vrf_proof)),
+        ),
+        header::BabePreDigestRef::SecondaryPlain(digest) => {
+            (digest.authority_index, digest.slot_number, false, None)
+        }
+        header::BabePreDigestRef::SecondaryVRF(digest) => (
+            digest.authority_index,
+            digest.slot_number,
+            false,
+            Some((*digest.vrf_output, *digest.vrf_proof)),
+        ),
+    };
 
     // Make sure that the slot of the block is increasing compared to its parent's.
     let parent_slot_number = if config.parent_block_header.number != 0 {
@@ -322,22 +334,15 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
     //       execute the runtime
 
     // Check that the claim is one of the allowed slot types.
-    match (
-        block_epoch_info.allowed_slots,
-        primary_slot_claim,
-        vrf_output_and_proof,
-    ) {
-        (_, true, None) => unreachable!(),
-        (_, true, Some(_)) => {}
-        (header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots, false, None) => {}
-        (header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots, false, Some(_)) => {}
-        _ => return Err(VerifyError::ForbiddenSlotType),
+    if !block_epoch_info.allowed_slots.allows(&pre_digest) {
+        return Err(VerifyError::ForbiddenSlotType);
     }
 
     // Signature contained in the seal is copied and stored for later.
     let seal_signature = match config.header.digest.babe_seal() {
+        Some(_) if config.skip_seal_verification => None,
         Some(seal) => {
-            schnorrkel::Signature::from_bytes(seal).map_err(|_| VerifyError::BadSignature)?
+            Some(schnorrkel::Signature::from_bytes(seal).map_err(|_| VerifyError::BadSignature)?)
         }
         None => return Err(VerifyError::MissingSeal),
     };
@@ -400,55 +405,58 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
         .nth(usize::try_from(authority_index).map_err(|_| VerifyError::InvalidAuthorityIndex)?)
         .ok_or(VerifyError::InvalidAuthorityIndex)?;
 
-    // This `unwrap()` can only panic if `public_key` is the wrong length, which we know can't
-    // happen as it's of type `[u8; 32]`.
-    let signing_public_key =
-        schnorrkel::PublicKey::from_bytes(signing_authority.public_key).unwrap();
-
-    // Now verifying the signature in the seal.
-    signing_public_key
-        .verify_simple(b"substrate", &pre_seal_hash, &seal_signature)
-        .map_err(|_| VerifyError::BadSignature)?;
-
-    // Now verify the VRF output and proof, if any.
-    // The lack of VRF output/proof in the header is checked when we check whether the slot
-    // type is allowed by the current configuration.
-    if let Some((vrf_output, vrf_proof)) = vrf_output_and_proof {
-        // In order to verify the VRF output, we first need to create a transcript containing all
-        // the data to verify the VRF against.
-        let transcript = {
-            let mut transcript = merlin::Transcript::new(&b"BABE"[..]);
-            transcript.append_u64(b"slot number", slot_number);
-            transcript.append_u64(b"current epoch", block_epoch_info.epoch_index);
-            transcript.append_message(b"chain randomness", &block_epoch_info.randomness[..]);
-            transcript
-        };
-
-        // These `unwrap()`s can only panic if `vrf_output` or `vrf_proof` are of the wrong
-        // length, which we know can't happen as they're of types `[u8; 32]` and `[u8; 64]`.
-        let vrf_output = schnorrkel::vrf::VRFPreOut::from_bytes(&vrf_output[..]).unwrap();
-        let vrf_proof = schnorrkel::vrf::VRFProof::from_bytes(&vrf_proof[..]).unwrap();
-
-        let (vrf_in_out, _) = signing_public_key
-            .vrf_verify(transcript, &vrf_output, &vrf_proof)
-            .map_err(|_| VerifyError::BadVrfProof)?;
-
-        // If this is a primary slot claim, we need to make sure that the VRF output is below
-        // a certain threshold, otherwise all the authorities could claim all the slots.
-        if primary_slot_claim {
-            let threshold = calculate_primary_threshold(
-                block_epoch_info.c,
-                block_epoch_info.authorities.clone().map(|a| a.weight),
-                signing_authority.weight,
-            );
-            if u128::from_le_bytes(vrf_in_out.make_bytes::<[u8; 16]>(b"substrate-babe-vrf"))
-                >= threshold
-            {
-                return Err(VerifyError::OverPrimaryClaimThreshold);
+    // Verifying the signature and the VRF output is skipped if the seal has been ignored.
+    if let Some(seal_signature) = seal_signature {
+        // This `unwrap()` can only panic if `public_key` is the wrong length, which we know can't
+        // happen as it's of type `[u8; 32]`.
+        let signing_public_key =
+            schnorrkel::PublicKey::from_bytes(signing_authority.public_key).unwrap();
+
+        // Now verifying the signature in the seal.
+        signing_public_key
+            .verify_simple(b"substrate", &pre_seal_hash, &seal_signature)
+            .map_err(|_| VerifyError::BadSignature)?;
+
+        // Now verify the VRF output and proof, if any.
+        // The lack of VRF output/proof in the header is checked when we check whether the slot
+        // type is allowed by the current configuration.
+        if let Some((vrf_output, vrf_proof)) = vrf_output_and_proof {
+            // In order to verify the VRF output, we first need to create a transcript containing
+            // all the data to verify the VRF against.
+            let transcript = {
+                let mut transcript = merlin::Transcript::new(&b"BABE"[..]);
+                transcript.append_u64(b"slot number", slot_number);
+                transcript.append_u64(b"current epoch", block_epoch_info.epoch_index);
+                transcript.append_message(b"chain randomness", &block_epoch_info.randomness[..]);
+                transcript
+            };
+
+            // These `unwrap()`s can only panic if `vrf_output` or `vrf_proof` are of the wrong
+            // length, which we know can't happen as they're of types `[u8; 32]` and `[u8; 64]`.
+            let vrf_output = schnorrkel::vrf::VRFPreOut::from_bytes(&vrf_output[..]).unwrap();
+            let vrf_proof = schnorrkel::vrf::VRFProof::from_bytes(&vrf_proof[..]).unwrap();
+
+            let (vrf_in_out, _) = signing_public_key
+                .vrf_verify(transcript, &vrf_output, &vrf_proof)
+                .map_err(|_| VerifyError::BadVrfProof)?;
+
+            // If this is a primary slot claim, we need to make sure that the VRF output is below
+            // a certain threshold, otherwise all the authorities could claim all the slots.
+            if primary_slot_claim {
+                let threshold = calculate_primary_threshold(
+                    block_epoch_info.c,
+                    block_epoch_info.authorities.clone().map(|a| a.weight),
+                    signing_authority.weight,
+                );
+                if u128::from_le_bytes(vrf_in_out.make_bytes::<[u8; 16]>(b"substrate-babe-vrf"))
+                    >= threshold
+                {
+                    return Err(VerifyError::OverPrimaryClaimThreshold);
+                }
             }
+        } else {
+            debug_assert!(!primary_slot_claim);
         }
-    } else {
-        debug_assert!(!primary_slot_claim);
     }
 
     // Each slot can be claimed by one specific authority in what is called a secondary slot
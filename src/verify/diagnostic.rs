@@ -0,0 +1,203 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Diagnostic about a block that has failed to verify.
+//!
+//! The errors returned by the verification functions only indicate what is wrong with a block.
+//! In order to debug a verification failure, it is often useful to also know what the verifier
+//! has seen. A [`Diagnostic`] contains this information.
+//!
+//! Building a [`Diagnostic`] is opt-in and has no cost on the verification itself. After a
+//! verification has failed, call [`super::header_only::diagnostic`] or
+//! [`super::header_body::diagnostic`], passing the same header and consensus configuration as
+//! the ones that were passed to the verification.
+
+use crate::{chain::chain_information, header};
+
+/// Information about a block that has failed to verify.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// Hash of the header of the block.
+    pub block_hash: [u8; 32],
+
+    /// Height of the block.
+    pub block_number: u64,
+
+    /// Slot number claimed in the pre-runtime digest item of the block. `None` if the chain
+    /// doesn't use slots or if the block doesn't have any valid pre-runtime digest item.
+    pub claimed_slot: Option<u64>,
+
+    /// Index of the authority that pretends to have authored the block, as found in the
+    /// pre-runtime digest item of the block. `None` if the consensus engine doesn't put this
+    /// index in blocks, which is the case for Aura, or if the block doesn't have any valid
+    /// pre-runtime digest item.
+    pub claimed_authority_index: Option<u32>,
+
+    /// Public key of the authority that is expected to have authored the block, given the
+    /// authorities known by the verifier and the information in the pre-runtime digest item.
+    /// `None` if it couldn't be determined.
+    pub expected_author: Option<[u8; 32]>,
+
+    /// State root found in the header of the block.
+    pub state_root_in_header: [u8; 32],
+
+    /// State root calculated by executing the block. `None` if the block hasn't been executed or
+    /// if its execution has failed before the state root could be calculated.
+    pub calculated_state_root: Option<[u8; 32]>,
+
+    /// Digest items found in the header of the block.
+    pub digest: header::Digest,
+}
+
+/// Consensus-related information known by the verifier.
+pub(super) enum DiagnosticConsensus<'a> {
+    AllAuthorized,
+    Aura {
+        current_authorities: header::AuraAuthoritiesIter<'a>,
+    },
+    Babe {
+        parent_block_epoch: Option<chain_information::BabeEpochInformationRef<'a>>,
+        parent_block_next_epoch: chain_information::BabeEpochInformationRef<'a>,
+    },
+}
+
+/// Builds a [`Diagnostic`] about the given block.
+pub(super) fn build(
+    block_header: &header::HeaderRef,
+    consensus: DiagnosticConsensus,
+    calculated_state_root: Option<[u8; 32]>,
+) -> Diagnostic {
+    let (claimed_slot, claimed_authority_index, expected_author) = match consensus {
+        DiagnosticConsensus::AllAuthorized => (None, None, None),
+        DiagnosticConsensus::Aura {
+            mut current_authorities,
+        } => match block_header.digest.aura_pre_runtime() {
+            Some(pre_digest) => {
+                // In Aura, the author of a block is determined by its slot number.
+                let expected_author = u64::try_from(current_authorities.len())
+                    .ok()
+                    .filter(|len| *len != 0)
+                    .and_then(|len| usize::try_from(pre_digest.slot_number % len).ok())
+                    .and_then(|index| current_authorities.nth(index))
+                    .map(|authority| *authority.public_key);
+                (Some(pre_digest.slot_number), None, expected_author)
+            }
+            None => (None, None, None),
+        },
+        DiagnosticConsensus::Babe {
+            parent_block_epoch,
+            parent_block_next_epoch,
+        } => match block_header.digest.babe_pre_runtime() {
+            Some(pre_digest) => {
+                // Blocks that contain an epoch transition are the first block of the epoch
+                // that follows the one of their parent.
+                let block_epoch = match parent_block_epoch {
+                    Some(epoch) if block_header.digest.babe_epoch_information().is_none() => epoch,
+                    _ => parent_block_next_epoch,
+                };
+                let expected_author = usize::try_from(pre_digest.authority_index())
+                    .ok()
+                    .and_then(|index| block_epoch.authorities.clone().nth(index))
+                    .map(|authority| *authority.public_key);
+                (
+                    Some(pre_digest.slot_number()),
+                    Some(pre_digest.authority_index()),
+                    expected_author,
+                )
+            }
+            None => (None, None, None),
+        },
+    };
+
+    Diagnostic {
+        block_hash: block_header.hash(),
+        block_number: block_header.number,
+        claimed_slot,
+        claimed_authority_index,
+        expected_author,
+        state_root_in_header: *block_header.state_root,
+        calculated_state_root,
+        digest: block_header.digest.clone().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{aura, header_only};
+    use crate::header;
+    use core::{num::NonZeroU64, time::Duration};
+
+    #[test]
+    fn bad_aura_block() {
+        let authorities = (0..4u8)
+            .map(|n| header::AuraAuthority {
+                public_key: [n; 32],
+            })
+            .collect::<Vec<_>>();
+
+        let parent_block_header = header::Header {
+            parent_hash: [0; 32],
+            number: 0,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+
+        // The block claims slot 10, but doesn't have any seal.
+        let digest_items = [header::DigestItem::AuraPreDigest(header::AuraPreDigest {
+            slot_number: 10,
+        })];
+        let block_header = header::Header {
+            parent_hash: parent_block_header.hash(),
+            number: 1,
+            state_root: [0xaa; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::from_slice(&digest_items).unwrap().into(),
+        };
+
+        let consensus = || header_only::ConfigConsensus::Aura {
+            current_authorities: header::AuraAuthoritiesIter::from_slice(&authorities),
+            slot_duration: NonZeroU64::new(6000).unwrap(),
+            now_from_unix_epoch: Duration::from_secs(3600),
+        };
+
+        let error = match header_only::verify(header_only::Config {
+            parent_block_header: (&parent_block_header).into(),
+            block_header: (&block_header).into(),
+            consensus: consensus(),
+            skip_seal_verification: false,
+        }) {
+            Err(err) => err,
+            Ok(_) => panic!(),
+        };
+
+        assert!(matches!(
+            error,
+            header_only::Error::AuraVerification(aura::VerifyError::MissingSeal)
+        ));
+
+        let diagnostic = header_only::diagnostic((&block_header).into(), consensus());
+        assert_eq!(diagnostic.block_hash, block_header.hash());
+        assert_eq!(diagnostic.block_number, 1);
+        assert_eq!(diagnostic.claimed_slot, Some(10));
+        assert_eq!(diagnostic.claimed_authority_index, None);
+        assert_eq!(diagnostic.expected_author, Some([2; 32]));
+        assert_eq!(diagnostic.state_root_in_header, [0xaa; 32]);
+        assert_eq!(diagnostic.calculated_state_root, None);
+        assert_eq!(diagnostic.digest.logs().len(), 1);
+    }
+}
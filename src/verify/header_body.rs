@@ -15,13 +15,15 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+mod tests;
+
 use crate::{
     chain::chain_information,
     executor::{self, host, runtime_host, storage_diff, vm},
     header,
     trie::calculate_root,
     util,
-    verify::{aura, babe, inherents},
+    verify::{aura, babe, diagnostic, inherents},
 };
 
 use alloc::{string::String, vec::Vec};
@@ -57,6 +59,47 @@ pub struct Config<'a, TBody> {
     /// Optional cache corresponding to the storage trie root hash calculation of the parent
     /// block.
     pub top_trie_root_calculation_cache: Option<calculate_root::CalculationCache>,
+
+    /// If `true`, the keys of the storage values loaded through [`Verify::StorageGet`] are
+    /// recorded and returned in [`Success::accessed_keys`].
+    ///
+    /// The verification never accesses the storage directly, and instead asks the API user for
+    /// storage values one by one. When accessing the storage is expensive, for example because
+    /// each value must be requested from the network, the list of keys found in
+    /// [`Success::accessed_keys`] can be used in order to speculatively fetch in parallel, ahead
+    /// of time, the storage values that the verification of a similar block (e.g. the same block
+    /// on a different fork, or the child of that block) is likely to request.
+    ///
+    /// There is intentionally no prefetching hook that would run concurrently with the
+    /// verification, as the library never spawns threads or performs any I/O by itself (see
+    /// `CODING_GUIDE.md`). Instead, the API user is expected to pass the recorded keys to its
+    /// own prefetcher, and to answer the [`Verify::StorageGet`] requests from what this
+    /// prefetcher has already fetched.
+    pub record_accessed_keys: bool,
+
+    /// If `true`, the inherents of the block (for example its timestamp) are verified by calling
+    /// the `BlockBuilder_check_inherents` runtime function before the block is executed.
+    ///
+    /// Errors that the runtime considers as fatal make the verification fail with
+    /// [`Error::CheckInherentsError`], while the other errors are reported in
+    /// [`Success::inherents_warnings`].
+    pub check_inherents: bool,
+
+    /// If `true`, the storage values loaded through [`Verify::StorageGet`] are kept in memory
+    /// for the entire duration of the verification, and a storage value that is read multiple
+    /// times, including by different runtime calls, is only requested once.
+    pub cache_storage_reads: bool,
+    /// If `true`, the signature found in the seal of the header, and, for Babe, the VRF proof,
+    /// aren't verified. The rest of the header is still verified.
+    ///
+    /// See [`aura::VerifyConfig::skip_seal_verification`] and
+    /// [`babe::VerifyConfig::skip_seal_verification`].
+    ///
+    /// > **Note**: This makes it possible for anyone to produce blocks that pass the
+    /// >           verification. It must only be set to `true` if the source of the block is
+    /// >           trusted, for example a database previously filled by the local node. Should
+    /// >           be `false` in doubt.
+    pub skip_seal_verification: bool,
 }
 
 /// Extra items of [`Config`] that are dependant on the consensus engine of the chain.
@@ -121,6 +164,30 @@ pub struct Success {
 
     /// Concatenation of all the log messages printed by the runtime.
     pub logs: String,
+
+    /// List of keys of the storage values that have been loaded through [`Verify::StorageGet`],
+    /// in the order in which they have been requested. A key might appear multiple times.
+    ///
+    /// Always empty if [`Config::record_accessed_keys`] was `false`.
+    pub accessed_keys: Vec<Vec<u8>>,
+
+    /// Root of the storage trie of the block, as calculated while executing it. Guaranteed to
+    /// be equal to the state root found in the header of the block.
+    ///
+    /// Contains `None` if the runtime hasn't calculated the state root at the end of the block
+    /// execution, in which case the state root found in the header couldn't be verified. This
+    /// isn't supposed to happen with well-behaved runtimes.
+    pub state_root: Option<[u8; 32]>,
+
+    /// List of non-fatal errors returned by the runtime when verifying the inherents of the
+    /// block. Only contains [`inherents::InherentError::NonFatal`] items.
+    ///
+    /// A non-fatal error indicates that the block isn't valid at the moment, but might become
+    /// valid later, for example because its timestamp is too close to the one of its parent. It
+    /// is up to the API user to decide whether to accept the block anyway.
+    ///
+    /// Always empty if [`Config::check_inherents`] was `false`.
+    pub inherents_warnings: Vec<inherents::InherentError>,
 }
 
 /// Extra items in [`Success`] relevant to the consensus engine.
@@ -160,25 +227,28 @@ pub enum Error {
     /// Error while running the Wasm virtual machine to execute the block.
     #[display(fmt = "{}", _0)]
     WasmVm(runtime_host::ErrorDetail),
-    /// Runtime has returned some errors when verifying inherents.
+    /// Runtime has returned some fatal errors when verifying inherents.
     #[display(
         fmt = "Runtime has returned some errors when verifying inherents: {:?}",
         errors
     )]
     CheckInherentsError {
-        /// List of errors produced by the runtime.
-        ///
-        /// The first element of each tuple is an identifier of the module that produced the
-        /// error, while the second element is a SCALE-encoded piece of data.
-        ///
-        /// Due to the fact that errors are not supposed to happen, and that the format of errors
-        /// has changed depending on runtime versions, no utility is provided to decode them.
-        errors: Vec<([u8; 8], Vec<u8>)>,
+        /// List of errors produced by the runtime. They are all fatal, unless this error has
+        /// been built from [`Success::inherents_warnings`].
+        errors: Vec<inherents::InherentError>,
     },
     /// Failed to parse the output of `BlockBuilder_check_inherents`.
     CheckInherentsOutputParseFailure,
     /// Output of `Core_execute_block` wasn't empty.
     NonEmptyOutput,
+    /// State root calculated while executing the block doesn't match the one in the header.
+    #[display(fmt = "The calculated state root doesn't match the one in the header")]
+    StateRootMismatch {
+        /// State root calculated while executing the block.
+        calculated: [u8; 32],
+        /// State root found in the header.
+        in_header: [u8; 32],
+    },
     /// Block header contains items relevant to multiple consensus engines at the same time.
     MultipleConsensusEngines,
     /// Failed to verify the authenticity of the block with the AURA algorithm.
@@ -235,6 +305,7 @@ pub fn verify(
                 now_from_unix_epoch: config.now_from_unix_epoch,
                 current_authorities: current_authorities.clone(),
                 slot_duration: *slot_duration,
+                skip_seal_verification: config.skip_seal_verification,
             });
 
             match result {
@@ -268,6 +339,7 @@ pub fn verify(
                 parent_block_epoch: parent_block_epoch.clone(),
                 slots_per_epoch: *slots_per_epoch,
                 now_from_unix_epoch: config.now_from_unix_epoch,
+                skip_seal_verification: config.skip_seal_verification,
             });
 
             match result {
@@ -288,7 +360,7 @@ pub fn verify(
     // Now that we have verified the header, we need to call two runtime functions:
     //
     // - `BlockBuilder_check_inherents`, which does some basic verification of the inherents
-    //   contained in the block.
+    //   contained in the block. This step is skipped if `config.check_inherents` is `false`.
     // - `Core_execute_block`, which goes through transactions and makes sure that everything is
     //   valid.
     //
@@ -314,6 +386,40 @@ pub fn verify(
             })
     };
 
+    // If inherents aren't checked, directly start the virtual machine with `Core_execute_block`.
+    if !config.check_inherents {
+        let import_process = runtime_host::run(runtime_host::Config {
+            virtual_machine: config.parent_runtime,
+            function_to_call: "Core_execute_block",
+            parameter: iter::once(&block_parameter),
+            top_trie_root_calculation_cache: config.top_trie_root_calculation_cache,
+            storage_top_trie_changes: Default::default(),
+            offchain_storage_changes: Default::default(),
+            storage_read_cache: if config.cache_storage_reads {
+                Some(runtime_host::StorageReadCache::new())
+            } else {
+                None
+            },
+        });
+
+        return match import_process {
+            Ok(inner) => VerifyInner {
+                inner,
+                execution_not_started: None,
+                consensus_success,
+                block_state_root: *config.block_header.state_root,
+                accessed_keys: if config.record_accessed_keys {
+                    Some(Vec::new())
+                } else {
+                    None
+                },
+                inherents_warnings: Vec::new(),
+            }
+            .run(),
+            Err((error, prototype)) => Verify::Finished(Err((Error::WasmStart(error), prototype))),
+        };
+    }
+
     // Start the virtual machine with `BlockBuilder_check_inherents`.
     let check_inherents_process = {
         // The second parameter of `BlockBuilder_check_inherents` contains information such as
@@ -322,6 +428,7 @@ pub fn verify(
         let inherent_data = inherents::InherentData {
             timestamp: u64::try_from(config.now_from_unix_epoch.as_millis())
                 .unwrap_or(u64::max_value()),
+            consensus: None,
         };
 
         let vm = runtime_host::run(runtime_host::Config {
@@ -350,6 +457,11 @@ pub fn verify(
             top_trie_root_calculation_cache: config.top_trie_root_calculation_cache,
             storage_top_trie_changes: Default::default(),
             offchain_storage_changes: Default::default(),
+            storage_read_cache: if config.cache_storage_reads {
+                Some(runtime_host::StorageReadCache::new())
+            } else {
+                None
+            },
         });
 
         match vm {
@@ -364,10 +476,53 @@ pub fn verify(
         inner: check_inherents_process,
         execution_not_started: Some(block_parameter),
         consensus_success,
+        block_state_root: *config.block_header.state_root,
+        accessed_keys: if config.record_accessed_keys {
+            Some(Vec::new())
+        } else {
+            None
+        },
+        inherents_warnings: Vec::new(),
     }
     .run()
 }
 
+/// Builds a [`diagnostic::Diagnostic`] about a block whose verification has failed with the
+/// given error.
+///
+/// `block_header` and `consensus` must be the same as the ones that were passed through the
+/// [`Config`].
+pub fn diagnostic(
+    block_header: header::HeaderRef,
+    consensus: &ConfigConsensus,
+    error: &Error,
+) -> diagnostic::Diagnostic {
+    let consensus = match consensus {
+        ConfigConsensus::AllAuthorized => diagnostic::DiagnosticConsensus::AllAuthorized,
+        ConfigConsensus::Aura {
+            current_authorities,
+            ..
+        } => diagnostic::DiagnosticConsensus::Aura {
+            current_authorities: current_authorities.clone(),
+        },
+        ConfigConsensus::Babe {
+            parent_block_epoch,
+            parent_block_next_epoch,
+            ..
+        } => diagnostic::DiagnosticConsensus::Babe {
+            parent_block_epoch: parent_block_epoch.clone(),
+            parent_block_next_epoch: parent_block_next_epoch.clone(),
+        },
+    };
+
+    let calculated_state_root = match error {
+        Error::StateRootMismatch { calculated, .. } => Some(*calculated),
+        _ => None,
+    };
+
+    diagnostic::build(&block_header, consensus, calculated_state_root)
+}
+
 /// Current state of the verification.
 #[must_use]
 pub enum Verify {
@@ -396,6 +551,12 @@ struct VerifyInner {
     /// executing the block.
     execution_not_started: Option<Vec<u8>>,
     consensus_success: SuccessConsensus,
+    /// See [`Success::accessed_keys`]. `None` if [`Config::record_accessed_keys`] was `false`.
+    accessed_keys: Option<Vec<Vec<u8>>>,
+    /// State root found in the header of the block being verified.
+    block_state_root: [u8; 32],
+    /// See [`Success::inherents_warnings`].
+    inherents_warnings: Vec<inherents::InherentError>,
 }
 
 impl VerifyInner {
@@ -411,12 +572,15 @@ impl VerifyInner {
                     // Check the output of the `BlockBuilder_check_inherents` runtime call.
                     let check_inherents_result =
                         check_check_inherents_output(success.virtual_machine.value().as_ref());
-                    if let Err(err) = check_inherents_result {
-                        return Verify::Finished(Err((
-                            err,
-                            success.virtual_machine.into_prototype(),
-                        )));
-                    }
+                    let inherents_warnings = match check_inherents_result {
+                        Ok(warnings) => warnings,
+                        Err(err) => {
+                            return Verify::Finished(Err((
+                                err,
+                                success.virtual_machine.into_prototype(),
+                            )))
+                        }
+                    };
 
                     // Switch to phase 2: calling `Core_execute_block`.
                     let import_process = {
@@ -429,6 +593,7 @@ impl VerifyInner {
                             ),
                             storage_top_trie_changes: success.storage_top_trie_changes,
                             offchain_storage_changes: success.offchain_storage_changes,
+                            storage_read_cache: success.storage_read_cache,
                         });
 
                         match vm {
@@ -441,8 +606,11 @@ impl VerifyInner {
 
                     self = VerifyInner {
                         consensus_success: self.consensus_success,
+                        accessed_keys: self.accessed_keys,
+                        block_state_root: self.block_state_root,
                         execution_not_started: None,
                         inner: import_process,
+                        inherents_warnings,
                     };
                 }
                 runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
@@ -453,6 +621,21 @@ impl VerifyInner {
                         )));
                     }
 
+                    // The runtime is expected to compare the state root it calculates with the
+                    // one in the header. This check is done again here in case the runtime is
+                    // buggy or malicious.
+                    if let Some(state_root) = success.storage_root {
+                        if state_root != self.block_state_root {
+                            return Verify::Finished(Err((
+                                Error::StateRootMismatch {
+                                    calculated: state_root,
+                                    in_header: self.block_state_root,
+                                },
+                                success.virtual_machine.into_prototype(),
+                            )));
+                        }
+                    }
+
                     match (
                         success.storage_top_trie_changes.diff_get(&b":code"[..]),
                         success
@@ -492,6 +675,8 @@ impl VerifyInner {
 
                             return Verify::RuntimeCompilation(RuntimeCompilation {
                                 consensus_success: self.consensus_success,
+                                accessed_keys: self.accessed_keys,
+                                state_root: success.storage_root,
                                 parent_runtime,
                                 heap_pages,
                                 logs: success.logs,
@@ -499,6 +684,7 @@ impl VerifyInner {
                                 storage_top_trie_changes: success.storage_top_trie_changes,
                                 top_trie_root_calculation_cache: success
                                     .top_trie_root_calculation_cache,
+                                inherents_warnings: self.inherents_warnings,
                             });
                         }
                     }
@@ -511,6 +697,9 @@ impl VerifyInner {
                         offchain_storage_changes: success.offchain_storage_changes,
                         top_trie_root_calculation_cache: success.top_trie_root_calculation_cache,
                         logs: success.logs,
+                        accessed_keys: self.accessed_keys.unwrap_or_default(),
+                        state_root: success.storage_root,
+                        inherents_warnings: self.inherents_warnings,
                     }));
                 }
                 runtime_host::RuntimeHostVm::StorageGet(inner) => {
@@ -518,6 +707,9 @@ impl VerifyInner {
                         inner,
                         execution_not_started: self.execution_not_started,
                         consensus_success: self.consensus_success,
+                        accessed_keys: self.accessed_keys,
+                        block_state_root: self.block_state_root,
+                        inherents_warnings: self.inherents_warnings,
                     })
                 }
                 runtime_host::RuntimeHostVm::PrefixKeys(inner) => {
@@ -525,6 +717,9 @@ impl VerifyInner {
                         inner,
                         execution_not_started: self.execution_not_started,
                         consensus_success: self.consensus_success,
+                        accessed_keys: self.accessed_keys,
+                        block_state_root: self.block_state_root,
+                        inherents_warnings: self.inherents_warnings,
                     })
                 }
                 runtime_host::RuntimeHostVm::NextKey(inner) => {
@@ -532,6 +727,9 @@ impl VerifyInner {
                         inner,
                         execution_not_started: self.execution_not_started,
                         consensus_success: self.consensus_success,
+                        accessed_keys: self.accessed_keys,
+                        block_state_root: self.block_state_root,
+                        inherents_warnings: self.inherents_warnings,
                     })
                 }
             }
@@ -546,6 +744,12 @@ pub struct StorageGet {
     /// See [`VerifyInner::execution_not_started`].
     execution_not_started: Option<Vec<u8>>,
     consensus_success: SuccessConsensus,
+    /// See [`VerifyInner::accessed_keys`].
+    accessed_keys: Option<Vec<Vec<u8>>>,
+    /// See [`VerifyInner::block_state_root`].
+    block_state_root: [u8; 32],
+    /// See [`VerifyInner::inherents_warnings`].
+    inherents_warnings: Vec<inherents::InherentError>,
 }
 
 impl StorageGet {
@@ -562,11 +766,18 @@ impl StorageGet {
     }
 
     /// Injects the corresponding storage value.
-    pub fn inject_value(self, value: Option<impl Iterator<Item = impl AsRef<[u8]>>>) -> Verify {
+    pub fn inject_value(mut self, value: Option<impl Iterator<Item = impl AsRef<[u8]>>>) -> Verify {
+        if let Some(accessed_keys) = &mut self.accessed_keys {
+            accessed_keys.push(self.inner.key_as_vec());
+        }
+
         VerifyInner {
             inner: self.inner.inject_value(value),
             execution_not_started: self.execution_not_started,
             consensus_success: self.consensus_success,
+            accessed_keys: self.accessed_keys,
+            block_state_root: self.block_state_root,
+            inherents_warnings: self.inherents_warnings,
         }
         .run()
     }
@@ -579,6 +790,12 @@ pub struct StoragePrefixKeys {
     /// See [`VerifyInner::execution_not_started`].
     execution_not_started: Option<Vec<u8>>,
     consensus_success: SuccessConsensus,
+    /// See [`VerifyInner::accessed_keys`].
+    accessed_keys: Option<Vec<Vec<u8>>>,
+    /// See [`VerifyInner::block_state_root`].
+    block_state_root: [u8; 32],
+    /// See [`VerifyInner::inherents_warnings`].
+    inherents_warnings: Vec<inherents::InherentError>,
 }
 
 impl StoragePrefixKeys {
@@ -593,6 +810,9 @@ impl StoragePrefixKeys {
             inner: self.inner.inject_keys_ordered(keys),
             execution_not_started: self.execution_not_started,
             consensus_success: self.consensus_success,
+            accessed_keys: self.accessed_keys,
+            block_state_root: self.block_state_root,
+            inherents_warnings: self.inherents_warnings,
         }
         .run()
     }
@@ -605,6 +825,12 @@ pub struct StorageNextKey {
     /// See [`VerifyInner::execution_not_started`].
     execution_not_started: Option<Vec<u8>>,
     consensus_success: SuccessConsensus,
+    /// See [`VerifyInner::accessed_keys`].
+    accessed_keys: Option<Vec<Vec<u8>>>,
+    /// See [`VerifyInner::block_state_root`].
+    block_state_root: [u8; 32],
+    /// See [`VerifyInner::inherents_warnings`].
+    inherents_warnings: Vec<inherents::InherentError>,
 }
 
 impl StorageNextKey {
@@ -624,6 +850,9 @@ impl StorageNextKey {
             inner: self.inner.inject_key(key),
             execution_not_started: self.execution_not_started,
             consensus_success: self.consensus_success,
+            accessed_keys: self.accessed_keys,
+            block_state_root: self.block_state_root,
+            inherents_warnings: self.inherents_warnings,
         }
         .run()
     }
@@ -642,6 +871,9 @@ pub struct RuntimeCompilation {
     logs: String,
     heap_pages: vm::HeapPages,
     consensus_success: SuccessConsensus,
+    accessed_keys: Option<Vec<Vec<u8>>>,
+    state_root: Option<[u8; 32]>,
+    inherents_warnings: Vec<inherents::InherentError>,
 }
 
 impl RuntimeCompilation {
@@ -678,49 +910,34 @@ impl RuntimeCompilation {
             offchain_storage_changes: self.offchain_storage_changes,
             top_trie_root_calculation_cache: self.top_trie_root_calculation_cache,
             logs: self.logs,
+            accessed_keys: self.accessed_keys.unwrap_or_default(),
+            state_root: self.state_root,
+            inherents_warnings: self.inherents_warnings,
         }))
     }
 }
 
 /// Checks the output of the `BlockBuilder_check_inherents` runtime call.
-fn check_check_inherents_output(output: &[u8]) -> Result<(), Error> {
-    // The format of the output of `check_inherents` consists of two booleans and a list of
-    // errors.
-    // We don't care about the value of the two booleans, and they are ignored during the parsing.
+///
+/// On success, returns the list of non-fatal errors.
+fn check_check_inherents_output(output: &[u8]) -> Result<Vec<inherents::InherentError>, Error> {
+    let mut errors = inherents::decode_check_inherents_result(output)
+        .map_err(|_| Error::CheckInherentsOutputParseFailure)?;
+
     // Because we don't pass as parameter the `auraslot` or `babeslot`, errors will be generated
     // on older runtimes that expect these values. For this reason, errors concerning `auraslot`
     // and `babeslot` are ignored.
-    let parser = nom::sequence::preceded(
-        nom::sequence::tuple((crate::util::nom_bool_decode, crate::util::nom_bool_decode)),
-        nom::combinator::flat_map(crate::util::nom_scale_compact_usize, |num_elems| {
-            nom::multi::fold_many_m_n(
-                num_elems,
-                num_elems,
-                nom::sequence::tuple((
-                    nom::combinator::map(nom::bytes::complete::take(8u8), |b| {
-                        <[u8; 8]>::try_from(b).unwrap()
-                    }),
-                    crate::util::nom_bytes_decode,
-                )),
-                || Vec::new(),
-                |mut errors, (module, error)| {
-                    if module != *b"auraslot" && module != *b"babeslot" {
-                        errors.push((module, error.to_vec()));
-                    }
-                    errors
-                },
-            )
-        }),
-    );
-
-    match nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(parser)(output) {
-        Err(_err) => Err(Error::CheckInherentsOutputParseFailure),
-        Ok((_, errors)) => {
-            if errors.is_empty() {
-                Ok(())
-            } else {
-                Err(Error::CheckInherentsError { errors })
-            }
-        }
+    errors.retain(|err| {
+        !matches!(
+            err.identifier(),
+            inherents::InherentIdentifier::AuraSlot | inherents::InherentIdentifier::BabeSlot
+        )
+    });
+
+    // Errors are either all fatal or all non-fatal.
+    if errors.iter().any(|err| err.is_fatal()) {
+        Err(Error::CheckInherentsError { errors })
+    } else {
+        Ok(errors)
     }
 }
@@ -0,0 +1,323 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use crate::{
+    executor::test_module::{self, Instr, ModuleBuilder, ValType},
+    header,
+    verify::inherents,
+};
+use core::{iter, time::Duration};
+
+/// Verifies a block containing only a timestamp inherent with the given value, at the given
+/// time, against an empty storage.
+fn verify_timestamp(
+    block_timestamp: u64,
+    now_from_unix_epoch: Duration,
+) -> Result<super::Success, super::Error> {
+    let runtime = crate::executor::host::HostVmPrototype::new(crate::executor::host::Config {
+        module: &include_bytes!("../../executor/vm/test-polkadot-runtime-v9160.wasm")[..],
+        heap_pages: crate::executor::DEFAULT_HEAP_PAGES,
+        exec_hint: crate::executor::vm::ExecHint::Oneshot,
+        allow_unresolved_imports: false,
+    })
+    .unwrap();
+
+    let parent_block_header = header::Header {
+        parent_hash: [0; 32],
+        number: 0,
+        state_root: [0; 32],
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::empty().into(),
+    };
+    let block_header = header::Header {
+        parent_hash: parent_block_header.hash(),
+        number: 1,
+        state_root: [0; 32],
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::empty().into(),
+    };
+
+    // Unsigned extrinsic calling `Timestamp::set`, which is the call at index 0 of the pallet at
+    // index 3 in the Polkadot runtime.
+    let timestamp_extrinsic = {
+        let mut call = vec![4, 3, 0];
+        call.extend_from_slice(crate::util::encode_scale_compact_u64(block_timestamp).as_ref());
+        let mut extrinsic = crate::util::encode_scale_compact_usize(call.len())
+            .as_ref()
+            .to_vec();
+        extrinsic.extend_from_slice(&call);
+        extrinsic
+    };
+
+    let mut verify = super::verify(super::Config {
+        parent_runtime: runtime,
+        parent_block_header: (&parent_block_header).into(),
+        consensus: super::ConfigConsensus::AllAuthorized,
+        now_from_unix_epoch,
+        block_header: (&block_header).into(),
+        block_body: iter::once(&timestamp_extrinsic),
+        top_trie_root_calculation_cache: None,
+        record_accessed_keys: false,
+        check_inherents: true,
+        cache_storage_reads: false,
+        skip_seal_verification: false,
+    });
+
+    loop {
+        match verify {
+            super::Verify::Finished(result) => break result.map_err(|(err, _)| err),
+            super::Verify::RuntimeCompilation(c) => verify = c.build(),
+            super::Verify::StorageGet(get) => verify = get.inject_value(None::<iter::Empty<&[u8]>>),
+            super::Verify::StorageNextKey(next) => verify = next.inject_key(None::<&[u8]>),
+            super::Verify::StoragePrefixKeys(prefix) => {
+                verify = prefix.inject_keys_ordered(iter::empty::<&[u8]>())
+            }
+        }
+    }
+}
+
+#[test]
+fn timestamp_too_far_in_future() {
+    // The block is one hour in advance compared to the current time.
+    match verify_timestamp(3_600_000 + 60_000, Duration::from_millis(60_000)) {
+        Err(super::Error::CheckInherentsError { errors }) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(
+                errors[0].identifier(),
+                inherents::InherentIdentifier::Timestamp
+            );
+        }
+        Err(err) => panic!("{}", err),
+        Ok(_) => panic!(),
+    }
+}
+
+#[test]
+fn timestamp_within_tolerance() {
+    // The execution of the block itself fails, as the storage is empty, but the inherents
+    // must pass.
+    let result = verify_timestamp(60_000, Duration::from_millis(60_000));
+    assert!(!matches!(
+        result,
+        Err(super::Error::CheckInherentsError { .. }
+            | super::Error::CheckInherentsOutputParseFailure)
+    ));
+}
+
+#[test]
+fn check_inherents_output_fatal_and_non_fatal() {
+    // `okay`, `fatal_error`, then one `timstap0` error containing a SCALE-encoded `[0x2a]`.
+    let errors_list = [&[4][..], b"timstap0", &[4, 0x2a]].concat();
+
+    let non_fatal = [&[0, 0][..], &errors_list].concat();
+    assert_eq!(
+        super::check_check_inherents_output(&non_fatal).unwrap(),
+        vec![inherents::InherentError::NonFatal {
+            identifier: inherents::InherentIdentifier::Timestamp,
+            error: vec![0x2a]
+        }]
+    );
+
+    let fatal = [&[0, 1][..], &errors_list].concat();
+    assert!(matches!(
+        super::check_check_inherents_output(&fatal),
+        Err(super::Error::CheckInherentsError { errors }) if errors == [inherents::InherentError::Fatal {
+            identifier: inherents::InherentIdentifier::Timestamp,
+            error: vec![0x2a]
+        }]
+    ));
+
+    // Errors about the slot are ignored, even if fatal.
+    let slot = [&[0, 1, 4][..], b"auraslot", &[0]].concat();
+    assert!(super::check_check_inherents_output(&slot)
+        .unwrap()
+        .is_empty());
+
+    assert!(super::check_check_inherents_output(&[1, 0, 0])
+        .unwrap()
+        .is_empty());
+    assert!(matches!(
+        super::check_check_inherents_output(&[1, 0]),
+        Err(super::Error::CheckInherentsOutputParseFailure)
+    ));
+}
+
+/// Verifies an empty block whose header contains the given state root, with a runtime built
+/// from the given module, against an empty storage.
+fn verify_empty_block(
+    module: Vec<u8>,
+    state_root: [u8; 32],
+    record_accessed_keys: bool,
+) -> Result<super::Success, super::Error> {
+    let runtime = crate::executor::host::HostVmPrototype::new(crate::executor::host::Config {
+        module,
+        heap_pages: crate::executor::DEFAULT_HEAP_PAGES,
+        exec_hint: crate::executor::vm::ExecHint::Oneshot,
+        allow_unresolved_imports: false,
+    })
+    .unwrap();
+
+    let parent_block_header = header::Header {
+        parent_hash: [0; 32],
+        number: 0,
+        state_root: [0; 32],
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::empty().into(),
+    };
+    let block_header = header::Header {
+        parent_hash: parent_block_header.hash(),
+        number: 1,
+        state_root,
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::empty().into(),
+    };
+
+    let mut verify = super::verify(super::Config {
+        parent_runtime: runtime,
+        parent_block_header: (&parent_block_header).into(),
+        consensus: super::ConfigConsensus::AllAuthorized,
+        now_from_unix_epoch: Duration::from_secs(0),
+        block_header: (&block_header).into(),
+        block_body: iter::empty::<&[u8]>(),
+        top_trie_root_calculation_cache: None,
+        record_accessed_keys,
+        check_inherents: false,
+        cache_storage_reads: false,
+        skip_seal_verification: false,
+    });
+
+    loop {
+        match verify {
+            super::Verify::Finished(result) => break result.map_err(|(err, _)| err),
+            super::Verify::RuntimeCompilation(c) => verify = c.build(),
+            super::Verify::StorageGet(get) => verify = get.inject_value(None::<iter::Empty<&[u8]>>),
+            super::Verify::StorageNextKey(next) => verify = next.inject_key(None::<&[u8]>),
+            super::Verify::StoragePrefixKeys(prefix) => {
+                verify = prefix.inject_keys_ordered(iter::empty::<&[u8]>())
+            }
+        }
+    }
+}
+
+/// Verifies an empty block whose header contains the given state root, with a runtime whose
+/// `Core_execute_block` function calculates the root of the storage, which is empty.
+fn verify_state_root(state_root: [u8; 32]) -> Result<super::Success, super::Error> {
+    let mut module = ModuleBuilder::new();
+    let root = module.import_function("ext_storage_root_version_1", &[], &[ValType::I64]);
+    module.export_function(
+        "Core_execute_block",
+        [Instr::Call(root), Instr::Drop, Instr::I64Const(0)],
+    );
+    verify_empty_block(module.build(), state_root, false)
+}
+
+#[test]
+fn state_root_matching() {
+    let empty_trie = crate::trie::empty_trie_merkle_value();
+    match verify_state_root(empty_trie) {
+        Ok(success) => assert_eq!(success.state_root, Some(empty_trie)),
+        Err(err) => panic!("{}", err),
+    }
+}
+
+#[test]
+fn state_root_mismatch() {
+    match verify_state_root([0xaa; 32]) {
+        Err(super::Error::StateRootMismatch {
+            calculated,
+            in_header,
+        }) => {
+            assert_eq!(calculated, crate::trie::empty_trie_merkle_value());
+            assert_eq!(in_header, [0xaa; 32]);
+        }
+        Err(err) => panic!("{}", err),
+        Ok(_) => panic!(),
+    }
+}
+
+#[test]
+fn accessed_keys_recorded() {
+    // `Core_execute_block` reads the storage values at `foo`, `bar`, then `foo` again.
+    let module = {
+        let mut module = ModuleBuilder::new();
+        let get = module.import_function(
+            "ext_storage_get_version_1",
+            &[ValType::I64],
+            &[ValType::I64],
+        );
+        module.data(0, b"foo");
+        module.data(8, b"bar");
+        module.export_function(
+            "Core_execute_block",
+            [(0, 3), (8, 3), (0, 3)]
+                .into_iter()
+                .flat_map(|(ptr, size)| {
+                    [
+                        Instr::I64Const(test_module::pointer_size(ptr, size)),
+                        Instr::Call(get),
+                        Instr::Drop,
+                    ]
+                })
+                .chain([Instr::I64Const(0)]),
+        );
+        module.build()
+    };
+
+    let success = verify_empty_block(module.clone(), [0; 32], true).unwrap();
+    assert_eq!(
+        success.accessed_keys,
+        [&b"foo"[..], &b"bar"[..], &b"foo"[..]]
+    );
+
+    let success = verify_empty_block(module, [0; 32], false).unwrap();
+    assert!(success.accessed_keys.is_empty());
+}
+
+#[test]
+fn diagnostic_state_root_mismatch() {
+    let block_header = header::Header {
+        parent_hash: [1; 32],
+        number: 5,
+        state_root: [2; 32],
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::empty().into(),
+    };
+
+    let diagnostic = super::diagnostic(
+        (&block_header).into(),
+        &super::ConfigConsensus::AllAuthorized,
+        &super::Error::StateRootMismatch {
+            calculated: [3; 32],
+            in_header: [2; 32],
+        },
+    );
+    assert_eq!(diagnostic.block_hash, block_header.hash());
+    assert_eq!(diagnostic.block_number, 5);
+    assert_eq!(diagnostic.claimed_slot, None);
+    assert_eq!(diagnostic.expected_author, None);
+    assert_eq!(diagnostic.state_root_in_header, [2; 32]);
+    assert_eq!(diagnostic.calculated_state_root, Some([3; 32]));
+
+    let diagnostic = super::diagnostic(
+        (&block_header).into(),
+        &super::ConfigConsensus::AllAuthorized,
+        &super::Error::NonEmptyOutput,
+    );
+    assert_eq!(diagnostic.calculated_state_root, None);
+}
@@ -18,7 +18,7 @@
 use crate::{
     chain::chain_information,
     header,
-    verify::{aura, babe},
+    verify::{aura, babe, diagnostic},
 };
 
 use core::{num::NonZeroU64, time::Duration};
@@ -38,6 +38,17 @@ pub struct Config<'a> {
 
     /// Configuration items related to the consensus engine.
     pub consensus: ConfigConsensus<'a>,
+    /// If `true`, the signature found in the seal of the header, and, for Babe, the VRF proof,
+    /// aren't verified. The rest of the header is still verified.
+    ///
+    /// See [`aura::VerifyConfig::skip_seal_verification`] and
+    /// [`babe::VerifyConfig::skip_seal_verification`].
+    ///
+    /// > **Note**: This makes it possible for anyone to produce blocks that pass the
+    /// >           verification. It must only be set to `true` if the source of the block is
+    /// >           trusted, for example a database previously filled by the local node. Should
+    /// >           be `false` in doubt.
+    pub skip_seal_verification: bool,
 }
 
 /// Extra items of [`Config`] that are dependant on the consensus engine of the chain.
@@ -183,6 +194,7 @@ pub fn verify(config: Config) -> Result<Success, Error> {
                 now_from_unix_epoch,
                 current_authorities,
                 slot_duration,
+                skip_seal_verification: config.skip_seal_verification,
             });
 
             match result {
@@ -209,6 +221,7 @@ pub fn verify(config: Config) -> Result<Success, Error> {
                 parent_block_next_epoch,
                 slots_per_epoch,
                 now_from_unix_epoch,
+                skip_seal_verification: config.skip_seal_verification,
             });
 
             match result {
@@ -221,3 +234,121 @@ pub fn verify(config: Config) -> Result<Success, Error> {
         }
     }
 }
+
+/// Builds a [`diagnostic::Diagnostic`] about a block whose verification has failed.
+///
+/// `block_header` and `consensus` must be the same as the ones that were passed through the
+/// [`Config`].
+pub fn diagnostic(
+    block_header: header::HeaderRef,
+    consensus: ConfigConsensus,
+) -> diagnostic::Diagnostic {
+    let consensus = match consensus {
+        ConfigConsensus::AllAuthorized => diagnostic::DiagnosticConsensus::AllAuthorized,
+        ConfigConsensus::Aura {
+            current_authorities,
+            ..
+        } => diagnostic::DiagnosticConsensus::Aura {
+            current_authorities,
+        },
+        ConfigConsensus::Babe {
+            parent_block_epoch,
+            parent_block_next_epoch,
+            ..
+        } => diagnostic::DiagnosticConsensus::Babe {
+            parent_block_epoch,
+            parent_block_next_epoch,
+        },
+    };
+
+    diagnostic::build(&block_header, consensus, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::aura;
+    use crate::header;
+    use core::{num::NonZeroU64, time::Duration};
+
+    #[test]
+    fn skip_seal_verification() {
+        let authorities = (0..4u8)
+            .map(|n| header::AuraAuthority {
+                public_key: [n; 32],
+            })
+            .collect::<Vec<_>>();
+
+        let parent_block_header = header::Header {
+            parent_hash: [0; 32],
+            number: 0,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+
+        // The seal doesn't contain a valid signature.
+        let digest_items = [
+            header::DigestItem::AuraPreDigest(header::AuraPreDigest { slot_number: 10 }),
+            header::DigestItem::AuraSeal([0; 64]),
+        ];
+        let block_header = header::Header {
+            parent_hash: parent_block_header.hash(),
+            number: 1,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::from_slice(&digest_items).unwrap().into(),
+        };
+
+        let verify = |skip_seal_verification| {
+            super::verify(super::Config {
+                parent_block_header: (&parent_block_header).into(),
+                block_header: (&block_header).into(),
+                consensus: super::ConfigConsensus::Aura {
+                    current_authorities: header::AuraAuthoritiesIter::from_slice(&authorities),
+                    slot_duration: NonZeroU64::new(6000).unwrap(),
+                    now_from_unix_epoch: Duration::from_secs(3600),
+                },
+                skip_seal_verification,
+            })
+        };
+
+        assert!(matches!(
+            verify(false),
+            Err(super::Error::AuraVerification(
+                aura::VerifyError::BadSignature
+            ))
+        ));
+        assert!(matches!(
+            verify(true),
+            Ok(super::Success::Aura {
+                authorities_change: false
+            })
+        ));
+
+        // The rest of the header is still verified.
+        let unsealed_digest_items = [header::DigestItem::AuraPreDigest(header::AuraPreDigest {
+            slot_number: 10,
+        })];
+        let unsealed_block_header = header::Header {
+            digest: header::DigestRef::from_slice(&unsealed_digest_items)
+                .unwrap()
+                .into(),
+            ..block_header.clone()
+        };
+        assert!(matches!(
+            super::verify(super::Config {
+                parent_block_header: (&parent_block_header).into(),
+                block_header: (&unsealed_block_header).into(),
+                consensus: super::ConfigConsensus::Aura {
+                    current_authorities: header::AuraAuthoritiesIter::from_slice(&authorities),
+                    slot_duration: NonZeroU64::new(6000).unwrap(),
+                    now_from_unix_epoch: Duration::from_secs(3600),
+                },
+                skip_seal_verification: true,
+            }),
+            Err(super::Error::AuraVerification(
+                aura::VerifyError::MissingSeal
+            ))
+        ));
+    }
+}
@@ -26,17 +26,18 @@
 //! [`InherentData`].
 //!
 //! When a block is later verified, the inherents are verified by calling a runtime function and
-//! passing as parameter an encoded [`InherentData`] as well.
+//! passing as parameter an encoded [`InherentData`] as well. The output of this runtime function
+//! can be decoded with [`decode_check_inherents_result`].
+
+use alloc::vec::Vec;
 
 /// Values of the inherents to pass to the runtime.
 ///
 /// Historically, the inherent data included an Aura or Babe slot number, using the identifiers
 /// `auraslot` or `babeslot`. The runtime-side verification of the slot number has been removed in
 /// May 2021, and all the checks performed by the runtime are now performed by the client instead.
-/// Older runtime versions still require the slot number. For this reason, verifying the inherents
-/// (calling `BlockBuilder_check_inherents`) of blocks that are using older runtime versions will
-/// lead to errors concerning the Aura or Babe modules that should simply be ignored. Authoring
-/// blocks using older runtime versions is not supported anymore.
+/// Older runtime versions still require the slot number, which can be provided through
+/// [`InherentData::consensus`]. Newer runtime versions simply ignore it.
 #[derive(Debug)]
 pub struct InherentData {
     /// Number of milliseconds since the UNIX epoch when the block is generated, ignoring leap
@@ -44,6 +45,12 @@ pub struct InherentData {
     ///
     /// Its identifier passed to the runtime is: `timstap0`.
     pub timestamp: u64,
+
+    /// Consensus-specific slot number of the block.
+    ///
+    /// Its identifier passed to the runtime is: `auraslot` or `babeslot`. Nothing is passed if
+    /// `None`.
+    pub consensus: Option<InherentDataConsensus>,
     // TODO: figure out uncles
     /*/// List of valid block headers that have the same height as the parent of the one being
     /// generated.
@@ -56,11 +63,241 @@ pub struct InherentData {
     // TODO: parachain-related inherents are missing
 }
 
+/// Consensus-specific inherent. See [`InherentData::consensus`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InherentDataConsensus {
+    /// Chain is using the Aura consensus engine.
+    Aura {
+        /// Aura slot number of the block.
+        slot_number: u64,
+    },
+    /// Chain is using the Babe consensus engine.
+    Babe {
+        /// Babe slot number of the block.
+        slot_number: u64,
+    },
+}
+
 impl InherentData {
     /// Turns this list of inherents into a list that can be passed as parameter to the runtime.
     pub fn as_raw_list(
         &'_ self,
     ) -> impl ExactSizeIterator<Item = ([u8; 8], impl AsRef<[u8]> + Clone + '_)> + Clone + '_ {
-        [(*b"timstap0", self.timestamp.to_le_bytes())].into_iter()
+        let consensus = match self.consensus {
+            Some(InherentDataConsensus::Aura { slot_number }) => Some((*b"auraslot", slot_number)),
+            Some(InherentDataConsensus::Babe { slot_number }) => Some((*b"babeslot", slot_number)),
+            None => None,
+        };
+
+        // An array is used rather than chaining iterators in order to preserve the
+        // `ExactSizeIterator` property.
+        let (consensus_id, consensus_slot) = consensus.unwrap_or(([0; 8], 0));
+        [
+            (*b"timstap0", self.timestamp.to_le_bytes()),
+            (consensus_id, consensus_slot.to_le_bytes()),
+        ]
+        .into_iter()
+        .take(if consensus.is_some() { 2 } else { 1 })
+    }
+}
+
+/// Identifier of an inherent, as found in [`InherentData::as_raw_list`] or in the output of
+/// `BlockBuilder_check_inherents`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InherentIdentifier {
+    /// `timstap0`. See [`InherentData::timestamp`].
+    Timestamp,
+    /// `auraslot`. See [`InherentDataConsensus::Aura`].
+    AuraSlot,
+    /// `babeslot`. See [`InherentDataConsensus::Babe`].
+    BabeSlot,
+    /// `uncles00`.
+    Uncles,
+    /// Identifier unknown to smoldot, for example specific to a certain chain.
+    Unknown([u8; 8]),
+}
+
+impl InherentIdentifier {
+    /// Builds an [`InherentIdentifier`] from the identifier passed to or returned by the runtime.
+    pub fn from_raw(raw: [u8; 8]) -> Self {
+        match &raw {
+            b"timstap0" => InherentIdentifier::Timestamp,
+            b"auraslot" => InherentIdentifier::AuraSlot,
+            b"babeslot" => InherentIdentifier::BabeSlot,
+            b"uncles00" => InherentIdentifier::Uncles,
+            _ => InherentIdentifier::Unknown(raw),
+        }
+    }
+
+    /// Returns the identifier as passed to or returned by the runtime.
+    pub fn as_raw(&self) -> [u8; 8] {
+        match self {
+            InherentIdentifier::Timestamp => *b"timstap0",
+            InherentIdentifier::AuraSlot => *b"auraslot",
+            InherentIdentifier::BabeSlot => *b"babeslot",
+            InherentIdentifier::Uncles => *b"uncles00",
+            InherentIdentifier::Unknown(raw) => *raw,
+        }
+    }
+}
+
+/// Error concerning one inherent, as returned by `BlockBuilder_check_inherents`.
+///
+/// The error itself is a SCALE-encoded piece of data whose format depends on the inherent and
+/// has changed depending on runtime versions. No utility is provided to decode it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InherentError {
+    /// The block is invalid and must be rejected. For example, its timestamp is earlier than
+    /// the one of its parent.
+    Fatal {
+        /// Inherent that has produced the error.
+        identifier: InherentIdentifier,
+        /// SCALE-encoded error.
+        error: Vec<u8>,
+    },
+    /// The block isn't valid at the moment, but might become valid later. For example, its
+    /// timestamp is too far in the future compared to the current time.
+    NonFatal {
+        /// Inherent that has produced the error.
+        identifier: InherentIdentifier,
+        /// SCALE-encoded error.
+        error: Vec<u8>,
+    },
+}
+
+impl InherentError {
+    /// Returns `true` for [`InherentError::Fatal`].
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, InherentError::Fatal { .. })
+    }
+
+    /// Returns the inherent that has produced the error.
+    pub fn identifier(&self) -> InherentIdentifier {
+        match self {
+            InherentError::Fatal { identifier, .. }
+            | InherentError::NonFatal { identifier, .. } => *identifier,
+        }
+    }
+
+    /// Returns the SCALE-encoded error.
+    pub fn error(&self) -> &[u8] {
+        match self {
+            InherentError::Fatal { error, .. } | InherentError::NonFatal { error, .. } => error,
+        }
+    }
+}
+
+/// Decodes the output of the `BlockBuilder_check_inherents` runtime function into the list of
+/// errors concerning the inherents of the block. The list is empty if all the inherents are
+/// valid.
+pub fn decode_check_inherents_result(
+    output: &[u8],
+) -> Result<Vec<InherentError>, DecodeCheckInherentsResultError> {
+    // The format of the output consists of two booleans and a list of errors.
+    // The first boolean indicates whether the list of errors is empty and is ignored during the
+    // parsing. The second boolean indicates whether the errors are fatal. When generating the
+    // list, the runtime discards the non-fatal errors as soon as a fatal error is encountered,
+    // meaning that errors are either all fatal or all non-fatal.
+    let parser = nom::sequence::preceded(
+        crate::util::nom_bool_decode,
+        nom::combinator::flat_map(crate::util::nom_bool_decode, |fatal| {
+            nom::combinator::flat_map(crate::util::nom_scale_compact_usize, move |num_elems| {
+                nom::multi::fold_many_m_n(
+                    num_elems,
+                    num_elems,
+                    nom::sequence::tuple((
+                        nom::combinator::map(nom::bytes::complete::take(8u8), |b| {
+                            InherentIdentifier::from_raw(<[u8; 8]>::try_from(b).unwrap())
+                        }),
+                        crate::util::nom_bytes_decode,
+                    )),
+                    Vec::new,
+                    move |mut errors, (identifier, error)| {
+                        let error = error.to_vec();
+                        errors.push(if fatal {
+                            InherentError::Fatal { identifier, error }
+                        } else {
+                            InherentError::NonFatal { identifier, error }
+                        });
+                        errors
+                    },
+                )
+            })
+        }),
+    );
+
+    nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(parser)(output)
+        .map(|(_, errors)| errors)
+        .map_err(|_| DecodeCheckInherentsResultError())
+}
+
+/// Error potentially returned by [`decode_check_inherents_result`].
+#[derive(Debug, derive_more::Display, Clone)]
+#[display(fmt = "Failed to decode the output of BlockBuilder_check_inherents")]
+pub struct DecodeCheckInherentsResultError();
+
+#[cfg(test)]
+mod tests {
+    use super::{InherentData, InherentDataConsensus, InherentError, InherentIdentifier};
+
+    #[test]
+    fn raw_list() {
+        let list = InherentData {
+            timestamp: 1234,
+            consensus: None,
+        }
+        .as_raw_list()
+        .map(|(id, value)| (id, value.as_ref().to_vec()))
+        .collect::<Vec<_>>();
+        assert_eq!(list, vec![(*b"timstap0", 1234u64.to_le_bytes().to_vec())]);
+
+        let inherents = InherentData {
+            timestamp: 1234,
+            consensus: Some(InherentDataConsensus::Babe { slot_number: 5 }),
+        };
+        assert_eq!(inherents.as_raw_list().len(), 2);
+        let list = inherents
+            .as_raw_list()
+            .map(|(id, value)| (id, value.as_ref().to_vec()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            list,
+            vec![
+                (*b"timstap0", 1234u64.to_le_bytes().to_vec()),
+                (*b"babeslot", 5u64.to_le_bytes().to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_check_inherents_result() {
+        // Two errors: one concerning the timestamp, and one with an unknown identifier.
+        let errors_list = [&[8][..], b"timstap0", &[4, 0x2a], b"foobar00", &[8, 1, 2]].concat();
+
+        let fatal = [&[0, 1][..], &errors_list].concat();
+        assert_eq!(
+            super::decode_check_inherents_result(&fatal).unwrap(),
+            vec![
+                InherentError::Fatal {
+                    identifier: InherentIdentifier::Timestamp,
+                    error: vec![0x2a]
+                },
+                InherentError::Fatal {
+                    identifier: InherentIdentifier::Unknown(*b"foobar00"),
+                    error: vec![1, 2]
+                },
+            ]
+        );
+
+        let non_fatal = [&[0, 0][..], &errors_list].concat();
+        let errors = super::decode_check_inherents_result(&non_fatal).unwrap();
+        assert!(errors.iter().all(|err| !err.is_fatal()));
+        assert_eq!(errors[1].identifier().as_raw(), *b"foobar00");
+        assert_eq!(errors[1].error(), &[1, 2]);
+
+        assert!(super::decode_check_inherents_result(&[1, 0, 0])
+            .unwrap()
+            .is_empty());
+        assert!(super::decode_check_inherents_result(&[1, 0, 4]).is_err());
     }
 }
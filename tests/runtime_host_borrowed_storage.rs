@@ -0,0 +1,105 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Checks that storage values injected with `inject_value_slice` aren't copied.
+//!
+//! This test replaces the global allocator in order to count allocations, and is thus in its own
+//! test binary so as to not affect the other tests.
+
+use smoldot::executor::{host, runtime_host, storage_diff, vm};
+use std::{alloc, cell::Cell, iter};
+
+/// Global allocator that counts the number of allocations performed by each thread.
+struct CountingAllocator;
+
+std::thread_local! {
+    static NUM_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: alloc::Layout) -> *mut u8 {
+        let _ = NUM_ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: alloc::Layout) {
+        alloc::System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn borrowed_storage_value_not_copied() {
+    // Zeroed `AccountInfo`, corresponding to an account whose nonce is 0.
+    let account_info = [0u8; 80];
+
+    // Returns the number of storage reads and the number of allocations performed while
+    // injecting the values.
+    let run = |borrowed: bool| -> (usize, usize) {
+        let virtual_machine = host::HostVmPrototype::new(host::Config {
+            module: &include_bytes!("../src/executor/vm/test-polkadot-runtime-v9160.wasm")[..],
+            heap_pages: vm::HeapPages::new(1024),
+            exec_hint: vm::ExecHint::Oneshot,
+            allow_unresolved_imports: false,
+        })
+        .unwrap();
+
+        let mut execution = runtime_host::run(runtime_host::Config {
+            virtual_machine,
+            function_to_call: "AccountNonceApi_account_nonce",
+            parameter: iter::once(&[0; 32][..]),
+            top_trie_root_calculation_cache: None,
+            storage_top_trie_changes: storage_diff::StorageDiff::empty(),
+            offchain_storage_changes: storage_diff::StorageDiff::empty(),
+            storage_read_cache: None,
+        })
+        .unwrap();
+
+        let mut num_reads = 0;
+        let mut num_allocations = 0;
+        loop {
+            match execution {
+                runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                    assert_eq!(success.virtual_machine.value().as_ref(), &[0, 0, 0, 0]);
+                    break (num_reads, num_allocations);
+                }
+                runtime_host::RuntimeHostVm::Finished(Err(err)) => panic!("{}", err),
+                runtime_host::RuntimeHostVm::StorageGet(req) => {
+                    num_reads += 1;
+                    let before = NUM_ALLOCATIONS.with(|n| n.get());
+                    execution = if borrowed {
+                        req.inject_value_slice(Some(&account_info))
+                    } else {
+                        req.inject_value(Some(iter::once(&account_info[..])))
+                    };
+                    num_allocations += NUM_ALLOCATIONS.with(|n| n.get()) - before;
+                }
+                _ => panic!(),
+            }
+        }
+    };
+
+    let (num_reads, owned_allocations) = run(false);
+    let (num_reads_borrowed, borrowed_allocations) = run(true);
+    assert!(num_reads >= 1);
+    assert_eq!(num_reads, num_reads_borrowed);
+
+    // Each owned read copies the value into a new buffer, while borrowed reads don't.
+    assert_eq!(borrowed_allocations + num_reads, owned_allocations);
+}